@@ -858,7 +858,7 @@ export class BeamProgramClient {
    * - escrow_token_account.owner == escrow_account PDA
    *
    * EVENTS EMITTED:
-   * - PaymentSettled { payer, merchant, amount, nonce, bundle_id }
+   * - PaymentSettled { payer, merchant, amount, nonce, bundle_id, order_ref, escrow_balance, total_spent }
    * - BundleHistoryRecorded { payer, merchant, bundle_hash, amount, nonce, settled_at }
    *
    * ERROR CODES:
@@ -1296,6 +1296,9 @@ export class BeamProgramClient {
    * - bundle_id (variable): string (4-byte length prefix + UTF-8)
    * - conflicting_hash (32 bytes): [u8; 32]
    * - reason (variable): FraudReason enum
+   * - evidence (variable): FraudEvidence enum; always FraudEvidence::None here, which the
+   *   program only accepts from the arbiter quorum (duplicateBundle needs the payer's
+   *   signed conflicting bundle instead)
    */
   private buildReportFraudulentBundleInstruction(
     nonceRegistry: PublicKey,
@@ -1327,6 +1330,8 @@ export class BeamProgramClient {
     } else {
       reasonBuffer = Buffer.from([2]); // 'other'
     }
+    // FraudEvidence::None = 0
+    const evidenceBuffer = Buffer.from([0]);
 
     // Concatenate all data
     const data = Buffer.concat([
@@ -1334,6 +1339,7 @@ export class BeamProgramClient {
       bundleIdData,
       conflictingHashBuffer,
       reasonBuffer,
+      evidenceBuffer,
     ]);
 
     // Build accounts array according to IDL order (lines 210-240)
@@ -10,8 +10,22 @@ export interface AttestationRootInput {
   bundleNonce: bigint | number;
   attestationNonce: Uint8Array;
   attestationTimestamp: bigint | number;
+  /** Optional settlement deadline, committed after the timestamp when present */
+  deadline?: SettlementDeadline;
+  /** Optional 16-byte merchant order reference, committed last when non-zero */
+  orderRef?: Uint8Array;
+  /** Optional courier key and fee, committed after the order reference when present */
+  courier?: { courier: Uint8Array; fee: bigint | number };
+  /** Optional 32-byte device id hash, committed after the courier when present */
+  deviceIdHash?: Uint8Array;
+  /** Set on proofs signed by the fallback verifier; committed last when present */
+  fallbackReason?: number;
 }
 
+export type SettlementDeadline =
+  | { kind: 'timestamp'; value: bigint | number }
+  | { kind: 'slot'; value: bigint | number };
+
 const PREFIX = new TextEncoder().encode('beam.attestation.v1');
 
 function concatBytes(...arrays: Uint8Array[]): Uint8Array {
@@ -51,6 +65,36 @@ export function computeAttestationRoot(input: AttestationRootInput): Uint8Array
   const bundleNonceBytes = toLittleEndianBytes(input.bundleNonce, 8);
   const timestampBytes = toLittleEndianBytes(input.attestationTimestamp, 8);
 
+  const deadlineBytes = input.deadline
+    ? concatBytes(
+        new Uint8Array([input.deadline.kind === 'slot' ? 1 : 0]),
+        toLittleEndianBytes(input.deadline.value, 8),
+      )
+    : new Uint8Array(0);
+
+  if (input.orderRef && input.orderRef.length !== 16) {
+    throw new Error('Order reference must be 16 bytes');
+  }
+  const orderRefBytes =
+    input.orderRef && input.orderRef.some(byte => byte !== 0)
+      ? input.orderRef
+      : new Uint8Array(0);
+
+  if (input.courier && input.courier.courier.length !== 32) {
+    throw new Error('Courier key must be 32 bytes');
+  }
+  const courierBytes = input.courier
+    ? concatBytes(input.courier.courier, toLittleEndianBytes(input.courier.fee, 8))
+    : new Uint8Array(0);
+
+  if (input.deviceIdHash && input.deviceIdHash.length !== 32) {
+    throw new Error('Device id hash must be 32 bytes');
+  }
+  const deviceBytes = input.deviceIdHash ?? new Uint8Array(0);
+
+  const fallbackBytes =
+    input.fallbackReason === undefined ? new Uint8Array(0) : new Uint8Array([input.fallbackReason]);
+
   const preimage = concatBytes(
     PREFIX,
     bundleIdBytes,
@@ -61,6 +105,11 @@ export function computeAttestationRoot(input: AttestationRootInput): Uint8Array
     roleByte,
     input.attestationNonce,
     timestampBytes,
+    deadlineBytes,
+    orderRefBytes,
+    courierBytes,
+    deviceBytes,
+    fallbackBytes,
   );
 
   return sha256(preimage);
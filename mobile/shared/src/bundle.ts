@@ -187,9 +187,13 @@ export function computeBundleHash(bundle: OfflineBundle): Uint8Array {
   return keccak_256(serialized);
 }
 
+/** Bundle id hash algorithm fixed in the deployment's program config */
+export type BundleHashAlgo = 'keccak256' | 'sha256';
+
 /**
- * Compute keccak256 hash used on-chain for bundle identifiers
+ * Compute the hash used on-chain for bundle identifiers
  */
-export function hashBundleId(bundleId: string): Uint8Array {
-  return keccak_256(new TextEncoder().encode(bundleId));
+export function hashBundleId(bundleId: string, algo: BundleHashAlgo = 'keccak256'): Uint8Array {
+  const bytes = new TextEncoder().encode(bundleId);
+  return algo === 'sha256' ? sha256(bytes) : keccak_256(bytes);
 }
@@ -2,14 +2,25 @@ use anchor_lang::prelude::*;
 use ed25519_dalek::{PublicKey, Signature, Verifier};
 use sha2::{Digest, Sha256};
 
+use crate::state::AggregateKey;
+
 const ATTESTATION_PREFIX: &[u8] = b"beam.attestation.v1";
+const VOUCHER_PREFIX: &[u8] = b"beam.voucher.v1";
+const CONDITION_PREFIX: &[u8] = b"beam.condition.v1";
+const PAYER_BUNDLE_PREFIX: &[u8] = b"beam.bundle.v1";
 // Verifier service public key - signs attestation envelopes
 // Generated: 2025-01-27
 // Private key stored in verifier service .env (VERIFIER_SIGNING_KEY)
 const VERIFIER_PUBKEY_BYTES: [u8; 32] = [
     87, 206, 238, 248, 74, 20, 230, 164, 179, 203, 197, 110, 238, 157, 193, 117, 227, 137, 50, 120, 126, 101, 72, 203, 104, 54, 224, 253, 192, 80, 235, 17
 ];
-const MAX_ATTESTATION_AGE: i64 = 86_400; // 24 hours
+pub const MAX_ATTESTATION_AGE: i64 = 86_400; // 24 hours
+/// Bit in `AttestationPolicy::accepted_schemes` for ed25519 verifier signatures
+pub const ATTESTATION_SCHEME_ED25519: u8 = 1 << 0;
+/// Bit for payer proofs signed by the escrow's registered key shares
+pub const ATTESTATION_SCHEME_AGGREGATE: u8 = 1 << 1;
+/// The primary verifier as a signing account, for its heartbeat
+pub const VERIFIER_PUBKEY: Pubkey = Pubkey::new_from_array(VERIFIER_PUBKEY_BYTES);
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
 pub enum AttestationRole {
@@ -17,12 +28,68 @@ pub enum AttestationRole {
     Merchant,
 }
 
+/// Latest point a bundle may settle. Slot deadlines are immune to validator clock drift.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum SettlementDeadline {
+    Timestamp(i64),
+    Slot(u64),
+}
+
+impl SettlementDeadline {
+    /// Settlement is allowed up to and including the deadline itself
+    pub fn has_passed(&self, now: i64, slot: u64) -> bool {
+        match *self {
+            SettlementDeadline::Timestamp(deadline) => now > deadline,
+            SettlementDeadline::Slot(deadline) => slot > deadline,
+        }
+    }
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone)]
 pub struct AttestationProof {
     pub attestation_root: [u8; 32],
     pub attestation_nonce: [u8; 32],
     pub attestation_timestamp: i64,
     pub verifier_signature: [u8; 64],
+    /// Committed in the attestation root when present
+    pub deadline: Option<SettlementDeadline>,
+    /// Set only on proofs signed by the fallback verifier while the primary is
+    /// down; committed in the attestation root
+    pub fallback_reason: Option<u8>,
+    /// Payer proofs only: signatures over the attestation root by the escrow's
+    /// registered key shares, replacing the verifier signature
+    pub aggregate_signatures: Option<Vec<ShareSignature>>,
+    /// Set only on voucher proofs, whose root commits this window in place of
+    /// the attestation timestamp; the proof is then valid for the whole window
+    pub validity_window: Option<ValidityWindow>,
+}
+
+/// Lifetime of a voucher proof, inclusive at both ends
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ValidityWindow {
+    pub valid_from: i64,
+    pub valid_until: i64,
+}
+
+impl ValidityWindow {
+    pub fn is_ordered(&self) -> bool {
+        self.valid_from < self.valid_until
+    }
+
+    pub fn duration(&self) -> i64 {
+        self.valid_until.saturating_sub(self.valid_from)
+    }
+
+    pub fn contains(&self, now: i64) -> bool {
+        self.valid_from <= now && now <= self.valid_until
+    }
+}
+
+/// One key share's ed25519 signature, `share_index` into `AggregateKey::shares`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct ShareSignature {
+    pub share_index: u8,
+    pub signature: [u8; 64],
 }
 
 impl Default for AttestationProof {
@@ -32,16 +99,93 @@ impl Default for AttestationProof {
             attestation_nonce: [0u8; 32],
             attestation_timestamp: 0,
             verifier_signature: [0u8; 64],
+            deadline: None,
+            fallback_reason: None,
+            aggregate_signatures: None,
+            validity_window: None,
         }
     }
 }
 
+impl AttestationProof {
+    /// Reject trivially forged proofs before any hashing: an all-zero nonce, root or
+    /// signature (what `Default` produces) can never be a genuine verifier output.
+    /// Aggregate proofs carry share signatures instead of a verifier signature.
+    pub fn is_well_formed(&self) -> bool {
+        let signed = match self.aggregate_signatures.as_ref() {
+            Some(signatures) => !signatures.is_empty(),
+            None => self.verifier_signature != [0u8; 64],
+        };
+        self.attestation_nonce != [0u8; 32] && self.attestation_root != [0u8; 32] && signed
+    }
+}
+
+/// Courier who carried the bundle to connectivity and the fee the payer promised it
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct CourierCommitment {
+    pub courier: Pubkey,
+    pub fee: u64,
+}
+
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
 pub struct SettlementEvidence {
     pub payer_proof: Option<AttestationProof>,
     pub merchant_proof: Option<AttestationProof>,
+    /// Merchant order reference from the bundle, committed in both attestations
+    pub order_ref: Option<[u8; 16]>,
+    /// Courier fee from the bundle, committed in both attestations
+    pub courier: Option<CourierCommitment>,
+    /// Device that signed the bundle, committed in both attestations. Selects the
+    /// device's nonce registry; absent for bundles from the primary registry.
+    pub device_id_hash: Option<[u8; 32]>,
+    /// Real-world condition the payment depends on, e.g. a hash of the shipment
+    /// id; committed in both attestations. Such a bundle only settles with
+    /// `condition_signature`.
+    pub condition_id: Option<[u8; 32]>,
+    /// The config's condition oracle signing `condition_message(condition_id)`
+    /// once the condition is met
+    pub condition_signature: Option<[u8; 64]>,
+}
+
+/// Every bundle field an attestation commits to, as `verify_attestation_only`
+/// takes them
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct BundleFields {
+    pub bundle_id: String,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    pub order_ref: Option<[u8; 16]>,
+    pub courier: Option<CourierCommitment>,
+    pub device_id_hash: Option<[u8; 32]>,
+    pub condition_id: Option<[u8; 32]>,
 }
 
+impl SettlementEvidence {
+    /// The order reference, zeroed when absent
+    pub fn order_ref(&self) -> [u8; 16] {
+        self.order_ref.unwrap_or_default()
+    }
+
+    /// When the bundle was created: the payer's attestation timestamp, else the
+    /// merchant's, zero without either. Voucher proofs carry no creation time, so
+    /// they count as none. Only meaningful once the proofs are verified.
+    pub fn bundle_created_at(&self) -> i64 {
+        self.payer_proof
+            .as_ref()
+            .or(self.merchant_proof.as_ref())
+            .filter(|proof| proof.validity_window.is_none())
+            .map_or(0, |proof| proof.attestation_timestamp)
+    }
+}
+
+/// What the condition oracle signs to confirm `condition_id` is met
+pub fn condition_message(condition_id: &[u8; 32]) -> Vec<u8> {
+    [CONDITION_PREFIX, condition_id.as_ref()].concat()
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn verify_attestation(
     proof: &AttestationProof,
     role: AttestationRole,
@@ -50,42 +194,312 @@ pub fn verify_attestation(
     merchant: &Pubkey,
     amount: u64,
     bundle_nonce: u64,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+    fallback_verifier: &Pubkey,
+    aggregate_key: Option<&AggregateKey>,
     now: i64,
 ) -> bool {
-    if proof.attestation_timestamp <= 0 || (now - proof.attestation_timestamp).abs() > MAX_ATTESTATION_AGE {
+    if !proof.is_well_formed() {
         return false;
     }
 
-    let expected_root = compute_attestation_root(
+    // Vouchers are checked against their own window instead of the attestation age
+    let current = match proof.validity_window {
+        Some(window) => window.contains(now),
+        None => {
+            proof.attestation_timestamp > 0
+                && (now - proof.attestation_timestamp).abs() <= MAX_ATTESTATION_AGE
+        }
+    };
+    if !current {
+        return false;
+    }
+
+    let expected_root = expected_root(
+        proof,
         role,
         bundle_id,
         payer,
         merchant,
         amount,
         bundle_nonce,
-        &proof.attestation_nonce,
-        proof.attestation_timestamp,
+        order_ref,
+        courier,
+        device_id_hash,
+        condition_id,
     );
-
     if proof.attestation_root != expected_root {
         return false;
     }
 
+    // Share signatures stand in for the verifier's; they must reach the threshold
+    if let Some(signatures) = proof.aggregate_signatures.as_ref() {
+        return aggregate_key.is_some_and(|key| {
+            key.is_registered() && key.signed_weight(&expected_root, signatures) >= key.threshold
+        });
+    }
+
+    verifier_signed(proof, &expected_root, fallback_verifier)
+}
+
+/// The root `proof` must carry to attest these bundle fields: its voucher root
+/// when it has a validity window, its attestation root otherwise
+#[allow(clippy::too_many_arguments)]
+pub fn expected_root(
+    proof: &AttestationProof,
+    role: AttestationRole,
+    bundle_id: &str,
+    payer: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+    bundle_nonce: u64,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+) -> [u8; 32] {
+    match proof.validity_window {
+        Some(window) => compute_voucher_root(
+            role,
+            bundle_id,
+            payer,
+            merchant,
+            amount,
+            bundle_nonce,
+            &proof.attestation_nonce,
+            &window,
+            order_ref,
+            courier,
+            device_id_hash,
+            condition_id,
+        ),
+        None => compute_attestation_root(
+            role,
+            bundle_id,
+            payer,
+            merchant,
+            amount,
+            bundle_nonce,
+            &proof.attestation_nonce,
+            proof.attestation_timestamp,
+            proof.deadline,
+            order_ref,
+            courier,
+            device_id_hash,
+            condition_id,
+            proof.fallback_reason,
+        ),
+    }
+}
+
+/// The deadline a verifier attested `proof`'s bundle with, when `proof` is a
+/// genuine verifier attestation of exactly these bundle fields. Unlike
+/// `verify_attestation` the proof may be of any age, so a settled bundle's
+/// attestation still counts as evidence long after it settled.
+#[allow(clippy::too_many_arguments)]
+pub fn attested_deadline(
+    proof: &AttestationProof,
+    role: AttestationRole,
+    bundle_id: &str,
+    payer: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+    bundle_nonce: u64,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+    fallback_verifier: &Pubkey,
+) -> Option<SettlementDeadline> {
+    let root = attested_root(
+        proof,
+        role,
+        bundle_id,
+        payer,
+        merchant,
+        amount,
+        bundle_nonce,
+        order_ref,
+        courier,
+        device_id_hash,
+        condition_id,
+    )?;
+    if !verifier_signed(proof, &root, fallback_verifier) {
+        return None;
+    }
+    proof.deadline
+}
+
+/// Whether `verifier` itself signed `proof` as an attestation of exactly these
+/// bundle fields, whichever role it held when it did. Of any age, like
+/// `attested_deadline`.
+#[allow(clippy::too_many_arguments)]
+pub fn attested_by(
+    verifier: &Pubkey,
+    proof: &AttestationProof,
+    role: AttestationRole,
+    bundle_id: &str,
+    payer: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+    bundle_nonce: u64,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+) -> bool {
+    attested_root(
+        proof,
+        role,
+        bundle_id,
+        payer,
+        merchant,
+        amount,
+        bundle_nonce,
+        order_ref,
+        courier,
+        device_id_hash,
+        condition_id,
+    )
+    .is_some_and(|root| signature_is_valid(verifier, &root, &proof.verifier_signature))
+}
+
+/// `proof`'s attestation root when it commits exactly these bundle fields.
+/// Vouchers commit no deadline and share signatures aren't a verifier's, so
+/// neither counts.
+#[allow(clippy::too_many_arguments)]
+fn attested_root(
+    proof: &AttestationProof,
+    role: AttestationRole,
+    bundle_id: &str,
+    payer: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+    bundle_nonce: u64,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+) -> Option<[u8; 32]> {
+    if !proof.is_well_formed()
+        || proof.validity_window.is_some()
+        || proof.aggregate_signatures.is_some()
+    {
+        return None;
+    }
+    let expected_root = expected_root(
+        proof,
+        role,
+        bundle_id,
+        payer,
+        merchant,
+        amount,
+        bundle_nonce,
+        order_ref,
+        courier,
+        device_id_hash,
+        condition_id,
+    );
+    (proof.attestation_root == expected_root).then_some(expected_root)
+}
+
+/// Whether the verifier signed `root`: the fallback key for proofs carrying a
+/// fallback reason, the primary key otherwise
+fn verifier_signed(proof: &AttestationProof, root: &[u8; 32], fallback_verifier: &Pubkey) -> bool {
     let signature = match Signature::from_bytes(&proof.verifier_signature) {
         Ok(sig) => sig,
         Err(_) => return false,
     };
 
-    let verifying_key = match PublicKey::from_bytes(&VERIFIER_PUBKEY_BYTES) {
+    // Fallback proofs are only ever checked against the fallback key
+    let key_bytes = match proof.fallback_reason {
+        Some(_) => fallback_verifier.to_bytes(),
+        None => VERIFIER_PUBKEY_BYTES,
+    };
+    let verifying_key = match PublicKey::from_bytes(&key_bytes) {
         Ok(key) => key,
         Err(_) => return false,
     };
 
-    verifying_key
-        .verify(expected_root.as_ref(), &signature)
-        .is_ok()
+    verifying_key.verify(root.as_ref(), &signature).is_ok()
+}
+
+/// What a payer signs to commit to a bundle in a form the program can check:
+/// the bundle's hash with the merchant, amount and nonce it pays. Fraud reports
+/// present such a signature as proof of what the payer actually agreed to.
+pub fn payer_bundle_digest(
+    bundle_hash: &[u8; 32],
+    merchant: &Pubkey,
+    amount: u64,
+    nonce: u64,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(PAYER_BUNDLE_PREFIX);
+    hasher.update(bundle_hash);
+    hasher.update(merchant.as_ref());
+    hasher.update(amount.to_le_bytes());
+    hasher.update(nonce.to_le_bytes());
+    hasher.finalize().into()
+}
+
+/// Whether `signature` is `signer`'s ed25519 signature over `message`
+pub fn signature_is_valid(signer: &Pubkey, message: &[u8], signature: &[u8; 64]) -> bool {
+    let (Ok(signature), Ok(key)) = (
+        Signature::from_bytes(signature),
+        PublicKey::from_bytes(&signer.to_bytes()),
+    ) else {
+        return false;
+    };
+    key.verify(message, &signature).is_ok()
 }
 
+fn role_byte(role: AttestationRole) -> [u8; 1] {
+    match role {
+        AttestationRole::Payer => [0u8],
+        AttestationRole::Merchant => [1u8],
+    }
+}
+
+/// Tags of the optional bundle extras in an attestation or voucher root, after
+/// the deadline's 0 (timestamp) and 1 (slot)
+const EXTRA_ORDER_REF: u8 = 2;
+const EXTRA_COURIER: u8 = 3;
+const EXTRA_DEVICE_ID: u8 = 4;
+const EXTRA_CONDITION_ID: u8 = 5;
+
+/// The bundle commitments appended after the timing fields of either root
+fn commit_bundle_extras(
+    hasher: &mut Sha256,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+) {
+    // Each extra is tagged, so dropping one never reads as another of the same
+    // length. A zeroed order reference means none was set.
+    if *order_ref != [0u8; 16] {
+        hasher.update([EXTRA_ORDER_REF]);
+        hasher.update(order_ref);
+    }
+    if let Some(courier) = courier {
+        hasher.update([EXTRA_COURIER]);
+        hasher.update(courier.courier.as_ref());
+        hasher.update(courier.fee.to_le_bytes());
+    }
+    if let Some(device_id_hash) = device_id_hash {
+        hasher.update([EXTRA_DEVICE_ID]);
+        hasher.update(device_id_hash);
+    }
+    if let Some(condition_id) = condition_id {
+        hasher.update([EXTRA_CONDITION_ID]);
+        hasher.update(condition_id);
+    }
+}
+
+#[allow(clippy::too_many_arguments)]
 pub fn compute_attestation_root(
     role: AttestationRole,
     bundle_id: &str,
@@ -95,14 +509,16 @@ pub fn compute_attestation_root(
     bundle_nonce: u64,
     attestation_nonce: &[u8; 32],
     attestation_timestamp: i64,
+    deadline: Option<SettlementDeadline>,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+    fallback_reason: Option<u8>,
 ) -> [u8; 32] {
     let amount_bytes = amount.to_le_bytes();
     let nonce_bytes = bundle_nonce.to_le_bytes();
     let timestamp_bytes = attestation_timestamp.to_le_bytes();
-    let role_byte: [u8; 1] = match role {
-        AttestationRole::Payer => [0u8],
-        AttestationRole::Merchant => [1u8],
-    };
 
     // Use SHA256 for attestation root computation (matches verifier and tests)
     let mut hasher = Sha256::new();
@@ -110,11 +526,63 @@ pub fn compute_attestation_root(
     hasher.update(bundle_id.as_bytes());
     hasher.update(payer.as_ref());
     hasher.update(merchant.as_ref());
-    hasher.update(&amount_bytes);
-    hasher.update(&nonce_bytes);
-    hasher.update(&role_byte);
+    hasher.update(amount_bytes);
+    hasher.update(nonce_bytes);
+    hasher.update(role_byte(role));
+    hasher.update(attestation_nonce);
+    hasher.update(timestamp_bytes);
+    // Appended only when set, so roots of proofs without a deadline are unchanged
+    match deadline {
+        Some(SettlementDeadline::Timestamp(ts)) => {
+            hasher.update([0u8]);
+            hasher.update(ts.to_le_bytes());
+        }
+        Some(SettlementDeadline::Slot(slot)) => {
+            hasher.update([1u8]);
+            hasher.update(slot.to_le_bytes());
+        }
+        None => {}
+    }
+    commit_bundle_extras(&mut hasher, order_ref, courier, device_id_hash, condition_id);
+    if let Some(reason) = fallback_reason {
+        hasher.update([reason]);
+    }
+
+    let hash_result = hasher.finalize();
+    let mut hash_bytes = [0u8; 32];
+    hash_bytes.copy_from_slice(&hash_result);
+    hash_bytes
+}
+
+/// Root of a voucher proof. Under its own prefix so it can never collide with an
+/// attestation root; commits the validity window where those commit a timestamp.
+#[allow(clippy::too_many_arguments)]
+pub fn compute_voucher_root(
+    role: AttestationRole,
+    bundle_id: &str,
+    payer: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+    bundle_nonce: u64,
+    attestation_nonce: &[u8; 32],
+    window: &ValidityWindow,
+    order_ref: &[u8; 16],
+    courier: Option<&CourierCommitment>,
+    device_id_hash: Option<&[u8; 32]>,
+    condition_id: Option<&[u8; 32]>,
+) -> [u8; 32] {
+    let mut hasher = Sha256::new();
+    hasher.update(VOUCHER_PREFIX);
+    hasher.update(bundle_id.as_bytes());
+    hasher.update(payer.as_ref());
+    hasher.update(merchant.as_ref());
+    hasher.update(amount.to_le_bytes());
+    hasher.update(bundle_nonce.to_le_bytes());
+    hasher.update(role_byte(role));
     hasher.update(attestation_nonce);
-    hasher.update(&timestamp_bytes);
+    hasher.update(window.valid_from.to_le_bytes());
+    hasher.update(window.valid_until.to_le_bytes());
+    commit_bundle_extras(&mut hasher, order_ref, courier, device_id_hash, condition_id);
 
     let hash_result = hasher.finalize();
     let mut hash_bytes = [0u8; 32];
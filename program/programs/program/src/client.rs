@@ -0,0 +1,32 @@
+//! Off-chain helpers shared by Beam clients, built with the `client` feature.
+
+use crate::OfflineEscrowAccount;
+
+/// Convert a UI amount such as `"12.5"` to base units of a mint with `decimals`.
+/// Returns `None` for malformed input, more fractional digits than the mint
+/// has, or overflow; it never rounds, so a mistyped amount can't quietly change.
+pub fn ui_amount_to_base_units(ui_amount: &str, decimals: u8) -> Option<u64> {
+    let ui_amount = ui_amount.trim();
+    let (whole, fraction) = ui_amount.split_once('.').unwrap_or((ui_amount, ""));
+    let is_digits = |part: &str| part.bytes().all(|byte| byte.is_ascii_digit());
+    if (whole.is_empty() && fraction.is_empty())
+        || !is_digits(whole)
+        || !is_digits(fraction)
+        || fraction.len() > usize::from(decimals)
+    {
+        return None;
+    }
+
+    let parse = |part: &str| if part.is_empty() { Some(0) } else { part.parse::<u64>().ok() };
+    let scale = 10u64.checked_pow(u32::from(decimals))?;
+    let fraction_scale = 10u64.checked_pow(u32::from(decimals) - fraction.len() as u32)?;
+    parse(whole)?
+        .checked_mul(scale)?
+        .checked_add(parse(fraction)?.checked_mul(fraction_scale)?)
+}
+
+/// `ui_amount_to_base_units` against the decimals recorded on `escrow`; `None`
+/// until they are recorded (see `record_mint_decimals`)
+pub fn escrow_ui_amount_to_base_units(escrow: &OfflineEscrowAccount, ui_amount: &str) -> Option<u64> {
+    ui_amount_to_base_units(ui_amount, escrow.mint_decimals()?)
+}
@@ -0,0 +1,87 @@
+//! Optional compliance gate for deployments that must screen both parties of a
+//! payment against an external KYC registry.
+//!
+//! The gate is off while `ProgramConfig::compliance_registry` is the default key.
+//! Once it names a registry program, every settlement passes that registry's
+//! record of the payer and then of the merchant as its first remaining accounts,
+//! ahead of any receipt accounts, and fails with `ComplianceCheckFailed` unless
+//! both approve. Paths that move funds to more than one party at a time refuse to
+//! run instead. Withdrawals, funding and fraud reports never consult the
+//! registry, so turning the gate on can't strand balances already in escrows.
+//!
+//! A registry only has to keep one account per screened wallet, owned by the
+//! registry program at the address it derives from `[COMPLIANCE_RECORD_SEED, wallet]`:
+//!
+//! | offset | type     | field                                          |
+//! |--------|----------|------------------------------------------------|
+//! | 0      | [u8; 8]  | discriminator, ignored                         |
+//! | 8      | u8       | `approved`, 1 while the wallet may transact    |
+//! | 9      | i64 (LE) | `expires_at`, unix time; zero never expires    |
+//!
+//! An Anchor registry gets this shape from an `#[account]` struct declaring
+//! `approved: bool` and `expires_at: i64` first. Further fields may follow.
+
+use anchor_lang::prelude::*;
+
+use crate::state::ProgramConfig;
+use crate::BeamError;
+
+pub const COMPLIANCE_RECORD_SEED: &[u8] = b"compliance";
+/// Bytes of a record this program reads
+pub const COMPLIANCE_RECORD_LEN: usize = 17;
+/// Records a settlement passes while the gate is on: payer, then merchant
+const COMPLIANCE_RECORD_COUNT: usize = 2;
+
+/// Remaining accounts taken up by compliance records under `config`, so receipt
+/// accounts can be found after them
+#[cfg(feature = "receipt-nft")]
+pub fn record_count(config: &ProgramConfig) -> usize {
+    if config.is_compliance_enabled() {
+        COMPLIANCE_RECORD_COUNT
+    } else {
+        0
+    }
+}
+
+/// Require the registry's approval of `payer` and `merchant` from the records
+/// leading `accounts`. Always passes while the gate is off.
+pub fn check_parties(
+    config: &ProgramConfig,
+    accounts: &[AccountInfo],
+    payer: &Pubkey,
+    merchant: &Pubkey,
+    now: i64,
+) -> std::result::Result<(), BeamError> {
+    if !config.is_compliance_enabled() {
+        return Ok(());
+    }
+    let Some([payer_record, merchant_record]) = accounts.first_chunk::<COMPLIANCE_RECORD_COUNT>()
+    else {
+        return Err(BeamError::ComplianceCheckFailed);
+    };
+    let registry = &config.compliance_registry;
+    if !is_approved(registry, payer_record, payer, now)
+        || !is_approved(registry, merchant_record, merchant, now)
+    {
+        return Err(BeamError::ComplianceCheckFailed);
+    }
+    Ok(())
+}
+
+fn is_approved(registry: &Pubkey, record: &AccountInfo, wallet: &Pubkey, now: i64) -> bool {
+    let (expected, _) =
+        Pubkey::find_program_address(&[COMPLIANCE_RECORD_SEED, wallet.as_ref()], registry);
+    if record.key() != expected || record.owner != registry {
+        return false;
+    }
+    let Ok(data) = record.try_borrow_data() else {
+        return false;
+    };
+    if data.len() < COMPLIANCE_RECORD_LEN {
+        return false;
+    }
+    let Ok(expires_at) = data[9..COMPLIANCE_RECORD_LEN].try_into().map(i64::from_le_bytes) else {
+        return false;
+    };
+    data[8] == 1 && (expires_at == 0 || now <= expires_at)
+}
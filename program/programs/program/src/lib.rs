@@ -1,13 +1,44 @@
+// Anchor's generated IDL instructions still call the deprecated `realloc`
+#![allow(deprecated)]
+
 mod state;
 use anchor_lang::prelude::*;
-use anchor_spl::token::{self, Token, TokenAccount, Transfer};
-use anchor_lang::solana_program::keccak;
+use anchor_spl::associated_token::get_associated_token_address_with_program_id;
+use anchor_spl::token_interface::{self as token, CloseAccount, Mint, TokenAccount, TokenInterface, Transfer};
+use anchor_lang::solana_program::hash;
+use anchor_lang::solana_program::program::set_return_data;
+use anchor_lang::solana_program::instruction::{get_stack_height, TRANSACTION_LEVEL_STACK_HEIGHT};
+use anchor_lang::solana_program::sysvar::instructions as instructions_sysvar;
 
 mod attestation;
-use crate::attestation::{SettlementEvidence, AttestationRole, verify_attestation};
-use crate::state::{BundleRecord, FraudReason, NonceRegistry, MAX_BUNDLE_HISTORY, MAX_FRAUD_RECORDS};
+mod compliance;
+#[cfg(feature = "receipt-nft")]
+mod receipt;
+#[cfg(feature = "client")]
+pub mod client;
+mod token_fee;
+use crate::token_fee::{inverse_transfer_fee, transfer_fee, transfer_tokens};
+use crate::attestation::{
+    AttestationProof, BundleFields, CourierCommitment, SettlementEvidence, AttestationRole, condition_message,
+    attested_by, expected_root, signature_is_valid, verify_attestation,
+    ATTESTATION_SCHEME_AGGREGATE, ATTESTATION_SCHEME_ED25519, MAX_ATTESTATION_AGE, VERIFIER_PUBKEY,
+};
+use crate::state::{
+    is_valid_label, AggregateKey, Allowance, RoundupConfig, AttestationPolicy, BatchSettlementItem, CreditLine, MAX_CREDIT_FEE_BPS, DelegatedSpender, BatchSettlementResult, BundleRecord, CashbackProgram,
+    AdminAction, AdminAuditLog, BundleHashAlgo, Chargeback, ChargebackOutcome, FeePayer, ChargebackReason, ChargebackStatus, ConfigUpdate, EscrowAddress, EscrowSummary, FraudArchive, AttestationVerification, EvidenceVerification, BundleAttestation, FraudEvidence, FraudReason, FraudSummary, ProofVerification, Guarantee, MultihopLeg,
+    FundingTranche, Invoice, MerchantLimit, MAX_MERCHANT_LIMITS, EscrowWatcher, MAX_ESCROW_WATCHERS, InvoiceMode, InvoiceStatus, MAX_PERMIT_SUMMARIES, NonceRegistry, OwnerTombstone,
+    Permit, PermitSummary, Preauthorization, MAX_PREAUTHORIZATIONS, VerifierBond, VerifierFeeAccount, VerifierHeartbeat, VerifierSlash, SettlementHold,
+    ProgramConfig, TransferFeePayer, BATCH_ITEM_SETTLED,
+    HISTORY_EXPORT_HEADER_LEN, HISTORY_EXPORT_VERSION, INVOICE_KEEPER_CUT_BPS, MAX_EXPORT_RECORDS, MAX_ROLLING_WINDOW_DAYS,
+    IdentityReputation, SettlementCheck, SettlementPreflight, SettlementReceipt, SpendBucket,
+    MAX_BATCH_SIZE, MAX_CASHBACK_BPS, MAX_CONSOLIDATED_ESCROWS, MAX_FEE_BPS, MAX_FUNDING_LOCKUP,
+    MAX_FRAUD_WITHDRAWAL_DELAY, MAX_ARBITERS, MAX_REPUTATION_ADJUSTMENT, MAX_PERIOD_REPUTATION_ADJUSTMENT,
+    REPUTATION_ADJUSTMENT_PERIOD, MAX_BACKING_TOKEN_ACCOUNTS, MAX_FRAUD_PENALTY_BPS, FRAUD_REASON_COUNT, SUNSET_TIMELOCK, ESCROW_REPUTATION_MIGRATED, ESCROW_DORMANT, ESCROW_PROCESSING, ESCROW_MINT_DECIMALS_RECORDED, ESCROW_AMOUNT_CHECK_OPTED_OUT, MintAmountCap, MIN_DORMANCY_PERIOD, MIN_ESCHEAT_NOTICE_PERIOD, MAX_VOUCHER_VALIDITY, MAX_NONCE_CORRECTION_WINDOW, MAX_CHARGEBACK_WINDOW, MAX_SETTLEMENT_HOLD_PERIOD, MAX_VERIFIER_BOND_COOLDOWN, CHARGEBACK_URI_LEN, with_flag, MIN_REPUTATION, MAX_REPUTATION, INITIAL_REPUTATION, FRAUD_REPUTATION_PENALTY,
+    MAX_FUNDING_TRANCHES, MAX_PENDING_NONCES, MAX_PENDING_LIABILITIES, LegacyRegistryLayout, RegistryCapacity, MAX_REGISTRY_GROWTH_STEPS, COUNTER_STATEMENT_LEN, PendingLiability, MIN_BENEFICIARY_INACTIVITY, BENEFICIARY_NOTICE_PERIOD,
+    ROTATION_GRACE_PERIOD, PUNCTUALITY_BUCKETS, PUNCTUALITY_BUCKET_COUNT,
+};
 
-const MAX_RECENT_HASHES: usize = 16;
+const SECONDS_PER_DAY: i64 = 86_400;
 
 
 declare_id!("6BjVpGR1pGJ41xDJF4mMuvC7vymFBZ8QXxoRKFqsuDDi");
@@ -16,221 +47,446 @@ declare_id!("6BjVpGR1pGJ41xDJF4mMuvC7vymFBZ8QXxoRKFqsuDDi");
 pub mod beam {
     use super::*;
 
-    /// Initialize escrow account for offline payments
-    pub fn initialize_escrow(ctx: Context<InitializeEscrow>, initial_amount: u64) -> Result<()> {
-        let escrow = &mut ctx.accounts.escrow_account;
-        escrow.owner = ctx.accounts.owner.key();
-        escrow.escrow_token_account = ctx.accounts.escrow_token_account.key();
-        escrow.escrow_balance = 0;
-        escrow.last_nonce = 0;
-        escrow.reputation_score = 100;
-        escrow.total_spent = 0;
-        escrow.created_at = Clock::get()?.unix_timestamp;
-        escrow.bump = ctx.bumps.escrow_account;
-        // Phase 1.3: Initialize fraud detection fields
-        escrow.stake_locked = 0;
-        escrow.fraud_count = 0;
-        escrow.last_fraud_timestamp = 0;
+    /// Create the global program config. Only the program's upgrade authority may call this,
+    /// and it becomes the config admin.
+    /// `bundle_hash_algo` is fixed for the life of the deployment
+    pub fn initialize_config(
+        ctx: Context<InitializeConfig>,
+        funding_lockup_secs: i64,
+        bundle_hash_algo: BundleHashAlgo,
+    ) -> Result<()> {
+        require!(
+            (0..=MAX_FUNDING_LOCKUP).contains(&funding_lockup_secs),
+            BeamError::InvalidConfig
+        );
 
-        // Transfer initial funds to escrow
-        if initial_amount > 0 {
-            let cpi_accounts = Transfer {
-                from: ctx.accounts.owner_token_account.to_account_info(),
-                to: ctx.accounts.escrow_token_account.to_account_info(),
-                authority: ctx.accounts.owner.to_account_info(),
-            };
-            let cpi_program = ctx.accounts.token_program.to_account_info();
-            let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-            token::transfer(cpi_ctx, initial_amount)?;
+        let config = &mut ctx.accounts.config;
+        config.admin = ctx.accounts.admin.key();
+        config.funding_lockup_secs = funding_lockup_secs;
+        config.bundle_hash_algo = bundle_hash_algo;
+        config.bump = ctx.bumps.config;
 
-            escrow.escrow_balance = initial_amount;
-        }
+        let audit_log = &mut ctx.accounts.audit_log;
+        audit_log.bump = ctx.bumps.audit_log;
+        record_admin_action(
+            audit_log,
+            config.admin,
+            AdminAction::InitializeConfig,
+            &(funding_lockup_secs, bundle_hash_algo),
+        )?;
 
-        emit!(EscrowInitialized {
-            owner: escrow.owner,
-            initial_balance: initial_amount,
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            update: ConfigUpdate {
+                funding_lockup_secs: Some(funding_lockup_secs),
+                ..Default::default()
+            },
         });
 
         Ok(())
     }
 
-    /// Add funds to existing escrow
-    pub fn fund_escrow(ctx: Context<FundEscrow>, amount: u64) -> Result<()> {
-        require!(amount > 0, BeamError::InvalidAmount);
+    /// Update config fields; `None` leaves a field unchanged
+    pub fn update_config(ctx: Context<UpdateConfig>, update: ConfigUpdate) -> Result<()> {
+        let config = &mut ctx.accounts.config;
 
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.owner_token_account.to_account_info(),
-            to: ctx.accounts.escrow_token_account.to_account_info(),
-            authority: ctx.accounts.owner.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new(cpi_program, cpi_accounts);
-        token::transfer(cpi_ctx, amount)?;
+        if let Some(funding_lockup_secs) = update.funding_lockup_secs {
+            require!(
+                (0..=MAX_FUNDING_LOCKUP).contains(&funding_lockup_secs),
+                BeamError::InvalidConfig
+            );
+            config.funding_lockup_secs = funding_lockup_secs;
+        }
+        if let Some(rolling_window_days) = update.rolling_window_days {
+            require!(
+                rolling_window_days as usize <= MAX_ROLLING_WINDOW_DAYS,
+                BeamError::InvalidConfig
+            );
+            config.rolling_window_days = rolling_window_days;
+        }
+        if let Some(rolling_cap) = update.rolling_cap {
+            config.rolling_cap = rolling_cap;
+        }
+        if let Some(fee_bps) = update.fee_bps {
+            require!(fee_bps <= MAX_FEE_BPS, BeamError::InvalidConfig);
+            config.fee_bps = fee_bps;
+        }
+        if let Some(treasury) = update.treasury {
+            config.treasury = treasury;
+        }
+        if let Some(referral_reward) = update.referral_reward {
+            config.referral_reward = referral_reward;
+        }
+        if let Some(referral_reward_limit) = update.referral_reward_limit {
+            config.referral_reward_limit = referral_reward_limit;
+        }
+        if let Some(block_zero_reputation) = update.block_zero_reputation {
+            config.set_zero_reputation_blocked(block_zero_reputation);
+        }
+        if let Some(min_fee) = update.min_fee {
+            config.min_fee = min_fee;
+        }
+        if let Some(max_fee) = update.max_fee {
+            config.max_fee = max_fee;
+        }
+        if let Some(max_courier_fee) = update.max_courier_fee {
+            config.max_courier_fee = max_courier_fee;
+        }
+        if let Some(reputation_quorum_threshold) = update.reputation_quorum_threshold {
+            config.reputation_quorum_threshold = reputation_quorum_threshold;
+        }
+        if let Some(strict_vault_checks) = update.strict_vault_checks {
+            config.set_strict_vault_checks(strict_vault_checks);
+        }
+        if let Some(identity_authority) = update.identity_authority {
+            config.identity_authority = identity_authority;
+        }
+        if let Some(min_identity_reputation) = update.min_identity_reputation {
+            require!(
+                (MIN_REPUTATION..=MAX_REPUTATION).contains(&min_identity_reputation),
+                BeamError::InvalidConfig
+            );
+            config.min_identity_reputation = min_identity_reputation;
+        }
+        if let Some(require_identity) = update.require_identity {
+            config.set_identity_required(require_identity);
+        }
+        if let Some(aggregate_attestation) = update.aggregate_attestation {
+            config.set_aggregate_attestation_enabled(aggregate_attestation);
+        }
+        if let Some(dispute_reserve) = update.dispute_reserve {
+            config.set_dispute_reserve_enabled(dispute_reserve);
+        }
+        if let Some(condition_oracle) = update.condition_oracle {
+            config.condition_oracle = condition_oracle;
+        }
+        if let Some(verifier_fee) = update.verifier_fee {
+            config.verifier_fee = verifier_fee;
+        }
+        if let Some(settlement_reputation_reward) = update.settlement_reputation_reward {
+            require!(
+                settlement_reputation_reward <= MAX_REPUTATION_ADJUSTMENT,
+                BeamError::InvalidConfig
+            );
+            config.settlement_reputation_reward = settlement_reputation_reward;
+        }
+        if let Some(reputation_reward_merchant_cap) = update.reputation_reward_merchant_cap {
+            config.reputation_reward_merchant_cap = reputation_reward_merchant_cap;
+        }
+        if let Some(reputation_reward_period) = update.reputation_reward_period {
+            require!(reputation_reward_period >= 0, BeamError::InvalidConfig);
+            config.reputation_reward_period = reputation_reward_period;
+        }
+        if let Some(settlement_hold_period) = update.settlement_hold_period {
+            require!(
+                (0..=MAX_SETTLEMENT_HOLD_PERIOD).contains(&settlement_hold_period),
+                BeamError::InvalidConfig
+            );
+            config.settlement_hold_period = settlement_hold_period;
+        }
+        if let Some(custodial_onboarding) = update.custodial_onboarding {
+            config.set_custodial_onboarding(custodial_onboarding);
+        }
+        if let Some(compliance_registry) = update.compliance_registry {
+            config.compliance_registry = compliance_registry;
+        }
+        if let Some(min_verifier_bond) = update.min_verifier_bond {
+            config.min_verifier_bond = min_verifier_bond;
+        }
+        if let Some(verifier_slash_bps) = update.verifier_slash_bps {
+            require!(verifier_slash_bps <= 10_000, BeamError::InvalidConfig);
+            config.verifier_slash_bps = verifier_slash_bps;
+        }
+        if let Some(verifier_bond_cooldown) = update.verifier_bond_cooldown {
+            require!(
+                (0..=MAX_VERIFIER_BOND_COOLDOWN).contains(&verifier_bond_cooldown),
+                BeamError::InvalidConfig
+            );
+            config.verifier_bond_cooldown = verifier_bond_cooldown;
+        }
+        if let Some(transfer_fee_payer) = update.transfer_fee_payer {
+            config.transfer_fee_payer = transfer_fee_payer;
+        }
+        if let Some(max_heartbeat_age) = update.max_heartbeat_age {
+            require!(max_heartbeat_age >= 0, BeamError::InvalidConfig);
+            config.max_heartbeat_age = max_heartbeat_age;
+        }
+        if let Some(fraud_withdrawal_delay) = update.fraud_withdrawal_delay {
+            require!(
+                (0..=MAX_FRAUD_WITHDRAWAL_DELAY).contains(&fraud_withdrawal_delay),
+                BeamError::InvalidConfig
+            );
+            config.fraud_withdrawal_delay = fraud_withdrawal_delay;
+        }
+        if let Some(dormancy_period) = update.dormancy_period {
+            config.dormancy_period = dormancy_period;
+        }
+        if let Some(escheat_notice_period) = update.escheat_notice_period {
+            config.escheat_notice_period = escheat_notice_period;
+        }
+        if let Some(custodial_token_account) = update.custodial_token_account {
+            config.custodial_token_account = custodial_token_account;
+        }
+        if let Some(max_voucher_validity) = update.max_voucher_validity {
+            require!(
+                (0..=MAX_VOUCHER_VALIDITY).contains(&max_voucher_validity),
+                BeamError::InvalidConfig
+            );
+            config.max_voucher_validity = max_voucher_validity;
+        }
+        if let Some(chargeback_window) = update.chargeback_window {
+            require!(
+                (0..=MAX_CHARGEBACK_WINDOW).contains(&chargeback_window),
+                BeamError::InvalidConfig
+            );
+            config.chargeback_window = chargeback_window;
+        }
+        if let Some(chargeback_response_window) = update.chargeback_response_window {
+            require!(
+                (0..=MAX_CHARGEBACK_WINDOW).contains(&chargeback_response_window),
+                BeamError::InvalidConfig
+            );
+            config.chargeback_response_window = chargeback_response_window;
+        }
+        if let Some(fee_payer) = update.fee_payer {
+            config.fee_payer = fee_payer;
+        }
+        if let Some(reputation_decay_period) = update.reputation_decay_period {
+            require!(reputation_decay_period >= 0, BeamError::InvalidConfig);
+            config.reputation_decay_period = reputation_decay_period;
+        }
+        if let Some(reputation_decay_points) = update.reputation_decay_points {
+            require!(
+                reputation_decay_points <= MAX_REPUTATION_ADJUSTMENT,
+                BeamError::InvalidConfig
+            );
+            config.reputation_decay_points = reputation_decay_points;
+        }
+        if let Some(nonce_correction_window) = update.nonce_correction_window {
+            require!(
+                (0..=MAX_NONCE_CORRECTION_WINDOW).contains(&nonce_correction_window),
+                BeamError::InvalidConfig
+            );
+            config.nonce_correction_window = nonce_correction_window;
+        }
+        if let Some(reputation_decay_baseline) = update.reputation_decay_baseline {
+            require!(
+                (MIN_REPUTATION..=MAX_REPUTATION).contains(&reputation_decay_baseline),
+                BeamError::InvalidConfig
+            );
+            config.reputation_decay_baseline = reputation_decay_baseline;
+        }
+        require!(
+            config.max_fee == 0 || config.min_fee <= config.max_fee,
+            BeamError::InvalidConfig
+        );
+        // A chargeback must always leave the merchant a chance to respond
+        require!(
+            config.chargeback_window == 0 || config.chargeback_response_window > 0,
+            BeamError::InvalidConfig
+        );
+        // Escheatment periods have hard floors so it can never reach active funds quickly
+        require!(
+            config.dormancy_period == 0
+                || (config.dormancy_period >= MIN_DORMANCY_PERIOD
+                    && config.escheat_notice_period >= MIN_ESCHEAT_NOTICE_PERIOD
+                    && config.custodial_token_account != Pubkey::default()),
+            BeamError::InvalidConfig
+        );
 
-        let escrow = &mut ctx.accounts.escrow_account;
-        escrow.escrow_balance = escrow.escrow_balance.checked_add(amount)
-            .ok_or(BeamError::Overflow)?;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::UpdateConfig,
+            &update,
+        )?;
 
-        emit!(EscrowFunded {
-            owner: escrow.owner,
-            amount,
-            new_balance: escrow.escrow_balance,
+        emit!(ConfigUpdated {
+            admin: config.admin,
+            update,
         });
 
         Ok(())
     }
 
-    /// Settle offline payment (called when either party goes online)
-    pub fn settle_offline_payment(
-        ctx: Context<SettlePayment>,
-        amount: u64,
-        payer_nonce: u64,
-        bundle_id: String,
-        evidence: SettlementEvidence,
-    ) -> Result<()> {
-        require!(!bundle_id.is_empty() && bundle_id.len() <= 128, BeamError::InvalidBundleId);
-
-        let clock = Clock::get()?;
-        let now = clock.unix_timestamp;
-
-        let merchant_key = ctx.accounts.merchant.key();
+    /// Start the admin audit chain on a deployment whose config predates it
+    pub fn initialize_admin_audit_log(ctx: Context<InitializeAdminAuditLog>) -> Result<()> {
+        ctx.accounts.audit_log.bump = ctx.bumps.audit_log;
+        Ok(())
+    }
 
-        // Make attestation optional - validate only if provided
-        // For online payments, attestation can be omitted (direct wallet signature verification)
-        // For offline payments, client should provide hardware attestation
-        if let Some(payer_proof) = evidence.payer_proof.as_ref() {
+    /// Grow the config account to the current layout after an upgrade adds fields.
+    /// New fields start zeroed, which leaves every added feature disabled.
+    pub fn migrate_config(ctx: Context<MigrateConfig>) -> Result<()> {
+        let config_info = &ctx.accounts.config;
+        {
+            let data = config_info.try_borrow_data()?;
             require!(
-                verify_attestation(
-                    payer_proof,
-                    AttestationRole::Payer,
-                    &bundle_id,
-                    &ctx.accounts.payer.key(),
-                    &merchant_key,
-                    amount,
-                    payer_nonce,
-                    now,
-                ),
-                BeamError::InvalidAttestation
+                data.len() >= 8 + 32 && data[..8] == *ProgramConfig::DISCRIMINATOR,
+                BeamError::InvalidConfig
             );
-        }
-
-        if let Some(merchant_proof) = evidence.merchant_proof.as_ref() {
             require!(
-                verify_attestation(
-                    merchant_proof,
-                    AttestationRole::Merchant,
-                    &bundle_id,
-                    &ctx.accounts.payer.key(),
-                    &merchant_key,
-                    amount,
-                    payer_nonce,
-                    now,
-                ),
-                BeamError::InvalidAttestation
+                data[8..40] == ctx.accounts.admin.key().to_bytes(),
+                BeamError::Unauthorized
             );
         }
 
-        let bundle_hash = keccak::hash(bundle_id.as_bytes()).to_bytes();
-        require!(ctx.accounts.nonce_registry.owner == ctx.accounts.payer.key(), BeamError::InvalidOwner);
+        grow_account(
+            config_info,
+            &ctx.accounts.admin,
+            &ctx.accounts.system_program,
+            8 + ProgramConfig::INIT_SPACE,
+        )?;
+
+        let mut data = config_info.try_borrow_mut_data()?;
+        let mut config = ProgramConfig::try_deserialize(&mut &data[..])?;
+        config.fold_legacy_flags();
+        config.try_serialize(&mut &mut data[..])?;
+        Ok(())
+    }
+
+    /// Nominate the next admin. Nothing changes hands until that key signs
+    /// `accept_admin`; proposing again replaces the nominee.
+    pub fn propose_admin(ctx: Context<UpdateConfig>, new_admin: Pubkey) -> Result<()> {
+        let config = &mut ctx.accounts.config;
         require!(
-            !ctx.accounts.nonce_registry.recent_bundle_hashes.iter().any(|h| *h == bundle_hash),
-            BeamError::DuplicateBundle
+            new_admin != Pubkey::default() && new_admin != config.admin,
+            BeamError::InvalidPendingAdmin
         );
+        config.pending_admin = new_admin;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::ProposeAdmin,
+            &new_admin,
+        )?;
 
-        // Verify nonce (prevent replay)
-        require!(payer_nonce > ctx.accounts.nonce_registry.last_nonce, BeamError::InvalidNonce);
-        require!(payer_nonce > ctx.accounts.escrow_account.last_nonce, BeamError::InvalidNonce);
+        emit!(AdminProposed {
+            admin: config.admin,
+            pending_admin: new_admin,
+        });
 
-        // Verify sufficient balance
-        require!(ctx.accounts.escrow_account.escrow_balance >= amount, BeamError::InsufficientFunds);
+        Ok(())
+    }
 
-        // Transfer from escrow to merchant
-        let owner_key = ctx.accounts.escrow_account.owner;
-        let bump = ctx.accounts.escrow_account.bump;
-        let seeds = &[
-            b"escrow",
-            owner_key.as_ref(),
-            &[bump],
-        ];
-        let signer = &[&seeds[..]];
+    /// Withdraw the pending nomination before it is accepted
+    pub fn cancel_admin_proposal(ctx: Context<UpdateConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let pending_admin = config.pending_admin;
+        require!(pending_admin != Pubkey::default(), BeamError::NoPendingAdmin);
+        config.pending_admin = Pubkey::default();
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::CancelAdminProposal,
+            &pending_admin,
+        )?;
 
-        let cpi_accounts = Transfer {
-            from: ctx.accounts.escrow_token_account.to_account_info(),
-            to: ctx.accounts.merchant_token_account.to_account_info(),
-            authority: ctx.accounts.escrow_account.to_account_info(),
-        };
-        let cpi_program = ctx.accounts.token_program.to_account_info();
-        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
-        token::transfer(cpi_ctx, amount)?;
+        emit!(AdminProposalCancelled {
+            admin: config.admin,
+            pending_admin,
+        });
 
-        // Update escrow state
-        let escrow = &mut ctx.accounts.escrow_account;
-        escrow.escrow_balance = escrow.escrow_balance.checked_sub(amount)
-            .ok_or(BeamError::Underflow)?;
-        escrow.last_nonce = payer_nonce;
-        escrow.total_spent = escrow.total_spent.checked_add(amount)
-            .ok_or(BeamError::Overflow)?;
-        ctx.accounts.nonce_registry.last_nonce = payer_nonce;
+        Ok(())
+    }
 
-        // Track recent bundle hashes and history for dispute resolution
-        let registry = &mut ctx.accounts.nonce_registry;
-        let recent = &mut registry.recent_bundle_hashes;
-        if recent.len() >= MAX_RECENT_HASHES {
-            recent.remove(0);
-        }
-        recent.push(bundle_hash);
+    /// Take over as admin; must be signed by the key `propose_admin` nominated
+    pub fn accept_admin(ctx: Context<AcceptAdmin>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let previous_admin = config.admin;
+        config.admin = config.pending_admin;
+        config.pending_admin = Pubkey::default();
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::AcceptAdmin,
+            &previous_admin,
+        )?;
 
-        let history = &mut registry.bundle_history;
-        if history.len() >= MAX_BUNDLE_HISTORY {
-            history.remove(0);
-        }
-        history.push(BundleRecord {
-            bundle_hash,
-            merchant: merchant_key,
-            amount,
-            settled_at: now,
-            nonce: payer_nonce,
+        emit!(AdminTransferred {
+            previous_admin,
+            new_admin: config.admin,
         });
 
-        emit!(PaymentSettled {
-            payer: owner_key,
-            merchant: merchant_key,
-            amount,
-            nonce: payer_nonce,
-            bundle_id,
+        Ok(())
+    }
+
+    /// Stop all settlements (single and batch) until `resume_settlements`.
+    /// Nothing else is affected, so users can always withdraw during an incident.
+    pub fn halt_settlements(ctx: Context<UpdateConfig>, reason: u16) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.set_settlements_halted(true);
+        config.halt_reason = reason;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::HaltSettlements,
+            &reason,
+        )?;
+
+        emit!(SettlementsHalted {
+            admin: config.admin,
+            reason,
+            halted_at: Clock::get()?.unix_timestamp,
         });
 
-        emit!(BundleHistoryRecorded {
-            payer: owner_key,
-            merchant: merchant_key,
-            bundle_hash,
-            amount,
-            nonce: payer_nonce,
-            settled_at: now,
+        Ok(())
+    }
+
+    /// Announce that the deployment is winding down. After `SUNSET_TIMELOCK` the
+    /// admin may return locked stake to owners with `emergency_unlock_stake`.
+    pub fn declare_sunset(ctx: Context<UpdateConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(config.sunset_at == 0, BeamError::SunsetAlreadyDeclared);
+        let now = Clock::get()?.unix_timestamp;
+        config.sunset_at = now
+            .checked_add(SUNSET_TIMELOCK)
+            .ok_or(BeamError::Overflow)?;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::DeclareSunset,
+            &config.sunset_at,
+        )?;
+
+        emit!(SunsetDeclared {
+            admin: config.admin,
+            declared_at: now,
+            effective_at: config.sunset_at,
         });
 
         Ok(())
     }
 
-    /// Initialize nonce registry for payer
-    pub fn initialize_nonce_registry(ctx: Context<InitializeNonceRegistry>) -> Result<()> {
-        let registry = &mut ctx.accounts.nonce_registry;
-        registry.owner = ctx.accounts.payer.key();
-        registry.last_nonce = 0;
-        registry.bump = ctx.bumps.nonce_registry;
+    pub fn cancel_sunset(ctx: Context<UpdateConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        require!(config.sunset_at != 0, BeamError::SunsetNotActive);
+        config.sunset_at = 0;
+        record_admin_action(&mut ctx.accounts.audit_log, config.admin, AdminAction::CancelSunset, &())?;
+
+        emit!(SunsetCancelled {
+            admin: config.admin,
+            cancelled_at: Clock::get()?.unix_timestamp,
+        });
+
         Ok(())
     }
 
-    /// Withdraw unused escrow funds
-    pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>, amount: u64) -> Result<()> {
+    /// Return an escrow's locked stake to its owner once the sunset is in effect.
+    /// Fraud penalties would otherwise stay trapped after the program is retired.
+    pub fn emergency_unlock_stake(ctx: Context<EmergencyUnlockStake>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        require!(ctx.accounts.config.is_sunset(now), BeamError::SunsetNotActive);
+
+        let amount = ctx.accounts.escrow_account.stake_locked;
         require!(amount > 0, BeamError::InvalidAmount);
-        require!(ctx.accounts.escrow_account.escrow_balance >= amount, BeamError::InsufficientFunds);
 
         let owner_key = ctx.accounts.escrow_account.owner;
         let bump = ctx.accounts.escrow_account.bump;
+        let seed_key = *ctx.accounts.escrow_account.seed_key();
         let seeds = &[
             b"escrow",
-            owner_key.as_ref(),
+            seed_key.as_ref(),
             &[bump],
         ];
         let signer = &[&seeds[..]];
@@ -244,406 +500,10116 @@ pub mod beam {
         let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
         token::transfer(cpi_ctx, amount)?;
 
-        let escrow = &mut ctx.accounts.escrow_account;
-        escrow.escrow_balance = escrow.escrow_balance.checked_sub(amount)
-            .ok_or(BeamError::Underflow)?;
+        ctx.accounts.escrow_account.stake_locked = 0;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            ctx.accounts.admin.key(),
+            AdminAction::EmergencyUnlockStake,
+            &(owner_key, amount),
+        )?;
 
-        emit!(EscrowWithdrawn {
+        emit!(StakeUnlocked {
             owner: owner_key,
+            admin: ctx.accounts.admin.key(),
             amount,
-            remaining_balance: escrow.escrow_balance,
         });
 
         Ok(())
     }
 
-    /// Report conflicting bundle evidence to initiate a fraud dispute
-    pub fn report_fraudulent_bundle(
-        ctx: Context<ReportFraud>,
-        bundle_id: String,
-        conflicting_hash: [u8; 32],
+    /// Replace the arbiter set. `quorum` must be reachable by the set; an empty set
+    /// with a zero quorum disables every arbiter-gated action.
+    pub fn set_arbiters(ctx: Context<UpdateConfig>, arbiters: Vec<Pubkey>, quorum: u8) -> Result<()> {
+        require!(arbiters.len() <= MAX_ARBITERS, BeamError::InvalidConfig);
+        require!(
+            usize::from(quorum) <= arbiters.len() && (quorum > 0 || arbiters.is_empty()),
+            BeamError::InvalidConfig
+        );
+        for (index, arbiter) in arbiters.iter().enumerate() {
+            require!(
+                *arbiter != Pubkey::default() && !arbiters[..index].contains(arbiter),
+                BeamError::InvalidConfig
+            );
+        }
+
+        let config = &mut ctx.accounts.config;
+        config.arbiters = [Pubkey::default(); MAX_ARBITERS];
+        config.arbiters[..arbiters.len()].copy_from_slice(&arbiters);
+        config.arbiter_quorum = quorum;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::SetArbiters,
+            &(&arbiters, quorum),
+        )?;
+
+        emit!(ArbitersUpdated {
+            admin: config.admin,
+            arbiters,
+            quorum,
+        });
+
+        Ok(())
+    }
+
+    /// Set the stake slashed for fraud reports of `reason`, in bps of the disputed
+    /// amount. Zero restores the default penalty.
+    pub fn set_fraud_penalty(
+        ctx: Context<UpdateConfig>,
         reason: FraudReason,
+        penalty_bps: u16,
     ) -> Result<()> {
-        require!(!bundle_id.is_empty() && bundle_id.len() <= 128, BeamError::InvalidBundleId);
-        require!(conflicting_hash != [0u8; 32], BeamError::InvalidBundleHash);
+        require!(penalty_bps <= MAX_FRAUD_PENALTY_BPS, BeamError::InvalidConfig);
 
-        let registry = &mut ctx.accounts.nonce_registry;
-        require_keys_eq!(registry.owner, ctx.accounts.payer.key(), BeamError::InvalidOwner);
+        let config = &mut ctx.accounts.config;
+        config.fraud_penalty_bps[reason as usize] = penalty_bps;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::SetFraudPenalty,
+            &(reason, penalty_bps),
+        )?;
 
-        let bundle_hash = keccak::hash(bundle_id.as_bytes()).to_bytes();
-        let has_record = registry
-            .bundle_history
-            .iter()
-            .any(|record| record.bundle_hash == bundle_hash);
-        require!(has_record, BeamError::BundleHistoryNotFound);
-        require!(bundle_hash != conflicting_hash, BeamError::FraudHashMatches);
+        emit!(FraudPenaltyUpdated {
+            admin: config.admin,
+            reason,
+            penalty_bps,
+        });
 
-        let duplicate = registry
-            .fraud_records
-            .iter()
-            .any(|record| record.bundle_hash == bundle_hash && record.conflicting_hash == conflicting_hash);
-        require!(!duplicate, BeamError::FraudEvidenceExists);
+        Ok(())
+    }
 
-        if registry.fraud_records.len() >= MAX_FRAUD_RECORDS {
-            registry.fraud_records.remove(0);
+    /// Set the largest single settlement in `mint` accepted from escrows that keep
+    /// the sanity check on. Zero removes the mint's ceiling.
+    pub fn set_max_reasonable_amount(
+        ctx: Context<UpdateConfig>,
+        mint: Pubkey,
+        max_amount: u64,
+    ) -> Result<()> {
+        require!(mint != Pubkey::default(), BeamError::InvalidConfig);
+
+        let config = &mut ctx.accounts.config;
+        let caps = &mut config.max_reasonable_amounts;
+        match caps.iter().position(|cap| cap.max_amount > 0 && cap.mint == mint) {
+            Some(slot) => caps[slot] = MintAmountCap { mint, max_amount },
+            None if max_amount > 0 => {
+                let slot = caps
+                    .iter()
+                    .position(|cap| cap.max_amount == 0)
+                    .ok_or(BeamError::AmountCapTableFull)?;
+                caps[slot] = MintAmountCap { mint, max_amount };
+            }
+            None => {}
         }
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::SetMaxReasonableAmount,
+            &(mint, max_amount),
+        )?;
 
-        let now = Clock::get()?.unix_timestamp;
-        registry.fraud_records.push(crate::state::FraudRecord {
-            bundle_hash,
-            conflicting_hash,
-            reporter: ctx.accounts.reporter.key(),
-            reported_at: now,
-            reason,
+        emit!(MaxReasonableAmountUpdated {
+            admin: config.admin,
+            mint,
+            max_amount,
         });
 
-        emit!(FraudEvidenceSubmitted {
-            payer: registry.owner,
-            reporter: ctx.accounts.reporter.key(),
-            bundle_hash,
-            conflicting_hash,
-            reason,
-            reported_at: now,
-        });
+        Ok(())
+    }
 
-        // Phase 1.3: Apply stake slashing for fraud
+    /// Support correction of an escrow's reputation. Needs a non-zero reason, is
+    /// bounded per call and per period, and beyond the config threshold also needs
+    /// the arbiter quorum signing in `remaining_accounts`.
+    pub fn adjust_reputation<'info>(
+        ctx: Context<'_, '_, '_, 'info, AdjustReputation<'info>>,
+        delta: i32,
+        reason_code: u16,
+    ) -> Result<()> {
+        let magnitude = delta.unsigned_abs();
+        require!(
+            delta != 0 && reason_code != 0 && magnitude <= u32::from(MAX_REPUTATION_ADJUSTMENT),
+            BeamError::InvalidReputationAdjustment
+        );
+        let config = &ctx.accounts.config;
+        let arbiters = if magnitude > u32::from(config.reputation_quorum_threshold) {
+            require_arbiter_quorum(config, ctx.remaining_accounts)?
+        } else {
+            Vec::new()
+        };
+
+        let now = Clock::get()?.unix_timestamp;
         let escrow = &mut ctx.accounts.escrow_account;
+        escrow.record_reputation_adjustment(magnitude, now)?;
+        let old_score = escrow.reputation_score;
+        escrow.apply_reputation_delta(delta);
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::AdjustReputation,
+            &(escrow.owner, delta, reason_code),
+        )?;
+
+        emit!(ReputationAdjusted {
+            owner: escrow.owner,
+            admin: config.admin,
+            reason_code,
+            delta,
+            old_score,
+            new_score: escrow.reputation_score,
+            arbiters,
+        });
+
+        Ok(())
+    }
+
+    /// Register the fallback verifier key and the largest settlement its proofs may back.
+    /// While the config sets `min_verifier_bond`, the key must have at least that much
+    /// bonded in its `verifier_bond` and not be stepping down.
+    pub fn set_fallback_verifier(
+        ctx: Context<SetFallbackVerifier>,
+        verifier: Pubkey,
+        cap: u64,
+    ) -> Result<()> {
+        require!(
+            verifier != Pubkey::default() && cap > 0,
+            BeamError::InvalidConfig
+        );
+        let min_bond = ctx.accounts.config.min_verifier_bond;
+        if min_bond > 0 {
+            let bond = ctx
+                .accounts
+                .verifier_bond
+                .as_ref()
+                .ok_or(BeamError::VerifierBondRequired)?;
+            require!(
+                !bond.is_deregistered() && bond.amount >= min_bond,
+                BeamError::VerifierBondRequired
+            );
+        }
+        let config = &mut ctx.accounts.config;
+        config.fallback_verifier = verifier;
+        config.fallback_cap = cap;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::SetFallbackVerifier,
+            &(verifier, cap),
+        )?;
+
+        emit!(FallbackVerifierSet {
+            admin: config.admin,
+            verifier,
+            cap,
+        });
+
+        Ok(())
+    }
+
+    /// Stop accepting fallback proofs; the primary verifier is unaffected
+    pub fn revoke_fallback_verifier(ctx: Context<UpdateConfig>) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        let verifier = config.fallback_verifier;
+        config.fallback_verifier = Pubkey::default();
+        config.fallback_cap = 0;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::RevokeFallbackVerifier,
+            &verifier,
+        )?;
+
+        emit!(FallbackVerifierRevoked {
+            admin: config.admin,
+            verifier,
+        });
+
+        Ok(())
+    }
+
+    /// Create the heartbeat account; signed by the primary verifier key
+    pub fn initialize_verifier_heartbeat(ctx: Context<InitializeVerifierHeartbeat>) -> Result<()> {
+        let heartbeat = &mut ctx.accounts.verifier_heartbeat;
+        heartbeat.counter = 0;
+        heartbeat.last_beat = Clock::get()?.unix_timestamp;
+        heartbeat.bump = ctx.bumps.verifier_heartbeat;
+        Ok(())
+    }
+
+    /// Verifier proves it is alive; `counter` must exceed the last beat's
+    pub fn record_heartbeat(ctx: Context<RecordHeartbeat>, counter: u64) -> Result<()> {
+        let heartbeat = &mut ctx.accounts.verifier_heartbeat;
+        require!(counter > heartbeat.counter, BeamError::StaleHeartbeatCounter);
+        heartbeat.counter = counter;
+        heartbeat.last_beat = Clock::get()?.unix_timestamp;
+
+        emit!(VerifierHeartbeatRecorded {
+            counter,
+            beat_at: heartbeat.last_beat,
+        });
+
+        Ok(())
+    }
+
+    /// Open the fee account a verifier key is paid its cut of settlement fees
+    /// into; signed by that key. `fee_vault` must be owned by the new account.
+    pub fn register_verifier_fee_account(ctx: Context<RegisterVerifierFeeAccount>) -> Result<()> {
+        let account = &mut ctx.accounts.verifier_fee_account;
+        account.verifier = ctx.accounts.verifier.key();
+        account.fee_vault = ctx.accounts.fee_vault.key();
+        account.earned = 0;
+        account.claimed = 0;
+        account.bump = ctx.bumps.verifier_fee_account;
+
+        emit!(VerifierFeeAccountRegistered {
+            verifier: account.verifier,
+            fee_vault: account.fee_vault,
+        });
+
+        Ok(())
+    }
+
+    /// Sweep the fees a verifier key has earned to `destination_token_account`.
+    /// Works whether or not the key is still an active verifier.
+    pub fn claim_verifier_fees(ctx: Context<ClaimVerifierFees>) -> Result<()> {
+        let amount = ctx.accounts.fee_vault.amount;
+        require!(amount > 0, BeamError::NoVerifierFeesToClaim);
+
+        let account = &ctx.accounts.verifier_fee_account;
+        let seeds = &[b"verifier_fees".as_ref(), account.verifier.as_ref(), &[account.bump]];
+        transfer_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.fee_vault.to_account_info(),
+            ctx.accounts.destination_token_account.to_account_info(),
+            account.to_account_info(),
+            ctx.accounts.mint.as_deref(),
+            amount,
+            &[&seeds[..]],
+        )?;
+
+        let account = &mut ctx.accounts.verifier_fee_account;
+        account.claimed = account.claimed.checked_add(amount).ok_or(BeamError::Overflow)?;
+
+        emit!(VerifierFeesClaimed {
+            verifier: account.verifier,
+            destination: ctx.accounts.destination_token_account.key(),
+            amount,
+            total_claimed: account.claimed,
+        });
+
+        Ok(())
+    }
+
+    /// Open the bond a verifier key answers for its attestations with and post
+    /// `amount` into it; signed by that key. `bond_vault` must be owned by the new
+    /// account.
+    pub fn register_verifier_bond(
+        mut ctx: Context<RegisterVerifierBond>,
+        amount: u64,
+    ) -> Result<()> {
+        let bond = &mut ctx.accounts.verifier_bond;
+        bond.verifier = ctx.accounts.verifier.key();
+        bond.vault = ctx.accounts.bond_vault.key();
+        bond.amount = 0;
+        bond.slashed = 0;
+        bond.deregistered_at = 0;
+        bond.bump = ctx.bumps.verifier_bond;
+
+        let accounts = &mut ctx.accounts;
+        deposit_verifier_bond(
+            &mut accounts.verifier_bond,
+            &accounts.bond_vault,
+            &accounts.verifier_token_account,
+            &accounts.verifier,
+            accounts.mint.as_deref(),
+            &accounts.token_program,
+            amount,
+        )
+    }
+
+    /// Add `amount` to a verifier's bond, e.g. to get back above `min_verifier_bond`
+    /// after a slash. Not once the key has deregistered.
+    pub fn top_up_verifier_bond(mut ctx: Context<TopUpVerifierBond>, amount: u64) -> Result<()> {
+        require!(
+            !ctx.accounts.verifier_bond.is_deregistered(),
+            BeamError::VerifierDeregistered
+        );
+        let accounts = &mut ctx.accounts;
+        deposit_verifier_bond(
+            &mut accounts.verifier_bond,
+            &accounts.bond_vault,
+            &accounts.verifier_token_account,
+            &accounts.verifier,
+            accounts.mint.as_deref(),
+            &accounts.token_program,
+            amount,
+        )
+    }
+
+    /// Step down as a verifier, starting the `verifier_bond_cooldown` after which the
+    /// bond can be withdrawn. Attestations signed earlier stay slashable until then.
+    /// The primary key and the configured fallback can't step down.
+    pub fn deregister_verifier_bond(ctx: Context<DeregisterVerifierBond>) -> Result<()> {
+        let verifier = ctx.accounts.verifier.key();
+        let config = &ctx.accounts.config;
+        require!(
+            verifier != VERIFIER_PUBKEY && verifier != config.fallback_verifier,
+            BeamError::VerifierStillActive
+        );
+        let bond = &mut ctx.accounts.verifier_bond;
+        require!(!bond.is_deregistered(), BeamError::VerifierDeregistered);
+        let now = Clock::get()?.unix_timestamp;
+        bond.deregistered_at = now;
+
+        emit!(VerifierBondDeregistered {
+            verifier,
+            amount: bond.amount,
+            withdrawable_at: now.saturating_add(config.verifier_bond_cooldown),
+        });
+
+        Ok(())
+    }
+
+    /// Pay a deregistered verifier's bond out once the cooldown has passed, closing
+    /// the bond and its vault to the verifier
+    pub fn withdraw_verifier_bond(ctx: Context<WithdrawVerifierBond>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let bond = &ctx.accounts.verifier_bond;
+        let cooldown = ctx.accounts.config.verifier_bond_cooldown;
+        require!(
+            bond.is_deregistered() && now >= bond.deregistered_at.saturating_add(cooldown),
+            BeamError::VerifierBondLocked
+        );
+
+        let amount = ctx.accounts.bond_vault.amount;
+        let seeds = &[b"verifier_bond".as_ref(), bond.verifier.as_ref(), &[bond.bump]];
+        let signer = &[&seeds[..]];
+        if amount > 0 {
+            transfer_tokens(
+                ctx.accounts.token_program.to_account_info(),
+                ctx.accounts.bond_vault.to_account_info(),
+                ctx.accounts.destination_token_account.to_account_info(),
+                bond.to_account_info(),
+                ctx.accounts.mint.as_deref(),
+                amount,
+                signer,
+            )?;
+        }
+        token::close_account(CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            CloseAccount {
+                account: ctx.accounts.bond_vault.to_account_info(),
+                destination: ctx.accounts.verifier.to_account_info(),
+                authority: bond.to_account_info(),
+            },
+            signer,
+        ))?;
+
+        emit!(VerifierBondWithdrawn {
+            verifier: bond.verifier,
+            destination: ctx.accounts.destination_token_account.key(),
+            amount,
+            total_slashed: bond.slashed,
+        });
+
+        Ok(())
+    }
 
-        // Find the fraudulent bundle to get amount
-        let fraud_bundle = registry
+    /// Slash `verifier_slash_bps` of a verifier's bond into the treasury for a bundle
+    /// it attested that turned out to be a double spend. Anyone may call it. The
+    /// payer's registry must hold a `DuplicateBundle` or `NonceReuse` report of the
+    /// bundle, which only the payer's own conflicting signature proves, past its
+    /// dispute window; `attestation` must be the verifier's own signature over the
+    /// settled bundle. A verifier pays for each bundle once.
+    pub fn slash_verifier(
+        ctx: Context<SlashVerifier>,
+        bundle_id: String,
+        attestation: BundleAttestation,
+    ) -> Result<()> {
+        require!(!bundle_id.is_empty() && bundle_id.len() <= 128, BeamError::InvalidBundleId);
+        let now = Clock::get()?.unix_timestamp;
+        let config = &ctx.accounts.config;
+        let registry = &ctx.accounts.nonce_registry;
+        let bundle_hash = config.bundle_hash_algo.hash(&bundle_id);
+        require!(
+            registry.fraud_records.iter().any(|record| {
+                record.bundle_hash == bundle_hash
+                    && record.reason.is_double_spend()
+                    && !record.is_open(config.fraud_withdrawal_delay, now)
+            }),
+            BeamError::FraudNotUpheld
+        );
+        let record = registry
             .bundle_history
             .iter()
             .find(|record| record.bundle_hash == bundle_hash)
             .ok_or(BeamError::BundleHistoryNotFound)?;
 
-        // Slash 2x the payment amount
-        let slash_amount = fraud_bundle.amount.checked_mul(2)
-            .ok_or(BeamError::Overflow)?;
-
-        // Ensure sufficient balance to slash
+        let bond = &ctx.accounts.verifier_bond;
         require!(
-            escrow.escrow_balance >= slash_amount,
-            BeamError::InsufficientFundsForSlash
+            attested_by(
+                &bond.verifier,
+                &attestation.proof,
+                attestation.role,
+                &bundle_id,
+                &registry.owner,
+                &record.merchant,
+                record.amount,
+                record.nonce,
+                &record.order_ref,
+                attestation.courier.as_ref(),
+                attestation.device_id_hash.as_ref(),
+                attestation.condition_id.as_ref(),
+            ),
+            BeamError::VerifierDidNotAttest
         );
 
-        // Lock slashed funds (remove from escrow_balance, add to stake_locked)
-        escrow.escrow_balance = escrow.escrow_balance.checked_sub(slash_amount)
-            .ok_or(BeamError::Underflow)?;
-        escrow.stake_locked = escrow.stake_locked.checked_add(slash_amount)
-            .ok_or(BeamError::Overflow)?;
+        let amount = config.verifier_slash(bond.amount);
+        require!(amount > 0, BeamError::NothingToSlash);
+        let seeds = &[b"verifier_bond".as_ref(), bond.verifier.as_ref(), &[bond.bump]];
+        transfer_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.bond_vault.to_account_info(),
+            ctx.accounts.treasury_token_account.to_account_info(),
+            bond.to_account_info(),
+            ctx.accounts.mint.as_deref(),
+            amount,
+            &[&seeds[..]],
+        )?;
 
-        // Update fraud tracking
-        escrow.fraud_count = escrow.fraud_count.checked_add(1)
-            .ok_or(BeamError::Overflow)?;
-        escrow.last_fraud_timestamp = now;
+        let payer = registry.owner;
+        let bond = &mut ctx.accounts.verifier_bond;
+        bond.amount = bond.amount.checked_sub(amount).ok_or(BeamError::Underflow)?;
+        bond.slashed = bond.slashed.checked_add(amount).ok_or(BeamError::Overflow)?;
 
-        // Permanently reduce reputation score
-        escrow.reputation_score = escrow.reputation_score.saturating_sub(1000);
+        let slash = &mut ctx.accounts.verifier_slash;
+        slash.verifier = bond.verifier;
+        slash.bundle_hash = bundle_hash;
+        slash.payer = payer;
+        slash.amount = amount;
+        slash.slashed_at = now;
+        slash.bump = ctx.bumps.verifier_slash;
 
-        emit!(FraudPenaltyApplied {
-            payer: escrow.owner,
-            slashed_amount: slash_amount,
-            new_reputation: escrow.reputation_score,
-            fraud_count: escrow.fraud_count,
+        emit!(VerifierSlashed {
+            verifier: bond.verifier,
+            payer,
+            bundle_hash,
+            amount,
+            remaining_bond: bond.amount,
+            slashed_by: ctx.accounts.reporter.key(),
         });
 
         Ok(())
     }
 
-    /// Migrate old escrow account (107 bytes) to new format (127 bytes)
-    /// This is a one-time migration for accounts created before fraud fields were added
-    pub fn migrate_escrow(ctx: Context<MigrateEscrow>) -> Result<()> {
-        msg!("Migrating escrow account to new format with fraud fields");
+    pub fn resume_settlements(ctx: Context<UpdateConfig>, reason: u16) -> Result<()> {
+        let config = &mut ctx.accounts.config;
+        config.set_settlements_halted(false);
+        config.halt_reason = reason;
+        record_admin_action(
+            &mut ctx.accounts.audit_log,
+            config.admin,
+            AdminAction::ResumeSettlements,
+            &reason,
+        )?;
 
-        let escrow_info = &ctx.accounts.escrow_account;
-        let owner = &ctx.accounts.owner;
-        let system_program = &ctx.accounts.system_program;
+        emit!(SettlementsResumed {
+            admin: config.admin,
+            reason,
+            resumed_at: Clock::get()?.unix_timestamp,
+        });
 
-        // Manually reallocate the account
-        let current_size = escrow_info.data_len();
-        let new_size = 8 + std::mem::size_of::<OfflineEscrowAccount>();
+        Ok(())
+    }
 
-        msg!("Current size: {}, New size: {}", current_size, new_size);
+    /// Initialize escrow account for offline payments. `rent_payer` pays the rent;
+    /// the owner signs unless the config allows custodial onboarding, and always
+    /// when `initial_amount` is funded from its token account.
+    pub fn initialize_escrow(ctx: Context<InitializeEscrow>, initial_amount: u64) -> Result<()> {
+        let owner_signed = ctx.accounts.owner.is_signer;
+        require!(
+            owner_signed || (ctx.accounts.config.is_custodial_onboarding() && initial_amount == 0),
+            BeamError::OwnerSignatureRequired
+        );
 
-        if current_size < new_size {
-            // Reallocate to new size using realloc (size, zero_init)
-            escrow_info.realloc(new_size, false)?;
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.initialize(
+            ctx.accounts.owner.key(),
+            ctx.accounts.rent_payer.key(),
+            ctx.accounts.escrow_token_account.key(),
+            ctx.bumps.escrow_account,
+            Clock::get()?.unix_timestamp,
+        );
+        if let Some(referrer) = ctx.accounts.referrer.as_ref() {
+            require_keys_neq!(referrer.key(), escrow.owner, BeamError::SelfReferral);
+            escrow.referrer = referrer.key();
+        }
+        if let Some(mint) = ctx.accounts.mint.as_deref() {
+            escrow.record_mint_decimals(mint.decimals);
+        }
+        admit_identity(
+            &ctx.accounts.config,
+            ctx.accounts.identity_reputation.as_deref_mut(),
+            ctx.accounts.identity_authority.as_ref(),
+            escrow,
+        )?;
 
-            // Transfer lamports for rent exemption difference
-            let rent = Rent::get()?;
-            let old_rent = rent.minimum_balance(current_size);
-            let new_rent = rent.minimum_balance(new_size);
-            let lamports_diff = new_rent.saturating_sub(old_rent);
+        // Transfer initial funds to escrow
+        if initial_amount > 0 {
+            let created_at = escrow.created_at;
+            deposit_to_escrow(
+                escrow,
+                &mut ctx.accounts.escrow_token_account,
+                ctx.accounts.owner_token_account.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.mint.as_deref(),
+                ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.config,
+                initial_amount,
+                created_at,
+            )?;
+        }
 
-            if lamports_diff > 0 {
-                msg!("Transferring {} lamports for rent", lamports_diff);
-                anchor_lang::system_program::transfer(
-                    CpiContext::new(
-                        system_program.to_account_info(),
-                        anchor_lang::system_program::Transfer {
-                            from: owner.to_account_info(),
-                            to: escrow_info.to_account_info(),
-                        },
-                    ),
-                    lamports_diff,
-                )?;
-            }
+        emit!(EscrowInitialized {
+            owner: escrow.owner,
+            initial_balance: escrow.escrow_balance,
+        });
 
-            // Zero out the new bytes (fraud fields at the end)
-            let mut data = escrow_info.try_borrow_mut_data()?;
-            let fraud_offset = current_size;
-            data[fraud_offset..new_size].fill(0);
+        Ok(())
+    }
 
-            msg!("✅ Account reallocated from {} to {} bytes", current_size, new_size);
-            msg!("✅ Fraud fields initialized to 0");
+    /// Create and fund an escrow plus its nonce registry in one instruction, paid for
+    /// by an enterprise sponsor. The sponsor pays the rent of both accounts and funds
+    /// `initial_amount` from its own token account; the owner only co-signs so nobody
+    /// can squat on another user's escrow address.
+    ///
+    /// Bulk onboarding: every user touches a disjoint set of accounts, so clients pack
+    /// as many `onboard_escrow` instructions into a transaction as signatures and size
+    /// allow and send the transactions concurrently. Create the vaults (and, if
+    /// needed, the sponsor's rent lamports) in earlier transactions; only the sponsor
+    /// token account is written by all of them and serializes within a slot.
+    pub fn onboard_escrow(ctx: Context<OnboardEscrow>, initial_amount: u64) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        let registry = &mut ctx.accounts.nonce_registry;
+        registry.owner = owner;
+        registry.last_nonce = 0;
+        registry.bump = ctx.bumps.nonce_registry;
+        registry.rent_payer = ctx.accounts.sponsor.key();
+
+        ctx.accounts.escrow_account.initialize(
+            owner,
+            ctx.accounts.sponsor.key(),
+            ctx.accounts.escrow_token_account.key(),
+            ctx.bumps.escrow_account,
+            now,
+        );
+        admit_identity(
+            &ctx.accounts.config,
+            ctx.accounts.identity_reputation.as_deref_mut(),
+            ctx.accounts.identity_authority.as_ref(),
+            &mut ctx.accounts.escrow_account,
+        )?;
+
+        if initial_amount > 0 {
+            deposit_to_escrow(
+                &mut ctx.accounts.escrow_account,
+                &mut ctx.accounts.escrow_token_account,
+                ctx.accounts.sponsor_token_account.to_account_info(),
+                ctx.accounts.sponsor.to_account_info(),
+                ctx.accounts.mint.as_deref(),
+                ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.config,
+                initial_amount,
+                now,
+            )?;
+        }
+
+        let initial_balance = ctx.accounts.escrow_account.escrow_balance;
+        emit!(EscrowInitialized {
+            owner,
+            initial_balance,
+        });
+        emit!(EscrowOnboarded {
+            owner,
+            sponsor: ctx.accounts.sponsor.key(),
+            initial_balance,
+        });
+
+        Ok(())
+    }
+
+    /// Onboard in one signature: create the escrow and its nonce registry unless they
+    /// already exist, then deposit `initial_amount`. Safe to repeat: existing accounts
+    /// keep every field and the deposit simply tops the escrow up.
+    pub fn initialize_and_fund(ctx: Context<InitializeAndFund>, initial_amount: u64) -> Result<()> {
+        let owner = ctx.accounts.owner.key();
+        let now = Clock::get()?.unix_timestamp;
+
+        let registry = &mut ctx.accounts.nonce_registry;
+        if registry.owner == Pubkey::default() {
+            registry.owner = owner;
+            registry.last_nonce = 0;
+            registry.bump = ctx.bumps.nonce_registry;
+            registry.rent_payer = owner;
+        }
+        require_keys_eq!(registry.owner, owner, BeamError::InvalidOwner);
+
+        // A zeroed account was just created by `init_if_needed`
+        let created = ctx.accounts.escrow_account.owner == Pubkey::default();
+        if created {
+            ctx.accounts.escrow_account.initialize(
+                owner,
+                owner,
+                ctx.accounts.escrow_token_account.key(),
+                ctx.bumps.escrow_account,
+                now,
+            );
+            admit_identity(
+                &ctx.accounts.config,
+                ctx.accounts.identity_reputation.as_deref_mut(),
+                ctx.accounts.identity_authority.as_ref(),
+                &mut ctx.accounts.escrow_account,
+            )?;
         } else {
-            msg!("⚠️  Account already at correct size, no migration needed");
+            let escrow = &ctx.accounts.escrow_account;
+            require_keys_eq!(escrow.owner, owner, BeamError::InvalidOwner);
+            require!(
+                escrow.is_backing_account(&ctx.accounts.escrow_token_account.key()),
+                BeamError::InvalidEscrowTokenAccount
+            );
+            // Top-ups on an indebted escrow go through `fund_escrow`, which repays first
+            require!(escrow.credit_debt == 0, BeamError::CreditRepaymentRequired);
+        }
+
+        if initial_amount > 0 {
+            let credited = deposit_to_escrow(
+                &mut ctx.accounts.escrow_account,
+                &mut ctx.accounts.escrow_token_account,
+                ctx.accounts.owner_token_account.to_account_info(),
+                ctx.accounts.owner.to_account_info(),
+                ctx.accounts.mint.as_deref(),
+                ctx.accounts.token_program.to_account_info(),
+                &ctx.accounts.config,
+                initial_amount,
+                now,
+            )?;
+            let escrow = &ctx.accounts.escrow_account;
+            if !created {
+                emit!(EscrowFunded {
+                    owner,
+                    amount: credited,
+                    new_balance: escrow.escrow_balance,
+                    escrow_token_account: ctx.accounts.escrow_token_account.key(),
+                });
+            }
+        }
+
+        let escrow = &mut ctx.accounts.escrow_account;
+        if created {
+            emit!(EscrowInitialized {
+                owner,
+                initial_balance: escrow.escrow_balance,
+            });
+        } else {
+            escrow.record_owner_activity(now);
         }
 
         Ok(())
     }
-}
 
-#[derive(Accounts)]
-pub struct InitializeEscrow<'info> {
-    #[account(
-        init,
-        payer = owner,
-        space = 8 + OfflineEscrowAccount::INIT_SPACE,
-        seeds = [b"escrow", owner.key().as_ref()],
-        bump
-    )]
-    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+    /// Add funds to existing escrow
+    pub fn fund_escrow(ctx: Context<FundEscrow>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+        // Deposits pay down credit-line debt before they restore spendable balance
+        let repaid = ctx.accounts.repay_credit(amount)?;
+        let amount = amount - repaid;
+        if amount == 0 {
+            return Ok(());
+        }
 
-    #[account(mut)]
-    pub owner_token_account: Account<'info, TokenAccount>,
+        let now = Clock::get()?.unix_timestamp;
+        let balance_before = ctx.accounts.escrow_account.escrow_balance;
+        let credited = deposit_to_escrow(
+            &mut ctx.accounts.escrow_account,
+            &mut ctx.accounts.escrow_token_account,
+            ctx.accounts.owner_token_account.to_account_info(),
+            ctx.accounts.owner.to_account_info(),
+            ctx.accounts.mint.as_deref(),
+            ctx.accounts.token_program.to_account_info(),
+            &ctx.accounts.config,
+            amount,
+            now,
+        )?;
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.record_owner_activity(now);
 
-    #[account(
-        mut,
-        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
+        emit!(EscrowFunded {
+            owner: escrow.owner,
+            amount: credited,
+            new_balance: escrow.escrow_balance,
+            escrow_token_account: ctx.accounts.escrow_token_account.key(),
+        });
+        for watcher in escrow
+            .watchers
+            .iter()
+            .filter(|watcher| watcher.is_crossed(balance_before, escrow.escrow_balance))
+        {
+            emit!(EscrowFundedForWatcher {
+                watcher: watcher.merchant,
+                owner: escrow.owner,
+                threshold: watcher.threshold,
+                new_balance: escrow.escrow_balance,
+            });
+        }
 
-    pub token_program: Program<'info, Token>,
-    pub system_program: Program<'info, System>,
-}
+        Ok(())
+    }
 
-#[derive(Accounts)]
-pub struct FundEscrow<'info> {
-    #[account(
-        mut,
-        seeds = [b"escrow", owner.key().as_ref()],
-        bump = escrow_account.bump,
+    /// Settle offline payment (called when either party goes online). Returns the
+    /// `SettlementReceipt` for the merchant to have counter-signed.
+    pub fn settle_offline_payment<'info>(
+        ctx: Context<'_, '_, '_, 'info, SettlePayment<'info>>,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: String,
+        evidence: SettlementEvidence,
+    ) -> Result<SettlementReceipt> {
+        require!(
+            !ctx.accounts.config.is_settlements_halted(),
+            BeamError::SettlementsHalted
+        );
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        compliance::check_parties(
+            &ctx.accounts.config,
+            ctx.remaining_accounts,
+            &ctx.accounts.escrow_account.owner,
+            &ctx.accounts.merchant.key(),
+            now,
+        )?;
+        ctx.accounts.bootstrap_nonce_registry(ctx.bumps.nonce_registry);
+        ctx.accounts.enter_processing()?;
+
+        let bundle_hash = ctx.accounts.validate_settlement(
+            amount,
+            payer_nonce,
+            &bundle_id,
+            &evidence,
+            now,
+            clock.slot,
+        )?;
+        let snapshot = ctx.accounts.vault_snapshot();
+        ctx.accounts.apply_settlement(
+            amount,
+            payer_nonce,
+            bundle_id,
+            bundle_hash,
+            &evidence,
+            now,
+            false,
+        )?;
+        ctx.accounts.check_vault_snapshot(snapshot)?;
+
+        // Receipt accounts in `remaining_accounts`, after any compliance records,
+        // opt this settlement into a receipt
+        #[cfg(feature = "receipt-nft")]
+        let receipt_accounts = &ctx.remaining_accounts[compliance::record_count(&ctx.accounts.config)..];
+        #[cfg(feature = "receipt-nft")]
+        if !receipt_accounts.is_empty() {
+            receipt::mint_settlement_receipt(
+                &ctx.accounts.payer.to_account_info(),
+                &ctx.accounts.owner.to_account_info(),
+                receipt_accounts,
+                receipt::ReceiptDetails {
+                    owner: ctx.accounts.escrow_account.owner,
+                    merchant: ctx.accounts.merchant.key(),
+                    bundle_hash,
+                    amount,
+                    settled_at: now,
+                    settlement_number: ctx.accounts.escrow_account.settlement_count,
+                },
+            )?;
+        }
+
+        let receipt = SettlementReceipt {
+            bundle_hash,
+            amount,
+            merchant: ctx.accounts.merchant.key(),
+            payer: ctx.accounts.escrow_account.owner,
+            nonce: payer_nonce,
+            slot: clock.slot,
+            settlement_index: ctx.accounts.escrow_account.settlement_count,
+            fee: ctx.accounts.config.settlement_fee(amount),
+            escrow_balance: ctx.accounts.escrow_account.escrow_balance,
+        };
+        emit!(SettlementReceiptIssued { receipt });
+
+        ctx.accounts.exit_processing();
+        Ok(receipt)
+    }
+
+    /// Dry run of `settle_offline_payment` with the same accounts and arguments.
+    /// Runs the same checks and returns the first one that would fail as a
+    /// `SettlementPreflight` instead of aborting. It settles nothing and writes
+    /// nothing: a missing registry is checked as the empty one settling would
+    /// bootstrap, and while settlements are held the hold accounts are left out,
+    /// since passing them would create the hold the real settlement needs.
+    pub fn preflight_settlement(
+        ctx: Context<SettlePayment>,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: String,
+        evidence: SettlementEvidence,
+    ) -> Result<SettlementPreflight> {
+        let clock = Clock::get()?;
+        // Failing here undoes the hold's creation along with the rest
+        require!(
+            ctx.accounts.settlement_hold.is_none() && ctx.accounts.hold_vault.is_none(),
+            BeamError::InvalidSettlementHold
+        );
+
+        let outcome = if ctx.accounts.config.is_settlements_halted() {
+            Err((SettlementCheck::SettlementsHalted, BeamError::SettlementsHalted))
+        } else if let Err(err) = compliance::check_parties(
+            &ctx.accounts.config,
+            ctx.remaining_accounts,
+            &ctx.accounts.escrow_account.owner,
+            &ctx.accounts.merchant.key(),
+            clock.unix_timestamp,
+        ) {
+            Err((SettlementCheck::Compliance, err))
+        } else {
+            ctx.accounts.run_settlement_checks(
+                amount,
+                payer_nonce,
+                &bundle_id,
+                &evidence,
+                clock.unix_timestamp,
+                clock.slot,
+            )
+        };
+        Ok(match outcome {
+            Ok(_) => SettlementPreflight {
+                check: SettlementCheck::Passed,
+                error_code: 0,
+            },
+            Err((check, err)) => SettlementPreflight {
+                check,
+                error_code: u32::from(err),
+            },
+        })
+    }
+
+    /// Settle a batch of bundles from one payer, skipping items that fail validation
+    /// instead of aborting the whole transaction. Per-item outcomes are returned
+    /// as a `BatchSettlementResult` so the merchant can retry only genuine failures.
+    /// With `aggregate` the merchant's payouts are summed into one transfer after the
+    /// loop; every item is still recorded and emitted exactly as if settled alone.
+    pub fn settle_batch_best_effort(
+        ctx: Context<SettlePayment>,
+        items: Vec<BatchSettlementItem>,
+        aggregate: bool,
+    ) -> Result<BatchSettlementResult> {
+        require!(
+            !items.is_empty() && items.len() <= MAX_BATCH_SIZE,
+            BeamError::InvalidBatchSize
+        );
+        require!(
+            !ctx.accounts.config.is_settlements_halted(),
+            BeamError::SettlementsHalted
+        );
+        // Each held settlement needs its own hold account
+        require!(
+            ctx.accounts.config.settlement_hold_period == 0,
+            BeamError::SettlementHoldUnsupported
+        );
+
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        compliance::check_parties(
+            &ctx.accounts.config,
+            ctx.remaining_accounts,
+            &ctx.accounts.escrow_account.owner,
+            &ctx.accounts.merchant.key(),
+            now,
+        )?;
+        ctx.accounts.bootstrap_nonce_registry(ctx.bumps.nonce_registry);
+        ctx.accounts.enter_processing()?;
+        let mut result = BatchSettlementResult::default();
+        let mut merchant_payout: u64 = 0;
+        let snapshot = ctx.accounts.vault_snapshot();
+
+        for (index, item) in items.into_iter().enumerate() {
+            // Items are validated against the state left by earlier successes, so
+            // nonce monotonicity and duplicate detection hold across the batch.
+            let code = match ctx.accounts.validate_settlement(
+                item.amount,
+                item.payer_nonce,
+                &item.bundle_id,
+                &item.evidence,
+                now,
+                clock.slot,
+            ) {
+                Ok(bundle_hash) => {
+                    let deferred = ctx.accounts.apply_settlement(
+                        item.amount,
+                        item.payer_nonce,
+                        item.bundle_id,
+                        bundle_hash,
+                        &item.evidence,
+                        now,
+                        aggregate,
+                    )?;
+                    merchant_payout = merchant_payout
+                        .checked_add(deferred)
+                        .ok_or(BeamError::Overflow)?;
+                    result.settled_mask |= 1 << index;
+                    result.settled_count += 1;
+                    result.total_settled = result
+                        .total_settled
+                        .checked_add(item.amount)
+                        .ok_or(BeamError::Overflow)?;
+                    BATCH_ITEM_SETTLED
+                }
+                Err(err) => u32::from(err),
+            };
+            result.item_codes.push(code);
+        }
+
+        if merchant_payout > 0 {
+            ctx.accounts.pay_merchant(merchant_payout)?;
+        }
+        ctx.accounts.check_vault_snapshot(snapshot)?;
+
+        emit!(BatchSettlementProcessed {
+            payer: ctx.accounts.escrow_account.owner,
+            merchant: ctx.accounts.merchant.key(),
+            item_count: result.item_codes.len() as u8,
+            settled_count: result.settled_count,
+            settled_mask: result.settled_mask,
+            total_settled: result.total_settled,
+        });
+
+        ctx.accounts.exit_processing();
+        Ok(result)
+    }
+
+    /// Settle a payer -> runner -> merchant route in one transaction. The first bundle
+    /// pays the runner `amount + hop_fee`, the second pays the merchant `amount`; the
+    /// payer's escrow funds both, so the merchant gets `amount` (net of the protocol fee)
+    /// and the runner keeps `hop_fee`. Each leg needs its payer's attestation, both
+    /// parties sign, and both bundle hashes are marked so neither leg settles again.
+    pub fn settle_multihop(
+        ctx: Context<SettleMultihop>,
+        amount: u64,
+        hop_fee: u64,
+        first: MultihopLeg,
+        second: MultihopLeg,
+    ) -> Result<()> {
+        enter_processing(&mut ctx.accounts.escrow_account)?;
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        let accounts = &ctx.accounts;
+        let config = &accounts.config;
+        require!(!config.is_settlements_halted(), BeamError::SettlementsHalted);
+        require!(config.settlement_hold_period == 0, BeamError::SettlementHoldUnsupported);
+        // Compliance records cover a single payer and merchant
+        require!(!config.is_compliance_enabled(), BeamError::ComplianceCheckUnsupported);
+        require!(amount > 0, BeamError::InvalidAmount);
+        let total = amount.checked_add(hop_fee).ok_or(BeamError::Overflow)?;
+        for leg in [&first, &second] {
+            require!(
+                !leg.bundle_id.is_empty() && leg.bundle_id.len() <= 128,
+                BeamError::InvalidBundleId
+            );
+            // Device bundles only settle against their device's registry
+            require!(
+                leg.evidence.device_id_hash.is_none(),
+                BeamError::DeviceRegistryMismatch
+            );
+        }
+        require!(first.bundle_id != second.bundle_id, BeamError::DuplicateBundle);
+
+        let owner_key = accounts.owner.key();
+        let runner_key = accounts.runner.key();
+        let merchant_key = accounts.merchant.key();
+        let escrow = &accounts.escrow_account;
+        require!(
+            !(config.is_zero_reputation_blocked() && escrow.reputation_score <= 0),
+            BeamError::ReputationExhausted
+        );
+
+        // Verify the chain: payer -> runner for the total, runner -> merchant for the rest
+        let first_hash = config.bundle_hash_algo.hash(&first.bundle_id);
+        let second_hash = config.bundle_hash_algo.hash(&second.bundle_id);
+        let first_order_ref = first.evidence.order_ref();
+        let first_created_at = first.evidence.bundle_created_at();
+        let second_order_ref = second.evidence.order_ref();
+        let heartbeat = accounts.verifier_heartbeat.as_ref().map(|h| h.last_beat);
+        let legs = [
+            (
+                &first,
+                AttestedBundle {
+                    bundle_id: &first.bundle_id,
+                    payer: &owner_key,
+                    merchant: &runner_key,
+                    amount: total,
+                    nonce: first.nonce,
+                    order_ref: &first_order_ref,
+                    courier: first.evidence.courier.as_ref(),
+                    device_id_hash: None,
+                    condition_id: first.evidence.condition_id.as_ref(),
+                    aggregate_key: Some(&escrow.aggregate_key),
+                },
+            ),
+            (
+                &second,
+                AttestedBundle {
+                    bundle_id: &second.bundle_id,
+                    payer: &runner_key,
+                    merchant: &merchant_key,
+                    amount,
+                    nonce: second.nonce,
+                    order_ref: &second_order_ref,
+                    courier: second.evidence.courier.as_ref(),
+                    device_id_hash: None,
+                    condition_id: second.evidence.condition_id.as_ref(),
+                    aggregate_key: None,
+                },
+            ),
+        ];
+        for (leg, bundle) in &legs {
+            let payer_proof = leg
+                .evidence
+                .payer_proof
+                .as_ref()
+                .ok_or(BeamError::MissingAttestation)?;
+            check_proof(payer_proof, AttestationRole::Payer, bundle, config, heartbeat, now, clock.slot)?;
+            if let Some(merchant_proof) = leg.evidence.merchant_proof.as_ref() {
+                check_proof(
+                    merchant_proof,
+                    AttestationRole::Merchant,
+                    bundle,
+                    config,
+                    heartbeat,
+                    now,
+                    clock.slot,
+                )?;
+            }
+            check_condition(&leg.evidence, config)?;
+        }
+
+        let registry = &accounts.nonce_registry;
+        let runner_registry = &accounts.runner_nonce_registry;
+        require!(
+            !registry.recent_bundle_hashes.contains(&first_hash)
+                && !runner_registry.recent_bundle_hashes.contains(&second_hash),
+            BeamError::DuplicateBundle
+        );
+        require!(
+            registry.pending_nonces.contains(&first.nonce)
+                || (first.nonce > registry.last_nonce && first.nonce > escrow.last_nonce),
+            BeamError::InvalidNonce
+        );
+        require!(
+            runner_registry.pending_nonces.contains(&second.nonce)
+                || second.nonce > runner_registry.last_nonce,
+            BeamError::InvalidNonce
+        );
+
+        require!(escrow.escrow_balance >= total, BeamError::InsufficientFunds);
+        require!(
+            escrow.settleable_balance(now, config.funding_lockup_secs) >= total,
+            BeamError::FundsStillLocked
+        );
+        if config.rolling_cap > 0 {
+            let spent = escrow.rolling_spent(now, config.rolling_window_days);
+            require!(
+                spent.saturating_add(total) <= config.rolling_cap,
+                BeamError::RollingLimitExceeded
+            );
+        }
+        if let Some(entry) = escrow.merchant_limit(&merchant_key) {
+            require!(
+                entry.settled.saturating_add(total) <= entry.limit,
+                BeamError::MerchantLimitExceeded
+            );
+        }
+        escrow.check_plausible_amount(config, &accounts.escrow_token_account.mint, total)?;
+
+        // The protocol fee comes out of the merchant's leg, as for a direct settlement
+        let fee = config.settlement_fee(amount);
+        require!(fee == 0 || fee < amount, BeamError::FeeExceedsAmount);
+        let treasury = if fee > 0 {
+            let treasury = accounts
+                .treasury_token_account
+                .as_ref()
+                .ok_or(BeamError::InvalidTreasuryAccount)?;
+            require!(
+                treasury.owner == config.treasury
+                    && treasury.mint == accounts.escrow_token_account.mint,
+                BeamError::InvalidTreasuryAccount
+            );
+            Some(treasury.to_account_info())
+        } else {
+            None
+        };
+
+        accounts.transfer_from_escrow(accounts.merchant_token_account.to_account_info(), amount - fee)?;
+        if hop_fee > 0 {
+            accounts.transfer_from_escrow(accounts.runner_token_account.to_account_info(), hop_fee)?;
+        }
+        if let Some(treasury) = treasury {
+            accounts.transfer_from_escrow(treasury, fee)?;
+        }
+
+        let accounts = &mut *ctx.accounts;
+        accounts
+            .escrow_account
+            .record_settlement(&accounts.config, &merchant_key, total, first.nonce, first_created_at, now)?;
+        reward_settlement(
+            &mut accounts.escrow_account,
+            &accounts.nonce_registry,
+            &accounts.config,
+            &merchant_key,
+            now,
+        );
+        let capacity = RegistryCapacity::of(&accounts.nonce_registry.to_account_info());
+        accounts.nonce_registry.record_settlement(
+            BundleRecord {
+                bundle_hash: first_hash,
+                merchant: merchant_key,
+                amount: total,
+                settled_at: now,
+                nonce: first.nonce,
+                order_ref: first_order_ref,
+                bundle_created_at: first_created_at,
+                settlement_index: accounts.escrow_account.settlement_count,
+            },
+            capacity,
+        );
+        // The runner's bundle was paid from the payer's escrow: consume it, but keep it
+        // out of the runner's history so it can't be used to slash the runner
+        accounts
+            .runner_nonce_registry
+            .mark_bundle(second_hash, second.nonce);
+
+        if fee > 0 {
+            emit!(SettlementFeeCollected {
+                payer: owner_key,
+                merchant: merchant_key,
+                fee,
+                referrer: Pubkey::default(),
+                referral_reward: 0,
+                fee_payer: FeePayer::Merchant,
+                verifier: Pubkey::default(),
+                verifier_fee: 0,
+            });
+        }
+        emit!(PaymentSettled {
+            payer: owner_key,
+            merchant: merchant_key,
+            amount: total,
+            nonce: first.nonce,
+            bundle_id: first.bundle_id,
+            order_ref: first_order_ref,
+            roundup: 0,
+            escrow_balance: accounts.escrow_account.escrow_balance,
+            total_spent: accounts.escrow_account.total_spent,
+            settlement_count: accounts.escrow_account.settlement_count,
+            merchant_token_account: accounts.merchant_token_account.key(),
+            escrow_token_account: accounts.escrow_token_account.key(),
+        });
+        emit!(MultihopSettled {
+            payer: owner_key,
+            runner: runner_key,
+            merchant: merchant_key,
+            amount,
+            hop_fee,
+            fee,
+            first_bundle_hash: first_hash,
+            second_bundle_hash: second_hash,
+            payer_nonce: first.nonce,
+            runner_nonce: second.nonce,
+        });
+
+        accounts.escrow_account.set_processing(false);
+        Ok(())
+    }
+
+    /// Settle bundles two escrow holders owe each other in one transaction. Every
+    /// bundle is validated and recorded at face value against its payer's escrow,
+    /// limits and history; the fees on each direction go to the treasury and only
+    /// the difference between the two payouts moves between the vaults. Payouts are
+    /// credited to the receiving escrow, subject to the funding lockup.
+    pub fn settle_netted(
+        ctx: Context<SettleNetted>,
+        a_to_b: Vec<BatchSettlementItem>,
+        b_to_a: Vec<BatchSettlementItem>,
+    ) -> Result<()> {
+        require!(
+            !(a_to_b.is_empty() && b_to_a.is_empty())
+                && a_to_b.len() + b_to_a.len() <= MAX_BATCH_SIZE,
+            BeamError::InvalidBatchSize
+        );
+        require!(
+            !ctx.accounts.config.is_settlements_halted(),
+            BeamError::SettlementsHalted
+        );
+        require!(
+            ctx.accounts.config.settlement_hold_period == 0,
+            BeamError::SettlementHoldUnsupported
+        );
+        // Compliance records cover a single payer and merchant
+        require!(
+            !ctx.accounts.config.is_compliance_enabled(),
+            BeamError::ComplianceCheckUnsupported
+        );
+
+        let clock = Clock::get()?;
+        let now = clock.unix_timestamp;
+        let accounts = &mut *ctx.accounts;
+        enter_processing(&mut accounts.escrow_a)?;
+        enter_processing(&mut accounts.escrow_b)?;
+        let party_a = accounts.party_a.key();
+        let party_b = accounts.party_b.key();
+        let config = &accounts.config;
+        let heartbeat = accounts.verifier_heartbeat.as_ref().map(|h| h.last_beat);
+        let mint = accounts.escrow_token_account_a.mint;
+        let capacity_a = RegistryCapacity::of(&accounts.nonce_registry_a.to_account_info());
+        let capacity_b = RegistryCapacity::of(&accounts.nonce_registry_b.to_account_info());
+
+        // Gross amounts are booked against each payer before anything is credited
+        let (gross_a_to_b, fee_a_to_b) = record_netted_bundles(
+            &mut accounts.escrow_a,
+            &mut accounts.nonce_registry_a,
+            capacity_a,
+            &party_b,
+            &accounts.escrow_token_account_b.key(),
+            &mint,
+            a_to_b,
+            config,
+            heartbeat,
+            now,
+            clock.slot,
+        )?;
+        let (gross_b_to_a, fee_b_to_a) = record_netted_bundles(
+            &mut accounts.escrow_b,
+            &mut accounts.nonce_registry_b,
+            capacity_b,
+            &party_a,
+            &accounts.escrow_token_account_a.key(),
+            &mint,
+            b_to_a,
+            config,
+            heartbeat,
+            now,
+            clock.slot,
+        )?;
+
+        let payout_a_to_b = gross_a_to_b - fee_a_to_b;
+        let payout_b_to_a = gross_b_to_a - fee_b_to_a;
+        let lockup = config.funding_lockup_secs;
+        for (escrow, payout) in [
+            (&mut accounts.escrow_b, payout_a_to_b),
+            (&mut accounts.escrow_a, payout_b_to_a),
+        ] {
+            escrow.escrow_balance = escrow.escrow_balance.checked_add(payout)
+                .ok_or(BeamError::Overflow)?;
+            escrow.record_funding(payout, now, lockup);
+        }
+
+        let fees = fee_a_to_b.checked_add(fee_b_to_a).ok_or(BeamError::Overflow)?;
+        if fees > 0 {
+            let treasury = accounts
+                .treasury_token_account
+                .as_ref()
+                .ok_or(BeamError::InvalidTreasuryAccount)?;
+            require!(
+                treasury.owner == accounts.config.treasury
+                    && treasury.mint == accounts.escrow_token_account_a.mint,
+                BeamError::InvalidTreasuryAccount
+            );
+            let treasury = treasury.to_account_info();
+            for (from_a, fee, payer, merchant) in [
+                (true, fee_a_to_b, party_a, party_b),
+                (false, fee_b_to_a, party_b, party_a),
+            ] {
+                if fee > 0 {
+                    accounts.transfer(from_a, treasury.clone(), fee)?;
+                    emit!(SettlementFeeCollected {
+                        payer,
+                        merchant,
+                        fee,
+                        referrer: Pubkey::default(),
+                        referral_reward: 0,
+                        fee_payer: FeePayer::Merchant,
+                        verifier: Pubkey::default(),
+                        verifier_fee: 0,
+                    });
+                }
+            }
+        }
+
+        let net_amount = payout_a_to_b.abs_diff(payout_b_to_a);
+        let a_pays = payout_a_to_b >= payout_b_to_a;
+        if net_amount > 0 {
+            let to = if a_pays {
+                accounts.escrow_token_account_b.to_account_info()
+            } else {
+                accounts.escrow_token_account_a.to_account_info()
+            };
+            accounts.transfer(a_pays, to, net_amount)?;
+        }
+
+        emit!(NettedSettlement {
+            party_a,
+            party_b,
+            gross_a_to_b,
+            gross_b_to_a,
+            fees,
+            net_amount,
+            net_payer: if a_pays { party_a } else { party_b },
+        });
+
+        accounts.escrow_a.set_processing(false);
+        accounts.escrow_b.set_processing(false);
+        Ok(())
+    }
+
+    /// Create an invoice that settlements can reference. `amount` is the exact price
+    /// for `Exact`, the cap for `UpTo`, and ignored for `Open`.
+    pub fn create_invoice(
+        ctx: Context<CreateInvoice>,
+        invoice_id: [u8; 16],
+        mode: InvoiceMode,
+        amount: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(
+            mode == InvoiceMode::Open || amount > 0,
+            BeamError::InvalidAmount
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(expires_at > now, BeamError::InvoiceExpired);
+
+        let invoice = &mut ctx.accounts.invoice;
+        invoice.merchant = ctx.accounts.merchant.key();
+        invoice.invoice_id = invoice_id;
+        invoice.mode = mode;
+        invoice.amount = if mode == InvoiceMode::Open { 0 } else { amount };
+        invoice.amount_paid = 0;
+        invoice.payment_count = 0;
+        invoice.status = InvoiceStatus::Open;
+        invoice.created_at = now;
+        invoice.bump = ctx.bumps.invoice;
+        invoice.expires_at = expires_at;
+        invoice.settled_bundles = Vec::new();
+        invoice.open_disputes = 0;
+        invoice.last_disputed_at = 0;
+        invoice.rent_payer = ctx.accounts.rent_payer.key();
+
+        emit!(InvoiceCreated {
+            invoice: invoice.key(),
+            merchant: invoice.merchant,
+            invoice_id,
+            mode,
+            amount: invoice.amount,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant closes a paid or expired invoice, returning its rent to the rent payer.
+    /// Fraud reports against its bundles hold it open for the config's
+    /// `fraud_withdrawal_delay` after the latest one.
+    pub fn close_invoice(ctx: Context<CloseInvoice>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let invoice = &ctx.accounts.invoice;
+        require!(
+            invoice.status == InvoiceStatus::Paid || invoice.is_expired(now),
+            BeamError::InvoiceStillOpen
+        );
+        require!(
+            !invoice.has_open_disputes(ctx.accounts.config.fraud_withdrawal_delay, now),
+            BeamError::InvoiceDisputed
+        );
+
+        emit!(InvoiceClosed {
+            invoice: invoice.key(),
+            merchant: invoice.merchant,
+            closed_by: ctx.accounts.merchant.key(),
+            keeper_cut: 0,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless cleanup of an expired invoice. Rent goes back to the rent payer
+    /// minus a small cut for the keeper that cranked it.
+    pub fn close_expired_invoice(ctx: Context<CloseExpiredInvoice>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let invoice = &ctx.accounts.invoice;
+        require!(invoice.is_expired(now), BeamError::InvoiceNotExpired);
+        require!(
+            !invoice.has_open_disputes(ctx.accounts.config.fraud_withdrawal_delay, now),
+            BeamError::InvoiceDisputed
+        );
+
+        let invoice_info = invoice.to_account_info();
+        let keeper_cut = invoice_info.lamports() * INVOICE_KEEPER_CUT_BPS / 10_000;
+        if keeper_cut > 0 {
+            **invoice_info.try_borrow_mut_lamports()? -= keeper_cut;
+            **ctx.accounts.keeper.try_borrow_mut_lamports()? += keeper_cut;
+        }
+
+        emit!(InvoiceClosed {
+            invoice: invoice.key(),
+            merchant: invoice.merchant,
+            closed_by: ctx.accounts.keeper.key(),
+            keeper_cut,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant redirects the invoice's rent refund once the account that paid the
+    /// rent no longer exists
+    pub fn set_invoice_rent_payer(ctx: Context<SetInvoiceRentPayer>, rent_payer: Pubkey) -> Result<()> {
+        require!(
+            ctx.accounts.current_rent_payer.lamports() == 0,
+            BeamError::RentPayerStillExists
+        );
+        let invoice = &mut ctx.accounts.invoice;
+        let previous = invoice.rent_refund_recipient();
+        invoice.rent_payer = rent_payer;
+
+        emit!(RentPayerChanged {
+            account: invoice.key(),
+            previous,
+            rent_payer,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant opens a cashback program paying `rate_bps` of each settlement back to payers
+    pub fn create_cashback_program(ctx: Context<CreateCashbackProgram>, rate_bps: u16) -> Result<()> {
+        require!(
+            rate_bps > 0 && rate_bps <= MAX_CASHBACK_BPS,
+            BeamError::InvalidCashbackRate
+        );
+
+        let cashback = &mut ctx.accounts.cashback_program;
+        cashback.merchant = ctx.accounts.merchant.key();
+        cashback.vault = ctx.accounts.vault.key();
+        cashback.rate_bps = rate_bps;
+        cashback.total_paid = 0;
+        cashback.bump = ctx.bumps.cashback_program;
+
+        emit!(CashbackProgramUpdated {
+            merchant: cashback.merchant,
+            vault: cashback.vault,
+            rate_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Change the cashback rate; a rate of zero pauses cashback without closing the program
+    pub fn set_cashback_rate(ctx: Context<SetCashbackRate>, rate_bps: u16) -> Result<()> {
+        require!(rate_bps <= MAX_CASHBACK_BPS, BeamError::InvalidCashbackRate);
+
+        let cashback = &mut ctx.accounts.cashback_program;
+        cashback.rate_bps = rate_bps;
+
+        emit!(CashbackProgramUpdated {
+            merchant: cashback.merchant,
+            vault: cashback.vault,
+            rate_bps,
+        });
+
+        Ok(())
+    }
+
+    /// Top up a cashback vault
+    pub fn fund_cashback(ctx: Context<FundCashback>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.funder_token_account.to_account_info(),
+            to: ctx.accounts.vault.to_account_info(),
+            authority: ctx.accounts.funder.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new(ctx.accounts.token_program.to_account_info(), cpi_accounts);
+        token::transfer(cpi_ctx, amount)?;
+
+        Ok(())
+    }
+
+    /// Merchant takes unspent funds back out of the cashback vault
+    pub fn withdraw_cashback(ctx: Context<WithdrawCashback>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+
+        let merchant_key = ctx.accounts.merchant.key();
+        let seeds = &[
+            b"cashback",
+            merchant_key.as_ref(),
+            &[ctx.accounts.cashback_program.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: ctx.accounts.vault.to_account_info(),
+            to: ctx.accounts.merchant_token_account.to_account_info(),
+            authority: ctx.accounts.cashback_program.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(
+            ctx.accounts.token_program.to_account_info(),
+            cpi_accounts,
+            signer,
+        );
+        token::transfer(cpi_ctx, amount)?;
+
+        Ok(())
+    }
+
+    /// Create the owner's primary nonce registry with rent from `rent_payer`. The
+    /// owner signs unless the config allows custodial onboarding.
+    pub fn initialize_nonce_registry(ctx: Context<InitializeNonceRegistry>) -> Result<()> {
+        require!(
+            ctx.accounts.owner.is_signer || ctx.accounts.config.is_custodial_onboarding(),
+            BeamError::OwnerSignatureRequired
+        );
+        let registry = &mut ctx.accounts.nonce_registry;
+        registry.owner = ctx.accounts.owner.key();
+        registry.last_nonce = 0;
+        registry.bump = ctx.bumps.nonce_registry;
+        registry.rent_payer = ctx.accounts.rent_payer.key();
+        Ok(())
+    }
+
+    /// Give a device of the owner its own nonce space, seeded by
+    /// `[b"nonce", owner, device_id_hash]`, so devices signing offline in parallel
+    /// never collide. Bundles from it commit `device_id_hash` in their evidence.
+    pub fn enroll_device(ctx: Context<EnrollDevice>, device_id_hash: [u8; 32]) -> Result<()> {
+        require!(device_id_hash != [0u8; 32], BeamError::InvalidDeviceId);
+        let registry = &mut ctx.accounts.device_nonce_registry;
+        registry.owner = ctx.accounts.owner.key();
+        registry.last_nonce = 0;
+        registry.bump = ctx.bumps.device_nonce_registry;
+        registry.device_id_hash = device_id_hash;
+        registry.rent_payer = registry.owner;
+        let primary = &mut ctx.accounts.primary_nonce_registry;
+        primary.device_count = primary.device_count.checked_add(1).ok_or(BeamError::Overflow)?;
+
+        emit!(DeviceEnrolled {
+            owner: registry.owner,
+            device_id_hash,
+        });
+        Ok(())
+    }
+
+    /// Merge a retired device's settlement history into the primary registry and
+    /// close its registry to its rent payer. Its nonce space goes with it, so nothing
+    /// may still be reserved or registered as a liability there.
+    pub fn retire_device(ctx: Context<RetireDevice>) -> Result<()> {
+        let device = &ctx.accounts.device_nonce_registry;
+        require!(
+            device.pending_nonces.is_empty() && device.pending_liabilities.is_empty(),
+            BeamError::DeviceRegistryBusy
+        );
+        let capacity = RegistryCapacity::of(&ctx.accounts.primary_nonce_registry.to_account_info());
+        let primary = &mut ctx.accounts.primary_nonce_registry;
+        primary.absorb(device, capacity);
+        primary.device_count = primary.device_count.saturating_sub(1);
+
+        emit!(DeviceRetired {
+            owner: device.owner,
+            device_id_hash: device.device_id_hash,
+            merged_records: device.bundle_history.len() as u16,
+        });
+        Ok(())
+    }
+
+    /// Merge one nonce registry into another after consolidating devices or wallets.
+    /// Both owners sign (they may be the same key). The source's history, recent hashes
+    /// and fraud records are appended to the destination without duplicating bundles,
+    /// the destination keeps the higher nonce, and the source is closed with its rent
+    /// returned to whoever paid it. A source with reserved nonces, pending liabilities or fraud reports
+    /// still inside the withdrawal delay can't be merged, nor a primary with devices still
+    /// enrolled. A device registry only merges into its owner's primary, like `retire_device`.
+    pub fn merge_registries(ctx: Context<MergeRegistries>) -> Result<()> {
+        let source = &ctx.accounts.source_registry;
+        require_keys_neq!(
+            source.key(),
+            ctx.accounts.destination_registry.key(),
+            BeamError::InvalidRegistryMerge
+        );
+        require!(
+            source.pending_nonces.is_empty() && source.pending_liabilities.is_empty(),
+            BeamError::DeviceRegistryBusy
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            !source.has_open_disputes(ctx.accounts.config.fraud_withdrawal_delay, now),
+            BeamError::RegistryDisputed
+        );
+
+        require!(source.device_count == 0, BeamError::DevicesEnrolled);
+        if source.is_device() {
+            let destination = &ctx.accounts.destination_registry;
+            require!(
+                destination.owner == source.owner && !destination.is_device(),
+                BeamError::DeviceRegistryMismatch
+            );
+        }
+
+        let capacity = RegistryCapacity::of(&ctx.accounts.destination_registry.to_account_info());
+        let destination = &mut ctx.accounts.destination_registry;
+        destination.absorb(source, capacity);
+        destination.last_nonce = destination.last_nonce.max(source.last_nonce);
+        if source.is_device() {
+            destination.device_count = destination.device_count.saturating_sub(1);
+        }
+
+        emit!(RegistriesMerged {
+            source_owner: source.owner,
+            source_device_id_hash: source.device_id_hash,
+            destination_owner: destination.owner,
+            destination_device_id_hash: destination.device_id_hash,
+            merged_records: source.bundle_history.len() as u16,
+            last_nonce: destination.last_nonce,
+        });
+        Ok(())
+    }
+
+    /// Undo an early integration mistake, such as a far-ahead nonce merged in from
+    /// another registry or reservations that will never settle: reset the primary
+    /// nonce mark on both the registry and the escrow and drop every reservation.
+    /// Only within the config's correction window after the escrow was created
+    /// (registries don't record their own creation, and every onboarding path
+    /// creates both together), and never once the escrow has settled, since that
+    /// could reopen settled nonces.
+    pub fn correct_nonce_state(ctx: Context<CorrectNonceState>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        let window = ctx.accounts.config.nonce_correction_window;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            window > 0 && now <= escrow.created_at.saturating_add(window),
+            BeamError::NonceCorrectionWindowClosed
+        );
+        require!(escrow.settlement_count == 0, BeamError::NonceCorrectionLocked);
+
+        let registry = &mut ctx.accounts.nonce_registry;
+        let previous_last_nonce = registry.last_nonce.max(escrow.last_nonce);
+        let released_nonces = std::mem::take(&mut registry.pending_nonces);
+        registry.last_nonce = 0;
+        escrow.last_nonce = 0;
+        escrow.record_owner_activity(now);
+
+        emit!(NonceCorrected {
+            owner: escrow.owner,
+            previous_last_nonce,
+            released_nonces,
+        });
+
+        Ok(())
+    }
+
+    /// Reserve a nonce above the high-water mark so its bundle can still settle if a
+    /// higher nonce lands first, e.g. when an earlier settlement failed and is retried.
+    pub fn reserve_nonce(ctx: Context<ManageNonceRegistry>, nonce: u64) -> Result<()> {
+        let registry = &mut ctx.accounts.nonce_registry;
+        require!(
+            nonce > registry.last_nonce && !registry.pending_nonces.contains(&nonce),
+            BeamError::InvalidNonce
+        );
+        require!(
+            registry.pending_nonces.len() < MAX_PENDING_NONCES,
+            BeamError::PendingNonceLimitReached
+        );
+        registry.pending_nonces.push(nonce);
+
+        emit!(NonceReserved {
+            owner: registry.owner,
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    /// Drop a reservation whose bundle will never settle
+    pub fn release_nonce(ctx: Context<ManageNonceRegistry>, nonce: u64) -> Result<()> {
+        let registry = &mut ctx.accounts.nonce_registry;
+        let index = registry
+            .pending_nonces
+            .iter()
+            .position(|pending| *pending == nonce)
+            .ok_or(BeamError::NonceNotPending)?;
+        registry.pending_nonces.remove(index);
+
+        emit!(NonceReleased {
+            owner: registry.owner,
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    /// Register a bundle signed offline whose merchant has not settled yet. Until it
+    /// settles or goes stale, withdrawals can't take the escrow below the total of
+    /// registered liabilities. When the list is full, stale entries are pruned first.
+    pub fn register_liability(
+        ctx: Context<ManageNonceRegistry>,
+        bundle_hash: [u8; 32],
+        merchant: Pubkey,
+        amount: u64,
+    ) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let registry = &mut ctx.accounts.nonce_registry;
+        require!(
+            !registry.recent_bundle_hashes.contains(&bundle_hash)
+                && !registry
+                    .pending_liabilities
+                    .iter()
+                    .any(|liability| liability.bundle_hash == bundle_hash),
+            BeamError::DuplicateBundle
+        );
+
+        if registry.pending_liabilities.len() >= MAX_PENDING_LIABILITIES {
+            prune_stale_liabilities(registry, now);
+        }
+        require!(
+            registry.pending_liabilities.len() < MAX_PENDING_LIABILITIES,
+            BeamError::PendingLiabilityLimitReached
+        );
+        registry.pending_liabilities.push(PendingLiability {
+            bundle_hash,
+            merchant,
+            amount,
+            registered_at: now,
+        });
+
+        emit!(LiabilityRegistered {
+            owner: registry.owner,
+            merchant,
+            bundle_hash,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless crank dropping liabilities past the attestation max age
+    pub fn prune_liabilities(ctx: Context<PruneLiabilities>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        prune_stale_liabilities(&mut ctx.accounts.nonce_registry, now);
+        Ok(())
+    }
+
+    /// Bring a nonce registry up to the current layout: grow it to fit fields added
+    /// since it was created and rewrite records that predate any of them
+    pub fn migrate_nonce_registry(ctx: Context<MigrateNonceRegistry>) -> Result<()> {
+        let registry_info = &ctx.accounts.nonce_registry;
+        let target_size = 8 + NonceRegistry::INIT_SPACE;
+        let legacy = {
+            let data = registry_info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 + 32 && data[..8] == *NonceRegistry::DISCRIMINATOR,
+                BeamError::InvalidOwner
+            );
+            require!(
+                data[8..40] == ctx.accounts.owner.key().to_bytes(),
+                BeamError::InvalidOwner
+            );
+            LegacyRegistryLayout::of_len(data.len())
+                .map(|layout| NonceRegistry::from_legacy_bytes(&data[8..], layout))
+                .transpose()
+                .map_err(|_| BeamError::InvalidOwner)?
+        };
+
+        grow_account(
+            registry_info,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            target_size,
+        )?;
+
+        if let Some(registry) = legacy {
+            let mut data = registry_info.try_borrow_mut_data()?;
+            let mut writer: &mut [u8] = &mut data;
+            registry.try_serialize(&mut writer)?;
+        }
+
+        Ok(())
+    }
+
+    /// Make room in a nonce registry for `REGISTRY_GROWTH_HISTORY` more history
+    /// records and `REGISTRY_GROWTH_FRAUD_RECORDS` more fraud records, up to
+    /// `MAX_REGISTRY_GROWTH_STEPS` times. Entries stay as they are; the owner
+    /// pays the added rent. Legacy registries must be migrated first.
+    pub fn grow_registry(ctx: Context<GrowRegistry>) -> Result<()> {
+        let registry_info = ctx.accounts.nonce_registry.to_account_info();
+        require!(
+            LegacyRegistryLayout::of_len(registry_info.data_len()).is_none(),
+            BeamError::RegistryNotMigrated
+        );
+        let capacity = RegistryCapacity::of(&registry_info);
+        require!(
+            capacity.growth_steps < MAX_REGISTRY_GROWTH_STEPS,
+            BeamError::RegistryAtMaxCapacity
+        );
+
+        let grown = RegistryCapacity {
+            growth_steps: capacity.growth_steps + 1,
+        };
+        grow_account(
+            &registry_info,
+            &ctx.accounts.owner,
+            &ctx.accounts.system_program,
+            RegistryCapacity::account_len(grown.growth_steps),
+        )?;
+
+        let registry = &ctx.accounts.nonce_registry;
+        emit!(RegistryGrown {
+            owner: registry.owner,
+            device_id_hash: registry.device_id_hash,
+            growth_steps: grown.growth_steps,
+            bundle_history_capacity: grown.bundle_history() as u16,
+            fraud_record_capacity: grown.fraud_records() as u16,
+        });
+        Ok(())
+    }
+
+    /// Export a page of `bundle_history` via return data in the packed layout
+    /// documented on `HISTORY_EXPORT_VERSION`, oldest record first
+    pub fn export_history(ctx: Context<ExportHistory>, start: u16, max_records: u8) -> Result<()> {
+        let history = &ctx.accounts.nonce_registry.bundle_history;
+        let start = start as usize;
+        require!(start <= history.len(), BeamError::InvalidExportRange);
+
+        let count = (max_records as usize)
+            .min(MAX_EXPORT_RECORDS)
+            .min(history.len() - start);
+
+        let mut out = Vec::with_capacity(HISTORY_EXPORT_HEADER_LEN + count * BundleRecord::PACKED_LEN);
+        out.push(HISTORY_EXPORT_VERSION);
+        out.extend_from_slice(&(history.len() as u16).to_le_bytes());
+        out.extend_from_slice(&(start as u16).to_le_bytes());
+        out.push(count as u8);
+        for record in &history[start..start + count] {
+            record.pack_into(&mut out);
+        }
+
+        set_return_data(&out);
+        Ok(())
+    }
+
+    /// Remaining settlement budget in the current rolling window, or `u64::MAX`
+    /// when no rolling cap is configured
+    pub fn get_rolling_budget(ctx: Context<EscrowView>) -> Result<u64> {
+        let config = &ctx.accounts.config;
+        if config.rolling_cap == 0 {
+            return Ok(u64::MAX);
+        }
+        let now = Clock::get()?.unix_timestamp;
+        let spent = ctx
+            .accounts
+            .escrow_account
+            .rolling_spent(now, config.rolling_window_days);
+        Ok(config.rolling_cap.saturating_sub(spent))
+    }
+
+    /// Dry-run every proof in `evidence` against the bundle fields, exactly as a
+    /// settlement would check them, and report which proofs pass and why not
+    pub fn verify_evidence(
+        ctx: Context<VerifyEvidence>,
+        payer: Pubkey,
+        merchant: Pubkey,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: String,
+        evidence: SettlementEvidence,
+    ) -> Result<EvidenceVerification> {
+        let clock = Clock::get()?;
+        let bundle = BundleFields {
+            bundle_id,
+            payer,
+            merchant,
+            amount,
+            nonce: payer_nonce,
+            order_ref: evidence.order_ref,
+            courier: evidence.courier,
+            device_id_hash: evidence.device_id_hash,
+            condition_id: evidence.condition_id,
+        };
+        let check = |proof: Option<&AttestationProof>, role| {
+            let Some(proof) = proof else {
+                return ProofVerification::default();
+            };
+            let result = ctx.accounts.dry_run(proof, role, &bundle, &clock);
+            ProofVerification { present: true, valid: result.valid, error_code: result.error_code }
+        };
+
+        Ok(EvidenceVerification {
+            payer: check(evidence.payer_proof.as_ref(), AttestationRole::Payer),
+            merchant: check(evidence.merchant_proof.as_ref(), AttestationRole::Merchant),
+        })
+    }
+
+    /// Dry-run one attestation proof for `role` against the bundle fields with the
+    /// same checks settlement runs, under the current config and verifier keys.
+    /// Mutates nothing; besides the verdict it reports the root the proof should
+    /// carry and the key that has to have signed it.
+    pub fn verify_attestation_only(
+        ctx: Context<VerifyEvidence>,
+        proof: AttestationProof,
+        role: AttestationRole,
+        bundle: BundleFields,
+    ) -> Result<AttestationVerification> {
+        let clock = Clock::get()?;
+        Ok(ctx.accounts.dry_run(&proof, role, &bundle, &clock))
+    }
+
+    /// Attestation requirements of this deployment, so clients can shape their
+    /// attestation requests to it rather than hardcoding them
+    pub fn get_attestation_policy(ctx: Context<ConfigView>) -> Result<AttestationPolicy> {
+        let config = &ctx.accounts.config;
+        let has_fallback = config.fallback_verifier != Pubkey::default();
+        Ok(AttestationPolicy {
+            max_unattested_amount: u64::MAX,
+            max_attestation_age: MAX_ATTESTATION_AGE,
+            accepted_schemes: if config.is_aggregate_attestation_enabled() {
+                ATTESTATION_SCHEME_ED25519 | ATTESTATION_SCHEME_AGGREGATE
+            } else {
+                ATTESTATION_SCHEME_ED25519
+            },
+            verifier_key_count: 1 + u8::from(has_fallback),
+            primary_verifier: VERIFIER_PUBKEY,
+            fallback_verifier: config.fallback_verifier,
+            fallback_cap: config.fallback_cap,
+            max_heartbeat_age: config.max_heartbeat_age,
+            max_voucher_validity: config.max_voucher_validity,
+        })
+    }
+
+    /// Snapshot of the escrow for wallets juggling several escrows
+    pub fn get_escrow_summary(ctx: Context<EscrowView>) -> Result<EscrowSummary> {
+        let escrow = &ctx.accounts.escrow_account;
+        let now = Clock::get()?.unix_timestamp;
+        Ok(EscrowSummary {
+            owner: escrow.owner,
+            label: escrow.label,
+            escrow_balance: escrow.escrow_balance,
+            settleable_balance: escrow.settleable_balance(now, ctx.accounts.config.funding_lockup_secs),
+            stake_locked: escrow.stake_locked,
+            total_spent: escrow.total_spent,
+            last_nonce: escrow.last_nonce,
+            reputation_score: escrow.decayed_reputation(&ctx.accounts.config, now).1,
+            fraud_count: escrow.fraud_count,
+            settlement_count: escrow.settlement_count,
+            timed_settlement_count: escrow.timed_settlement_count,
+            settled_within: escrow.settled_within,
+        })
+    }
+
+    /// Fraud history of an escrow, broken down by reason
+    pub fn get_fraud_summary(ctx: Context<EscrowView>) -> Result<FraudSummary> {
+        let escrow = &ctx.accounts.escrow_account;
+        Ok(FraudSummary {
+            owner: escrow.owner,
+            fraud_count: escrow.fraud_count,
+            stake_locked: escrow.stake_locked,
+            last_fraud_timestamp: escrow.last_fraud_timestamp,
+            by_reason: escrow.fraud_counts_by_reason,
+        })
+    }
+
+    /// Where `owner`'s escrow lives and the token account holding its `mint`, so
+    /// composing programs can derive them on-chain instead of copying the seeds.
+    /// Escrows keep their address when ownership moves, so this is the address of
+    /// the escrow `owner` opened. An escrow may be opened on any token account it
+    /// owns: pass the existing escrow to get the one it recorded, which settlement
+    /// checks against. Otherwise the canonical associated token account is returned.
+    pub fn derive_escrow_address(
+        ctx: Context<DeriveEscrowAddress>,
+        owner: Pubkey,
+        mint: Pubkey,
+    ) -> Result<EscrowAddress> {
+        let (escrow, bump) = Pubkey::find_program_address(&[b"escrow", owner.as_ref()], &crate::ID);
+        let recorded = ctx
+            .accounts
+            .escrow_account
+            .as_ref()
+            .and_then(|account| recorded_escrow_token_account(account));
+        Ok(EscrowAddress {
+            escrow,
+            bump,
+            token_account: recorded.unwrap_or_else(|| {
+                get_associated_token_address_with_program_id(
+                    &escrow,
+                    &mint,
+                    &ctx.accounts.token_program.key(),
+                )
+            }),
+            recorded: recorded.is_some(),
+        })
+    }
+
+    /// Name the escrow for display, e.g. "Groceries". All zeroes clears the label.
+    pub fn set_label(ctx: Context<OwnerEscrowAction>, label: [u8; 32]) -> Result<()> {
+        require!(is_valid_label(&label), BeamError::InvalidLabel);
+
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.label = label;
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(EscrowLabelUpdated {
+            owner: escrow.owner,
+            label,
+        });
+
+        Ok(())
+    }
+
+    /// Back the escrow with another escrow-owned token account of the same mint.
+    /// Funding, settlement and withdrawal may then use any backing account.
+    pub fn add_backing_token_account(ctx: Context<AddBackingTokenAccount>) -> Result<()> {
+        let backing = ctx.accounts.backing_token_account.key();
+        let escrow = &mut ctx.accounts.escrow_account;
+        require!(!escrow.is_backing_account(&backing), BeamError::InvalidEscrowTokenAccount);
+        let slot = escrow
+            .backing_token_accounts
+            .iter_mut()
+            .find(|account| **account == Pubkey::default())
+            .ok_or(BeamError::BackingAccountLimitReached)?;
+        *slot = backing;
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(BackingAccountAdded {
+            owner: escrow.owner,
+            token_account: backing,
+        });
+
+        Ok(())
+    }
+
+    /// Stop using an emptied backing account. The primary account cannot be removed.
+    pub fn remove_backing_token_account(ctx: Context<RemoveBackingTokenAccount>) -> Result<()> {
+        require!(
+            ctx.accounts.backing_token_account.amount == 0,
+            BeamError::BackingAccountNotEmpty
+        );
+        let backing = ctx.accounts.backing_token_account.key();
+        let escrow = &mut ctx.accounts.escrow_account;
+        let slot = escrow
+            .backing_token_accounts
+            .iter_mut()
+            .find(|account| **account == backing)
+            .ok_or(BeamError::InvalidEscrowTokenAccount)?;
+        *slot = Pubkey::default();
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(BackingAccountRemoved {
+            owner: escrow.owner,
+            token_account: backing,
+        });
+
+        Ok(())
+    }
+
+    /// Check the escrow's books against its token accounts. `remaining_accounts`
+    /// must list every backing account, primary included, exactly once; their
+    /// combined balance has to cover `escrow_balance`.
+    pub fn verify_solvency<'info>(
+        ctx: Context<'_, '_, 'info, 'info, EscrowView<'info>>,
+    ) -> Result<u64> {
+        let escrow = &ctx.accounts.escrow_account;
+        let expected = escrow.backing_accounts().count();
+        require!(
+            ctx.remaining_accounts.len() == expected,
+            BeamError::InvalidEscrowTokenAccount
+        );
+
+        let mut seen: Vec<Pubkey> = Vec::with_capacity(expected);
+        let mut backing_total: u64 = 0;
+        for info in ctx.remaining_accounts {
+            let vault = InterfaceAccount::<TokenAccount>::try_from(info)?;
+            require!(
+                escrow.is_backing_account(&info.key()) && !seen.contains(&info.key()),
+                BeamError::InvalidEscrowTokenAccount
+            );
+            seen.push(info.key());
+            backing_total = backing_total
+                .checked_add(vault.amount)
+                .ok_or(BeamError::Overflow)?;
+        }
+        require!(backing_total >= escrow.escrow_balance, BeamError::EscrowInsolvent);
+
+        emit!(SolvencyVerified {
+            owner: escrow.owner,
+            escrow_balance: escrow.escrow_balance,
+            backing_total,
+        });
+
+        Ok(backing_total)
+    }
+
+    /// Withdraw unused escrow funds. During the fraud withdrawal delay the whole
+    /// balance is frozen, unless the config enables the dispute reserve: then the
+    /// owner may withdraw down to pending liabilities plus that reserve. Slashed
+    /// funds already sit in `stake_locked` and never count toward either.
+    pub fn withdraw_escrow(ctx: Context<WithdrawEscrow>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        require!(ctx.accounts.escrow_account.escrow_balance >= amount, BeamError::InsufficientFunds);
+        require!(ctx.accounts.escrow_account.credit_debt == 0, BeamError::CreditDebtOutstanding);
+        let now = Clock::get()?.unix_timestamp;
+        let config = &ctx.accounts.config;
+        let disputed = ctx
+            .accounts
+            .escrow_account
+            .fraud_unlock_at(config.fraud_withdrawal_delay, now)
+            .is_some();
+        // With the dispute reserve on, an open dispute holds back only what
+        // further reports could slash rather than the whole balance
+        if !config.is_dispute_reserve_enabled() {
+            check_fraud_withdrawal_delay(
+                &ctx.accounts.escrow_account,
+                config.fraud_withdrawal_delay,
+                now,
+            )?;
+        }
+        require!(
+            ctx.accounts.escrow_account.unreserved_balance(now) >= amount,
+            BeamError::FundsReserved
+        );
+        let remaining = ctx.accounts.escrow_account.escrow_balance - amount;
+        let liabilities = outstanding_liabilities(&ctx.accounts.nonce_registry, now)?;
+        require!(remaining >= liabilities, BeamError::OutstandingLiabilities);
+        if config.is_dispute_reserve_enabled() && disputed {
+            let reserve = dispute_reserve(&ctx.accounts.nonce_registry, config)?;
+            if remaining - liabilities < reserve {
+                msg!("Open disputes hold back {}", reserve);
+                return err!(BeamError::DisputeReserveRequired);
+            }
+        }
+
+        let owner_key = ctx.accounts.escrow_account.owner;
+        let bump = ctx.accounts.escrow_account.bump;
+        let seed_key = *ctx.accounts.escrow_account.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        // The owner bears any transfer fee on what it withdraws
+        let vault_before = ctx.accounts.escrow_token_account.amount;
+        transfer_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.owner_token_account.to_account_info(),
+            ctx.accounts.escrow_account.to_account_info(),
+            ctx.accounts.mint.as_deref(),
+            amount,
+            signer,
+        )?;
+        if ctx.accounts.config.is_strict_vault_checks() {
+            check_vault_delta(
+                &mut ctx.accounts.escrow_token_account,
+                vault_before,
+                -i128::from(amount),
+            )?;
+        }
+
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.escrow_balance = escrow.escrow_balance.checked_sub(amount)
+            .ok_or(BeamError::Underflow)?;
+        // Withdrawals take back the most recent (least matured) deposits first
+        escrow.release_newest_funding(amount);
+        escrow.record_owner_activity(now);
+
+        emit!(EscrowWithdrawn {
+            owner: owner_key,
+            amount,
+            remaining_balance: escrow.escrow_balance,
+            escrow_token_account: ctx.accounts.escrow_token_account.key(),
+        });
+
+        Ok(())
+    }
+
+    /// Set `amount` aside for bundles signed while offline. Until `expires_at` it can
+    /// only leave the escrow through settlements, which draw on it first. Adding to an
+    /// active reservation keeps the later of the two expiries.
+    pub fn reserve_funds(ctx: Context<OwnerEscrowAction>, amount: u64, expires_at: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &mut ctx.accounts.escrow_account;
+        require!(amount > 0 && expires_at > now, BeamError::InvalidReservation);
+        require!(escrow.unreserved_balance(now) >= amount, BeamError::InsufficientFunds);
+
+        let active = escrow.active_reservation(now);
+        escrow.reserved_balance = active + amount;
+        escrow.reservation_expires_at = if active > 0 {
+            escrow.reservation_expires_at.max(expires_at)
+        } else {
+            expires_at
+        };
+        escrow.record_owner_activity(now);
+
+        emit!(FundsReserved {
+            owner: escrow.owner,
+            amount,
+            reserved_balance: escrow.reserved_balance,
+            expires_at: escrow.reservation_expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Free `amount` of an active reservation early
+    pub fn release_reservation(ctx: Context<OwnerEscrowAction>, amount: u64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &mut ctx.accounts.escrow_account;
+        let active = escrow.active_reservation(now);
+        require!(amount > 0 && amount <= active, BeamError::InvalidReservation);
+
+        escrow.reserved_balance = active - amount;
+        escrow.record_owner_activity(now);
+
+        emit!(ReservationReleased {
+            owner: escrow.owner,
+            amount,
+            reserved_balance: escrow.reserved_balance,
+            expired: false,
+        });
+
+        Ok(())
+    }
+
+    /// Permissionless crank clearing a reservation past its expiry
+    pub fn expire_reservation(ctx: Context<ExpireReservation>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &mut ctx.accounts.escrow_account;
+        require!(
+            escrow.reserved_balance > 0 && escrow.reservation_expires_at <= now,
+            BeamError::InvalidReservation
+        );
+
+        let amount = escrow.reserved_balance;
+        escrow.reserved_balance = 0;
+        escrow.reservation_expires_at = 0;
+
+        emit!(ReservationReleased {
+            owner: escrow.owner,
+            amount,
+            reserved_balance: 0,
+            expired: true,
+        });
+
+        Ok(())
+    }
+
+    /// Let `merchant` pull up to `amount` from the escrow until `expires_at`
+    /// with only its own signature, e.g. for recurring bills
+    pub fn grant_allowance(ctx: Context<GrantAllowance>, amount: u64, expires_at: i64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        require!(expires_at > now, BeamError::AllowanceExpired);
+
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.owner = ctx.accounts.owner.key();
+        allowance.merchant = ctx.accounts.merchant.key();
+        allowance.remaining = amount;
+        allowance.expires_at = expires_at;
+        allowance.bump = ctx.bumps.allowance;
+        ctx.accounts.escrow_account.record_owner_activity(now);
+
+        emit!(AllowanceGranted {
+            owner: allowance.owner,
+            merchant: allowance.merchant,
+            amount,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Owner cancels an allowance and reclaims its rent
+    pub fn revoke_allowance(ctx: Context<RevokeAllowance>) -> Result<()> {
+        let allowance = &ctx.accounts.allowance;
+
+        emit!(AllowanceRevoked {
+            owner: allowance.owner,
+            merchant: allowance.merchant,
+            remaining: allowance.remaining,
+        });
+
+        Ok(())
+    }
+
+    /// Let `delegated_program` settle from the escrow by CPI, up to `cap` in
+    /// every `period_secs` window, e.g. for an automated bill-payer
+    pub fn grant_delegated_spender(
+        ctx: Context<GrantDelegatedSpender>,
+        cap: u64,
+        period_secs: i64,
+    ) -> Result<()> {
+        require!(cap > 0, BeamError::InvalidAmount);
+        require!(period_secs > 0, BeamError::InvalidConfig);
+        let now = Clock::get()?.unix_timestamp;
+
+        let delegation = &mut ctx.accounts.delegated_spender;
+        delegation.owner = ctx.accounts.owner.key();
+        delegation.program = ctx.accounts.delegated_program.key();
+        delegation.cap = cap;
+        delegation.period_secs = period_secs;
+        delegation.period_start = now;
+        delegation.spent_in_period = 0;
+        delegation.bump = ctx.bumps.delegated_spender;
+        ctx.accounts.escrow_account.record_owner_activity(now);
+
+        emit!(DelegatedSpenderGranted {
+            owner: delegation.owner,
+            program: delegation.program,
+            cap,
+            period_secs,
+        });
+
+        Ok(())
+    }
+
+    /// Owner withdraws a program's spending authority and reclaims the rent
+    pub fn revoke_delegated_spender(ctx: Context<RevokeDelegatedSpender>) -> Result<()> {
+        let delegation = &ctx.accounts.delegated_spender;
+
+        emit!(DelegatedSpenderRevoked {
+            owner: delegation.owner,
+            program: delegation.program,
+        });
+
+        Ok(())
+    }
+
+    /// Guarantor agrees to cover `payer`'s settlement shortfalls from its own
+    /// escrow, up to `max_exposure` in total
+    pub fn grant_guarantee(ctx: Context<GrantGuarantee>, max_exposure: u64) -> Result<()> {
+        require!(max_exposure > 0, BeamError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+
+        let guarantee = &mut ctx.accounts.guarantee;
+        guarantee.guarantor = ctx.accounts.guarantor.key();
+        guarantee.payer = ctx.accounts.payer.key();
+        guarantee.max_exposure = max_exposure;
+        guarantee.drawn = 0;
+        guarantee.bump = ctx.bumps.guarantee;
+        ctx.accounts.guarantor_escrow.record_owner_activity(now);
+
+        emit!(GuaranteeGranted {
+            guarantor: guarantee.guarantor,
+            payer: guarantee.payer,
+            max_exposure,
+        });
+
+        Ok(())
+    }
+
+    /// Guarantor withdraws its consent and reclaims the rent; settlements
+    /// already drawn are unaffected
+    pub fn revoke_guarantee(ctx: Context<RevokeGuarantee>) -> Result<()> {
+        let guarantee = &ctx.accounts.guarantee;
+
+        emit!(GuaranteeRevoked {
+            guarantor: guarantee.guarantor,
+            payer: guarantee.payer,
+            drawn: guarantee.drawn,
+        });
+
+        Ok(())
+    }
+
+    /// Underwriter extends `payer` a credit line drawn from a vault owned by the line's
+    /// PDA, which the underwriter funds with ordinary token transfers. Settlements the
+    /// payer's escrow can't cover draw the shortfall, and the payer owes it back plus
+    /// `fee_bps`, up to `limit` outstanding.
+    pub fn open_credit_line(
+        ctx: Context<OpenCreditLine>,
+        limit: u64,
+        fee_bps: u16,
+        min_reputation: i32,
+    ) -> Result<()> {
+        require!(limit > 0, BeamError::InvalidAmount);
+        require!(fee_bps <= MAX_CREDIT_FEE_BPS, BeamError::InvalidConfig);
+        require!(
+            (MIN_REPUTATION..=MAX_REPUTATION).contains(&min_reputation),
+            BeamError::InvalidConfig
+        );
+
+        let line = &mut ctx.accounts.credit_line;
+        line.underwriter = ctx.accounts.underwriter.key();
+        line.payer = ctx.accounts.payer.key();
+        line.vault = ctx.accounts.credit_vault.key();
+        line.limit = limit;
+        line.fee_bps = fee_bps;
+        line.min_reputation = min_reputation;
+        line.frozen = false;
+        line.bump = ctx.bumps.credit_line;
+
+        emit!(CreditLineOpened {
+            underwriter: line.underwriter,
+            payer: line.payer,
+            vault: line.vault,
+            limit,
+            fee_bps,
+            min_reputation,
+        });
+
+        Ok(())
+    }
+
+    /// Stop or resume draws on a credit line. Repayments are accepted either way.
+    pub fn set_credit_line_frozen(ctx: Context<ManageCreditLine>, frozen: bool) -> Result<()> {
+        let line = &mut ctx.accounts.credit_line;
+        line.frozen = frozen;
+
+        emit!(CreditLineFrozen {
+            underwriter: line.underwriter,
+            payer: line.payer,
+            frozen,
+        });
+
+        Ok(())
+    }
+
+    /// Underwriter takes repayments or unused funds out of the credit vault
+    pub fn withdraw_credit_vault(ctx: Context<WithdrawCreditVault>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        let line = &ctx.accounts.credit_line;
+        let seeds = &[
+            b"credit_line",
+            line.underwriter.as_ref(),
+            line.payer.as_ref(),
+            &[line.bump],
+        ];
+        transfer_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.credit_vault.to_account_info(),
+            ctx.accounts.underwriter_token_account.to_account_info(),
+            line.to_account_info(),
+            None,
+            amount,
+            &[&seeds[..]],
+        )?;
+
+        emit!(CreditVaultWithdrawn {
+            underwriter: line.underwriter,
+            payer: line.payer,
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant pulls `amount` against its allowance. Escrow balance, lockup, rolling
+    /// cap, merchant cap and protocol fee apply exactly as for a settlement.
+    pub fn draw_allowance(ctx: Context<DrawAllowance>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let merchant_key = ctx.accounts.merchant.key();
+        let config = &ctx.accounts.config;
+        let allowance = &ctx.accounts.allowance;
+        let escrow = &ctx.accounts.escrow_account;
+
+        require!(now <= allowance.expires_at, BeamError::AllowanceExpired);
+        require!(amount <= allowance.remaining, BeamError::AllowanceExceeded);
+        check_merchant_pull(escrow, config, &ctx.accounts.escrow_token_account.mint, &merchant_key, amount, now)?;
+        compliance::check_parties(config, ctx.remaining_accounts, &escrow.owner, &merchant_key, now)?;
+
+        let owner_key = escrow.owner;
+        let fee = pay_merchant_pull(
+            &mut ctx.accounts.escrow_account,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.merchant_token_account,
+            ctx.accounts.treasury_token_account.as_ref(),
+            &ctx.accounts.config,
+            &ctx.accounts.token_program,
+            &merchant_key,
+            amount,
+            now,
+        )?;
+
+        let allowance = &mut ctx.accounts.allowance;
+        allowance.remaining -= amount;
+
+        emit!(AllowanceDrawn {
+            owner: owner_key,
+            merchant: merchant_key,
+            amount,
+            fee,
+            remaining: allowance.remaining,
+        });
+
+        Ok(())
+    }
+
+    /// Let `merchant` pull from the escrow without a bundle signature, at most
+    /// `per_pull_cap` per pull and `total_cap` altogether until `expires_at`
+    pub fn grant_permit(
+        ctx: Context<GrantPermit>,
+        total_cap: u64,
+        per_pull_cap: u64,
+        expires_at: i64,
+    ) -> Result<()> {
+        require!(
+            per_pull_cap > 0 && per_pull_cap <= total_cap,
+            BeamError::InvalidPermit
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(expires_at > now, BeamError::PermitExpired);
+
+        let permit = &mut ctx.accounts.permit;
+        permit.owner = ctx.accounts.owner.key();
+        permit.merchant = ctx.accounts.merchant.key();
+        permit.total_cap = total_cap;
+        permit.per_pull_cap = per_pull_cap;
+        permit.pulled = 0;
+        permit.pull_count = 0;
+        permit.expires_at = expires_at;
+        permit.bump = ctx.bumps.permit;
+        permit.revoked_at = 0;
+        ctx.accounts.escrow_account.record_owner_activity(now);
+
+        emit!(PermitGranted {
+            owner: permit.owner,
+            merchant: permit.merchant,
+            total_cap,
+            per_pull_cap,
+            expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant pulls `amount` against its permit. The escrow's balance, lockup,
+    /// rolling cap, merchant cap and protocol fee apply as for a settlement.
+    pub fn pull_with_permit(ctx: Context<PullWithPermit>, amount: u64) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        let now = Clock::get()?.unix_timestamp;
+        let merchant_key = ctx.accounts.merchant.key();
+        let permit = &ctx.accounts.permit;
+        let escrow = &ctx.accounts.escrow_account;
+
+        require!(!permit.is_revoked(), BeamError::PermitRevoked);
+        require!(now <= permit.expires_at, BeamError::PermitExpired);
+        require!(amount <= permit.per_pull_cap, BeamError::PermitPullCapExceeded);
+        require!(amount <= permit.remaining(), BeamError::PermitCapExceeded);
+        check_merchant_pull(
+            escrow,
+            &ctx.accounts.config,
+            &ctx.accounts.escrow_token_account.mint,
+            &merchant_key,
+            amount,
+            now,
+        )?;
+        compliance::check_parties(
+            &ctx.accounts.config,
+            ctx.remaining_accounts,
+            &escrow.owner,
+            &merchant_key,
+            now,
+        )?;
+
+        let owner_key = escrow.owner;
+        let fee = pay_merchant_pull(
+            &mut ctx.accounts.escrow_account,
+            &ctx.accounts.escrow_token_account,
+            &ctx.accounts.merchant_token_account,
+            ctx.accounts.treasury_token_account.as_ref(),
+            &ctx.accounts.config,
+            &ctx.accounts.token_program,
+            &merchant_key,
+            amount,
+            now,
+        )?;
+
+        let permit = &mut ctx.accounts.permit;
+        permit.pulled = permit.pulled.checked_add(amount).ok_or(BeamError::Overflow)?;
+        permit.pull_count = permit.pull_count.saturating_add(1);
+
+        emit!(PermitPulled {
+            owner: owner_key,
+            merchant: merchant_key,
+            amount,
+            fee,
+            remaining: permit.remaining(),
+            expires_at: permit.expires_at,
+        });
+
+        Ok(())
+    }
+
+    /// Owner cancels a permit, effective for every later pull. The account stays
+    /// until `close_permit` so pulls still in flight fail with `PermitRevoked`.
+    pub fn revoke_permit(ctx: Context<PermitOwnerAction>) -> Result<()> {
+        let permit = &mut ctx.accounts.permit;
+        require!(!permit.is_revoked(), BeamError::PermitRevoked);
+        permit.revoked_at = Clock::get()?.unix_timestamp;
+
+        emit!(PermitRevoked {
+            owner: permit.owner,
+            merchant: permit.merchant,
+            remaining: permit.remaining(),
+        });
+
+        Ok(())
+    }
+
+    /// Reclaim the rent of a revoked or expired permit
+    pub fn close_permit(ctx: Context<ClosePermit>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let permit = &ctx.accounts.permit;
+        require!(
+            permit.is_revoked() || now > permit.expires_at,
+            BeamError::PermitStillActive
+        );
+
+        emit!(PermitClosed {
+            owner: permit.owner,
+            merchant: permit.merchant,
+            pulled: permit.pulled,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant, remaining allowance, expiry and status of each of `owner`'s
+    /// permits passed in `remaining_accounts`, in the order given
+    pub fn get_permits<'info>(
+        ctx: Context<'_, '_, 'info, 'info, PermitsView<'info>>,
+    ) -> Result<Vec<PermitSummary>> {
+        require!(
+            ctx.remaining_accounts.len() <= MAX_PERMIT_SUMMARIES,
+            BeamError::TooManyPermits
+        );
+        let now = Clock::get()?.unix_timestamp;
+        let owner = ctx.accounts.owner.key();
+
+        ctx.remaining_accounts
+            .iter()
+            .map(|info| {
+                let permit = Account::<Permit>::try_from(info)?;
+                require_keys_eq!(permit.owner, owner, BeamError::InvalidOwner);
+                Ok(PermitSummary {
+                    merchant: permit.merchant,
+                    remaining: permit.remaining(),
+                    expires_at: permit.expires_at,
+                    status: permit.status(now),
+                })
+            })
+            .collect()
+    }
+
+    /// Designate who may claim the escrow after `inactivity_period` seconds without owner
+    /// activity. Passing the default pubkey removes the beneficiary.
+    pub fn set_beneficiary(
+        ctx: Context<OwnerEscrowAction>,
+        beneficiary: Pubkey,
+        inactivity_period: i64,
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        if beneficiary == Pubkey::default() {
+            escrow.inactivity_period = 0;
+        } else {
+            require_keys_neq!(beneficiary, escrow.owner, BeamError::InvalidBeneficiary);
+            require!(
+                inactivity_period >= MIN_BENEFICIARY_INACTIVITY,
+                BeamError::InvalidBeneficiary
+            );
+            escrow.inactivity_period = inactivity_period;
+        }
+        escrow.beneficiary = beneficiary;
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(BeneficiaryUpdated {
+            owner: escrow.owner,
+            beneficiary,
+            inactivity_period: escrow.inactivity_period,
+        });
+
+        Ok(())
+    }
+
+    /// Cap the total this escrow may ever settle to `merchant`. A limit of zero removes the cap.
+    pub fn set_merchant_limit(
+        ctx: Context<OwnerEscrowAction>,
+        merchant: Pubkey,
+        limit: u64,
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        let existing = escrow
+            .merchant_limits
+            .iter()
+            .position(|entry| entry.limit > 0 && entry.merchant == merchant);
+
+        match (existing, limit) {
+            (Some(index), 0) => escrow.merchant_limits[index] = MerchantLimit::default(),
+            (Some(index), _) => escrow.merchant_limits[index].limit = limit,
+            (None, 0) => {}
+            (None, _) => {
+                let slot = escrow
+                    .merchant_limits
+                    .iter_mut()
+                    .find(|entry| entry.limit == 0)
+                    .ok_or(BeamError::MerchantLimitTableFull)?;
+                *slot = MerchantLimit {
+                    merchant,
+                    limit,
+                    settled: 0,
+                };
+            }
+        }
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(MerchantLimitUpdated {
+            owner: escrow.owner,
+            merchant,
+            limit,
+        });
+
+        Ok(())
+    }
+
+    /// Register `merchant` to be named in `EscrowFundedForWatcher` whenever a
+    /// deposit lifts the balance above `threshold`, or move an existing
+    /// watcher's threshold
+    pub fn set_escrow_watcher(
+        ctx: Context<OwnerEscrowAction>,
+        merchant: Pubkey,
+        threshold: u64,
+    ) -> Result<()> {
+        require!(merchant != Pubkey::default(), BeamError::InvalidWatcher);
+        let escrow = &mut ctx.accounts.escrow_account;
+        let slot = match escrow.watchers.iter().position(|watcher| watcher.merchant == merchant) {
+            Some(index) => index,
+            None => escrow
+                .watchers
+                .iter()
+                .position(|watcher| watcher.merchant == Pubkey::default())
+                .ok_or(BeamError::WatcherTableFull)?,
+        };
+        escrow.watchers[slot] = EscrowWatcher { merchant, threshold };
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(EscrowWatcherUpdated {
+            owner: escrow.owner,
+            merchant,
+            threshold: Some(threshold),
+        });
+
+        Ok(())
+    }
+
+    /// Stop notifying `merchant` of top-ups
+    pub fn remove_escrow_watcher(ctx: Context<OwnerEscrowAction>, merchant: Pubkey) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        let slot = escrow
+            .watchers
+            .iter_mut()
+            .find(|watcher| watcher.merchant == merchant && merchant != Pubkey::default())
+            .ok_or(BeamError::InvalidWatcher)?;
+        *slot = EscrowWatcher::default();
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(EscrowWatcherUpdated {
+            owner: escrow.owner,
+            merchant,
+            threshold: None,
+        });
+
+        Ok(())
+    }
+
+    /// Turn the config's `max_reasonable_amount` check on or off for this escrow.
+    /// Owners who really do settle that much per payment opt out here.
+    pub fn set_amount_sanity_check(ctx: Context<OwnerEscrowAction>, enabled: bool) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.set_amount_check_opted_out(!enabled);
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(AmountSanityCheckUpdated {
+            owner: escrow.owner,
+            enabled,
+        });
+
+        Ok(())
+    }
+
+    /// Store the vault mint's decimals on an escrow created without its mint, so
+    /// clients can convert UI amounts against it. Anyone may call it; the value is
+    /// read from the mint itself.
+    pub fn record_mint_decimals(ctx: Context<RecordMintDecimals>) -> Result<()> {
+        let decimals = ctx.accounts.mint.decimals;
+        ctx.accounts.escrow_account.record_mint_decimals(decimals);
+        Ok(())
+    }
+
+    /// Register the key shares whose combined signatures may attest this escrow's
+    /// payer proofs, replacing any earlier set. An all-default key unregisters.
+    pub fn register_aggregate_key(ctx: Context<OwnerEscrowAction>, key: AggregateKey) -> Result<()> {
+        require!(key.is_valid(), BeamError::InvalidAggregateKey);
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.aggregate_key = key;
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(AggregateKeyRegistered {
+            owner: escrow.owner,
+            share_count: key.weights.iter().filter(|weight| **weight > 0).count() as u8,
+            total_weight: key.total_weight(),
+            threshold: key.threshold,
+        });
+
+        Ok(())
+    }
+
+    /// Opt in to rounding every settlement up to a multiple of `unit`, donating the
+    /// difference to `charity_token_account`. Disabling keeps the stored settings.
+    pub fn set_roundup_config(ctx: Context<SetRoundupConfig>, enabled: bool, unit: u64) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        if enabled {
+            let charity = ctx
+                .accounts
+                .charity_token_account
+                .as_ref()
+                .ok_or(BeamError::InvalidRoundupConfig)?;
+            require!(unit > 0, BeamError::InvalidRoundupConfig);
+            escrow.roundup_config = RoundupConfig {
+                enabled,
+                unit,
+                charity_token_account: charity.key(),
+            };
+        } else {
+            escrow.roundup_config.enabled = false;
+        }
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(RoundupConfigUpdated {
+            owner: escrow.owner,
+            enabled: escrow.roundup_config.enabled,
+            unit: escrow.roundup_config.unit,
+            charity_token_account: escrow.roundup_config.charity_token_account,
+        });
+
+        Ok(())
+    }
+
+    /// Approve a specific future bundle while online. When it later settles with the
+    /// same hash, merchant, amount and nonce, attestation verification is skipped.
+    pub fn preauthorize_bundle(
+        ctx: Context<OwnerEscrowAction>,
+        bundle_hash: [u8; 32],
+        merchant: Pubkey,
+        amount: u64,
+        nonce: u64,
+    ) -> Result<()> {
+        require!(amount > 0, BeamError::InvalidAmount);
+        let escrow = &mut ctx.accounts.escrow_account;
+        require!(nonce > escrow.last_nonce, BeamError::InvalidNonce);
+        require!(
+            !escrow
+                .preauthorizations
+                .iter()
+                .any(|entry| entry.amount > 0 && entry.bundle_hash == bundle_hash),
+            BeamError::DuplicateBundle
+        );
+
+        let slot = escrow
+            .preauthorizations
+            .iter_mut()
+            .find(|entry| entry.amount == 0)
+            .ok_or(BeamError::PreauthorizationTableFull)?;
+        *slot = Preauthorization {
+            bundle_hash,
+            merchant,
+            amount,
+            nonce,
+        };
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(BundlePreauthorized {
+            owner: escrow.owner,
+            merchant,
+            bundle_hash,
+            amount,
+            nonce,
+        });
+
+        Ok(())
+    }
+
+    /// Drop an unused pre-authorization, freeing its slot
+    pub fn cancel_preauthorization(
+        ctx: Context<OwnerEscrowAction>,
+        bundle_hash: [u8; 32],
+    ) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        let slot = escrow
+            .preauthorizations
+            .iter_mut()
+            .find(|entry| entry.amount > 0 && entry.bundle_hash == bundle_hash)
+            .ok_or(BeamError::PreauthorizationNotFound)?;
+        let merchant = slot.merchant;
+        *slot = Preauthorization::default();
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+
+        emit!(PreauthorizationCancelled {
+            owner: escrow.owner,
+            merchant,
+            bundle_hash,
+        });
+
+        Ok(())
+    }
+
+    /// Start the beneficiary notice window once the owner has been inactive long enough.
+    /// Any owner activity before the window ends cancels the claim.
+    pub fn claim_as_beneficiary(ctx: Context<ClaimAsBeneficiary>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &mut ctx.accounts.escrow_account;
+        require!(
+            escrow.beneficiary_claim_started_at == 0,
+            BeamError::BeneficiaryClaimPending
+        );
+        let inactive_for = now.saturating_sub(escrow.last_activity_at);
+        require!(
+            inactive_for > escrow.inactivity_period,
+            BeamError::OwnerStillActive
+        );
+
+        escrow.beneficiary_claim_started_at = now;
+        let notice_ends_at = now
+            .checked_add(BENEFICIARY_NOTICE_PERIOD)
+            .ok_or(BeamError::Overflow)?;
+
+        emit!(BeneficiaryClaimStarted {
+            owner: escrow.owner,
+            beneficiary: escrow.beneficiary,
+            notice_ends_at,
+        });
+
+        Ok(())
+    }
+
+    /// After an uncontested notice window, sweep the escrow to the beneficiary and close
+    /// it, returning its rent to the rent payer
+    pub fn finalize_beneficiary_claim(ctx: Context<FinalizeBeneficiaryClaim>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &ctx.accounts.escrow_account;
+        require!(
+            escrow.beneficiary_claim_started_at > 0,
+            BeamError::NoBeneficiaryClaim
+        );
+        let notice_ends_at = escrow
+            .beneficiary_claim_started_at
+            .checked_add(BENEFICIARY_NOTICE_PERIOD)
+            .ok_or(BeamError::Overflow)?;
+        require!(now >= notice_ends_at, BeamError::BeneficiaryNoticeActive);
+        // Locked stake is a fraud penalty and is not inheritable
+        require!(escrow.stake_locked == 0, BeamError::StakeStillLocked);
+        require!(!escrow.has_extra_backing_accounts(), BeamError::BackingAccountsRemain);
+
+        let owner_key = escrow.owner;
+        let amount = ctx.accounts.escrow_token_account.amount;
+        let bump = escrow.bump;
+        let seed_key = *escrow.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        if amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.escrow_token_account.to_account_info(),
+                to: ctx.accounts.beneficiary_token_account.to_account_info(),
+                authority: ctx.accounts.escrow_account.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, amount)?;
+        }
+
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.escrow_token_account.to_account_info(),
+            destination: ctx.accounts.beneficiary.to_account_info(),
+            authority: ctx.accounts.escrow_account.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+
+        emit!(BeneficiaryClaimCompleted {
+            owner: owner_key,
+            beneficiary: ctx.accounts.beneficiary.key(),
+            amount,
+        });
+
+        Ok(())
+    }
+
+    /// Flag an escrow whose owner has been inactive for the config dormancy period.
+    /// Anyone may call it; any owner activity clears the flag again.
+    pub fn flag_dormant_escrow(ctx: Context<FlagDormantEscrow>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let config = &ctx.accounts.config;
+        require!(config.dormancy_period > 0, BeamError::EscheatmentDisabled);
+        let escrow = &mut ctx.accounts.escrow_account;
+        require!(!escrow.is_dormant(), BeamError::EscrowAlreadyDormant);
+        require!(
+            now.saturating_sub(escrow.last_activity_at) >= config.dormancy_period,
+            BeamError::OwnerStillActive
+        );
+
+        escrow.set_dormant(true);
+        escrow.dormant_since = now;
+        let sweepable_at = now
+            .checked_add(config.escheat_notice_period)
+            .ok_or(BeamError::Overflow)?;
+
+        emit!(EscrowFlaggedDormant {
+            owner: escrow.owner,
+            last_activity_at: escrow.last_activity_at,
+            sweepable_at,
+        });
+
+        Ok(())
+    }
+
+    /// Once a dormant escrow's notice period has passed without owner activity, move
+    /// its balance held in `escrow_token_account` to the config custodial account.
+    /// Locked stake stays behind. Call once per backing account.
+    pub fn sweep_dormant_escrow(ctx: Context<SweepDormantEscrow>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let escrow = &ctx.accounts.escrow_account;
+        require!(ctx.accounts.config.dormancy_period > 0, BeamError::EscheatmentDisabled);
+        require!(escrow.is_dormant(), BeamError::EscrowNotDormant);
+        let sweepable_at = escrow
+            .dormant_since
+            .checked_add(ctx.accounts.config.escheat_notice_period)
+            .ok_or(BeamError::Overflow)?;
+        require!(now >= sweepable_at, BeamError::EscheatNoticeActive);
+
+        let amount = escrow.escrow_balance.min(ctx.accounts.escrow_token_account.amount);
+        require!(amount > 0, BeamError::InvalidAmount);
+
+        let seed_key = *escrow.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+        transfer_tokens(
+            ctx.accounts.token_program.to_account_info(),
+            ctx.accounts.escrow_token_account.to_account_info(),
+            ctx.accounts.custodial_token_account.to_account_info(),
+            ctx.accounts.escrow_account.to_account_info(),
+            ctx.accounts.mint.as_deref(),
+            amount,
+            signer,
+        )?;
+
+        let escrow = &mut ctx.accounts.escrow_account;
+        escrow.escrow_balance = escrow.escrow_balance.checked_sub(amount)
+            .ok_or(BeamError::Underflow)?;
+        escrow.release_newest_funding(amount);
+
+        emit!(DormantEscrowSwept {
+            owner: escrow.owner,
+            custodial_token_account: ctx.accounts.custodial_token_account.key(),
+            amount,
+            remaining_balance: escrow.escrow_balance,
+        });
+
+        Ok(())
+    }
+
+    /// Report conflicting bundle evidence to initiate a fraud dispute. `evidence`
+    /// must prove `reason` as it documents; reasons with nothing to prove on-chain
+    /// need the arbiter quorum signing as remaining accounts instead. The slash is
+    /// the config penalty for that reason.
+    pub fn report_fraudulent_bundle(
+        ctx: Context<ReportFraud>,
+        bundle_id: String,
+        conflicting_hash: [u8; 32],
+        reason: FraudReason,
+        evidence: FraudEvidence,
+    ) -> Result<()> {
+        require!(!bundle_id.is_empty() && bundle_id.len() <= 128, BeamError::InvalidBundleId);
+        require!(conflicting_hash != [0u8; 32], BeamError::InvalidBundleHash);
+
+        let capacity = RegistryCapacity::of(&ctx.accounts.nonce_registry.to_account_info());
+        let registry = &mut ctx.accounts.nonce_registry;
+        require_keys_eq!(registry.owner, ctx.accounts.payer.key(), BeamError::InvalidOwner);
+
+        let bundle_hash = ctx.accounts.config.bundle_hash_algo.hash(&bundle_id);
+        let fraud_bundle = *registry
+            .bundle_history
+            .iter()
+            .find(|record| record.bundle_hash == bundle_hash)
+            .ok_or(BeamError::BundleHistoryNotFound)?;
+        let order_ref = fraud_bundle.order_ref;
+        require!(bundle_hash != conflicting_hash, BeamError::FraudHashMatches);
+        let config = &ctx.accounts.config;
+        require!(
+            evidence.supports(
+                reason,
+                &fraud_bundle,
+                &bundle_id,
+                &registry.owner,
+                &conflicting_hash,
+                &config.fallback_verifier,
+            ),
+            BeamError::FraudEvidenceMismatch
+        );
+        if reason.needs_arbiters() {
+            require_arbiter_quorum(config, ctx.remaining_accounts)?;
+        }
+
+        let duplicate = registry
+            .fraud_records
+            .iter()
+            .any(|record| record.bundle_hash == bundle_hash && record.conflicting_hash == conflicting_hash);
+        require!(!duplicate, BeamError::FraudEvidenceExists);
+
+        let now = Clock::get()?.unix_timestamp;
+        if let Some(invoice) = ctx.accounts.invoice.as_mut() {
+            require!(
+                invoice.settled_bundles.contains(&bundle_hash),
+                BeamError::InvoiceBundleMismatch
+            );
+            invoice.open_disputes = invoice.open_disputes.saturating_add(1);
+            invoice.last_disputed_at = now;
+        }
+
+        if registry.fraud_records.len() >= capacity.fraud_records() {
+            registry.fraud_records.remove(0);
+        }
+
+        registry.fraud_records.push(crate::state::FraudRecord {
+            bundle_hash,
+            conflicting_hash,
+            reporter: ctx.accounts.reporter.key(),
+            reported_at: now,
+            reason,
+            ..Default::default()
+        });
+
+        emit!(FraudEvidenceSubmitted {
+            payer: registry.owner,
+            reporter: ctx.accounts.reporter.key(),
+            bundle_hash,
+            conflicting_hash,
+            reason,
+            reported_at: now,
+            order_ref,
+        });
+
+        // Phase 1.3: Apply stake slashing for fraud
+        let escrow = &mut ctx.accounts.escrow_account;
+
+        // Slash the configured multiple of the payment amount for this reason
+        let slash_amount = ctx.accounts.config.fraud_penalty(reason, fraud_bundle.amount)
+            .ok_or(BeamError::Overflow)?;
+
+        // Ensure sufficient balance to slash
+        require!(
+            escrow.escrow_balance >= slash_amount,
+            BeamError::InsufficientFundsForSlash
+        );
+
+        // Lock slashed funds (remove from escrow_balance, add to stake_locked)
+        escrow.escrow_balance = escrow.escrow_balance.checked_sub(slash_amount)
+            .ok_or(BeamError::Underflow)?;
+        escrow.stake_locked = escrow.stake_locked.checked_add(slash_amount)
+            .ok_or(BeamError::Overflow)?;
+
+        // Update fraud tracking
+        escrow.fraud_count = escrow.fraud_count.checked_add(1)
+            .ok_or(BeamError::Overflow)?;
+        let by_reason = &mut escrow.fraud_counts_by_reason[reason as usize];
+        *by_reason = by_reason.saturating_add(1);
+        escrow.last_fraud_timestamp = now;
+
+        // Permanently reduce reputation score
+        escrow.apply_reputation_delta(-FRAUD_REPUTATION_PENALTY);
+
+        // The linked identity carries the fraud to any escrow its owner opens later
+        if escrow.identity != Pubkey::default() {
+            let identity = ctx
+                .accounts
+                .identity_reputation
+                .as_mut()
+                .ok_or(BeamError::IdentityRequired)?;
+            require_keys_eq!(identity.identity, escrow.identity, BeamError::IdentityRequired);
+            identity.record_fraud();
+            emit!(IdentityFraudRecorded {
+                identity: identity.identity,
+                owner: escrow.owner,
+                reputation_score: identity.reputation_score,
+                fraud_count: identity.fraud_count,
+            });
+        }
+
+        emit!(FraudPenaltyApplied {
+            payer: escrow.owner,
+            slashed_amount: slash_amount,
+            new_reputation: escrow.reputation_score,
+            fraud_count: escrow.fraud_count,
+            reason,
+        });
+
+        Ok(())
+    }
+
+    /// Let the accused payer answer an open fraud report with a hash of their
+    /// off-chain evidence and a short statement. One rebuttal per record; it
+    /// doesn't undo the slash, but keeps the payer's side next to the report.
+    pub fn submit_counter_evidence(
+        ctx: Context<SubmitCounterEvidence>,
+        bundle_hash: [u8; 32],
+        conflicting_hash: [u8; 32],
+        evidence_hash: [u8; 32],
+        statement: [u8; COUNTER_STATEMENT_LEN],
+    ) -> Result<()> {
+        require!(
+            evidence_hash != [0u8; 32] && is_valid_label(&statement),
+            BeamError::InvalidCounterEvidence
+        );
+
+        let registry = &mut ctx.accounts.nonce_registry;
+        let record = registry
+            .fraud_records
+            .iter_mut()
+            .find(|record| record.bundle_hash == bundle_hash && record.conflicting_hash == conflicting_hash)
+            .ok_or(BeamError::FraudRecordNotFound)?;
+        require!(!record.is_countered(), BeamError::CounterEvidenceExists);
+
+        let now = Clock::get()?.unix_timestamp;
+        record.counter_evidence_hash = evidence_hash;
+        record.counter_statement = statement;
+        record.countered_at = now;
+
+        emit!(CounterEvidenceSubmitted {
+            payer: registry.owner,
+            bundle_hash,
+            conflicting_hash,
+            evidence_hash,
+            statement,
+            submitted_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Payer disputes one of their settled bundles, e.g. when the merchant never
+    /// delivered, within `chargeback_window` of its settlement. The bundle is looked
+    /// up in the registry's history; each bundle can be charged back once.
+    pub fn request_chargeback(
+        ctx: Context<RequestChargeback>,
+        bundle_hash: [u8; 32],
+        reason: ChargebackReason,
+    ) -> Result<()> {
+        let window = ctx.accounts.config.chargeback_window;
+        require!(window > 0, BeamError::ChargebacksDisabled);
+
+        let record = *ctx
+            .accounts
+            .nonce_registry
+            .bundle_history
+            .iter()
+            .find(|record| record.bundle_hash == bundle_hash)
+            .ok_or(BeamError::BundleHistoryNotFound)?;
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            now <= record.settled_at.saturating_add(window),
+            BeamError::ChargebackWindowClosed
+        );
+
+        let chargeback = &mut ctx.accounts.chargeback;
+        chargeback.payer = ctx.accounts.payer.key();
+        chargeback.merchant = record.merchant;
+        chargeback.bundle_hash = bundle_hash;
+        chargeback.amount = record.amount;
+        chargeback.settlement_index = record.settlement_index;
+        chargeback.settled_at = record.settled_at;
+        chargeback.reason = reason;
+        chargeback.status = ChargebackStatus::Requested;
+        chargeback.requested_at = now;
+        chargeback.bump = ctx.bumps.chargeback;
+        chargeback.respond_by = now.saturating_add(ctx.accounts.config.chargeback_response_window);
+
+        emit!(ChargebackRequested {
+            chargeback: chargeback.key(),
+            payer: chargeback.payer,
+            merchant: chargeback.merchant,
+            bundle_hash,
+            amount: chargeback.amount,
+            settlement_index: chargeback.settlement_index,
+            reason,
+            requested_at: now,
+            respond_by: chargeback.respond_by,
+        });
+
+        Ok(())
+    }
+
+    /// Merchant defends a chargeback before its `respond_by` with a hash of their
+    /// delivery proof and a reference to it, leaving the dispute for arbiters
+    pub fn respond_to_chargeback(
+        ctx: Context<RespondToChargeback>,
+        evidence_hash: [u8; 32],
+        uri: [u8; CHARGEBACK_URI_LEN],
+    ) -> Result<()> {
+        require!(
+            evidence_hash != [0u8; 32] && uri[0] != 0 && is_valid_label(&uri),
+            BeamError::InvalidChargebackEvidence
+        );
+
+        let chargeback = &mut ctx.accounts.chargeback;
+        require!(
+            chargeback.status == ChargebackStatus::Requested,
+            BeamError::ChargebackNotAwaitingResponse
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            chargeback.is_response_window_open(now),
+            BeamError::ChargebackResponseWindowClosed
+        );
+
+        chargeback.evidence_hash = evidence_hash;
+        chargeback.evidence_uri = uri;
+        chargeback.responded_at = now;
+        chargeback.status = ChargebackStatus::Responded;
+
+        emit!(ChargebackResponded {
+            chargeback: chargeback.key(),
+            payer: chargeback.payer,
+            merchant: chargeback.merchant,
+            evidence_hash,
+            uri,
+            responded_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Resolve a chargeback the merchant left unanswered past `respond_by` in the
+    /// payer's favor. Anyone may call it, so a crank can clear them.
+    pub fn resolve_unanswered_chargeback(ctx: Context<ResolveUnansweredChargeback>) -> Result<()> {
+        let chargeback = &mut ctx.accounts.chargeback;
+        require!(
+            chargeback.status == ChargebackStatus::Requested,
+            BeamError::ChargebackNotAwaitingResponse
+        );
+        let now = Clock::get()?.unix_timestamp;
+        require!(
+            !chargeback.is_response_window_open(now),
+            BeamError::ChargebackResponseWindowOpen
+        );
+
+        chargeback.status = ChargebackStatus::Upheld;
+        chargeback.resolved_at = now;
+
+        emit!(ChargebackResolved {
+            chargeback: chargeback.key(),
+            payer: chargeback.payer,
+            merchant: chargeback.merchant,
+            outcome: chargeback.status,
+            resolved_by: ctx.accounts.resolver.key(),
+            arbiters: Vec::new(),
+            resolved_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Arbiter quorum, signing in `remaining_accounts`, rules on a chargeback the
+    /// merchant answered. No funds move since settlements pay merchants directly;
+    /// a ruling for the merchant counts against the payer's `rejected_chargebacks`.
+    pub fn resolve_chargeback<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveChargeback<'info>>,
+        outcome: ChargebackOutcome,
+    ) -> Result<()> {
+        let arbiters = require_arbiter_quorum(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let chargeback = &mut ctx.accounts.chargeback;
+        require!(
+            chargeback.status == ChargebackStatus::Responded,
+            BeamError::ChargebackNotResponded
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+        chargeback.status = match outcome {
+            ChargebackOutcome::ForPayer => ChargebackStatus::Upheld,
+            ChargebackOutcome::ForMerchant => {
+                let escrow = &mut ctx.accounts.escrow_account;
+                escrow.rejected_chargebacks = escrow.rejected_chargebacks.saturating_add(1);
+                ChargebackStatus::Rejected
+            }
+        };
+        chargeback.resolved_at = now;
+
+        emit!(ChargebackResolved {
+            chargeback: chargeback.key(),
+            payer: chargeback.payer,
+            merchant: chargeback.merchant,
+            outcome: chargeback.status,
+            resolved_by: ctx.accounts.resolver.key(),
+            arbiters,
+            resolved_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Payer closes a resolved chargeback and reclaims its rent
+    pub fn close_chargeback(ctx: Context<CloseChargeback>) -> Result<()> {
+        require!(ctx.accounts.chargeback.is_resolved(), BeamError::ChargebackNotResolved);
+        Ok(())
+    }
+
+    /// Pay a held settlement out to the merchant once its hold period is over.
+    /// Anyone may call it, so a crank can release holds.
+    pub fn release_held_funds(ctx: Context<ReleaseHeldFunds>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let hold = &ctx.accounts.settlement_hold;
+        require!(hold.disputed_at == 0, BeamError::SettlementHoldDisputed);
+        require!(now >= hold.release_at, BeamError::SettlementHoldActive);
+
+        let amount = ctx.accounts.hold_vault.amount;
+        ctx.accounts.empty_hold_vault(ctx.accounts.merchant_token_account.to_account_info(), amount)?;
+
+        let hold = &ctx.accounts.settlement_hold;
+        emit!(HeldFundsReleased {
+            hold: hold.key(),
+            payer: hold.payer,
+            merchant: hold.merchant,
+            bundle_hash: hold.bundle_hash,
+            amount,
+            released_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Payer disputes a held settlement before its release. The funds stay in the
+    /// hold, which stops releasing, until the arbiters rule on it with
+    /// `resolve_held_settlement`.
+    pub fn dispute_held_settlement(ctx: Context<DisputeHeldSettlement>) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let hold = &mut ctx.accounts.settlement_hold;
+        require!(now < hold.release_at, BeamError::SettlementHoldReleasable);
+        require!(hold.disputed_at == 0, BeamError::SettlementHoldDisputed);
+        hold.disputed_at = now;
+
+        emit!(HeldSettlementDisputed {
+            hold: hold.key(),
+            payer: hold.payer,
+            merchant: hold.merchant,
+            bundle_hash: hold.bundle_hash,
+            amount: hold.amount,
+            disputed_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Arbiter quorum, signing as remaining accounts, rules on a disputed hold:
+    /// `ForPayer` refunds the held funds into the payer's escrow, `ForMerchant`
+    /// pays them out as a release would. Either way the hold closes.
+    pub fn resolve_held_settlement<'info>(
+        ctx: Context<'_, '_, '_, 'info, ResolveHeldSettlement<'info>>,
+        outcome: ChargebackOutcome,
+    ) -> Result<()> {
+        let arbiters = require_arbiter_quorum(&ctx.accounts.config, ctx.remaining_accounts)?;
+        let hold = &ctx.accounts.settlement_hold;
+        require!(hold.disputed_at != 0, BeamError::SettlementHoldNotDisputed);
+
+        let now = Clock::get()?.unix_timestamp;
+        let amount = ctx.accounts.hold_vault.amount;
+        match outcome {
+            ChargebackOutcome::ForPayer => {
+                let credited = match ctx.accounts.mint.as_deref() {
+                    Some(mint) => amount - transfer_fee(mint, amount)?,
+                    None => amount,
+                };
+                ctx.accounts
+                    .empty_hold_vault(ctx.accounts.escrow_token_account.to_account_info(), amount)?;
+
+                let escrow = &mut ctx.accounts.escrow_account;
+                escrow.escrow_balance = escrow.escrow_balance.checked_add(credited)
+                    .ok_or(BeamError::Overflow)?;
+
+                let hold = &ctx.accounts.settlement_hold;
+                emit!(HeldSettlementRefunded {
+                    hold: hold.key(),
+                    payer: hold.payer,
+                    merchant: hold.merchant,
+                    bundle_hash: hold.bundle_hash,
+                    amount: credited,
+                    new_balance: escrow.escrow_balance,
+                    disputed_at: hold.disputed_at,
+                });
+            }
+            ChargebackOutcome::ForMerchant => {
+                ctx.accounts
+                    .empty_hold_vault(ctx.accounts.merchant_token_account.to_account_info(), amount)?;
+
+                let hold = &ctx.accounts.settlement_hold;
+                emit!(HeldFundsReleased {
+                    hold: hold.key(),
+                    payer: hold.payer,
+                    merchant: hold.merchant,
+                    bundle_hash: hold.bundle_hash,
+                    amount,
+                    released_at: now,
+                });
+            }
+        }
+
+        emit!(HeldSettlementResolved {
+            hold: ctx.accounts.settlement_hold.key(),
+            outcome,
+            resolved_by: ctx.accounts.resolver.key(),
+            arbiters,
+            resolved_at: now,
+        });
+
+        Ok(())
+    }
+
+    /// Move the payer's fraud records reported before `older_than` into their
+    /// `FraudArchive`, freeing registry space. The payer or the config admin may
+    /// prune; records whose dispute is still open are kept in the registry.
+    pub fn prune_fraud_records(ctx: Context<PruneFraudRecords>, older_than: i64) -> Result<()> {
+        let now = Clock::get()?.unix_timestamp;
+        let registry = &mut ctx.accounts.nonce_registry;
+        let (pruned, still_open) = registry.take_closed_fraud_records(
+            older_than,
+            ctx.accounts.config.fraud_withdrawal_delay,
+            now,
+        );
+        require!(!pruned.is_empty(), BeamError::NoFraudRecordsToPrune);
+
+        let archive = &mut ctx.accounts.fraud_archive;
+        if archive.owner == Pubkey::default() {
+            archive.owner = registry.owner;
+            archive.bump = ctx.bumps.fraud_archive;
+        }
+        archive.archive(&pruned)?;
+
+        let authority = ctx.accounts.authority.key();
+        if authority != registry.owner {
+            record_admin_action(
+                &mut ctx.accounts.audit_log,
+                authority,
+                AdminAction::PruneFraudRecords,
+                &(registry.owner, older_than),
+            )?;
+        }
+
+        emit!(FraudRecordsPruned {
+            payer: registry.owner,
+            authority: ctx.accounts.authority.key(),
+            older_than,
+            pruned: pruned.len() as u16,
+            still_open: still_open as u16,
+            remaining: registry.fraud_records.len() as u16,
+            total_archived: archive.total_archived,
+        });
+
+        Ok(())
+    }
+
+    /// Start tracking reputation for a KYC identity. Only the config's identity
+    /// authority registers identities; the key is whatever it uses to name them.
+    pub fn register_identity(ctx: Context<RegisterIdentity>, identity: Pubkey) -> Result<()> {
+        let record = &mut ctx.accounts.identity_reputation;
+        record.identity = identity;
+        record.reputation_score = INITIAL_REPUTATION;
+        record.fraud_count = 0;
+        record.linked_escrows = 0;
+        record.banned = false;
+        record.bump = ctx.bumps.identity_reputation;
+
+        emit!(IdentityRegistered { identity });
+        Ok(())
+    }
+
+    /// Link an existing escrow to its owner's identity. The escrow's fraud history
+    /// is carried over, and its future fraud reports count against the identity.
+    pub fn link_identity(ctx: Context<LinkIdentity>) -> Result<()> {
+        let escrow = &mut ctx.accounts.escrow_account;
+        require_keys_eq!(escrow.identity, Pubkey::default(), BeamError::IdentityAlreadyLinked);
+        let identity = &mut ctx.accounts.identity_reputation;
+        identity.absorb_record(escrow.reputation_score, escrow.fraud_count);
+        identity.linked_escrows = identity.linked_escrows.saturating_add(1);
+        escrow.identity = identity.identity;
+
+        emit!(IdentityLinked {
+            identity: identity.identity,
+            owner: escrow.owner,
+            reputation_score: identity.reputation_score,
+            linked_escrows: identity.linked_escrows,
+        });
+        Ok(())
+    }
+
+    /// Ban or reinstate an identity. Banned identities can't open escrows; escrows
+    /// they already hold are unaffected.
+    pub fn set_identity_ban(ctx: Context<ManageIdentity>, banned: bool) -> Result<()> {
+        let identity = &mut ctx.accounts.identity_reputation;
+        identity.banned = banned;
+
+        emit!(IdentityBanUpdated {
+            identity: identity.identity,
+            banned,
+        });
+        Ok(())
+    }
+
+    /// Fail unless the identity may open another escrow, for clients to check
+    /// before onboarding a user
+    pub fn check_identity(ctx: Context<IdentityView>) -> Result<()> {
+        ctx.accounts
+            .identity_reputation
+            .check_eligible(ctx.accounts.config.min_identity_reputation)?;
+        Ok(())
+    }
+
+    /// Rotate escrow ownership to a new keypair controlled by the same user.
+    /// Both keys sign; balances, stats and history move to the new key's PDAs and the
+    /// old key is tombstoned so bundles it signed can still settle during the grace period.
+    /// The new registry keeps the old one's grown capacity; the old key pays its rent
+    /// and the old registry's rent goes back to whoever paid it. Devices enrolled under
+    /// the old key are retired first, as their registries stay seeded by it.
+    pub fn rotate_owner_key(ctx: Context<RotateOwnerKey>) -> Result<()> {
+        let old_owner = ctx.accounts.old_owner.key();
+        let new_owner = ctx.accounts.new_owner.key();
+        require_keys_neq!(old_owner, new_owner, BeamError::InvalidOwner);
+        require!(
+            !ctx.accounts.old_escrow.has_extra_backing_accounts(),
+            BeamError::BackingAccountsRemain
+        );
+        require!(
+            ctx.accounts.old_nonce_registry.device_count == 0,
+            BeamError::DevicesEnrolled
+        );
+
+        let now = Clock::get()?.unix_timestamp;
+
+        // Move everything held by the old vault, including locked stake, then close it
+        let bump = ctx.accounts.old_escrow.bump;
+        let seed_key = *ctx.accounts.old_escrow.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let vault_amount = ctx.accounts.old_escrow_token_account.amount;
+        if vault_amount > 0 {
+            let cpi_accounts = Transfer {
+                from: ctx.accounts.old_escrow_token_account.to_account_info(),
+                to: ctx.accounts.new_escrow_token_account.to_account_info(),
+                authority: ctx.accounts.old_escrow.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::transfer(cpi_ctx, vault_amount)?;
+        }
+
+        let cpi_accounts = CloseAccount {
+            account: ctx.accounts.old_escrow_token_account.to_account_info(),
+            destination: ctx.accounts.old_owner.to_account_info(),
+            authority: ctx.accounts.old_escrow.to_account_info(),
+        };
+        let cpi_program = ctx.accounts.token_program.to_account_info();
+        let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+        token::close_account(cpi_ctx)?;
+
+        // Copy the full escrow so stats and penalties follow the user to the new key
+        let mut escrow = (**ctx.accounts.old_escrow).clone();
+        escrow.record_owner_activity(now);
+        escrow.owner = new_owner;
+        escrow.pda_seed = new_owner;
+        escrow.escrow_token_account = ctx.accounts.new_escrow_token_account.key();
+        escrow.bump = ctx.bumps.new_escrow;
+        escrow.rent_payer = old_owner;
+        ctx.accounts.new_escrow.set_inner(escrow);
+
+        let mut registry = (**ctx.accounts.old_nonce_registry).clone();
+        registry.owner = new_owner;
+        registry.bump = ctx.bumps.new_nonce_registry;
+        registry.rent_payer = old_owner;
+        ctx.accounts.new_nonce_registry.set_inner(registry);
+
+        let grace_until = now
+            .checked_add(ROTATION_GRACE_PERIOD)
+            .ok_or(BeamError::Overflow)?;
+        let tombstone = &mut ctx.accounts.owner_tombstone;
+        tombstone.old_owner = old_owner;
+        tombstone.new_owner = new_owner;
+        tombstone.rotated_at = now;
+        tombstone.grace_until = grace_until;
+        tombstone.bump = ctx.bumps.owner_tombstone;
+
+        emit!(OwnerKeyRotated {
+            old_owner,
+            new_owner,
+            escrow_balance: ctx.accounts.new_escrow.escrow_balance,
+            stake_locked: ctx.accounts.new_escrow.stake_locked,
+            grace_until,
+        });
+
+        Ok(())
+    }
+
+    /// Owner redirects the escrow's rent refund once the account that paid the rent
+    /// no longer exists
+    pub fn set_escrow_rent_payer(ctx: Context<SetEscrowRentPayer>, rent_payer: Pubkey) -> Result<()> {
+        require!(
+            ctx.accounts.current_rent_payer.lamports() == 0,
+            BeamError::RentPayerStillExists
+        );
+        let escrow = &mut ctx.accounts.escrow_account;
+        let previous = escrow.rent_refund_recipient();
+        escrow.rent_payer = rent_payer;
+
+        emit!(RentPayerChanged {
+            account: escrow.key(),
+            previous,
+            rent_payer,
+        });
+
+        Ok(())
+    }
+
+    /// Merge up to `MAX_CONSOLIDATED_ESCROWS` escrows of the same user into the signer's.
+    /// Each source is passed in `remaining_accounts` as
+    /// `[escrow, vault, owner, nonce registry, escrow rent payer, registry rent payer]`
+    /// and its owner key must sign. A source whose registry still carries pending
+    /// liabilities or fraud reports inside the withdrawal delay can't be merged, since
+    /// bundles against it may still settle, nor one with devices still enrolled, whose
+    /// registries would be orphaned. Balances are swept, counters folded into
+    /// the destination, each source vault is closed to its owner and its escrow and
+    /// registry to their rent payers. A source that never created its registry passes
+    /// the empty PDA, and any account as its registry rent payer.
+    pub fn consolidate_escrows<'info>(
+        ctx: Context<'_, '_, 'info, 'info, ConsolidateEscrows<'info>>,
+    ) -> Result<()> {
+        let remaining = ctx.remaining_accounts;
+        require!(
+            !remaining.is_empty()
+                && remaining.len().is_multiple_of(6)
+                && remaining.len() / 6 <= MAX_CONSOLIDATED_ESCROWS,
+            BeamError::InvalidConsolidation
+        );
+
+        enter_processing(&mut ctx.accounts.escrow_account)?;
+        let now = Clock::get()?.unix_timestamp;
+        let lockup = ctx.accounts.config.funding_lockup_secs;
+        let dispute_delay = ctx.accounts.config.fraud_withdrawal_delay;
+        let destination_key = ctx.accounts.escrow_account.key();
+        let mint = ctx.accounts.escrow_token_account.mint;
+
+        // Validate every source before moving anything
+        let mut sources: Vec<ConsolidationSource> = Vec::with_capacity(remaining.len() / 6);
+        for chunk in remaining.chunks(6) {
+            let (escrow_info, vault_info, owner_info, registry_info) =
+                (&chunk[0], &chunk[1], &chunk[2], &chunk[3]);
+            let (rent_payer_info, registry_rent_payer_info) = (&chunk[4], &chunk[5]);
+            let escrow = Account::<OfflineEscrowAccount>::try_from(escrow_info)?;
+            // The destination among the sources shows up here still marked
+            require!(!escrow.is_processing(), BeamError::Reentrancy);
+            let expected = Pubkey::create_program_address(
+                &[b"escrow", escrow.seed_key().as_ref(), &[escrow.bump]],
+                &crate::ID,
+            )
+            .map_err(|_| BeamError::InvalidConsolidation)?;
+            require_keys_eq!(escrow_info.key(), expected, BeamError::InvalidConsolidation);
+            require_keys_neq!(escrow_info.key(), destination_key, BeamError::InvalidConsolidation);
+            require!(
+                sources.iter().all(|seen| seen.escrow.key() != expected),
+                BeamError::InvalidConsolidation
+            );
+            require!(
+                owner_info.is_signer && owner_info.key() == escrow.owner,
+                BeamError::Unauthorized
+            );
+            require!(escrow.stake_locked == 0, BeamError::StakeStillLocked);
+            require!(!escrow.has_extra_backing_accounts(), BeamError::BackingAccountsRemain);
+            require!(escrow.active_reservation(now) == 0, BeamError::FundsReserved);
+            require!(escrow.credit_debt == 0, BeamError::CreditDebtOutstanding);
+            require!(
+                escrow.beneficiary_claim_started_at == 0,
+                BeamError::BeneficiaryClaimPending
+            );
+
+            let vault = InterfaceAccount::<TokenAccount>::try_from(vault_info)?;
+            require!(
+                vault.owner == expected && vault.mint == mint,
+                BeamError::InvalidEscrowTokenAccount
+            );
+            require_keys_eq!(
+                rent_payer_info.key(),
+                escrow.rent_refund_recipient(),
+                BeamError::InvalidRentRecipient
+            );
+
+            let (registry_key, _) =
+                Pubkey::find_program_address(&[b"nonce", escrow.owner.as_ref()], &crate::ID);
+            require_keys_eq!(registry_info.key(), registry_key, BeamError::InvalidConsolidation);
+            let registry = if registry_info.data_is_empty() {
+                None
+            } else {
+                let registry = Account::<NonceRegistry>::try_from(registry_info)?;
+                require!(
+                    registry.pending_nonces.is_empty() && registry.pending_liabilities.is_empty(),
+                    BeamError::DeviceRegistryBusy
+                );
+                require!(
+                    !registry.has_open_disputes(dispute_delay, now),
+                    BeamError::RegistryDisputed
+                );
+                require!(registry.device_count == 0, BeamError::DevicesEnrolled);
+                require_keys_eq!(
+                    registry_rent_payer_info.key(),
+                    registry.rent_refund_recipient(),
+                    BeamError::InvalidRentRecipient
+                );
+                Some(registry)
+            };
+            sources.push(ConsolidationSource {
+                escrow,
+                vault,
+                owner: owner_info,
+                registry,
+                rent_payer: rent_payer_info,
+                registry_rent_payer: registry_rent_payer_info,
+            });
+        }
+
+        let mut moved: u64 = 0;
+        for ConsolidationSource {
+            escrow: source,
+            vault,
+            owner: owner_info,
+            registry,
+            rent_payer: rent_payer_info,
+            registry_rent_payer: registry_rent_payer_info,
+        } in sources
+        {
+            let seed_key = *source.seed_key();
+            let seeds = &[
+                b"escrow",
+                seed_key.as_ref(),
+                &[source.bump],
+            ];
+            let signer = &[&seeds[..]];
+
+            if vault.amount > 0 {
+                let cpi_accounts = Transfer {
+                    from: vault.to_account_info(),
+                    to: ctx.accounts.escrow_token_account.to_account_info(),
+                    authority: source.to_account_info(),
+                };
+                let cpi_program = ctx.accounts.token_program.to_account_info();
+                let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+                token::transfer(cpi_ctx, vault.amount)?;
+            }
+
+            let cpi_accounts = CloseAccount {
+                account: vault.to_account_info(),
+                destination: owner_info.clone(),
+                authority: source.to_account_info(),
+            };
+            let cpi_program = ctx.accounts.token_program.to_account_info();
+            let cpi_ctx = CpiContext::new_with_signer(cpi_program, cpi_accounts, signer);
+            token::close_account(cpi_ctx)?;
+
+            let destination = &mut ctx.accounts.escrow_account;
+            destination.escrow_balance = destination.escrow_balance.checked_add(source.escrow_balance)
+                .ok_or(BeamError::Overflow)?;
+            destination.total_spent = destination.total_spent.checked_add(source.total_spent)
+                .ok_or(BeamError::Overflow)?;
+            destination.fraud_count = destination.fraud_count.checked_add(source.fraud_count)
+                .ok_or(BeamError::Overflow)?;
+            destination.last_fraud_timestamp =
+                destination.last_fraud_timestamp.max(source.last_fraud_timestamp);
+            // The merged escrow keeps the worse of the two scores
+            let drop = source.reputation_score.saturating_sub(destination.reputation_score).min(0);
+            destination.apply_reputation_delta(drop);
+            // Deposits still inside their lockup stay locked after the move
+            for tranche in source.funding_tranches.iter() {
+                if tranche.amount > 0 && tranche.funded_at.saturating_add(lockup) > now {
+                    destination.record_funding(tranche.amount, tranche.funded_at, lockup);
+                }
+            }
+            moved = moved.checked_add(source.escrow_balance)
+                .ok_or(BeamError::Overflow)?;
+
+            if let Some(registry) = registry {
+                registry.close(registry_rent_payer_info.clone())?;
+            }
+            source.close(rent_payer_info.clone())?;
+        }
+
+        let destination = &mut ctx.accounts.escrow_account;
+        destination.record_owner_activity(now);
+        destination.set_processing(false);
+
+        emit!(EscrowsConsolidated {
+            owner: destination.owner,
+            source_count: (remaining.len() / 6) as u8,
+            amount_moved: moved,
+            new_balance: destination.escrow_balance,
+        });
+
+        Ok(())
+    }
+
+    /// Migrate old escrow account (107 bytes) to new format (127 bytes)
+    /// This is a one-time migration for accounts created before fraud fields were added
+    pub fn migrate_escrow(ctx: Context<MigrateEscrow>) -> Result<()> {
+        msg!("Migrating escrow account to new format with fraud fields");
+
+        let escrow_info = &ctx.accounts.escrow_account;
+        let owner = &ctx.accounts.owner;
+        let system_program = &ctx.accounts.system_program;
+
+        // The account is unchecked, so make sure it really is an escrow before
+        // resizing or rewriting a single byte of it
+        {
+            let data = escrow_info.try_borrow_data()?;
+            require!(
+                data.len() >= 8 && data[..8] == *OfflineEscrowAccount::DISCRIMINATOR,
+                ErrorCode::AccountDiscriminatorMismatch
+            );
+        }
+        require_keys_eq!(*escrow_info.owner, crate::ID, ErrorCode::AccountOwnedByWrongProgram);
+
+        // Manually reallocate the account
+        let current_size = escrow_info.data_len();
+        let new_size = 8 + std::mem::size_of::<OfflineEscrowAccount>();
+
+        msg!("Current size: {}, New size: {}", current_size, new_size);
+
+        if current_size < new_size {
+            // Reallocate to new size
+            escrow_info.resize(new_size)?;
+
+            // Transfer lamports for rent exemption difference
+            let rent = Rent::get()?;
+            let old_rent = rent.minimum_balance(current_size);
+            let new_rent = rent.minimum_balance(new_size);
+            let lamports_diff = new_rent.saturating_sub(old_rent);
+
+            if lamports_diff > 0 {
+                msg!("Transferring {} lamports for rent", lamports_diff);
+                anchor_lang::system_program::transfer(
+                    CpiContext::new(
+                        system_program.to_account_info(),
+                        anchor_lang::system_program::Transfer {
+                            from: owner.to_account_info(),
+                            to: escrow_info.to_account_info(),
+                        },
+                    ),
+                    lamports_diff,
+                )?;
+            }
+
+            // Zero out the new bytes (fraud fields at the end)
+            let mut data = escrow_info.try_borrow_mut_data()?;
+            let fraud_offset = current_size;
+            data[fraud_offset..new_size].fill(0);
+
+            msg!("✅ Account reallocated from {} to {} bytes", current_size, new_size);
+            msg!("✅ Fraud fields initialized to 0");
+        } else {
+            msg!("⚠️  Account already at correct size, no migration needed");
+        }
+
+        // Fold the old bool into `status`, then carry the old u16 score over into
+        // the signed one, once
+        let mut data = escrow_info.try_borrow_mut_data()?;
+        let mut escrow = OfflineEscrowAccount::try_deserialize(&mut &data[..])?;
+        if escrow.legacy_reputation_migrated {
+            escrow.set_reputation_migrated(true);
+            escrow.legacy_reputation_migrated = false;
+        }
+        if !escrow.is_reputation_migrated() {
+            escrow.apply_reputation_delta(i32::from(escrow.legacy_reputation_score));
+            escrow.set_reputation_migrated(true);
+            msg!("✅ Reputation migrated: {}", escrow.reputation_score);
+        }
+        // The context derived this PDA from the owner, so pin that as its seed key
+        if escrow.pda_seed == Pubkey::default() {
+            escrow.pda_seed = escrow.owner;
+        }
+        escrow.try_serialize(&mut &mut data[..])?;
+
+        Ok(())
+    }
+}
+
+/// Append an admin instruction to the audit chain and emit the entry's preimage.
+/// Every admin path records itself through here, right after its checks pass.
+fn record_admin_action<T: AnchorSerialize>(
+    audit_log: &mut Account<AdminAuditLog>,
+    admin: Pubkey,
+    action: AdminAction,
+    params: &T,
+) -> Result<()> {
+    let params_hash = hash::hash(&params.try_to_vec()?).to_bytes();
+    let timestamp = Clock::get()?.unix_timestamp;
+    let prev_hash = audit_log.append(action, &params_hash, timestamp);
+
+    emit!(AdminActionRecorded {
+        admin,
+        action,
+        prev_hash,
+        params_hash,
+        timestamp,
+        head: audit_log.head,
+        length: audit_log.length,
+    });
+    Ok(())
+}
+
+/// Configured arbiters signing among `accounts`, failing unless they meet the quorum
+fn require_arbiter_quorum(
+    config: &ProgramConfig,
+    accounts: &[AccountInfo],
+) -> std::result::Result<Vec<Pubkey>, BeamError> {
+    let signers: Vec<Pubkey> = accounts
+        .iter()
+        .filter(|account| account.is_signer)
+        .map(|account| account.key())
+        .collect();
+    let arbiters = config.signing_arbiters(&signers);
+    if config.arbiter_quorum == 0 || arbiters.len() < usize::from(config.arbiter_quorum) {
+        return Err(BeamError::ArbiterQuorumNotMet);
+    }
+    Ok(arbiters)
+}
+
+/// Funds can't leave an escrow until `delay` has passed since its latest
+/// fraud report; the unlock time is logged so the owner knows when to retry
+fn check_fraud_withdrawal_delay(
+    escrow: &OfflineEscrowAccount,
+    delay: i64,
+    now: i64,
+) -> Result<()> {
+    if let Some(unlock_at) = escrow.fraud_unlock_at(delay, now) {
+        msg!("Withdrawals unlock at {}", unlock_at);
+        return err!(BeamError::WithdrawalDelayedDueToFraud);
+    }
+    Ok(())
+}
+
+/// Bundle fields an attestation commits to
+struct AttestedBundle<'a> {
+    bundle_id: &'a str,
+    payer: &'a Pubkey,
+    merchant: &'a Pubkey,
+    amount: u64,
+    nonce: u64,
+    order_ref: &'a [u8; 16],
+    courier: Option<&'a CourierCommitment>,
+    device_id_hash: Option<&'a [u8; 32]>,
+    condition_id: Option<&'a [u8; 32]>,
+    /// Payer's registered key shares, when the payer's escrow is at hand
+    aggregate_key: Option<&'a AggregateKey>,
+}
+
+/// Validate one direction of a netted settlement bundle by bundle and book each
+/// at face value against the payer's escrow and history. Returns the gross
+/// amount and the protocol fees on it.
+#[allow(clippy::too_many_arguments)]
+fn record_netted_bundles(
+    escrow: &mut OfflineEscrowAccount,
+    registry: &mut NonceRegistry,
+    capacity: RegistryCapacity,
+    payee: &Pubkey,
+    payee_token_account: &Pubkey,
+    mint: &Pubkey,
+    bundles: Vec<BatchSettlementItem>,
+    config: &ProgramConfig,
+    heartbeat: Option<i64>,
+    now: i64,
+    slot: u64,
+) -> Result<(u64, u64)> {
+    let payer = escrow.owner;
+    let aggregate_key = escrow.aggregate_key;
+    require!(
+        !(config.is_zero_reputation_blocked() && escrow.reputation_score <= 0),
+        BeamError::ReputationExhausted
+    );
+
+    let mut gross: u64 = 0;
+    let mut fees: u64 = 0;
+    for bundle in bundles {
+        require!(
+            !bundle.bundle_id.is_empty() && bundle.bundle_id.len() <= 128,
+            BeamError::InvalidBundleId
+        );
+        require!(bundle.amount > 0, BeamError::InvalidAmount);
+        // Couriers are paid per bundle and don't net
+        require!(bundle.evidence.courier.is_none(), BeamError::CourierMismatch);
+        // Device bundles only settle against their device's registry
+        require!(
+            bundle.evidence.device_id_hash.is_none(),
+            BeamError::DeviceRegistryMismatch
+        );
+
+        let bundle_hash = config.bundle_hash_algo.hash(&bundle.bundle_id);
+        let order_ref = bundle.evidence.order_ref();
+        let attested = AttestedBundle {
+            bundle_id: &bundle.bundle_id,
+            payer: &payer,
+            merchant: payee,
+            amount: bundle.amount,
+            nonce: bundle.payer_nonce,
+            order_ref: &order_ref,
+            courier: None,
+            device_id_hash: None,
+            condition_id: bundle.evidence.condition_id.as_ref(),
+            aggregate_key: Some(&aggregate_key),
+        };
+        for (proof, role) in [
+            (bundle.evidence.payer_proof.as_ref(), AttestationRole::Payer),
+            (bundle.evidence.merchant_proof.as_ref(), AttestationRole::Merchant),
+        ] {
+            if let Some(proof) = proof {
+                check_proof(proof, role, &attested, config, heartbeat, now, slot)?;
+            }
+        }
+        check_condition(&bundle.evidence, config)?;
+
+        require!(
+            !registry.recent_bundle_hashes.contains(&bundle_hash),
+            BeamError::DuplicateBundle
+        );
+        require!(
+            registry.pending_nonces.contains(&bundle.payer_nonce)
+                || (bundle.payer_nonce > registry.last_nonce
+                    && bundle.payer_nonce > escrow.last_nonce),
+            BeamError::InvalidNonce
+        );
+        require!(escrow.escrow_balance >= bundle.amount, BeamError::InsufficientFunds);
+        require!(
+            escrow.settleable_balance(now, config.funding_lockup_secs) >= bundle.amount,
+            BeamError::FundsStillLocked
+        );
+        if config.rolling_cap > 0 {
+            let spent = escrow.rolling_spent(now, config.rolling_window_days);
+            require!(
+                spent.saturating_add(bundle.amount) <= config.rolling_cap,
+                BeamError::RollingLimitExceeded
+            );
+        }
+        if let Some(entry) = escrow.merchant_limit(payee) {
+            require!(
+                entry.settled.saturating_add(bundle.amount) <= entry.limit,
+                BeamError::MerchantLimitExceeded
+            );
+        }
+        escrow.check_plausible_amount(config, mint, bundle.amount)?;
+        let fee = config.settlement_fee(bundle.amount);
+        require!(fee == 0 || fee < bundle.amount, BeamError::FeeExceedsAmount);
+
+        let bundle_created_at = bundle.evidence.bundle_created_at();
+        escrow.record_settlement(config, payee, bundle.amount, bundle.payer_nonce, bundle_created_at, now)?;
+        reward_settlement(escrow, registry, config, payee, now);
+        registry.record_settlement(
+            BundleRecord {
+                bundle_hash,
+                merchant: *payee,
+                amount: bundle.amount,
+                settled_at: now,
+                nonce: bundle.payer_nonce,
+                order_ref,
+                bundle_created_at,
+                settlement_index: escrow.settlement_count,
+            },
+            capacity,
+        );
+
+        emit!(PaymentSettled {
+            payer,
+            merchant: *payee,
+            amount: bundle.amount,
+            nonce: bundle.payer_nonce,
+            bundle_id: bundle.bundle_id,
+            order_ref,
+            roundup: 0,
+            escrow_balance: escrow.escrow_balance,
+            total_spent: escrow.total_spent,
+            settlement_count: escrow.settlement_count,
+            merchant_token_account: *payee_token_account,
+            escrow_token_account: escrow.escrow_token_account,
+        });
+        emit!(BundleHistoryRecorded {
+            payer,
+            merchant: *payee,
+            bundle_hash,
+            amount: bundle.amount,
+            nonce: bundle.payer_nonce,
+            settled_at: now,
+            order_ref,
+            settlement_index: escrow.settlement_count,
+        });
+
+        gross = gross.checked_add(bundle.amount).ok_or(BeamError::Overflow)?;
+        fees = fees.checked_add(fee).ok_or(BeamError::Overflow)?;
+    }
+
+    Ok((gross, fees))
+}
+
+/// Link a new escrow to the identity its owner was verified as, refusing banned or
+/// low-reputation identities. Without an identity the escrow opens unlinked unless
+/// the config requires one.
+fn admit_identity(
+    config: &ProgramConfig,
+    identity: Option<&mut Account<IdentityReputation>>,
+    identity_authority: Option<&Signer>,
+    escrow: &mut OfflineEscrowAccount,
+) -> Result<()> {
+    let Some(identity) = identity else {
+        require!(!config.is_identity_required(), BeamError::IdentityRequired);
+        return Ok(());
+    };
+    require!(
+        identity_authority.is_some_and(|authority| authority.key() == config.identity_authority),
+        BeamError::Unauthorized
+    );
+    identity.check_eligible(config.min_identity_reputation)?;
+    identity.linked_escrows = identity.linked_escrows.saturating_add(1);
+    escrow.identity = identity.identity;
+
+    emit!(IdentityLinked {
+        identity: identity.identity,
+        owner: escrow.owner,
+        reputation_score: identity.reputation_score,
+        linked_escrows: identity.linked_escrows,
+    });
+    Ok(())
+}
+
+/// Reload `vault` and fail unless it moved by exactly `expected` from `before`.
+/// Catches rebasing mints, a substituted token program and our own split bugs.
+/// Move `amount` from `from` into the escrow's vault and book only what arrives
+/// there after any Token-2022 transfer fee, checking the vault in strict mode.
+/// Returns the amount credited to `escrow_balance`.
+#[allow(clippy::too_many_arguments)]
+fn deposit_to_escrow<'info>(
+    escrow: &mut OfflineEscrowAccount,
+    vault: &mut InterfaceAccount<'info, TokenAccount>,
+    from: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: AccountInfo<'info>,
+    config: &ProgramConfig,
+    amount: u64,
+    now: i64,
+) -> Result<u64> {
+    let vault_before = vault.amount;
+    let depositor = authority.key();
+    transfer_tokens(
+        token_program,
+        from,
+        vault.to_account_info(),
+        authority,
+        mint,
+        amount,
+        &[],
+    )?;
+    let fee = match mint {
+        Some(mint) => transfer_fee(mint, amount)?,
+        None => 0,
+    };
+    let credited = amount - fee;
+    if config.is_strict_vault_checks() {
+        check_vault_delta(vault, vault_before, i128::from(credited))?;
+    }
+
+    escrow.escrow_balance = escrow.escrow_balance.checked_add(credited)
+        .ok_or(BeamError::Overflow)?;
+    escrow.total_transfer_fees = escrow.total_transfer_fees.saturating_add(fee);
+    escrow.record_funding(credited, now, config.funding_lockup_secs);
+    if fee > 0 {
+        emit!(TransferFeeWithheld {
+            owner: escrow.owner,
+            counterparty: depositor,
+            gross: amount,
+            net: credited,
+            fee,
+        });
+    }
+    Ok(credited)
+}
+
+fn check_vault_delta(
+    vault: &mut InterfaceAccount<TokenAccount>,
+    before: u64,
+    expected: i128,
+) -> Result<()> {
+    vault.reload()?;
+    require!(
+        i128::from(vault.amount) - i128::from(before) == expected,
+        BeamError::UnexpectedVaultDelta
+    );
+    Ok(())
+}
+
+/// Verify one attestation proof against `bundle`, reporting fallback use
+fn check_proof(
+    proof: &AttestationProof,
+    role: AttestationRole,
+    bundle: &AttestedBundle,
+    config: &ProgramConfig,
+    heartbeat: Option<i64>,
+    now: i64,
+    slot: u64,
+) -> std::result::Result<(), BeamError> {
+    verify_proof(proof, role, bundle, config, heartbeat, now, slot)?;
+    if let Some(reason) = proof.fallback_reason {
+        emit!(FallbackAttestationUsed {
+            verifier: config.fallback_verifier,
+            payer: *bundle.payer,
+            merchant: *bundle.merchant,
+            amount: bundle.amount,
+            role,
+            reason,
+        });
+    }
+    Ok(())
+}
+
+/// Verifier key that signed the evidence: the payer proof's, else the merchant's.
+/// Aggregate proofs are signed by the payer's key shares rather than a verifier.
+fn attesting_verifier(evidence: &SettlementEvidence, config: &ProgramConfig) -> Option<Pubkey> {
+    [evidence.payer_proof.as_ref(), evidence.merchant_proof.as_ref()]
+        .into_iter()
+        .flatten()
+        .find(|proof| proof.aggregate_signatures.is_none())
+        .map(|proof| {
+            if proof.fallback_reason.is_some() {
+                config.fallback_verifier
+            } else {
+                VERIFIER_PUBKEY
+            }
+        })
+}
+
+/// A bundle committing to a condition settles only once the config's condition
+/// oracle has signed that the condition was met
+fn check_condition(
+    evidence: &SettlementEvidence,
+    config: &ProgramConfig,
+) -> std::result::Result<(), BeamError> {
+    let Some(condition_id) = evidence.condition_id.as_ref() else {
+        return Ok(());
+    };
+    let met = config.condition_oracle != Pubkey::default()
+        && evidence.condition_signature.is_some_and(|signature| {
+            signature_is_valid(&config.condition_oracle, &condition_message(condition_id), &signature)
+        });
+    if !met {
+        return Err(BeamError::ConditionNotMet);
+    }
+    Ok(())
+}
+
+/// Verify one attestation proof against `bundle`, including its deadline.
+/// Fallback-signed proofs additionally need a registered fallback key and an
+/// amount within the fallback cap; primary ones a fresh verifier `heartbeat`
+/// whenever the config asks for one. Aggregate proofs involve no verifier: they
+/// need the config to accept them and the payer's registered key shares.
+fn verify_proof(
+    proof: &AttestationProof,
+    role: AttestationRole,
+    bundle: &AttestedBundle,
+    config: &ProgramConfig,
+    heartbeat: Option<i64>,
+    now: i64,
+    slot: u64,
+) -> std::result::Result<(), BeamError> {
+    if !proof.is_well_formed() {
+        return Err(BeamError::MalformedAttestation);
+    }
+    // A voucher's window replaces both the attestation age and any deadline
+    if let Some(window) = proof.validity_window {
+        if config.max_voucher_validity == 0 {
+            return Err(BeamError::VoucherAttestationDisabled);
+        }
+        if !window.is_ordered()
+            || window.duration() > config.max_voucher_validity
+            || proof.deadline.is_some()
+            || proof.fallback_reason.is_some()
+        {
+            return Err(BeamError::InvalidValidityWindow);
+        }
+        if now < window.valid_from {
+            return Err(BeamError::VoucherNotYetValid);
+        }
+        if now > window.valid_until {
+            return Err(BeamError::VoucherExpired);
+        }
+    }
+    if proof.aggregate_signatures.is_some() {
+        if !config.is_aggregate_attestation_enabled() {
+            return Err(BeamError::AggregateAttestationDisabled);
+        }
+        if role != AttestationRole::Payer || proof.fallback_reason.is_some() {
+            return Err(BeamError::InvalidAttestation);
+        }
+        if !bundle.aggregate_key.is_some_and(AggregateKey::is_registered) {
+            return Err(BeamError::AggregateKeyNotRegistered);
+        }
+    } else if proof.fallback_reason.is_some() {
+        if config.fallback_verifier == Pubkey::default() {
+            return Err(BeamError::FallbackVerifierUnavailable);
+        }
+        if bundle.amount > config.fallback_cap {
+            return Err(BeamError::FallbackCapExceeded);
+        }
+    } else if config.max_heartbeat_age > 0 {
+        let fresh = matches!(heartbeat, Some(beat) if now - beat <= config.max_heartbeat_age);
+        if !fresh {
+            return Err(BeamError::VerifierHeartbeatStale);
+        }
+    }
+    if !verify_attestation(
+        proof,
+        role,
+        bundle.bundle_id,
+        bundle.payer,
+        bundle.merchant,
+        bundle.amount,
+        bundle.nonce,
+        bundle.order_ref,
+        bundle.courier,
+        bundle.device_id_hash,
+        bundle.condition_id,
+        &config.fallback_verifier,
+        bundle.aggregate_key,
+        now,
+    ) {
+        return Err(BeamError::InvalidAttestation);
+    }
+    if proof.deadline.is_some_and(|d| d.has_passed(now, slot)) {
+        return Err(BeamError::SettlementDeadlinePassed);
+    }
+    Ok(())
+}
+
+/// Drop stale liabilities, emitting `LiabilityCleared` for each
+/// Credit the config's settlement reputation reward for a payment to `merchant`,
+/// or nothing once the escrow has hit the per-merchant cap for the period. Runs
+/// before the settlement joins `registry`'s history.
+fn reward_settlement(
+    escrow: &mut OfflineEscrowAccount,
+    registry: &NonceRegistry,
+    config: &ProgramConfig,
+    merchant: &Pubkey,
+    now: i64,
+) {
+    if config.settlement_reputation_reward == 0 {
+        return;
+    }
+    let since = if config.reputation_reward_period > 0 {
+        now.saturating_sub(config.reputation_reward_period)
+    } else {
+        i64::MIN
+    };
+    let (prior_to_merchant, distinct_merchants) = registry.merchant_activity(merchant, since);
+    let reward = config.reputation_reward(prior_to_merchant);
+    let new_score = escrow.apply_reputation_delta(reward);
+    emit!(SettlementReputationRewarded {
+        owner: escrow.owner,
+        merchant: *merchant,
+        reward,
+        new_score,
+        prior_to_merchant: prior_to_merchant as u32,
+        distinct_merchants: distinct_merchants as u32,
+    });
+}
+
+/// Check a merchant pull of `amount` against the escrow limits a settlement of it
+/// would face: balance and lockup, the rolling and per-merchant caps, and the
+/// plausibility cap of the mint
+fn check_merchant_pull(
+    escrow: &OfflineEscrowAccount,
+    config: &ProgramConfig,
+    mint: &Pubkey,
+    merchant: &Pubkey,
+    amount: u64,
+    now: i64,
+) -> Result<()> {
+    require!(escrow.escrow_balance >= amount, BeamError::InsufficientFunds);
+    require!(
+        escrow.settleable_balance(now, config.funding_lockup_secs) >= amount,
+        BeamError::FundsStillLocked
+    );
+    if config.rolling_cap > 0 {
+        let spent = escrow.rolling_spent(now, config.rolling_window_days);
+        require!(
+            spent.saturating_add(amount) <= config.rolling_cap,
+            BeamError::RollingLimitExceeded
+        );
+    }
+    if let Some(entry) = escrow.merchant_limit(merchant) {
+        require!(
+            entry.settled.saturating_add(amount) <= entry.limit,
+            BeamError::MerchantLimitExceeded
+        );
+    }
+    escrow.check_plausible_amount(config, mint, amount)?;
+    Ok(())
+}
+
+/// Pay a checked merchant pull out of the vault, less the protocol fee for the
+/// treasury, and book it against the escrow. Returns the fee.
+#[allow(clippy::too_many_arguments)]
+fn pay_merchant_pull<'info>(
+    escrow: &mut Account<'info, OfflineEscrowAccount>,
+    escrow_token_account: &InterfaceAccount<'info, TokenAccount>,
+    merchant_token_account: &InterfaceAccount<'info, TokenAccount>,
+    treasury_token_account: Option<&InterfaceAccount<'info, TokenAccount>>,
+    config: &ProgramConfig,
+    token_program: &Interface<'info, TokenInterface>,
+    merchant: &Pubkey,
+    amount: u64,
+    now: i64,
+) -> Result<u64> {
+    let fee = config.settlement_fee(amount);
+    require!(fee == 0 || fee < amount, BeamError::FeeExceedsAmount);
+    let treasury = if fee > 0 {
+        let treasury = treasury_token_account.ok_or(BeamError::InvalidTreasuryAccount)?;
+        require!(
+            treasury.owner == config.treasury && treasury.mint == escrow_token_account.mint,
+            BeamError::InvalidTreasuryAccount
+        );
+        Some(treasury.to_account_info())
+    } else {
+        None
+    };
+
+    let seed_key = *escrow.seed_key();
+    let seeds = &[
+        b"escrow",
+        seed_key.as_ref(),
+        &[escrow.bump],
+    ];
+    let signer = &[&seeds[..]];
+    let mut payouts = vec![(merchant_token_account.to_account_info(), amount - fee)];
+    if let Some(treasury) = treasury {
+        payouts.push((treasury, fee));
+    }
+    for (to, value) in payouts {
+        let cpi_accounts = Transfer {
+            from: escrow_token_account.to_account_info(),
+            to,
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx = CpiContext::new_with_signer(token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, value)?;
+    }
+
+    escrow.consume_reservation(merchant, amount, now);
+    escrow.escrow_balance = escrow.escrow_balance.checked_sub(amount)
+        .ok_or(BeamError::Underflow)?;
+    escrow.total_spent = escrow.total_spent.checked_add(amount)
+        .ok_or(BeamError::Overflow)?;
+    escrow.record_rolling_spend(now, amount);
+    escrow.record_merchant_spend(merchant, amount);
+    Ok(fee)
+}
+
+fn prune_stale_liabilities(registry: &mut NonceRegistry, now: i64) {
+    let owner = registry.owner;
+    registry.pending_liabilities.retain(|liability| {
+        if !liability.is_stale(now) {
+            return true;
+        }
+        emit!(LiabilityCleared {
+            owner,
+            merchant: liability.merchant,
+            bundle_hash: liability.bundle_hash,
+            amount: liability.amount,
+            pruned: true,
+        });
+        false
+    });
+}
+
+/// A validated `consolidate_escrows` source, swept only once every source has passed
+struct ConsolidationSource<'a, 'info> {
+    escrow: Account<'info, OfflineEscrowAccount>,
+    vault: InterfaceAccount<'info, TokenAccount>,
+    owner: &'a AccountInfo<'info>,
+    /// `None` when the owner never opened a nonce registry
+    registry: Option<Account<'info, NonceRegistry>>,
+    rent_payer: &'a AccountInfo<'info>,
+    registry_rent_payer: &'a AccountInfo<'info>,
+}
+
+/// Mark `escrow` as mid-instruction and write the mark through to the account before
+/// any CPI, so an instruction re-entering on the same escrow fails with `Reentrancy`.
+/// The instruction clears the mark before returning; a failed one rolls it back
+/// with everything else.
+fn enter_processing(escrow: &mut Account<OfflineEscrowAccount>) -> Result<()> {
+    require!(!escrow.is_processing(), BeamError::Reentrancy);
+    escrow.set_processing(true);
+    escrow.exit(&crate::ID)
+}
+
+/// Last seed of the registry at `registry`, read before Anchor loads it. Empty while
+/// the account doesn't exist, as settlement only ever creates primary registries.
+fn registry_device_seed(registry: &AccountInfo) -> Vec<u8> {
+    if *registry.owner != crate::ID {
+        return Vec::new();
+    }
+    let Ok(data) = registry.try_borrow_data() else {
+        return Vec::new();
+    };
+    NonceRegistry::try_deserialize(&mut &data[..])
+        .map(|registry| registry.device_seed().to_vec())
+        .unwrap_or_default()
+}
+
+/// The token account recorded on the escrow at `escrow`, or `None` while it doesn't exist
+fn recorded_escrow_token_account(escrow: &AccountInfo) -> Option<Pubkey> {
+    if *escrow.owner != crate::ID {
+        return None;
+    }
+    let data = escrow.try_borrow_data().ok()?;
+    OfflineEscrowAccount::try_deserialize(&mut &data[..])
+        .ok()
+        .map(|escrow| escrow.escrow_token_account)
+}
+
+/// Registered liabilities for an owner's registry; zero if it was never created.
+/// Registries in an older layout must be migrated first.
+fn outstanding_liabilities(registry: &AccountInfo, now: i64) -> Result<u64> {
+    if *registry.owner != crate::ID {
+        return Ok(0);
+    }
+    let data = registry.try_borrow_data()?;
+    let registry = NonceRegistry::try_deserialize(&mut &data[..])?;
+    Ok(registry.liability_total(now))
+}
+
+/// `NonceRegistry::dispute_reserve` of an owner's registry; zero if it was never
+/// created. Registries in an older layout must be migrated first.
+fn dispute_reserve(registry: &AccountInfo, config: &ProgramConfig) -> Result<u64> {
+    if *registry.owner != crate::ID {
+        return Ok(0);
+    }
+    let data = registry.try_borrow_data()?;
+    let registry = NonceRegistry::try_deserialize(&mut &data[..])?;
+    Ok(registry.dispute_reserve(config))
+}
+
+/// Resize a program-owned account up to `new_size`, topping up rent from `payer`
+/// and zeroing the added bytes. Smaller or equal targets are a no-op.
+fn grow_account<'info>(
+    account: &AccountInfo<'info>,
+    payer: &Signer<'info>,
+    system_program: &Program<'info, System>,
+    new_size: usize,
+) -> Result<()> {
+    let current_size = account.data_len();
+    if current_size >= new_size {
+        return Ok(());
+    }
+
+    account.resize(new_size)?;
+
+    let rent = Rent::get()?;
+    let lamports_diff = rent
+        .minimum_balance(new_size)
+        .saturating_sub(account.lamports());
+    if lamports_diff > 0 {
+        anchor_lang::system_program::transfer(
+            CpiContext::new(
+                system_program.to_account_info(),
+                anchor_lang::system_program::Transfer {
+                    from: payer.to_account_info(),
+                    to: account.clone(),
+                },
+            ),
+            lamports_diff,
+        )?;
+    }
+
+    account.try_borrow_mut_data()?[current_size..new_size].fill(0);
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct InitializeConfig<'info> {
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + ProgramConfig::INIT_SPACE,
+        seeds = [b"config"],
+        bump
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + AdminAuditLog::INIT_SPACE,
+        seeds = [b"admin_audit"],
+        bump
+    )]
+    pub audit_log: Account<'info, AdminAuditLog>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(constraint = program.programdata_address()? == Some(program_data.key()) @ BeamError::Unauthorized)]
+    pub program: Program<'info, crate::program::Beam>,
+
+    #[account(constraint = program_data.upgrade_authority_address == Some(admin.key()) @ BeamError::Unauthorized)]
+    pub program_data: Account<'info, ProgramData>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct UpdateConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ BeamError::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut, seeds = [b"admin_audit"], bump = audit_log.bump)]
+    pub audit_log: Account<'info, AdminAuditLog>,
+}
+
+#[derive(Accounts)]
+pub struct AcceptAdmin<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        constraint = config.pending_admin == pending_admin.key() @ BeamError::NotPendingAdmin
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub pending_admin: Signer<'info>,
+
+    #[account(mut, seeds = [b"admin_audit"], bump = audit_log.bump)]
+    pub audit_log: Account<'info, AdminAuditLog>,
+}
+
+#[derive(Accounts)]
+pub struct AdjustReputation<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ BeamError::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(mut, seeds = [b"admin_audit"], bump = audit_log.bump)]
+    pub audit_log: Account<'info, AdminAuditLog>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAdminAuditLog<'info> {
+    #[account(seeds = [b"config"], bump = config.bump, has_one = admin @ BeamError::Unauthorized)]
+    pub config: Account<'info, ProgramConfig>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    #[account(
+        init,
+        payer = admin,
+        space = 8 + AdminAuditLog::INIT_SPACE,
+        seeds = [b"admin_audit"],
+        bump
+    )]
+    pub audit_log: Account<'info, AdminAuditLog>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateConfig<'info> {
+    /// CHECK: Admin and discriminator are validated manually before resizing
+    #[account(mut, seeds = [b"config"], bump)]
+    pub config: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub admin: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeEscrow<'info> {
+    /// Seeded by the owner alone, so each key holds at most one escrow and a per-owner
+    /// escrow count can't grow past it
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + OfflineEscrowAccount::INIT_SPACE,
+        seeds = [b"escrow", owner.key().as_ref()],
+        bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    /// CHECK: Authority stored in the escrow; must sign unless the config allows
+    /// custodial onboarding, checked in the handler
+    pub owner: UncheckedAccount<'info>,
+
+    /// Pays the escrow's rent and gets it back when the escrow closes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// CHECK: Agent who onboarded the owner; rewarded on the escrow's first settlements
+    pub referrer: Option<UncheckedAccount<'info>>,
+
+    /// Identity the owner was verified as; required while the config requires identities
+    #[account(mut, seeds = [b"identity", identity_reputation.identity.as_ref()], bump = identity_reputation.bump)]
+    pub identity_reputation: Option<Box<Account<'info, IdentityReputation>>>,
+
+    /// Required with `identity_reputation`: the KYC service vouching for the link
+    pub identity_authority: Option<Signer<'info>>,
+
+    /// Required when the mint carries a Token-2022 transfer fee; its decimals are
+    /// recorded whenever it is passed
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct OnboardEscrow<'info> {
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + OfflineEscrowAccount::INIT_SPACE,
+        seeds = [b"escrow", owner.key().as_ref()],
+        bump
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(
+        init,
+        payer = sponsor,
+        space = 8 + NonceRegistry::INIT_SPACE,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump
+    )]
+    pub nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    pub owner: Signer<'info>,
+
+    /// Pays the rent of both accounts and the initial funding
+    #[account(mut)]
+    pub sponsor: Signer<'info>,
+
+    #[account(mut)]
+    pub sponsor_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Identity the owner was verified as; required while the config requires identities
+    #[account(mut, seeds = [b"identity", identity_reputation.identity.as_ref()], bump = identity_reputation.bump)]
+    pub identity_reputation: Option<Box<Account<'info, IdentityReputation>>>,
+
+    /// Required with `identity_reputation`: the KYC service vouching for the link
+    pub identity_authority: Option<Signer<'info>>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeAndFund<'info> {
+    /// Created on first use; an existing escrow must belong to `owner`
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + OfflineEscrowAccount::INIT_SPACE,
+        seeds = [b"escrow", owner.key().as_ref()],
+        bump
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(
+        init_if_needed,
+        payer = owner,
+        space = 8 + NonceRegistry::INIT_SPACE,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump
+    )]
+    pub nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Identity the owner was verified as; read only when the escrow is created
+    #[account(mut, seeds = [b"identity", identity_reputation.identity.as_ref()], bump = identity_reputation.bump)]
+    pub identity_reputation: Option<Box<Account<'info, IdentityReputation>>>,
+
+    /// Required with `identity_reputation`: the KYC service vouching for the link
+    pub identity_authority: Option<Signer<'info>>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct FundEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Required while the escrow owes credit-line debt: the line it is owed to
+    #[account(address = escrow_account.credit_line @ BeamError::CreditLineMismatch)]
+    pub credit_line: Option<Box<Account<'info, CreditLine>>>,
+
+    /// Required with `credit_line`; its vault, which receives the repayment
+    #[account(mut)]
+    pub credit_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> FundEscrow<'info> {
+    /// Send as much of a deposit of `amount` as the escrow owes its credit line to the
+    /// line's vault. Returns the part repaid; zero when there is no debt.
+    fn repay_credit(&mut self, amount: u64) -> Result<u64> {
+        let debt = self.escrow_account.credit_debt;
+        if debt == 0 {
+            return Ok(0);
+        }
+        let (Some(line), Some(vault)) = (self.credit_line.as_ref(), self.credit_vault.as_ref())
+        else {
+            return err!(BeamError::CreditRepaymentRequired);
+        };
+        require_keys_eq!(vault.key(), line.vault, BeamError::CreditLineMismatch);
+
+        let repaid = amount.min(debt);
+        // The underwriter bears any Token-2022 fee on the repayment
+        transfer_tokens(
+            self.token_program.to_account_info(),
+            self.owner_token_account.to_account_info(),
+            vault.to_account_info(),
+            self.owner.to_account_info(),
+            self.mint.as_deref(),
+            repaid,
+            &[],
+        )?;
+
+        let escrow = &mut self.escrow_account;
+        escrow.credit_debt = debt - repaid;
+        escrow.record_owner_activity(Clock::get()?.unix_timestamp);
+        if escrow.credit_debt == 0 {
+            escrow.credit_line = Pubkey::default();
+        }
+
+        emit!(CreditRepaid {
+            payer: escrow.owner,
+            underwriter: line.underwriter,
+            amount: repaid,
+            debt: escrow.credit_debt,
+        });
+        Ok(repaid)
+    }
+}
+
+#[derive(Accounts)]
+pub struct SettleMultihop<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump = nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Intermediary that carried the payer's bundle and paid the merchant offline
+    #[account(
+        constraint = runner.key() != owner.key() && runner.key() != merchant.key() @ BeamError::InvalidMultihopRoute
+    )]
+    pub runner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"nonce", runner.key().as_ref()],
+        bump = runner_nonce_registry.bump,
+        constraint = runner_nonce_registry.owner == runner.key() @ BeamError::InvalidOwner
+    )]
+    pub runner_nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        constraint = runner_token_account.owner == runner.key() @ BeamError::InvalidMultihopRoute,
+        constraint = runner_token_account.mint == escrow_token_account.mint @ BeamError::InvalidMultihopRoute
+    )]
+    pub runner_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// CHECK: Merchant at the end of the route
+    #[account(constraint = merchant.key() != owner.key() @ BeamError::InvalidMultihopRoute)]
+    pub merchant: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = merchant_token_account.owner == merchant.key() @ BeamError::InvalidMultihopRoute,
+        constraint = merchant_token_account.mint == escrow_token_account.mint @ BeamError::InvalidMultihopRoute
+    )]
+    pub merchant_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Box<Account<'info, ProgramConfig>>,
+
+    #[account(mut)]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required while the config enforces verifier liveness
+    #[account(seeds = [b"verifier_heartbeat"], bump = verifier_heartbeat.bump)]
+    pub verifier_heartbeat: Option<Box<Account<'info, VerifierHeartbeat>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SettleMultihop<'info> {
+    fn transfer_from_escrow(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let seed_key = *self.escrow_account.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[self.escrow_account.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: self.escrow_token_account.to_account_info(),
+            to,
+            authority: self.escrow_account.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)
+    }
+}
+
+#[derive(Accounts)]
+pub struct SettleNetted<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_a.seed_key().as_ref()],
+        bump = escrow_a.bump,
+        constraint = escrow_a.owner == party_a.key() @ BeamError::InvalidOwner
+    )]
+    pub escrow_a: Box<Account<'info, OfflineEscrowAccount>>,
+
+    pub party_a: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"nonce", party_a.key().as_ref()],
+        bump = nonce_registry_a.bump,
+        constraint = nonce_registry_a.owner == party_a.key() @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry_a: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account_a.owner == escrow_a.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_a.is_backing_account(&escrow_token_account_a.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account_a: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_b.seed_key().as_ref()],
+        bump = escrow_b.bump,
+        constraint = escrow_b.owner == party_b.key() @ BeamError::InvalidOwner
+    )]
+    pub escrow_b: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(constraint = party_b.key() != party_a.key() @ BeamError::InvalidEscrowTransfer)]
+    pub party_b: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"nonce", party_b.key().as_ref()],
+        bump = nonce_registry_b.bump,
+        constraint = nonce_registry_b.owner == party_b.key() @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry_b: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account_b.owner == escrow_b.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_b.is_backing_account(&escrow_token_account_b.key()) @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_token_account_b.mint == escrow_token_account_a.mint @ BeamError::InvalidEscrowTransfer
+    )]
+    pub escrow_token_account_b: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Box<Account<'info, ProgramConfig>>,
+
+    #[account(mut)]
+    pub treasury_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required while the config enforces verifier liveness
+    #[account(seeds = [b"verifier_heartbeat"], bump = verifier_heartbeat.bump)]
+    pub verifier_heartbeat: Option<Box<Account<'info, VerifierHeartbeat>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> SettleNetted<'info> {
+    /// Sign a transfer out of party A's vault, or party B's when `from_a` is false
+    fn transfer(&self, from_a: bool, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let (escrow, vault) = if from_a {
+            (&self.escrow_a, &self.escrow_token_account_a)
+        } else {
+            (&self.escrow_b, &self.escrow_token_account_b)
+        };
+        let seed_key = *escrow.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[escrow.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        let cpi_accounts = Transfer {
+            from: vault.to_account_info(),
+            to,
+            authority: escrow.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, amount)
+    }
+}
+
+#[derive(Accounts)]
+pub struct SettlePayment<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    /// CHECK: Owner from escrow account
+    pub owner: UncheckedAccount<'info>,
+
+    /// CHECK: Payer who made offline payment; the owner, or a rotated-out key within its grace period
+    pub payer: Signer<'info>,
+
+    /// Required only when `payer` is a former owner key mapped to this escrow
+    #[account(
+        seeds = [b"tombstone", payer.key().as_ref()],
+        bump = owner_tombstone.bump
+    )]
+    pub owner_tombstone: Option<Account<'info, OwnerTombstone>>,
+
+    /// CHECK: Merchant receiving payment
+    pub merchant: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// The owner's primary registry or the registry of the device that signed the bundle.
+    /// A payer's first settlement creates their primary registry if they never did.
+    #[account(
+        init_if_needed,
+        payer = rent_payer,
+        space = 8 + NonceRegistry::INIT_SPACE,
+        seeds = [b"nonce", owner.key().as_ref(), registry_device_seed(nonce_registry).as_ref()],
+        bump,
+        constraint = nonce_registry.owner == owner.key()
+            || nonce_registry.owner == Pubkey::default() @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    /// Required when `nonce_registry` belongs to a device
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump = primary_nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub primary_nonce_registry: Option<Box<Account<'info, NonceRegistry>>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Invoice being paid, when the bundle settles one
+    #[account(
+        mut,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice.invoice_id.as_ref()],
+        bump = invoice.bump,
+        has_one = merchant @ BeamError::InvoiceMerchantMismatch
+    )]
+    pub invoice: Option<Account<'info, Invoice>>,
+
+    /// Merchant cashback program, when the merchant offers one
+    #[account(
+        mut,
+        seeds = [b"cashback", merchant.key().as_ref()],
+        bump = cashback_program.bump,
+        has_one = merchant @ BeamError::CashbackMismatch
+    )]
+    pub cashback_program: Option<Account<'info, CashbackProgram>>,
+
+    /// Required with `cashback_program`; must be its vault
+    #[account(mut)]
+    pub cashback_vault: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Owner token account to receive cashback; when omitted it is credited to the escrow
+    #[account(mut)]
+    pub cashback_destination: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Receives the protocol fee; required whenever the config fee is non-zero
+    #[account(mut)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Referrer's token account; the referral reward is skipped when omitted
+    #[account(mut)]
+    pub referrer_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Fee account of the verifier whose proof backs the bundle; the verifier's
+    /// cut stays with the treasury when omitted
+    #[account(
+        mut,
+        seeds = [b"verifier_fees", verifier_fee_account.verifier.as_ref()],
+        bump = verifier_fee_account.bump
+    )]
+    pub verifier_fee_account: Option<Box<Account<'info, VerifierFeeAccount>>>,
+
+    /// Required with `verifier_fee_account`; its fee vault
+    #[account(mut)]
+    pub verifier_fee_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Courier's token account; a committed courier fee stays in escrow when omitted
+    #[account(mut)]
+    pub courier_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// The escrow's round-up charity account; the round-up is skipped when omitted
+    #[account(mut)]
+    pub charity_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Guarantor's consent to cover this payer's shortfall
+    #[account(
+        mut,
+        seeds = [b"guarantee", guarantee.guarantor.as_ref(), owner.key().as_ref()],
+        bump = guarantee.bump
+    )]
+    pub guarantee: Option<Box<Account<'info, Guarantee>>>,
+
+    /// Required with `guarantee`; the escrow the shortfall is drawn from
+    #[account(
+        mut,
+        seeds = [b"escrow", guarantor_escrow.seed_key().as_ref()],
+        bump = guarantor_escrow.bump
+    )]
+    pub guarantor_escrow: Option<Box<Account<'info, OfflineEscrowAccount>>>,
+
+    /// Required with `guarantee`; must be the guarantor escrow's vault
+    #[account(mut)]
+    pub guarantor_token_account: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Underwriter's credit for this payer's shortfall when no guarantee covers it
+    #[account(
+        seeds = [b"credit_line", credit_line.underwriter.as_ref(), owner.key().as_ref()],
+        bump = credit_line.bump
+    )]
+    pub credit_line: Option<Box<Account<'info, CreditLine>>>,
+
+    /// Required with `credit_line`; its vault
+    #[account(mut)]
+    pub credit_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    /// Required while the config enforces verifier liveness
+    #[account(seeds = [b"verifier_heartbeat"], bump = verifier_heartbeat.bump)]
+    pub verifier_heartbeat: Option<Box<Account<'info, VerifierHeartbeat>>>,
+
+    /// Required when a program the owner delegated to settles by CPI
+    #[account(
+        mut,
+        seeds = [b"delegated_spender", owner.key().as_ref(), delegated_spender.program.as_ref()],
+        bump = delegated_spender.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub delegated_spender: Option<Box<Account<'info, DelegatedSpender>>>,
+
+    /// CHECK: Instructions sysvar, required with `delegated_spender`
+    #[account(address = instructions_sysvar::ID)]
+    pub instructions_sysvar: Option<UncheckedAccount<'info>>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    /// Pays rent when this settlement creates the payer's nonce registry
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    /// Required while the config holds settlements: created here to hold this
+    /// settlement's payout, keyed by the escrow and the settlement's index
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + SettlementHold::INIT_SPACE,
+        seeds = [
+            b"settlement_hold",
+            escrow_account.key().as_ref(),
+            &escrow_account.settlement_count.saturating_add(1).to_le_bytes()
+        ],
+        bump
+    )]
+    pub settlement_hold: Option<Box<Account<'info, SettlementHold>>>,
+
+    /// Required with `settlement_hold`: token account owned by it that takes the payout
+    #[account(mut)]
+    pub hold_vault: Option<Box<InterfaceAccount<'info, TokenAccount>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+impl<'info> SettlePayment<'info> {
+    /// `enter_processing` for the payer's escrow and, when given, the guarantor's
+    fn enter_processing(&mut self) -> Result<()> {
+        enter_processing(&mut self.escrow_account)?;
+        if let Some(guarantor_escrow) = self.guarantor_escrow.as_mut() {
+            enter_processing(guarantor_escrow)?;
+        }
+        Ok(())
+    }
+
+    fn exit_processing(&mut self) {
+        self.escrow_account.set_processing(false);
+        if let Some(guarantor_escrow) = self.guarantor_escrow.as_mut() {
+            guarantor_escrow.set_processing(false);
+        }
+    }
+
+    /// Fill in a registry `init_if_needed` just created. One that already existed
+    /// was checked against the owner by the account constraints.
+    fn bootstrap_nonce_registry(&mut self, bump: u8) {
+        let registry = &mut self.nonce_registry;
+        if registry.owner != Pubkey::default() {
+            return;
+        }
+        registry.owner = self.owner.key();
+        registry.last_nonce = 0;
+        registry.bump = bump;
+        registry.rent_payer = self.rent_payer.key();
+        emit!(NonceRegistryBootstrapped {
+            owner: registry.owner,
+            rent_payer: self.rent_payer.key(),
+        });
+    }
+
+    /// Run every settlement check without mutating state.
+    /// Returns the bundle hash on success so callers don't hash twice.
+    fn validate_settlement(
+        &self,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: &str,
+        evidence: &SettlementEvidence,
+        now: i64,
+        slot: u64,
+    ) -> std::result::Result<[u8; 32], BeamError> {
+        let bundle_hash = self
+            .run_settlement_checks(amount, payer_nonce, bundle_id, evidence, now, slot)
+            .map_err(|(_, err)| err)?;
+        self.validate_hold_accounts()?;
+        Ok(bundle_hash)
+    }
+
+    /// The checks behind `validate_settlement`, in order, short of the hold
+    /// accounts a dry run never creates. A failure names the check that raised
+    /// it, which is what `preflight_settlement` reports.
+    fn run_settlement_checks(
+        &self,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: &str,
+        evidence: &SettlementEvidence,
+        now: i64,
+        slot: u64,
+    ) -> std::result::Result<[u8; 32], (SettlementCheck, BeamError)> {
+        let failed = |check: SettlementCheck| move |err: BeamError| (check, err);
+
+        if bundle_id.is_empty() || bundle_id.len() > 128 {
+            return Err((SettlementCheck::BundleId, BeamError::InvalidBundleId));
+        }
+        self.authorize_payer(amount, now)
+            .map_err(failed(SettlementCheck::PayerAuthorization))?;
+        self.check_reputation()
+            .map_err(failed(SettlementCheck::Reputation))?;
+
+        let bundle_hash = self.config.bundle_hash_algo.hash(bundle_id);
+        // A bundle pre-authorized on-chain while both parties were online needs no attestation
+        let preauthorized = self
+            .escrow_account
+            .preauthorization(&bundle_hash, &self.merchant.key(), amount, payer_nonce)
+            .is_some();
+        if !preauthorized {
+            self.check_attestations(amount, payer_nonce, bundle_id, evidence, now, slot)
+                .map_err(failed(SettlementCheck::Attestation))?;
+        }
+        check_condition(evidence, &self.config).map_err(failed(SettlementCheck::Condition))?;
+
+        self.check_registry(evidence)
+            .map_err(failed(SettlementCheck::Registry))?;
+        self.check_duplicate(&bundle_hash)
+            .map_err(failed(SettlementCheck::Duplicate))?;
+        self.check_nonce(payer_nonce)
+            .map_err(failed(SettlementCheck::Nonce))?;
+        let debit = self
+            .check_funds(amount, evidence, now)
+            .map_err(failed(SettlementCheck::Balance))?;
+        self.check_spending_limits(amount, debit, now)
+            .map_err(failed(SettlementCheck::SpendingLimit))?;
+        self.escrow_account
+            .check_plausible_amount(&self.config, &self.escrow_token_account.mint, amount)
+            .map_err(failed(SettlementCheck::AmountPlausibility))?;
+        self.check_invoice(amount, now)
+            .map_err(failed(SettlementCheck::Invoice))?;
+        self.validate_cashback_accounts()
+            .and_then(|()| self.validate_fee_accounts(amount, evidence))
+            .map_err(failed(SettlementCheck::Accounts))?;
+
+        Ok(bundle_hash)
+    }
+
+    fn check_reputation(&self) -> std::result::Result<(), BeamError> {
+        if self.config.is_zero_reputation_blocked() && self.escrow_account.reputation_score <= 0 {
+            return Err(BeamError::ReputationExhausted);
+        }
+        Ok(())
+    }
+
+    /// Make attestation optional - validate only if provided
+    /// For online payments, attestation can be omitted (direct wallet signature verification)
+    /// For offline payments, client should provide hardware attestation
+    fn check_attestations(
+        &self,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: &str,
+        evidence: &SettlementEvidence,
+        now: i64,
+        slot: u64,
+    ) -> std::result::Result<(), BeamError> {
+        let payer_key = self.payer.key();
+        let merchant_key = self.merchant.key();
+        let order_ref = evidence.order_ref();
+        let bundle = AttestedBundle {
+            bundle_id,
+            payer: &payer_key,
+            merchant: &merchant_key,
+            amount,
+            nonce: payer_nonce,
+            order_ref: &order_ref,
+            courier: evidence.courier.as_ref(),
+            device_id_hash: evidence.device_id_hash.as_ref(),
+            condition_id: evidence.condition_id.as_ref(),
+            aggregate_key: Some(&self.escrow_account.aggregate_key),
+        };
+        let heartbeat = self.verifier_heartbeat.as_ref().map(|h| h.last_beat);
+        if let Some(payer_proof) = evidence.payer_proof.as_ref() {
+            check_proof(
+                payer_proof,
+                AttestationRole::Payer,
+                &bundle,
+                &self.config,
+                heartbeat,
+                now,
+                slot,
+            )?;
+        }
+        if let Some(merchant_proof) = evidence.merchant_proof.as_ref() {
+            check_proof(
+                merchant_proof,
+                AttestationRole::Merchant,
+                &bundle,
+                &self.config,
+                heartbeat,
+                now,
+                slot,
+            )?;
+        }
+        Ok(())
+    }
+
+    /// The registry must be the escrow owner's, and the one of the device that
+    /// signed the bundle
+    fn check_registry(&self, evidence: &SettlementEvidence) -> std::result::Result<(), BeamError> {
+        // Only a preflight sees a registry still to be bootstrapped, which
+        // settling would fill in for the owner first
+        let registry_owner = match self.nonce_registry.owner {
+            owner if owner == Pubkey::default() => self.owner.key(),
+            owner => owner,
+        };
+        if registry_owner != self.escrow_account.owner {
+            return Err(BeamError::InvalidOwner);
+        }
+        if evidence.device_id_hash.unwrap_or_default() != self.nonce_registry.device_id_hash {
+            return Err(BeamError::DeviceRegistryMismatch);
+        }
+        Ok(())
+    }
+
+    fn check_duplicate(&self, bundle_hash: &[u8; 32]) -> std::result::Result<(), BeamError> {
+        if self.nonce_registry.recent_bundle_hashes.contains(bundle_hash) {
+            return Err(BeamError::DuplicateBundle);
+        }
+        // Every device's settlements are remembered by the primary registry, which
+        // makes duplicate detection global across devices
+        if self.nonce_registry.is_device() {
+            let primary = self
+                .primary_nonce_registry
+                .as_ref()
+                .ok_or(BeamError::PrimaryRegistryRequired)?;
+            if primary.recent_bundle_hashes.contains(bundle_hash) {
+                return Err(BeamError::DuplicateBundle);
+            }
+        }
+        Ok(())
+    }
+
+    /// Verify nonce (prevent replay); reserved nonces may sit below the mark.
+    /// The escrow's mark only tracks the primary nonce space.
+    fn check_nonce(&self, payer_nonce: u64) -> std::result::Result<(), BeamError> {
+        let reserved = self.nonce_registry.pending_nonces.contains(&payer_nonce);
+        if !reserved
+            && (payer_nonce <= self.nonce_registry.last_nonce
+                || (!self.nonce_registry.is_device()
+                    && payer_nonce <= self.escrow_account.last_nonce))
+        {
+            return Err(BeamError::InvalidNonce);
+        }
+        Ok(())
+    }
+
+    /// A paid courier fee, and the protocol fee when the payer bears it, leave
+    /// the escrow alongside the payment. Returns that total debit once the
+    /// settleable balance, counting any guarantor cover, is known to cover it.
+    fn check_funds(
+        &self,
+        amount: u64,
+        evidence: &SettlementEvidence,
+        now: i64,
+    ) -> std::result::Result<u64, BeamError> {
+        let debit = self.settlement_debit(amount, evidence)?;
+        self.funding_split(debit, now)?;
+        Ok(debit)
+    }
+
+    fn check_spending_limits(
+        &self,
+        amount: u64,
+        debit: u64,
+        now: i64,
+    ) -> std::result::Result<(), BeamError> {
+        if self.config.rolling_cap > 0 {
+            let spent = self
+                .escrow_account
+                .rolling_spent(now, self.config.rolling_window_days);
+            if spent.saturating_add(debit) > self.config.rolling_cap {
+                return Err(BeamError::RollingLimitExceeded);
+            }
+        }
+
+        if let Some(entry) = self.escrow_account.merchant_limit(&self.merchant.key()) {
+            if entry.settled.saturating_add(amount) > entry.limit {
+                return Err(BeamError::MerchantLimitExceeded);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_invoice(&self, amount: u64, now: i64) -> std::result::Result<(), BeamError> {
+        if let Some(invoice) = self.invoice.as_ref() {
+            if invoice.is_expired(now) {
+                return Err(BeamError::InvoiceExpired);
+            }
+            invoice.check_payment(amount)?;
+        }
+        Ok(())
+    }
+
+    /// Fees go to a treasury-owned account in the escrow's mint; referral
+    /// rewards only to an account owned by the escrow's referrer, and verifier
+    /// fees only to the fee vault of the key that signed the evidence.
+    fn validate_fee_accounts(
+        &self,
+        amount: u64,
+        evidence: &SettlementEvidence,
+    ) -> std::result::Result<(), BeamError> {
+        let mint = self.escrow_token_account.mint;
+        let fee = self.config.settlement_fee(amount);
+        // A payment at or below the fee floor would leave the merchant nothing
+        if fee > 0 && fee >= amount && self.config.fee_payer == FeePayer::Merchant {
+            return Err(BeamError::FeeExceedsAmount);
+        }
+        if fee > 0 {
+            let treasury = self
+                .treasury_token_account
+                .as_ref()
+                .ok_or(BeamError::InvalidTreasuryAccount)?;
+            if treasury.owner != self.config.treasury || treasury.mint != mint {
+                return Err(BeamError::InvalidTreasuryAccount);
+            }
+        }
+        if let Some(referrer) = self.referrer_token_account.as_ref() {
+            if self.escrow_account.referrer == Pubkey::default()
+                || referrer.owner != self.escrow_account.referrer
+                || referrer.mint != mint
+            {
+                return Err(BeamError::InvalidReferrerAccount);
+            }
+        }
+        if let Some(account) = self.verifier_fee_account.as_ref() {
+            let vault = self
+                .verifier_fee_vault
+                .as_ref()
+                .ok_or(BeamError::InvalidVerifierFeeAccount)?;
+            if attesting_verifier(evidence, &self.config) != Some(account.verifier)
+                || vault.key() != account.fee_vault
+                || vault.mint != mint
+            {
+                return Err(BeamError::InvalidVerifierFeeAccount);
+            }
+        }
+        Ok(())
+    }
+
+    /// While settlements are held, the payout goes to a fresh hold's own vault in
+    /// the escrow's mint. Otherwise no hold may be created.
+    fn validate_hold_accounts(&self) -> std::result::Result<(), BeamError> {
+        if self.config.settlement_hold_period == 0 {
+            if self.settlement_hold.is_some() {
+                return Err(BeamError::InvalidSettlementHold);
+            }
+            return Ok(());
+        }
+        let (Some(hold), Some(vault)) = (self.settlement_hold.as_ref(), self.hold_vault.as_ref())
+        else {
+            return Err(BeamError::SettlementHoldRequired);
+        };
+        if vault.owner != hold.key() || vault.mint != self.escrow_token_account.mint {
+            return Err(BeamError::InvalidSettlementHold);
+        }
+        Ok(())
+    }
+
+    /// A cashback program must come with its own vault, and cashback may only be
+    /// paid in the escrow's mint to an account the escrow owner controls.
+    fn validate_cashback_accounts(&self) -> std::result::Result<(), BeamError> {
+        let Some(cashback) = self.cashback_program.as_ref() else {
+            return Ok(());
+        };
+        let vault = self
+            .cashback_vault
+            .as_ref()
+            .ok_or(BeamError::CashbackMismatch)?;
+        if vault.key() != cashback.vault || vault.mint != self.escrow_token_account.mint {
+            return Err(BeamError::CashbackMismatch);
+        }
+        if let Some(destination) = self.cashback_destination.as_ref() {
+            if destination.owner != self.escrow_account.owner
+                || destination.mint != self.escrow_token_account.mint
+            {
+                return Err(BeamError::CashbackMismatch);
+            }
+        }
+        Ok(())
+    }
+
+    /// Everything a settlement of `amount` takes from the escrow: the payment, the
+    /// courier fee and, when the payer bears it, the protocol fee
+    fn settlement_debit(
+        &self,
+        amount: u64,
+        evidence: &SettlementEvidence,
+    ) -> std::result::Result<u64, BeamError> {
+        amount
+            .checked_add(self.courier_fee(evidence)?)
+            .and_then(|debit| debit.checked_add(self.config.payer_settlement_fee(amount)))
+            .ok_or(BeamError::Overflow)
+    }
+
+    /// Courier fee this settlement pays out: the committed fee when the courier's
+    /// token account is supplied, zero when it isn't. Committed fees are bounded by
+    /// config whether or not they are paid.
+    fn courier_fee(&self, evidence: &SettlementEvidence) -> std::result::Result<u64, BeamError> {
+        let Some(commitment) = evidence.courier.as_ref() else {
+            return match self.courier_token_account {
+                Some(_) => Err(BeamError::CourierMismatch),
+                None => Ok(0),
+            };
+        };
+        if commitment.fee > self.config.max_courier_fee {
+            return Err(BeamError::CourierFeeTooHigh);
+        }
+        let Some(courier) = self.courier_token_account.as_ref() else {
+            return Ok(0);
+        };
+        if courier.owner != commitment.courier || courier.mint != self.escrow_token_account.mint {
+            return Err(BeamError::CourierMismatch);
+        }
+        Ok(commitment.fee)
+    }
+
+    /// Split `amount` into what the payer's settleable balance covers and the
+    /// shortfall a supplied guarantee or credit line must cover. Without either the
+    /// usual balance and lockup errors apply.
+    fn funding_split(&self, amount: u64, now: i64) -> std::result::Result<(u64, u64), BeamError> {
+        let lockup = self.config.funding_lockup_secs;
+        let escrow = &self.escrow_account;
+        let available = escrow.settleable_balance(now, lockup);
+        if available >= amount {
+            return Ok((amount, 0));
+        }
+
+        let (Some(guarantee), Some(guarantor)) =
+            (self.guarantee.as_ref(), self.guarantor_escrow.as_ref())
+        else {
+            if let Some(line) = self.credit_line.as_ref() {
+                let shortfall = amount - available;
+                self.check_credit(line, shortfall)?;
+                return Ok((available, shortfall));
+            }
+            return Err(if escrow.escrow_balance < amount {
+                BeamError::InsufficientFunds
+            } else {
+                BeamError::FundsStillLocked
+            });
+        };
+        let vault = self
+            .guarantor_token_account
+            .as_ref()
+            .ok_or(BeamError::GuaranteeMismatch)?;
+        if guarantee.guarantor != guarantor.owner
+            || !guarantor.is_backing_account(&vault.key())
+            || vault.mint != self.escrow_token_account.mint
+        {
+            return Err(BeamError::GuaranteeMismatch);
+        }
+
+        let shortfall = amount - available;
+        if guarantee.drawn.saturating_add(shortfall) > guarantee.max_exposure {
+            return Err(BeamError::GuaranteeExposureExceeded);
+        }
+        // The guarantor's own reservations and locked tranches stay out of reach
+        let guarantor_available = guarantor
+            .settleable_balance(now, lockup)
+            .min(guarantor.unreserved_balance(now));
+        if guarantor_available < shortfall {
+            return Err(BeamError::InsufficientFunds);
+        }
+        Ok((available, shortfall))
+    }
+
+    /// Move `shortfall` from the guarantor's escrow into the payer's so the
+    /// settlement can be paid out as usual. The guarantor bears any transfer fee,
+    /// so the whole shortfall arrives.
+    fn draw_guarantee(&mut self, amount: u64, shortfall: u64) -> Result<()> {
+        let fee = match self.mint.as_deref() {
+            Some(mint) => inverse_transfer_fee(mint, shortfall)?,
+            None => 0,
+        };
+        let gross = shortfall.checked_add(fee).ok_or(BeamError::Overflow)?;
+        let guarantor = self
+            .guarantor_escrow
+            .as_mut()
+            .ok_or(BeamError::GuaranteeMismatch)?;
+        let guarantor_key = guarantor.owner;
+        let seed_key = *guarantor.seed_key();
+        let seeds = &[b"escrow", seed_key.as_ref(), &[guarantor.bump]];
+        let signer = &[&seeds[..]];
+        let vault = self
+            .guarantor_token_account
+            .as_ref()
+            .ok_or(BeamError::GuaranteeMismatch)?;
+
+        transfer_tokens(
+            self.token_program.to_account_info(),
+            vault.to_account_info(),
+            self.escrow_token_account.to_account_info(),
+            guarantor.to_account_info(),
+            self.mint.as_deref(),
+            gross,
+            signer,
+        )?;
+
+        guarantor.escrow_balance = guarantor
+            .escrow_balance
+            .checked_sub(gross)
+            .ok_or(BeamError::InsufficientFunds)?;
+        guarantor.total_transfer_fees = guarantor.total_transfer_fees.saturating_add(fee);
+        self.escrow_account.escrow_balance = self
+            .escrow_account
+            .escrow_balance
+            .checked_add(shortfall)
+            .ok_or(BeamError::Overflow)?;
+        if fee > 0 {
+            emit!(TransferFeeWithheld {
+                owner: guarantor_key,
+                counterparty: self.escrow_account.owner,
+                gross,
+                net: shortfall,
+                fee,
+            });
+        }
+
+        let guarantee = self
+            .guarantee
+            .as_mut()
+            .ok_or(BeamError::GuaranteeMismatch)?;
+        guarantee.drawn = guarantee
+            .drawn
+            .checked_add(shortfall)
+            .ok_or(BeamError::Overflow)?;
+
+        emit!(GuaranteeDrawn {
+            payer: guarantee.payer,
+            guarantor: guarantor_key,
+            merchant: self.merchant.key(),
+            payer_amount: amount - shortfall,
+            guarantor_amount: shortfall,
+            drawn: guarantee.drawn,
+            max_exposure: guarantee.max_exposure,
+        });
+        Ok(())
+    }
+
+    /// Whether `line` may lend this payer `shortfall`: it must be open, its vault must
+    /// hold enough, the payer must be in good standing and any debt must already be
+    /// owed to this line, and the new debt plus fee must fit the limit.
+    fn check_credit(&self, line: &CreditLine, shortfall: u64) -> std::result::Result<(), BeamError> {
+        let escrow = &self.escrow_account;
+        let vault = self
+            .credit_vault
+            .as_ref()
+            .ok_or(BeamError::CreditLineMismatch)?;
+        if vault.key() != line.vault || vault.mint != self.escrow_token_account.mint {
+            return Err(BeamError::CreditLineMismatch);
+        }
+        if escrow.credit_debt > 0 && escrow.credit_line != self.credit_line_key() {
+            return Err(BeamError::CreditLineMismatch);
+        }
+        if line.frozen {
+            return Err(BeamError::CreditLineFrozen);
+        }
+        if escrow.fraud_count > 0 || escrow.reputation_score < line.min_reputation {
+            return Err(BeamError::CreditLineIneligible);
+        }
+        let fee = line.fee_on(shortfall).ok_or(BeamError::Overflow)?;
+        let debt = escrow
+            .credit_debt
+            .checked_add(shortfall)
+            .and_then(|debt| debt.checked_add(fee))
+            .ok_or(BeamError::Overflow)?;
+        if debt > line.limit {
+            return Err(BeamError::CreditLimitExceeded);
+        }
+        if vault.amount < shortfall {
+            return Err(BeamError::InsufficientFunds);
+        }
+        Ok(())
+    }
+
+    fn credit_line_key(&self) -> Pubkey {
+        self.credit_line.as_ref().map_or_else(Pubkey::default, |line| line.key())
+    }
+
+    /// Move `shortfall` from the credit vault into the payer's escrow so the
+    /// settlement can be paid out as usual, and book it plus the fee as debt
+    fn draw_credit(&mut self, amount: u64, shortfall: u64) -> Result<()> {
+        let line_key = self.credit_line_key();
+        let line = self
+            .credit_line
+            .as_ref()
+            .ok_or(BeamError::CreditLineMismatch)?;
+        let vault = self
+            .credit_vault
+            .as_ref()
+            .ok_or(BeamError::CreditLineMismatch)?;
+        let seeds = &[
+            b"credit_line",
+            line.underwriter.as_ref(),
+            line.payer.as_ref(),
+            &[line.bump],
+        ];
+        transfer_tokens(
+            self.token_program.to_account_info(),
+            vault.to_account_info(),
+            self.escrow_token_account.to_account_info(),
+            line.to_account_info(),
+            None,
+            shortfall,
+            &[&seeds[..]],
+        )?;
+
+        let fee = line.fee_on(shortfall).ok_or(BeamError::Overflow)?;
+        let escrow = &mut self.escrow_account;
+        escrow.escrow_balance = escrow
+            .escrow_balance
+            .checked_add(shortfall)
+            .ok_or(BeamError::Overflow)?;
+        escrow.credit_debt = escrow
+            .credit_debt
+            .checked_add(shortfall + fee)
+            .ok_or(BeamError::Overflow)?;
+        escrow.credit_line = line_key;
+
+        emit!(CreditDrawn {
+            payer: line.payer,
+            underwriter: line.underwriter,
+            merchant: self.merchant.key(),
+            payer_amount: amount - shortfall,
+            credit_amount: shortfall,
+            fee,
+            debt: escrow.credit_debt,
+        });
+        Ok(())
+    }
+
+    /// The payer must be the escrow owner, or a key rotated into this escrow
+    /// whose tombstone grace period has not yet elapsed.
+    fn authorize_payer(&self, amount: u64, now: i64) -> std::result::Result<(), BeamError> {
+        let payer_key = self.payer.key();
+        if payer_key == self.escrow_account.owner {
+            return Ok(());
+        }
+        if let Some(delegation) = self.delegated_spender.as_ref() {
+            return self.authorize_delegation(delegation, amount, now);
+        }
+
+        let tombstone = self
+            .owner_tombstone
+            .as_ref()
+            .ok_or(BeamError::InvalidOwner)?;
+        if tombstone.old_owner != payer_key || tombstone.new_owner != self.escrow_account.owner {
+            return Err(BeamError::InvalidOwner);
+        }
+        if now > tombstone.grace_until {
+            return Err(BeamError::RotationGraceExpired);
+        }
+        Ok(())
+    }
+
+    /// Accept a settlement made by CPI from the delegated program within its cap.
+    /// The program is identified by the top-level instruction of the transaction.
+    fn authorize_delegation(
+        &self,
+        delegation: &DelegatedSpender,
+        amount: u64,
+        now: i64,
+    ) -> std::result::Result<(), BeamError> {
+        let sysvar = self
+            .instructions_sysvar
+            .as_ref()
+            .ok_or(BeamError::DelegationNotInvoked)?;
+        if get_stack_height() <= TRANSACTION_LEVEL_STACK_HEIGHT {
+            return Err(BeamError::DelegationNotInvoked);
+        }
+        let invoking = instructions_sysvar::get_instruction_relative(0, &sysvar.to_account_info())
+            .map_err(|_| BeamError::DelegationNotInvoked)?;
+        if invoking.program_id != delegation.program {
+            return Err(BeamError::DelegationNotInvoked);
+        }
+        if delegation.spent(now).saturating_add(amount) > delegation.cap {
+            return Err(BeamError::DelegationCapExceeded);
+        }
+        Ok(())
+    }
+
+    /// Transfer a validated bundle to the merchant and record it in escrow and registry state.
+    /// With `defer_payout` the merchant transfer is left to the caller and its amount
+    /// returned; otherwise it happens here and zero is returned.
+    #[allow(clippy::too_many_arguments)]
+    fn apply_settlement(
+        &mut self,
+        amount: u64,
+        payer_nonce: u64,
+        bundle_id: String,
+        bundle_hash: [u8; 32],
+        evidence: &SettlementEvidence,
+        now: i64,
+        defer_payout: bool,
+    ) -> Result<u64> {
+        let merchant_key = self.merchant.key();
+        let owner_key = self.escrow_account.owner;
+        let order_ref = evidence.order_ref();
+        let courier_fee = self.courier_fee(evidence)?;
+        let debit = self.settlement_debit(amount, evidence)?;
+        // Proofs of pre-authorized bundles are never checked, so neither their
+        // timestamps nor their verifiers are trusted
+        let preauthorized = self
+            .escrow_account
+            .preauthorization(&bundle_hash, &merchant_key, amount, payer_nonce)
+            .is_some();
+
+        // Top the payer's escrow up from the guarantor or credit line before paying out
+        let (_, shortfall) = self.funding_split(debit, now)?;
+        if shortfall > 0 {
+            if self.guarantee.is_some() {
+                self.draw_guarantee(debit, shortfall)?;
+            } else {
+                self.draw_credit(debit, shortfall)?;
+            }
+        }
+
+        // Transfer from escrow to merchant, net of the protocol fee unless the
+        // payer bears it on top
+        let fee = self.config.settlement_fee(amount);
+        let surcharge = self.config.payer_settlement_fee(amount);
+        let payout = amount - (fee - surcharge);
+        let deferred = if defer_payout {
+            payout
+        } else if self.config.settlement_hold_period > 0 {
+            self.hold_payout(payout, bundle_hash, now)?;
+            0
+        } else {
+            self.pay_merchant(payout)?;
+            0
+        };
+        if fee > 0 {
+            let verifier = if preauthorized {
+                None
+            } else {
+                attesting_verifier(evidence, &self.config)
+            };
+            self.collect_fee(fee, verifier)?;
+        }
+
+        // Update escrow state
+        let escrow = &mut self.escrow_account;
+        // Device nonces live only in their own registry
+        let device = self.nonce_registry.is_device();
+        let escrow_nonce = if device { 0 } else { payer_nonce };
+        let bundle_created_at = if preauthorized { 0 } else { evidence.bundle_created_at() };
+        escrow.record_settlement(&self.config, &merchant_key, amount, escrow_nonce, bundle_created_at, now)?;
+        if surcharge > 0 {
+            escrow.record_fee_debit(surcharge, now)?;
+        }
+        if let Some(index) = escrow.preauthorization(&bundle_hash, &merchant_key, amount, payer_nonce) {
+            escrow.preauthorizations[index] = Preauthorization::default();
+            emit!(PreauthorizationConsumed {
+                owner: owner_key,
+                merchant: merchant_key,
+                bundle_hash,
+                amount,
+                nonce: payer_nonce,
+            });
+        }
+
+        if courier_fee > 0 {
+            self.pay_courier(courier_fee, bundle_hash, now)?;
+        }
+
+        reward_settlement(&mut self.escrow_account, &self.nonce_registry, &self.config, &merchant_key, now);
+
+        // Track recent bundle hashes and history for dispute resolution
+        let record = BundleRecord {
+            bundle_hash,
+            merchant: merchant_key,
+            amount,
+            settled_at: now,
+            nonce: payer_nonce,
+            order_ref,
+            bundle_created_at,
+            settlement_index: self.escrow_account.settlement_count,
+        };
+        let capacity = RegistryCapacity::of(&self.nonce_registry.to_account_info());
+        self.nonce_registry.record_settlement(record, capacity);
+        if let Some(primary) = self.primary_nonce_registry.as_mut().filter(|_| device) {
+            let capacity = RegistryCapacity::of(&primary.to_account_info());
+            primary.record_device_settlement(record, capacity);
+        }
+
+        // Settlements by the owner's own key never count against a delegation
+        if self.payer.key() != owner_key {
+            if let Some(delegation) = self.delegated_spender.as_mut() {
+                delegation.record_spend(amount, now);
+                emit!(DelegatedSpend {
+                    owner: owner_key,
+                    program: delegation.program,
+                    merchant: merchant_key,
+                    amount,
+                    spent_in_period: delegation.spent_in_period,
+                });
+            }
+        }
+
+        let roundup = self.apply_roundup(amount, now)?;
+
+        if let Some(invoice) = self.invoice.as_mut() {
+            invoice.record_payment(amount, bundle_hash)?;
+            emit!(InvoicePaymentApplied {
+                invoice: invoice.key(),
+                merchant: merchant_key,
+                amount,
+                amount_paid: invoice.amount_paid,
+                status: invoice.status,
+            });
+        }
+
+        emit!(PaymentSettled {
+            payer: owner_key,
+            merchant: merchant_key,
+            amount,
+            nonce: payer_nonce,
+            bundle_id,
+            order_ref,
+            roundup,
+            escrow_balance: self.escrow_account.escrow_balance,
+            total_spent: self.escrow_account.total_spent,
+            settlement_count: self.escrow_account.settlement_count,
+            merchant_token_account: self.merchant_token_account.key(),
+            escrow_token_account: self.escrow_token_account.key(),
+        });
+
+        emit!(BundleHistoryRecorded {
+            payer: owner_key,
+            merchant: merchant_key,
+            bundle_hash,
+            amount,
+            nonce: payer_nonce,
+            settled_at: now,
+            order_ref,
+            settlement_index: self.escrow_account.settlement_count,
+        });
+
+        self.apply_cashback(amount)?;
+        Ok(deferred)
+    }
+
+    /// Sign a transfer out of the escrow vault
+    fn transfer_from_escrow(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        let seed_key = *self.escrow_account.seed_key();
+        let seeds = &[
+            b"escrow",
+            seed_key.as_ref(),
+            &[self.escrow_account.bump],
+        ];
+        let signer = &[&seeds[..]];
+
+        transfer_tokens(
+            self.token_program.to_account_info(),
+            self.escrow_token_account.to_account_info(),
+            to,
+            self.escrow_account.to_account_info(),
+            self.mint.as_deref(),
+            amount,
+            signer,
+        )
+    }
+
+    /// Vault amount and booked balance before a settlement, taken only in strict mode
+    fn vault_snapshot(&self) -> Option<(u64, u64)> {
+        self.config.is_strict_vault_checks().then(|| {
+            (self.escrow_token_account.amount, self.escrow_account.escrow_balance)
+        })
+    }
+
+    /// Fail unless the vault moved exactly as the booked balance did since `snapshot`
+    fn check_vault_snapshot(&mut self, snapshot: Option<(u64, u64)>) -> Result<()> {
+        let Some((vault_before, balance_before)) = snapshot else {
+            return Ok(());
+        };
+        let expected =
+            i128::from(self.escrow_account.escrow_balance) - i128::from(balance_before);
+        check_vault_delta(&mut self.escrow_token_account, vault_before, expected)
+    }
+
+    /// Send a settlement payout to the merchant. With a Token-2022 transfer fee mint
+    /// the fee comes out of the payout, or on top of it from the escrow when the
+    /// config puts it on the payer.
+    fn pay_merchant(&mut self, payout: u64) -> Result<()> {
+        self.pay_out(self.merchant_token_account.to_account_info(), payout)?;
+        Ok(())
+    }
+
+    /// Pay `payout` into the settlement's hold instead of to the merchant
+    fn hold_payout(&mut self, payout: u64, bundle_hash: [u8; 32], now: i64) -> Result<()> {
+        let vault = self
+            .hold_vault
+            .as_ref()
+            .ok_or(BeamError::SettlementHoldRequired)?
+            .to_account_info();
+        let held = self.pay_out(vault.clone(), payout)?;
+
+        let escrow_key = self.escrow_account.key();
+        let settlement_index = self.escrow_account.settlement_count.saturating_add(1);
+        let (_, bump) = Pubkey::find_program_address(
+            &[b"settlement_hold", escrow_key.as_ref(), &settlement_index.to_le_bytes()],
+            &crate::ID,
+        );
+        let hold = self
+            .settlement_hold
+            .as_mut()
+            .ok_or(BeamError::SettlementHoldRequired)?;
+        hold.payer = self.escrow_account.owner;
+        hold.escrow = escrow_key;
+        hold.merchant = self.merchant.key();
+        hold.bundle_hash = bundle_hash;
+        hold.settlement_index = settlement_index;
+        hold.hold_vault = vault.key();
+        hold.amount = held;
+        hold.held_at = now;
+        hold.release_at = now.saturating_add(self.config.settlement_hold_period);
+        hold.rent_payer = self.rent_payer.key();
+        hold.bump = bump;
+        hold.disputed_at = 0;
+
+        emit!(SettlementHeld {
+            hold: hold.key(),
+            payer: hold.payer,
+            merchant: hold.merchant,
+            bundle_hash,
+            amount: held,
+            release_at: hold.release_at,
+        });
+        Ok(())
+    }
+
+    /// Transfer `payout` from the escrow to `to` on the merchant's behalf, applying
+    /// the config's transfer fee policy. Returns what reached `to`.
+    fn pay_out(&mut self, to: AccountInfo<'info>, payout: u64) -> Result<u64> {
+        let Some(mint) = self.mint.as_deref() else {
+            self.transfer_from_escrow(to, payout)?;
+            return Ok(payout);
+        };
+
+        let payer_absorbs = self.config.transfer_fee_payer == TransferFeePayer::Payer;
+        let (gross, fee) = if payer_absorbs {
+            let fee = inverse_transfer_fee(mint, payout)?;
+            (payout.checked_add(fee).ok_or(BeamError::Overflow)?, fee)
+        } else {
+            (payout, transfer_fee(mint, payout)?)
+        };
+        if payer_absorbs && fee > 0 {
+            let escrow = &mut self.escrow_account;
+            escrow.escrow_balance = escrow.escrow_balance.checked_sub(fee)
+                .ok_or(BeamError::InsufficientFunds)?;
+            escrow.total_transfer_fees = escrow.total_transfer_fees.saturating_add(fee);
+        }
+        self.transfer_from_escrow(to, gross)?;
+
+        if fee > 0 {
+            emit!(TransferFeeWithheld {
+                owner: self.escrow_account.owner,
+                counterparty: self.merchant.key(),
+                gross,
+                net: gross - fee,
+                fee,
+            });
+        }
+        Ok(gross - fee)
+    }
+
+    /// Donate the escrow's round-up on a settled `amount` and return it. A round-up
+    /// never fails the payment: it is skipped (and zero returned) when the charity
+    /// account is missing, the payer is on credit, or the settleable balance or
+    /// spending caps can't take it.
+    fn apply_roundup(&mut self, amount: u64, now: i64) -> Result<u64> {
+        let escrow = &self.escrow_account;
+        let config = escrow.roundup_config;
+        let roundup = config.roundup_for(amount);
+        let Some(charity) = self.charity_token_account.as_ref() else {
+            return Ok(0);
+        };
+        if roundup == 0 || charity.key() != config.charity_token_account || escrow.credit_debt > 0 {
+            return Ok(0);
+        }
+
+        let available = escrow
+            .settleable_balance(now, self.config.funding_lockup_secs)
+            .min(escrow.unreserved_balance(now));
+        if available < roundup {
+            return Ok(0);
+        }
+        if self.config.rolling_cap > 0
+            && escrow
+                .rolling_spent(now, self.config.rolling_window_days)
+                .saturating_add(roundup)
+                > self.config.rolling_cap
+        {
+            return Ok(0);
+        }
+        let delegated = self.payer.key() != escrow.owner;
+        if let Some(delegation) = self.delegated_spender.as_ref().filter(|_| delegated) {
+            if delegation.spent(now).saturating_add(roundup) > delegation.cap {
+                return Ok(0);
+            }
+        }
+
+        self.transfer_from_escrow(charity.to_account_info(), roundup)?;
+        let escrow = &mut self.escrow_account;
+        escrow.escrow_balance = escrow.escrow_balance.checked_sub(roundup)
+            .ok_or(BeamError::Underflow)?;
+        escrow.total_donated = escrow.total_donated.saturating_add(roundup);
+        escrow.record_rolling_spend(now, roundup);
+        if let Some(delegation) = self.delegated_spender.as_mut().filter(|_| delegated) {
+            delegation.record_spend(roundup, now);
+        }
+        Ok(roundup)
+    }
+
+    /// Pay the courier who delivered the bundle out of the escrow
+    fn pay_courier(&mut self, fee: u64, bundle_hash: [u8; 32], now: i64) -> Result<()> {
+        let courier = self
+            .courier_token_account
+            .as_ref()
+            .ok_or(BeamError::CourierMismatch)?;
+        let courier_key = courier.owner;
+        self.transfer_from_escrow(courier.to_account_info(), fee)?;
+        self.escrow_account.record_fee_debit(fee, now)?;
+
+        emit!(CourierPaid {
+            payer: self.escrow_account.owner,
+            merchant: self.merchant.key(),
+            courier: courier_key,
+            bundle_hash,
+            fee,
+        });
+        Ok(())
+    }
+
+    /// Route the protocol fee to the treasury, first carving out the referral
+    /// reward while the escrow is still within its rewarded settlements, then
+    /// the fee of the `verifier` whose proof backed the bundle.
+    fn collect_fee(&mut self, fee: u64, verifier: Option<Pubkey>) -> Result<()> {
+        let escrow = &self.escrow_account;
+        let rewarding = escrow.referrer != Pubkey::default()
+            && escrow.referral_rewards_paid < self.config.referral_reward_limit;
+        let referral_reward = match self.referrer_token_account.as_ref() {
+            Some(referrer) if rewarding => {
+                let reward = self.config.referral_reward.min(fee);
+                if reward > 0 {
+                    self.transfer_from_escrow(referrer.to_account_info(), reward)?;
+                }
+                reward
+            }
+            _ => 0,
+        };
+        if referral_reward > 0 {
+            self.escrow_account.referral_rewards_paid += 1;
+        }
+
+        let verifier_fee = match (self.verifier_fee_account.as_ref(), self.verifier_fee_vault.as_ref()) {
+            (Some(account), Some(vault)) if verifier == Some(account.verifier) => {
+                let verifier_fee = self.config.verifier_fee.min(fee - referral_reward);
+                if verifier_fee > 0 {
+                    self.transfer_from_escrow(vault.to_account_info(), verifier_fee)?;
+                }
+                verifier_fee
+            }
+            _ => 0,
+        };
+        if let Some(account) = self.verifier_fee_account.as_mut() {
+            account.earned = account.earned.checked_add(verifier_fee).ok_or(BeamError::Overflow)?;
+        }
+
+        let treasury = self
+            .treasury_token_account
+            .as_ref()
+            .ok_or(BeamError::InvalidTreasuryAccount)?;
+        let treasury_share = fee - referral_reward - verifier_fee;
+        if treasury_share > 0 {
+            self.transfer_from_escrow(treasury.to_account_info(), treasury_share)?;
+        }
+
+        emit!(SettlementFeeCollected {
+            payer: self.escrow_account.owner,
+            merchant: self.merchant.key(),
+            fee,
+            referrer: self.escrow_account.referrer,
+            referral_reward,
+            fee_payer: self.config.fee_payer,
+            verifier: verifier.unwrap_or_default(),
+            verifier_fee,
+        });
+
+        Ok(())
+    }
+
+    /// Pay the merchant's cashback on a settled `amount`. An underfunded vault
+    /// never fails the payment: cashback is skipped and `CashbackUnderfunded` emitted.
+    fn apply_cashback(&mut self, amount: u64) -> Result<()> {
+        let (Some(cashback), Some(vault)) =
+            (self.cashback_program.as_mut(), self.cashback_vault.as_mut())
+        else {
+            return Ok(());
+        };
+        let owed = cashback.cashback_for(amount);
+        if owed == 0 {
+            return Ok(());
+        }
+
+        let owner_key = self.escrow_account.owner;
+        if vault.amount < owed {
+            emit!(CashbackUnderfunded {
+                payer: owner_key,
+                merchant: cashback.merchant,
+                owed,
+                available: vault.amount,
+            });
+            return Ok(());
+        }
+
+        let to = match self.cashback_destination.as_ref() {
+            Some(destination) => destination.to_account_info(),
+            None => self.escrow_token_account.to_account_info(),
+        };
+        let merchant_key = cashback.merchant;
+        let seeds = &[b"cashback", merchant_key.as_ref(), &[cashback.bump]];
+        let signer = &[&seeds[..]];
+        let cpi_accounts = Transfer {
+            from: vault.to_account_info(),
+            to,
+            authority: cashback.to_account_info(),
+        };
+        let cpi_ctx =
+            CpiContext::new_with_signer(self.token_program.to_account_info(), cpi_accounts, signer);
+        token::transfer(cpi_ctx, owed)?;
+        // Batches pay cashback per item, so keep the cached vault balance current
+        vault.reload()?;
+
+        cashback.total_paid = cashback.total_paid.checked_add(owed)
+            .ok_or(BeamError::Overflow)?;
+        let credited_to_escrow = self.cashback_destination.is_none();
+        if credited_to_escrow {
+            let escrow = &mut self.escrow_account;
+            escrow.escrow_balance = escrow.escrow_balance.checked_add(owed)
+                .ok_or(BeamError::Overflow)?;
+        }
+
+        emit!(CashbackPaid {
+            payer: owner_key,
+            merchant: merchant_key,
+            amount: owed,
+            credited_to_escrow,
+        });
+
+        Ok(())
+    }
+}
+
+#[derive(Accounts)]
+#[instruction(invoice_id: [u8; 16])]
+pub struct CreateInvoice<'info> {
+    #[account(
+        init,
+        payer = rent_payer,
+        space = 8 + Invoice::INIT_SPACE,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice_id.as_ref()],
+        bump
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub merchant: Signer<'info>,
+
+    /// Pays the invoice's rent and gets it back when the invoice closes
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct CloseInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice.invoice_id.as_ref()],
+        bump = invoice.bump,
+        has_one = merchant @ BeamError::InvoiceMerchantMismatch,
+        close = rent_payer
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Receives the invoice's rent
+    #[account(mut, address = invoice.rent_refund_recipient() @ BeamError::InvalidRentRecipient)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct CloseExpiredInvoice<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice.invoice_id.as_ref()],
+        bump = invoice.bump,
+        has_one = merchant @ BeamError::InvoiceMerchantMismatch,
+        close = rent_payer
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    /// CHECK: Validated against the invoice
+    pub merchant: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the invoice's rent
+    #[account(mut, address = invoice.rent_refund_recipient() @ BeamError::InvalidRentRecipient)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    #[account(mut)]
+    pub keeper: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SetInvoiceRentPayer<'info> {
+    #[account(
+        mut,
+        seeds = [b"invoice", merchant.key().as_ref(), invoice.invoice_id.as_ref()],
+        bump = invoice.bump,
+        has_one = merchant @ BeamError::InvoiceMerchantMismatch
+    )]
+    pub invoice: Account<'info, Invoice>,
+
+    pub merchant: Signer<'info>,
+
+    /// CHECK: Must hold no lamports, i.e. no longer exist
+    #[account(address = invoice.rent_refund_recipient() @ BeamError::InvalidRentRecipient)]
+    pub current_rent_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CreateCashbackProgram<'info> {
+    #[account(
+        init,
+        payer = merchant,
+        space = 8 + CashbackProgram::INIT_SPACE,
+        seeds = [b"cashback", merchant.key().as_ref()],
+        bump
+    )]
+    pub cashback_program: Account<'info, CashbackProgram>,
+
+    #[account(
+        constraint = vault.owner == cashback_program.key() @ BeamError::CashbackMismatch
+    )]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub merchant: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetCashbackRate<'info> {
+    #[account(
+        mut,
+        seeds = [b"cashback", merchant.key().as_ref()],
+        bump = cashback_program.bump,
+        has_one = merchant @ BeamError::CashbackMismatch
+    )]
+    pub cashback_program: Account<'info, CashbackProgram>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FundCashback<'info> {
+    #[account(
+        seeds = [b"cashback", cashback_program.merchant.as_ref()],
+        bump = cashback_program.bump,
+        has_one = vault @ BeamError::CashbackMismatch
+    )]
+    pub cashback_program: Account<'info, CashbackProgram>,
+
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub funder: Signer<'info>,
+
+    #[account(mut)]
+    pub funder_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCashback<'info> {
+    #[account(
+        seeds = [b"cashback", merchant.key().as_ref()],
+        bump = cashback_program.bump,
+        has_one = merchant @ BeamError::CashbackMismatch,
+        has_one = vault @ BeamError::CashbackMismatch
+    )]
+    pub cashback_program: Account<'info, CashbackProgram>,
+
+    #[account(mut)]
+    pub vault: InterfaceAccount<'info, TokenAccount>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(mut)]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct InitializeNonceRegistry<'info> {
+    /// CHECK: Authority stored in the registry; must sign unless the config allows
+    /// custodial onboarding, checked in the handler
+    pub owner: UncheckedAccount<'info>,
+    #[account(mut)]
+    pub rent_payer: Signer<'info>,
+    #[account(
+        init,
+        payer = rent_payer,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump,
+        space = 8 + NonceRegistry::INIT_SPACE
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(device_id_hash: [u8; 32])]
+pub struct EnrollDevice<'info> {
+    /// Devices are enrolled under an existing primary registry, which counts them
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump = primary_nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub primary_nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(
+        init,
+        payer = owner,
+        seeds = [b"nonce", owner.key().as_ref(), device_id_hash.as_ref()],
+        bump,
+        space = 8 + NonceRegistry::INIT_SPACE
+    )]
+    pub device_nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct GrowRegistry<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref(), nonce_registry.device_seed()],
+        bump = nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    /// Pays rent for the added space
+    #[account(mut)]
+    pub owner: Signer<'info>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RetireDevice<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump = primary_nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub primary_nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [b"nonce", owner.key().as_ref(), device_nonce_registry.device_id_hash.as_ref()],
+        bump = device_nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner,
+        constraint = device_nonce_registry.is_device() @ BeamError::DeviceRegistryMismatch
+    )]
+    pub device_nonce_registry: Account<'info, NonceRegistry>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Receives the device registry's rent
+    #[account(
+        mut,
+        address = device_nonce_registry.rent_refund_recipient() @ BeamError::InvalidRentRecipient
+    )]
+    pub rent_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MergeRegistries<'info> {
+    #[account(
+        mut,
+        close = source_rent_payer,
+        seeds = [b"nonce", source_owner.key().as_ref(), source_registry.device_seed()],
+        bump = source_registry.bump,
+        constraint = source_registry.owner == source_owner.key() @ BeamError::InvalidOwner
+    )]
+    pub source_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        seeds = [
+            b"nonce",
+            destination_owner.key().as_ref(),
+            destination_registry.device_seed()
+        ],
+        bump = destination_registry.bump,
+        constraint = destination_registry.owner == destination_owner.key() @ BeamError::InvalidOwner
+    )]
+    pub destination_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub source_owner: Signer<'info>,
+
+    pub destination_owner: Signer<'info>,
+
+    /// CHECK: Receives the source registry's rent
+    #[account(
+        mut,
+        address = source_registry.rent_refund_recipient() @ BeamError::InvalidRentRecipient
+    )]
+    pub source_rent_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ManageNonceRegistry<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump = nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct CorrectNonceState<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", owner.key().as_ref()],
+        bump = nonce_registry.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PruneLiabilities<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", nonce_registry.owner.as_ref()],
+        bump = nonce_registry.bump
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateNonceRegistry<'info> {
+    /// CHECK: Owner and discriminator are validated manually before resizing
+    #[account(mut, seeds = [b"nonce", owner.key().as_ref()], bump)]
+    pub nonce_registry: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ExpireReservation<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+}
+
+#[derive(Accounts)]
+pub struct EscrowView<'info> {
+    #[account(
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+#[instruction(owner: Pubkey)]
+pub struct DeriveEscrowAddress<'info> {
+    /// Token program the escrow's token account belongs to
+    pub token_program: Interface<'info, TokenInterface>,
+
+    /// CHECK: `owner`'s escrow, read for its recorded token account when it exists
+    #[account(seeds = [b"escrow", owner.as_ref()], bump)]
+    pub escrow_account: Option<UncheckedAccount<'info>>,
+}
+
+#[derive(Accounts)]
+pub struct ConfigView<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct VerifyEvidence<'info> {
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Required while the config enforces verifier liveness
+    #[account(seeds = [b"verifier_heartbeat"], bump = verifier_heartbeat.bump)]
+    pub verifier_heartbeat: Option<Account<'info, VerifierHeartbeat>>,
+
+    /// Payer's escrow; needed to check aggregate proofs against its key shares
+    #[account(seeds = [b"escrow", escrow_account.seed_key().as_ref()], bump = escrow_account.bump)]
+    pub escrow_account: Option<Account<'info, OfflineEscrowAccount>>,
+}
+
+impl<'info> VerifyEvidence<'info> {
+    /// Check `proof` for `role` against `bundle` with settlement's `verify_proof`,
+    /// shared by `verify_evidence` and `verify_attestation_only`
+    fn dry_run(
+        &self,
+        proof: &AttestationProof,
+        role: AttestationRole,
+        bundle: &BundleFields,
+        clock: &Clock,
+    ) -> AttestationVerification {
+        let order_ref = bundle.order_ref.unwrap_or_default();
+        let attested = AttestedBundle {
+            bundle_id: &bundle.bundle_id,
+            payer: &bundle.payer,
+            merchant: &bundle.merchant,
+            amount: bundle.amount,
+            nonce: bundle.nonce,
+            order_ref: &order_ref,
+            courier: bundle.courier.as_ref(),
+            device_id_hash: bundle.device_id_hash.as_ref(),
+            condition_id: bundle.condition_id.as_ref(),
+            aggregate_key: self.escrow_account.as_ref().map(|escrow| &escrow.aggregate_key),
+        };
+        let config = &self.config;
+        let heartbeat = self.verifier_heartbeat.as_ref().map(|h| h.last_beat);
+        let error_code = match verify_proof(
+            proof,
+            role,
+            &attested,
+            config,
+            heartbeat,
+            clock.unix_timestamp,
+            clock.slot,
+        ) {
+            Ok(()) => 0,
+            Err(err) => u32::from(err),
+        };
+        let root = expected_root(
+            proof,
+            role,
+            attested.bundle_id,
+            attested.payer,
+            attested.merchant,
+            attested.amount,
+            attested.nonce,
+            attested.order_ref,
+            attested.courier,
+            attested.device_id_hash,
+            attested.condition_id,
+        );
+        let verifier = if proof.aggregate_signatures.is_some() {
+            Pubkey::default()
+        } else if proof.fallback_reason.is_some() {
+            config.fallback_verifier
+        } else {
+            VERIFIER_PUBKEY
+        };
+
+        AttestationVerification {
+            valid: error_code == 0,
+            error_code,
+            expected_root: root,
+            root_matches: proof.attestation_root == root,
+            verifier,
+        }
+    }
+}
+
+#[derive(Accounts)]
+pub struct InitializeVerifierHeartbeat<'info> {
+    #[account(
+        init,
+        payer = verifier,
+        space = 8 + VerifierHeartbeat::INIT_SPACE,
+        seeds = [b"verifier_heartbeat"],
+        bump
+    )]
+    pub verifier_heartbeat: Account<'info, VerifierHeartbeat>,
+
+    #[account(mut, address = VERIFIER_PUBKEY @ BeamError::Unauthorized)]
+    pub verifier: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(verifier: Pubkey)]
+pub struct SetFallbackVerifier<'info> {
+    #[account(
+        mut,
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ BeamError::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(mut, seeds = [b"admin_audit"], bump = audit_log.bump)]
+    pub audit_log: Account<'info, AdminAuditLog>,
+
+    /// Required while the config sets `min_verifier_bond`
+    #[account(seeds = [b"verifier_bond", verifier.as_ref()], bump = verifier_bond.bump)]
+    pub verifier_bond: Option<Account<'info, VerifierBond>>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterVerifierFeeAccount<'info> {
+    #[account(
+        init,
+        payer = verifier,
+        space = 8 + VerifierFeeAccount::INIT_SPACE,
+        seeds = [b"verifier_fees", verifier.key().as_ref()],
+        bump
+    )]
+    pub verifier_fee_account: Account<'info, VerifierFeeAccount>,
+
+    #[account(
+        constraint = fee_vault.owner == verifier_fee_account.key() @ BeamError::InvalidVerifierFeeAccount
+    )]
+    pub fee_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub verifier: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RegisterVerifierBond<'info> {
+    #[account(
+        init,
+        payer = verifier,
+        space = 8 + VerifierBond::INIT_SPACE,
+        seeds = [b"verifier_bond", verifier.key().as_ref()],
+        bump
+    )]
+    pub verifier_bond: Account<'info, VerifierBond>,
+
+    #[account(
+        mut,
+        constraint = bond_vault.owner == verifier_bond.key() @ BeamError::InvalidVerifierBond
+    )]
+    pub bond_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = verifier_token_account.mint == bond_vault.mint @ BeamError::InvalidVerifierBond
+    )]
+    pub verifier_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub verifier: Signer<'info>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == bond_vault.mint @ BeamError::InvalidVerifierBond)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct TopUpVerifierBond<'info> {
+    #[account(
+        mut,
+        seeds = [b"verifier_bond", verifier.key().as_ref()],
+        bump = verifier_bond.bump,
+        has_one = verifier @ BeamError::Unauthorized,
+        constraint = verifier_bond.vault == bond_vault.key() @ BeamError::InvalidVerifierBond
+    )]
+    pub verifier_bond: Account<'info, VerifierBond>,
+
+    #[account(mut)]
+    pub bond_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = verifier_token_account.mint == bond_vault.mint @ BeamError::InvalidVerifierBond
+    )]
+    pub verifier_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub verifier: Signer<'info>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == bond_vault.mint @ BeamError::InvalidVerifierBond)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DeregisterVerifierBond<'info> {
+    #[account(
+        mut,
+        seeds = [b"verifier_bond", verifier.key().as_ref()],
+        bump = verifier_bond.bump,
+        has_one = verifier @ BeamError::Unauthorized
+    )]
+    pub verifier_bond: Account<'info, VerifierBond>,
+
+    pub verifier: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawVerifierBond<'info> {
+    #[account(
+        mut,
+        close = verifier,
+        seeds = [b"verifier_bond", verifier.key().as_ref()],
+        bump = verifier_bond.bump,
+        has_one = verifier @ BeamError::Unauthorized,
+        constraint = verifier_bond.vault == bond_vault.key() @ BeamError::InvalidVerifierBond
+    )]
+    pub verifier_bond: Account<'info, VerifierBond>,
+
+    #[account(mut)]
+    pub bond_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = destination_token_account.mint == bond_vault.mint @ BeamError::InvalidVerifierBond
+    )]
+    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub verifier: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == bond_vault.mint @ BeamError::InvalidVerifierBond)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_id: String)]
+pub struct SlashVerifier<'info> {
+    #[account(
+        mut,
+        seeds = [b"verifier_bond", verifier_bond.verifier.as_ref()],
+        bump = verifier_bond.bump,
+        constraint = verifier_bond.vault == bond_vault.key() @ BeamError::InvalidVerifierBond
+    )]
+    pub verifier_bond: Box<Account<'info, VerifierBond>>,
+
+    #[account(mut)]
+    pub bond_vault: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    /// Registry of the payer whose double spend the verifier attested
+    #[account(
+        seeds = [b"nonce", nonce_registry.owner.as_ref()],
+        bump = nonce_registry.bump
+    )]
+    pub nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        init,
+        payer = reporter,
+        space = 8 + VerifierSlash::INIT_SPACE,
+        seeds = [
+            b"verifier_slash",
+            verifier_bond.verifier.as_ref(),
+            config.bundle_hash_algo.hash(&bundle_id).as_ref()
+        ],
+        bump
+    )]
+    pub verifier_slash: Account<'info, VerifierSlash>,
+
+    #[account(
+        mut,
+        constraint = treasury_token_account.owner == config.treasury
+            && treasury_token_account.mint == bond_vault.mint @ BeamError::InvalidTreasuryAccount
+    )]
+    pub treasury_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(mut)]
+    pub reporter: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Box<Account<'info, ProgramConfig>>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == bond_vault.mint @ BeamError::InvalidVerifierBond)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimVerifierFees<'info> {
+    #[account(
+        mut,
+        seeds = [b"verifier_fees", verifier.key().as_ref()],
+        bump = verifier_fee_account.bump,
+        has_one = verifier @ BeamError::Unauthorized,
+        has_one = fee_vault @ BeamError::InvalidVerifierFeeAccount
+    )]
+    pub verifier_fee_account: Account<'info, VerifierFeeAccount>,
+
+    #[account(mut)]
+    pub fee_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = destination_token_account.mint == fee_vault.mint @ BeamError::InvalidVerifierFeeAccount
+    )]
+    pub destination_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub verifier: Signer<'info>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == fee_vault.mint @ BeamError::InvalidVerifierFeeAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RecordHeartbeat<'info> {
+    #[account(
+        mut,
+        seeds = [b"verifier_heartbeat"],
+        bump = verifier_heartbeat.bump
+    )]
+    pub verifier_heartbeat: Account<'info, VerifierHeartbeat>,
+
+    #[account(address = VERIFIER_PUBKEY @ BeamError::Unauthorized)]
+    pub verifier: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ExportHistory<'info> {
+    #[account(
+        seeds = [b"nonce", nonce_registry.owner.as_ref()],
+        bump = nonce_registry.bump
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    #[account(mut)]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Read only for its pending liabilities; may not exist yet
+    #[account(seeds = [b"nonce", owner.key().as_ref()], bump)]
+    pub nonce_registry: UncheckedAccount<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct GrantAllowance<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Allowance::INIT_SPACE,
+        seeds = [b"allowance", owner.key().as_ref(), merchant.key().as_ref()],
+        bump
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Merchant allowed to draw
+    pub merchant: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowance", owner.key().as_ref(), allowance.merchant.as_ref()],
+        bump = allowance.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GrantDelegatedSpender<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + DelegatedSpender::INIT_SPACE,
+        seeds = [b"delegated_spender", owner.key().as_ref(), delegated_program.key().as_ref()],
+        bump
+    )]
+    pub delegated_spender: Account<'info, DelegatedSpender>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Program allowed to settle by CPI; only its key is stored
+    #[account(executable)]
+    pub delegated_program: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeDelegatedSpender<'info> {
+    #[account(
+        mut,
+        seeds = [b"delegated_spender", owner.key().as_ref(), delegated_spender.program.as_ref()],
+        bump = delegated_spender.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub delegated_spender: Account<'info, DelegatedSpender>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct GrantGuarantee<'info> {
+    #[account(
+        init,
+        payer = guarantor,
+        space = 8 + Guarantee::INIT_SPACE,
+        seeds = [b"guarantee", guarantor.key().as_ref(), payer.key().as_ref()],
+        bump
+    )]
+    pub guarantee: Account<'info, Guarantee>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", guarantor_escrow.seed_key().as_ref()],
+        bump = guarantor_escrow.bump,
+        constraint = guarantor_escrow.owner == guarantor.key() @ BeamError::InvalidOwner
+    )]
+    pub guarantor_escrow: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(mut)]
+    pub guarantor: Signer<'info>,
+
+    /// CHECK: Payer whose shortfalls the guarantor covers
+    #[account(constraint = payer.key() != guarantor.key() @ BeamError::GuaranteeMismatch)]
+    pub payer: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RevokeGuarantee<'info> {
+    #[account(
+        mut,
+        seeds = [b"guarantee", guarantor.key().as_ref(), guarantee.payer.as_ref()],
+        bump = guarantee.bump,
+        has_one = guarantor,
+        close = guarantor
+    )]
+    pub guarantee: Account<'info, Guarantee>,
+
+    #[account(mut)]
+    pub guarantor: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct OpenCreditLine<'info> {
+    #[account(
+        init,
+        payer = underwriter,
+        space = 8 + CreditLine::INIT_SPACE,
+        seeds = [b"credit_line", underwriter.key().as_ref(), payer.key().as_ref()],
+        bump
+    )]
+    pub credit_line: Account<'info, CreditLine>,
+
+    #[account(
+        constraint = credit_vault.owner == credit_line.key() @ BeamError::CreditLineMismatch
+    )]
+    pub credit_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub underwriter: Signer<'info>,
+
+    /// CHECK: Payer whose shortfalls the line covers
+    #[account(constraint = payer.key() != underwriter.key() @ BeamError::CreditLineMismatch)]
+    pub payer: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct ManageCreditLine<'info> {
+    #[account(
+        mut,
+        seeds = [b"credit_line", underwriter.key().as_ref(), credit_line.payer.as_ref()],
+        bump = credit_line.bump,
+        has_one = underwriter
+    )]
+    pub credit_line: Account<'info, CreditLine>,
+
+    pub underwriter: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct WithdrawCreditVault<'info> {
+    #[account(
+        seeds = [b"credit_line", underwriter.key().as_ref(), credit_line.payer.as_ref()],
+        bump = credit_line.bump,
+        has_one = underwriter
+    )]
+    pub credit_line: Account<'info, CreditLine>,
+
+    #[account(mut, address = credit_line.vault @ BeamError::CreditLineMismatch)]
+    pub credit_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub underwriter_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub underwriter: Signer<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct DrawAllowance<'info> {
+    #[account(
+        mut,
+        seeds = [b"allowance", owner.key().as_ref(), merchant.key().as_ref()],
+        bump = allowance.bump,
+        has_one = owner,
+        has_one = merchant
+    )]
+    pub allowance: Account<'info, Allowance>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    /// CHECK: Escrow owner who granted the allowance
+    pub owner: UncheckedAccount<'info>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives the protocol fee; required whenever the config fee is non-zero
+    #[account(mut)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct GrantPermit<'info> {
+    #[account(
+        init,
+        payer = owner,
+        space = 8 + Permit::INIT_SPACE,
+        seeds = [b"permit", owner.key().as_ref(), merchant.key().as_ref()],
+        bump
+    )]
+    pub permit: Account<'info, Permit>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    /// CHECK: Merchant allowed to pull
+    pub merchant: UncheckedAccount<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct PermitOwnerAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"permit", owner.key().as_ref(), permit.merchant.as_ref()],
+        bump = permit.bump,
+        has_one = owner
+    )]
+    pub permit: Account<'info, Permit>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ClosePermit<'info> {
+    #[account(
+        mut,
+        seeds = [b"permit", owner.key().as_ref(), permit.merchant.as_ref()],
+        bump = permit.bump,
+        has_one = owner,
+        close = owner
+    )]
+    pub permit: Account<'info, Permit>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PermitsView<'info> {
+    /// CHECK: Payer whose permits are listed
+    pub owner: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct PullWithPermit<'info> {
+    #[account(
+        mut,
+        seeds = [b"permit", owner.key().as_ref(), merchant.key().as_ref()],
+        bump = permit.bump,
+        has_one = owner,
+        has_one = merchant
+    )]
+    pub permit: Account<'info, Permit>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    /// CHECK: Escrow owner who granted the permit
+    pub owner: UncheckedAccount<'info>,
+
+    pub merchant: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(mut)]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Receives the protocol fee; required whenever the config fee is non-zero
+    #[account(mut)]
+    pub treasury_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ConsolidateEscrows<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct RecordMintDecimals<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = escrow_token_account @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(address = escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: InterfaceAccount<'info, Mint>,
+}
+
+#[derive(Accounts)]
+pub struct OwnerEscrowAction<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct EmergencyUnlockStake<'info> {
+    #[account(
+        seeds = [b"config"],
+        bump = config.bump,
+        has_one = admin @ BeamError::Unauthorized
+    )]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub admin: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = owner_token_account.owner == escrow_account.owner @ BeamError::InvalidOwner,
+        constraint = owner_token_account.mint == escrow_token_account.mint @ BeamError::InvalidOwner
+    )]
+    pub owner_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+
+    #[account(mut, seeds = [b"admin_audit"], bump = audit_log.bump)]
+    pub audit_log: Account<'info, AdminAuditLog>,
+}
+
+#[derive(Accounts)]
+pub struct SetRoundupConfig<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner,
+        has_one = escrow_token_account @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub owner: Signer<'info>,
+
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// Required when enabling; donations are paid in the vault's mint
+    #[account(
+        constraint = charity_token_account.mint == escrow_token_account.mint @ BeamError::InvalidRoundupConfig
+    )]
+    pub charity_token_account: Option<InterfaceAccount<'info, TokenAccount>>,
+}
+
+#[derive(Accounts)]
+pub struct AddBackingTokenAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner,
+        has_one = escrow_token_account @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub owner: Signer<'info>,
+
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    // Only the escrow may move or close it
+    #[account(
+        constraint = backing_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = backing_token_account.mint == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount,
+        constraint = backing_token_account.delegate.is_none() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = backing_token_account.close_authority.is_none() @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub backing_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct RemoveBackingTokenAccount<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
         has_one = owner
     )]
     pub escrow_account: Account<'info, OfflineEscrowAccount>,
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+    pub owner: Signer<'info>,
+
+    pub backing_token_account: InterfaceAccount<'info, TokenAccount>,
+}
+
+#[derive(Accounts)]
+pub struct ClaimAsBeneficiary<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = beneficiary @ BeamError::InvalidBeneficiary
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub beneficiary: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct FinalizeBeneficiaryClaim<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = beneficiary @ BeamError::InvalidBeneficiary,
+        close = rent_payer
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(mut)]
+    pub beneficiary: Signer<'info>,
+
+    /// CHECK: Receives the escrow's rent
+    #[account(mut, address = escrow_account.rent_refund_recipient() @ BeamError::InvalidRentRecipient)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = beneficiary_token_account.owner == beneficiary.key() @ BeamError::InvalidBeneficiary,
+        constraint = beneficiary_token_account.mint == escrow_token_account.mint @ BeamError::InvalidBeneficiary
+    )]
+    pub beneficiary_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct FlagDormantEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct SweepDormantEscrow<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        address = config.custodial_token_account @ BeamError::InvalidCustodialAccount,
+        constraint = custodial_token_account.mint == escrow_token_account.mint @ BeamError::InvalidCustodialAccount
+    )]
+    pub custodial_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+#[derive(Accounts)]
+pub struct ReportFraud<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", payer.key().as_ref()],
+        bump = nonce_registry.bump
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        constraint = escrow_account.owner == payer.key() @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    /// CHECK: Verified against the escrow and nonce registry owners
+    pub payer: UncheckedAccount<'info>,
+
+    pub reporter: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Invoice the disputed bundle paid; marking it keeps it from being closed
+    #[account(
+        mut,
+        seeds = [b"invoice", invoice.merchant.as_ref(), invoice.invoice_id.as_ref()],
+        bump = invoice.bump
+    )]
+    pub invoice: Option<Account<'info, Invoice>>,
+
+    /// Required when the escrow is linked to an identity
+    #[account(mut, seeds = [b"identity", escrow_account.identity.as_ref()], bump = identity_reputation.bump)]
+    pub identity_reputation: Option<Account<'info, IdentityReputation>>,
+}
+
+#[derive(Accounts)]
+pub struct SubmitCounterEvidence<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", payer.key().as_ref()],
+        bump = nonce_registry.bump,
+        constraint = nonce_registry.owner == payer.key() @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+#[instruction(bundle_hash: [u8; 32])]
+pub struct RequestChargeback<'info> {
+    #[account(
+        init,
+        payer = payer,
+        space = 8 + Chargeback::INIT_SPACE,
+        seeds = [b"chargeback", payer.key().as_ref(), bundle_hash.as_ref()],
+        bump
+    )]
+    pub chargeback: Account<'info, Chargeback>,
+
+    /// Primary or device registry whose history holds the bundle
+    #[account(
+        seeds = [b"nonce", payer.key().as_ref(), nonce_registry.device_seed()],
+        bump = nonce_registry.bump,
+        constraint = nonce_registry.owner == payer.key() @ BeamError::InvalidOwner
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct RespondToChargeback<'info> {
+    #[account(
+        mut,
+        seeds = [b"chargeback", chargeback.payer.as_ref(), chargeback.bundle_hash.as_ref()],
+        bump = chargeback.bump,
+        has_one = merchant @ BeamError::Unauthorized
+    )]
+    pub chargeback: Account<'info, Chargeback>,
+
+    pub merchant: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveUnansweredChargeback<'info> {
+    #[account(
+        mut,
+        seeds = [b"chargeback", chargeback.payer.as_ref(), chargeback.bundle_hash.as_ref()],
+        bump = chargeback.bump
+    )]
+    pub chargeback: Account<'info, Chargeback>,
+
+    pub resolver: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveChargeback<'info> {
+    #[account(
+        mut,
+        seeds = [b"chargeback", chargeback.payer.as_ref(), chargeback.bundle_hash.as_ref()],
+        bump = chargeback.bump
+    )]
+    pub chargeback: Account<'info, Chargeback>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        constraint = escrow_account.owner == chargeback.payer @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Box<Account<'info, OfflineEscrowAccount>>,
+
+    pub resolver: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct CloseChargeback<'info> {
+    #[account(
+        mut,
+        close = payer,
+        seeds = [b"chargeback", chargeback.payer.as_ref(), chargeback.bundle_hash.as_ref()],
+        bump = chargeback.bump,
+        has_one = payer @ BeamError::Unauthorized
+    )]
+    pub chargeback: Account<'info, Chargeback>,
+
+    #[account(mut)]
+    pub payer: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ReleaseHeldFunds<'info> {
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [
+            b"settlement_hold",
+            settlement_hold.escrow.as_ref(),
+            &settlement_hold.settlement_index.to_le_bytes()
+        ],
+        bump = settlement_hold.bump,
+        has_one = hold_vault @ BeamError::InvalidSettlementHold,
+        has_one = rent_payer @ BeamError::InvalidSettlementHold
+    )]
+    pub settlement_hold: Account<'info, SettlementHold>,
+
+    #[account(mut)]
+    pub hold_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = merchant_token_account.owner == settlement_hold.merchant @ BeamError::InvalidSettlementHold,
+        constraint = merchant_token_account.mint == hold_vault.mint @ BeamError::InvalidSettlementHold
+    )]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    /// CHECK: Receives the hold's rent; must be the one recorded on it
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == hold_vault.mint @ BeamError::InvalidSettlementHold)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ReleaseHeldFunds<'info> {
+    fn empty_hold_vault(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        empty_hold_vault(
+            &self.settlement_hold,
+            &self.hold_vault,
+            to,
+            self.rent_payer.to_account_info(),
+            self.mint.as_deref(),
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+#[derive(Accounts)]
+pub struct DisputeHeldSettlement<'info> {
+    #[account(
+        mut,
+        seeds = [
+            b"settlement_hold",
+            settlement_hold.escrow.as_ref(),
+            &settlement_hold.settlement_index.to_le_bytes()
+        ],
+        bump = settlement_hold.bump,
+        constraint = settlement_hold.escrow == escrow_account.key() @ BeamError::InvalidSettlementHold
+    )]
+    pub settlement_hold: Account<'info, SettlementHold>,
+
+    #[account(
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub owner: Signer<'info>,
+}
+
+#[derive(Accounts)]
+pub struct ResolveHeldSettlement<'info> {
+    #[account(
+        mut,
+        close = rent_payer,
+        seeds = [
+            b"settlement_hold",
+            settlement_hold.escrow.as_ref(),
+            &settlement_hold.settlement_index.to_le_bytes()
+        ],
+        bump = settlement_hold.bump,
+        has_one = hold_vault @ BeamError::InvalidSettlementHold,
+        has_one = rent_payer @ BeamError::InvalidSettlementHold,
+        constraint = settlement_hold.escrow == escrow_account.key() @ BeamError::InvalidSettlementHold
+    )]
+    pub settlement_hold: Account<'info, SettlementHold>,
+
+    #[account(mut)]
+    pub hold_vault: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    #[account(
+        mut,
+        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = escrow_account.is_backing_account(&escrow_token_account.key()) @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub escrow_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    #[account(
+        mut,
+        constraint = merchant_token_account.owner == settlement_hold.merchant @ BeamError::InvalidSettlementHold,
+        constraint = merchant_token_account.mint == hold_vault.mint @ BeamError::InvalidSettlementHold
+    )]
+    pub merchant_token_account: InterfaceAccount<'info, TokenAccount>,
+
+    pub resolver: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// CHECK: Receives the hold's rent; must be the one recorded on it
+    #[account(mut)]
+    pub rent_payer: UncheckedAccount<'info>,
+
+    /// Required when the mint carries a Token-2022 transfer fee
+    #[account(constraint = mint.key() == hold_vault.mint @ BeamError::InvalidSettlementHold)]
+    pub mint: Option<Box<InterfaceAccount<'info, Mint>>>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+}
+
+impl<'info> ResolveHeldSettlement<'info> {
+    fn empty_hold_vault(&self, to: AccountInfo<'info>, amount: u64) -> Result<()> {
+        empty_hold_vault(
+            &self.settlement_hold,
+            &self.hold_vault,
+            to,
+            self.rent_payer.to_account_info(),
+            self.mint.as_deref(),
+            &self.token_program,
+            amount,
+        )
+    }
+}
+
+/// Move `amount` out of a hold's vault to `to`, then close the vault to the hold's
+/// rent payer
+fn empty_hold_vault<'info>(
+    hold: &Account<'info, SettlementHold>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    to: AccountInfo<'info>,
+    rent_payer: AccountInfo<'info>,
+    mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    let index = hold.settlement_index.to_le_bytes();
+    let seeds = &[b"settlement_hold".as_ref(), hold.escrow.as_ref(), &index, &[hold.bump]];
+    let signer = &[&seeds[..]];
+    if amount > 0 {
+        transfer_tokens(
+            token_program.to_account_info(),
+            vault.to_account_info(),
+            to,
+            hold.to_account_info(),
+            mint,
+            amount,
+            signer,
+        )?;
+    }
+    token::close_account(CpiContext::new_with_signer(
+        token_program.to_account_info(),
+        CloseAccount {
+            account: vault.to_account_info(),
+            destination: rent_payer,
+            authority: hold.to_account_info(),
+        },
+        signer,
+    ))
+}
+
+/// Move `amount` from the verifier's token account into its bond vault and credit
+/// the bond with what arrives after any transfer fee
+fn deposit_verifier_bond<'info>(
+    bond: &mut Account<'info, VerifierBond>,
+    vault: &InterfaceAccount<'info, TokenAccount>,
+    from: &InterfaceAccount<'info, TokenAccount>,
+    verifier: &Signer<'info>,
+    mint: Option<&InterfaceAccount<'info, Mint>>,
+    token_program: &Interface<'info, TokenInterface>,
+    amount: u64,
+) -> Result<()> {
+    require!(amount > 0, BeamError::InvalidAmount);
+    let credited = match mint {
+        Some(mint) => amount - transfer_fee(mint, amount)?,
+        None => amount,
+    };
+    transfer_tokens(
+        token_program.to_account_info(),
+        from.to_account_info(),
+        vault.to_account_info(),
+        verifier.to_account_info(),
+        mint,
+        amount,
+        &[],
+    )?;
+    bond.amount = bond.amount.checked_add(credited).ok_or(BeamError::Overflow)?;
+
+    emit!(VerifierBondPosted {
+        verifier: bond.verifier,
+        amount: credited,
+        bond: bond.amount,
+    });
+
+    Ok(())
+}
+
+#[derive(Accounts)]
+pub struct PruneFraudRecords<'info> {
+    #[account(
+        mut,
+        seeds = [b"nonce", nonce_registry.owner.as_ref()],
+        bump = nonce_registry.bump
+    )]
+    pub nonce_registry: Account<'info, NonceRegistry>,
+
+    #[account(
+        init_if_needed,
+        payer = authority,
+        space = 8 + FraudArchive::INIT_SPACE,
+        seeds = [b"fraud_archive", nonce_registry.owner.as_ref()],
+        bump
+    )]
+    pub fraud_archive: Account<'info, FraudArchive>,
+
+    /// The registry's owner or the config admin
+    #[account(
+        mut,
+        constraint = authority.key() == nonce_registry.owner
+            || authority.key() == config.admin @ BeamError::Unauthorized
+    )]
+    pub authority: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    /// Appended to only when the admin prunes
+    #[account(mut, seeds = [b"admin_audit"], bump = audit_log.bump)]
+    pub audit_log: Account<'info, AdminAuditLog>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+#[instruction(identity: Pubkey)]
+pub struct RegisterIdentity<'info> {
+    #[account(
+        init,
+        payer = identity_authority,
+        space = 8 + IdentityReputation::INIT_SPACE,
+        seeds = [b"identity", identity.as_ref()],
+        bump
+    )]
+    pub identity_reputation: Account<'info, IdentityReputation>,
+
+    #[account(
+        mut,
+        constraint = identity_authority.key() == config.identity_authority @ BeamError::Unauthorized
+    )]
+    pub identity_authority: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct LinkIdentity<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub owner: Signer<'info>,
+
+    #[account(
+        mut,
+        seeds = [b"identity", identity_reputation.identity.as_ref()],
+        bump = identity_reputation.bump
+    )]
+    pub identity_reputation: Account<'info, IdentityReputation>,
+
+    #[account(constraint = identity_authority.key() == config.identity_authority @ BeamError::Unauthorized)]
+    pub identity_authority: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct ManageIdentity<'info> {
+    #[account(
+        mut,
+        seeds = [b"identity", identity_reputation.identity.as_ref()],
+        bump = identity_reputation.bump
+    )]
+    pub identity_reputation: Account<'info, IdentityReputation>,
+
+    #[account(constraint = identity_authority.key() == config.identity_authority @ BeamError::Unauthorized)]
+    pub identity_authority: Signer<'info>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct IdentityView<'info> {
+    #[account(
+        seeds = [b"identity", identity_reputation.identity.as_ref()],
+        bump = identity_reputation.bump
+    )]
+    pub identity_reputation: Account<'info, IdentityReputation>,
+
+    #[account(seeds = [b"config"], bump = config.bump)]
+    pub config: Account<'info, ProgramConfig>,
+}
+
+#[derive(Accounts)]
+pub struct RotateOwnerKey<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", old_escrow.seed_key().as_ref()],
+        bump = old_escrow.bump,
+        constraint = old_escrow.owner == old_owner.key() @ BeamError::InvalidOwner,
+        close = escrow_rent_payer
+    )]
+    pub old_escrow: Box<Account<'info, OfflineEscrowAccount>>,
+
+    #[account(
+        mut,
+        seeds = [b"nonce", old_owner.key().as_ref()],
+        bump = old_nonce_registry.bump,
+        constraint = old_nonce_registry.owner == old_owner.key() @ BeamError::InvalidOwner,
+        close = registry_rent_payer
+    )]
+    pub old_nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        address = old_escrow.escrow_token_account @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub old_escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = old_owner,
+        space = 8 + OfflineEscrowAccount::INIT_SPACE,
+        seeds = [b"escrow", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_escrow: Box<Account<'info, OfflineEscrowAccount>>,
+
+    /// Sized for the old registry's capacity, so a grown registry keeps every record
+    #[account(
+        init,
+        payer = old_owner,
+        space = RegistryCapacity::account_len(
+            RegistryCapacity::of(&old_nonce_registry.to_account_info()).growth_steps
+        ),
+        seeds = [b"nonce", new_owner.key().as_ref()],
+        bump
+    )]
+    pub new_nonce_registry: Box<Account<'info, NonceRegistry>>,
+
+    #[account(
+        mut,
+        constraint = new_escrow_token_account.owner == new_escrow.key() @ BeamError::InvalidEscrowTokenAccount,
+        constraint = new_escrow_token_account.mint == old_escrow_token_account.mint @ BeamError::InvalidEscrowTokenAccount
+    )]
+    pub new_escrow_token_account: Box<InterfaceAccount<'info, TokenAccount>>,
+
+    #[account(
+        init,
+        payer = old_owner,
+        space = 8 + OwnerTombstone::INIT_SPACE,
+        seeds = [b"tombstone", old_owner.key().as_ref()],
+        bump
+    )]
+    pub owner_tombstone: Account<'info, OwnerTombstone>,
+
+    #[account(mut)]
+    pub old_owner: Signer<'info>,
+
+    pub new_owner: Signer<'info>,
+
+    /// CHECK: Receives the old escrow's rent
+    #[account(mut, address = old_escrow.rent_refund_recipient() @ BeamError::InvalidRentRecipient)]
+    pub escrow_rent_payer: UncheckedAccount<'info>,
+
+    /// CHECK: Receives the old registry's rent
+    #[account(
+        mut,
+        address = old_nonce_registry.rent_refund_recipient() @ BeamError::InvalidRentRecipient
+    )]
+    pub registry_rent_payer: UncheckedAccount<'info>,
+
+    pub token_program: Interface<'info, TokenInterface>,
+    pub system_program: Program<'info, System>,
+}
+
+#[derive(Accounts)]
+pub struct SetEscrowRentPayer<'info> {
+    #[account(
+        mut,
+        seeds = [b"escrow", escrow_account.seed_key().as_ref()],
+        bump = escrow_account.bump,
+        has_one = owner @ BeamError::InvalidOwner
+    )]
+    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+
+    pub owner: Signer<'info>,
+
+    /// CHECK: Must hold no lamports, i.e. no longer exist
+    #[account(address = escrow_account.rent_refund_recipient() @ BeamError::InvalidRentRecipient)]
+    pub current_rent_payer: UncheckedAccount<'info>,
+}
+
+#[derive(Accounts)]
+pub struct MigrateEscrow<'info> {
+    /// CHECK: Owner and discriminator are validated in the handler before it is reallocated
+    #[account(
+        mut,
+        seeds = [b"escrow", owner.key().as_ref()],
+        bump,
+    )]
+    pub escrow_account: AccountInfo<'info>,
+
+    #[account(mut)]
+    pub owner: Signer<'info>,
+
+    pub system_program: Program<'info, System>,
+}
+
+#[account]
+#[derive(InitSpace)]
+pub struct OfflineEscrowAccount {
+    pub owner: Pubkey,
+    pub escrow_token_account: Pubkey,  // Store token account address
+    pub escrow_balance: u64,
+    pub last_nonce: u64,
+    // Pre-i32 score; only read by `migrate_escrow` to seed `reputation_score`
+    pub legacy_reputation_score: u16,
+    pub total_spent: u64,
+    pub created_at: i64,
+    pub bump: u8,
+    // Phase 1.3: Stake slashing fields
+    pub stake_locked: u64,        // Funds locked as penalty for fraud
+    pub fraud_count: u32,          // Number of detected fraud attempts
+    pub last_fraud_timestamp: i64, // When last fraud was detected
+    // Recent deposits still subject to the config funding lockup
+    pub funding_tranches: [FundingTranche; MAX_FUNDING_TRANCHES],
+    // Dead-man switch: beneficiary may claim after `inactivity_period` without owner activity
+    pub beneficiary: Pubkey,
+    pub inactivity_period: i64,
+    pub last_activity_at: i64,
+    pub beneficiary_claim_started_at: i64, // 0 when no claim is pending
+    // Per-day settlement totals for the config rolling-window cap, indexed by day % len
+    pub rolling_spend: [SpendBucket; MAX_ROLLING_WINDOW_DAYS],
+    // Owner-configured per-merchant lifetime caps; empty slots have a zero limit
+    pub merchant_limits: [MerchantLimit; MAX_MERCHANT_LIMITS],
+    // Set once at initialization; rewarded from protocol fees on the first settlements
+    pub referrer: Pubkey,
+    pub referral_rewards_paid: u16,
+    // Display name, UTF-8 padded with zero bytes
+    pub label: [u8; 32],
+    // Bundles approved while online; matched exactly at settlement
+    pub preauthorizations: [Preauthorization; MAX_PREAUTHORIZATIONS],
+    // Set aside for outstanding offline bundles; only settlements may spend it
+    // until `reservation_expires_at`
+    pub reserved_balance: u64,
+    pub reservation_expires_at: i64,
+    // Manual reputation change applied in the period starting at `reputation_adjustment_period_start`
+    pub reputation_adjustment_period_start: i64,
+    pub reputation_adjusted_in_period: u32,
+    // Settlements completed on this escrow; unlike the nonce it never skips. Its
+    // value after a settlement is that settlement's `settlement_index`.
+    pub settlement_count: u64,
+    // Signed score within [MIN_REPUTATION, MAX_REPUTATION]; only change it through
+    // `apply_reputation_delta`
+    pub reputation_score: i32,
+    // Pre-`status` flag; `migrate_escrow` folds it into `status` and clears it
+    pub legacy_reputation_migrated: bool,
+    // Extra escrow-owned token accounts of the vault's mint; empty slots are default
+    pub backing_token_accounts: [Pubkey; MAX_BACKING_TOKEN_ACCOUNTS],
+    // Lifetime fraud reports indexed by `FraudReason`
+    pub fraud_counts_by_reason: [u32; FRAUD_REASON_COUNT],
+    // `ESCROW_*` flag bits
+    pub status: u32,
+    // Token-2022 transfer fees withheld on funding and on payouts the payer absorbed
+    pub total_transfer_fees: u64,
+    // Key the PDA was derived from; read it through `seed_key`
+    pub pda_seed: Pubkey,
+    // KYC identity whose `IdentityReputation` this escrow's fraud also counts against;
+    // default while unlinked
+    pub identity: Pubkey,
+    // Settlements whose bundle creation time was attested, and how many of them
+    // settled within each `PUNCTUALITY_BUCKETS` bound (cumulative)
+    pub timed_settlement_count: u64,
+    pub settled_within: [u64; PUNCTUALITY_BUCKET_COUNT],
+    // Owed to `credit_line`, fees included; deposits repay it first and nothing can
+    // leave the escrow while it is non-zero
+    pub credit_debt: u64,
+    pub credit_line: Pubkey,
+    // Opt-in charity round-up applied on top of each settlement, and what it has
+    // donated so far
+    pub roundup_config: RoundupConfig,
+    pub total_donated: u64,
+    // When `ESCROW_DORMANT` was set; cleared with it by owner activity
+    pub dormant_since: i64,
+    // Key shares that may sign payer proofs in place of the verifier
+    pub aggregate_key: AggregateKey,
+    // Chargebacks the arbiters ruled against this payer
+    pub rejected_chargebacks: u32,
+    // Merchants named in a targeted event when a deposit lifts the balance past
+    // their threshold; managed by the owner
+    pub watchers: [EscrowWatcher; MAX_ESCROW_WATCHERS],
+    // Start of the current inactivity stretch for reputation decay; zero until the
+    // first settlement after it was added
+    pub last_settlement_at: i64,
+    // Decimals of the vault's mint; only meaningful once `ESCROW_MINT_DECIMALS_RECORDED`
+    // is set, so read it through `mint_decimals`
+    pub mint_decimals: u8,
+    // Paid the escrow's rent and gets it back when the escrow closes; default on
+    // escrows opened before it was recorded, read it through `rent_refund_recipient`
+    pub rent_payer: Pubkey,
+}
+
+impl OfflineEscrowAccount {
+    /// Set up a freshly created, still unfunded escrow for `owner` whose rent
+    /// `rent_payer` paid
+    pub fn initialize(
+        &mut self,
+        owner: Pubkey,
+        rent_payer: Pubkey,
+        escrow_token_account: Pubkey,
+        bump: u8,
+        now: i64,
+    ) {
+        self.owner = owner;
+        self.pda_seed = owner;
+        self.rent_payer = rent_payer;
+        self.escrow_token_account = escrow_token_account;
+        self.escrow_balance = 0;
+        self.last_nonce = 0;
+        self.apply_reputation_delta(INITIAL_REPUTATION);
+        self.set_reputation_migrated(true);
+        self.total_spent = 0;
+        self.settlement_count = 0;
+        self.created_at = now;
+        self.bump = bump;
+        self.funding_tranches = [FundingTranche::default(); MAX_FUNDING_TRANCHES];
+        self.beneficiary = Pubkey::default();
+        self.inactivity_period = 0;
+        self.last_activity_at = now;
+        self.beneficiary_claim_started_at = 0;
+        self.referral_rewards_paid = 0;
+        // Phase 1.3: Initialize fraud detection fields
+        self.stake_locked = 0;
+        self.fraud_count = 0;
+        self.last_fraud_timestamp = 0;
+    }
+
+    /// Whether `reputation_score` has been seeded from the pre-i32 score
+    pub fn is_reputation_migrated(&self) -> bool {
+        self.status & ESCROW_REPUTATION_MIGRATED != 0
+    }
+
+    pub fn set_reputation_migrated(&mut self, on: bool) {
+        self.status = with_flag(self.status, ESCROW_REPUTATION_MIGRATED, on);
+    }
+
+    /// Where the escrow's rent goes when it closes: whoever paid it, or the owner
+    /// for escrows opened before the payer was recorded
+    pub fn rent_refund_recipient(&self) -> Pubkey {
+        if self.rent_payer == Pubkey::default() {
+            self.owner
+        } else {
+            self.rent_payer
+        }
+    }
+
+    /// Decimals of the vault's mint, once recorded
+    pub fn mint_decimals(&self) -> Option<u8> {
+        (self.status & ESCROW_MINT_DECIMALS_RECORDED != 0).then_some(self.mint_decimals)
+    }
+
+    pub fn record_mint_decimals(&mut self, decimals: u8) {
+        self.mint_decimals = decimals;
+        self.status = with_flag(self.status, ESCROW_MINT_DECIMALS_RECORDED, true);
+    }
+
+    /// Whether the owner waived the config's `max_reasonable_amount` check
+    pub fn is_amount_check_opted_out(&self) -> bool {
+        self.status & ESCROW_AMOUNT_CHECK_OPTED_OUT != 0
+    }
+
+    pub fn set_amount_check_opted_out(&mut self, on: bool) {
+        self.status = with_flag(self.status, ESCROW_AMOUNT_CHECK_OPTED_OUT, on);
+    }
+
+    /// Reject a settlement of `amount` base units of `mint` above the config's
+    /// ceiling for that mint, unless the owner opted out
+    pub fn check_plausible_amount(
+        &self,
+        config: &ProgramConfig,
+        mint: &Pubkey,
+        amount: u64,
+    ) -> std::result::Result<(), BeamError> {
+        match config.max_reasonable_amount(mint) {
+            Some(max_amount) if amount > max_amount && !self.is_amount_check_opted_out() => {
+                Err(BeamError::AmountImplausiblyLarge)
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Flagged by `flag_dormant_escrow` and swept to the custodial account after notice
+    pub fn is_dormant(&self) -> bool {
+        self.status & ESCROW_DORMANT != 0
+    }
+
+    pub fn set_dormant(&mut self, on: bool) {
+        self.status = with_flag(self.status, ESCROW_DORMANT, on);
+    }
+
+    /// Set for the duration of a composite instruction by `enter_processing`
+    pub fn is_processing(&self) -> bool {
+        self.status & ESCROW_PROCESSING != 0
+    }
+
+    pub fn set_processing(&mut self, on: bool) {
+        self.status = with_flag(self.status, ESCROW_PROCESSING, on);
+    }
+
+    /// Key the escrow PDA is derived from. It is fixed at creation, so authority
+    /// checks go through `owner` and never through the seeds. Escrows created
+    /// before it was stored were always derived from their owner.
+    pub fn seed_key(&self) -> &Pubkey {
+        if self.pda_seed == Pubkey::default() {
+            &self.owner
+        } else {
+            &self.pda_seed
+        }
+    }
+
+    /// The primary vault followed by every extra backing account
+    pub fn backing_accounts(&self) -> impl Iterator<Item = &Pubkey> {
+        std::iter::once(&self.escrow_token_account).chain(
+            self.backing_token_accounts
+                .iter()
+                .filter(|account| **account != Pubkey::default()),
+        )
+    }
+
+    pub fn is_backing_account(&self, token_account: &Pubkey) -> bool {
+        self.backing_accounts().any(|account| account == token_account)
+    }
+
+    /// Whether any backing account besides the primary vault is still registered
+    pub fn has_extra_backing_accounts(&self) -> bool {
+        self.backing_token_accounts
+            .iter()
+            .any(|account| *account != Pubkey::default())
+    }
+
+    /// Move the reputation score by `delta`, saturating at the configured bounds.
+    /// Returns the new score.
+    pub fn apply_reputation_delta(&mut self, delta: i32) -> i32 {
+        self.reputation_score = self
+            .reputation_score
+            .saturating_add(delta)
+            .clamp(MIN_REPUTATION, MAX_REPUTATION);
+        self.reputation_score
+    }
+
+    /// Whole decay periods since the last settlement and the score after decaying
+    /// that long toward the config baseline. Only scores above the baseline decay:
+    /// staleness erodes unproven good standing, while scores below it are left to
+    /// fraud penalties and manual adjustments.
+    pub fn decayed_reputation(&self, config: &ProgramConfig, now: i64) -> (i64, i32) {
+        if config.reputation_decay_period == 0 || self.last_settlement_at == 0 {
+            return (0, self.reputation_score);
+        }
+        let periods = now.saturating_sub(self.last_settlement_at).max(0) / config.reputation_decay_period;
+        let baseline = config.reputation_decay_baseline;
+        if self.reputation_score <= baseline {
+            return (periods, self.reputation_score);
+        }
+        let decay = periods.saturating_mul(i64::from(config.reputation_decay_points));
+        let decayed = i64::from(self.reputation_score)
+            .saturating_sub(decay)
+            .max(i64::from(baseline));
+        (periods, decayed as i32)
+    }
+
+    /// Fold pending inactivity decay into the stored score
+    pub fn apply_reputation_decay(&mut self, config: &ProgramConfig, now: i64) {
+        let (inactive_periods, decayed) = self.decayed_reputation(config, now);
+        if decayed == self.reputation_score {
+            return;
+        }
+        let old_score = self.reputation_score;
+        self.reputation_score = decayed;
+        emit!(ReputationDecayed {
+            owner: self.owner,
+            old_score,
+            new_score: decayed,
+            inactive_periods,
+            last_settlement_at: self.last_settlement_at,
+        });
+    }
+
+    /// Mark the owner as active, cancelling any pending beneficiary claim
+    pub fn record_owner_activity(&mut self, now: i64) {
+        self.last_activity_at = now;
+        if self.beneficiary_claim_started_at != 0 {
+            self.beneficiary_claim_started_at = 0;
+            emit!(BeneficiaryClaimCancelled {
+                owner: self.owner,
+                beneficiary: self.beneficiary,
+            });
+        }
+        if self.is_dormant() {
+            self.set_dormant(false);
+            self.dormant_since = 0;
+            emit!(EscrowReactivated { owner: self.owner });
+        }
+    }
+
+    /// Record a deposit so it only becomes settleable after `lockup` seconds.
+    /// Matured tranches are dropped first; when every slot is still locked the deposit
+    /// merges into the newest tranche, which can only lengthen the lock.
+    pub fn record_funding(&mut self, amount: u64, now: i64, lockup: i64) {
+        if amount == 0 || lockup <= 0 {
+            return;
+        }
+
+        for tranche in self.funding_tranches.iter_mut() {
+            if tranche.amount > 0 && tranche.funded_at.saturating_add(lockup) <= now {
+                *tranche = FundingTranche::default();
+            }
+        }
+
+        if let Some(slot) = self.funding_tranches.iter_mut().find(|t| t.amount == 0) {
+            *slot = FundingTranche { amount, funded_at: now };
+        } else if let Some(newest) = self.funding_tranches.iter_mut().max_by_key(|t| t.funded_at) {
+            newest.amount = newest.amount.saturating_add(amount);
+            newest.funded_at = now;
+        }
+    }
+
+    /// Amount settled over the last `window_days` days, including today
+    pub fn rolling_spent(&self, now: i64, window_days: u8) -> u64 {
+        let today = now.div_euclid(SECONDS_PER_DAY);
+        let oldest = today - i64::from(window_days.max(1)) + 1;
+        self.rolling_spend
+            .iter()
+            .filter(|bucket| bucket.amount > 0 && i64::from(bucket.day) >= oldest)
+            .fold(0u64, |acc, bucket| acc.saturating_add(bucket.amount))
+    }
+
+    /// Add a settlement to today's bucket, recycling the slot if it holds an older day
+    pub fn record_rolling_spend(&mut self, now: i64, amount: u64) {
+        let today = now.div_euclid(SECONDS_PER_DAY);
+        let slot = &mut self.rolling_spend[today.rem_euclid(MAX_ROLLING_WINDOW_DAYS as i64) as usize];
+        if i64::from(slot.day) != today {
+            *slot = SpendBucket {
+                day: today as u32,
+                amount: 0,
+            };
+        }
+        slot.amount = slot.amount.saturating_add(amount);
+    }
+
+    /// The active cap for `merchant`, if the owner set one
+    pub fn merchant_limit(&self, merchant: &Pubkey) -> Option<&MerchantLimit> {
+        self.merchant_limits
+            .iter()
+            .find(|entry| entry.limit > 0 && entry.merchant == *merchant)
+    }
+
+    /// Slot of the pre-authorization matching this exact settlement, if any
+    pub fn preauthorization(
+        &self,
+        bundle_hash: &[u8; 32],
+        merchant: &Pubkey,
+        amount: u64,
+        nonce: u64,
+    ) -> Option<usize> {
+        self.preauthorizations.iter().position(|entry| {
+            entry.amount > 0
+                && entry.amount == amount
+                && entry.nonce == nonce
+                && entry.bundle_hash == *bundle_hash
+                && entry.merchant == *merchant
+        })
+    }
+
+    /// Count a settlement against the merchant's cap, when one is set
+    pub fn record_merchant_spend(&mut self, merchant: &Pubkey, amount: u64) {
+        if let Some(entry) = self
+            .merchant_limits
+            .iter_mut()
+            .find(|entry| entry.limit > 0 && entry.merchant == *merchant)
+        {
+            entry.settled = entry.settled.saturating_add(amount);
+        }
+    }
+
+    /// Total of deposits that have not yet cleared the lockup
+    pub fn locked_funding(&self, now: i64, lockup: i64) -> u64 {
+        if lockup <= 0 {
+            return 0;
+        }
+        self.funding_tranches
+            .iter()
+            .filter(|t| t.funded_at.saturating_add(lockup) > now)
+            .fold(0u64, |acc, t| acc.saturating_add(t.amount))
+    }
+
+    /// Balance settlements may currently draw against
+    pub fn settleable_balance(&self, now: i64, lockup: i64) -> u64 {
+        self.escrow_balance.saturating_sub(self.locked_funding(now, lockup))
+    }
+
+    /// Reservation still in force, never more than the balance backing it
+    pub fn active_reservation(&self, now: i64) -> u64 {
+        if self.reservation_expires_at > now {
+            self.reserved_balance.min(self.escrow_balance)
+        } else {
+            0
+        }
+    }
+
+    /// Balance the owner may withdraw or move out; the reservation stays behind
+    pub fn unreserved_balance(&self, now: i64) -> u64 {
+        self.escrow_balance.saturating_sub(self.active_reservation(now))
+    }
+
+    /// Debit a settled payment and update the spend, punctuality and reputation
+    /// decay tracking it feeds
+    pub fn record_settlement(
+        &mut self,
+        config: &ProgramConfig,
+        merchant: &Pubkey,
+        amount: u64,
+        nonce: u64,
+        bundle_created_at: i64,
+        now: i64,
+    ) -> Result<()> {
+        self.consume_reservation(merchant, amount, now);
+        self.escrow_balance = self.escrow_balance.checked_sub(amount)
+            .ok_or(BeamError::Underflow)?;
+        self.last_nonce = self.last_nonce.max(nonce);
+        self.total_spent = self.total_spent.checked_add(amount)
+            .ok_or(BeamError::Overflow)?;
+        self.settlement_count += 1;
+        self.apply_reputation_decay(config, now);
+        self.last_settlement_at = now;
+        self.record_owner_activity(now);
+        self.record_rolling_spend(now, amount);
+        self.record_merchant_spend(merchant, amount);
+        self.record_punctuality(bundle_created_at, now)
+    }
+
+    /// Count a settlement into every `PUNCTUALITY_BUCKETS` bucket it landed within.
+    /// Bundles with no attested creation time are left out of the aggregates.
+    fn record_punctuality(&mut self, bundle_created_at: i64, now: i64) -> Result<()> {
+        if bundle_created_at <= 0 {
+            return Ok(());
+        }
+        // Attestation timestamps may run slightly ahead of the cluster clock
+        let delay = now.checked_sub(bundle_created_at).ok_or(BeamError::Overflow)?.max(0);
+        self.timed_settlement_count = self
+            .timed_settlement_count
+            .checked_add(1)
+            .ok_or(BeamError::Overflow)?;
+        for (count, bound) in self.settled_within.iter_mut().zip(PUNCTUALITY_BUCKETS) {
+            if delay <= bound {
+                *count = count.checked_add(1).ok_or(BeamError::Overflow)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Count a manual reputation adjustment against the per-period cap
+    pub fn record_reputation_adjustment(&mut self, magnitude: u32, now: i64) -> Result<()> {
+        if now - self.reputation_adjustment_period_start >= REPUTATION_ADJUSTMENT_PERIOD {
+            self.reputation_adjustment_period_start = now;
+            self.reputation_adjusted_in_period = 0;
+        }
+        let total = self.reputation_adjusted_in_period.saturating_add(magnitude);
+        require!(
+            total <= MAX_PERIOD_REPUTATION_ADJUSTMENT,
+            BeamError::ReputationAdjustmentCapExceeded
+        );
+        self.reputation_adjusted_in_period = total;
+        Ok(())
+    }
+
+    /// When withdrawals unlock again after a fraud report within the last
+    /// `delay` seconds; `None` for clean escrows or once the delay has run out
+    pub fn fraud_unlock_at(&self, delay: i64, now: i64) -> Option<i64> {
+        if delay == 0 || self.last_fraud_timestamp == 0 {
+            return None;
+        }
+        let unlock_at = self.last_fraud_timestamp.saturating_add(delay);
+        (now < unlock_at).then_some(unlock_at)
+    }
+
+    /// Debit a courier fee or payer-borne protocol fee paid alongside a settlement;
+    /// it counts as spend toward the rolling cap but not toward any merchant's limit
+    pub fn record_fee_debit(&mut self, fee: u64, now: i64) -> Result<()> {
+        self.escrow_balance = self.escrow_balance.checked_sub(fee)
+            .ok_or(BeamError::Underflow)?;
+        self.total_spent = self.total_spent.checked_add(fee)
+            .ok_or(BeamError::Overflow)?;
+        self.record_rolling_spend(now, fee);
+        Ok(())
+    }
+
+    /// Settle `amount` out of the reservation first, emitting what it covered
+    pub fn consume_reservation(&mut self, merchant: &Pubkey, amount: u64, now: i64) {
+        let consumed = self.active_reservation(now).min(amount);
+        if consumed == 0 {
+            return;
+        }
+        self.reserved_balance -= consumed;
+        emit!(ReservationConsumed {
+            owner: self.owner,
+            merchant: *merchant,
+            amount: consumed,
+            reserved_balance: self.reserved_balance,
+        });
+    }
+
+    /// Remove `amount` from the tranches, newest first
+    pub fn release_newest_funding(&mut self, mut amount: u64) {
+        while amount > 0 {
+            let Some(newest) = self
+                .funding_tranches
+                .iter_mut()
+                .filter(|t| t.amount > 0)
+                .max_by_key(|t| t.funded_at)
+            else {
+                break;
+            };
+            let taken = newest.amount.min(amount);
+            newest.amount -= taken;
+            amount -= taken;
+        }
+    }
+}
+
+/// One `AdminAuditLog` entry: `head` is `sha256(prev_hash || action as u8 ||
+/// params_hash || timestamp as i64 LE)`, where `params_hash` is the sha256 of the
+/// instruction's Borsh-encoded parameters
+#[event]
+pub struct AdminActionRecorded {
+    pub admin: Pubkey,
+    pub action: AdminAction,
+    pub prev_hash: [u8; 32],
+    pub params_hash: [u8; 32],
+    pub timestamp: i64,
+    pub head: [u8; 32],
+    pub length: u64,
+}
+
+#[event]
+pub struct ConfigUpdated {
+    pub admin: Pubkey,
+    pub update: ConfigUpdate,
+}
+
+#[event]
+pub struct LiabilityRegistered {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+}
+
+#[event]
+pub struct LiabilityCleared {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    /// Dropped as stale rather than cleared by its settlement
+    pub pruned: bool,
+}
+
+#[event]
+pub struct FundsReserved {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub reserved_balance: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct ReservationConsumed {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub reserved_balance: u64,
+}
+
+#[event]
+pub struct ReservationReleased {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub reserved_balance: u64,
+    /// Cleared by `expire_reservation` rather than released by the owner
+    pub expired: bool,
+}
+
+#[event]
+pub struct BundlePreauthorized {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct PreauthorizationConsumed {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub nonce: u64,
+}
+
+#[event]
+pub struct PreauthorizationCancelled {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+}
+
+#[event]
+pub struct VerifierHeartbeatRecorded {
+    pub counter: u64,
+    pub beat_at: i64,
+}
+
+#[event]
+pub struct ArbitersUpdated {
+    pub admin: Pubkey,
+    pub arbiters: Vec<Pubkey>,
+    pub quorum: u8,
+}
+
+#[event]
+pub struct ReputationAdjusted {
+    pub owner: Pubkey,
+    pub admin: Pubkey,
+    pub reason_code: u16,
+    pub delta: i32,
+    pub old_score: i32,
+    pub new_score: i32,
+    /// Arbiters that co-signed; empty below the quorum threshold
+    pub arbiters: Vec<Pubkey>,
+}
+
+/// Reputation a settlement earned; `reward` is zero once the escrow reached the
+/// per-merchant cap
+#[event]
+pub struct SettlementReputationRewarded {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub reward: i32,
+    pub new_score: i32,
+    /// Earlier settlements to `merchant` within the reward period
+    pub prior_to_merchant: u32,
+    /// Merchants paid within the reward period, not counting this settlement
+    pub distinct_merchants: u32,
+}
+
+#[event]
+pub struct SettlementHeld {
+    pub hold: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub release_at: i64,
+}
+
+#[event]
+pub struct HeldFundsReleased {
+    pub hold: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub released_at: i64,
+}
+
+/// The payer froze a held settlement pending the arbiters' ruling
+#[event]
+pub struct HeldSettlementDisputed {
+    pub hold: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub disputed_at: i64,
+}
+
+/// A disputed hold the arbiters ruled for the payer; `amount` went back into
+/// their escrow
+#[event]
+pub struct HeldSettlementRefunded {
+    pub hold: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub new_balance: u64,
+    pub disputed_at: i64,
+}
+
+#[event]
+pub struct HeldSettlementResolved {
+    pub hold: Pubkey,
+    pub outcome: ChargebackOutcome,
+    pub resolved_by: Pubkey,
+    /// Arbiters whose signatures made up the quorum
+    pub arbiters: Vec<Pubkey>,
+    pub resolved_at: i64,
+}
+
+/// Inactivity decay folded into an escrow's score by its next settlement
+#[event]
+pub struct ReputationDecayed {
+    pub owner: Pubkey,
+    pub old_score: i32,
+    pub new_score: i32,
+    /// Whole `reputation_decay_period`s since `last_settlement_at`
+    pub inactive_periods: i64,
+    pub last_settlement_at: i64,
+}
+
+#[event]
+pub struct FallbackVerifierSet {
+    pub admin: Pubkey,
+    pub verifier: Pubkey,
+    pub cap: u64,
+}
+
+#[event]
+pub struct FallbackVerifierRevoked {
+    pub admin: Pubkey,
+    pub verifier: Pubkey,
+}
+
+#[event]
+pub struct VerifierFeeAccountRegistered {
+    pub verifier: Pubkey,
+    pub fee_vault: Pubkey,
+}
+
+#[event]
+pub struct VerifierFeesClaimed {
+    pub verifier: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub total_claimed: u64,
+}
+
+/// `amount` is what reached the vault; `bond` the new total
+#[event]
+pub struct VerifierBondPosted {
+    pub verifier: Pubkey,
+    pub amount: u64,
+    pub bond: u64,
+}
+
+#[event]
+pub struct VerifierBondDeregistered {
+    pub verifier: Pubkey,
+    pub amount: u64,
+    pub withdrawable_at: i64,
+}
+
+#[event]
+pub struct VerifierBondWithdrawn {
+    pub verifier: Pubkey,
+    pub destination: Pubkey,
+    pub amount: u64,
+    pub total_slashed: u64,
+}
+
+#[event]
+pub struct VerifierSlashed {
+    pub verifier: Pubkey,
+    pub payer: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub remaining_bond: u64,
+    pub slashed_by: Pubkey,
+}
+
+#[event]
+pub struct FallbackAttestationUsed {
+    pub verifier: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub role: AttestationRole,
+    pub reason: u8,
+}
+
+#[event]
+pub struct AdminProposed {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminProposalCancelled {
+    pub admin: Pubkey,
+    pub pending_admin: Pubkey,
+}
+
+#[event]
+pub struct AdminTransferred {
+    pub previous_admin: Pubkey,
+    pub new_admin: Pubkey,
+}
+
+/// `max_amount` is zero when the mint's ceiling was removed
+#[event]
+pub struct MaxReasonableAmountUpdated {
+    pub admin: Pubkey,
+    pub mint: Pubkey,
+    pub max_amount: u64,
+}
+
+#[event]
+pub struct SettlementsHalted {
+    pub admin: Pubkey,
+    pub reason: u16,
+    pub halted_at: i64,
+}
+
+#[event]
+pub struct SettlementsResumed {
+    pub admin: Pubkey,
+    pub reason: u16,
+    pub resumed_at: i64,
+}
+
+#[event]
+pub struct EscrowInitialized {
+    pub owner: Pubkey,
+    pub initial_balance: u64,
+}
+
+/// An escrow and its nonce registry were created and funded by a sponsor
+#[event]
+pub struct EscrowOnboarded {
+    pub owner: Pubkey,
+    pub sponsor: Pubkey,
+    pub initial_balance: u64,
+}
+
+/// Settlement created the payer's missing primary registry
+#[event]
+pub struct NonceRegistryBootstrapped {
+    pub owner: Pubkey,
+    pub rent_payer: Pubkey,
+}
+
+#[event]
+pub struct DeviceEnrolled {
+    pub owner: Pubkey,
+    pub device_id_hash: [u8; 32],
+}
+
+/// A device registry was merged into the primary one and closed
+#[event]
+pub struct DeviceRetired {
+    pub owner: Pubkey,
+    pub device_id_hash: [u8; 32],
+    pub merged_records: u16,
+}
+
+/// A nonce registry was grown to hold more history and fraud records
+#[event]
+pub struct RegistryGrown {
+    pub owner: Pubkey,
+    pub device_id_hash: [u8; 32],
+    pub growth_steps: u8,
+    pub bundle_history_capacity: u16,
+    pub fraud_record_capacity: u16,
+}
+
+/// A nonce registry was merged into another and closed
+#[event]
+pub struct RegistriesMerged {
+    pub source_owner: Pubkey,
+    pub source_device_id_hash: [u8; 32],
+    pub destination_owner: Pubkey,
+    pub destination_device_id_hash: [u8; 32],
+    pub merged_records: u16,
+    pub last_nonce: u64,
+}
+
+/// Copy of the receipt returned by `settle_offline_payment`, for log-based indexers
+#[event]
+pub struct SettlementReceiptIssued {
+    pub receipt: SettlementReceipt,
+}
+
+/// A Token-2022 transfer fee was withheld from a transfer into or out of an escrow
+#[event]
+pub struct TransferFeeWithheld {
+    pub owner: Pubkey,
+    /// The owner for funding, the merchant for settlement payouts
+    pub counterparty: Pubkey,
+    pub gross: u64,
+    pub net: u64,
+    pub fee: u64,
+}
+
+#[event]
+pub struct EscrowFunded {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub new_balance: u64,
+    pub escrow_token_account: Pubkey,
+}
+
+#[event]
+pub struct PaymentSettled {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    pub bundle_id: String,
+    pub order_ref: [u8; 16],
+    /// Donated to the payer's charity on top of `amount`; zero when skipped
+    pub roundup: u64,
+    /// Escrow state after this settlement, so each event is enough to rebuild balances
+    pub escrow_balance: u64,
+    pub total_spent: u64,
+    /// Sequence number of this settlement on the escrow, starting at 1
+    pub settlement_count: u64,
+    /// Token account the merchant was paid into and the vault it was paid from
+    pub merchant_token_account: Pubkey,
+    pub escrow_token_account: Pubkey,
+}
+
+/// Full route of a `settle_multihop`: payer -> runner -> merchant
+#[event]
+pub struct MultihopSettled {
+    pub payer: Pubkey,
+    pub runner: Pubkey,
+    pub merchant: Pubkey,
+    /// Paid to the merchant, before the protocol fee
+    pub amount: u64,
+    pub hop_fee: u64,
+    pub fee: u64,
+    pub first_bundle_hash: [u8; 32],
+    pub second_bundle_hash: [u8; 32],
+    pub payer_nonce: u64,
+    pub runner_nonce: u64,
+}
+
+/// Outcome of a `settle_netted`; the individual bundles are reported by their
+/// own `PaymentSettled` events
+#[event]
+pub struct NettedSettlement {
+    pub party_a: Pubkey,
+    pub party_b: Pubkey,
+    pub gross_a_to_b: u64,
+    pub gross_b_to_a: u64,
+    pub fees: u64,
+    /// Moved between the vaults after fees
+    pub net_amount: u64,
+    pub net_payer: Pubkey,
+}
+
+#[event]
+pub struct BatchSettlementProcessed {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub item_count: u8,
+    pub settled_count: u8,
+    pub settled_mask: u32,
+    pub total_settled: u64,
+}
+
+#[event]
+pub struct InvoiceCreated {
+    pub invoice: Pubkey,
+    pub merchant: Pubkey,
+    pub invoice_id: [u8; 16],
+    pub mode: InvoiceMode,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct InvoiceClosed {
+    pub invoice: Pubkey,
+    pub merchant: Pubkey,
+    pub closed_by: Pubkey,
+    pub keeper_cut: u64,
+}
+
+#[event]
+pub struct InvoicePaymentApplied {
+    pub invoice: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub amount_paid: u64,
+    pub status: InvoiceStatus,
+}
+
+/// `referral_reward` of `fee` went to the referrer, `verifier_fee` to the
+/// verifier, the rest to the treasury
+#[event]
+pub struct SettlementFeeCollected {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    /// Final fee after clamping to the configured floor and ceiling
+    pub fee: u64,
+    pub referrer: Pubkey,
+    pub referral_reward: u64,
+    /// `Payer` when the fee was charged to the escrow on top of the payment
+    /// rather than deducted from the merchant's payout
+    pub fee_payer: FeePayer,
+    /// Key whose proof backed the bundle; default when none did
+    pub verifier: Pubkey,
+    pub verifier_fee: u64,
+}
+
+#[event]
+pub struct CashbackProgramUpdated {
+    pub merchant: Pubkey,
+    pub vault: Pubkey,
+    pub rate_bps: u16,
+}
+
+#[event]
+pub struct CashbackPaid {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub credited_to_escrow: bool,
+}
+
+/// Settlement went through without cashback because the vault could not cover it
+#[event]
+pub struct CashbackUnderfunded {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub owed: u64,
+    pub available: u64,
+}
+
+#[event]
+pub struct BundleHistoryRecorded {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub nonce: u64,
+    pub settled_at: i64,
+    pub order_ref: [u8; 16],
+    pub settlement_index: u64,
+}
+
+#[event]
+pub struct EscrowFlaggedDormant {
+    pub owner: Pubkey,
+    pub last_activity_at: i64,
+    pub sweepable_at: i64,
+}
+
+#[event]
+pub struct EscrowReactivated {
+    pub owner: Pubkey,
+}
+
+#[event]
+pub struct DormantEscrowSwept {
+    pub owner: Pubkey,
+    pub custodial_token_account: Pubkey,
+    pub amount: u64,
+    pub remaining_balance: u64,
+}
+
+#[event]
+pub struct FraudEvidenceSubmitted {
+    pub payer: Pubkey,
+    pub reporter: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub conflicting_hash: [u8; 32],
+    pub reason: FraudReason,
+    pub reported_at: i64,
+    /// Order reference of the disputed settlement, zeroed when it had none
+    pub order_ref: [u8; 16],
+}
+
+#[event]
+pub struct CounterEvidenceSubmitted {
+    pub payer: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub conflicting_hash: [u8; 32],
+    pub evidence_hash: [u8; 32],
+    pub statement: [u8; COUNTER_STATEMENT_LEN],
+    pub submitted_at: i64,
+}
+
+#[event]
+pub struct ChargebackRequested {
+    pub chargeback: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub settlement_index: u64,
+    pub reason: ChargebackReason,
+    pub requested_at: i64,
+    pub respond_by: i64,
+}
+
+#[event]
+pub struct ChargebackResponded {
+    pub chargeback: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub evidence_hash: [u8; 32],
+    pub uri: [u8; CHARGEBACK_URI_LEN],
+    pub responded_at: i64,
+}
+
+#[event]
+pub struct ChargebackResolved {
+    pub chargeback: Pubkey,
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub outcome: ChargebackStatus,
+    pub resolved_by: Pubkey,
+    /// Arbiters whose quorum ruled; empty for a chargeback left unanswered
+    pub arbiters: Vec<Pubkey>,
+    pub resolved_at: i64,
+}
+
+#[event]
+pub struct FraudRecordsPruned {
+    pub payer: Pubkey,
+    pub authority: Pubkey,
+    pub older_than: i64,
+    pub pruned: u16,
+    /// Records before the cutoff left in the registry because their dispute is open
+    pub still_open: u16,
+    pub remaining: u16,
+    pub total_archived: u64,
+}
+
+#[event]
+pub struct EscrowWithdrawn {
+    pub owner: Pubkey,
+    pub amount: u64,
+    pub remaining_balance: u64,
+    pub escrow_token_account: Pubkey,
+}
+
+#[event]
+pub struct AllowanceGranted {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub expires_at: i64,
+}
+
+#[event]
+pub struct AllowanceRevoked {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub remaining: u64,
+}
+
+#[event]
+pub struct DelegatedSpenderGranted {
+    pub owner: Pubkey,
+    pub program: Pubkey,
+    pub cap: u64,
+    pub period_secs: i64,
+}
+
+#[event]
+pub struct DelegatedSpenderRevoked {
+    pub owner: Pubkey,
+    pub program: Pubkey,
+}
+
+#[event]
+pub struct DelegatedSpend {
+    pub owner: Pubkey,
+    pub program: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub spent_in_period: u64,
+}
+
+#[event]
+pub struct CourierPaid {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub courier: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub fee: u64,
+}
+
+#[event]
+pub struct GuaranteeGranted {
+    pub guarantor: Pubkey,
+    pub payer: Pubkey,
+    pub max_exposure: u64,
+}
+
+#[event]
+pub struct GuaranteeRevoked {
+    pub guarantor: Pubkey,
+    pub payer: Pubkey,
+    pub drawn: u64,
+}
+
+#[event]
+pub struct GuaranteeDrawn {
+    pub payer: Pubkey,
+    pub guarantor: Pubkey,
+    pub merchant: Pubkey,
+    /// Portion of the settlement covered by the payer's own escrow
+    pub payer_amount: u64,
+    /// Portion drawn from the guarantor's escrow
+    pub guarantor_amount: u64,
+    pub drawn: u64,
+    pub max_exposure: u64,
+}
+
+#[event]
+pub struct CreditLineOpened {
+    pub underwriter: Pubkey,
+    pub payer: Pubkey,
+    pub vault: Pubkey,
+    pub limit: u64,
+    pub fee_bps: u16,
+    pub min_reputation: i32,
+}
+
+#[event]
+pub struct CreditLineFrozen {
+    pub underwriter: Pubkey,
+    pub payer: Pubkey,
+    pub frozen: bool,
+}
 
-    #[account(mut)]
-    pub owner_token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct CreditDrawn {
+    pub payer: Pubkey,
+    pub underwriter: Pubkey,
+    pub merchant: Pubkey,
+    /// Portion of the settlement covered by the payer's own escrow
+    pub payer_amount: u64,
+    /// Portion drawn from the credit vault
+    pub credit_amount: u64,
+    /// Underwriter fee added to the debt
+    pub fee: u64,
+    /// Debt outstanding after the draw
+    pub debt: u64,
+}
 
-    #[account(
-        mut,
-        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct CreditRepaid {
+    pub payer: Pubkey,
+    pub underwriter: Pubkey,
+    pub amount: u64,
+    pub debt: u64,
+}
 
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct CreditVaultWithdrawn {
+    pub underwriter: Pubkey,
+    pub payer: Pubkey,
+    pub amount: u64,
 }
 
-#[derive(Accounts)]
-pub struct SettlePayment<'info> {
-    #[account(
-        mut,
-        seeds = [b"escrow", payer.key().as_ref()],
-        bump = escrow_account.bump,
-        has_one = owner @ BeamError::InvalidOwner
-    )]
-    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+#[event]
+pub struct PermitGranted {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub total_cap: u64,
+    pub per_pull_cap: u64,
+    pub expires_at: i64,
+}
 
-    /// CHECK: Owner from escrow account
-    pub owner: UncheckedAccount<'info>,
+#[event]
+pub struct PermitPulled {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    /// What the merchant may still pull under the permit
+    pub remaining: u64,
+    pub expires_at: i64,
+}
 
-    /// CHECK: Payer who made offline payment
-    pub payer: Signer<'info>,
+#[event]
+pub struct PermitRevoked {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub remaining: u64,
+}
 
-    /// CHECK: Merchant receiving payment
-    pub merchant: UncheckedAccount<'info>,
+#[event]
+pub struct PermitClosed {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub pulled: u64,
+}
 
-    #[account(
-        mut,
-        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct AllowanceDrawn {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub fee: u64,
+    pub remaining: u64,
+}
 
-    #[account(mut)]
-    pub merchant_token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct EscrowsConsolidated {
+    pub owner: Pubkey,
+    pub source_count: u8,
+    pub amount_moved: u64,
+    pub new_balance: u64,
+}
 
-    #[account(
-        mut,
-        seeds = [b"nonce", payer.key().as_ref()],
-        bump = nonce_registry.bump,
-        has_one = owner @ BeamError::InvalidOwner
-    )]
-    pub nonce_registry: Account<'info, NonceRegistry>,
+/// `previous_last_nonce` is the higher of the registry's and the escrow's marks
+#[event]
+pub struct NonceCorrected {
+    pub owner: Pubkey,
+    pub previous_last_nonce: u64,
+    pub released_nonces: Vec<u64>,
+}
 
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct NonceReserved {
+    pub owner: Pubkey,
+    pub nonce: u64,
 }
 
-#[derive(Accounts)]
-pub struct InitializeNonceRegistry<'info> {
-    #[account(mut)]
-    pub payer: Signer<'info>,
-    #[account(
-        init,
-        payer = payer,
-        seeds = [b"nonce", payer.key().as_ref()],
-        bump,
-        space = 8 + NonceRegistry::INIT_SPACE
-    )]
-    pub nonce_registry: Account<'info, NonceRegistry>,
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct NonceReleased {
+    pub owner: Pubkey,
+    pub nonce: u64,
 }
 
-#[derive(Accounts)]
-pub struct WithdrawEscrow<'info> {
-    #[account(
-        mut,
-        seeds = [b"escrow", owner.key().as_ref()],
-        bump = escrow_account.bump,
-        has_one = owner
-    )]
-    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+#[event]
+pub struct EscrowLabelUpdated {
+    pub owner: Pubkey,
+    pub label: [u8; 32],
+}
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+#[event]
+pub struct RentPayerChanged {
+    pub account: Pubkey,
+    pub previous: Pubkey,
+    pub rent_payer: Pubkey,
+}
 
-    #[account(mut)]
-    pub owner_token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct OwnerKeyRotated {
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub escrow_balance: u64,
+    pub stake_locked: u64,
+    pub grace_until: i64,
+}
 
-    #[account(
-        mut,
-        constraint = escrow_token_account.owner == escrow_account.key() @ BeamError::InvalidEscrowTokenAccount
-    )]
-    pub escrow_token_account: Account<'info, TokenAccount>,
+#[event]
+pub struct BackingAccountAdded {
+    pub owner: Pubkey,
+    pub token_account: Pubkey,
+}
 
-    pub token_program: Program<'info, Token>,
+#[event]
+pub struct BackingAccountRemoved {
+    pub owner: Pubkey,
+    pub token_account: Pubkey,
 }
 
-#[derive(Accounts)]
-pub struct ReportFraud<'info> {
-    #[account(
-        mut,
-        seeds = [b"nonce", payer.key().as_ref()],
-        bump = nonce_registry.bump
-    )]
-    pub nonce_registry: Account<'info, NonceRegistry>,
+#[event]
+pub struct SolvencyVerified {
+    pub owner: Pubkey,
+    pub escrow_balance: u64,
+    pub backing_total: u64,
+}
 
-    #[account(
-        mut,
-        seeds = [b"escrow", payer.key().as_ref()],
-        bump = escrow_account.bump
-    )]
-    pub escrow_account: Account<'info, OfflineEscrowAccount>,
+#[event]
+pub struct AggregateKeyRegistered {
+    pub owner: Pubkey,
+    pub share_count: u8,
+    pub total_weight: u16,
+    /// Zero when the key was unregistered
+    pub threshold: u16,
+}
 
-    /// CHECK: Verified against nonce registry owner
-    pub payer: UncheckedAccount<'info>,
+#[event]
+pub struct RoundupConfigUpdated {
+    pub owner: Pubkey,
+    pub enabled: bool,
+    pub unit: u64,
+    pub charity_token_account: Pubkey,
+}
 
-    pub reporter: Signer<'info>,
+/// Top-up crossing `threshold`, one per watcher, so a merchant's indexer can filter
+/// on `watcher` alone
+#[event]
+pub struct EscrowFundedForWatcher {
+    pub watcher: Pubkey,
+    pub owner: Pubkey,
+    pub threshold: u64,
+    pub new_balance: u64,
 }
 
-#[derive(Accounts)]
-pub struct MigrateEscrow<'info> {
-    /// CHECK: Manual validation and reallocation
-    #[account(
-        mut,
-        seeds = [b"escrow", owner.key().as_ref()],
-        bump,
-    )]
-    pub escrow_account: AccountInfo<'info>,
+#[event]
+pub struct AmountSanityCheckUpdated {
+    pub owner: Pubkey,
+    pub enabled: bool,
+}
 
-    #[account(mut)]
-    pub owner: Signer<'info>,
+/// `threshold` is `None` when the watcher was removed
+#[event]
+pub struct EscrowWatcherUpdated {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub threshold: Option<u64>,
+}
 
-    pub system_program: Program<'info, System>,
+#[event]
+pub struct MerchantLimitUpdated {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub limit: u64,
 }
 
-#[account]
-#[derive(InitSpace)]
-pub struct OfflineEscrowAccount {
+#[event]
+pub struct BeneficiaryUpdated {
     pub owner: Pubkey,
-    pub escrow_token_account: Pubkey,  // Store token account address
-    pub escrow_balance: u64,
-    pub last_nonce: u64,
-    pub reputation_score: u16,
-    pub total_spent: u64,
-    pub created_at: i64,
-    pub bump: u8,
-    // Phase 1.3: Stake slashing fields
-    pub stake_locked: u64,        // Funds locked as penalty for fraud
-    pub fraud_count: u32,          // Number of detected fraud attempts
-    pub last_fraud_timestamp: i64, // When last fraud was detected
+    pub beneficiary: Pubkey,
+    pub inactivity_period: i64,
 }
 
 #[event]
-pub struct EscrowInitialized {
+pub struct BeneficiaryClaimStarted {
     pub owner: Pubkey,
-    pub initial_balance: u64,
+    pub beneficiary: Pubkey,
+    pub notice_ends_at: i64,
 }
 
 #[event]
-pub struct EscrowFunded {
+pub struct BeneficiaryClaimCancelled {
     pub owner: Pubkey,
-    pub amount: u64,
-    pub new_balance: u64,
+    pub beneficiary: Pubkey,
 }
 
 #[event]
-pub struct PaymentSettled {
-    pub payer: Pubkey,
-    pub merchant: Pubkey,
+pub struct BeneficiaryClaimCompleted {
+    pub owner: Pubkey,
+    pub beneficiary: Pubkey,
     pub amount: u64,
-    pub nonce: u64,
-    pub bundle_id: String,
 }
 
 #[event]
-pub struct BundleHistoryRecorded {
-    pub payer: Pubkey,
-    pub merchant: Pubkey,
-    pub bundle_hash: [u8; 32],
-    pub amount: u64,
-    pub nonce: u64,
-    pub settled_at: i64,
+pub struct SunsetDeclared {
+    pub admin: Pubkey,
+    pub declared_at: i64,
+    pub effective_at: i64,
 }
 
 #[event]
-pub struct FraudEvidenceSubmitted {
-    pub payer: Pubkey,
-    pub reporter: Pubkey,
-    pub bundle_hash: [u8; 32],
-    pub conflicting_hash: [u8; 32],
-    pub reason: FraudReason,
-    pub reported_at: i64,
+pub struct SunsetCancelled {
+    pub admin: Pubkey,
+    pub cancelled_at: i64,
 }
 
 #[event]
-pub struct EscrowWithdrawn {
+pub struct StakeUnlocked {
     pub owner: Pubkey,
+    pub admin: Pubkey,
     pub amount: u64,
-    pub remaining_balance: u64,
+}
+
+#[event]
+pub struct FraudPenaltyUpdated {
+    pub admin: Pubkey,
+    pub reason: FraudReason,
+    pub penalty_bps: u16,
 }
 
 #[event]
 pub struct FraudPenaltyApplied {
     pub payer: Pubkey,
     pub slashed_amount: u64,
-    pub new_reputation: u16,
+    pub new_reputation: i32,
+    pub fraud_count: u32,
+    pub reason: FraudReason,
+}
+
+#[event]
+pub struct IdentityRegistered {
+    pub identity: Pubkey,
+}
+
+#[event]
+pub struct IdentityLinked {
+    pub identity: Pubkey,
+    pub owner: Pubkey,
+    pub reputation_score: i32,
+    pub linked_escrows: u16,
+}
+
+#[event]
+pub struct IdentityBanUpdated {
+    pub identity: Pubkey,
+    pub banned: bool,
+}
+
+/// Fraud on `owner`'s escrow was counted against its linked identity
+#[event]
+pub struct IdentityFraudRecorded {
+    pub identity: Pubkey,
+    pub owner: Pubkey,
+    pub reputation_score: i32,
     pub fraud_count: u32,
 }
 
@@ -681,4 +10647,420 @@ pub enum BeamError {
     Underflow,
     #[msg("Insufficient funds for slash penalty")]
     InsufficientFundsForSlash,
+    #[msg("Batch must contain between 1 and MAX_BATCH_SIZE items")]
+    InvalidBatchSize,
+    #[msg("Rotated owner key is past its settlement grace period")]
+    RotationGraceExpired,
+    #[msg("Unauthorized")]
+    Unauthorized,
+    #[msg("Invalid config value")]
+    InvalidConfig,
+    #[msg("Settlement exceeds the balance that has cleared the funding lockup")]
+    FundsStillLocked,
+    #[msg("Invalid beneficiary or inactivity period")]
+    InvalidBeneficiary,
+    #[msg("Owner has been active within the inactivity period")]
+    OwnerStillActive,
+    #[msg("A beneficiary claim is already pending")]
+    BeneficiaryClaimPending,
+    #[msg("No beneficiary claim is pending")]
+    NoBeneficiaryClaim,
+    #[msg("Beneficiary notice window has not ended")]
+    BeneficiaryNoticeActive,
+    #[msg("Escrow still has locked stake")]
+    StakeStillLocked,
+    #[msg("Export start index is past the end of the history")]
+    InvalidExportRange,
+    #[msg("Invoice belongs to a different merchant")]
+    InvoiceMerchantMismatch,
+    #[msg("Invoice is already paid")]
+    InvoiceAlreadyPaid,
+    #[msg("Payment exceeds the invoice amount")]
+    InvoiceOverpaid,
+    #[msg("Payment is below the invoice amount")]
+    InvoiceUnderpaid,
+    #[msg("Settlement exceeds the rolling window cap")]
+    RollingLimitExceeded,
+    #[msg("Invoice has expired")]
+    InvoiceExpired,
+    #[msg("Invoice has not expired")]
+    InvoiceNotExpired,
+    #[msg("Invoice is neither paid nor expired")]
+    InvoiceStillOpen,
+    #[msg("Invoice has a settlement under dispute")]
+    InvoiceDisputed,
+    #[msg("Bundle did not pay this invoice")]
+    InvoiceBundleMismatch,
+    #[msg("Invoice has reached its maximum number of settlements")]
+    InvoicePaymentLimitReached,
+    #[msg("Attestation proof has an all-zero nonce, root or signature")]
+    MalformedAttestation,
+    #[msg("Cashback rate must be at most MAX_CASHBACK_BPS")]
+    InvalidCashbackRate,
+    #[msg("Cashback program, vault or destination does not match the settlement")]
+    CashbackMismatch,
+    #[msg("Settlement exceeds the owner's cap for this merchant")]
+    MerchantLimitExceeded,
+    #[msg("No free merchant limit slot; remove a limit first")]
+    MerchantLimitTableFull,
+    #[msg("Escrow owner cannot refer themselves")]
+    SelfReferral,
+    #[msg("Treasury token account missing or not owned by the config treasury")]
+    InvalidTreasuryAccount,
+    #[msg("Referrer token account does not belong to the escrow's referrer")]
+    InvalidReferrerAccount,
+    #[msg("The attested settlement deadline has passed")]
+    SettlementDeadlinePassed,
+    #[msg("Destination must be a different escrow with the same mint")]
+    InvalidEscrowTransfer,
+    #[msg("Allowance has expired")]
+    AllowanceExpired,
+    #[msg("Draw exceeds the remaining allowance")]
+    AllowanceExceeded,
+    #[msg("Consolidation sources must be 1-3 distinct escrows passed as escrow, vault, owner")]
+    InvalidConsolidation,
+    #[msg("Too many reserved nonces; settle or release one first")]
+    PendingNonceLimitReached,
+    #[msg("Nonce is not reserved")]
+    NonceNotPending,
+    #[msg("Label must be zero-padded UTF-8 without control characters")]
+    InvalidLabel,
+    #[msg("Escrow reputation is exhausted; settlements are blocked")]
+    ReputationExhausted,
+    #[msg("Settlements are halted by the admin")]
+    SettlementsHalted,
+    #[msg("Too many pre-authorized bundles")]
+    PreauthorizationTableFull,
+    #[msg("No pre-authorization for this bundle")]
+    PreauthorizationNotFound,
+    #[msg("Amount exceeds the balance not reserved for outstanding bundles")]
+    FundsReserved,
+    #[msg("Invalid reservation amount or expiry")]
+    InvalidReservation,
+    #[msg("Protocol fee would consume the whole payment")]
+    FeeExceedsAmount,
+    #[msg("Too many pending liabilities registered")]
+    PendingLiabilityLimitReached,
+    #[msg("Withdrawal would leave less than the registered liabilities")]
+    OutstandingLiabilities,
+    #[msg("Multihop route must link distinct payer, runner and merchant accounts")]
+    InvalidMultihopRoute,
+    #[msg("Guarantee accounts do not match the guarantor escrow")]
+    GuaranteeMismatch,
+    #[msg("Settlement would exceed the guarantor's maximum exposure")]
+    GuaranteeExposureExceeded,
+    #[msg("Courier fee exceeds the configured maximum")]
+    CourierFeeTooHigh,
+    #[msg("Courier token account does not match the committed courier")]
+    CourierMismatch,
+    #[msg("Withdrawals are delayed after a recent fraud report")]
+    WithdrawalDelayedDueToFraud,
+    #[msg("No fallback verifier is registered")]
+    FallbackVerifierUnavailable,
+    #[msg("Amount exceeds the fallback verifier cap")]
+    FallbackCapExceeded,
+    #[msg("Heartbeat counter must increase")]
+    StaleHeartbeatCounter,
+    #[msg("Verifier heartbeat is missing or too old")]
+    VerifierHeartbeatStale,
+    #[msg("Not enough configured arbiters signed")]
+    ArbiterQuorumNotMet,
+    #[msg("Reputation adjustment needs a non-zero delta within bounds and a reason code")]
+    InvalidReputationAdjustment,
+    #[msg("Manual reputation adjustments for this escrow exceed the period cap")]
+    ReputationAdjustmentCapExceeded,
+    #[msg("Escrow already has the maximum number of backing token accounts")]
+    BackingAccountLimitReached,
+    #[msg("Backing token account still holds funds")]
+    BackingAccountNotEmpty,
+    #[msg("Remove the escrow's extra backing token accounts first")]
+    BackingAccountsRemain,
+    #[msg("Backing token accounts hold less than the escrow balance")]
+    EscrowInsolvent,
+    #[msg("Fraud evidence does not match the claimed reason")]
+    FraudEvidenceMismatch,
+    #[msg("No sunset is in effect")]
+    SunsetNotActive,
+    #[msg("A sunset has already been declared")]
+    SunsetAlreadyDeclared,
+    #[msg("Settlement was not invoked by the delegated program")]
+    DelegationNotInvoked,
+    #[msg("Settlement exceeds the delegated spender's cap for this period")]
+    DelegationCapExceeded,
+    #[msg("Vault balance changed by a different amount than was booked")]
+    UnexpectedVaultDelta,
+    #[msg("Nonce registry does not belong to the bundle's device")]
+    DeviceRegistryMismatch,
+    #[msg("Device settlements require the owner's primary nonce registry")]
+    PrimaryRegistryRequired,
+    #[msg("Device id hash must be non-zero")]
+    InvalidDeviceId,
+    #[msg("Registry still has reserved nonces or pending liabilities")]
+    DeviceRegistryBusy,
+    #[msg("Identity is banned from opening escrows")]
+    IdentityBanned,
+    #[msg("Identity reputation is below the configured minimum")]
+    IdentityReputationTooLow,
+    #[msg("A linked identity account is required")]
+    IdentityRequired,
+    #[msg("Escrow is already linked to an identity")]
+    IdentityAlreadyLinked,
+    #[msg("A registry can't be merged into itself")]
+    InvalidRegistryMerge,
+    #[msg("Source registry has fraud reports still under dispute")]
+    RegistryDisputed,
+    #[msg("Credit line accounts do not match the escrow")]
+    CreditLineMismatch,
+    #[msg("Credit line is frozen")]
+    CreditLineFrozen,
+    #[msg("Escrow does not qualify for this credit line")]
+    CreditLineIneligible,
+    #[msg("Draw would take the debt past the credit limit")]
+    CreditLimitExceeded,
+    #[msg("Escrow owes credit-line debt")]
+    CreditDebtOutstanding,
+    #[msg("Deposits must repay the credit line first")]
+    CreditRepaymentRequired,
+    #[msg("Counter evidence needs a non-zero hash and a UTF-8 statement")]
+    InvalidCounterEvidence,
+    #[msg("No fraud record matches these hashes")]
+    FraudRecordNotFound,
+    #[msg("Counter evidence was already submitted for this record")]
+    CounterEvidenceExists,
+    #[msg("Round-up needs a non-zero unit and a charity account in the vault's mint")]
+    InvalidRoundupConfig,
+    #[msg("Escheatment is not configured")]
+    EscheatmentDisabled,
+    #[msg("Escrow is already flagged dormant")]
+    EscrowAlreadyDormant,
+    #[msg("Escrow is not flagged dormant")]
+    EscrowNotDormant,
+    #[msg("Dormant escrow is still within its notice period")]
+    EscheatNoticeActive,
+    #[msg("Token account is not the configured custodial account")]
+    InvalidCustodialAccount,
+    #[msg("Aggregate key shares are inconsistent or the threshold is unreachable")]
+    InvalidAggregateKey,
+    #[msg("Aggregate attestation is disabled")]
+    AggregateAttestationDisabled,
+    #[msg("Payer has no aggregate key registered")]
+    AggregateKeyNotRegistered,
+    #[msg("Voucher proofs are not accepted on this deployment")]
+    VoucherAttestationDisabled,
+    #[msg("Voucher validity window is empty, too long or combined with a deadline or fallback")]
+    InvalidValidityWindow,
+    #[msg("Voucher is not valid yet")]
+    VoucherNotYetValid,
+    #[msg("Voucher has expired")]
+    VoucherExpired,
+    #[msg("Escrow is already being processed by an instruction further up the call stack")]
+    Reentrancy,
+    #[msg("No closed fraud records were reported before the cutoff")]
+    NoFraudRecordsToPrune,
+    #[msg("Fraud archive has no room for these records")]
+    FraudArchiveFull,
+    #[msg("Chargebacks are not enabled")]
+    ChargebacksDisabled,
+    #[msg("The chargeback window for this settlement has closed")]
+    ChargebackWindowClosed,
+    #[msg("Chargeback evidence needs a non-zero hash and a UTF-8 reference")]
+    InvalidChargebackEvidence,
+    #[msg("Chargeback is no longer awaiting the merchant's response")]
+    ChargebackNotAwaitingResponse,
+    #[msg("The merchant's response window has closed")]
+    ChargebackResponseWindowClosed,
+    #[msg("The merchant may still respond to this chargeback")]
+    ChargebackResponseWindowOpen,
+    #[msg("Only chargebacks the merchant responded to go to the arbiters")]
+    ChargebackNotResponded,
+    #[msg("Chargeback has not been resolved")]
+    ChargebackNotResolved,
+    #[msg("Watcher is the default key or not registered on this escrow")]
+    InvalidWatcher,
+    #[msg("Escrow already has the maximum number of watchers")]
+    WatcherTableFull,
+    #[msg("Proposed admin must be a new, non-default key")]
+    InvalidPendingAdmin,
+    #[msg("No admin transfer is pending")]
+    NoPendingAdmin,
+    #[msg("Signer is not the pending admin")]
+    NotPendingAdmin,
+    #[msg("Amount exceeds the plausible maximum for this mint; check it is in base units")]
+    AmountImplausiblyLarge,
+    #[msg("Config already holds the maximum number of mint amount caps")]
+    AmountCapTableFull,
+    #[msg("Nonce corrections are disabled or the escrow's correction window has passed")]
+    NonceCorrectionWindowClosed,
+    #[msg("Nonce state can't be corrected once the escrow has settled")]
+    NonceCorrectionLocked,
+    #[msg("Nonce registry is in a legacy layout; migrate it first")]
+    RegistryNotMigrated,
+    #[msg("Nonce registry has already been grown the maximum number of times")]
+    RegistryAtMaxCapacity,
+    #[msg("Withdrawal would leave less than the reserve held for open fraud disputes")]
+    DisputeReserveRequired,
+    #[msg("Bundle's condition has not been confirmed by the condition oracle")]
+    ConditionNotMet,
+    #[msg("Verifier fee account doesn't belong to the bundle's verifier or mint")]
+    InvalidVerifierFeeAccount,
+    #[msg("Verifier has no fees to claim")]
+    NoVerifierFeesToClaim,
+    #[msg("Settlements are held; pass a new settlement hold and its vault")]
+    SettlementHoldRequired,
+    #[msg("Settlement hold or its vault doesn't match")]
+    InvalidSettlementHold,
+    #[msg("Held settlements must be settled one at a time")]
+    SettlementHoldUnsupported,
+    #[msg("Held funds can't be released before the hold period ends")]
+    SettlementHoldActive,
+    #[msg("Hold period is over; the held funds can only be released")]
+    SettlementHoldReleasable,
+    #[msg("Owner must sign unless custodial onboarding is enabled and nothing is funded")]
+    OwnerSignatureRequired,
+    #[msg("Account doesn't receive this account's rent")]
+    InvalidRentRecipient,
+    #[msg("Rent payer still exists; its refund can only be redirected once it is gone")]
+    RentPayerStillExists,
+    #[msg("Permit caps must be non-zero with the per-pull cap within the total")]
+    InvalidPermit,
+    #[msg("Permit has expired")]
+    PermitExpired,
+    #[msg("Pull exceeds the permit's per-pull cap")]
+    PermitPullCapExceeded,
+    #[msg("Pull exceeds what remains of the permit")]
+    PermitCapExceeded,
+    #[msg("Permit has been revoked by the payer")]
+    PermitRevoked,
+    #[msg("Permit is neither revoked nor expired")]
+    PermitStillActive,
+    #[msg("Too many permits to summarize in one call")]
+    TooManyPermits,
+    #[msg("Compliance registry has not approved both payer and merchant")]
+    ComplianceCheckFailed,
+    #[msg("Instruction is unavailable while the compliance check is on")]
+    ComplianceCheckUnsupported,
+    #[msg("Held settlement is disputed and waits for the arbiters")]
+    SettlementHoldDisputed,
+    #[msg("Held settlement was never disputed")]
+    SettlementHoldNotDisputed,
+    #[msg("Fallback verifier must hold at least the configured minimum bond")]
+    VerifierBondRequired,
+    #[msg("Verifier bond vault or token account does not match the bond")]
+    InvalidVerifierBond,
+    #[msg("Verifier has deregistered its bond")]
+    VerifierDeregistered,
+    #[msg("An active verifier key cannot deregister its bond")]
+    VerifierStillActive,
+    #[msg("Verifier bond is still inside its cooldown")]
+    VerifierBondLocked,
+    #[msg("No settled double-spend report for this bundle")]
+    FraudNotUpheld,
+    #[msg("Attestation was not signed by this verifier for the bundle")]
+    VerifierDidNotAttest,
+    #[msg("Nothing left to slash from this bond")]
+    NothingToSlash,
+    #[msg("Devices are still enrolled under this registry; retire them first")]
+    DevicesEnrolled,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn escrow_with_score(score: i32) -> OfflineEscrowAccount {
+        let zeroed = vec![0u8; OfflineEscrowAccount::INIT_SPACE];
+        let mut escrow = OfflineEscrowAccount::deserialize(&mut zeroed.as_slice()).unwrap();
+        escrow.reputation_score = score;
+        escrow
+    }
+
+    #[test]
+    fn reputation_delta_saturates_at_the_upper_cap() {
+        let mut escrow = escrow_with_score(MAX_REPUTATION - 5);
+        assert_eq!(escrow.apply_reputation_delta(10), MAX_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(1), MAX_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(-1), MAX_REPUTATION - 1);
+    }
+
+    #[test]
+    fn reputation_delta_saturates_at_the_i32_bound() {
+        let mut escrow = escrow_with_score(MAX_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(i32::MAX), MAX_REPUTATION);
+
+        let mut escrow = escrow_with_score(MIN_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(i32::MIN), MIN_REPUTATION);
+    }
+
+    #[test]
+    fn reputation_delta_saturates_at_the_floor() {
+        let mut escrow = escrow_with_score(MIN_REPUTATION + 5);
+        assert_eq!(escrow.apply_reputation_delta(-10), MIN_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(-1), MIN_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(1), MIN_REPUTATION + 1);
+    }
+
+    #[test]
+    fn reputation_delta_moves_freely_within_bounds() {
+        let mut escrow = escrow_with_score(INITIAL_REPUTATION);
+        assert_eq!(escrow.apply_reputation_delta(-150), INITIAL_REPUTATION - 150);
+        assert_eq!(escrow.apply_reputation_delta(150), INITIAL_REPUTATION);
+    }
+
+    #[test]
+    fn registry_capacity_counts_steps_of_registries_without_a_rent_payer() {
+        let rent_payer_len = std::mem::size_of::<Pubkey>() + std::mem::size_of::<u16>();
+        for steps in 0..=MAX_REGISTRY_GROWTH_STEPS {
+            let len = RegistryCapacity::account_len(steps);
+            assert_eq!(RegistryCapacity::of_len(len).growth_steps, steps);
+            assert_eq!(RegistryCapacity::of_len(len - rent_payer_len).growth_steps, steps);
+        }
+    }
+
+    #[test]
+    fn device_and_condition_extras_commit_to_different_roots() {
+        use crate::attestation::{compute_attestation_root, compute_voucher_root, ValidityWindow};
+
+        let payer = Pubkey::new_unique();
+        let merchant = Pubkey::new_unique();
+        let extra = [7u8; 32];
+        let nonce = [1u8; 32];
+        let attestation = |device: Option<&[u8; 32]>, condition: Option<&[u8; 32]>| {
+            compute_attestation_root(
+                AttestationRole::Payer,
+                "tagged-extras",
+                &payer,
+                &merchant,
+                1_000_000,
+                1,
+                &nonce,
+                1_700_000_000,
+                None,
+                &[0u8; 16],
+                None,
+                device,
+                condition,
+                None,
+            )
+        };
+        assert_ne!(attestation(Some(&extra), None), attestation(None, Some(&extra)));
+
+        let window = ValidityWindow { valid_from: 0, valid_until: 60 };
+        let voucher = |device: Option<&[u8; 32]>, condition: Option<&[u8; 32]>| {
+            compute_voucher_root(
+                AttestationRole::Payer,
+                "tagged-extras",
+                &payer,
+                &merchant,
+                1_000_000,
+                1,
+                &nonce,
+                &window,
+                &[0u8; 16],
+                None,
+                device,
+                condition,
+            )
+        };
+        assert_ne!(voucher(Some(&extra), None), voucher(None, Some(&extra)));
+    }
 }
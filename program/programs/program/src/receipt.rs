@@ -0,0 +1,184 @@
+//! Opt-in proof-of-purchase receipts minted as Bubblegum compressed NFTs.
+//! Compiled only with the `receipt-nft` feature.
+//!
+//! A settlement opts in by appending, as remaining accounts:
+//!   [bubblegum program, tree config (mut), merkle tree (mut), receipt authority,
+//!    log wrapper, compression program, system program]
+//! The receipt authority is the `[b"receipt"]` PDA, which must be the tree's delegate.
+//! The receipt goes to the escrow owner; its URI carries the bundle hash, merchant,
+//! amount, settlement time and the escrow's settlement number, so they are covered
+//! by Bubblegum's metadata hash.
+//!
+//! A failed CPI aborts the whole transaction on Solana, so the mint cannot be made
+//! fallible after the fact. Instead every precondition we can check is verified first,
+//! and if any fails the receipt is skipped with `ReceiptMintFailed` and the payment
+//! still completes.
+
+use anchor_lang::prelude::*;
+use anchor_lang::solana_program::instruction::{AccountMeta, Instruction};
+use anchor_lang::solana_program::program::invoke_signed;
+use anchor_lang::solana_program::pubkey;
+
+pub const BUBBLEGUM_PROGRAM_ID: Pubkey = pubkey!("BGUMAp9Gq7iTEuizy4pqaxsTyUCBK68MDfK752saRPUY");
+pub const SPL_NOOP_PROGRAM_ID: Pubkey = pubkey!("noopb9bkMVfRPU8AsbpTUg8AQkHtKwMYZiFUjNRtMmV");
+pub const SPL_ACCOUNT_COMPRESSION_PROGRAM_ID: Pubkey =
+    pubkey!("cmtDvXumGCrqC1Age74AVPhSRVXJMd8PJS91L8KbNCK");
+pub const RECEIPT_AUTHORITY_SEED: &[u8] = b"receipt";
+
+const RECEIPT_ACCOUNT_COUNT: usize = 7;
+// sha256("global:mint_v1")[..8]
+const MINT_V1_DISCRIMINATOR: [u8; 8] = [145, 98, 192, 118, 184, 147, 118, 104];
+const RECEIPT_NAME: &str = "Beam Receipt";
+const RECEIPT_SYMBOL: &str = "BEAMRCPT";
+
+#[event]
+pub struct ReceiptMinted {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub merkle_tree: Pubkey,
+}
+
+#[event]
+pub struct ReceiptMintFailed {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub reason: String,
+}
+
+pub struct ReceiptDetails {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub settled_at: i64,
+    /// The settlement's `settlement_index`: the escrow's `settlement_count` after it
+    pub settlement_number: u64,
+}
+
+/// Mint a receipt for a completed settlement, or emit `ReceiptMintFailed` when the
+/// supplied accounts can't be used. `payer` signs for the mint; `owner` receives it.
+pub fn mint_settlement_receipt<'info>(
+    payer: &AccountInfo<'info>,
+    owner: &AccountInfo<'info>,
+    accounts: &[AccountInfo<'info>],
+    details: ReceiptDetails,
+) -> Result<()> {
+    let (authority, authority_bump) =
+        Pubkey::find_program_address(&[RECEIPT_AUTHORITY_SEED], &crate::ID);
+
+    if let Err(reason) = check_receipt_accounts(accounts, &authority) {
+        emit!(ReceiptMintFailed {
+            owner: details.owner,
+            merchant: details.merchant,
+            bundle_hash: details.bundle_hash,
+            reason: reason.to_string(),
+        });
+        return Ok(());
+    }
+
+    let [bubblegum, tree_config, merkle_tree, receipt_authority, log_wrapper, compression, system_program] =
+        &accounts[..RECEIPT_ACCOUNT_COUNT]
+    else {
+        unreachable!("length checked above");
+    };
+
+    let mut data = MINT_V1_DISCRIMINATOR.to_vec();
+    data.extend(metadata_args(&details));
+
+    let instruction = Instruction {
+        program_id: BUBBLEGUM_PROGRAM_ID,
+        accounts: vec![
+            AccountMeta::new(tree_config.key(), false),
+            AccountMeta::new_readonly(owner.key(), false),
+            AccountMeta::new_readonly(owner.key(), false),
+            AccountMeta::new(merkle_tree.key(), false),
+            AccountMeta::new_readonly(payer.key(), true),
+            AccountMeta::new_readonly(authority, true),
+            AccountMeta::new_readonly(log_wrapper.key(), false),
+            AccountMeta::new_readonly(compression.key(), false),
+            AccountMeta::new_readonly(system_program.key(), false),
+        ],
+        data,
+    };
+    invoke_signed(
+        &instruction,
+        &[
+            tree_config.clone(),
+            owner.clone(),
+            merkle_tree.clone(),
+            payer.clone(),
+            receipt_authority.clone(),
+            log_wrapper.clone(),
+            compression.clone(),
+            system_program.clone(),
+            bubblegum.clone(),
+        ],
+        &[&[RECEIPT_AUTHORITY_SEED, &[authority_bump]]],
+    )?;
+
+    emit!(ReceiptMinted {
+        owner: details.owner,
+        merchant: details.merchant,
+        bundle_hash: details.bundle_hash,
+        merkle_tree: merkle_tree.key(),
+    });
+
+    Ok(())
+}
+
+fn check_receipt_accounts(accounts: &[AccountInfo], authority: &Pubkey) -> std::result::Result<(), &'static str> {
+    if accounts.len() < RECEIPT_ACCOUNT_COUNT {
+        return Err("missing receipt accounts");
+    }
+    let bubblegum = &accounts[0];
+    if bubblegum.key() != BUBBLEGUM_PROGRAM_ID || !bubblegum.executable {
+        return Err("not the bubblegum program");
+    }
+    if *accounts[1].owner != BUBBLEGUM_PROGRAM_ID || !accounts[1].is_writable {
+        return Err("invalid tree config");
+    }
+    if *accounts[2].owner != SPL_ACCOUNT_COMPRESSION_PROGRAM_ID || !accounts[2].is_writable {
+        return Err("invalid merkle tree");
+    }
+    if accounts[3].key() != *authority {
+        return Err("invalid receipt authority");
+    }
+    if accounts[4].key() != SPL_NOOP_PROGRAM_ID
+        || accounts[5].key() != SPL_ACCOUNT_COMPRESSION_PROGRAM_ID
+        || accounts[6].key() != anchor_lang::system_program::ID
+    {
+        return Err("invalid supporting program");
+    }
+    Ok(())
+}
+
+/// Borsh encoding of Bubblegum's `MetadataArgs` for a non-fungible receipt
+fn metadata_args(details: &ReceiptDetails) -> Vec<u8> {
+    let hash_hex: String = details
+        .bundle_hash
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect();
+    let uri = format!(
+        "beam://receipt/{hash_hex}?merchant={}&amount={}&settled_at={}&number={}",
+        details.merchant, details.amount, details.settled_at, details.settlement_number
+    );
+
+    let mut data = Vec::with_capacity(256);
+    for text in [RECEIPT_NAME, RECEIPT_SYMBOL, uri.as_str()] {
+        data.extend((text.len() as u32).to_le_bytes());
+        data.extend(text.as_bytes());
+    }
+    data.extend(0u16.to_le_bytes()); // seller_fee_basis_points
+    data.push(0); // primary_sale_happened
+    data.push(0); // is_mutable
+    data.push(0); // edition_nonce: None
+    data.extend([1, 0]); // token_standard: Some(NonFungible)
+    data.push(0); // collection: None
+    data.push(0); // uses: None
+    data.push(0); // token_program_version: Original
+    data.extend(0u32.to_le_bytes()); // creators: []
+    data
+}
@@ -1,7 +1,184 @@
 use anchor_lang::prelude::*;
+use anchor_lang::solana_program::{hash, keccak};
+
+use crate::attestation::{
+    attested_deadline, payer_bundle_digest, signature_is_valid, AttestationProof, AttestationRole,
+    CourierCommitment, SettlementDeadline, SettlementEvidence, ShareSignature, MAX_ATTESTATION_AGE,
+};
+use crate::{BeamError, LiabilityCleared};
 
 pub const MAX_BUNDLE_HISTORY: usize = 32;
+pub const MAX_RECENT_HASHES: usize = 16;
 pub const MAX_FRAUD_RECORDS: usize = 16;
+/// Pruned fraud records a payer's `FraudArchive` holds
+pub const MAX_ARCHIVED_FRAUD_RECORDS: usize = 32;
+/// Nonces a payer can hold in reserve at once
+pub const MAX_PENDING_NONCES: usize = 8;
+/// Signed-but-unsettled bundles a payer can register as liabilities at once
+pub const MAX_PENDING_LIABILITIES: usize = 8;
+/// `FraudRecord` size before the counter-evidence fields were added
+const LEGACY_FRAUD_RECORD_LEN: usize = 32 + 32 + 32 + 8 + 1;
+/// Largest registry written before `BundleRecord::order_ref`; anything this size or
+/// smaller still uses the 88-byte history records
+pub const LEGACY_REGISTRY_MAX_LEN: usize = 8
+    + 32
+    + 8
+    + (4 + 16 * 32)
+    + (4 + MAX_BUNDLE_HISTORY * 88)
+    + (4 + MAX_FRAUD_RECORDS * LEGACY_FRAUD_RECORD_LEN)
+    + 1
+    + (4 + MAX_PENDING_NONCES * 8);
+/// Largest registry written before `BundleRecord::bundle_created_at`; anything this
+/// size or smaller (and above `LEGACY_REGISTRY_MAX_LEN`) uses 104-byte records
+pub const ORDER_REF_REGISTRY_MAX_LEN: usize = 8
+    + 32
+    + 8
+    + (4 + 16 * 32)
+    + (4 + MAX_BUNDLE_HISTORY * 104)
+    + (4 + MAX_FRAUD_RECORDS * LEGACY_FRAUD_RECORD_LEN)
+    + 1
+    + (4 + MAX_PENDING_NONCES * 8)
+    + (4 + MAX_PENDING_LIABILITIES * PendingLiability::INIT_SPACE)
+    + 32;
+/// Largest registry written before `FraudRecord::counter_evidence_hash`; anything
+/// this size or smaller (and above `ORDER_REF_REGISTRY_MAX_LEN`) has 112-byte
+/// history records and the older fraud records
+pub const CREATED_AT_REGISTRY_MAX_LEN: usize =
+    ORDER_REF_REGISTRY_MAX_LEN + MAX_BUNDLE_HISTORY * (112 - 104);
+/// Largest registry written before `BundleRecord::settlement_index`; anything this
+/// size or smaller (and above `CREATED_AT_REGISTRY_MAX_LEN`) has current fraud
+/// records but 112-byte history records
+pub const COUNTER_EVIDENCE_REGISTRY_MAX_LEN: usize = CREATED_AT_REGISTRY_MAX_LEN
+    + MAX_FRAUD_RECORDS * (FraudRecord::INIT_SPACE - LEGACY_FRAUD_RECORD_LEN);
+/// Bundle history records each `grow_registry` step adds room for
+pub const REGISTRY_GROWTH_HISTORY: usize = 32;
+/// Fraud records each `grow_registry` step adds room for
+pub const REGISTRY_GROWTH_FRAUD_RECORDS: usize = 16;
+/// Times a registry can be grown, keeping it loadable within the compute budget
+/// of a settlement
+pub const MAX_REGISTRY_GROWTH_STEPS: u8 = 3;
+/// Bytes of the statement an accused payer attaches with `submit_counter_evidence`
+pub const COUNTER_STATEMENT_LEN: usize = 64;
+/// Bytes of the delivery-proof reference a merchant attaches with `respond_to_chargeback`
+pub const CHARGEBACK_URI_LEN: usize = 128;
+// Bounded by the 32-bit settled mask and the 1 KiB return data limit
+pub const MAX_BATCH_SIZE: usize = 16;
+pub const MAX_FUNDING_TRANCHES: usize = 4;
+/// Upper bound on the configurable funding lockup (7 days)
+pub const MAX_FUNDING_LOCKUP: i64 = 7 * 86_400;
+/// Upper bound on how long a fraud report can freeze withdrawals
+pub const MAX_FRAUD_WITHDRAWAL_DELAY: i64 = 30 * 86_400;
+/// Packed `export_history` layout (integers little-endian):
+///   [0]     format version
+///   [1..3]  total records in the registry (u16)
+///   [3..5]  index of the first record in this page (u16)
+///   [5]     records in this page (u8)
+///   then `BundleRecord::PACKED_LEN` bytes per record:
+///   bundle_hash [32] | merchant [32] | amount u64 | settled_at i64 | nonce u64 | order_ref [16]
+///   | bundle_created_at i64 | settlement_index u64
+pub const HISTORY_EXPORT_VERSION: u8 = 4;
+pub const HISTORY_EXPORT_HEADER_LEN: usize = 6;
+// Largest page that fits in the 1 KiB return data limit
+pub const MAX_EXPORT_RECORDS: usize = 8;
+/// Highest protocol fee the admin may configure (10%)
+pub const MAX_FEE_BPS: u16 = 1_000;
+pub const PUNCTUALITY_BUCKET_COUNT: usize = 3;
+/// Upper bounds, in seconds from a bundle's creation to its settlement, of the
+/// escrow's punctuality buckets: within 1 hour, 24 hours and 7 days
+#[constant]
+pub const PUNCTUALITY_BUCKETS: [i64; PUNCTUALITY_BUCKET_COUNT] = [3_600, 86_400, 7 * 86_400];
+/// Shortest inactivity period an owner may configure for their beneficiary (90 days)
+pub const MIN_BENEFICIARY_INACTIVITY: i64 = 90 * 86_400;
+/// Notice window between a beneficiary claim and the sweep (30 days)
+pub const BENEFICIARY_NOTICE_PERIOD: i64 = 30 * 86_400;
+/// Shortest owner inactivity after which an escrow may be flagged dormant (3 years)
+pub const MIN_DORMANCY_PERIOD: i64 = 3 * 365 * 86_400;
+/// Shortest notice between flagging an escrow dormant and sweeping it (180 days)
+pub const MIN_ESCHEAT_NOTICE_PERIOD: i64 = 180 * 86_400;
+/// Longest validity window the admin may allow on voucher proofs (2 years)
+pub const MAX_VOUCHER_VALIDITY: i64 = 2 * 365 * 86_400;
+/// Longest the admin may let payers charge back a settlement after it (180 days)
+pub const MAX_CHARGEBACK_WINDOW: i64 = 180 * 86_400;
+/// Longest the admin may hold settled funds back from merchants (30 days)
+pub const MAX_SETTLEMENT_HOLD_PERIOD: i64 = 30 * 86_400;
+/// Longest the admin may keep a deregistered verifier's bond locked (180 days)
+pub const MAX_VERIFIER_BOND_COOLDOWN: i64 = 180 * 86_400;
+/// Longest the admin may keep nonce corrections open after escrow creation (7 days)
+pub const MAX_NONCE_CORRECTION_WINDOW: i64 = 7 * 86_400;
+/// Longest configurable rolling settlement window; one spend bucket per day
+pub const MAX_ROLLING_WINDOW_DAYS: usize = 31;
+/// Merchants an owner can set a lifetime settlement cap for
+pub const MAX_MERCHANT_LIMITS: usize = 8;
+/// Merchants an owner can register to be told when their escrow is topped up
+pub const MAX_ESCROW_WATCHERS: usize = 4;
+/// Settlements one invoice can record; bounds partial payments on up-to invoices
+pub const MAX_INVOICE_SETTLEMENTS: usize = 8;
+/// Share of an expired invoice's rent paid to the keeper that closes it (5%)
+pub const INVOICE_KEEPER_CUT_BPS: u64 = 500;
+/// Highest fee an underwriter may charge on credit drawn (10%)
+pub const MAX_CREDIT_FEE_BPS: u16 = 1_000;
+/// Highest cashback rate a merchant may offer (10%)
+pub const MAX_CASHBACK_BPS: u16 = 1_000;
+/// Mints the config can hold a `max_reasonable_amount` for
+pub const MAX_AMOUNT_CAPS: usize = 8;
+/// Source escrows one `consolidate_escrows` call can merge
+pub const MAX_CONSOLIDATED_ESCROWS: usize = 3;
+/// `ProgramConfig::status` bits
+pub const CONFIG_SETTLEMENTS_HALTED: u32 = 1 << 0;
+pub const CONFIG_BLOCK_ZERO_REPUTATION: u32 = 1 << 1;
+pub const CONFIG_STRICT_VAULT_CHECKS: u32 = 1 << 2;
+pub const CONFIG_REQUIRE_IDENTITY: u32 = 1 << 3;
+pub const CONFIG_AGGREGATE_ATTESTATION: u32 = 1 << 4;
+pub const CONFIG_DISPUTE_RESERVE: u32 = 1 << 5;
+pub const CONFIG_CUSTODIAL_ONBOARDING: u32 = 1 << 6;
+/// `OfflineEscrowAccount::status` bits
+pub const ESCROW_REPUTATION_MIGRATED: u32 = 1 << 0;
+pub const ESCROW_DORMANT: u32 = 1 << 1;
+pub const ESCROW_PROCESSING: u32 = 1 << 2;
+pub const ESCROW_MINT_DECIMALS_RECORDED: u32 = 1 << 3;
+pub const ESCROW_AMOUNT_CHECK_OPTED_OUT: u32 = 1 << 4;
+
+/// `status` with `flag` set or cleared, other bits untouched
+pub fn with_flag(status: u32, flag: u32, on: bool) -> u32 {
+    if on {
+        status | flag
+    } else {
+        status & !flag
+    }
+}
+
+/// Notice between declaring a sunset and stake becoming unlockable
+pub const SUNSET_TIMELOCK: i64 = 14 * 86_400;
+/// Number of `FraudReason` variants
+pub const FRAUD_REASON_COUNT: usize = 7;
+/// Slash for fraud reasons without a configured penalty: twice the disputed amount
+pub const DEFAULT_FRAUD_PENALTY_BPS: u16 = 20_000;
+/// Largest configurable fraud penalty, five times the disputed amount
+pub const MAX_FRAUD_PENALTY_BPS: u16 = 50_000;
+/// Extra token accounts an escrow can be backed by, beyond its primary vault
+pub const MAX_BACKING_TOKEN_ACCOUNTS: usize = 4;
+/// Permits one `get_permits` call can summarize within the return data limit
+pub const MAX_PERMIT_SUMMARIES: usize = 20;
+/// Bundles an owner can have pre-authorized at once
+pub const MAX_PREAUTHORIZATIONS: usize = 4;
+/// How long bundles signed by a rotated-out owner key remain settleable
+pub const ROTATION_GRACE_PERIOD: i64 = 7 * 86_400;
+/// Per-item code reported for a settled batch item; failures carry the BeamError code
+pub const BATCH_ITEM_SETTLED: u32 = 0;
+/// Size of the config's arbiter set
+pub const MAX_ARBITERS: usize = 5;
+/// Largest single manual reputation adjustment
+pub const MAX_REPUTATION_ADJUSTMENT: u16 = 1_000;
+/// Most manual reputation change, in either direction, one escrow can receive per period
+pub const MAX_PERIOD_REPUTATION_ADJUSTMENT: u32 = 2_000;
+pub const REPUTATION_ADJUSTMENT_PERIOD: i64 = 30 * 86_400;
+/// Bounds of an escrow's reputation score; every change saturates here
+pub const MIN_REPUTATION: i32 = -10_000;
+pub const MAX_REPUTATION: i32 = 10_000;
+/// Score a new escrow starts with
+pub const INITIAL_REPUTATION: i32 = 100;
+/// Taken off the score for each reported fraud
+pub const FRAUD_REPUTATION_PENALTY: i32 = 1_000;
 
 #[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
 pub struct BundleRecord {
@@ -10,28 +187,342 @@ pub struct BundleRecord {
     pub amount: u64,
     pub settled_at: i64,
     pub nonce: u64,
+    /// Merchant order reference; zeroed when the bundle carried none
+    pub order_ref: [u8; 16],
+    /// When the bundle was created, from its verified attestation; zero when no
+    /// attestation vouched for it
+    pub bundle_created_at: i64,
+    /// The payer escrow's `settlement_count` once this settlement was counted, so
+    /// records order definitively and gaps show; zero on records migrated from
+    /// before it was kept
+    pub settlement_index: u64,
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+impl BundleRecord {
+    pub const PACKED_LEN: usize = 32 + 32 + 8 + 8 + 8 + 16 + 8 + 8;
+
+    /// Append the record in the `export_history` layout
+    pub fn pack_into(&self, out: &mut Vec<u8>) {
+        out.extend_from_slice(&self.bundle_hash);
+        out.extend_from_slice(self.merchant.as_ref());
+        out.extend_from_slice(&self.amount.to_le_bytes());
+        out.extend_from_slice(&self.settled_at.to_le_bytes());
+        out.extend_from_slice(&self.nonce.to_le_bytes());
+        out.extend_from_slice(&self.order_ref);
+        out.extend_from_slice(&self.bundle_created_at.to_le_bytes());
+        out.extend_from_slice(&self.settlement_index.to_le_bytes());
+    }
+}
+
+/// `BundleRecord` as stored before `order_ref` was added
+#[derive(AnchorDeserialize)]
+struct LegacyBundleRecord {
+    bundle_hash: [u8; 32],
+    merchant: Pubkey,
+    amount: u64,
+    settled_at: i64,
+    nonce: u64,
+}
+
+/// `BundleRecord` as stored before `bundle_created_at` was added
+#[derive(AnchorDeserialize)]
+struct OrderRefBundleRecord {
+    bundle_hash: [u8; 32],
+    merchant: Pubkey,
+    amount: u64,
+    settled_at: i64,
+    nonce: u64,
+    order_ref: [u8; 16],
+}
+
+/// `BundleRecord` as stored before `settlement_index` was added
+#[derive(AnchorDeserialize)]
+struct CreatedAtBundleRecord {
+    bundle_hash: [u8; 32],
+    merchant: Pubkey,
+    amount: u64,
+    settled_at: i64,
+    nonce: u64,
+    order_ref: [u8; 16],
+    bundle_created_at: i64,
+}
+
+impl From<LegacyBundleRecord> for BundleRecord {
+    fn from(record: LegacyBundleRecord) -> Self {
+        Self {
+            bundle_hash: record.bundle_hash,
+            merchant: record.merchant,
+            amount: record.amount,
+            settled_at: record.settled_at,
+            nonce: record.nonce,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<OrderRefBundleRecord> for BundleRecord {
+    fn from(record: OrderRefBundleRecord) -> Self {
+        Self {
+            bundle_hash: record.bundle_hash,
+            merchant: record.merchant,
+            amount: record.amount,
+            settled_at: record.settled_at,
+            nonce: record.nonce,
+            order_ref: record.order_ref,
+            ..Self::default()
+        }
+    }
+}
+
+impl From<CreatedAtBundleRecord> for BundleRecord {
+    fn from(record: CreatedAtBundleRecord) -> Self {
+        Self {
+            bundle_hash: record.bundle_hash,
+            merchant: record.merchant,
+            amount: record.amount,
+            settled_at: record.settled_at,
+            nonce: record.nonce,
+            order_ref: record.order_ref,
+            bundle_created_at: record.bundle_created_at,
+            settlement_index: 0,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
 pub enum FraudReason {
+    /// Evidence: `FraudEvidence::SignedBundle`, another bundle the payer signed
+    /// with the settled nonce for the same merchant and amount
     DuplicateBundle,
+    /// Needs the arbiter quorum
     InvalidAttestation,
+    /// Needs the arbiter quorum
+    #[default]
     Other,
+    /// Evidence: `FraudEvidence::SignedBundle`, another bundle the payer signed
+    /// with the settled nonce for a different merchant or amount
+    NonceReuse,
+    /// Evidence: `FraudEvidence::SignedBundle`, the payer's signature over the
+    /// settled bundle itself at a different amount
+    AmountTampering,
+    /// The settled bundle's signature was invalid. Settlement verifies every
+    /// proof it accepts and keeps none, so nothing on-chain can show this; it
+    /// needs the arbiter quorum.
+    ForgedSignature,
+    /// Evidence: `FraudEvidence::Attestation`, the verifier's attestation of the
+    /// settled bundle with a timestamp deadline that passed before it settled
+    ExpiredBundleSettled,
 }
 
-impl Default for FraudReason {
-    fn default() -> Self {
-        FraudReason::Other
+impl FraudReason {
+    /// Reasons no on-chain evidence can prove; reports of these must be signed
+    /// by the arbiter quorum instead
+    pub fn needs_arbiters(&self) -> bool {
+        matches!(
+            self,
+            FraudReason::InvalidAttestation | FraudReason::Other | FraudReason::ForgedSignature
+        )
+    }
+
+    /// Reasons proven by a second bundle the payer signed with the settled nonce,
+    /// i.e. an objective double spend
+    pub fn is_double_spend(&self) -> bool {
+        matches!(self, FraudReason::DuplicateBundle | FraudReason::NonceReuse)
     }
 }
 
-#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+/// A bundle the payer signed, as `payer_bundle_digest` commits it. The report's
+/// conflicting hash must be that digest.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub struct SignedBundle {
+    pub bundle_hash: [u8; 32],
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+    /// The payer's ed25519 signature over the digest
+    pub signature: [u8; 64],
+}
+
+/// A verifier attestation of the settled bundle, with the bundle fields it
+/// commits that the bundle record doesn't keep. The report's conflicting hash
+/// must be its attestation root.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BundleAttestation {
+    pub proof: AttestationProof,
+    pub role: AttestationRole,
+    pub courier: Option<CourierCommitment>,
+    pub device_id_hash: Option<[u8; 32]>,
+    pub condition_id: Option<[u8; 32]>,
+}
+
+/// Proof accompanying a fraud report, of the kind its reason documents
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub enum FraudEvidence {
+    /// Only for reasons that need the arbiter quorum
+    None,
+    SignedBundle(SignedBundle),
+    Attestation(BundleAttestation),
+}
+
+impl FraudEvidence {
+    /// Whether this evidence proves `reason` against the settled bundle `record`
+    /// of `payer`. The arbiter quorum, which reasons without evidence need, is
+    /// checked by the caller.
+    pub fn supports(
+        &self,
+        reason: FraudReason,
+        record: &BundleRecord,
+        bundle_id: &str,
+        payer: &Pubkey,
+        conflicting_hash: &[u8; 32],
+        fallback_verifier: &Pubkey,
+    ) -> bool {
+        match (reason, self) {
+            (_, FraudEvidence::None) => reason.needs_arbiters(),
+            (
+                FraudReason::DuplicateBundle | FraudReason::NonceReuse | FraudReason::AmountTampering,
+                FraudEvidence::SignedBundle(signed),
+            ) => {
+                let digest =
+                    payer_bundle_digest(&signed.bundle_hash, &signed.merchant, signed.amount, signed.nonce);
+                if digest != *conflicting_hash || !signature_is_valid(payer, &digest, &signed.signature) {
+                    return false;
+                }
+                let same_bundle = signed.bundle_hash == record.bundle_hash;
+                let same_terms = signed.merchant == record.merchant && signed.amount == record.amount;
+                signed.nonce == record.nonce
+                    && match reason {
+                        FraudReason::DuplicateBundle => !same_bundle && same_terms,
+                        FraudReason::NonceReuse => !same_bundle && !same_terms,
+                        _ => {
+                            same_bundle
+                                && signed.merchant == record.merchant
+                                && signed.amount != record.amount
+                        }
+                    }
+            }
+            (FraudReason::ExpiredBundleSettled, FraudEvidence::Attestation(attestation)) => {
+                if attestation.proof.attestation_root != *conflicting_hash {
+                    return false;
+                }
+                let deadline = attested_deadline(
+                    &attestation.proof,
+                    attestation.role,
+                    bundle_id,
+                    payer,
+                    &record.merchant,
+                    record.amount,
+                    record.nonce,
+                    &record.order_ref,
+                    attestation.courier.as_ref(),
+                    attestation.device_id_hash.as_ref(),
+                    attestation.condition_id.as_ref(),
+                    fallback_verifier,
+                );
+                matches!(deadline, Some(SettlementDeadline::Timestamp(deadline)) if deadline < record.settled_at)
+            }
+            _ => false,
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
 pub struct FraudRecord {
     pub bundle_hash: [u8; 32],
     pub conflicting_hash: [u8; 32],
     pub reporter: Pubkey,
     pub reported_at: i64,
     pub reason: FraudReason,
+    /// The accused payer's rebuttal: hash of their off-chain evidence, zero until
+    /// they submit one
+    pub counter_evidence_hash: [u8; 32],
+    /// UTF-8 statement padded with zero bytes, kept for arbiters to weigh
+    pub counter_statement: [u8; COUNTER_STATEMENT_LEN],
+    pub countered_at: i64,
+}
+
+impl Default for FraudRecord {
+    fn default() -> Self {
+        Self {
+            bundle_hash: [0u8; 32],
+            conflicting_hash: [0u8; 32],
+            reporter: Pubkey::default(),
+            reported_at: 0,
+            reason: FraudReason::default(),
+            counter_evidence_hash: [0u8; 32],
+            counter_statement: [0u8; COUNTER_STATEMENT_LEN],
+            countered_at: 0,
+        }
+    }
+}
+
+impl FraudRecord {
+    pub fn is_countered(&self) -> bool {
+        self.countered_at != 0
+    }
+
+    /// Whether the report is still inside the `delay` window that holds the
+    /// escrow's funds, i.e. its dispute may still be acted on
+    pub fn is_open(&self, delay: i64, now: i64) -> bool {
+        delay > 0 && now < self.reported_at.saturating_add(delay)
+    }
+}
+
+/// `FraudRecord` as stored before the counter-evidence fields were added
+#[derive(AnchorDeserialize)]
+struct LegacyFraudRecord {
+    bundle_hash: [u8; 32],
+    conflicting_hash: [u8; 32],
+    reporter: Pubkey,
+    reported_at: i64,
+    reason: FraudReason,
+}
+
+impl From<LegacyFraudRecord> for FraudRecord {
+    fn from(record: LegacyFraudRecord) -> Self {
+        Self {
+            bundle_hash: record.bundle_hash,
+            conflicting_hash: record.conflicting_hash,
+            reporter: record.reporter,
+            reported_at: record.reported_at,
+            reason: record.reason,
+            ..Self::default()
+        }
+    }
+}
+
+/// Nonce registry layouts `migrate_nonce_registry` rewrites, oldest first, named by
+/// the newest field they have. Each is recognised by size, since registries only
+/// ever grow to the current layout.
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum LegacyRegistryLayout {
+    /// 88-byte history records, before `BundleRecord::order_ref`
+    Original,
+    /// 104-byte history records, before `BundleRecord::bundle_created_at`
+    OrderRef,
+    /// 112-byte history records, and fraud records before
+    /// `FraudRecord::counter_evidence_hash`
+    BundleCreatedAt,
+    /// Current fraud records, but 112-byte history records before
+    /// `BundleRecord::settlement_index`
+    CounterEvidence,
+}
+
+impl LegacyRegistryLayout {
+    /// Layout of a registry account of `len` bytes; `None` for the current one
+    pub fn of_len(len: usize) -> Option<Self> {
+        if len <= LEGACY_REGISTRY_MAX_LEN {
+            Some(Self::Original)
+        } else if len <= ORDER_REF_REGISTRY_MAX_LEN {
+            Some(Self::OrderRef)
+        } else if len <= CREATED_AT_REGISTRY_MAX_LEN {
+            Some(Self::BundleCreatedAt)
+        } else if len <= COUNTER_EVIDENCE_REGISTRY_MAX_LEN {
+            Some(Self::CounterEvidence)
+        } else {
+            None
+        }
+    }
 }
 
 #[account]
@@ -46,4 +537,1670 @@ pub struct NonceRegistry {
     #[max_len(MAX_FRAUD_RECORDS)]
     pub fraud_records: Vec<FraudRecord>,
     pub bump: u8,
+    /// Reserved nonces that stay settleable after a higher nonce moves `last_nonce`
+    /// past them; each is consumed by its settlement or dropped by `release_nonce`
+    #[max_len(MAX_PENDING_NONCES)]
+    pub pending_nonces: Vec<u64>,
+    /// Bundles the payer reports as signed but not yet settled. Withdrawals can't
+    /// take the escrow below their total; settlement clears the matching entry.
+    #[max_len(MAX_PENDING_LIABILITIES)]
+    pub pending_liabilities: Vec<PendingLiability>,
+    /// Zero for the owner's primary registry `[b"nonce", owner]`; otherwise the
+    /// enrolled device whose registry is `[b"nonce", owner, device_id_hash]`
+    pub device_id_hash: [u8; 32],
+    /// Paid the registry's rent and gets it back when the registry closes; default on
+    /// registries opened before it was recorded, read it through `rent_refund_recipient`
+    pub rent_payer: Pubkey,
+    /// Devices enrolled under this primary registry and not yet retired. While any
+    /// remain the primary can't be closed, which would orphan their registries.
+    pub device_count: u16,
+}
+
+/// A bundle registered by the payer's app, identified by the hash of its bundle id
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub struct PendingLiability {
+    pub bundle_hash: [u8; 32],
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub registered_at: i64,
+}
+
+impl PendingLiability {
+    /// Past the attestation max age, so the bundle can no longer settle with its
+    /// attestation and the entry may be pruned
+    pub fn is_stale(&self, now: i64) -> bool {
+        now.saturating_sub(self.registered_at) > MAX_ATTESTATION_AGE
+    }
+}
+
+/// How many history and fraud records a registry account has room for. Every
+/// registry starts at `MAX_BUNDLE_HISTORY` and `MAX_FRAUD_RECORDS`, and each
+/// `grow_registry` step adds a fixed number of both. Steps are read off the
+/// account size, so grown registries keep the current layout and, being larger,
+/// are never mistaken for a `LegacyRegistryLayout`.
+#[derive(Clone, Copy, PartialEq, Eq, Debug)]
+pub struct RegistryCapacity {
+    pub growth_steps: u8,
+}
+
+impl RegistryCapacity {
+    /// Bytes one growth step adds to the account
+    pub const STEP_LEN: usize = REGISTRY_GROWTH_HISTORY * BundleRecord::INIT_SPACE
+        + REGISTRY_GROWTH_FRAUD_RECORDS * FraudRecord::INIT_SPACE;
+
+    /// Capacity of a registry account of `len` bytes. Rounded up, so registries grown
+    /// before `NonceRegistry::rent_payer` and `device_count` were added, those fields
+    /// short of `account_len`, keep their steps.
+    pub fn of_len(len: usize) -> Self {
+        let steps = len.saturating_sub(Self::account_len(0)).div_ceil(Self::STEP_LEN);
+        Self {
+            growth_steps: steps.min(usize::from(MAX_REGISTRY_GROWTH_STEPS)) as u8,
+        }
+    }
+
+    pub fn of(registry: &AccountInfo) -> Self {
+        Self::of_len(registry.data_len())
+    }
+
+    /// Account size of a registry grown `growth_steps` times
+    pub fn account_len(growth_steps: u8) -> usize {
+        8 + NonceRegistry::INIT_SPACE + usize::from(growth_steps) * Self::STEP_LEN
+    }
+
+    pub fn bundle_history(&self) -> usize {
+        MAX_BUNDLE_HISTORY + usize::from(self.growth_steps) * REGISTRY_GROWTH_HISTORY
+    }
+
+    pub fn fraud_records(&self) -> usize {
+        MAX_FRAUD_RECORDS + usize::from(self.growth_steps) * REGISTRY_GROWTH_FRAUD_RECORDS
+    }
+}
+
+impl NonceRegistry {
+    pub fn is_device(&self) -> bool {
+        self.device_id_hash != [0u8; 32]
+    }
+
+    /// Last PDA seed. Empty for the primary registry, so one seed list derives both
+    /// `[b"nonce", owner]` and `[b"nonce", owner, device_id_hash]`.
+    pub fn device_seed(&self) -> &[u8] {
+        if self.is_device() {
+            &self.device_id_hash
+        } else {
+            &[]
+        }
+    }
+
+    /// Where the registry's rent goes when it closes: whoever paid it, or the owner
+    /// for registries opened before the payer was recorded
+    pub fn rent_refund_recipient(&self) -> Pubkey {
+        if self.rent_payer == Pubkey::default() {
+            self.owner
+        } else {
+            self.rent_payer
+        }
+    }
+
+    /// Consume `nonce` and remember `bundle_hash` for duplicate detection
+    pub fn mark_bundle(&mut self, bundle_hash: [u8; 32], nonce: u64) {
+        self.last_nonce = self.last_nonce.max(nonce);
+        self.pending_nonces.retain(|pending| *pending != nonce);
+        self.remember_bundle_hash(bundle_hash);
+    }
+
+    /// Remember `bundle_hash` for duplicate detection without touching nonces
+    pub fn remember_bundle_hash(&mut self, bundle_hash: [u8; 32]) {
+        if self.recent_bundle_hashes.len() >= MAX_RECENT_HASHES {
+            self.recent_bundle_hashes.remove(0);
+        }
+        self.recent_bundle_hashes.push(bundle_hash);
+    }
+
+    /// Fold a retired registry's history, recent hashes and fraud records into
+    /// this one, skipping bundles already recorded and keeping the newest entries
+    /// when a list is full
+    pub fn absorb(&mut self, retired: &NonceRegistry, capacity: RegistryCapacity) {
+        for hash in &retired.recent_bundle_hashes {
+            if !self.recent_bundle_hashes.contains(hash) {
+                self.remember_bundle_hash(*hash);
+            }
+        }
+
+        for record in &retired.bundle_history {
+            if !self
+                .bundle_history
+                .iter()
+                .any(|existing| existing.bundle_hash == record.bundle_hash)
+            {
+                self.bundle_history.push(*record);
+            }
+        }
+        self.bundle_history.sort_by_key(|record| record.settled_at);
+        let excess = self.bundle_history.len().saturating_sub(capacity.bundle_history());
+        self.bundle_history.drain(..excess);
+
+        for record in &retired.fraud_records {
+            if !self.fraud_records.iter().any(|existing| {
+                existing.bundle_hash == record.bundle_hash
+                    && existing.conflicting_hash == record.conflicting_hash
+            }) {
+                self.fraud_records.push(*record);
+            }
+        }
+        self.fraud_records.sort_by_key(|record| record.reported_at);
+        let excess = self.fraud_records.len().saturating_sub(capacity.fraud_records());
+        self.fraud_records.drain(..excess);
+    }
+
+    /// What further fraud reports could still slash while a dispute is open: the
+    /// heaviest configured penalty on every bundle in history not yet reported.
+    /// Slashes already taken sit in `stake_locked` and aren't counted again.
+    pub fn dispute_reserve(&self, config: &ProgramConfig) -> u64 {
+        self.bundle_history
+            .iter()
+            .filter(|record| {
+                !self
+                    .fraud_records
+                    .iter()
+                    .any(|fraud| fraud.bundle_hash == record.bundle_hash)
+            })
+            .fold(0u64, |acc, record| {
+                acc.saturating_add(config.max_fraud_penalty(record.amount).unwrap_or(u64::MAX))
+            })
+    }
+
+    /// Whether any fraud report is still open, see `FraudRecord::is_open`
+    pub fn has_open_disputes(&self, delay: i64, now: i64) -> bool {
+        self.fraud_records.iter().any(|record| record.is_open(delay, now))
+    }
+
+    /// Remove the fraud records reported before `older_than` whose disputes are
+    /// closed, oldest first. Also returns how many records before the cutoff were
+    /// kept because they are still open.
+    pub fn take_closed_fraud_records(
+        &mut self,
+        older_than: i64,
+        delay: i64,
+        now: i64,
+    ) -> (Vec<FraudRecord>, usize) {
+        let (pruned, kept): (Vec<_>, Vec<_>) = std::mem::take(&mut self.fraud_records)
+            .into_iter()
+            .partition(|record| record.reported_at < older_than && !record.is_open(delay, now));
+        let still_open = kept.iter().filter(|record| record.reported_at < older_than).count();
+        self.fraud_records = kept;
+        (pruned, still_open)
+    }
+
+    /// Mark a bundle paid from this owner's escrow, clearing its liability and
+    /// keeping it in history for dispute resolution
+    pub fn record_settlement(&mut self, record: BundleRecord, capacity: RegistryCapacity) {
+        self.mark_bundle(record.bundle_hash, record.nonce);
+        self.clear_liability(&record.bundle_hash);
+        self.push_history(record, capacity);
+    }
+
+    /// Mirror a settlement through one of the owner's device registries into this
+    /// primary one. Its hash makes duplicate detection global across devices, its
+    /// record counts toward the dispute reserve and can be reported here, and the
+    /// liability the owner registered for it, which only ever lives here, is cleared.
+    /// The device's nonce space is left alone.
+    pub fn record_device_settlement(&mut self, record: BundleRecord, capacity: RegistryCapacity) {
+        self.remember_bundle_hash(record.bundle_hash);
+        self.clear_liability(&record.bundle_hash);
+        self.push_history(record, capacity);
+    }
+
+    /// Drop the liability registered for `bundle_hash`, if any, now that it settled
+    pub fn clear_liability(&mut self, bundle_hash: &[u8; 32]) {
+        if let Some(index) = self
+            .pending_liabilities
+            .iter()
+            .position(|liability| liability.bundle_hash == *bundle_hash)
+        {
+            let liability = self.pending_liabilities.remove(index);
+            emit!(LiabilityCleared {
+                owner: self.owner,
+                merchant: liability.merchant,
+                bundle_hash: *bundle_hash,
+                amount: liability.amount,
+                pruned: false,
+            });
+        }
+    }
+
+    fn push_history(&mut self, record: BundleRecord, capacity: RegistryCapacity) {
+        if self.bundle_history.len() >= capacity.bundle_history() {
+            self.bundle_history.remove(0);
+        }
+        self.bundle_history.push(record);
+    }
+
+    /// Settlements in the history from `since` on: how many of them paid
+    /// `merchant`, and how many distinct merchants they paid
+    pub fn merchant_activity(&self, merchant: &Pubkey, since: i64) -> (usize, usize) {
+        let mut merchants: Vec<&Pubkey> = Vec::new();
+        let mut to_merchant = 0;
+        for record in self.bundle_history.iter().filter(|record| record.settled_at >= since) {
+            if record.merchant == *merchant {
+                to_merchant += 1;
+            }
+            if !merchants.contains(&&record.merchant) {
+                merchants.push(&record.merchant);
+            }
+        }
+        (to_merchant, merchants.len())
+    }
+
+    /// Total of the liabilities that are not yet stale
+    pub fn liability_total(&self, now: i64) -> u64 {
+        self.pending_liabilities
+            .iter()
+            .filter(|liability| !liability.is_stale(now))
+            .fold(0u64, |acc, liability| acc.saturating_add(liability.amount))
+    }
+
+    /// Decode a registry in `layout` (after the discriminator). Registries older
+    /// than the fields that follow `bump` may end before them.
+    pub fn from_legacy_bytes(
+        mut data: &[u8],
+        layout: LegacyRegistryLayout,
+    ) -> std::io::Result<Self> {
+        let owner = Pubkey::deserialize(&mut data)?;
+        let last_nonce = u64::deserialize(&mut data)?;
+        let recent_bundle_hashes = Vec::<[u8; 32]>::deserialize(&mut data)?;
+        let bundle_history = match layout {
+            LegacyRegistryLayout::BundleCreatedAt | LegacyRegistryLayout::CounterEvidence => {
+                Vec::<CreatedAtBundleRecord>::deserialize(&mut data)?
+                    .into_iter()
+                    .map(BundleRecord::from)
+                    .collect()
+            }
+            LegacyRegistryLayout::OrderRef => Vec::<OrderRefBundleRecord>::deserialize(&mut data)?
+                .into_iter()
+                .map(BundleRecord::from)
+                .collect(),
+            LegacyRegistryLayout::Original => Vec::<LegacyBundleRecord>::deserialize(&mut data)?
+                .into_iter()
+                .map(BundleRecord::from)
+                .collect(),
+        };
+        let fraud_records = match layout {
+            LegacyRegistryLayout::CounterEvidence => Vec::<FraudRecord>::deserialize(&mut data)?,
+            _ => Vec::<LegacyFraudRecord>::deserialize(&mut data)?
+                .into_iter()
+                .map(FraudRecord::from)
+                .collect(),
+        };
+        let bump = u8::deserialize(&mut data)?;
+        let pending_nonces = if data.len() >= 4 {
+            Vec::<u64>::deserialize(&mut data)?
+        } else {
+            Vec::new()
+        };
+        let pending_liabilities = if data.len() >= 4 {
+            Vec::<PendingLiability>::deserialize(&mut data)?
+        } else {
+            Vec::new()
+        };
+        let device_id_hash = if data.len() >= 32 {
+            <[u8; 32]>::deserialize(&mut data)?
+        } else {
+            [0u8; 32]
+        };
+        Ok(Self {
+            owner,
+            last_nonce,
+            recent_bundle_hashes,
+            bundle_history,
+            fraud_records,
+            bump,
+            pending_nonces,
+            pending_liabilities,
+            device_id_hash,
+            rent_payer: Pubkey::default(),
+            device_count: 0,
+        })
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct BatchSettlementItem {
+    pub amount: u64,
+    pub payer_nonce: u64,
+    pub bundle_id: String,
+    pub evidence: SettlementEvidence,
+}
+
+/// One signed bundle of a `settle_multihop` route
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct MultihopLeg {
+    pub bundle_id: String,
+    /// Nonce of the leg's payer: the escrow owner on the first leg, the runner on the second
+    pub nonce: u64,
+    pub evidence: SettlementEvidence,
+}
+
+/// Return data of `settle_batch_best_effort`.
+/// Bit `i` of `settled_mask` is set when item `i` settled; `item_codes[i]` is
+/// `BATCH_ITEM_SETTLED` or the error code that caused the item to be skipped.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct BatchSettlementResult {
+    pub settled_mask: u32,
+    pub settled_count: u8,
+    pub total_settled: u64,
+    pub item_codes: Vec<u32>,
+}
+
+/// Settlement checks in the order `settle_offline_payment` runs them
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub enum SettlementCheck {
+    /// Every check passed
+    Passed,
+    SettlementsHalted,
+    BundleId,
+    /// Payer is neither the owner, a rotated-out key in grace, nor a delegate within its cap
+    PayerAuthorization,
+    Reputation,
+    Attestation,
+    /// Nonce registry belongs to another owner or device
+    Registry,
+    Duplicate,
+    Nonce,
+    /// Settleable balance, with any guarantor cover, falls short of the debit
+    Balance,
+    /// Rolling cap or per-merchant limit
+    SpendingLimit,
+    AmountPlausibility,
+    Invoice,
+    /// Fee, referral or cashback accounts don't fit the settlement
+    Accounts,
+    /// The bundle's condition lacks a valid oracle signature
+    Condition,
+    /// The compliance registry hasn't approved payer or merchant
+    Compliance,
+}
+
+/// Return data of `preflight_settlement`: the first check a settlement would
+/// fail, with the error code it would fail with (0 when `check` is `Passed`)
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SettlementPreflight {
+    pub check: SettlementCheck,
+    pub error_code: u32,
+}
+
+/// Prefix of every signed `SettlementReceipt`, so the signature can't be replayed
+/// as a signature over any other message
+pub const SETTLEMENT_RECEIPT_DOMAIN: &[u8; 16] = b"beam-receipt\0\0\0\0";
+/// Bumped whenever the receipt layout changes
+pub const SETTLEMENT_RECEIPT_VERSION: u8 = 3;
+
+/// Return data of `settle_offline_payment`, also emitted as `SettlementReceiptIssued`.
+/// Programs settling through CPI read it with `get_return_data` right after the
+/// call, since they can't see events. The program can't sign it; an off-chain
+/// verifier counter-signs `signing_bytes`:
+///   domain [16] | version u8 | bundle_hash [32] | amount u64 | merchant [32] |
+///   payer [32] | nonce u64 | slot u64 | settlement_index u64 | fee u64 |
+///   escrow_balance u64
+/// Integers are little-endian. Past the version byte this is exactly the Borsh
+/// encoding, i.e. the return data, so either can be signed as received.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, Debug)]
+pub struct SettlementReceipt {
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub merchant: Pubkey,
+    pub payer: Pubkey,
+    pub nonce: u64,
+    pub slot: u64,
+    /// As stamped into the settlement's `BundleRecord`
+    pub settlement_index: u64,
+    /// Protocol fee charged on the settlement, whichever side bore it
+    pub fee: u64,
+    /// Payer's escrow balance once the settlement and its fees were debited
+    pub escrow_balance: u64,
+}
+
+impl SettlementReceipt {
+    pub const PACKED_LEN: usize = 32 + 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8;
+    pub const SIGNING_LEN: usize = SETTLEMENT_RECEIPT_DOMAIN.len() + 1 + Self::PACKED_LEN;
+
+    /// Deterministic message a verifier signs for this receipt
+    pub fn signing_bytes(&self) -> [u8; Self::SIGNING_LEN] {
+        let mut out = [0u8; Self::SIGNING_LEN];
+        let fields: [&[u8]; 11] = [
+            SETTLEMENT_RECEIPT_DOMAIN,
+            &[SETTLEMENT_RECEIPT_VERSION],
+            &self.bundle_hash,
+            &self.amount.to_le_bytes(),
+            self.merchant.as_ref(),
+            self.payer.as_ref(),
+            &self.nonce.to_le_bytes(),
+            &self.slot.to_le_bytes(),
+            &self.settlement_index.to_le_bytes(),
+            &self.fee.to_le_bytes(),
+            &self.escrow_balance.to_le_bytes(),
+        ];
+        let mut offset = 0;
+        for field in fields {
+            out[offset..offset + field.len()].copy_from_slice(field);
+            offset += field.len();
+        }
+        out
+    }
+}
+
+/// Reputation shared by every escrow linked to one off-chain KYC identity, so fraud
+/// on one escrow follows its owner to the next. Seeded by `[b"identity", identity]`
+/// and only written with the config's identity authority or by fraud reports.
+#[account]
+#[derive(InitSpace)]
+pub struct IdentityReputation {
+    pub identity: Pubkey,
+    pub reputation_score: i32,
+    pub fraud_count: u32,
+    pub linked_escrows: u16,
+    pub banned: bool,
+    pub bump: u8,
+}
+
+impl IdentityReputation {
+    /// Whether the identity may open another escrow
+    pub fn check_eligible(&self, min_reputation: i32) -> std::result::Result<(), BeamError> {
+        if self.banned {
+            return Err(BeamError::IdentityBanned);
+        }
+        if self.reputation_score < min_reputation {
+            return Err(BeamError::IdentityReputationTooLow);
+        }
+        Ok(())
+    }
+
+    /// Carry a linked escrow's fraud history over to the identity
+    pub fn absorb_record(&mut self, reputation_score: i32, fraud_count: u32) {
+        self.reputation_score = self.reputation_score.min(reputation_score);
+        self.fraud_count = self.fraud_count.saturating_add(fraud_count);
+    }
+
+    pub fn record_fraud(&mut self) {
+        self.fraud_count = self.fraud_count.saturating_add(1);
+        self.reputation_score = self
+            .reputation_score
+            .saturating_sub(FRAUD_REPUTATION_PENALTY)
+            .clamp(MIN_REPUTATION, MAX_REPUTATION);
+    }
+}
+
+/// Maps a rotated-out owner key to the key that now owns its escrow.
+/// Seeded by `[b"tombstone", old_owner]`; left behind by `rotate_owner_key`.
+#[account]
+#[derive(InitSpace)]
+pub struct OwnerTombstone {
+    pub old_owner: Pubkey,
+    pub new_owner: Pubkey,
+    pub rotated_at: i64,
+    pub grace_until: i64,
+    pub bump: u8,
+}
+
+/// Global deployment settings, seeded by `[b"config"]`
+#[account]
+#[derive(InitSpace)]
+pub struct ProgramConfig {
+    pub admin: Pubkey,
+    /// Seconds a deposit must sit in escrow before settlements can draw on it (0 disables)
+    pub funding_lockup_secs: i64,
+    pub bump: u8,
+    /// Rolling settlement cap per escrow over `rolling_window_days` (0 disables)
+    pub rolling_window_days: u8,
+    pub rolling_cap: u64,
+    /// Protocol fee taken out of each settlement before the merchant is paid (0 disables)
+    pub fee_bps: u16,
+    /// Owner of the token accounts that receive protocol fees
+    pub treasury: Pubkey,
+    /// Paid from the protocol fee to an escrow's referrer, for at most
+    /// `referral_reward_limit` settlements per escrow
+    pub referral_reward: u64,
+    pub referral_reward_limit: u16,
+    /// Pre-`status` flags; `migrate_config` folds them into `status` and clears them
+    pub legacy_block_zero_reputation: bool,
+    pub legacy_settlements_halted: bool,
+    /// Reason code given with the latest halt or resume
+    pub halt_reason: u16,
+    /// Absolute bounds the bps fee is clamped into while fees are enabled
+    /// (`max_fee == 0` means no ceiling)
+    pub min_fee: u64,
+    pub max_fee: u64,
+    /// Largest courier fee a bundle may commit to; zero disables courier fees
+    pub max_courier_fee: u64,
+    /// How long after its latest fraud report an escrow's funds stay put;
+    /// zero disables the delay
+    pub fraud_withdrawal_delay: i64,
+    /// Secondary verifier accepted while the primary is down; default when unset
+    pub fallback_verifier: Pubkey,
+    /// Largest settlement a fallback-signed proof may back
+    pub fallback_cap: u64,
+    /// Primary-verifier proofs are rejected once its heartbeat is older than
+    /// this; zero disables the check
+    pub max_heartbeat_age: i64,
+    /// Fixed at initialization: changing it would orphan every stored bundle hash
+    pub bundle_hash_algo: BundleHashAlgo,
+    /// Keys whose joint signatures back sensitive admin actions; unused slots are default
+    pub arbiters: [Pubkey; MAX_ARBITERS],
+    /// Distinct arbiter signatures such an action needs; zero while no set is configured
+    pub arbiter_quorum: u8,
+    /// Manual reputation adjustments larger than this also need the arbiter quorum
+    pub reputation_quorum_threshold: u16,
+    /// Stake slashed per fraud report as bps of the disputed amount, indexed by
+    /// `FraudReason`; zero falls back to `DEFAULT_FRAUD_PENALTY_BPS`
+    pub fraud_penalty_bps: [u16; FRAUD_REASON_COUNT],
+    /// When a declared wind-down takes effect, unlocking stake for return to owners;
+    /// zero while no sunset is declared
+    pub sunset_at: i64,
+    /// `CONFIG_*` flag bits
+    pub status: u32,
+    /// Who bears a Token-2022 transfer fee on merchant payouts
+    pub transfer_fee_payer: TransferFeePayer,
+    /// Off-chain KYC service that registers identities and links escrows to them;
+    /// default while identity linking is disabled
+    pub identity_authority: Pubkey,
+    /// Identities scoring below this may not open another escrow
+    pub min_identity_reputation: i32,
+    /// Owner inactivity after which anyone may flag an escrow dormant, and the notice
+    /// before its balance can be swept to `custodial_token_account`. Zero
+    /// `dormancy_period` disables escheatment; otherwise both are at least their
+    /// `MIN_*` bounds.
+    pub dormancy_period: i64,
+    pub escheat_notice_period: i64,
+    pub custodial_token_account: Pubkey,
+    /// Longest validity window a voucher proof may carry; zero rejects vouchers
+    pub max_voucher_validity: i64,
+    /// How long after a settlement its payer may request a chargeback; zero
+    /// disables chargebacks
+    pub chargeback_window: i64,
+    /// How long a merchant has to answer a chargeback before it can be resolved
+    /// for the payer; non-zero whenever chargebacks are enabled
+    pub chargeback_response_window: i64,
+    /// Who bears the protocol fee on direct settlements
+    pub fee_payer: FeePayer,
+    /// An escrow's reputation drifts down toward `reputation_decay_baseline` by
+    /// `reputation_decay_points` for each whole `reputation_decay_period` without a
+    /// settlement; a zero period disables decay
+    pub reputation_decay_period: i64,
+    pub reputation_decay_points: u16,
+    pub reputation_decay_baseline: i32,
+    /// Key `propose_admin` nominated to take over from `admin`; default when no
+    /// transfer is pending. `admin` keeps full control until it accepts.
+    pub pending_admin: Pubkey,
+    /// Largest single settlement, in base units, considered plausible per mint
+    pub max_reasonable_amounts: [MintAmountCap; MAX_AMOUNT_CAPS],
+    /// How long after an escrow is created its owner may reset the nonce state with
+    /// `correct_nonce_state`; zero disables corrections
+    pub nonce_correction_window: i64,
+    /// Signs that the real-world condition of a conditional bundle was met;
+    /// default while conditional settlement is unavailable
+    pub condition_oracle: Pubkey,
+    /// Cut of the protocol fee paid to the verifier whose proof backed a
+    /// settlement, capped at what the referrer leaves of the fee
+    pub verifier_fee: u64,
+    /// Reputation an escrow earns per settlement; zero disables settlement rewards.
+    /// Only its first `reputation_reward_merchant_cap` settlements to each merchant
+    /// within `reputation_reward_period` earn it (a zero cap leaves them uncapped,
+    /// a zero period looks at the whole registry history), so paying one merchant
+    /// over and over can't farm reputation
+    pub settlement_reputation_reward: u16,
+    pub reputation_reward_merchant_cap: u16,
+    pub reputation_reward_period: i64,
+    /// Settlements pay into a `SettlementHold` the payer may dispute for this long
+    /// before the merchant can have it; zero pays merchants directly
+    pub settlement_hold_period: i64,
+    /// Registry program whose approval of payer and merchant every settlement
+    /// needs, as described in `compliance`; the default key turns the check off
+    pub compliance_registry: Pubkey,
+    /// Bond a key must hold in its `VerifierBond` to be set as fallback verifier;
+    /// zero lets unbonded keys verify
+    pub min_verifier_bond: u64,
+    /// Share of a verifier's bond `slash_verifier` takes per double-spent bundle
+    /// the verifier attested
+    pub verifier_slash_bps: u16,
+    /// How long a deregistered verifier's bond stays slashable before it can be
+    /// withdrawn; should outlast `MAX_ATTESTATION_AGE` plus `fraud_withdrawal_delay`
+    pub verifier_bond_cooldown: i64,
+}
+
+impl ProgramConfig {
+    pub fn is_compliance_enabled(&self) -> bool {
+        self.compliance_registry != Pubkey::default()
+    }
+
+    /// Settlement-only circuit breaker; withdrawals, funding and fraud reports stay open
+    pub fn is_settlements_halted(&self) -> bool {
+        self.status & CONFIG_SETTLEMENTS_HALTED != 0
+    }
+
+    pub fn set_settlements_halted(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_SETTLEMENTS_HALTED, on);
+    }
+
+    /// Reject settlements from escrows whose reputation is at or below zero
+    pub fn is_zero_reputation_blocked(&self) -> bool {
+        self.status & CONFIG_BLOCK_ZERO_REPUTATION != 0
+    }
+
+    pub fn set_zero_reputation_blocked(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_BLOCK_ZERO_REPUTATION, on);
+    }
+
+    /// Reload the vault after funding, settlement and withdrawal transfers and
+    /// require it to have moved exactly as `escrow_balance` did
+    pub fn is_strict_vault_checks(&self) -> bool {
+        self.status & CONFIG_STRICT_VAULT_CHECKS != 0
+    }
+
+    pub fn set_strict_vault_checks(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_STRICT_VAULT_CHECKS, on);
+    }
+
+    /// New escrows must be linked to a registered identity when they are opened
+    pub fn is_identity_required(&self) -> bool {
+        self.status & CONFIG_REQUIRE_IDENTITY != 0
+    }
+
+    pub fn set_identity_required(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_REQUIRE_IDENTITY, on);
+    }
+
+    /// Accept payer proofs signed by the escrow's registered key shares in place of
+    /// a verifier signature
+    pub fn is_aggregate_attestation_enabled(&self) -> bool {
+        self.status & CONFIG_AGGREGATE_ATTESTATION != 0
+    }
+
+    pub fn set_aggregate_attestation_enabled(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_AGGREGATE_ATTESTATION, on);
+    }
+
+    /// While a fraud report is inside `fraud_withdrawal_delay`, let the owner
+    /// withdraw down to the dispute reserve instead of freezing withdrawals
+    pub fn is_dispute_reserve_enabled(&self) -> bool {
+        self.status & CONFIG_DISPUTE_RESERVE != 0
+    }
+
+    pub fn set_dispute_reserve_enabled(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_DISPUTE_RESERVE, on);
+    }
+
+    /// Let a rent payer open escrows and nonce registries for owners who don't sign
+    pub fn is_custodial_onboarding(&self) -> bool {
+        self.status & CONFIG_CUSTODIAL_ONBOARDING != 0
+    }
+
+    pub fn set_custodial_onboarding(&mut self, on: bool) {
+        self.status = with_flag(self.status, CONFIG_CUSTODIAL_ONBOARDING, on);
+    }
+
+    /// Move the pre-`status` bools into their flags. Idempotent.
+    pub fn fold_legacy_flags(&mut self) {
+        if self.legacy_block_zero_reputation {
+            self.set_zero_reputation_blocked(true);
+            self.legacy_block_zero_reputation = false;
+        }
+        if self.legacy_settlements_halted {
+            self.set_settlements_halted(true);
+            self.legacy_settlements_halted = false;
+        }
+    }
+
+    /// Distinct configured arbiters among `signers`
+    pub fn signing_arbiters(&self, signers: &[Pubkey]) -> Vec<Pubkey> {
+        let mut found: Vec<Pubkey> = Vec::new();
+        for key in signers {
+            if *key != Pubkey::default() && self.arbiters.contains(key) && !found.contains(key) {
+                found.push(*key);
+            }
+        }
+        found
+    }
+
+    /// Whether a declared sunset has passed its timelock
+    pub fn is_sunset(&self, now: i64) -> bool {
+        self.sunset_at > 0 && now >= self.sunset_at
+    }
+
+    /// Slash applied for a fraud report of `reason` on a bundle of `amount`
+    pub fn fraud_penalty(&self, reason: FraudReason, amount: u64) -> Option<u64> {
+        let bps = match self.fraud_penalty_bps[reason as usize] {
+            0 => DEFAULT_FRAUD_PENALTY_BPS,
+            bps => bps,
+        };
+        u64::try_from(u128::from(amount) * u128::from(bps) / 10_000).ok()
+    }
+
+    /// Reputation earned by a settlement to a merchant the escrow already paid
+    /// `prior` times within the reward period
+    pub fn reputation_reward(&self, prior: usize) -> i32 {
+        let cap = usize::from(self.reputation_reward_merchant_cap);
+        if cap > 0 && prior >= cap {
+            return 0;
+        }
+        i32::from(self.settlement_reputation_reward)
+    }
+
+    /// The heaviest slash any fraud reason would apply to a bundle of `amount`
+    pub fn max_fraud_penalty(&self, amount: u64) -> Option<u64> {
+        let bps = self
+            .fraud_penalty_bps
+            .iter()
+            .map(|bps| match bps {
+                0 => DEFAULT_FRAUD_PENALTY_BPS,
+                bps => *bps,
+            })
+            .max()
+            .unwrap_or(DEFAULT_FRAUD_PENALTY_BPS);
+        u64::try_from(u128::from(amount) * u128::from(bps) / 10_000).ok()
+    }
+
+    /// What `slash_verifier` takes from a verifier bond of `bond`
+    pub fn verifier_slash(&self, bond: u64) -> u64 {
+        (u128::from(bond) * u128::from(self.verifier_slash_bps) / 10_000) as u64
+    }
+}
+
+impl ProgramConfig {
+    /// The plausibility ceiling for settlements in `mint`, if the admin set one
+    pub fn max_reasonable_amount(&self, mint: &Pubkey) -> Option<u64> {
+        self.max_reasonable_amounts
+            .iter()
+            .find(|cap| cap.max_amount > 0 && cap.mint == *mint)
+            .map(|cap| cap.max_amount)
+    }
+
+    /// Protocol fee on a settlement of `amount`: the bps fee rounded down, then
+    /// clamped into `[min_fee, max_fee]`. Zero whenever `fee_bps` is zero.
+    pub fn settlement_fee(&self, amount: u64) -> u64 {
+        if self.fee_bps == 0 {
+            return 0;
+        }
+        let fee = (u128::from(amount) * u128::from(self.fee_bps) / 10_000) as u64;
+        let fee = fee.max(self.min_fee);
+        if self.max_fee > 0 {
+            fee.min(self.max_fee)
+        } else {
+            fee
+        }
+    }
+
+    /// Part of the protocol fee on `amount` the escrow pays on top of it; the
+    /// rest of `settlement_fee` comes out of the merchant's payout
+    pub fn payer_settlement_fee(&self, amount: u64) -> u64 {
+        match self.fee_payer {
+            FeePayer::Merchant => 0,
+            FeePayer::Payer => self.settlement_fee(amount),
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Default)]
+pub struct ConfigUpdate {
+    pub funding_lockup_secs: Option<i64>,
+    pub rolling_window_days: Option<u8>,
+    pub rolling_cap: Option<u64>,
+    pub fee_bps: Option<u16>,
+    pub treasury: Option<Pubkey>,
+    pub referral_reward: Option<u64>,
+    pub referral_reward_limit: Option<u16>,
+    pub block_zero_reputation: Option<bool>,
+    pub min_fee: Option<u64>,
+    pub max_fee: Option<u64>,
+    pub max_courier_fee: Option<u64>,
+    pub fraud_withdrawal_delay: Option<i64>,
+    pub max_heartbeat_age: Option<i64>,
+    pub reputation_quorum_threshold: Option<u16>,
+    pub transfer_fee_payer: Option<TransferFeePayer>,
+    pub strict_vault_checks: Option<bool>,
+    pub identity_authority: Option<Pubkey>,
+    pub min_identity_reputation: Option<i32>,
+    pub require_identity: Option<bool>,
+    pub dormancy_period: Option<i64>,
+    pub escheat_notice_period: Option<i64>,
+    pub custodial_token_account: Option<Pubkey>,
+    pub aggregate_attestation: Option<bool>,
+    pub max_voucher_validity: Option<i64>,
+    pub chargeback_window: Option<i64>,
+    pub chargeback_response_window: Option<i64>,
+    pub fee_payer: Option<FeePayer>,
+    pub reputation_decay_period: Option<i64>,
+    pub reputation_decay_points: Option<u16>,
+    pub reputation_decay_baseline: Option<i32>,
+    pub nonce_correction_window: Option<i64>,
+    pub dispute_reserve: Option<bool>,
+    pub condition_oracle: Option<Pubkey>,
+    pub verifier_fee: Option<u64>,
+    pub settlement_reputation_reward: Option<u16>,
+    pub reputation_reward_merchant_cap: Option<u16>,
+    pub reputation_reward_period: Option<i64>,
+    pub settlement_hold_period: Option<i64>,
+    pub custodial_onboarding: Option<bool>,
+    pub compliance_registry: Option<Pubkey>,
+    pub min_verifier_bond: Option<u64>,
+    pub verifier_slash_bps: Option<u16>,
+    pub verifier_bond_cooldown: Option<i64>,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct FundingTranche {
+    pub amount: u64,
+    pub funded_at: i64,
+}
+
+/// Owner-set cap on the total that may ever settle to one merchant.
+/// `settled` counts from when the cap was first set.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct MerchantLimit {
+    pub merchant: Pubkey,
+    pub limit: u64,
+    pub settled: u64,
+}
+
+/// Settlements above `max_amount` base units of `mint` are taken for a unit
+/// mix-up (UI amounts sent as base units) and rejected; a zero `max_amount` marks
+/// an empty slot
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct MintAmountCap {
+    pub mint: Pubkey,
+    pub max_amount: u64,
+}
+
+/// Merchant told by `EscrowFundedForWatcher` when a deposit lifts the escrow's
+/// balance from at or below `threshold` to above it; a default `merchant` marks
+/// an empty slot
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct EscrowWatcher {
+    pub merchant: Pubkey,
+    pub threshold: u64,
+}
+
+impl EscrowWatcher {
+    pub fn is_crossed(&self, balance_before: u64, balance_after: u64) -> bool {
+        self.merchant != Pubkey::default()
+            && balance_before <= self.threshold
+            && balance_after > self.threshold
+    }
+}
+
+/// Key shares one escrow can register for aggregate attestation
+pub const MAX_KEY_SHARES: usize = 4;
+
+/// Key shares the payer's wallet splits between device and cloud. A payer proof
+/// signed by distinct shares whose weights reach `threshold` is accepted without a
+/// verifier signature. A threshold wallet that already produces one group signature
+/// registers its group key as a single share. Unused slots are default with weight 0.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct AggregateKey {
+    pub shares: [Pubkey; MAX_KEY_SHARES],
+    pub weights: [u8; MAX_KEY_SHARES],
+    /// Zero while no key is registered
+    pub threshold: u16,
+}
+
+impl AggregateKey {
+    pub fn is_registered(&self) -> bool {
+        self.threshold > 0
+    }
+
+    /// Combined weight of the registered shares
+    pub fn total_weight(&self) -> u16 {
+        self.weights.iter().map(|weight| u16::from(*weight)).sum()
+    }
+
+    /// Every used slot has a weight, no share repeats and the threshold is reachable.
+    /// An all-default key (threshold zero) clears the registration.
+    pub fn is_valid(&self) -> bool {
+        if !self.is_registered() {
+            return *self == Self::default();
+        }
+        let slots_consistent = self
+            .shares
+            .iter()
+            .zip(self.weights.iter())
+            .all(|(share, weight)| (*share == Pubkey::default()) == (*weight == 0));
+        let distinct = self.shares.iter().enumerate().all(|(i, share)| {
+            *share == Pubkey::default() || !self.shares[..i].contains(share)
+        });
+        slots_consistent && distinct && self.threshold <= self.total_weight()
+    }
+
+    /// Weight of the distinct registered shares with a valid signature over `message`
+    pub fn signed_weight(&self, message: &[u8], signatures: &[ShareSignature]) -> u16 {
+        let mut counted = [false; MAX_KEY_SHARES];
+        for share_signature in signatures {
+            let index = share_signature.share_index as usize;
+            if index >= MAX_KEY_SHARES || counted[index] || self.weights[index] == 0 {
+                continue;
+            }
+            if signature_is_valid(&self.shares[index], message, &share_signature.signature) {
+                counted[index] = true;
+            }
+        }
+        counted
+            .iter()
+            .zip(self.weights.iter())
+            .filter(|(signed, _)| **signed)
+            .map(|(_, weight)| u16::from(*weight))
+            .sum()
+    }
+}
+
+/// Owner opt-in to round each settlement up to a multiple of `unit` and donate the
+/// difference to `charity_token_account`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct RoundupConfig {
+    pub enabled: bool,
+    pub unit: u64,
+    pub charity_token_account: Pubkey,
+}
+
+impl RoundupConfig {
+    /// Donation that brings `amount` up to the next multiple of `unit`; zero when
+    /// disabled or already a multiple
+    pub fn roundup_for(&self, amount: u64) -> u64 {
+        if !self.enabled || self.unit == 0 {
+            return 0;
+        }
+        match amount % self.unit {
+            0 => 0,
+            rem => self.unit - rem,
+        }
+    }
+}
+
+/// A bundle the owner approved on-chain ahead of time; it settles without attestations.
+/// Empty slots have `amount == 0`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct Preauthorization {
+    pub bundle_hash: [u8; 32],
+    pub merchant: Pubkey,
+    pub amount: u64,
+    pub nonce: u64,
+}
+
+/// How bundle ids are hashed into the bundle hashes stored and compared on-chain.
+/// Configs created before the choice existed read as `Keccak256`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum BundleHashAlgo {
+    #[default]
+    Keccak256,
+    Sha256,
+}
+
+impl BundleHashAlgo {
+    pub fn hash(&self, bundle_id: &str) -> [u8; 32] {
+        match self {
+            BundleHashAlgo::Keccak256 => keccak::hash(bundle_id.as_bytes()).to_bytes(),
+            BundleHashAlgo::Sha256 => hash::hash(bundle_id.as_bytes()).to_bytes(),
+        }
+    }
+}
+
+/// Who bears the transfer fee of a Token-2022 mint when a settlement pays the
+/// merchant. Configs created before the choice existed read as `Merchant`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum TransferFeePayer {
+    /// The merchant receives the payout less the fee
+    #[default]
+    Merchant,
+    /// The escrow sends enough extra that the full payout arrives
+    Payer,
+}
+
+/// Who bears the protocol fee on `settle_offline_payment` and
+/// `settle_batch_best_effort`. Multihop and netted settlements always take it from
+/// the merchant's leg. Configs created before the choice existed read as `Merchant`.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub enum FeePayer {
+    /// The fee is deducted from the merchant's payout
+    #[default]
+    Merchant,
+    /// The merchant receives the full amount and the escrow pays the fee on top
+    Payer,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum InvoiceMode {
+    /// Settlement must equal the invoice amount
+    Exact,
+    /// Any number of settlements up to the invoice amount in total (e.g. fuel pumps)
+    UpTo,
+    /// Any amount; the invoice only carries the order reference
+    Open,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum InvoiceStatus {
+    Open,
+    PartiallyPaid,
+    Paid,
+}
+
+/// Read-only snapshot of an escrow returned by `get_escrow_summary`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EscrowSummary {
+    pub owner: Pubkey,
+    pub label: [u8; 32],
+    pub escrow_balance: u64,
+    /// Balance that has cleared the funding lockup
+    pub settleable_balance: u64,
+    pub stake_locked: u64,
+    pub total_spent: u64,
+    pub last_nonce: u64,
+    /// Score with any pending inactivity decay applied
+    pub reputation_score: i32,
+    pub fraud_count: u32,
+    pub settlement_count: u64,
+    /// Settlements with an attested bundle creation time
+    pub timed_settlement_count: u64,
+    /// Of those, how many settled within each `PUNCTUALITY_BUCKETS` bound
+    pub settled_within: [u64; PUNCTUALITY_BUCKET_COUNT],
+}
+
+/// Fraud history of an escrow returned by `get_fraud_summary`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct FraudSummary {
+    pub owner: Pubkey,
+    pub fraud_count: u32,
+    pub stake_locked: u64,
+    pub last_fraud_timestamp: i64,
+    /// Reports per `FraudReason`, indexed by variant
+    pub by_reason: [u32; FRAUD_REASON_COUNT],
+}
+
+/// Addresses returned by `derive_escrow_address`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct EscrowAddress {
+    pub escrow: Pubkey,
+    pub bump: u8,
+    /// The token account recorded on the escrow when it was passed and exists;
+    /// otherwise its associated token account for the mint under the given token
+    /// program, which an escrow opened on another account won't settle from
+    pub token_account: Pubkey,
+    /// Whether `token_account` is the one recorded on the escrow
+    pub recorded: bool,
+}
+
+/// Outcome of checking one proof in `verify_evidence`. `error_code` is the
+/// `BeamError` code that rejected the proof, zero when it is valid or absent.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct ProofVerification {
+    pub present: bool,
+    pub valid: bool,
+    pub error_code: u32,
+}
+
+/// Return data of `verify_attestation_only`. `error_code` is the `BeamError` code
+/// settlement would reject the proof with, zero when it would pass.
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct AttestationVerification {
+    pub valid: bool,
+    pub error_code: u32,
+    /// The root the proof has to carry for these bundle fields
+    pub expected_root: [u8; 32],
+    pub root_matches: bool,
+    /// Key whose signature the proof needs: the primary or fallback verifier,
+    /// or the default key for aggregate proofs, which the payer's shares sign
+    pub verifier: Pubkey,
+}
+
+/// Return data of `verify_evidence`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, Default)]
+pub struct EvidenceVerification {
+    pub payer: ProofVerification,
+    pub merchant: ProofVerification,
+}
+
+/// Return data of `get_attestation_policy`: what a settlement's proofs must satisfy
+/// on this deployment
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy)]
+pub struct AttestationPolicy {
+    /// Largest single settlement that may omit attestation. `u64::MAX` while
+    /// proofs are optional; multi-hop settlements always need a payer proof.
+    pub max_unattested_amount: u64,
+    /// Proof timestamps must be within this many seconds of the cluster clock
+    pub max_attestation_age: i64,
+    /// `ATTESTATION_SCHEME_*` bits
+    pub accepted_schemes: u8,
+    /// The primary verifier, plus the fallback one when registered
+    pub verifier_key_count: u8,
+    pub primary_verifier: Pubkey,
+    /// Default when no fallback verifier is registered
+    pub fallback_verifier: Pubkey,
+    /// Largest amount a fallback-signed proof may cover
+    pub fallback_cap: u64,
+    /// Primary-verifier proofs need a heartbeat at most this old; zero when
+    /// liveness isn't enforced
+    pub max_heartbeat_age: i64,
+    /// Voucher proofs are checked against their validity window instead of
+    /// `max_attestation_age`; the window may span at most this, zero when
+    /// vouchers aren't accepted
+    pub max_voucher_validity: i64,
+}
+
+/// Labels and statements are UTF-8 text left-aligned in the buffer and padded with
+/// zero bytes
+pub fn is_valid_label(label: &[u8]) -> bool {
+    let len = label.iter().rposition(|byte| *byte != 0).map_or(0, |last| last + 1);
+    match std::str::from_utf8(&label[..len]) {
+        Ok(text) => !text.chars().any(char::is_control),
+        Err(_) => false,
+    }
+}
+
+/// Merchant-issued invoice, seeded by `[b"invoice", merchant, invoice_id]`
+#[account]
+#[derive(InitSpace)]
+pub struct Invoice {
+    pub merchant: Pubkey,
+    pub invoice_id: [u8; 16],
+    pub mode: InvoiceMode,
+    pub amount: u64,
+    pub amount_paid: u64,
+    pub payment_count: u32,
+    pub status: InvoiceStatus,
+    pub created_at: i64,
+    pub bump: u8,
+    pub expires_at: i64,
+    /// Bundles that paid this invoice, so disputes can be linked back to it
+    #[max_len(MAX_INVOICE_SETTLEMENTS)]
+    pub settled_bundles: Vec<[u8; 32]>,
+    /// Fraud reports against those bundles; see `has_open_disputes`
+    pub open_disputes: u16,
+    /// Paid the invoice's rent and gets it back on close; default on invoices
+    /// created before it was recorded, read it through `rent_refund_recipient`
+    pub rent_payer: Pubkey,
+    /// When the latest of those fraud reports was filed
+    pub last_disputed_at: i64,
+}
+
+impl Invoice {
+    /// Whether a fraud report against its bundles may still be acted on, which
+    /// keeps it from closing. Like `FraudRecord::is_open`, reports stay open for
+    /// `delay` after the latest one.
+    pub fn has_open_disputes(&self, delay: i64, now: i64) -> bool {
+        self.open_disputes > 0 && delay > 0 && now < self.last_disputed_at.saturating_add(delay)
+    }
+
+    /// Where the invoice's rent goes when it closes: whoever paid it, or the
+    /// merchant for invoices created before the payer was recorded
+    pub fn rent_refund_recipient(&self) -> Pubkey {
+        if self.rent_payer == Pubkey::default() {
+            self.merchant
+        } else {
+            self.rent_payer
+        }
+    }
+
+    pub fn is_expired(&self, now: i64) -> bool {
+        now > self.expires_at
+    }
+
+    /// Check a settlement of `amount` against the invoice mode without mutating it
+    pub fn check_payment(&self, amount: u64) -> std::result::Result<(), BeamError> {
+        if self.status == InvoiceStatus::Paid {
+            return Err(BeamError::InvoiceAlreadyPaid);
+        }
+        if self.settled_bundles.len() >= MAX_INVOICE_SETTLEMENTS {
+            return Err(BeamError::InvoicePaymentLimitReached);
+        }
+        match self.mode {
+            InvoiceMode::Exact => {
+                if amount > self.amount {
+                    return Err(BeamError::InvoiceOverpaid);
+                }
+                if amount < self.amount {
+                    return Err(BeamError::InvoiceUnderpaid);
+                }
+            }
+            InvoiceMode::UpTo => {
+                let total = self
+                    .amount_paid
+                    .checked_add(amount)
+                    .ok_or(BeamError::Overflow)?;
+                if total > self.amount {
+                    return Err(BeamError::InvoiceOverpaid);
+                }
+            }
+            InvoiceMode::Open => {}
+        }
+        Ok(())
+    }
+
+    /// Apply a settlement already accepted by `check_payment`
+    pub fn record_payment(
+        &mut self,
+        amount: u64,
+        bundle_hash: [u8; 32],
+    ) -> std::result::Result<(), BeamError> {
+        self.settled_bundles.push(bundle_hash);
+        self.amount_paid = self
+            .amount_paid
+            .checked_add(amount)
+            .ok_or(BeamError::Overflow)?;
+        self.payment_count = self
+            .payment_count
+            .checked_add(1)
+            .ok_or(BeamError::Overflow)?;
+        self.status = match self.mode {
+            InvoiceMode::UpTo if self.amount_paid < self.amount => InvoiceStatus::PartiallyPaid,
+            _ => InvoiceStatus::Paid,
+        };
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace, Default)]
+pub struct SpendBucket {
+    /// Days since the unix epoch
+    pub day: u32,
+    pub amount: u64,
+}
+
+/// Merchant-funded cashback, seeded by `[b"cashback", merchant]`. The vault is a token
+/// account owned by this PDA; settlements that pass it pay `rate_bps` of the amount back.
+#[account]
+#[derive(InitSpace)]
+pub struct CashbackProgram {
+    pub merchant: Pubkey,
+    pub vault: Pubkey,
+    pub rate_bps: u16,
+    pub total_paid: u64,
+    pub bump: u8,
+}
+
+impl CashbackProgram {
+    /// Cashback owed on a settlement of `amount`, rounded down
+    pub fn cashback_for(&self, amount: u64) -> u64 {
+        (u128::from(amount) * u128::from(self.rate_bps) / 10_000) as u64
+    }
+}
+
+/// Standing allowance a merchant may draw from an escrow without a payer attestation,
+/// seeded by `[b"allowance", owner, merchant]`
+#[account]
+#[derive(InitSpace)]
+pub struct Allowance {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub remaining: u64,
+    pub expires_at: i64,
+    pub bump: u8,
+}
+
+/// Standing authorization the payer signs once while online: `merchant` may pull up
+/// to `per_pull_cap` at a time and `total_cap` altogether until `expires_at`, with
+/// only its own signature. Seeded by `[b"permit", owner, merchant]`.
+///
+/// Revoking leaves the account in place, so a pull the merchant already sent
+/// fails with `PermitRevoked` rather than a missing account; the owner closes
+/// it afterwards with `close_permit`.
+#[account]
+#[derive(InitSpace)]
+pub struct Permit {
+    pub owner: Pubkey,
+    pub merchant: Pubkey,
+    pub total_cap: u64,
+    pub per_pull_cap: u64,
+    pub pulled: u64,
+    pub pull_count: u32,
+    pub expires_at: i64,
+    pub bump: u8,
+    /// When the owner revoked the permit, zero while it stands
+    pub revoked_at: i64,
+}
+
+impl Permit {
+    /// What the merchant may still pull in total
+    pub fn remaining(&self) -> u64 {
+        self.total_cap.saturating_sub(self.pulled)
+    }
+
+    pub fn is_revoked(&self) -> bool {
+        self.revoked_at != 0
+    }
+
+    pub fn status(&self, now: i64) -> PermitStatus {
+        if self.is_revoked() {
+            PermitStatus::Revoked
+        } else if now > self.expires_at {
+            PermitStatus::Expired
+        } else if self.remaining() == 0 {
+            PermitStatus::Exhausted
+        } else {
+            PermitStatus::Active
+        }
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum PermitStatus {
+    Active,
+    /// The total cap has been pulled in full
+    Exhausted,
+    Expired,
+    Revoked,
+}
+
+/// One permit as returned by `get_permits`
+#[derive(AnchorSerialize, AnchorDeserialize, Clone)]
+pub struct PermitSummary {
+    pub merchant: Pubkey,
+    pub remaining: u64,
+    pub expires_at: i64,
+    pub status: PermitStatus,
+}
+
+/// Lets another program settle from an escrow by CPI, up to `cap` in each
+/// `period_secs` window. Seeded by `[b"delegated_spender", owner, program]`
+#[account]
+#[derive(InitSpace)]
+pub struct DelegatedSpender {
+    pub owner: Pubkey,
+    /// Program whose top-level instruction must be the one invoking the settlement
+    pub program: Pubkey,
+    pub cap: u64,
+    pub period_secs: i64,
+    pub period_start: i64,
+    pub spent_in_period: u64,
+    pub bump: u8,
+}
+
+impl DelegatedSpender {
+    fn period_lapsed(&self, now: i64) -> bool {
+        now >= self.period_start.saturating_add(self.period_secs)
+    }
+
+    /// Amount spent in the period containing `now`
+    pub fn spent(&self, now: i64) -> u64 {
+        if self.period_lapsed(now) {
+            0
+        } else {
+            self.spent_in_period
+        }
+    }
+
+    /// Count `amount` against the current period, starting a new one if the last lapsed
+    pub fn record_spend(&mut self, amount: u64, now: i64) {
+        if self.period_lapsed(now) {
+            self.period_start = now;
+            self.spent_in_period = 0;
+        }
+        self.spent_in_period = self.spent_in_period.saturating_add(amount);
+    }
+}
+
+/// Admin instructions recorded in the `AdminAuditLog`; the discriminant is the
+/// instruction tag hashed into the chain
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum AdminAction {
+    InitializeConfig,
+    UpdateConfig,
+    HaltSettlements,
+    ResumeSettlements,
+    DeclareSunset,
+    CancelSunset,
+    EmergencyUnlockStake,
+    SetArbiters,
+    SetFraudPenalty,
+    AdjustReputation,
+    SetFallbackVerifier,
+    RevokeFallbackVerifier,
+    PruneFraudRecords,
+    ProposeAdmin,
+    CancelAdminProposal,
+    AcceptAdmin,
+    SetMaxReasonableAmount,
+}
+
+/// Tamper-evident trail of admin instructions, seeded by `[b"admin_audit"]`. Each
+/// one moves `head` to `sha256(head || tag || params_hash || timestamp)` and emits
+/// the preimage, so replaying the emitted entries must reproduce `head`.
+/// `migrate_config` only resizes the config and isn't recorded.
+#[account]
+#[derive(InitSpace)]
+pub struct AdminAuditLog {
+    pub head: [u8; 32],
+    /// Entries appended so far
+    pub length: u64,
+    pub bump: u8,
+}
+
+impl AdminAuditLog {
+    /// Link the next entry into the chain and return the head it replaced
+    pub fn append(&mut self, action: AdminAction, params_hash: &[u8; 32], timestamp: i64) -> [u8; 32] {
+        let prev_hash = self.head;
+        self.head = hash::hashv(&[
+            &prev_hash,
+            &[action as u8],
+            params_hash,
+            &timestamp.to_le_bytes(),
+        ])
+        .to_bytes();
+        self.length = self.length.saturating_add(1);
+        prev_hash
+    }
+}
+
+/// Liveness beacon the primary verifier refreshes, seeded by `[b"verifier_heartbeat"]`
+#[account]
+#[derive(InitSpace)]
+pub struct VerifierHeartbeat {
+    /// Strictly increasing across beats so a beat can't be replayed
+    pub counter: u64,
+    pub last_beat: i64,
+    pub bump: u8,
+}
+
+/// Settlement fees earned by one verifier key, seeded by `[b"verifier_fees", verifier]`.
+/// Fees are paid into `fee_vault` and stay claimable after the key is rotated out.
+#[account]
+#[derive(InitSpace)]
+pub struct VerifierFeeAccount {
+    pub verifier: Pubkey,
+    /// Token account owned by this PDA that fees are paid into
+    pub fee_vault: Pubkey,
+    pub earned: u64,
+    pub claimed: u64,
+    pub bump: u8,
+}
+
+/// Bond a verifier key posts to answer for its attestations, seeded by
+/// `[b"verifier_bond", verifier]`. Held in `vault`, owned by this PDA, until the key
+/// deregisters and the cooldown passes.
+#[account]
+#[derive(InitSpace)]
+pub struct VerifierBond {
+    pub verifier: Pubkey,
+    pub vault: Pubkey,
+    /// What the vault holds for the bond
+    pub amount: u64,
+    /// Taken by `slash_verifier` over the bond's lifetime
+    pub slashed: u64,
+    /// When the key stepped down as a verifier; zero while it stands behind new
+    /// attestations
+    pub deregistered_at: i64,
+    pub bump: u8,
+}
+
+impl VerifierBond {
+    pub fn is_deregistered(&self) -> bool {
+        self.deregistered_at != 0
+    }
+}
+
+/// A bundle a verifier's bond was slashed for, seeded by
+/// `[b"verifier_slash", verifier, bundle_hash]` so each bundle costs a verifier
+/// at most once
+#[account]
+#[derive(InitSpace)]
+pub struct VerifierSlash {
+    pub verifier: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub payer: Pubkey,
+    pub amount: u64,
+    pub slashed_at: i64,
+    pub bump: u8,
+}
+
+/// Fraud records pruned from a payer's primary registry by `prune_fraud_records`,
+/// seeded by `[b"fraud_archive", owner]`. Records keep their registry order and
+/// are never rewritten.
+#[account]
+#[derive(InitSpace)]
+pub struct FraudArchive {
+    pub owner: Pubkey,
+    #[max_len(MAX_ARCHIVED_FRAUD_RECORDS)]
+    pub records: Vec<FraudRecord>,
+    /// Records archived over the account's lifetime
+    pub total_archived: u64,
+    pub bump: u8,
+}
+
+impl FraudArchive {
+    /// Append pruned records; refuses the whole lot rather than dropping any
+    pub fn archive(&mut self, records: &[FraudRecord]) -> Result<()> {
+        require!(
+            self.records.len() + records.len() <= MAX_ARCHIVED_FRAUD_RECORDS,
+            BeamError::FraudArchiveFull
+        );
+        self.records.extend_from_slice(records);
+        self.total_archived = self.total_archived.saturating_add(records.len() as u64);
+        Ok(())
+    }
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ChargebackReason {
+    NotDelivered,
+    NotAsDescribed,
+    Unauthorized,
+    Other,
+}
+
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq, InitSpace)]
+pub enum ChargebackStatus {
+    /// Raised by the payer, awaiting the merchant
+    Requested,
+    /// The merchant attached delivery proof in time
+    Responded,
+    /// Decided for the payer; a merchant who never responded loses by default
+    Upheld,
+    /// Decided for the merchant by the arbiters
+    Rejected,
+}
+
+/// Arbiters' ruling on a responded chargeback
+#[derive(AnchorSerialize, AnchorDeserialize, Clone, Copy, PartialEq, Eq)]
+pub enum ChargebackOutcome {
+    ForPayer,
+    ForMerchant,
+}
+
+/// A payer's dispute of one of their settled bundles, seeded by
+/// `[b"chargeback", payer, bundle_hash]` so each bundle can be charged back once.
+/// No funds are held back for it; holding them is `SettlementHold`'s job.
+#[account]
+#[derive(InitSpace)]
+pub struct Chargeback {
+    pub payer: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub amount: u64,
+    pub settlement_index: u64,
+    pub settled_at: i64,
+    pub reason: ChargebackReason,
+    pub status: ChargebackStatus,
+    pub requested_at: i64,
+    pub bump: u8,
+    /// Last moment the merchant may respond, fixed when the chargeback is raised
+    pub respond_by: i64,
+    /// Merchant's defense: hash of their delivery proof and where to fetch it, as
+    /// UTF-8 padded with zero bytes. Zero until they respond.
+    pub evidence_hash: [u8; 32],
+    pub evidence_uri: [u8; CHARGEBACK_URI_LEN],
+    pub responded_at: i64,
+    pub resolved_at: i64,
+}
+
+impl Chargeback {
+    pub fn is_response_window_open(&self, now: i64) -> bool {
+        now <= self.respond_by
+    }
+
+    pub fn is_resolved(&self) -> bool {
+        matches!(self.status, ChargebackStatus::Upheld | ChargebackStatus::Rejected)
+    }
+}
+
+/// A settlement's payout held back from the merchant in `hold_vault`, seeded by
+/// `[b"settlement_hold", escrow, settlement_index]`. The payer may dispute it for a
+/// refund before `release_at`; from then on anyone may release it to the merchant.
+/// Either way the hold and its vault are closed to `rent_payer`.
+#[account]
+#[derive(InitSpace)]
+pub struct SettlementHold {
+    pub payer: Pubkey,
+    pub escrow: Pubkey,
+    pub merchant: Pubkey,
+    pub bundle_hash: [u8; 32],
+    pub settlement_index: u64,
+    /// Token account owned by this hold
+    pub hold_vault: Pubkey,
+    /// What reached the vault
+    pub amount: u64,
+    pub held_at: i64,
+    pub release_at: i64,
+    pub rent_payer: Pubkey,
+    pub bump: u8,
+    /// When the payer disputed it, zero if never. A disputed hold no longer
+    /// releases; the arbiters decide where the funds go.
+    pub disputed_at: i64,
+}
+
+/// Guarantor's consent to cover a payer's settlement shortfalls from its own escrow,
+/// seeded by `[b"guarantee", guarantor, payer]`
+#[account]
+#[derive(InitSpace)]
+pub struct Guarantee {
+    pub guarantor: Pubkey,
+    pub payer: Pubkey,
+    /// Most the guarantor will ever cover for this payer
+    pub max_exposure: u64,
+    /// Total drawn so far; counts against `max_exposure`
+    pub drawn: u64,
+    pub bump: u8,
+}
+
+/// Underwriter's credit for a payer's settlement shortfalls, paid from `vault` and
+/// seeded by `[b"credit_line", underwriter, payer]`. What is drawn plus the fee
+/// becomes `OfflineEscrowAccount::credit_debt`, repaid from the payer's next deposits.
+#[account]
+#[derive(InitSpace)]
+pub struct CreditLine {
+    pub underwriter: Pubkey,
+    pub payer: Pubkey,
+    /// Token account owned by this PDA that shortfalls are drawn from
+    pub vault: Pubkey,
+    /// Most the payer may owe at once, fees included
+    pub limit: u64,
+    /// Fee added to the debt on each draw, in bps of the amount drawn
+    pub fee_bps: u16,
+    /// Lowest reputation score that may still draw
+    pub min_reputation: i32,
+    /// Set by the underwriter to stop new draws; repayments still land
+    pub frozen: bool,
+    pub bump: u8,
+}
+
+impl CreditLine {
+    /// Underwriter fee on drawing `amount`, rounded up
+    pub fn fee_on(&self, amount: u64) -> Option<u64> {
+        let fee = (u128::from(amount) * u128::from(self.fee_bps)).div_ceil(10_000);
+        u64::try_from(fee).ok()
+    }
 }
@@ -0,0 +1,69 @@
+//! Token-2022 transfer fee support.
+//!
+//! Mints with the transfer fee extension withhold part of every transfer in the
+//! destination account, and the token program refuses plain transfers of them.
+//! Funding, withdrawal and settlement take the escrow mint so they can move such
+//! tokens with `transfer_checked` and book only what actually arrives. Every other
+//! path still uses plain transfers, so it fails for a fee-bearing mint instead of
+//! letting `escrow_balance` drift from the vault.
+
+use anchor_lang::prelude::*;
+use anchor_spl::token_2022::spl_token_2022::extension::transfer_fee::TransferFeeConfig;
+use anchor_spl::token_interface::{
+    self, get_mint_extension_data, Mint, Transfer, TransferChecked,
+};
+
+use crate::BeamError;
+
+/// Fee withheld from a transfer of `amount`; zero for mints without the extension
+pub fn transfer_fee(mint: &InterfaceAccount<Mint>, amount: u64) -> Result<u64> {
+    let Ok(fee_config) = get_mint_extension_data::<TransferFeeConfig>(&mint.to_account_info())
+    else {
+        return Ok(0);
+    };
+    let epoch = Clock::get()?.epoch;
+    Ok(fee_config
+        .calculate_epoch_fee(epoch, amount)
+        .ok_or(BeamError::Overflow)?)
+}
+
+/// Fee to add on top of a transfer so that `net` arrives
+pub fn inverse_transfer_fee(mint: &InterfaceAccount<Mint>, net: u64) -> Result<u64> {
+    let Ok(fee_config) = get_mint_extension_data::<TransferFeeConfig>(&mint.to_account_info())
+    else {
+        return Ok(0);
+    };
+    let epoch = Clock::get()?.epoch;
+    Ok(fee_config
+        .calculate_inverse_epoch_fee(epoch, net)
+        .ok_or(BeamError::Overflow)?)
+}
+
+/// Move `amount` with `transfer_checked` when the mint is given, a plain transfer otherwise
+pub fn transfer_tokens<'info>(
+    token_program: AccountInfo<'info>,
+    from: AccountInfo<'info>,
+    to: AccountInfo<'info>,
+    authority: AccountInfo<'info>,
+    mint: Option<&InterfaceAccount<'info, Mint>>,
+    amount: u64,
+    signer_seeds: &[&[&[u8]]],
+) -> Result<()> {
+    match mint {
+        Some(mint) => {
+            let cpi_accounts = TransferChecked {
+                from,
+                mint: mint.to_account_info(),
+                to,
+                authority,
+            };
+            let cpi_ctx = CpiContext::new_with_signer(token_program, cpi_accounts, signer_seeds);
+            token_interface::transfer_checked(cpi_ctx, amount, mint.decimals)
+        }
+        None => {
+            let cpi_accounts = Transfer { from, to, authority };
+            let cpi_ctx = CpiContext::new_with_signer(token_program, cpi_accounts, signer_seeds);
+            token_interface::transfer(cpi_ctx, amount)
+        }
+    }
+}
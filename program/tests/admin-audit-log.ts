@@ -0,0 +1,109 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { createHash } from "crypto";
+import { assert } from "chai";
+import { ensureConfig } from "./fixtures";
+
+describe("admin audit log", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // AdminAction discriminants
+  const SET_FALLBACK_VERIFIER = 10;
+  const REVOKE_FALLBACK_VERIFIER = 11;
+  const [auditLog] = PublicKey.findProgramAddressSync(
+    [Buffer.from("admin_audit")],
+    program.programId
+  );
+  let config: PublicKey;
+
+  const sha256 = (...parts: Buffer[]) =>
+    createHash("sha256").update(Buffer.concat(parts)).digest();
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const recorded = async (signature: string) =>
+    (await eventsOf(signature)).find((event) => event.name === "adminActionRecorded").data;
+
+  // Recomputes an entry's head from its emitted preimage
+  const chain = (prevHash: number[], action: number, paramsHash: Buffer, timestamp: anchor.BN) =>
+    sha256(
+      Buffer.from(prevHash),
+      Buffer.from([action]),
+      paramsHash,
+      timestamp.toArrayLike(Buffer, "le", 8)
+    );
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+  });
+
+  it("Chains each admin action onto the previous head", async () => {
+    const verifier = Keypair.generate().publicKey;
+    const cap = new anchor.BN(5_000000);
+    const before = await program.account.adminAuditLog.fetch(auditLog);
+
+    const setSignature = await program.methods
+      .setFallbackVerifier(verifier, cap)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc({ commitment: "confirmed" });
+    const set = await recorded(setSignature);
+
+    assert.deepEqual(set.action, { setFallbackVerifier: {} });
+    assert.ok(set.admin.equals(provider.wallet.publicKey));
+    assert.deepEqual(set.prevHash, before.head);
+    assert.deepEqual(
+      Buffer.from(set.paramsHash),
+      sha256(verifier.toBuffer(), cap.toArrayLike(Buffer, "le", 8))
+    );
+    assert.deepEqual(
+      Buffer.from(set.head),
+      chain(set.prevHash, SET_FALLBACK_VERIFIER, Buffer.from(set.paramsHash), set.timestamp)
+    );
+    assert.equal(set.length.toNumber(), before.length.toNumber() + 1);
+
+    const revokeSignature = await program.methods
+      .revokeFallbackVerifier()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc({ commitment: "confirmed" });
+    const revoke = await recorded(revokeSignature);
+
+    assert.deepEqual(revoke.prevHash, set.head);
+    assert.deepEqual(Buffer.from(revoke.paramsHash), sha256(verifier.toBuffer()));
+    assert.deepEqual(
+      Buffer.from(revoke.head),
+      chain(revoke.prevHash, REVOKE_FALLBACK_VERIFIER, Buffer.from(revoke.paramsHash), revoke.timestamp)
+    );
+
+    const after = await program.account.adminAuditLog.fetch(auditLog);
+    assert.deepEqual(after.head, revoke.head);
+    assert.equal(after.length.toNumber(), before.length.toNumber() + 2);
+  });
+
+  it("Leaves the chain untouched when an admin action fails", async () => {
+    const before = await program.account.adminAuditLog.fetch(auditLog);
+    try {
+      await program.methods
+        .setFallbackVerifier(PublicKey.default, new anchor.BN(1))
+        .accountsPartial({ config, admin: provider.wallet.publicKey })
+        .rpc();
+      assert.fail("Should have failed with InvalidConfig");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConfig");
+    }
+    const after = await program.account.adminAuditLog.fetch(auditLog);
+    assert.deepEqual(after.head, before.head);
+    assert.equal(after.length.toNumber(), before.length.toNumber());
+  });
+});
@@ -0,0 +1,128 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { ensureConfig } from "./fixtures";
+
+describe("two-step admin transfer", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const nominee = Keypair.generate();
+  const replacement = Keypair.generate();
+  let config: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const propose = (newAdmin: PublicKey, admin?: Keypair) =>
+    program.methods
+      .proposeAdmin(newAdmin)
+      .accountsPartial({ config, admin: admin ? admin.publicKey : provider.wallet.publicKey })
+      .signers(admin ? [admin] : [])
+      .rpc({ commitment: "confirmed" });
+
+  const cancel = () =>
+    program.methods
+      .cancelAdminProposal()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc({ commitment: "confirmed" });
+
+  const accept = (signer: Keypair) =>
+    program.methods
+      .acceptAdmin()
+      .accountsPartial({ config, pendingAdmin: signer.publicKey })
+      .signers([signer])
+      .rpc({ commitment: "confirmed" });
+
+  // An empty config update, which only the admin may send
+  const touchConfig = (admin: Keypair | null) =>
+    program.methods
+      .updateConfig({})
+      .accountsPartial({ config, admin: admin ? admin.publicKey : provider.wallet.publicKey })
+      .signers(admin ? [admin] : [])
+      .rpc();
+
+  const configState = () => program.account.programConfig.fetch(config);
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+  });
+
+  it("Rejects a default or unchanged nominee", async () => {
+    await expectError(propose(PublicKey.default), "InvalidPendingAdmin");
+    await expectError(propose(provider.wallet.publicKey), "InvalidPendingAdmin");
+    await expectError(cancel(), "NoPendingAdmin");
+  });
+
+  it("Records the nominee while the current admin keeps control", async () => {
+    const signature = await propose(nominee.publicKey);
+    const proposed = (await eventsOf(signature)).find((event) => event.name === "adminProposed");
+    assert.ok(proposed.data.pendingAdmin.equals(nominee.publicKey));
+
+    const state = await configState();
+    assert.ok(state.admin.equals(provider.wallet.publicKey));
+    assert.ok(state.pendingAdmin.equals(nominee.publicKey));
+    await touchConfig(null);
+    await expectError(touchConfig(nominee), "Unauthorized");
+    await expectError(propose(replacement.publicKey, nominee), "Unauthorized");
+  });
+
+  it("Only lets the nominee accept", async () => {
+    await expectError(accept(replacement), "NotPendingAdmin");
+  });
+
+  it("Refuses a cancelled proposal", async () => {
+    const signature = await cancel();
+    const cancelled = (await eventsOf(signature)).find(
+      (event) => event.name === "adminProposalCancelled"
+    );
+    assert.ok(cancelled.data.pendingAdmin.equals(nominee.publicKey));
+    assert.ok((await configState()).pendingAdmin.equals(PublicKey.default));
+    await expectError(accept(nominee), "NotPendingAdmin");
+  });
+
+  it("Refuses a superseded nominee and hands over to the current one", async () => {
+    await propose(nominee.publicKey);
+    await propose(replacement.publicKey);
+    await expectError(accept(nominee), "NotPendingAdmin");
+
+    const signature = await accept(replacement);
+    const transferred = (await eventsOf(signature)).find((event) => event.name === "adminTransferred");
+    assert.ok(transferred.data.previousAdmin.equals(provider.wallet.publicKey));
+    assert.ok(transferred.data.newAdmin.equals(replacement.publicKey));
+
+    const state = await configState();
+    assert.ok(state.admin.equals(replacement.publicKey));
+    assert.ok(state.pendingAdmin.equals(PublicKey.default));
+    await expectError(touchConfig(null), "Unauthorized");
+    await touchConfig(replacement);
+  });
+
+  it("Hands the admin role back the same way", async () => {
+    await propose(provider.wallet.publicKey, replacement);
+    await program.methods
+      .acceptAdmin()
+      .accountsPartial({ config, pendingAdmin: provider.wallet.publicKey })
+      .rpc();
+    assert.ok((await configState()).admin.equals(provider.wallet.publicKey));
+  });
+});
@@ -0,0 +1,146 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import * as crypto from "crypto";
+import * as ed25519 from "@noble/ed25519";
+import { assert } from "chai";
+import {
+  AttestationRole,
+  createAttestationProof,
+  signWithKeyShares,
+} from "./attestation-helper";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("aggregate attestation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AGGREGATE = 1 << 1;
+  // Device share weighs 2, cloud and backup shares 1 each; 3 is needed
+  const deviceKey = Uint8Array.from(crypto.randomBytes(32));
+  const cloudKey = Uint8Array.from(crypto.randomBytes(32));
+  const backupKey = Uint8Array.from(crypto.randomBytes(32));
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setEnabled = (aggregateAttestation: boolean) =>
+    program.methods
+      .updateConfig({ aggregateAttestation })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const register = (shares: PublicKey[], weights: number[], threshold: number) =>
+    program.methods
+      .registerAggregateKey({ shares, weights, threshold })
+      .accountsPartial({ escrowAccount: fixture.escrowPDA, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const settle = async (nonce: number, shares: [number, Uint8Array][]) => {
+    const bundleId = `aggregate-${nonce}`;
+    const proof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      1_000000,
+      nonce
+    );
+    return program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), bundleId, {
+        payerProof: await signWithKeyShares(proof, shares),
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  after(async () => {
+    await setEnabled(false);
+  });
+
+  it("Rejects aggregate proofs until the config enables them", async () => {
+    await expectError(settle(1, [[0, deviceKey]]), "AggregateAttestationDisabled");
+    await setEnabled(true);
+    const policy = await program.methods.getAttestationPolicy().accountsPartial({ config }).view();
+    assert.equal(policy.acceptedSchemes & AGGREGATE, AGGREGATE);
+  });
+
+  it("Needs the payer to have registered key shares", async () => {
+    await expectError(settle(1, [[0, deviceKey]]), "AggregateKeyNotRegistered");
+  });
+
+  it("Refuses share sets whose threshold can't be reached", async () => {
+    const device = new PublicKey(await ed25519.getPublicKeyAsync(deviceKey));
+    await expectError(
+      register([device, PublicKey.default, PublicKey.default, PublicKey.default], [2, 0, 0, 0], 3),
+      "InvalidAggregateKey"
+    );
+  });
+
+  it("Settles when the signing shares reach the threshold", async () => {
+    const shares = await Promise.all(
+      [deviceKey, cloudKey, backupKey].map(
+        async (key) => new PublicKey(await ed25519.getPublicKeyAsync(key))
+      )
+    );
+    await register([...shares, PublicKey.default], [2, 1, 1, 0], 3);
+
+    await settle(1, [
+      [0, deviceKey],
+      [1, cloudKey],
+    ]);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.settlementCount.toNumber(), 1);
+    assert.equal(escrow.aggregateKey.threshold, 3);
+  });
+
+  it("Rejects proofs signed by too few shares", async () => {
+    await expectError(
+      settle(2, [
+        [1, cloudKey],
+        [2, backupKey],
+      ]),
+      "InvalidAttestation"
+    );
+    // A share signing twice still counts once
+    await expectError(
+      settle(2, [
+        [0, deviceKey],
+        [0, deviceKey],
+      ]),
+      "InvalidAttestation"
+    );
+    // Signatures by an unregistered key add nothing
+    await expectError(
+      settle(2, [
+        [0, deviceKey],
+        [1, backupKey],
+      ]),
+      "InvalidAttestation"
+    );
+  });
+});
@@ -0,0 +1,116 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, airdrop, createEscrowFixture } from "./fixtures";
+
+describe("merchant allowances", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let allowance: PublicKey;
+
+  const grant = (amount: number, expiresAt: number) =>
+    program.methods
+      .grantAllowance(new anchor.BN(amount), new anchor.BN(expiresAt))
+      .accountsPartial({
+        allowance,
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        merchant: fixture.merchant.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const draw = (amount: number) =>
+    program.methods
+      .drawAllowance(new anchor.BN(amount))
+      .accountsPartial({
+        allowance,
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        merchant: fixture.merchant.publicKey,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.merchant])
+      .rpc();
+
+  const revoke = () =>
+    program.methods
+      .revokeAllowance()
+      .accountsPartial({ allowance, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 100_000000);
+    await airdrop(provider, fixture.merchant.publicKey);
+    allowance = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("allowance"),
+        fixture.owner.publicKey.toBuffer(),
+        fixture.merchant.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+  });
+
+  it("Lets the merchant draw with only its own signature", async () => {
+    await grant(30_000000, Math.floor(Date.now() / 1000) + 3600);
+    await draw(12_000000);
+
+    const state = await program.account.allowance.fetch(allowance);
+    assert.equal(state.remaining.toNumber(), 18_000000);
+    assert.equal(
+      Number(
+        (await getAccount(provider.connection, fixture.merchantTokenAccount))
+          .amount
+      ),
+      12_000000
+    );
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 88_000000);
+  });
+
+  it("Rejects draws beyond the remaining allowance", async () => {
+    try {
+      await draw(18_000001);
+      assert.fail("Should have failed with AllowanceExceeded");
+    } catch (err) {
+      assert.include(err.toString(), "AllowanceExceeded");
+    }
+  });
+
+  it("Closes the allowance on revoke", async () => {
+    await revoke();
+    assert.isNull(await provider.connection.getAccountInfo(allowance));
+    try {
+      await draw(1_000000);
+      assert.fail("Should have failed after revoke");
+    } catch (err) {
+      assert.ok(err);
+    }
+  });
+
+  it("Rejects draws after expiry", async () => {
+    await grant(10_000000, Math.floor(Date.now() / 1000) + 2);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    try {
+      await draw(1_000000);
+      assert.fail("Should have failed with AllowanceExpired");
+    } catch (err) {
+      assert.include(err.toString(), "AllowanceExpired");
+    }
+    await revoke();
+  });
+});
@@ -0,0 +1,116 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("amount sanity check", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // ESCROW_MINT_DECIMALS_RECORDED
+  const DECIMALS_RECORDED = 1 << 3;
+  const MAX_AMOUNT = 100_000000;
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setMaxAmount = (mint: PublicKey, maxAmount: number) =>
+    program.methods
+      .setMaxReasonableAmount(mint, new anchor.BN(maxAmount))
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const setCheck = (enabled: boolean) =>
+    program.methods
+      .setAmountSanityCheck(enabled)
+      .accountsPartial({ escrowAccount: fixture.escrowPDA, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(amount), new anchor.BN(nonce), `sanity-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 500_000000);
+    await setMaxAmount(fixture.mint, MAX_AMOUNT);
+  });
+
+  after(async () => {
+    await setMaxAmount(fixture.mint, 0);
+  });
+
+  it("Rejects a ceiling for the default mint", async () => {
+    await expectError(setMaxAmount(PublicKey.default, MAX_AMOUNT), "InvalidConfig");
+  });
+
+  it("Rejects settlements above the mint's ceiling", async () => {
+    await expectError(settle(MAX_AMOUNT + 1, 1), "AmountImplausiblyLarge");
+    await settle(MAX_AMOUNT, 1);
+  });
+
+  it("Lets an owner who opted out settle large amounts", async () => {
+    await setCheck(false);
+    await settle(150_000000, 2);
+    await setCheck(true);
+    await expectError(settle(150_000000, 3), "AmountImplausiblyLarge");
+  });
+
+  it("Stops checking once the ceiling is removed", async () => {
+    await setMaxAmount(fixture.mint, 0);
+    await settle(150_000000, 3);
+    await setMaxAmount(fixture.mint, MAX_AMOUNT);
+  });
+
+  it("Records the mint's decimals for escrows created without the mint", async () => {
+    let escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.status & DECIMALS_RECORDED, 0);
+
+    await program.methods
+      .recordMintDecimals()
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        mint: fixture.mint,
+      })
+      .rpc();
+
+    escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.mintDecimals, 6);
+    assert.equal(escrow.status & DECIMALS_RECORDED, DECIMALS_RECORDED);
+  });
+
+  it("Refuses a mint other than the vault's", async () => {
+    const other = await createEscrowFixture(provider, program, 1_000000);
+    await expectError(
+      program.methods
+        .recordMintDecimals()
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          escrowTokenAccount: fixture.escrowTokenAccount,
+          mint: other.mint,
+        })
+        .rpc(),
+      "InvalidEscrowTokenAccount"
+    );
+  });
+});
@@ -1,5 +1,5 @@
 import * as anchor from "@coral-xyz/anchor";
-import { PublicKey } from "@solana/web3.js";
+import { Keypair, PublicKey } from "@solana/web3.js";
 import * as crypto from "crypto";
 import * as ed25519 from "@noble/ed25519";
 
@@ -18,14 +18,72 @@ export enum AttestationRole {
   Merchant = 1,
 }
 
+// Mirrors the program's `SettlementDeadline` enum
+export type SettlementDeadline =
+  | { timestamp: { 0: anchor.BN } }
+  | { slot: { 0: anchor.BN } };
+
 export interface AttestationProof {
   attestationRoot: number[];
   attestationNonce: number[];
   attestationTimestamp: anchor.BN;
   verifierSignature: number[];
+  deadline: SettlementDeadline | null;
+  fallbackReason: number | null;
+  aggregateSignatures: ShareSignature[] | null;
+  validityWindow: ValidityWindow | null;
+}
+
+// Mirrors the program's `ValidityWindow`
+export interface ValidityWindow {
+  validFrom: anchor.BN;
+  validUntil: anchor.BN;
+}
+
+// Mirrors the program's `ShareSignature`
+export interface ShareSignature {
+  shareIndex: number;
+  signature: number[];
+}
+
+// Mirrors the program's `CourierCommitment`
+export interface CourierCommitment {
+  courier: PublicKey;
+  fee: anchor.BN;
 }
 
 const ATTESTATION_PREFIX = Buffer.from("beam.attestation.v1");
+const VOUCHER_PREFIX = Buffer.from("beam.voucher.v1");
+
+// Tags of the optional extras, after the deadline's 0 (timestamp) and 1 (slot)
+const EXTRA_ORDER_REF = 2;
+const EXTRA_COURIER = 3;
+const EXTRA_DEVICE_ID = 4;
+
+// The optional bundle commitments of either root, each behind its tag and only
+// when set: a non-zero order reference, the courier key and fee, and the id hash
+// of the enrolled device that signed the bundle
+function bundleExtras(
+  orderRef: Uint8Array | null,
+  courier: CourierCommitment | null,
+  deviceIdHash: Uint8Array | null
+): Buffer {
+  const parts: Buffer[] = [];
+  if (orderRef && orderRef.some((byte) => byte !== 0)) {
+    parts.push(Buffer.from([EXTRA_ORDER_REF]), Buffer.from(orderRef));
+  }
+  if (courier) {
+    parts.push(
+      Buffer.from([EXTRA_COURIER]),
+      courier.courier.toBuffer(),
+      courier.fee.toArrayLike(Buffer, "le", 8)
+    );
+  }
+  if (deviceIdHash) {
+    parts.push(Buffer.from([EXTRA_DEVICE_ID]), Buffer.from(deviceIdHash));
+  }
+  return Buffer.concat(parts);
+}
 
 export function computeAttestationRoot(
   role: AttestationRole,
@@ -35,7 +93,12 @@ export function computeAttestationRoot(
   amount: number | anchor.BN,
   bundleNonce: number | anchor.BN,
   attestationNonce: Uint8Array,
-  attestationTimestamp: number | anchor.BN
+  attestationTimestamp: number | anchor.BN,
+  deadline: SettlementDeadline | null = null,
+  orderRef: Uint8Array | null = null,
+  courier: CourierCommitment | null = null,
+  fallbackReason: number | null = null,
+  deviceIdHash: Uint8Array | null = null
 ): Uint8Array {
   const amountBN = typeof amount === "number" ? new anchor.BN(amount) : amount;
   const nonceBN =
@@ -49,6 +112,19 @@ export function computeAttestationRoot(
   const nonceBytes = nonceBN.toArrayLike(Buffer, "le", 8);
   const timestampBytes = timestampBN.toArrayLike(Buffer, "le", 8);
   const roleBytes = Buffer.from([role]);
+  // Tag byte (0 = timestamp, 1 = slot) and the deadline, only when one is set
+  const deadlineBytes = !deadline
+    ? Buffer.alloc(0)
+    : "slot" in deadline
+    ? Buffer.concat([Buffer.from([1]), deadline.slot[0].toArrayLike(Buffer, "le", 8)])
+    : Buffer.concat([
+        Buffer.from([0]),
+        deadline.timestamp[0].toTwos(64).toArrayLike(Buffer, "le", 8),
+      ]);
+  const extrasBytes = bundleExtras(orderRef, courier, deviceIdHash);
+  // The fallback reason byte, only on fallback-signed proofs
+  const fallbackBytes =
+    fallbackReason === null ? Buffer.alloc(0) : Buffer.from([fallbackReason]);
 
   // Concatenate all components for hashing (matching Solana's hashv)
   const components = Buffer.concat([
@@ -61,6 +137,9 @@ export function computeAttestationRoot(
     roleBytes,
     Buffer.from(attestationNonce),
     timestampBytes,
+    deadlineBytes,
+    extrasBytes,
+    fallbackBytes,
   ]);
 
   // Use SHA256 to match Solana's hashv behavior
@@ -74,10 +153,15 @@ export async function createAttestationProof(
   merchant: PublicKey,
   amount: number | anchor.BN,
   bundleNonce: number | anchor.BN,
-  privateKey?: Uint8Array
+  privateKey?: Uint8Array,
+  deadline: SettlementDeadline | null = null,
+  orderRef: Uint8Array | null = null,
+  courier: CourierCommitment | null = null,
+  fallbackReason: number | null = null,
+  deviceIdHash: Uint8Array | null = null,
+  attestationTimestamp: number = Math.floor(Date.now() / 1000)
 ): Promise<AttestationProof> {
   const attestationNonce = crypto.randomBytes(32);
-  const attestationTimestamp = Math.floor(Date.now() / 1000);
 
   const attestationRoot = computeAttestationRoot(
     role,
@@ -87,7 +171,12 @@ export async function createAttestationProof(
     amount,
     bundleNonce,
     attestationNonce,
-    attestationTimestamp
+    attestationTimestamp,
+    deadline,
+    orderRef,
+    courier,
+    fallbackReason,
+    deviceIdHash
   );
 
   // Sign the attestation root with the test verifier private key
@@ -99,9 +188,108 @@ export async function createAttestationProof(
     attestationNonce: Array.from(attestationNonce),
     attestationTimestamp: new anchor.BN(attestationTimestamp),
     verifierSignature: Array.from(signature),
+    deadline,
+    fallbackReason,
+    aggregateSignatures: null,
+    validityWindow: null,
   };
 }
 
+// Voucher roots commit the validity window in place of the attestation timestamp
+// and never carry a deadline or fallback reason
+export function computeVoucherRoot(
+  role: AttestationRole,
+  bundleId: string,
+  payer: PublicKey,
+  merchant: PublicKey,
+  amount: number | anchor.BN,
+  bundleNonce: number | anchor.BN,
+  attestationNonce: Uint8Array,
+  window: ValidityWindow,
+  orderRef: Uint8Array | null = null,
+  courier: CourierCommitment | null = null,
+  deviceIdHash: Uint8Array | null = null
+): Uint8Array {
+  const amountBN = typeof amount === "number" ? new anchor.BN(amount) : amount;
+  const nonceBN =
+    typeof bundleNonce === "number" ? new anchor.BN(bundleNonce) : bundleNonce;
+
+  const components = Buffer.concat([
+    VOUCHER_PREFIX,
+    Buffer.from(bundleId),
+    payer.toBuffer(),
+    merchant.toBuffer(),
+    amountBN.toArrayLike(Buffer, "le", 8),
+    nonceBN.toArrayLike(Buffer, "le", 8),
+    Buffer.from([role]),
+    Buffer.from(attestationNonce),
+    window.validFrom.toTwos(64).toArrayLike(Buffer, "le", 8),
+    window.validUntil.toTwos(64).toArrayLike(Buffer, "le", 8),
+    bundleExtras(orderRef, courier, deviceIdHash),
+  ]);
+
+  return crypto.createHash("sha256").update(components).digest();
+}
+
+export async function createVoucherProof(
+  role: AttestationRole,
+  bundleId: string,
+  payer: PublicKey,
+  merchant: PublicKey,
+  amount: number | anchor.BN,
+  bundleNonce: number | anchor.BN,
+  validFrom: number,
+  validUntil: number,
+  privateKey?: Uint8Array
+): Promise<AttestationProof> {
+  const attestationNonce = crypto.randomBytes(32);
+  const validityWindow = {
+    validFrom: new anchor.BN(validFrom),
+    validUntil: new anchor.BN(validUntil),
+  };
+  const attestationRoot = computeVoucherRoot(
+    role,
+    bundleId,
+    payer,
+    merchant,
+    amount,
+    bundleNonce,
+    attestationNonce,
+    validityWindow
+  );
+  const signature = await ed25519.signAsync(
+    attestationRoot,
+    privateKey || TEST_VERIFIER_PRIVATE_KEY
+  );
+
+  return {
+    attestationRoot: Array.from(attestationRoot),
+    attestationNonce: Array.from(attestationNonce),
+    attestationTimestamp: new anchor.BN(0),
+    verifierSignature: Array.from(signature),
+    deadline: null,
+    fallbackReason: null,
+    aggregateSignatures: null,
+    validityWindow,
+  };
+}
+
+// Replace a proof's verifier signature with signatures by the payer's key shares,
+// given as (index into the registered shares, private key) pairs
+export async function signWithKeyShares(
+  proof: AttestationProof,
+  shares: [number, Uint8Array][]
+): Promise<AttestationProof> {
+  const root = Uint8Array.from(proof.attestationRoot);
+  const aggregateSignatures = await Promise.all(
+    shares.map(async ([shareIndex, privateKey]) => ({
+      shareIndex,
+      signature: Array.from(await ed25519.signAsync(root, privateKey)),
+    }))
+  );
+  return { ...proof, verifierSignature: new Array(64).fill(0), aggregateSignatures };
+}
+
 export function getTestVerifierPublicKey(): Uint8Array {
   return TEST_VERIFIER_PUBLIC_KEY;
 }
@@ -109,3 +297,57 @@ export function getTestVerifierPublicKey(): Uint8Array {
 export function getTestVerifierPrivateKey(): Uint8Array {
   return TEST_VERIFIER_PRIVATE_KEY;
 }
+
+const PAYER_BUNDLE_PREFIX = Buffer.from("beam.bundle.v1");
+
+// What a payer signs to commit to a bundle; mirrors `payer_bundle_digest`
+export function computePayerBundleDigest(
+  bundleHash: Uint8Array,
+  merchant: PublicKey,
+  amount: number | anchor.BN,
+  nonce: number | anchor.BN
+): Buffer {
+  const amountBN = typeof amount === "number" ? new anchor.BN(amount) : amount;
+  const nonceBN = typeof nonce === "number" ? new anchor.BN(nonce) : nonce;
+  return crypto
+    .createHash("sha256")
+    .update(
+      Buffer.concat([
+        PAYER_BUNDLE_PREFIX,
+        Buffer.from(bundleHash),
+        merchant.toBuffer(),
+        amountBN.toArrayLike(Buffer, "le", 8),
+        nonceBN.toArrayLike(Buffer, "le", 8),
+      ])
+    )
+    .digest();
+}
+
+// A bundle signed by `payer` as fraud evidence, with the conflicting hash a
+// report of it must carry
+export async function signConflictingBundle(
+  payer: Keypair,
+  bundleHash: Uint8Array,
+  merchant: PublicKey,
+  amount: number | anchor.BN,
+  nonce: number | anchor.BN
+) {
+  const amountBN = typeof amount === "number" ? new anchor.BN(amount) : amount;
+  const nonceBN = typeof nonce === "number" ? new anchor.BN(nonce) : nonce;
+  const conflictingHash = computePayerBundleDigest(bundleHash, merchant, amountBN, nonceBN);
+  const signature = await ed25519.signAsync(conflictingHash, payer.secretKey.slice(0, 32));
+  return {
+    conflictingHash,
+    evidence: {
+      signedBundle: {
+        0: {
+          bundleHash: Array.from(bundleHash),
+          merchant,
+          amount: amountBN,
+          nonce: nonceBN,
+          signature: Array.from(signature),
+        },
+      },
+    },
+  };
+}
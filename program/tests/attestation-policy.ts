@@ -0,0 +1,55 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { getTestVerifierPublicKey } from "./attestation-helper";
+import { ensureConfig } from "./fixtures";
+
+describe("attestation policy", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const ED25519 = 1 << 0;
+  const FALLBACK_CAP = 5_000000;
+  const fallback = Keypair.generate().publicKey;
+  let config: PublicKey;
+
+  const policy = () => program.methods.getAttestationPolicy().accountsPartial({ config }).view();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+  });
+
+  after(async () => {
+    await program.methods
+      .revokeFallbackVerifier()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Reports the primary verifier and proof freshness", async () => {
+    const result = await policy();
+    assert.equal(result.maxAttestationAge.toNumber(), 86_400);
+    assert.equal(result.acceptedSchemes, ED25519);
+    assert.equal(result.verifierKeyCount, 1);
+    assert.ok(result.primaryVerifier.equals(new PublicKey(getTestVerifierPublicKey())));
+    assert.ok(result.fallbackVerifier.equals(PublicKey.default));
+    // Single settlements may still omit attestation
+    assert.equal(result.maxUnattestedAmount.toString(), "18446744073709551615");
+  });
+
+  it("Counts the fallback verifier once registered", async () => {
+    await program.methods
+      .setFallbackVerifier(fallback, new anchor.BN(FALLBACK_CAP))
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+    const result = await policy();
+    assert.equal(result.verifierKeyCount, 2);
+    assert.ok(result.fallbackVerifier.equals(fallback));
+    assert.equal(result.fallbackCap.toNumber(), FALLBACK_CAP);
+  });
+});
@@ -0,0 +1,100 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { createAttestationProof, AttestationRole } from "./attestation-helper";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("attestation proof validation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 1_000000;
+  const NONCE = 1;
+  const BUNDLE_ID = "zeroed-proof-bundle";
+  let fixture: EscrowFixture;
+
+  const validProof = () =>
+    createAttestationProof(
+      AttestationRole.Payer,
+      BUNDLE_ID,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      NONCE
+    );
+
+  const settleWith = (payerProof: object, merchantProof: object | null = null) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(AMOUNT),
+        new anchor.BN(NONCE),
+        BUNDLE_ID,
+        { payerProof, merchantProof } as any
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const expectMalformed = async (promise: Promise<unknown>) => {
+    try {
+      await promise;
+      assert.fail("Should have failed with MalformedAttestation");
+    } catch (err) {
+      assert.include(err.toString(), "MalformedAttestation");
+    }
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Rejects a default (all-zero) proof", async () => {
+    await expectMalformed(
+      settleWith({
+        attestationRoot: Array(32).fill(0),
+        attestationNonce: Array(32).fill(0),
+        attestationTimestamp: new anchor.BN(Math.floor(Date.now() / 1000)),
+        verifierSignature: Array(64).fill(0),
+      })
+    );
+  });
+
+  it("Rejects an all-zero attestation nonce", async () => {
+    const proof = await validProof();
+    await expectMalformed(
+      settleWith({ ...proof, attestationNonce: Array(32).fill(0) })
+    );
+  });
+
+  it("Rejects an all-zero verifier signature", async () => {
+    const proof = await validProof();
+    await expectMalformed(
+      settleWith({ ...proof, verifierSignature: Array(64).fill(0) })
+    );
+  });
+
+  it("Rejects an all-zero root", async () => {
+    const proof = await validProof();
+    await expectMalformed(
+      settleWith({ ...proof, attestationRoot: Array(32).fill(0) })
+    );
+  });
+
+  it("Checks the merchant proof too", async () => {
+    const proof = await validProof();
+    await expectMalformed(
+      settleWith(proof, { ...proof, verifierSignature: Array(64).fill(0) })
+    );
+  });
+
+  it("Still accepts a well-formed proof", async () => {
+    await settleWith(await validProof());
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.lastNonce.toNumber(), NONCE);
+  });
+});
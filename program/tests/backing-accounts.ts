@@ -0,0 +1,161 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("multiple backing token accounts", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let backing: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const escrow = () =>
+    program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const fund = (amount: number, escrowTokenAccount: PublicKey) =>
+    program.methods
+      .fundEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const withdraw = (amount: number, escrowTokenAccount: PublicKey) =>
+    program.methods
+      .withdrawEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const add = (backingTokenAccount: PublicKey) =>
+    program.methods
+      .addBackingTokenAccount()
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        backingTokenAccount,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const verifySolvency = (accounts: PublicKey[]) =>
+    program.methods
+      .verifySolvency()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA })
+      .remainingAccounts(
+        accounts.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false }))
+      )
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    backing = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      fixture.escrowPDA,
+      Keypair.generate()
+    );
+  });
+
+  it("Rejects unregistered escrow-owned accounts", async () => {
+    await expectError(fund(1_000000, backing), "InvalidEscrowTokenAccount");
+    await expectError(withdraw(1_000000, backing), "InvalidEscrowTokenAccount");
+    await expectError(
+      program.methods
+        .settleOfflinePayment(
+          new anchor.BN(1_000000),
+          new anchor.BN(1),
+          "backing-substitute",
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial({ ...settleAccounts(fixture), escrowTokenAccount: backing })
+        .signers([fixture.owner])
+        .rpc(),
+      "InvalidEscrowTokenAccount"
+    );
+  });
+
+  it("Funds, settles from and withdraws any registered account", async () => {
+    await add(backing);
+    const state = await escrow();
+    assert.ok(state.backingTokenAccounts[0].equals(backing));
+
+    await fund(10_000000, backing);
+    assert.equal((await escrow()).escrowBalance.toNumber(), 30_000000);
+
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(4_000000),
+        new anchor.BN(1),
+        "backing-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({ ...settleAccounts(fixture), escrowTokenAccount: backing })
+      .signers([fixture.owner])
+      .rpc();
+    assert.equal(await balanceOf(backing), 6_000000);
+
+    await withdraw(6_000000, backing);
+    assert.equal(await balanceOf(backing), 0);
+    assert.equal((await escrow()).escrowBalance.toNumber(), 20_000000);
+  });
+
+  it("Verifies the balance against the sum of every backing account", async () => {
+    await verifySolvency([fixture.escrowTokenAccount, backing]);
+    await expectError(
+      verifySolvency([fixture.escrowTokenAccount]),
+      "InvalidEscrowTokenAccount"
+    );
+  });
+
+  it("Removes only empty backing accounts", async () => {
+    await fund(1_000000, backing);
+    const remove = () =>
+      program.methods
+        .removeBackingTokenAccount()
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          owner: fixture.owner.publicKey,
+          backingTokenAccount: backing,
+        })
+        .signers([fixture.owner])
+        .rpc();
+    await expectError(remove(), "BackingAccountNotEmpty");
+
+    await withdraw(1_000000, backing);
+    await remove();
+    assert.ok((await escrow()).backingTokenAccounts[0].equals(PublicKey.default));
+    await expectError(fund(1_000000, backing), "InvalidEscrowTokenAccount");
+  });
+});
@@ -0,0 +1,141 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram, Transaction } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  airdrop,
+  ensureConfig,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("sponsored batch onboarding", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const USERS = 4;
+  const PER_TX = 2;
+  const INITIAL_AMOUNT = 5_000000;
+  const sponsor = Keypair.generate();
+  const users = Array.from({ length: USERS }, () => Keypair.generate());
+  let mint: PublicKey;
+  let sponsorTokenAccount: PublicKey;
+  const vaults: PublicKey[] = [];
+
+  const onboardIx = (user: Keypair, index: number) =>
+    program.methods
+      .onboardEscrow(new anchor.BN(INITIAL_AMOUNT))
+      .accountsPartial({
+        escrowAccount: findEscrowPDA(program, user.publicKey),
+        nonceRegistry: findNonceRegistryPDA(program, user.publicKey),
+        owner: user.publicKey,
+        sponsor: sponsor.publicKey,
+        sponsorTokenAccount,
+        escrowTokenAccount: vaults[index],
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .instruction();
+
+  before(async () => {
+    await ensureConfig(provider, program);
+    await airdrop(provider, sponsor.publicKey, 10);
+
+    mint = await createMint(provider.connection, sponsor, sponsor.publicKey, null, 6);
+    sponsorTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        sponsor,
+        mint,
+        sponsor.publicKey
+      )
+    ).address;
+    await mintTo(
+      provider.connection,
+      sponsor,
+      mint,
+      sponsorTokenAccount,
+      sponsor,
+      USERS * INITIAL_AMOUNT
+    );
+
+    for (const user of users) {
+      vaults.push(
+        await createAccount(
+          provider.connection,
+          sponsor,
+          mint,
+          findEscrowPDA(program, user.publicKey),
+          Keypair.generate()
+        )
+      );
+    }
+  });
+
+  it("Creates and funds several escrows in parallel transactions", async () => {
+    const sponsorLamportsBefore = await provider.connection.getBalance(sponsor.publicKey);
+
+    const batches: Promise<string>[] = [];
+    for (let start = 0; start < USERS; start += PER_TX) {
+      const chunk = users.slice(start, start + PER_TX);
+      const tx = new Transaction();
+      for (const [offset, user] of chunk.entries()) {
+        tx.add(await onboardIx(user, start + offset));
+      }
+      batches.push(provider.sendAndConfirm(tx, [sponsor, ...chunk]));
+    }
+    await Promise.all(batches);
+
+    for (const [index, user] of users.entries()) {
+      const escrow = await program.account.offlineEscrowAccount.fetch(
+        findEscrowPDA(program, user.publicKey)
+      );
+      assert.ok(escrow.owner.equals(user.publicKey));
+      assert.ok(escrow.escrowTokenAccount.equals(vaults[index]));
+      assert.equal(escrow.escrowBalance.toNumber(), INITIAL_AMOUNT);
+
+      const registry = await program.account.nonceRegistry.fetch(
+        findNonceRegistryPDA(program, user.publicKey)
+      );
+      assert.ok(registry.owner.equals(user.publicKey));
+      assert.equal(registry.lastNonce.toNumber(), 0);
+
+      const vault = await getAccount(provider.connection, vaults[index]);
+      assert.equal(Number(vault.amount), INITIAL_AMOUNT);
+    }
+
+    const sponsorTokens = await getAccount(provider.connection, sponsorTokenAccount);
+    assert.equal(Number(sponsorTokens.amount), 0);
+    // Rent for every account came from the sponsor, not the users
+    assert.isBelow(
+      await provider.connection.getBalance(sponsor.publicKey),
+      sponsorLamportsBefore
+    );
+    for (const user of users) {
+      assert.equal(await provider.connection.getBalance(user.publicKey), 0);
+    }
+  });
+
+  it("Refuses to onboard the same owner twice", async () => {
+    try {
+      await provider.sendAndConfirm(new Transaction().add(await onboardIx(users[0], 0)), [
+        sponsor,
+        users[0],
+      ]);
+      assert.fail("Should have failed");
+    } catch (err) {
+      assert.include(err.toString(), "already in use");
+    }
+  });
+});
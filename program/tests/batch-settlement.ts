@@ -0,0 +1,159 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { TOKEN_PROGRAM_ID, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { createAttestationProof, AttestationRole } from "./attestation-helper";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  fetchReturnData,
+  settleAccounts,
+} from "./fixtures";
+
+describe("batch settlement (best effort)", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 100_000000);
+  });
+
+  const item = async (amount: number, nonce: number, bundleId: string) => ({
+    amount: new anchor.BN(amount),
+    payerNonce: new anchor.BN(nonce),
+    bundleId,
+    evidence: {
+      payerProof: await createAttestationProof(
+        AttestationRole.Payer,
+        bundleId,
+        fixture.owner.publicKey,
+        fixture.merchant.publicKey,
+        amount,
+        nonce
+      ),
+      merchantProof: null,
+    },
+  });
+
+  it("Settles valid items and reports skipped ones", async () => {
+    const items = [
+      await item(1_000000, 1, "batch-1"),
+      // Stale nonce: must be skipped, not abort the batch
+      await item(1_000000, 1, "batch-stale"),
+      await item(2_000000, 2, "batch-2"),
+      // Exceeds the remaining balance
+      await item(500_000000, 3, "batch-too-large"),
+      await item(3_000000, 4, "batch-4"),
+    ];
+
+    const sig = await program.methods
+      .settleBatchBestEffort(items, false)
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+    const result = program.coder.types.decode(
+      "BatchSettlementResult",
+      await fetchReturnData(provider, sig)
+    );
+    assert.equal(result.settledMask, 0b10101);
+    assert.equal(result.settledCount, 3);
+    assert.equal(result.totalSettled.toNumber(), 6_000000);
+    assert.equal(result.itemCodes[0], 0);
+    assert.notEqual(result.itemCodes[1], 0);
+    assert.notEqual(result.itemCodes[3], 0);
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 94_000000);
+    assert.equal(escrow.lastNonce.toNumber(), 4);
+
+    const merchantAccount = await getAccount(
+      provider.connection,
+      fixture.merchantTokenAccount
+    );
+    assert.equal(merchantAccount.amount.toString(), "6000000");
+  });
+
+  it("Aggregates payouts into one transfer without changing the records", async () => {
+    const run = async (aggregate: boolean, firstNonce: number) => {
+      const items = [];
+      for (let i = 0; i < 6; i++) {
+        const nonce = firstNonce + i;
+        items.push(await item(100000, nonce, `batch-agg-${aggregate}-${nonce}`));
+      }
+      const merchantBefore = await getAccount(
+        provider.connection,
+        fixture.merchantTokenAccount
+      );
+      const sig = await program.methods
+        .settleBatchBestEffort(items, aggregate)
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc({ commitment: "confirmed" });
+      const tx = await provider.connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      });
+      const parser = new anchor.EventParser(program.programId, program.coder);
+      const settled = Array.from(parser.parseLogs(tx.meta.logMessages)).filter(
+        (event) => event.name === "paymentSettled"
+      );
+      const merchantAfter = await getAccount(
+        provider.connection,
+        fixture.merchantTokenAccount
+      );
+      const tokenProgram = tx.transaction.message.staticAccountKeys.findIndex(
+        (key) => key.equals(TOKEN_PROGRAM_ID)
+      );
+      return {
+        units: tx.meta.computeUnitsConsumed,
+        transfers: tx.meta.innerInstructions
+          .flatMap((inner) => inner.instructions)
+          .filter((ix) => ix.programIdIndex === tokenProgram).length,
+        settled,
+        paid: Number(merchantAfter.amount - merchantBefore.amount),
+      };
+    };
+
+    const separate = await run(false, 5);
+    const aggregated = await run(true, 11);
+
+    assert.equal(separate.transfers, 6);
+    assert.equal(aggregated.transfers, 1);
+    assert.equal(aggregated.paid, separate.paid);
+    assert.lengthOf(aggregated.settled, separate.settled.length);
+    assert.deepEqual(
+      aggregated.settled.map((event) => event.data.nonce.toNumber()),
+      [11, 12, 13, 14, 15, 16]
+    );
+    console.log(
+      `     CU: ${separate.units} per-bundle vs ${aggregated.units} aggregated`
+    );
+    assert.isBelow(aggregated.units, separate.units);
+
+    const registry = await program.account.nonceRegistry.fetch(
+      fixture.nonceRegistry
+    );
+    assert.equal(registry.lastNonce.toNumber(), 16);
+  });
+
+  it("Rejects an empty batch", async () => {
+    try {
+      await program.methods
+        .settleBatchBestEffort([], false)
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc();
+      assert.fail("Should have failed with InvalidBatchSize");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidBatchSize");
+    }
+  });
+});
@@ -11,7 +11,12 @@ import {
   getOrCreateAssociatedTokenAccount,
 } from "@solana/spl-token";
 import { assert } from "chai";
-import { createAttestationProof, AttestationRole } from "./attestation-helper";
+import { ensureConfig } from "./fixtures";
+import {
+  createAttestationProof,
+  AttestationRole,
+  signConflictingBundle,
+} from "./attestation-helper";
 
 describe("beam", () => {
   const provider = anchor.AnchorProvider.env();
@@ -32,6 +37,7 @@ describe("beam", () => {
   before(async () => {
     // Use provider wallet as payer
     payer = (provider.wallet as anchor.Wallet).payer;
+    await ensureConfig(provider, program);
 
     // Create USDC-like token mint
     mint = await createMint(
@@ -98,6 +104,7 @@ describe("beam", () => {
       .accounts({
         escrowAccount: escrowPDA,
         owner: payer.publicKey,
+        rentPayer: payer.publicKey,
         ownerTokenAccount: payerTokenAccount,
         escrowTokenAccount,
         tokenProgram: TOKEN_PROGRAM_ID,
@@ -151,7 +158,8 @@ describe("beam", () => {
     await program.methods
       .initializeNonceRegistry()
       .accountsPartial({
-        payer: payer.publicKey,
+        owner: payer.publicKey,
+        rentPayer: payer.publicKey,
         nonceRegistry,
         systemProgram: SystemProgram.programId,
       })
@@ -191,7 +199,9 @@ describe("beam", () => {
         evidence
       )
       .accountsPartial({
+        escrowAccount: escrowPDA,
         owner: payer.publicKey,
+        nonceRegistry,
         payer: payer.publicKey,
         merchant: merchant.publicKey,
         escrowTokenAccount,
@@ -246,7 +256,9 @@ describe("beam", () => {
         evidence
       )
       .accountsPartial({
+        escrowAccount: escrowPDA,
         owner: payer.publicKey,
+        nonceRegistry,
         payer: payer.publicKey,
         merchant: merchant.publicKey,
         escrowTokenAccount,
@@ -299,7 +311,9 @@ describe("beam", () => {
           evidence
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -312,7 +326,8 @@ describe("beam", () => {
 
       assert.fail("Should have rejected invalid attestation");
     } catch (err) {
-      assert.include(err.toString(), "InvalidAttestation");
+      // All-zero proofs are caught by the well-formedness check before verification
+      assert.include(err.toString(), "MalformedAttestation");
       console.log("✅ Invalid attestation correctly rejected");
     }
   });
@@ -342,7 +357,9 @@ describe("beam", () => {
           evidence
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -423,7 +440,9 @@ describe("beam", () => {
         evidence
       )
       .accountsPartial({
+        escrowAccount: escrowPDA,
         owner: payer.publicKey,
+        nonceRegistry,
         payer: payer.publicKey,
         merchant: merchant.publicKey,
         escrowTokenAccount,
@@ -443,7 +462,9 @@ describe("beam", () => {
           evidence
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -484,7 +505,9 @@ describe("beam", () => {
           evidence
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -543,7 +566,9 @@ describe("beam", () => {
           { payerProof, merchantProof: null }
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -572,19 +597,24 @@ describe("beam", () => {
     });
 
     it("Reports fraud and applies slashing penalty (2x amount)", async () => {
-      // Create conflicting hash (different from original)
-      const conflictingHash = Array(32)
-        .fill(0)
-        .map((_, i) => (i * 7) % 256);
-      const conflictingHashBuffer = Buffer.from(conflictingHash);
+      // The payer also signed the same payment under another bundle id
+      const { conflictingHash, evidence } = await signConflictingBundle(
+        payer,
+        Buffer.from(Array(32).fill(0).map((_, i) => (i * 7) % 256)),
+        merchant.publicKey,
+        fraudAmount,
+        fraudNonce
+      );
 
       await program.methods
         .reportFraudulentBundle(
           fraudBundleId,
-          conflictingHashBuffer,
-          { duplicateBundle: {} } // FraudReason::DuplicateBundle
+          conflictingHash,
+          { duplicateBundle: {} }, // FraudReason::DuplicateBundle
+          evidence as any
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           payer: payer.publicKey,
           reporter: reporter.publicKey,
         })
@@ -654,17 +684,24 @@ describe("beam", () => {
 
     it("Prevents duplicate fraud reports for same bundle", async () => {
       // Attempt to report the same fraud again
-      const conflictingHash = Array(32)
-        .fill(0)
-        .map((_, i) => (i * 7) % 256);
-      const conflictingHashBuffer = Buffer.from(conflictingHash);
+      const { conflictingHash, evidence } = await signConflictingBundle(
+        payer,
+        Buffer.from(Array(32).fill(0).map((_, i) => (i * 7) % 256)),
+        merchant.publicKey,
+        fraudAmount,
+        fraudNonce
+      );
 
       try {
         await program.methods
-          .reportFraudulentBundle(fraudBundleId, conflictingHashBuffer, {
-            duplicateBundle: {},
-          })
+          .reportFraudulentBundle(
+            fraudBundleId,
+            conflictingHash,
+            { duplicateBundle: {} },
+            evidence as any
+          )
           .accountsPartial({
+            escrowAccount: escrowPDA,
             payer: payer.publicKey,
             reporter: reporter.publicKey,
           })
@@ -684,10 +721,14 @@ describe("beam", () => {
 
       try {
         await program.methods
-          .reportFraudulentBundle(nonExistentBundleId, conflictingHash, {
-            other: {},
-          })
+          .reportFraudulentBundle(
+            nonExistentBundleId,
+            conflictingHash,
+            { other: {} },
+            { none: {} }
+          )
           .accountsPartial({
+            escrowAccount: escrowPDA,
             payer: payer.publicKey,
             reporter: reporter.publicKey,
           })
@@ -724,7 +765,9 @@ describe("beam", () => {
           { payerProof, merchantProof: null }
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -736,14 +779,24 @@ describe("beam", () => {
         .rpc();
 
       // Now try to report fraud (requires 2x 400 = 800 USDC, but balance is ~490 USDC)
-      const conflictingHash = Buffer.from(Array(32).fill(123));
+      const { conflictingHash, evidence } = await signConflictingBundle(
+        payer,
+        Buffer.from(Array(32).fill(123)),
+        merchant.publicKey,
+        largeAmount,
+        largeNonce
+      );
 
       try {
         await program.methods
-          .reportFraudulentBundle(largeBundleId, conflictingHash, {
-            invalidAttestation: {},
-          })
+          .reportFraudulentBundle(
+            largeBundleId,
+            conflictingHash,
+            { duplicateBundle: {} },
+            evidence as any
+          )
           .accountsPartial({
+            escrowAccount: escrowPDA,
             payer: payer.publicKey,
             reporter: reporter.publicKey,
           })
@@ -829,7 +882,9 @@ describe("beam", () => {
           { payerProof, merchantProof: null }
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -850,9 +905,11 @@ describe("beam", () => {
           .reportFraudulentBundle(
             testBundleId,
             bundleHash, // Using same hash
-            { other: {} }
+            { other: {} },
+            { none: {} }
           )
           .accountsPartial({
+            escrowAccount: escrowPDA,
             payer: payer.publicKey,
             reporter: reporter.publicKey,
           })
@@ -872,8 +929,14 @@ describe("beam", () => {
 
       try {
         await program.methods
-          .reportFraudulentBundle(emptyBundleId, conflictingHash, { other: {} })
+          .reportFraudulentBundle(
+            emptyBundleId,
+            conflictingHash,
+            { other: {} },
+            { none: {} }
+          )
           .accountsPartial({
+            escrowAccount: escrowPDA,
             payer: payer.publicKey,
             reporter: reporter.publicKey,
           })
@@ -909,7 +972,9 @@ describe("beam", () => {
           { payerProof, merchantProof: null }
         )
         .accountsPartial({
+          escrowAccount: escrowPDA,
           owner: payer.publicKey,
+          nonceRegistry,
           payer: payer.publicKey,
           merchant: merchant.publicKey,
           escrowTokenAccount,
@@ -924,8 +989,14 @@ describe("beam", () => {
 
       try {
         await program.methods
-          .reportFraudulentBundle(testBundleId, zeroHash, { other: {} })
+          .reportFraudulentBundle(
+            testBundleId,
+            zeroHash,
+            { other: {} },
+            { none: {} }
+          )
           .accountsPartial({
+            escrowAccount: escrowPDA,
             payer: payer.publicKey,
             reporter: reporter.publicKey,
           })
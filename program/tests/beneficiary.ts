@@ -0,0 +1,92 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, airdrop, createEscrowFixture } from "./fixtures";
+
+describe("beneficiary dead-man switch", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const NINETY_DAYS = 90 * 86_400;
+  const beneficiary = Keypair.generate();
+  let fixture: EscrowFixture;
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+    await airdrop(provider, beneficiary.publicKey, 1);
+  });
+
+  it("Rejects an inactivity period below the minimum", async () => {
+    try {
+      await program.methods
+        .setBeneficiary(beneficiary.publicKey, new anchor.BN(86_400))
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          owner: fixture.owner.publicKey,
+        })
+        .signers([fixture.owner])
+        .rpc();
+      assert.fail("Should have failed with InvalidBeneficiary");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidBeneficiary");
+    }
+  });
+
+  it("Sets a beneficiary", async () => {
+    await program.methods
+      .setBeneficiary(beneficiary.publicKey, new anchor.BN(NINETY_DAYS))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(
+      escrow.beneficiary.toString(),
+      beneficiary.publicKey.toString()
+    );
+    assert.equal(escrow.inactivityPeriod.toNumber(), NINETY_DAYS);
+    assert.equal(escrow.beneficiaryClaimStartedAt.toNumber(), 0);
+  });
+
+  it("Refuses a claim while the owner is active", async () => {
+    try {
+      await program.methods
+        .claimAsBeneficiary()
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          beneficiary: beneficiary.publicKey,
+        })
+        .signers([beneficiary])
+        .rpc();
+      assert.fail("Should have failed with OwnerStillActive");
+    } catch (err) {
+      assert.include(err.toString(), "OwnerStillActive");
+    }
+  });
+
+  it("Refuses a claim from anyone but the beneficiary", async () => {
+    const stranger = Keypair.generate();
+    try {
+      await program.methods
+        .claimAsBeneficiary()
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          beneficiary: stranger.publicKey,
+        })
+        .signers([stranger])
+        .rpc();
+      assert.fail("Should have failed with InvalidBeneficiary");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidBeneficiary");
+    }
+  });
+});
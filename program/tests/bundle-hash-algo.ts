@@ -0,0 +1,77 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import * as crypto from "crypto";
+import { keccak_256 } from "@noble/hashes/sha3";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+// Bundle id hash as the program computes it for each `BundleHashAlgo`
+const hashBundleId = (algo: "keccak256" | "sha256", bundleId: string) =>
+  algo === "sha256"
+    ? crypto.createHash("sha256").update(bundleId).digest("hex")
+    : Buffer.from(keccak_256(Buffer.from(bundleId))).toString("hex");
+
+describe("bundle hash algorithm", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  before(async () => {
+    await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Matches the reference vectors", () => {
+    assert.equal(
+      hashBundleId("keccak256", ""),
+      "c5d2460186f7233c927e7db2dcc703c0e500b653ca82273b7bfad8045d85a470"
+    );
+    assert.equal(
+      hashBundleId("keccak256", "abc"),
+      "4e03657aea45a94fc7d47ba826c8d667c0d1e6e33a64a036ec44f58fa12d6c45"
+    );
+    assert.equal(
+      hashBundleId("sha256", ""),
+      "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855"
+    );
+    assert.equal(
+      hashBundleId("sha256", "abc"),
+      "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+    );
+  });
+
+  it("Stores settled bundles under the configured algorithm", async () => {
+    const config = await program.account.programConfig.fetch(
+      await ensureConfig(provider, program)
+    );
+    const algo = "sha256" in config.bundleHashAlgo ? "sha256" : "keccak256";
+
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(1),
+        "hash-algo-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+    const registry = await program.account.nonceRegistry.fetch(
+      fixture.nonceRegistry
+    );
+    assert.equal(
+      Buffer.from(registry.recentBundleHashes[0]).toString("hex"),
+      hashBundleId(algo, "hash-algo-1")
+    );
+  });
+});
@@ -0,0 +1,170 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  settleAccounts,
+} from "./fixtures";
+
+describe("merchant cashback", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let cashbackProgram: PublicKey;
+  let vault: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const settle = (amount: number, nonce: number, destination: PublicKey | null) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `cashback-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        cashbackProgram,
+        cashbackVault: vault,
+        cashbackDestination: destination,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program);
+    await airdrop(provider, fixture.merchant.publicKey);
+
+    cashbackProgram = PublicKey.findProgramAddressSync(
+      [Buffer.from("cashback"), fixture.merchant.publicKey.toBuffer()],
+      program.programId
+    )[0];
+    vault = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      cashbackProgram,
+      Keypair.generate()
+    );
+
+    // 2% cashback
+    await program.methods
+      .createCashbackProgram(200)
+      .accountsPartial({
+        cashbackProgram,
+        vault,
+        merchant: fixture.merchant.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.merchant])
+      .rpc();
+
+    await program.methods
+      .fundCashback(new anchor.BN(1_000000))
+      .accountsPartial({
+        cashbackProgram,
+        vault,
+        funder: fixture.owner.publicKey,
+        funderTokenAccount: fixture.ownerTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+  });
+
+  it("Pays cashback to the owner's token account", async () => {
+    const before = await balanceOf(fixture.ownerTokenAccount);
+    await settle(10_000000, 1, fixture.ownerTokenAccount);
+
+    assert.equal(await balanceOf(fixture.ownerTokenAccount), before + 200000);
+    assert.equal(await balanceOf(vault), 800000);
+    const state = await program.account.cashbackProgram.fetch(cashbackProgram);
+    assert.equal(state.totalPaid.toNumber(), 200000);
+  });
+
+  it("Credits cashback to the escrow when no destination is given", async () => {
+    const before = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    await settle(10_000000, 2, null);
+
+    const after = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(
+      after.escrowBalance.toNumber(),
+      before.escrowBalance.toNumber() - 10_000000 + 200000
+    );
+    assert.equal(
+      await balanceOf(fixture.escrowTokenAccount),
+      after.escrowBalance.toNumber()
+    );
+  });
+
+  it("Settles without cashback when the vault is underfunded", async () => {
+    const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+    // 2% of 50 tokens exceeds the 0.6 left in the vault
+    await settle(50_000000, 3, fixture.ownerTokenAccount);
+
+    assert.equal(
+      await balanceOf(fixture.merchantTokenAccount),
+      merchantBefore + 50_000000
+    );
+    assert.equal(await balanceOf(vault), 600000);
+  });
+
+  it("Rejects a vault that isn't the program's", async () => {
+    const otherVault = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      cashbackProgram,
+      Keypair.generate()
+    );
+    try {
+      await program.methods
+        .settleOfflinePayment(
+          new anchor.BN(1_000000),
+          new anchor.BN(4),
+          "cashback-4",
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial({
+          ...settleAccounts(fixture),
+          cashbackProgram,
+          cashbackVault: otherVault,
+          cashbackDestination: null,
+        })
+        .signers([fixture.owner])
+        .rpc();
+      assert.fail("Should have failed with CashbackMismatch");
+    } catch (err) {
+      assert.include(err.toString(), "CashbackMismatch");
+    }
+  });
+
+  it("Lets the merchant withdraw unspent cashback funds", async () => {
+    await program.methods
+      .withdrawCashback(new anchor.BN(600000))
+      .accountsPartial({
+        cashbackProgram,
+        vault,
+        merchant: fixture.merchant.publicKey,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.merchant])
+      .rpc();
+    assert.equal(await balanceOf(vault), 0);
+  });
+});
@@ -0,0 +1,192 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("chargeback resolution", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const DAY = 86_400;
+  const arbiters = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  // Settles a bundle and charges it back, with the merchant's response unless `respond` is false
+  const disputed = async (nonce: number, respond = true) => {
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `ruling-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const bundleHash = registry.bundleHistory[registry.bundleHistory.length - 1].bundleHash;
+    const [chargeback] = PublicKey.findProgramAddressSync(
+      [Buffer.from("chargeback"), fixture.owner.publicKey.toBuffer(), Buffer.from(bundleHash)],
+      program.programId
+    );
+    await program.methods
+      .requestChargeback(bundleHash, { notAsDescribed: {} })
+      .accountsPartial({
+        chargeback,
+        nonceRegistry: fixture.nonceRegistry,
+        payer: fixture.owner.publicKey,
+        config,
+      })
+      .signers([fixture.owner])
+      .rpc();
+    if (respond) {
+      const uri = Buffer.alloc(128);
+      uri.write("https://merchant.example/pod/" + nonce);
+      await program.methods
+        .respondToChargeback(Array(32).fill(nonce), Array.from(uri))
+        .accountsPartial({ chargeback, merchant: fixture.merchant.publicKey })
+        .signers([fixture.merchant])
+        .rpc();
+    }
+    return chargeback;
+  };
+
+  const resolve = (chargeback: PublicKey, outcome: object, cosigners: Keypair[]) =>
+    program.methods
+      .resolveChargeback(outcome)
+      .accountsPartial({
+        chargeback,
+        escrowAccount: fixture.escrowPDA,
+        resolver: provider.wallet.publicKey,
+        config,
+      })
+      .remainingAccounts(
+        cosigners.map((arbiter) => ({
+          pubkey: arbiter.publicKey,
+          isSigner: true,
+          isWritable: false,
+        }))
+      )
+      .signers(cosigners)
+      .rpc({ commitment: "confirmed" });
+
+  const close = (chargeback: PublicKey) =>
+    program.methods
+      .closeChargeback()
+      .accountsPartial({ chargeback, payer: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const rejectedChargebacks = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)).rejectedChargebacks;
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await program.methods
+      .updateConfig({
+        chargebackWindow: new anchor.BN(30 * DAY),
+        chargebackResponseWindow: new anchor.BN(7 * DAY),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await program.methods
+      .setArbiters(
+        arbiters.map((arbiter) => arbiter.publicKey),
+        2
+      )
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .setArbiters([], 0)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await program.methods
+      .updateConfig({
+        chargebackWindow: new anchor.BN(0),
+        chargebackResponseWindow: new anchor.BN(0),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Needs the arbiter quorum", async () => {
+    const chargeback = await disputed(1);
+    await expectError(resolve(chargeback, { forPayer: {} }, [arbiters[0]]), "ArbiterQuorumNotMet");
+    await expectError(
+      resolve(chargeback, { forPayer: {} }, [Keypair.generate(), Keypair.generate()]),
+      "ArbiterQuorumNotMet"
+    );
+  });
+
+  it("Only rules on chargebacks the merchant responded to", async () => {
+    const chargeback = await disputed(2, false);
+    await expectError(
+      resolve(chargeback, { forPayer: {} }, arbiters.slice(0, 2)),
+      "ChargebackNotResponded"
+    );
+  });
+
+  it("Upholds a chargeback for the payer", async () => {
+    const chargeback = await disputed(3);
+    const signature = await resolve(chargeback, { forPayer: {} }, arbiters.slice(0, 2));
+
+    const record = await program.account.chargeback.fetch(chargeback);
+    assert.deepEqual(record.status, { upheld: {} });
+    assert.equal(await rejectedChargebacks(), 0);
+    const resolved = (await eventsOf(signature)).find((event) => event.name === "chargebackResolved");
+    assert.deepEqual(resolved.data.outcome, { upheld: {} });
+    assert.deepEqual(
+      resolved.data.arbiters.map((key: PublicKey) => key.toBase58()),
+      arbiters.slice(0, 2).map((arbiter) => arbiter.publicKey.toBase58())
+    );
+    await expectError(
+      resolve(chargeback, { forMerchant: {} }, arbiters.slice(0, 2)),
+      "ChargebackNotResponded"
+    );
+  });
+
+  it("Rejects a chargeback for the merchant and counts it against the payer", async () => {
+    const chargeback = await disputed(4);
+    await resolve(chargeback, { forMerchant: {} }, arbiters.slice(1));
+
+    const record = await program.account.chargeback.fetch(chargeback);
+    assert.deepEqual(record.status, { rejected: {} });
+    assert.equal(await rejectedChargebacks(), 1);
+  });
+
+  it("Returns a resolved chargeback's rent to the payer", async () => {
+    const open = await disputed(5);
+    await expectError(close(open), "ChargebackNotResolved");
+
+    await resolve(open, { forPayer: {} }, arbiters.slice(0, 2));
+    const before = await provider.connection.getBalance(fixture.owner.publicKey);
+    await close(open);
+    assert.isNull(await provider.connection.getAccountInfo(open));
+    assert.isAbove(await provider.connection.getBalance(fixture.owner.publicKey), before);
+  });
+});
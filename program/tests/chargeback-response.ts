@@ -0,0 +1,167 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("chargeback responses", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const DAY = 86_400;
+  const URI_LEN = 128;
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setWindows = (responseWindow: number) =>
+    program.methods
+      .updateConfig({
+        chargebackWindow: new anchor.BN(30 * DAY),
+        chargebackResponseWindow: new anchor.BN(responseWindow),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const uriOf = (text: string) => {
+    const uri = Buffer.alloc(URI_LEN);
+    uri.write(text);
+    return Array.from(uri);
+  };
+
+  // Settles a bundle and charges it back, returning the chargeback's address
+  const chargeBack = async (nonce: number) => {
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `respond-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const bundleHash = registry.bundleHistory[registry.bundleHistory.length - 1].bundleHash;
+    const [chargeback] = PublicKey.findProgramAddressSync(
+      [Buffer.from("chargeback"), fixture.owner.publicKey.toBuffer(), Buffer.from(bundleHash)],
+      program.programId
+    );
+    await program.methods
+      .requestChargeback(bundleHash, { notDelivered: {} })
+      .accountsPartial({
+        chargeback,
+        nonceRegistry: fixture.nonceRegistry,
+        payer: fixture.owner.publicKey,
+        config,
+      })
+      .signers([fixture.owner])
+      .rpc();
+    return chargeback;
+  };
+
+  const respond = (
+    chargeback: PublicKey,
+    signer: Keypair = fixture.merchant,
+    evidenceHash: number[] = Array(32).fill(4),
+    uri: number[] = uriOf("ipfs://delivery-receipt")
+  ) =>
+    program.methods
+      .respondToChargeback(evidenceHash, uri)
+      .accountsPartial({ chargeback, merchant: signer.publicKey })
+      .signers([signer])
+      .rpc();
+
+  const resolve = (chargeback: PublicKey, resolver: Keypair) =>
+    program.methods
+      .resolveUnansweredChargeback()
+      .accountsPartial({ chargeback, resolver: resolver.publicKey })
+      .signers([resolver])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  after(async () => {
+    await program.methods
+      .updateConfig({
+        chargebackWindow: new anchor.BN(0),
+        chargebackResponseWindow: new anchor.BN(0),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  describe("within the response window", () => {
+    let chargeback: PublicKey;
+
+    before(async () => {
+      await setWindows(7 * DAY);
+      chargeback = await chargeBack(1);
+    });
+
+    it("Only lets the merchant respond", async () => {
+      await expectError(respond(chargeback, fixture.owner), "Unauthorized");
+    });
+
+    it("Rejects a zero evidence hash or an empty reference", async () => {
+      await expectError(respond(chargeback, fixture.merchant, Array(32).fill(0)), "InvalidChargebackEvidence");
+      await expectError(
+        respond(chargeback, fixture.merchant, Array(32).fill(4), uriOf("")),
+        "InvalidChargebackEvidence"
+      );
+    });
+
+    it("Keeps the payer from resolving before the merchant's time is up", async () => {
+      await expectError(resolve(chargeback, fixture.owner), "ChargebackResponseWindowOpen");
+    });
+
+    it("Attaches the merchant's delivery proof", async () => {
+      await respond(chargeback);
+      const record = await program.account.chargeback.fetch(chargeback);
+      assert.deepEqual(record.status, { responded: {} });
+      assert.deepEqual(record.evidenceHash, Array(32).fill(4));
+      assert.deepEqual(record.evidenceUri, uriOf("ipfs://delivery-receipt"));
+      assert.isAbove(record.respondedAt.toNumber(), 0);
+    });
+
+    it("Accepts one response and leaves a responded chargeback to the arbiters", async () => {
+      await expectError(respond(chargeback), "ChargebackNotAwaitingResponse");
+      await expectError(resolve(chargeback, fixture.owner), "ChargebackNotAwaitingResponse");
+    });
+  });
+
+  describe("after the response window", () => {
+    const crank = Keypair.generate();
+    let chargeback: PublicKey;
+
+    before(async () => {
+      await setWindows(1);
+      chargeback = await chargeBack(2);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    it("Refuses a late response", async () => {
+      await expectError(respond(chargeback), "ChargebackResponseWindowClosed");
+    });
+
+    it("Lets anyone resolve it for the payer", async () => {
+      await resolve(chargeback, crank);
+      const record = await program.account.chargeback.fetch(chargeback);
+      assert.deepEqual(record.status, { upheld: {} });
+      assert.isAbove(record.resolvedAt.toNumber(), 0);
+      await expectError(resolve(chargeback, crank), "ChargebackNotAwaitingResponse");
+    });
+  });
+});
@@ -0,0 +1,144 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("chargeback requests", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const DAY = 86_400;
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setWindow = (seconds: number) =>
+    program.methods
+      .updateConfig({
+        chargebackWindow: new anchor.BN(seconds),
+        chargebackResponseWindow: new anchor.BN(7 * DAY),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  // Settles a bundle and returns its hash from the payer's history
+  const settle = async (nonce: number) => {
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `chargeback-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    return registry.bundleHistory[registry.bundleHistory.length - 1].bundleHash;
+  };
+
+  const chargebackPDA = (bundleHash: number[]) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("chargeback"), fixture.owner.publicKey.toBuffer(), Buffer.from(bundleHash)],
+      program.programId
+    )[0];
+
+  const request = (bundleHash: number[]) =>
+    program.methods
+      .requestChargeback(bundleHash, { notDelivered: {} })
+      .accountsPartial({
+        chargeback: chargebackPDA(bundleHash),
+        nonceRegistry: fixture.nonceRegistry,
+        payer: fixture.owner.publicKey,
+        config,
+      })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  after(async () => {
+    await setWindow(0);
+  });
+
+  it("Rejects chargebacks until the config enables them", async () => {
+    const bundleHash = await settle(1);
+    await expectError(request(bundleHash), "ChargebacksDisabled");
+  });
+
+  it("Bounds the configurable window", async () => {
+    await expectError(setWindow(181 * DAY), "InvalidConfig");
+  });
+
+  it("Requires a response window while chargebacks are enabled", async () => {
+    await expectError(
+      program.methods
+        .updateConfig({
+          chargebackWindow: new anchor.BN(DAY),
+          chargebackResponseWindow: new anchor.BN(0),
+        })
+        .accountsPartial({ config, admin: provider.wallet.publicKey })
+        .rpc(),
+      "InvalidConfig"
+    );
+  });
+
+  it("Records a chargeback against the settled bundle", async () => {
+    await setWindow(30 * DAY);
+    const bundleHash = await settle(2);
+
+    const signature = await request(bundleHash);
+
+    const chargeback = await program.account.chargeback.fetch(chargebackPDA(bundleHash));
+    assert.ok(chargeback.payer.equals(fixture.owner.publicKey));
+    assert.ok(chargeback.merchant.equals(fixture.merchant.publicKey));
+    assert.equal(chargeback.amount.toNumber(), 1_000000);
+    assert.equal(chargeback.settlementIndex.toNumber(), 2);
+    assert.deepEqual(chargeback.reason, { notDelivered: {} });
+    assert.deepEqual(chargeback.status, { requested: {} });
+    assert.equal(chargeback.respondBy.toNumber(), chargeback.requestedAt.toNumber() + 7 * DAY);
+    const requested = (await eventsOf(signature)).find(
+      (event) => event.name === "chargebackRequested"
+    );
+    assert.ok(requested.data.merchant.equals(fixture.merchant.publicKey));
+    assert.deepEqual(requested.data.bundleHash, bundleHash);
+  });
+
+  it("Rejects a second chargeback on the same bundle", async () => {
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    await expectError(request(registry.bundleHistory[1].bundleHash), "already in use");
+  });
+
+  it("Rejects bundles missing from the payer's history", async () => {
+    await expectError(request(Array(32).fill(7)), "BundleHistoryNotFound");
+  });
+
+  it("Rejects requests after the window has closed", async () => {
+    await setWindow(1);
+    const bundleHash = await settle(3);
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+    await expectError(request(bundleHash), "ChargebackWindowClosed");
+  });
+});
@@ -0,0 +1,101 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("compliance check", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // No registry program is deployed on the test validator, so no record can exist
+  const registry = Keypair.generate().publicKey;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setRegistry = (complianceRegistry: PublicKey) =>
+    program.methods
+      .updateConfig({ complianceRegistry })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const recordOf = (wallet: PublicKey) =>
+    PublicKey.findProgramAddressSync([Buffer.from("compliance"), wallet.toBuffer()], registry)[0];
+
+  const settle = (nonce: number, records: PublicKey[]) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `compliance-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .remainingAccounts(records.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  after(async () => {
+    await setRegistry(PublicKey.default);
+  });
+
+  it("Is off by default", async () => {
+    const state = await program.account.programConfig.fetch(config);
+    assert.ok(state.complianceRegistry.equals(PublicKey.default));
+    await settle(1, []);
+  });
+
+  describe("with a registry", () => {
+    before(async () => {
+      await setRegistry(registry);
+    });
+
+    it("Rejects settlements without approved records of both parties", async () => {
+      await expectError(settle(2, []), "ComplianceCheckFailed");
+      await expectError(
+        settle(2, [recordOf(fixture.owner.publicKey), recordOf(fixture.merchant.publicKey)]),
+        "ComplianceCheckFailed"
+      );
+      // Records of the wrong wallets don't count either
+      await expectError(
+        settle(2, [recordOf(fixture.merchant.publicKey), recordOf(fixture.owner.publicKey)]),
+        "ComplianceCheckFailed"
+      );
+    });
+
+    it("Still lets the payer withdraw", async () => {
+      await program.methods
+        .withdrawEscrow(new anchor.BN(1_000000))
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          owner: fixture.owner.publicKey,
+          ownerTokenAccount: fixture.ownerTokenAccount,
+          escrowTokenAccount: fixture.escrowTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.owner])
+        .rpc();
+    });
+
+    it("Settles again once turned off", async () => {
+      await setRegistry(PublicKey.default);
+      await settle(2, []);
+    });
+  });
+});
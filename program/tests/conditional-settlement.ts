@@ -0,0 +1,84 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import * as crypto from "crypto";
+import * as ed25519 from "@noble/ed25519";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("conditional settlement", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const oracleKey = Uint8Array.from(crypto.randomBytes(32));
+  const conditionId = crypto.randomBytes(32);
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setOracle = (conditionOracle: PublicKey) =>
+    program.methods
+      .updateConfig({ conditionOracle })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const confirm = async (key: Uint8Array, id: Buffer) =>
+    Array.from(
+      await ed25519.signAsync(Buffer.concat([Buffer.from("beam.condition.v1"), id]), key)
+    );
+
+  const settle = (nonce: number, conditionSignature: number[] | null) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `condition-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+        conditionId: Array.from(conditionId),
+        conditionSignature,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  after(async () => {
+    await setOracle(PublicKey.default);
+  });
+
+  it("Holds conditional bundles while no oracle is configured", async () => {
+    await expectError(settle(1, await confirm(oracleKey, conditionId)), "ConditionNotMet");
+  });
+
+  it("Rejects bundles without the oracle's confirmation", async () => {
+    await setOracle(new PublicKey(await ed25519.getPublicKeyAsync(oracleKey)));
+    await expectError(settle(1, null), "ConditionNotMet");
+
+    const impostor = Uint8Array.from(crypto.randomBytes(32));
+    await expectError(settle(1, await confirm(impostor, conditionId)), "ConditionNotMet");
+    await expectError(
+      settle(1, await confirm(oracleKey, crypto.randomBytes(32))),
+      "ConditionNotMet"
+    );
+  });
+
+  it("Settles once the oracle confirms the condition", async () => {
+    const before = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    await settle(1, await confirm(oracleKey, conditionId));
+    const after = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(after.escrowBalance.toNumber(), before.escrowBalance.toNumber() - 1_000000);
+  });
+});
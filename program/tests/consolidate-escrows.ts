@@ -0,0 +1,273 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("escrow consolidation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  interface Source {
+    owner: Keypair;
+    escrow: PublicKey;
+    vault: PublicKey;
+    registry: PublicKey;
+  }
+
+  let destination: EscrowFixture;
+
+  // Another escrow of the same user, in the destination's mint, under a second key
+  const createSource = async (amount: number): Promise<Source> => {
+    const owner = Keypair.generate();
+    await airdrop(provider, owner.publicKey);
+    const escrow = findEscrowPDA(program, owner.publicKey);
+    const vault = await createAccount(
+      provider.connection,
+      owner,
+      destination.mint,
+      escrow,
+      Keypair.generate()
+    );
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        owner,
+        destination.mint,
+        owner.publicKey
+      )
+    ).address;
+    await mintTo(
+      provider.connection,
+      destination.owner,
+      destination.mint,
+      ownerTokenAccount,
+      destination.owner,
+      amount
+    );
+    await program.methods
+      .initializeEscrow(new anchor.BN(amount))
+      .accounts({
+        escrowAccount: escrow,
+        owner: owner.publicKey,
+        rentPayer: owner.publicKey,
+        ownerTokenAccount,
+        escrowTokenAccount: vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+    const registry = findNonceRegistryPDA(program, owner.publicKey);
+    await program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: owner.publicKey,
+        rentPayer: owner.publicKey,
+        nonceRegistry: registry,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+    return { owner, escrow, vault, registry };
+  };
+
+  const consolidate = (sources: Source[], signers = sources.map((s) => s.owner)) =>
+    program.methods
+      .consolidateEscrows()
+      .accountsPartial({
+        escrowAccount: destination.escrowPDA,
+        owner: destination.owner.publicKey,
+        escrowTokenAccount: destination.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        sources.flatMap((s) => [
+          { pubkey: s.escrow, isSigner: false, isWritable: true },
+          { pubkey: s.vault, isSigner: false, isWritable: true },
+          { pubkey: s.owner.publicKey, isSigner: true, isWritable: true },
+          { pubkey: s.registry, isSigner: false, isWritable: true },
+          // The owner paid the escrow's and registry's rent
+          { pubkey: s.owner.publicKey, isSigner: false, isWritable: true },
+          { pubkey: s.owner.publicKey, isSigner: false, isWritable: true },
+        ])
+      )
+      .signers([destination.owner, ...signers])
+      .rpc();
+
+  before(async () => {
+    destination = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Sweeps and closes every source escrow", async () => {
+    const a = await createSource(5_000000);
+    const b = await createSource(7_000000);
+
+    await consolidate([a, b]);
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      destination.escrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 22_000000);
+    assert.equal(
+      Number(
+        (await getAccount(provider.connection, destination.escrowTokenAccount))
+          .amount
+      ),
+      22_000000
+    );
+    for (const source of [a, b]) {
+      assert.isNull(await provider.connection.getAccountInfo(source.escrow));
+      assert.isNull(await provider.connection.getAccountInfo(source.vault));
+      assert.isNull(await provider.connection.getAccountInfo(source.registry));
+    }
+  });
+
+  it("Refuses a source with liabilities still pending on its registry", async () => {
+    const a = await createSource(5_000000);
+    await program.methods
+      .registerLiability(
+        Array.from(Buffer.alloc(32, 9)),
+        destination.merchant.publicKey,
+        new anchor.BN(2_000000)
+      )
+      .accountsPartial({ nonceRegistry: a.registry, owner: a.owner.publicKey })
+      .signers([a.owner])
+      .rpc();
+    try {
+      await consolidate([a]);
+      assert.fail("Should have failed with DeviceRegistryBusy");
+    } catch (err) {
+      assert.include(err.toString(), "DeviceRegistryBusy");
+    }
+    const source = await program.account.offlineEscrowAccount.fetch(a.escrow);
+    assert.equal(source.escrowBalance.toNumber(), 5_000000);
+  });
+
+  it("Refuses a source with devices still enrolled until they retire", async () => {
+    const a = await createSource(3_000000);
+    const device = Array.from(Buffer.alloc(32, 4));
+    const deviceRegistry = PublicKey.findProgramAddressSync(
+      [Buffer.from("nonce"), a.owner.publicKey.toBuffer(), Buffer.from(device)],
+      program.programId
+    )[0];
+    await program.methods
+      .enrollDevice(device)
+      .accountsPartial({
+        primaryNonceRegistry: a.registry,
+        deviceNonceRegistry: deviceRegistry,
+        owner: a.owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([a.owner])
+      .rpc();
+    assert.equal((await program.account.nonceRegistry.fetch(a.registry)).deviceCount, 1);
+
+    try {
+      await consolidate([a]);
+      assert.fail("Should have failed with DevicesEnrolled");
+    } catch (err) {
+      assert.include(err.toString(), "DevicesEnrolled");
+    }
+
+    await program.methods
+      .retireDevice()
+      .accountsPartial({
+        primaryNonceRegistry: a.registry,
+        deviceNonceRegistry: deviceRegistry,
+        owner: a.owner.publicKey,
+        rentPayer: a.owner.publicKey,
+      })
+      .signers([a.owner])
+      .rpc();
+    await consolidate([a]);
+    assert.isNull(await provider.connection.getAccountInfo(a.escrow));
+    assert.isNull(await provider.connection.getAccountInfo(a.registry));
+  });
+
+  it("Rejects a registry that isn't the source owner's", async () => {
+    const a = await createSource(1_000000);
+    try {
+      await consolidate([{ ...a, registry: destination.nonceRegistry }]);
+      assert.fail("Should have failed with InvalidConsolidation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConsolidation");
+    }
+  });
+
+  it("Requires every source owner to sign", async () => {
+    const a = await createSource(1_000000);
+    const b = await createSource(1_000000);
+    try {
+      await program.methods
+        .consolidateEscrows()
+        .accountsPartial({
+          escrowAccount: destination.escrowPDA,
+          owner: destination.owner.publicKey,
+          escrowTokenAccount: destination.escrowTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: a.escrow, isSigner: false, isWritable: true },
+          { pubkey: a.vault, isSigner: false, isWritable: true },
+          { pubkey: a.owner.publicKey, isSigner: true, isWritable: true },
+          { pubkey: a.registry, isSigner: false, isWritable: true },
+          { pubkey: a.owner.publicKey, isSigner: false, isWritable: true },
+          { pubkey: a.owner.publicKey, isSigner: false, isWritable: true },
+          { pubkey: b.escrow, isSigner: false, isWritable: true },
+          { pubkey: b.vault, isSigner: false, isWritable: true },
+          { pubkey: b.owner.publicKey, isSigner: false, isWritable: true },
+          { pubkey: b.registry, isSigner: false, isWritable: true },
+          { pubkey: b.owner.publicKey, isSigner: false, isWritable: true },
+          { pubkey: b.owner.publicKey, isSigner: false, isWritable: true },
+        ])
+        .signers([destination.owner, a.owner])
+        .rpc();
+      assert.fail("Should have failed with Unauthorized");
+    } catch (err) {
+      assert.include(err.toString(), "Unauthorized");
+    }
+    // Nothing moved: the first, valid source is untouched
+    const source = await program.account.offlineEscrowAccount.fetch(a.escrow);
+    assert.equal(source.escrowBalance.toNumber(), 1_000000);
+  });
+
+  it("Rejects listing the same source twice", async () => {
+    const a = await createSource(1_000000);
+    try {
+      await consolidate([a, a], [a.owner]);
+      assert.fail("Should have failed with InvalidConsolidation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConsolidation");
+    }
+  });
+
+  it("Rejects more than three sources", async () => {
+    const sources = [];
+    for (let i = 0; i < 4; i++) {
+      sources.push(await createSource(1_000000));
+    }
+    try {
+      await consolidate(sources);
+      assert.fail("Should have failed with InvalidConsolidation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConsolidation");
+    }
+  });
+});
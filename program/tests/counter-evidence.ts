@@ -0,0 +1,119 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, airdrop, createEscrowFixture, settleAccounts } from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("fraud counter evidence", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const reporter = Keypair.generate();
+  const evidenceHash = Buffer.alloc(32, 3);
+  let fixture: EscrowFixture;
+  let bundleHash: number[];
+  let conflictingHash: Buffer;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const statementOf = (text: string) => {
+    const buffer = Buffer.alloc(64);
+    buffer.write(text, "utf8");
+    return Array.from(buffer);
+  };
+
+  const counter = (statement: number[], signer = fixture.owner, hash = evidenceHash) =>
+    program.methods
+      .submitCounterEvidence(bundleHash, Array.from(conflictingHash), Array.from(hash), statement)
+      .accountsPartial({ nonceRegistry: fixture.nonceRegistry, payer: signer.publicKey })
+      .signers([signer])
+      .rpc();
+
+  before(async () => {
+    await airdrop(provider, reporter.publicKey);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(1), "countered-1", {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    const conflict = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, 7),
+      fixture.merchant.publicKey,
+      1_000000,
+      1
+    );
+    conflictingHash = conflict.conflictingHash;
+    await program.methods
+      .reportFraudulentBundle("countered-1", conflictingHash, { duplicateBundle: {} }, conflict.evidence as any)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    bundleHash = registry.fraudRecords[0].bundleHash;
+  });
+
+  it("Rejects a zero evidence hash or a non-UTF-8 statement", async () => {
+    await expectError(
+      counter(statementOf("replayed by merchant"), fixture.owner, Buffer.alloc(32)),
+      "InvalidCounterEvidence"
+    );
+    const invalid = statementOf("");
+    invalid[0] = 0xff;
+    await expectError(counter(invalid), "InvalidCounterEvidence");
+  });
+
+  it("Only lets the accused payer respond", async () => {
+    await expectError(counter(statementOf("not mine"), reporter), "ConstraintSeeds");
+  });
+
+  it("Attaches the rebuttal to the fraud record", async () => {
+    let seen = null;
+    const listener = program.addEventListener("counterEvidenceSubmitted", (event) => {
+      seen = event;
+    });
+    await counter(statementOf("replayed by merchant"));
+    await new Promise((resolve) => setTimeout(resolve, 1000));
+    await program.removeEventListener(listener);
+
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const record = registry.fraudRecords[0];
+    assert.deepEqual(record.counterEvidenceHash, Array.from(evidenceHash));
+    assert.equal(
+      Buffer.from(record.counterStatement).toString("utf8").replace(/\0+$/, ""),
+      "replayed by merchant"
+    );
+    assert.isAbove(record.counteredAt.toNumber(), 0);
+    assert.isNotNull(seen);
+    assert.ok(seen.payer.equals(fixture.owner.publicKey));
+  });
+
+  it("Accepts only one rebuttal per record", async () => {
+    await expectError(counter(statementOf("again")), "CounterEvidenceExists");
+  });
+
+  it("Rejects a record that doesn't exist", async () => {
+    bundleHash = Array.from(Buffer.alloc(32, 1));
+    await expectError(counter(statementOf("what report")), "FraudRecordNotFound");
+  });
+});
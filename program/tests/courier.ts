@@ -0,0 +1,127 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { getAccount, getOrCreateAssociatedTokenAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("courier fees", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 5_000000;
+  const MAX_COURIER_FEE = 100000;
+  const courier = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let courierTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const escrowBalance = async () =>
+    (
+      await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)
+    ).escrowBalance.toNumber();
+
+  const settle = async (
+    nonce: number,
+    fee: number,
+    courierAccount: PublicKey | null = courierTokenAccount
+  ) => {
+    const bundleId = `courier-${nonce}-${fee}`;
+    const commitment = { courier: courier.publicKey, fee: new anchor.BN(fee) };
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      nonce,
+      undefined,
+      null,
+      null,
+      commitment
+    );
+    return program.methods
+      .settleOfflinePayment(
+        new anchor.BN(AMOUNT),
+        new anchor.BN(nonce),
+        bundleId,
+        { payerProof, merchantProof: null, courier: commitment }
+      )
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        courierTokenAccount: courierAccount,
+      })
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await airdrop(provider, courier.publicKey);
+    courierTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        courier,
+        fixture.mint,
+        courier.publicKey
+      )
+    ).address;
+    await program.methods
+      .updateConfig({ maxCourierFee: new anchor.BN(MAX_COURIER_FEE) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .updateConfig({ maxCourierFee: new anchor.BN(0) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Pays the committed fee to the courier from the escrow", async () => {
+    await settle(1, 50000);
+    assert.equal(await balanceOf(courierTokenAccount), 50000);
+    assert.equal(await balanceOf(fixture.merchantTokenAccount), AMOUNT);
+    assert.equal(await escrowBalance(), 20_000000 - AMOUNT - 50000);
+  });
+
+  it("Leaves the fee in escrow when no courier account is supplied", async () => {
+    const before = await escrowBalance();
+    await settle(2, 50000, null);
+    assert.equal(await balanceOf(courierTokenAccount), 50000);
+    assert.equal(await escrowBalance(), before - AMOUNT);
+  });
+
+  it("Rejects fees above the configured maximum", async () => {
+    try {
+      await settle(3, MAX_COURIER_FEE + 1);
+      assert.fail("Should have failed with CourierFeeTooHigh");
+    } catch (err) {
+      assert.include(err.toString(), "CourierFeeTooHigh");
+    }
+  });
+
+  it("Rejects a token account the committed courier doesn't own", async () => {
+    try {
+      await settle(3, 50000, fixture.merchantTokenAccount);
+      assert.fail("Should have failed with CourierMismatch");
+    } catch (err) {
+      assert.include(err.toString(), "CourierMismatch");
+    }
+  });
+});
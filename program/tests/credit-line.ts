@@ -0,0 +1,164 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, airdrop, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("credit lines", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const LIMIT = 10_500000;
+  const FEE_BPS = 500;
+  const underwriter = Keypair.generate();
+  let fixture: EscrowFixture;
+  let creditLine: PublicKey;
+  let creditVault: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(amount), new anchor.BN(nonce), `credit-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({ ...settleAccounts(fixture), creditLine, creditVault })
+      .signers([fixture.owner])
+      .rpc();
+
+  const fund = (amount: number, withCredit: boolean) =>
+    program.methods
+      .fundEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        creditLine: withCredit ? creditLine : null,
+        creditVault: withCredit ? creditVault : null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const setFrozen = (frozen: boolean) =>
+    program.methods
+      .setCreditLineFrozen(frozen)
+      .accountsPartial({ creditLine, underwriter: underwriter.publicKey })
+      .signers([underwriter])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+    await airdrop(provider, underwriter.publicKey);
+    creditLine = PublicKey.findProgramAddressSync(
+      [Buffer.from("credit_line"), underwriter.publicKey.toBuffer(), fixture.owner.publicKey.toBuffer()],
+      program.programId
+    )[0];
+    creditVault = await createAccount(
+      provider.connection,
+      underwriter,
+      fixture.mint,
+      creditLine,
+      Keypair.generate()
+    );
+    await mintTo(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      creditVault,
+      fixture.owner,
+      20_000000
+    );
+
+    await program.methods
+      .openCreditLine(new anchor.BN(LIMIT), FEE_BPS, 0)
+      .accountsPartial({
+        creditLine,
+        creditVault,
+        underwriter: underwriter.publicKey,
+        payer: fixture.owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([underwriter])
+      .rpc();
+  });
+
+  it("Draws the shortfall from the credit vault and books it as debt", async () => {
+    const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+    await settle(12_000000, 1);
+
+    assert.equal(await balanceOf(fixture.merchantTokenAccount) - merchantBefore, 12_000000);
+    assert.equal(await balanceOf(creditVault), 18_000000);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.escrowBalance.toNumber(), 0);
+    // 2.0 drawn plus the 5% fee
+    assert.equal(escrow.creditDebt.toNumber(), 2_100000);
+    assert.ok(escrow.creditLine.equals(creditLine));
+  });
+
+  it("Keeps the debt within the limit and honours a freeze", async () => {
+    await expectError(settle(9_000000, 2), "CreditLimitExceeded");
+
+    await setFrozen(true);
+    await expectError(settle(1_000000, 2), "CreditLineFrozen");
+    await setFrozen(false);
+  });
+
+  it("Makes deposits repay the debt before restoring balance", async () => {
+    await expectError(fund(1_000000, false), "CreditRepaymentRequired");
+
+    await fund(5_000000, true);
+    assert.equal(await balanceOf(creditVault), 20_100000);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.creditDebt.toNumber(), 0);
+    assert.equal(escrow.escrowBalance.toNumber(), 2_900000);
+    assert.ok(escrow.creditLine.equals(PublicKey.default));
+  });
+
+  it("Lets the underwriter withdraw from the vault", async () => {
+    const underwriterTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        underwriter,
+        fixture.mint,
+        underwriter.publicKey
+      )
+    ).address;
+    await program.methods
+      .withdrawCreditVault(new anchor.BN(2_100000))
+      .accountsPartial({
+        creditLine,
+        creditVault,
+        underwriterTokenAccount,
+        underwriter: underwriter.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([underwriter])
+      .rpc();
+
+    assert.equal(await balanceOf(underwriterTokenAccount), 2_100000);
+    assert.equal(await balanceOf(creditVault), 18_000000);
+  });
+});
@@ -0,0 +1,199 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import { airdrop, ensureConfig, findEscrowPDA, findNonceRegistryPDA } from "./fixtures";
+
+describe("custodial onboarding", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // The provider wallet plays the company wallet paying every user's rent
+  const company = provider.wallet.publicKey;
+  const companyKeypair = (provider.wallet as anchor.Wallet).payer;
+  let config: PublicKey;
+  let mint: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setCustodial = (custodialOnboarding: boolean) =>
+    program.methods
+      .updateConfig({ custodialOnboarding })
+      .accountsPartial({ config, admin: company })
+      .rpc();
+
+  const newUser = async () => {
+    const owner = Keypair.generate();
+    const escrowPDA = findEscrowPDA(program, owner.publicKey);
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        companyKeypair,
+        mint,
+        owner.publicKey
+      )
+    ).address;
+    const escrowTokenAccount = await createAccount(
+      provider.connection,
+      companyKeypair,
+      mint,
+      escrowPDA,
+      Keypair.generate()
+    );
+    return { owner, escrowPDA, ownerTokenAccount, escrowTokenAccount };
+  };
+  type User = Awaited<ReturnType<typeof newUser>>;
+
+  // The owner only signs when `ownerSigns`; the company wallet always pays the rent
+  const openEscrow = (user: User, amount: number, ownerSigns: boolean) =>
+    program.methods
+      .initializeEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: user.escrowPDA,
+        owner: user.owner.publicKey,
+        rentPayer: company,
+        ownerTokenAccount: user.ownerTokenAccount,
+        escrowTokenAccount: user.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        ownerSigns ? [{ pubkey: user.owner.publicKey, isSigner: true, isWritable: false }] : []
+      )
+      .signers(ownerSigns ? [user.owner] : [])
+      .rpc();
+
+  const openRegistry = (user: User) =>
+    program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: user.owner.publicKey,
+        rentPayer: company,
+        nonceRegistry: findNonceRegistryPDA(program, user.owner.publicKey),
+      })
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    mint = await createMint(provider.connection, companyKeypair, company, null, 6);
+  });
+
+  after(async () => {
+    await setCustodial(false);
+  });
+
+  it("Lets a company wallet pay rent for an owner who signs", async () => {
+    const user = await newUser();
+    await mintTo(
+      provider.connection,
+      companyKeypair,
+      mint,
+      user.ownerTokenAccount,
+      company,
+      5_000000
+    );
+    await openEscrow(user, 5_000000, true);
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(user.escrowPDA);
+    assert.ok(escrow.owner.equals(user.owner.publicKey));
+    assert.ok(escrow.rentPayer.equals(company));
+    assert.equal(escrow.escrowBalance.toNumber(), 5_000000);
+    // The owner holds no SOL at all
+    assert.equal(await provider.connection.getBalance(user.owner.publicKey), 0);
+  });
+
+  it("Requires the owner's signature unless custodial onboarding is on", async () => {
+    const user = await newUser();
+    await expectError(openEscrow(user, 0, false), "OwnerSignatureRequired");
+    await expectError(openRegistry(user), "OwnerSignatureRequired");
+  });
+
+  describe("with custodial onboarding", () => {
+    let user: User;
+
+    before(async () => {
+      await setCustodial(true);
+      user = await newUser();
+    });
+
+    it("Still needs the owner to sign for funding from its token account", async () => {
+      await expectError(openEscrow(user, 1_000000, false), "OwnerSignatureRequired");
+    });
+
+    it("Opens an unfunded escrow and registry without the owner", async () => {
+      await openEscrow(user, 0, false);
+      await openRegistry(user);
+
+      const escrow = await program.account.offlineEscrowAccount.fetch(user.escrowPDA);
+      assert.ok(escrow.owner.equals(user.owner.publicKey));
+      assert.ok(escrow.rentPayer.equals(company));
+      const registry = await program.account.nonceRegistry.fetch(
+        findNonceRegistryPDA(program, user.owner.publicKey)
+      );
+      assert.ok(registry.owner.equals(user.owner.publicKey));
+      assert.ok(registry.rentPayer.equals(company));
+    });
+
+    it("Returns the escrow's and registry's rent to the company when the owner rotates", async () => {
+      await airdrop(provider, user.owner.publicKey);
+      const newOwner = Keypair.generate();
+      const newEscrowPDA = findEscrowPDA(program, newOwner.publicKey);
+      const newEscrowTokenAccount = await createAccount(
+        provider.connection,
+        companyKeypair,
+        mint,
+        newEscrowPDA,
+        Keypair.generate()
+      );
+      const oldRegistry = findNonceRegistryPDA(program, user.owner.publicKey);
+      const rotate = (escrowRentPayer: PublicKey, registryRentPayer = company) =>
+        program.methods
+          .rotateOwnerKey()
+          .accountsPartial({
+            oldEscrow: user.escrowPDA,
+            oldNonceRegistry: oldRegistry,
+            oldEscrowTokenAccount: user.escrowTokenAccount,
+            newEscrow: newEscrowPDA,
+            newNonceRegistry: findNonceRegistryPDA(program, newOwner.publicKey),
+            newEscrowTokenAccount,
+            oldOwner: user.owner.publicKey,
+            newOwner: newOwner.publicKey,
+            escrowRentPayer,
+            registryRentPayer,
+            tokenProgram: TOKEN_PROGRAM_ID,
+          })
+          .signers([user.owner, newOwner])
+          .rpc();
+
+      await expectError(rotate(user.owner.publicKey), "InvalidRentRecipient");
+      await expectError(rotate(company, user.owner.publicKey), "InvalidRentRecipient");
+
+      const escrowRent = await provider.connection.getBalance(user.escrowPDA);
+      const registryRent = await provider.connection.getBalance(oldRegistry);
+      const companyBefore = await provider.connection.getBalance(company);
+      await rotate(company);
+
+      // The company paid the fees of this transaction, so allow for them
+      const refunded = (await provider.connection.getBalance(company)) - companyBefore;
+      assert.isAbove(refunded, escrowRent + registryRent - 20_000);
+      const rotated = await program.account.offlineEscrowAccount.fetch(newEscrowPDA);
+      assert.ok(rotated.rentPayer.equals(user.owner.publicKey));
+    });
+  });
+});
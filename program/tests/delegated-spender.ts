@@ -0,0 +1,109 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import {
+  Keypair,
+  PublicKey,
+  SYSVAR_INSTRUCTIONS_PUBKEY,
+  SystemProgram,
+} from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  settleAccounts,
+} from "./fixtures";
+
+describe("delegated spenders", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // Any executable account can be delegated to; the token program stands in
+  // for an automated bill-payer here
+  const delegatedProgram = TOKEN_PROGRAM_ID;
+  const spender = Keypair.generate();
+  let fixture: EscrowFixture;
+  let delegatedSpender: PublicKey;
+
+  const grant = (cap: number, periodSecs: number) =>
+    program.methods
+      .grantDelegatedSpender(new anchor.BN(cap), new anchor.BN(periodSecs))
+      .accountsPartial({
+        delegatedSpender,
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        delegatedProgram,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    await airdrop(provider, spender.publicKey);
+    delegatedSpender = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("delegated_spender"),
+        fixture.owner.publicKey.toBuffer(),
+        delegatedProgram.toBuffer(),
+      ],
+      program.programId
+    )[0];
+  });
+
+  it("Records the delegated program, cap and period", async () => {
+    await grant(10_000000, 86_400);
+    const state = await program.account.delegatedSpender.fetch(delegatedSpender);
+    assert.ok(state.owner.equals(fixture.owner.publicKey));
+    assert.ok(state.program.equals(delegatedProgram));
+    assert.equal(state.cap.toNumber(), 10_000000);
+    assert.equal(state.periodSecs.toNumber(), 86_400);
+    assert.equal(state.spentInPeriod.toNumber(), 0);
+  });
+
+  it("Rejects delegated settlements not made by CPI from the program", async () => {
+    await expectError(
+      program.methods
+        .settleOfflinePayment(
+          new anchor.BN(1_000000),
+          new anchor.BN(1),
+          "delegated-1",
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial({
+          ...settleAccounts(fixture),
+          payer: spender.publicKey,
+          delegatedSpender,
+          instructionsSysvar: SYSVAR_INSTRUCTIONS_PUBKEY,
+        })
+        .signers([spender])
+        .rpc(),
+      "DelegationNotInvoked"
+    );
+  });
+
+  it("Rejects an empty cap or period", async () => {
+    await program.methods
+      .revokeDelegatedSpender()
+      .accountsPartial({ delegatedSpender, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+    assert.isNull(await program.account.delegatedSpender.fetchNullable(delegatedSpender));
+
+    await expectError(grant(0, 86_400), "InvalidAmount");
+    await expectError(grant(10_000000, 0), "InvalidConfig");
+  });
+});
@@ -0,0 +1,80 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import {
+  TOKEN_2022_PROGRAM_ID,
+  TOKEN_PROGRAM_ID,
+  getAssociatedTokenAddressSync,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import { createEscrowFixture } from "./fixtures";
+
+describe("escrow address derivation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const derive = (
+    owner: PublicKey,
+    mint: PublicKey,
+    tokenProgram: PublicKey,
+    escrowAccount: PublicKey | null = null
+  ) =>
+    program.methods
+      .deriveEscrowAddress(owner, mint)
+      .accountsPartial({ tokenProgram, escrowAccount })
+      .view();
+
+  it("Matches the escrow an owner opens", async () => {
+    const fixture = await createEscrowFixture(provider, program, 1_000000);
+    const derived = await derive(fixture.owner.publicKey, fixture.mint, TOKEN_PROGRAM_ID);
+
+    assert.ok(derived.escrow.equals(fixture.escrowPDA));
+    assert.equal(
+      derived.bump,
+      (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)).bump
+    );
+    assert.isFalse(derived.recorded);
+    assert.ok(
+      derived.tokenAccount.equals(
+        getAssociatedTokenAddressSync(fixture.mint, fixture.escrowPDA, true)
+      )
+    );
+  });
+
+  it("Returns the token account an existing escrow recorded", async () => {
+    // The fixture opens its escrow on a token account other than the ATA
+    const fixture = await createEscrowFixture(provider, program, 1_000000);
+    const derived = await derive(
+      fixture.owner.publicKey,
+      fixture.mint,
+      TOKEN_PROGRAM_ID,
+      fixture.escrowPDA
+    );
+
+    assert.ok(derived.escrow.equals(fixture.escrowPDA));
+    assert.isTrue(derived.recorded);
+    assert.ok(derived.tokenAccount.equals(fixture.escrowTokenAccount));
+  });
+
+  it("Derives the token account under the given token program", async () => {
+    const owner = Keypair.generate().publicKey;
+    const mint = Keypair.generate().publicKey;
+    const derived = await derive(owner, mint, TOKEN_2022_PROGRAM_ID);
+
+    const [escrow, bump] = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), owner.toBuffer()],
+      program.programId
+    );
+    assert.ok(derived.escrow.equals(escrow));
+    assert.equal(derived.bump, bump);
+    assert.isFalse(derived.recorded);
+    assert.ok(
+      derived.tokenAccount.equals(
+        getAssociatedTokenAddressSync(mint, escrow, true, TOKEN_2022_PROGRAM_ID)
+      )
+    );
+  });
+});
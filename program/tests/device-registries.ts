@@ -0,0 +1,180 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, SystemProgram } from "@solana/web3.js";
+import { createHash } from "crypto";
+import { keccak_256 } from "@noble/hashes/sha3";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("per-device nonce registries", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const tablet = Array.from(createHash("sha256").update("tablet").digest());
+  let fixture: EscrowFixture;
+  let tabletRegistry: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const settle = (
+    nonce: number,
+    bundleId: string,
+    device: number[] | null,
+    accounts: Record<string, PublicKey | null> = {}
+  ) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), bundleId, {
+        payerProof: null,
+        merchantProof: null,
+        deviceIdHash: device,
+      })
+      .accountsPartial({ ...settleAccounts(fixture), ...accounts })
+      .signers([fixture.owner])
+      .rpc();
+
+  const onTablet = {
+    nonceRegistry: null as PublicKey,
+    primaryNonceRegistry: null as PublicKey,
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    tabletRegistry = PublicKey.findProgramAddressSync(
+      [Buffer.from("nonce"), fixture.owner.publicKey.toBuffer(), Buffer.from(tablet)],
+      program.programId
+    )[0];
+    onTablet.nonceRegistry = tabletRegistry;
+    onTablet.primaryNonceRegistry = fixture.nonceRegistry;
+  });
+
+  it("Enrolls a device with its own registry", async () => {
+    await program.methods
+      .enrollDevice(tablet)
+      .accountsPartial({
+        primaryNonceRegistry: fixture.nonceRegistry,
+        deviceNonceRegistry: tabletRegistry,
+        owner: fixture.owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+    const registry = await program.account.nonceRegistry.fetch(tabletRegistry);
+    assert.ok(registry.owner.equals(fixture.owner.publicKey));
+    assert.deepEqual(registry.deviceIdHash, tablet);
+    assert.equal(registry.lastNonce.toNumber(), 0);
+  });
+
+  it("Gives each device an independent nonce space", async () => {
+    await settle(1, "phone-1", null);
+    await settle(1, "tablet-1", tablet, onTablet);
+    await settle(2, "tablet-2", tablet, onTablet);
+    await settle(2, "phone-2", null);
+
+    const tabletState = await program.account.nonceRegistry.fetch(tabletRegistry);
+    const primary = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    assert.equal(tabletState.lastNonce.toNumber(), 2);
+    assert.equal(primary.lastNonce.toNumber(), 2);
+  });
+
+  it("Selects the registry matching the bundle's device", async () => {
+    await expectError(settle(3, "tablet-3", null, onTablet), "DeviceRegistryMismatch");
+    await expectError(settle(3, "phone-3", tablet), "DeviceRegistryMismatch");
+    await expectError(
+      settle(3, "tablet-3", tablet, { nonceRegistry: tabletRegistry }),
+      "PrimaryRegistryRequired"
+    );
+  });
+
+  it("Detects duplicate bundles across devices", async () => {
+    await expectError(settle(3, "tablet-1", null), "DuplicateBundle");
+    await expectError(settle(3, "phone-1", tablet, onTablet), "DuplicateBundle");
+  });
+
+  it("Commits the device in the payer's attestation", async () => {
+    const attest = (device: number[] | null) =>
+      createAttestationProof(
+        AttestationRole.Payer,
+        "tablet-attested",
+        fixture.owner.publicKey,
+        fixture.merchant.publicKey,
+        1_000000,
+        3,
+        undefined,
+        null,
+        null,
+        null,
+        null,
+        device ? Uint8Array.from(device) : null
+      );
+    const settleAttested = async (proofDevice: number[] | null) =>
+      program.methods
+        .settleOfflinePayment(
+          new anchor.BN(1_000000),
+          new anchor.BN(3),
+          "tablet-attested",
+          { payerProof: await attest(proofDevice), merchantProof: null, deviceIdHash: tablet }
+        )
+        .accountsPartial({ ...settleAccounts(fixture), ...onTablet })
+        .signers([fixture.owner])
+        .rpc();
+
+    await expectError(settleAttested(null), "InvalidAttestation");
+    await settleAttested(tablet);
+  });
+
+  it("Clears the owner's liability when a device settles the bundle", async () => {
+    const bundleHash = Array.from(keccak_256(Buffer.from("tablet-liability")));
+    await program.methods
+      .registerLiability(bundleHash, fixture.merchant.publicKey, new anchor.BN(1_000000))
+      .accountsPartial({ nonceRegistry: fixture.nonceRegistry, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+    await settle(4, "tablet-liability", tablet, onTablet);
+
+    const primary = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    assert.isEmpty(primary.pendingLiabilities);
+    // Kept in the primary history too, so it counts toward the dispute reserve
+    assert.isTrue(primary.bundleHistory.some((record) =>
+      Buffer.from(record.bundleHash).equals(Buffer.from(bundleHash))
+    ));
+  });
+
+  it("Merges a retired device's history into the primary registry", async () => {
+    const before = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const device = await program.account.nonceRegistry.fetch(tabletRegistry);
+    await program.methods
+      .retireDevice()
+      .accountsPartial({
+        primaryNonceRegistry: fixture.nonceRegistry,
+        deviceNonceRegistry: tabletRegistry,
+        owner: fixture.owner.publicKey,
+        rentPayer: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+    const after = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    // Device settlements were mirrored into the primary history as they happened
+    assert.equal(after.bundleHistory.length, before.bundleHistory.length);
+    for (const record of device.bundleHistory) {
+      assert.isTrue(after.bundleHistory.some((kept) =>
+        Buffer.from(kept.bundleHash).equals(Buffer.from(record.bundleHash))
+      ));
+    }
+    assert.equal(after.lastNonce.toNumber(), before.lastNonce.toNumber());
+    assert.isNull(await program.account.nonceRegistry.fetchNullable(tabletRegistry));
+  });
+});
@@ -0,0 +1,128 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("dispute reserve", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const DEFAULT_FRAUD_PENALTY_BPS = 20_000;
+  const BUNDLE_AMOUNT = 1_000000;
+  const reporter = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setConfig = (delay: number, disputeReserve: boolean) =>
+    program.methods
+      .updateConfig({ fraudWithdrawalDelay: new anchor.BN(delay), disputeReserve })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const withdraw = (amount: number) =>
+    program.methods
+      .withdrawEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const escrow = () => program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await airdrop(provider, reporter.publicKey);
+    await setConfig(3600, false);
+
+    for (let nonce = 1; nonce <= 3; nonce++) {
+      await program.methods
+        .settleOfflinePayment(
+          new anchor.BN(BUNDLE_AMOUNT),
+          new anchor.BN(nonce),
+          `reserve-${nonce}`,
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc();
+    }
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, 7),
+      fixture.merchant.publicKey,
+      BUNDLE_AMOUNT,
+      1
+    );
+    await program.methods
+      .reportFraudulentBundle("reserve-1", conflictingHash, { duplicateBundle: {} }, evidence as any)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+  });
+
+  after(async () => {
+    await setConfig(0, false);
+  });
+
+  it("Freezes withdrawals outright by default", async () => {
+    await expectError(withdraw(1), "WithdrawalDelayedDueToFraud");
+  });
+
+  it("Holds back the heaviest penalty on every unreported bundle", async () => {
+    await setConfig(3600, true);
+    const state = await program.account.programConfig.fetch(config);
+    const maxBps = Math.max(
+      ...state.fraudPenaltyBps.map((bps: number) => (bps === 0 ? DEFAULT_FRAUD_PENALTY_BPS : bps))
+    );
+    // Two of the three settled bundles haven't been reported
+    const reserve = Math.floor((2 * BUNDLE_AMOUNT * maxBps) / 10_000);
+
+    const before = await escrow();
+    assert.isAbove(before.stakeLocked.toNumber(), 0);
+    const withdrawable = before.escrowBalance.toNumber() - reserve;
+
+    await expectError(withdraw(withdrawable + 1), "DisputeReserveRequired");
+    await withdraw(withdrawable);
+
+    const after = await escrow();
+    assert.equal(after.escrowBalance.toNumber(), reserve);
+    assert.equal(after.stakeLocked.toString(), before.stakeLocked.toString());
+  });
+
+  it("Releases the reserve once the dispute window closes", async () => {
+    await setConfig(0, true);
+    await withdraw((await escrow()).escrowBalance.toNumber());
+    assert.equal((await escrow()).escrowBalance.toNumber(), 0);
+  });
+});
@@ -0,0 +1,111 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig } from "./fixtures";
+
+describe("dormant escrow escheatment", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const YEAR = 365 * 86_400;
+  const NOTICE = 180 * 86_400;
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+  let custodialTokenAccount: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const updateConfig = (update: object) =>
+    program.methods
+      .updateConfig(update)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const flag = () =>
+    program.methods
+      .flagDormantEscrow()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA, config })
+      .rpc();
+
+  const sweep = () =>
+    program.methods
+      .sweepDormantEscrow()
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        custodialTokenAccount,
+        config,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 5_000000);
+    custodialTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      Keypair.generate().publicKey,
+      Keypair.generate()
+    );
+  });
+
+  after(async () => {
+    await updateConfig({ dormancyPeriod: new anchor.BN(0) });
+  });
+
+  it("Stays off until configured", async () => {
+    await expectError(flag(), "EscheatmentDisabled");
+  });
+
+  it("Enforces the hard-coded minimum periods", async () => {
+    await expectError(
+      updateConfig({
+        dormancyPeriod: new anchor.BN(30 * 86_400),
+        escheatNoticePeriod: new anchor.BN(NOTICE),
+        custodialTokenAccount,
+      }),
+      "InvalidConfig"
+    );
+    await expectError(
+      updateConfig({
+        dormancyPeriod: new anchor.BN(3 * YEAR),
+        escheatNoticePeriod: new anchor.BN(86_400),
+        custodialTokenAccount,
+      }),
+      "InvalidConfig"
+    );
+
+    await updateConfig({
+      dormancyPeriod: new anchor.BN(3 * YEAR),
+      escheatNoticePeriod: new anchor.BN(NOTICE),
+      custodialTokenAccount,
+    });
+    const stored = await program.account.programConfig.fetch(config);
+    assert.equal(stored.dormancyPeriod.toNumber(), 3 * YEAR);
+    assert.ok(stored.custodialTokenAccount.equals(custodialTokenAccount));
+  });
+
+  it("Refuses to flag an active escrow", async () => {
+    await expectError(flag(), "OwnerStillActive");
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.dormantSince.toNumber(), 0);
+  });
+
+  it("Refuses to sweep an escrow that was never flagged", async () => {
+    await expectError(sweep(), "EscrowNotDormant");
+  });
+});
@@ -0,0 +1,89 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  settleAccounts,
+} from "./fixtures";
+
+describe("escrow seeds and authority", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let other: EscrowFixture;
+  const stranger = Keypair.generate();
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    other = await createEscrowFixture(provider, program, 20_000000);
+    await airdrop(provider, stranger.publicKey);
+  });
+
+  it("Records the key the escrow PDA was derived from", async () => {
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.ok(escrow.pdaSeed.equals(fixture.owner.publicKey));
+    assert.ok(escrow.owner.equals(fixture.owner.publicKey));
+  });
+
+  it("Checks the owner against the stored field, not the seeds", async () => {
+    // Signed by another escrow's owner: the PDA resolves but the owner doesn't match
+    await expectError(
+      program.methods
+        .fundEscrow(new anchor.BN(1_000000))
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          owner: other.owner.publicKey,
+          ownerTokenAccount: other.ownerTokenAccount,
+          escrowTokenAccount: fixture.escrowTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([other.owner])
+        .rpc(),
+      "ConstraintHasOne"
+    );
+  });
+
+  it("Only accepts the owner, a tombstoned key or a delegation as settlement signer", async () => {
+    await expectError(
+      program.methods
+        .settleOfflinePayment(
+          new anchor.BN(1_000000),
+          new anchor.BN(1),
+          "authority-1",
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial({ ...settleAccounts(fixture), payer: stranger.publicKey })
+        .signers([stranger])
+        .rpc(),
+      "InvalidOwner"
+    );
+
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(1),
+        "authority-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+  });
+});
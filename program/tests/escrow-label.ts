@@ -0,0 +1,75 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture } from "./fixtures";
+
+describe("escrow labels", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const encodeLabel = (text: string): number[] => {
+    const label = Buffer.alloc(32);
+    Buffer.from(text, "utf8").copy(label);
+    return Array.from(label);
+  };
+
+  const setLabel = (label: number[]) =>
+    program.methods
+      .setLabel(label)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const summary = () =>
+    program.methods
+      .getEscrowSummary()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA })
+      .view();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 25_000000);
+  });
+
+  it("Stores the label and surfaces it in the summary", async () => {
+    await setLabel(encodeLabel("Groceries 🛒"));
+
+    const result = await summary();
+    const text = Buffer.from(result.label).toString("utf8").replace(/\0+$/, "");
+    assert.equal(text, "Groceries 🛒");
+    assert.ok(result.owner.equals(fixture.owner.publicKey));
+    assert.equal(result.escrowBalance.toNumber(), 25_000000);
+  });
+
+  it("Rejects labels that are not valid UTF-8", async () => {
+    const label = encodeLabel("bad");
+    label[3] = 0xff;
+    try {
+      await setLabel(label);
+      assert.fail("Should have failed with InvalidLabel");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidLabel");
+    }
+  });
+
+  it("Rejects control characters", async () => {
+    try {
+      await setLabel(encodeLabel("line\nbreak"));
+      assert.fail("Should have failed with InvalidLabel");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidLabel");
+    }
+  });
+
+  it("Clears the label with all zeroes", async () => {
+    await setLabel(Array(32).fill(0));
+    assert.deepEqual((await summary()).label, Array(32).fill(0));
+  });
+});
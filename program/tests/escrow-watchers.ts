@@ -0,0 +1,143 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture } from "./fixtures";
+
+describe("escrow top-up watchers", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const MAX_WATCHERS = 4;
+  const lowThreshold = Keypair.generate().publicKey;
+  const highThreshold = Keypair.generate().publicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const ownerAccounts = () => ({
+    escrowAccount: fixture.escrowPDA,
+    owner: fixture.owner.publicKey,
+  });
+
+  const watch = (merchant: PublicKey, threshold: number) =>
+    program.methods
+      .setEscrowWatcher(merchant, new anchor.BN(threshold))
+      .accountsPartial(ownerAccounts())
+      .signers([fixture.owner])
+      .rpc();
+
+  const unwatch = (merchant: PublicKey) =>
+    program.methods
+      .removeEscrowWatcher(merchant)
+      .accountsPartial(ownerAccounts())
+      .signers([fixture.owner])
+      .rpc();
+
+  const tokenAccounts = () => ({
+    ...ownerAccounts(),
+    ownerTokenAccount: fixture.ownerTokenAccount,
+    escrowTokenAccount: fixture.escrowTokenAccount,
+    tokenProgram: TOKEN_PROGRAM_ID,
+  });
+
+  // Watchers named by the top-up's targeted events
+  const fund = async (amount: number) => {
+    const signature = await program.methods
+      .fundEscrow(new anchor.BN(amount))
+      .accountsPartial(tokenAccounts())
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+    return (await eventsOf(signature))
+      .filter((event) => event.name === "escrowFundedForWatcher")
+      .map((event) => event.data.watcher.toBase58());
+  };
+
+  const withdraw = (amount: number) =>
+    program.methods
+      .withdrawEscrow(new anchor.BN(amount))
+      .accountsPartial(tokenAccounts())
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 5_000000);
+    await watch(lowThreshold, 3_000000);
+    await watch(highThreshold, 10_000000);
+  });
+
+  it("Names only the watchers whose threshold the top-up crosses", async () => {
+    // 5 -> 15 crosses 10 but started above 3
+    assert.deepEqual(await fund(10_000000), [highThreshold.toBase58()]);
+    // Already above both
+    assert.deepEqual(await fund(1_000000), []);
+  });
+
+  it("Fires again once the balance has fallen back below the threshold", async () => {
+    await withdraw(14_000000);
+    // 2 -> 12 crosses both
+    assert.deepEqual(
+      (await fund(10_000000)).sort(),
+      [lowThreshold.toBase58(), highThreshold.toBase58()].sort()
+    );
+  });
+
+  it("Moves an existing watcher's threshold in place", async () => {
+    await watch(lowThreshold, 20_000000);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    const registered = escrow.watchers.filter((watcher) => !watcher.merchant.equals(PublicKey.default));
+    assert.equal(registered.length, 2);
+    assert.equal(
+      registered.find((watcher) => watcher.merchant.equals(lowThreshold)).threshold.toNumber(),
+      20_000000
+    );
+    assert.deepEqual(await fund(10_000000), [lowThreshold.toBase58()]);
+  });
+
+  it("Stops naming a removed watcher", async () => {
+    await withdraw(20_000000);
+    await unwatch(lowThreshold);
+    assert.deepEqual(await fund(20_000000), [highThreshold.toBase58()]);
+    await expectError(unwatch(lowThreshold), "InvalidWatcher");
+  });
+
+  it("Keeps the watcher set bounded", async () => {
+    for (let i = 1; i < MAX_WATCHERS; i++) {
+      await watch(Keypair.generate().publicKey, i);
+    }
+    await expectError(watch(Keypair.generate().publicKey, 1), "WatcherTableFull");
+    await expectError(watch(PublicKey.default, 1), "InvalidWatcher");
+  });
+
+  it("Only lets the owner manage watchers", async () => {
+    const stranger = Keypair.generate();
+    await expectError(
+      program.methods
+        .setEscrowWatcher(stranger.publicKey, new anchor.BN(1))
+        .accountsPartial({ escrowAccount: fixture.escrowPDA, owner: stranger.publicKey })
+        .signers([stranger])
+        .rpc(),
+      "ConstraintHasOne"
+    );
+  });
+});
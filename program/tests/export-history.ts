@@ -0,0 +1,106 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  settleAccounts,
+  simulateReturnData,
+} from "./fixtures";
+
+const HEADER_LEN = 6;
+const RECORD_LEN = 120;
+
+describe("history export", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const exportPage = async (start: number, maxRecords: number) =>
+    simulateReturnData(
+      provider,
+      await program.methods
+        .exportHistory(start, maxRecords)
+        .accountsPartial({ nonceRegistry: fixture.nonceRegistry })
+        .transaction()
+    );
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    for (let nonce = 1; nonce <= 3; nonce++) {
+      await program.methods
+        .settleOfflinePayment(
+          new anchor.BN(nonce * 1_000000),
+          new anchor.BN(nonce),
+          `export-${nonce}`,
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc();
+    }
+  });
+
+  it("Exports records in the packed layout", async () => {
+    const blob = await exportPage(0, 255);
+    assert.equal(blob[0], 4);
+    assert.equal(blob.readUInt16LE(1), 3);
+    assert.equal(blob.readUInt16LE(3), 0);
+    assert.equal(blob[5], 3);
+    assert.equal(blob.length, HEADER_LEN + 3 * RECORD_LEN);
+
+    const registry = await program.account.nonceRegistry.fetch(
+      fixture.nonceRegistry
+    );
+    registry.bundleHistory.forEach((record, i) => {
+      const offset = HEADER_LEN + i * RECORD_LEN;
+      assert.deepEqual(
+        Array.from(blob.subarray(offset, offset + 32)),
+        record.bundleHash
+      );
+      assert.equal(
+        new anchor.web3.PublicKey(
+          blob.subarray(offset + 32, offset + 64)
+        ).toString(),
+        record.merchant.toString()
+      );
+      assert.equal(
+        blob.readBigUInt64LE(offset + 64).toString(),
+        record.amount.toString()
+      );
+      assert.equal(
+        blob.readBigUInt64LE(offset + 80).toString(),
+        record.nonce.toString()
+      );
+      assert.deepEqual(
+        Array.from(blob.subarray(offset + 88, offset + 104)),
+        record.orderRef
+      );
+      assert.equal(
+        blob.readBigInt64LE(offset + 104).toString(),
+        record.bundleCreatedAt.toString()
+      );
+      assert.equal(blob.readBigUInt64LE(offset + 112).toString(), String(i + 1));
+    });
+  });
+
+  it("Pages from a start index", async () => {
+    const blob = await exportPage(2, 5);
+    assert.equal(blob.readUInt16LE(3), 2);
+    assert.equal(blob[5], 1);
+    assert.equal(blob.readBigUInt64LE(HEADER_LEN + 80).toString(), "3");
+  });
+
+  it("Rejects a start index past the end", async () => {
+    try {
+      await exportPage(4, 1);
+      assert.fail("Should have failed with InvalidExportRange");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidExportRange");
+    }
+  });
+});
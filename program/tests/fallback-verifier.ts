@@ -0,0 +1,135 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import * as crypto from "crypto";
+import * as ed25519 from "@noble/ed25519";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("fallback verifier", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const FALLBACK_CAP = 2_000000;
+  const PRIMARY_DOWN = 1;
+  const fallbackKey = Uint8Array.from(crypto.randomBytes(32));
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const settle = async (
+    amount: number,
+    nonce: number,
+    fallbackReason: number | null = PRIMARY_DOWN
+  ) => {
+    const bundleId = `fallback-${nonce}`;
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      amount,
+      nonce,
+      fallbackReason === null ? undefined : fallbackKey,
+      null,
+      null,
+      null,
+      fallbackReason
+    );
+    return program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        bundleId,
+        { payerProof, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+  };
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    const verifier = new PublicKey(await ed25519.getPublicKeyAsync(fallbackKey));
+    await program.methods
+      .setFallbackVerifier(verifier, new anchor.BN(FALLBACK_CAP))
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .revokeFallbackVerifier()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Accepts fallback proofs under the cap and reports them", async () => {
+    const signature = await settle(1_000000, 1);
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const used = Array.from(parser.parseLogs(tx.meta.logMessages)).find(
+      (event) => event.name === "fallbackAttestationUsed"
+    );
+    assert.equal(used.data.reason, PRIMARY_DOWN);
+    assert.equal(used.data.amount.toNumber(), 1_000000);
+  });
+
+  it("Rejects fallback proofs above the cap", async () => {
+    await expectError(settle(FALLBACK_CAP + 1, 2), "FallbackCapExceeded");
+  });
+
+  it("Rejects fallback-signed proofs that omit the reason", async () => {
+    const bundleId = "fallback-3";
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      1_000000,
+      3,
+      fallbackKey
+    );
+    await expectError(
+      program.methods
+        .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(3), bundleId, {
+          payerProof,
+          merchantProof: null,
+        })
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc(),
+      "InvalidAttestation"
+    );
+  });
+
+  it("Revoking the fallback leaves the primary verifier working", async () => {
+    await program.methods
+      .revokeFallbackVerifier()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await expectError(settle(1_000000, 4), "FallbackVerifierUnavailable");
+    await settle(1_000000, 4, null);
+  });
+});
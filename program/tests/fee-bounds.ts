@@ -0,0 +1,119 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("protocol fee floor and ceiling", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const MIN_FEE = 50000;
+  const MAX_FEE = 200000;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let treasuryTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `fee-bounds-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({ ...settleAccounts(fixture), treasuryTokenAccount })
+      .signers([fixture.owner])
+      .rpc();
+
+  // Fee charged on a settlement, read off the treasury balance
+  const feeOn = async (amount: number, nonce: number) => {
+    const before = await balanceOf(treasuryTokenAccount);
+    await settle(amount, nonce);
+    return (await balanceOf(treasuryTokenAccount)) - before;
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 200_000000);
+    treasuryTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      provider.wallet.publicKey,
+      Keypair.generate()
+    );
+
+    // 1%, clamped into [0.05, 0.2] tokens
+    await program.methods
+      .updateConfig({
+        feeBps: 100,
+        treasury: provider.wallet.publicKey,
+        minFee: new anchor.BN(MIN_FEE),
+        maxFee: new anchor.BN(MAX_FEE),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .updateConfig({
+        feeBps: 0,
+        minFee: new anchor.BN(0),
+        maxFee: new anchor.BN(0),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Raises dust fees to the floor", async () => {
+    assert.equal(await feeOn(1_000000, 1), MIN_FEE);
+  });
+
+  it("Charges the bps fee between the bounds", async () => {
+    assert.equal(await feeOn(10_000000, 2), 100000);
+  });
+
+  it("Caps large fees at the ceiling", async () => {
+    const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+    assert.equal(await feeOn(100_000000, 3), MAX_FEE);
+    assert.equal(
+      (await balanceOf(fixture.merchantTokenAccount)) - merchantBefore,
+      100_000000 - MAX_FEE
+    );
+  });
+
+  it("Rejects payments the floor fee would consume entirely", async () => {
+    try {
+      await settle(MIN_FEE, 4);
+      assert.fail("Should have failed with FeeExceedsAmount");
+    } catch (err) {
+      assert.include(err.toString(), "FeeExceedsAmount");
+    }
+  });
+
+  it("Rejects a floor above the ceiling", async () => {
+    try {
+      await program.methods
+        .updateConfig({ minFee: new anchor.BN(MAX_FEE + 1) })
+        .accountsPartial({ config, admin: provider.wallet.publicKey })
+        .rpc();
+      assert.fail("Should have failed with InvalidConfig");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConfig");
+    }
+  });
+});
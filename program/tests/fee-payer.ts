@@ -0,0 +1,132 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("protocol fee payer", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 10_000000;
+  // 1% of AMOUNT
+  const FEE = 100000;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let treasuryTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const escrowBalance = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)).escrowBalance.toNumber();
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setFeePayer = (feePayer: object) =>
+    program.methods
+      .updateConfig({ feePayer })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  // Balance changes of a settlement and the fee event it emitted
+  const settle = async (amount: number, nonce: number) => {
+    const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+    const treasuryBefore = await balanceOf(treasuryTokenAccount);
+    const escrowBefore = await escrowBalance();
+    const signature = await program.methods
+      .settleOfflinePayment(new anchor.BN(amount), new anchor.BN(nonce), `fee-payer-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({ ...settleAccounts(fixture), treasuryTokenAccount })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+    return {
+      merchant: (await balanceOf(fixture.merchantTokenAccount)) - merchantBefore,
+      treasury: (await balanceOf(treasuryTokenAccount)) - treasuryBefore,
+      escrow: escrowBefore - (await escrowBalance()),
+      event: (await eventsOf(signature)).find((event) => event.name === "settlementFeeCollected"),
+    };
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    treasuryTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      provider.wallet.publicKey,
+      Keypair.generate()
+    );
+    await program.methods
+      .updateConfig({ feeBps: 100, treasury: provider.wallet.publicKey })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .updateConfig({ feeBps: 0, minFee: new anchor.BN(0), feePayer: { merchant: {} } })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Deducts the fee from the merchant's payout by default", async () => {
+    const result = await settle(AMOUNT, 1);
+    assert.equal(result.merchant, AMOUNT - FEE);
+    assert.equal(result.treasury, FEE);
+    assert.equal(result.escrow, AMOUNT);
+    assert.deepEqual(result.event.data.feePayer, { merchant: {} });
+  });
+
+  it("Charges the escrow on top of the payment when the payer bears the fee", async () => {
+    await setFeePayer({ payer: {} });
+    const result = await settle(AMOUNT, 2);
+    assert.equal(result.merchant, AMOUNT);
+    assert.equal(result.treasury, FEE);
+    assert.equal(result.escrow, AMOUNT + FEE);
+    assert.equal(result.event.data.fee.toNumber(), FEE);
+    assert.deepEqual(result.event.data.feePayer, { payer: {} });
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.totalSpent.toNumber(), 2 * AMOUNT + FEE);
+  });
+
+  it("Requires the balance to cover the fee as well when the payer bears it", async () => {
+    const balance = await escrowBalance();
+    try {
+      await settle(balance, 3);
+      assert.fail("Should have failed with InsufficientFunds");
+    } catch (err) {
+      assert.include(err.toString(), "InsufficientFunds");
+    }
+  });
+
+  it("Lets a floor fee exceed the payment when the payer bears it", async () => {
+    await program.methods
+      .updateConfig({ minFee: new anchor.BN(2_000000) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    const result = await settle(1_000000, 3);
+    assert.equal(result.merchant, 1_000000);
+    assert.equal(result.treasury, 2_000000);
+  });
+});
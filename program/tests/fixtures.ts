@@ -0,0 +1,233 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createMint,
+  createAccount,
+  mintTo,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+
+const BPF_LOADER_UPGRADEABLE_PROGRAM_ID = new PublicKey(
+  "BPFLoaderUpgradeab1e11111111111111111111111"
+);
+
+export function findConfigPDA(program: Program<Beam>): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("config")],
+    program.programId
+  )[0];
+}
+
+// The config is global and created once by the upgrade authority (the provider wallet)
+export async function ensureConfig(
+  provider: anchor.AnchorProvider,
+  program: Program<Beam>
+): Promise<PublicKey> {
+  const config = findConfigPDA(program);
+  if (await provider.connection.getAccountInfo(config)) {
+    return config;
+  }
+
+  const [programData] = PublicKey.findProgramAddressSync(
+    [program.programId.toBuffer()],
+    BPF_LOADER_UPGRADEABLE_PROGRAM_ID
+  );
+  await program.methods
+    .initializeConfig(new anchor.BN(0), { keccak256: {} })
+    .accountsPartial({
+      config,
+      admin: provider.wallet.publicKey,
+      program: program.programId,
+      programData,
+      systemProgram: SystemProgram.programId,
+    })
+    .rpc();
+  return config;
+}
+
+// Fresh payer/merchant pair with an initialized, funded escrow and nonce registry.
+// Each feature suite builds its own so tests don't share escrow state.
+export interface EscrowFixture {
+  owner: Keypair;
+  merchant: Keypair;
+  mint: PublicKey;
+  ownerTokenAccount: PublicKey;
+  merchantTokenAccount: PublicKey;
+  escrowPDA: PublicKey;
+  escrowTokenAccount: PublicKey;
+  nonceRegistry: PublicKey;
+}
+
+export async function airdrop(
+  provider: anchor.AnchorProvider,
+  to: PublicKey,
+  sol = 2
+): Promise<void> {
+  const sig = await provider.connection.requestAirdrop(
+    to,
+    sol * anchor.web3.LAMPORTS_PER_SOL
+  );
+  await provider.connection.confirmTransaction(sig, "confirmed");
+}
+
+export function findEscrowPDA(
+  program: Program<Beam>,
+  owner: PublicKey
+): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("escrow"), owner.toBuffer()],
+    program.programId
+  )[0];
+}
+
+export function findNonceRegistryPDA(
+  program: Program<Beam>,
+  owner: PublicKey
+): PublicKey {
+  return PublicKey.findProgramAddressSync(
+    [Buffer.from("nonce"), owner.toBuffer()],
+    program.programId
+  )[0];
+}
+
+export async function createEscrowFixture(
+  provider: anchor.AnchorProvider,
+  program: Program<Beam>,
+  initialAmount = 500_000000,
+  mintAmount = 1000_000000,
+  referrer: PublicKey | null = null
+): Promise<EscrowFixture> {
+  await ensureConfig(provider, program);
+  const owner = Keypair.generate();
+  const merchant = Keypair.generate();
+  await airdrop(provider, owner.publicKey);
+
+  const mint = await createMint(
+    provider.connection,
+    owner,
+    owner.publicKey,
+    null,
+    6
+  );
+  const ownerTokenAccount = (
+    await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      owner,
+      mint,
+      owner.publicKey
+    )
+  ).address;
+  const merchantTokenAccount = (
+    await getOrCreateAssociatedTokenAccount(
+      provider.connection,
+      owner,
+      mint,
+      merchant.publicKey
+    )
+  ).address;
+
+  const escrowPDA = findEscrowPDA(program, owner.publicKey);
+  const nonceRegistry = findNonceRegistryPDA(program, owner.publicKey);
+  const escrowTokenAccount = await createAccount(
+    provider.connection,
+    owner,
+    mint,
+    escrowPDA,
+    Keypair.generate()
+  );
+
+  await mintTo(
+    provider.connection,
+    owner,
+    mint,
+    ownerTokenAccount,
+    owner,
+    mintAmount
+  );
+
+  await program.methods
+    .initializeEscrow(new anchor.BN(initialAmount))
+    .accounts({
+      escrowAccount: escrowPDA,
+      owner: owner.publicKey,
+      rentPayer: owner.publicKey,
+      ownerTokenAccount,
+      escrowTokenAccount,
+      referrer,
+      tokenProgram: TOKEN_PROGRAM_ID,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([owner])
+    .rpc();
+
+  await program.methods
+    .initializeNonceRegistry()
+    .accountsPartial({
+      owner: owner.publicKey,
+      rentPayer: owner.publicKey,
+      nonceRegistry,
+      systemProgram: SystemProgram.programId,
+    })
+    .signers([owner])
+    .rpc();
+
+  return {
+    owner,
+    merchant,
+    mint,
+    ownerTokenAccount,
+    merchantTokenAccount,
+    escrowPDA,
+    escrowTokenAccount,
+    nonceRegistry,
+  };
+}
+
+// Accounts for settle_offline_payment and the other SettlePayment-based instructions
+export function settleAccounts(fixture: EscrowFixture) {
+  return {
+    escrowAccount: fixture.escrowPDA,
+    owner: fixture.owner.publicKey,
+    payer: fixture.owner.publicKey,
+    merchant: fixture.merchant.publicKey,
+    escrowTokenAccount: fixture.escrowTokenAccount,
+    merchantTokenAccount: fixture.merchantTokenAccount,
+    nonceRegistry: fixture.nonceRegistry,
+    tokenProgram: TOKEN_PROGRAM_ID,
+  };
+}
+
+// Raw return data written by the program in a confirmed transaction
+export async function fetchReturnData(
+  provider: anchor.AnchorProvider,
+  signature: string
+): Promise<Buffer> {
+  await provider.connection.confirmTransaction(signature, "confirmed");
+  const tx = await provider.connection.getTransaction(signature, {
+    commitment: "confirmed",
+    maxSupportedTransactionVersion: 0,
+  });
+  const [data] = tx.meta.returnData.data;
+  return Buffer.from(data, "base64");
+}
+
+// Return data of a read-only instruction, obtained by simulation
+export async function simulateReturnData(
+  provider: anchor.AnchorProvider,
+  tx: anchor.web3.Transaction
+): Promise<Buffer> {
+  tx.feePayer = provider.wallet.publicKey;
+  const sim = await provider.connection.simulateTransaction(tx);
+  if (sim.value.err) {
+    throw new Error(
+      `Simulation failed: ${JSON.stringify(sim.value.err)}\n${sim.value.logs?.join(
+        "\n"
+      )}`
+    );
+  }
+  const [data] = sim.value.returnData.data;
+  return Buffer.from(data, "base64");
+}
@@ -0,0 +1,173 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("fraud record pruning", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const reporter = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let fraudArchive: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setDelay = (seconds: number) =>
+    program.methods
+      .updateConfig({ fraudWithdrawalDelay: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settleAndReport = async (nonce: number) => {
+    const bundleId = `archive-${nonce}`;
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), bundleId, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, nonce),
+      fixture.merchant.publicKey,
+      1_000000,
+      nonce
+    );
+    await program.methods
+      .reportFraudulentBundle(bundleId, conflictingHash, { duplicateBundle: {} }, evidence as any)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+  };
+
+  const prune = (olderThan: number, authority: Keypair | null = fixture.owner) =>
+    program.methods
+      .pruneFraudRecords(new anchor.BN(olderThan))
+      .accountsPartial({
+        nonceRegistry: fixture.nonceRegistry,
+        fraudArchive,
+        authority: authority ? authority.publicKey : provider.wallet.publicKey,
+        config,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers(authority ? [authority] : [])
+      .rpc({ commitment: "confirmed" });
+
+  const fraudRecords = async () =>
+    (await program.account.nonceRegistry.fetch(fixture.nonceRegistry)).fraudRecords;
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    await airdrop(provider, reporter.publicKey);
+    [fraudArchive] = PublicKey.findProgramAddressSync(
+      [Buffer.from("fraud_archive"), fixture.owner.publicKey.toBuffer()],
+      program.programId
+    );
+
+    await setDelay(3600);
+    await settleAndReport(1);
+    await settleAndReport(2);
+    const [record] = await fraudRecords();
+    await program.methods
+      .submitCounterEvidence(
+        Array.from(record.bundleHash),
+        Array.from(record.conflictingHash),
+        Array.from(Buffer.alloc(32, 9)),
+        Array.from(Buffer.concat([Buffer.from("replayed by merchant"), Buffer.alloc(44)]))
+      )
+      .accountsPartial({ nonceRegistry: fixture.nonceRegistry, payer: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+  });
+
+  after(async () => {
+    await setDelay(0);
+  });
+
+  it("Keeps records whose dispute is still open", async () => {
+    await expectError(prune(Math.floor(Date.now() / 1000) + 60), "NoFraudRecordsToPrune");
+    assert.equal((await fraudRecords()).length, 2);
+  });
+
+  it("Refuses signers other than the payer or the admin", async () => {
+    await setDelay(0);
+    const stranger = Keypair.generate();
+    await airdrop(provider, stranger.publicKey);
+    await expectError(prune(Math.floor(Date.now() / 1000) + 60, stranger), "Unauthorized");
+  });
+
+  it("Prunes only records reported strictly before the cutoff", async () => {
+    const before = await fraudRecords();
+    const cutoff = before[0].reportedAt.toNumber();
+    await expectError(prune(cutoff), "NoFraudRecordsToPrune");
+
+    const signature = await prune(cutoff + 1);
+
+    const expected = before.filter((record) => record.reportedAt.toNumber() <= cutoff);
+    const remaining = await fraudRecords();
+    assert.equal(remaining.length, before.length - expected.length);
+    assert.isTrue(remaining.every((record) => record.reportedAt.toNumber() > cutoff));
+
+    const archive = await program.account.fraudArchive.fetch(fraudArchive);
+    assert.ok(archive.owner.equals(fixture.owner.publicKey));
+    assert.deepEqual(archive.records, expected);
+    assert.equal(archive.totalArchived.toNumber(), expected.length);
+    // The counter evidence travels with its record
+    assert.isAbove(archive.records[0].counteredAt.toNumber(), 0);
+
+    const pruned = (await eventsOf(signature)).find((event) => event.name === "fraudRecordsPruned");
+    assert.equal(pruned.data.pruned, expected.length);
+    assert.equal(pruned.data.stillOpen, 0);
+    assert.equal(pruned.data.remaining, remaining.length);
+    assert.ok(pruned.data.authority.equals(fixture.owner.publicKey));
+  });
+
+  it("Lets the admin prune and appends to the existing archive", async () => {
+    await settleAndReport(3);
+    const before = await fraudRecords();
+    const archived = (await program.account.fraudArchive.fetch(fraudArchive)).records;
+
+    await prune(before[before.length - 1].reportedAt.toNumber() + 1, null);
+
+    assert.equal((await fraudRecords()).length, 0);
+    const archive = await program.account.fraudArchive.fetch(fraudArchive);
+    assert.deepEqual(archive.records, [...archived, ...before]);
+    assert.equal(archive.totalArchived.toNumber(), 3);
+  });
+});
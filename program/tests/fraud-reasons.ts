@@ -0,0 +1,235 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import {
+  AttestationRole,
+  createAttestationProof,
+  signConflictingBundle,
+} from "./attestation-helper";
+
+describe("fraud reasons", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 1_000000;
+  const reporter = Keypair.generate();
+  const arbiters = [Keypair.generate(), Keypair.generate()];
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const report = (
+    conflictingHash: Buffer | number[],
+    reason: object,
+    evidence: object,
+    arbiters: Keypair[] = []
+  ) =>
+    program.methods
+      .reportFraudulentBundle("reasons-1", conflictingHash, reason as any, evidence as any)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .remainingAccounts(
+        arbiters.map((arbiter) => ({ pubkey: arbiter.publicKey, isSigner: true, isWritable: false }))
+      )
+      .signers([reporter, ...arbiters])
+      .rpc();
+
+  // A bundle the payer signed with the settled nonce, `signer` standing in for
+  // the payer to forge one
+  const signed = (
+    bundleHash: Uint8Array,
+    merchant: PublicKey,
+    amount: number,
+    nonce = 1,
+    signer = fixture.owner
+  ) => signConflictingBundle(signer, bundleHash, merchant, amount, nonce);
+
+  const settledBundleHash = async () =>
+    Buffer.from(
+      (await program.account.nonceRegistry.fetch(fixture.nonceRegistry)).bundleHistory[0].bundleHash
+    );
+
+  const setPenalty = (reason: object, penaltyBps: number) =>
+    program.methods
+      .setFraudPenalty(reason as any, penaltyBps)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const summary = () =>
+    program.methods
+      .getFraudSummary()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA })
+      .view();
+
+  const stakeLocked = async () =>
+    (
+      await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)
+    ).stakeLocked.toNumber();
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const expectMismatch = (promise: Promise<unknown>) =>
+    expectError(promise, "FraudEvidenceMismatch");
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await airdrop(provider, reporter.publicKey);
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(AMOUNT),
+        new anchor.BN(1),
+        "reasons-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    await program.methods
+      .setArbiters(
+        arbiters.map((arbiter) => arbiter.publicKey),
+        2
+      )
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await setPenalty({ amountTampering: {} }, 0);
+    await program.methods
+      .setArbiters([], 0)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Rejects evidence of the wrong shape for the reason", async () => {
+    const { conflictingHash, evidence } = await signed(
+      Buffer.alloc(32, 1),
+      fixture.merchant.publicKey,
+      AMOUNT
+    );
+    await expectMismatch(report(conflictingHash, { nonceReuse: {} }, { none: {} }));
+    await expectMismatch(report(conflictingHash, { expiredBundleSettled: {} }, evidence));
+  });
+
+  it("Checks the payer really signed the conflicting bundle", async () => {
+    const other = Keypair.generate().publicKey;
+    const forged = await signed(Buffer.alloc(32, 1), other, AMOUNT, 1, Keypair.generate());
+    await expectMismatch(report(forged.conflictingHash, { nonceReuse: {} }, forged.evidence));
+
+    // A conflicting hash other than the signed digest doesn't count
+    const genuine = await signed(Buffer.alloc(32, 1), other, AMOUNT);
+    await expectMismatch(report(Buffer.alloc(32, 1), { nonceReuse: {} }, genuine.evidence));
+  });
+
+  it("Checks nonce reuse evidence against the settled bundle", async () => {
+    const other = Keypair.generate().publicKey;
+    const otherNonce = await signed(Buffer.alloc(32, 1), other, AMOUNT, 2);
+    await expectMismatch(report(otherNonce.conflictingHash, { nonceReuse: {} }, otherNonce.evidence));
+    // The same terms under the same nonce are a duplicate, not a reuse
+    const sameTerms = await signed(Buffer.alloc(32, 1), fixture.merchant.publicKey, AMOUNT);
+    await expectMismatch(report(sameTerms.conflictingHash, { nonceReuse: {} }, sameTerms.evidence));
+
+    const { conflictingHash, evidence } = await signed(Buffer.alloc(32, 1), other, AMOUNT);
+    await report(conflictingHash, { nonceReuse: {} }, evidence);
+    assert.equal(await stakeLocked(), 2 * AMOUNT);
+  });
+
+  it("Slashes by the configured penalty for the reason", async () => {
+    const bundleHash = await settledBundleHash();
+    const sameAmount = await signed(bundleHash, fixture.merchant.publicKey, AMOUNT);
+    await expectMismatch(
+      report(sameAmount.conflictingHash, { amountTampering: {} }, sameAmount.evidence)
+    );
+
+    await setPenalty({ amountTampering: {} }, 5_000);
+    const before = await stakeLocked();
+    const { conflictingHash, evidence } = await signed(
+      bundleHash,
+      fixture.merchant.publicKey,
+      AMOUNT / 2
+    );
+    await report(conflictingHash, { amountTampering: {} }, evidence);
+    assert.equal((await stakeLocked()) - before, AMOUNT / 2);
+  });
+
+  it("Takes the attested deadline as proof of an expired settlement", async () => {
+    const attest = (deadline: number) =>
+      createAttestationProof(
+        AttestationRole.Payer,
+        "reasons-1",
+        fixture.owner.publicKey,
+        fixture.merchant.publicKey,
+        AMOUNT,
+        1,
+        undefined,
+        { timestamp: { 0: new anchor.BN(deadline) } }
+      );
+    const evidenceOf = (proof: any) => ({
+      attestation: {
+        0: { proof, role: { payer: {} }, courier: null, deviceIdHash: null, conditionId: null },
+      },
+    });
+    const now = Math.floor(Date.now() / 1000);
+
+    const unexpired = await attest(now + 3600);
+    await expectMismatch(
+      report(unexpired.attestationRoot, { expiredBundleSettled: {} }, evidenceOf(unexpired))
+    );
+    const unsigned = { ...(await attest(now - 3600)), verifierSignature: new Array(64).fill(1) };
+    await expectMismatch(
+      report(unsigned.attestationRoot, { expiredBundleSettled: {} }, evidenceOf(unsigned))
+    );
+
+    const expired = await attest(now - 3600);
+    await report(expired.attestationRoot, { expiredBundleSettled: {} }, evidenceOf(expired));
+  });
+
+  it("Leaves reasons without on-chain proof to the arbiters", async () => {
+    await expectError(
+      report(Buffer.alloc(32, 3), { forgedSignature: {} }, { none: {} }),
+      "ArbiterQuorumNotMet"
+    );
+    await expectError(
+      report(Buffer.alloc(32, 3), { forgedSignature: {} }, { none: {} }, [arbiters[0]]),
+      "ArbiterQuorumNotMet"
+    );
+    await report(Buffer.alloc(32, 3), { forgedSignature: {} }, { none: {} }, arbiters);
+  });
+
+  it("Breaks the fraud summary down by reason", async () => {
+    const fraud = await summary();
+    assert.equal(fraud.fraudCount, 4);
+    // Indexed in FraudReason order
+    assert.deepEqual(fraud.byReason, [0, 0, 0, 1, 1, 1, 1]);
+  });
+
+  it("Rejects penalties above the maximum", async () => {
+    try {
+      await setPenalty({ forgedSignature: {} }, 50_001);
+      assert.fail("Should have failed with InvalidConfig");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConfig");
+    }
+  });
+});
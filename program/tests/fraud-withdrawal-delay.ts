@@ -0,0 +1,119 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("fraud withdrawal delay", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const reporter = Keypair.generate();
+  let fraudulent: EscrowFixture;
+  let clean: EscrowFixture;
+  let config: PublicKey;
+
+  const setDelay = (seconds: number) =>
+    program.methods
+      .updateConfig({ fraudWithdrawalDelay: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const withdraw = (fixture: EscrowFixture, amount: number) =>
+    program.methods
+      .withdrawEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fraudulent = await createEscrowFixture(provider, program, 20_000000);
+    clean = await createEscrowFixture(provider, program, 20_000000);
+    await airdrop(provider, reporter.publicKey);
+    await setDelay(3600);
+
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(1),
+        "delay-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fraudulent))
+      .signers([fraudulent.owner])
+      .rpc();
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fraudulent.owner,
+      Buffer.alloc(32, 5),
+      fraudulent.merchant.publicKey,
+      1_000000,
+      1
+    );
+    await program.methods
+      .reportFraudulentBundle(
+        "delay-1",
+        conflictingHash,
+        { duplicateBundle: {} },
+        evidence as any
+      )
+      .accountsPartial({
+        escrowAccount: fraudulent.escrowPDA,
+        payer: fraudulent.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+  });
+
+  after(async () => {
+    await setDelay(0);
+  });
+
+  it("Holds withdrawals after a recent fraud report", async () => {
+    try {
+      await withdraw(fraudulent, 1_000000);
+      assert.fail("Should have failed with WithdrawalDelayedDueToFraud");
+    } catch (err) {
+      assert.include(err.toString(), "WithdrawalDelayedDueToFraud");
+      assert.isTrue(
+        err.logs.some((line: string) => line.includes("Withdrawals unlock at"))
+      );
+    }
+  });
+
+  it("Leaves clean escrows alone", async () => {
+    await withdraw(clean, 1_000000);
+  });
+
+  it("Releases withdrawals once the delay is lifted", async () => {
+    await setDelay(0);
+    await withdraw(fraudulent, 1_000000);
+  });
+
+  it("Rejects delays beyond the maximum", async () => {
+    try {
+      await setDelay(31 * 86_400);
+      assert.fail("Should have failed with InvalidConfig");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConfig");
+    }
+  });
+});
@@ -0,0 +1,96 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("funding lockup", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const LOCKUP_SECS = 4;
+  let fixture: EscrowFixture;
+  let config: anchor.web3.PublicKey;
+
+  const setLockup = (secs: number) =>
+    program.methods
+      .updateConfig({ fundingLockupSecs: new anchor.BN(secs) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `lockup-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    // Initial funds are deposited before the lockup is enabled and are mature
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+    await setLockup(LOCKUP_SECS);
+  });
+
+  after(async () => {
+    await setLockup(0);
+  });
+
+  it("Rejects settlements drawing on freshly funded amounts", async () => {
+    await program.methods
+      .fundEscrow(new anchor.BN(20_000000))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+    // Matured balance still settles
+    await settle(10_000000, 1);
+
+    try {
+      await settle(1_000000, 2);
+      assert.fail("Should have failed with FundsStillLocked");
+    } catch (err) {
+      assert.include(err.toString(), "FundsStillLocked");
+    }
+  });
+
+  it("Allows the deposit once the lockup has elapsed", async () => {
+    await new Promise((resolve) =>
+      setTimeout(resolve, (LOCKUP_SECS + 2) * 1000)
+    );
+    await settle(20_000000, 3);
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 0);
+  });
+
+  it("Rejects a lockup above the maximum", async () => {
+    try {
+      await setLockup(30 * 86_400);
+      assert.fail("Should have failed with InvalidConfig");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConfig");
+    }
+  });
+});
@@ -0,0 +1,99 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("registry growth", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const BASE_HISTORY = 32;
+  const MAX_GROWTH_STEPS = 3;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const settle = (nonce: number) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(100000), new anchor.BN(nonce), `grow-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const grow = () =>
+    program.methods
+      .growRegistry()
+      .accountsPartial({ nonceRegistry: fixture.nonceRegistry, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const history = async () =>
+    (await program.account.nonceRegistry.fetch(fixture.nonceRegistry)).bundleHistory;
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    for (let nonce = 1; nonce <= BASE_HISTORY + 1; nonce++) {
+      await settle(nonce);
+    }
+  });
+
+  it("Keeps only the newest records while the registry is full", async () => {
+    const records = await history();
+    assert.equal(records.length, BASE_HISTORY);
+    assert.equal(records[0].nonce.toNumber(), 2);
+  });
+
+  it("Grows the account without disturbing its entries", async () => {
+    const before = await history();
+    const sizeBefore = (await provider.connection.getAccountInfo(fixture.nonceRegistry)).data.length;
+
+    await grow();
+
+    const sizeAfter = (await provider.connection.getAccountInfo(fixture.nonceRegistry)).data.length;
+    assert.isAbove(sizeAfter, sizeBefore);
+    assert.deepEqual(await history(), before);
+  });
+
+  it("Uses the added capacity for new settlements", async () => {
+    const next = BASE_HISTORY + 2;
+    for (let nonce = next; nonce < next + 5; nonce++) {
+      await settle(nonce);
+    }
+    const records = await history();
+    assert.equal(records.length, BASE_HISTORY + 5);
+    assert.equal(records[0].nonce.toNumber(), 2);
+    assert.equal(records[records.length - 1].nonce.toNumber(), next + 4);
+  });
+
+  it("Only lets the owner grow the registry", async () => {
+    const stranger = await createEscrowFixture(provider, program, 1_000000);
+    await expectError(
+      program.methods
+        .growRegistry()
+        .accountsPartial({ nonceRegistry: fixture.nonceRegistry, owner: stranger.owner.publicKey })
+        .signers([stranger.owner])
+        .rpc(),
+      "ConstraintSeeds"
+    );
+  });
+
+  it("Stops at the maximum number of growth steps", async () => {
+    for (let step = 1; step < MAX_GROWTH_STEPS; step++) {
+      await grow();
+    }
+    await expectError(grow(), "RegistryAtMaxCapacity");
+  });
+});
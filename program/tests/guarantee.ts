@@ -0,0 +1,167 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  findEscrowPDA,
+  settleAccounts,
+} from "./fixtures";
+
+describe("guarantor escrows", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const guarantor = Keypair.generate();
+  let fixture: EscrowFixture;
+  let guarantorEscrow: PublicKey;
+  let guarantorVault: PublicKey;
+  let guarantee: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const settle = (amount: number, nonce: number, withGuarantee = true) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `guaranteed-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        ...(withGuarantee && {
+          guarantee,
+          guarantorEscrow,
+          guarantorTokenAccount: guarantorVault,
+        }),
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+    await airdrop(provider, guarantor.publicKey);
+    guarantorEscrow = findEscrowPDA(program, guarantor.publicKey);
+    guarantorVault = await createAccount(
+      provider.connection,
+      guarantor,
+      fixture.mint,
+      guarantorEscrow,
+      Keypair.generate()
+    );
+    const guarantorTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        guarantor,
+        fixture.mint,
+        guarantor.publicKey
+      )
+    ).address;
+    await mintTo(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      guarantorTokenAccount,
+      fixture.owner,
+      50_000000
+    );
+    await program.methods
+      .initializeEscrow(new anchor.BN(50_000000))
+      .accounts({
+        escrowAccount: guarantorEscrow,
+        owner: guarantor.publicKey,
+        rentPayer: guarantor.publicKey,
+        ownerTokenAccount: guarantorTokenAccount,
+        escrowTokenAccount: guarantorVault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guarantor])
+      .rpc();
+
+    [guarantee] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("guarantee"),
+        guarantor.publicKey.toBuffer(),
+        fixture.owner.publicKey.toBuffer(),
+      ],
+      program.programId
+    );
+    await program.methods
+      .grantGuarantee(new anchor.BN(15_000000))
+      .accountsPartial({
+        guarantee,
+        guarantorEscrow,
+        guarantor: guarantor.publicKey,
+        payer: fixture.owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guarantor])
+      .rpc();
+  });
+
+  it("Rejects a shortfall when no guarantee is supplied", async () => {
+    try {
+      await settle(12_000000, 1, false);
+      assert.fail("Should have failed with InsufficientFunds");
+    } catch (err) {
+      assert.include(err.toString(), "InsufficientFunds");
+    }
+  });
+
+  it("Draws the shortfall from the guarantor's escrow", async () => {
+    const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+    await settle(12_000000, 1);
+
+    assert.equal(
+      (await balanceOf(fixture.merchantTokenAccount)) - merchantBefore,
+      12_000000
+    );
+    const payer = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    const backer = await program.account.offlineEscrowAccount.fetch(
+      guarantorEscrow
+    );
+    assert.equal(payer.escrowBalance.toNumber(), 0);
+    assert.equal(backer.escrowBalance.toNumber(), 48_000000);
+    assert.equal(await balanceOf(guarantorVault), 48_000000);
+    assert.equal(
+      (await program.account.guarantee.fetch(guarantee)).drawn.toNumber(),
+      2_000000
+    );
+  });
+
+  it("Stops at the guarantor's maximum exposure", async () => {
+    try {
+      await settle(14_000000, 2);
+      assert.fail("Should have failed with GuaranteeExposureExceeded");
+    } catch (err) {
+      assert.include(err.toString(), "GuaranteeExposureExceeded");
+    }
+    await settle(13_000000, 2);
+  });
+
+  it("Revoking closes the guarantee", async () => {
+    await program.methods
+      .revokeGuarantee()
+      .accountsPartial({ guarantee, guarantor: guarantor.publicKey })
+      .signers([guarantor])
+      .rpc();
+    assert.isNull(await provider.connection.getAccountInfo(guarantee));
+  });
+});
@@ -0,0 +1,205 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  findEscrowPDA,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("identity-linked reputation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // The provider wallet doubles as the KYC service here
+  const authority = provider.wallet.publicKey;
+  const identity = Keypair.generate().publicKey;
+  const reporter = Keypair.generate();
+  let config: PublicKey;
+  let identityReputation: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const updateConfig = (update: object) =>
+    program.methods
+      .updateConfig(update as any)
+      .accountsPartial({ config, admin: authority })
+      .rpc();
+
+  // A fresh user opening an escrow, optionally under `identityReputation`
+  const openEscrow = async (withIdentity: boolean) => {
+    const owner = Keypair.generate();
+    await airdrop(provider, owner.publicKey);
+    const mint = await createMint(provider.connection, owner, owner.publicKey, null, 6);
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, owner, mint, owner.publicKey)
+    ).address;
+    await mintTo(provider.connection, owner, mint, ownerTokenAccount, owner, 10_000000);
+    const escrowPDA = findEscrowPDA(program, owner.publicKey);
+    const escrowTokenAccount = await createAccount(
+      provider.connection,
+      owner,
+      mint,
+      escrowPDA,
+      Keypair.generate()
+    );
+
+    return program.methods
+      .initializeEscrow(new anchor.BN(1_000000))
+      .accountsPartial({
+        escrowAccount: escrowPDA,
+        owner: owner.publicKey,
+        rentPayer: owner.publicKey,
+        ownerTokenAccount,
+        escrowTokenAccount,
+        identityReputation: withIdentity ? identityReputation : null,
+        identityAuthority: withIdentity ? authority : null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+  };
+
+  const checkIdentity = () =>
+    program.methods.checkIdentity().accountsPartial({ identityReputation, config }).rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    await airdrop(provider, reporter.publicKey);
+    await updateConfig({ identityAuthority: authority, minIdentityReputation: 0 });
+    identityReputation = PublicKey.findProgramAddressSync(
+      [Buffer.from("identity"), identity.toBuffer()],
+      program.programId
+    )[0];
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  after(async () => {
+    await updateConfig({ identityAuthority: PublicKey.default, requireIdentity: false });
+  });
+
+  it("Registers an identity and links an existing escrow to it", async () => {
+    await program.methods
+      .registerIdentity(identity)
+      .accountsPartial({
+        identityReputation,
+        identityAuthority: authority,
+        config,
+        systemProgram: SystemProgram.programId,
+      })
+      .rpc();
+    await program.methods
+      .linkIdentity()
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        identityReputation,
+        identityAuthority: authority,
+        config,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.ok(escrow.identity.equals(identity));
+    const record = await program.account.identityReputation.fetch(identityReputation);
+    assert.equal(record.linkedEscrows, 1);
+    await checkIdentity();
+  });
+
+  it("Refuses a new escrow for an identity whose escrow committed fraud", async () => {
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(1), "identity-1", {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, 7),
+      fixture.merchant.publicKey,
+      1_000000,
+      1
+    );
+    const report = (withIdentity: boolean) =>
+      program.methods
+        .reportFraudulentBundle(
+          "identity-1",
+          conflictingHash,
+          { duplicateBundle: {} },
+          evidence as any
+        )
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          payer: fixture.owner.publicKey,
+          reporter: reporter.publicKey,
+          identityReputation: withIdentity ? identityReputation : null,
+        })
+        .signers([reporter])
+        .rpc();
+    // A linked escrow's fraud can't be reported around its identity
+    await expectError(report(false), "IdentityRequired");
+    await report(true);
+
+    const record = await program.account.identityReputation.fetch(identityReputation);
+    assert.equal(record.fraudCount, 1);
+    assert.isBelow(record.reputationScore, 0);
+
+    await expectError(checkIdentity(), "IdentityReputationTooLow");
+    await expectError(openEscrow(true), "IdentityReputationTooLow");
+  });
+
+  it("Refuses banned identities even above the minimum", async () => {
+    await updateConfig({ minIdentityReputation: -10_000 });
+    await checkIdentity();
+
+    await program.methods
+      .setIdentityBan(true)
+      .accountsPartial({ identityReputation, identityAuthority: authority, config })
+      .rpc();
+    await expectError(openEscrow(true), "IdentityBanned");
+
+    await program.methods
+      .setIdentityBan(false)
+      .accountsPartial({ identityReputation, identityAuthority: authority, config })
+      .rpc();
+    await openEscrow(true);
+    const record = await program.account.identityReputation.fetch(identityReputation);
+    assert.equal(record.linkedEscrows, 2);
+    await updateConfig({ minIdentityReputation: 0 });
+  });
+
+  it("Closes the unlinked path while identities are required", async () => {
+    await updateConfig({ requireIdentity: true });
+    await expectError(openEscrow(false), "IdentityRequired");
+    await updateConfig({ requireIdentity: false });
+    await openEscrow(false);
+  });
+});
@@ -0,0 +1,152 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  ensureConfig,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+  settleAccounts,
+} from "./fixtures";
+
+describe("initialize and fund", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // The fixture's accounts, before any of them exist on the program side
+  const newUser = async (): Promise<EscrowFixture> => {
+    const owner = Keypair.generate();
+    const merchant = Keypair.generate();
+    await airdrop(provider, owner.publicKey);
+    const mint = await createMint(provider.connection, owner, owner.publicKey, null, 6);
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, owner, mint, owner.publicKey)
+    ).address;
+    const merchantTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, owner, mint, merchant.publicKey)
+    ).address;
+    const escrowPDA = findEscrowPDA(program, owner.publicKey);
+    const escrowTokenAccount = await createAccount(
+      provider.connection,
+      owner,
+      mint,
+      escrowPDA,
+      Keypair.generate()
+    );
+    await mintTo(provider.connection, owner, mint, ownerTokenAccount, owner, 100_000000);
+    return {
+      owner,
+      merchant,
+      mint,
+      ownerTokenAccount,
+      merchantTokenAccount,
+      escrowPDA,
+      escrowTokenAccount,
+      nonceRegistry: findNonceRegistryPDA(program, owner.publicKey),
+    };
+  };
+
+  const initializeAndFund = (user: EscrowFixture, amount: number) =>
+    program.methods
+      .initializeAndFund(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: user.escrowPDA,
+        nonceRegistry: user.nonceRegistry,
+        owner: user.owner.publicKey,
+        ownerTokenAccount: user.ownerTokenAccount,
+        escrowTokenAccount: user.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user.owner])
+      .rpc();
+
+  let user: EscrowFixture;
+
+  before(async () => {
+    await ensureConfig(provider, program);
+    user = await newUser();
+  });
+
+  it("Creates and funds the escrow and registry in one instruction", async () => {
+    await initializeAndFund(user, 10_000000);
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(user.escrowPDA);
+    assert.ok(escrow.owner.equals(user.owner.publicKey));
+    assert.ok(escrow.escrowTokenAccount.equals(user.escrowTokenAccount));
+    assert.equal(escrow.escrowBalance.toNumber(), 10_000000);
+    assert.equal(escrow.reputationScore, 100);
+    const registry = await program.account.nonceRegistry.fetch(user.nonceRegistry);
+    assert.ok(registry.owner.equals(user.owner.publicKey));
+  });
+
+  it("Tops up on re-invocation without resetting any field", async () => {
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(1), "init-fund-1", {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(user))
+      .signers([user.owner])
+      .rpc();
+    const before = await program.account.offlineEscrowAccount.fetch(user.escrowPDA);
+
+    await initializeAndFund(user, 5_000000);
+
+    const after = await program.account.offlineEscrowAccount.fetch(user.escrowPDA);
+    assert.equal(after.escrowBalance.toNumber(), before.escrowBalance.toNumber() + 5_000000);
+    assert.equal(after.lastNonce.toNumber(), 1);
+    assert.equal(after.settlementCount.toNumber(), 1);
+    assert.equal(after.totalSpent.toNumber(), 1_000000);
+    assert.equal(after.createdAt.toNumber(), before.createdAt.toNumber());
+    const registry = await program.account.nonceRegistry.fetch(user.nonceRegistry);
+    assert.equal(registry.lastNonce.toNumber(), 1);
+    assert.equal(registry.bundleHistory.length, 1);
+  });
+
+  it("Reuses a nonce registry created earlier", async () => {
+    const other = await newUser();
+    await program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: other.owner.publicKey,
+        rentPayer: other.owner.publicKey,
+        nonceRegistry: other.nonceRegistry,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([other.owner])
+      .rpc();
+
+    await initializeAndFund(other, 2_000000);
+    const escrow = await program.account.offlineEscrowAccount.fetch(other.escrowPDA);
+    assert.equal(escrow.escrowBalance.toNumber(), 2_000000);
+  });
+
+  it("Refuses to top up from a vault the escrow doesn't use", async () => {
+    const strayVault = await createAccount(
+      provider.connection,
+      user.owner,
+      user.mint,
+      user.escrowPDA,
+      Keypair.generate()
+    );
+    try {
+      await initializeAndFund({ ...user, escrowTokenAccount: strayVault }, 1_000000);
+      assert.fail("Should have failed with InvalidEscrowTokenAccount");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidEscrowTokenAccount");
+    }
+  });
+});
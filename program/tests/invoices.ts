@@ -0,0 +1,288 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("invoice amount modes", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let nextNonce = 1;
+  let nextInvoiceId = 1;
+
+  const invoicePDA = (invoiceId: number[]) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("invoice"),
+        fixture.merchant.publicKey.toBuffer(),
+        Buffer.from(invoiceId),
+      ],
+      program.programId
+    )[0];
+
+  const createInvoice = async (
+    mode: object,
+    amount: number,
+    ttlSecs = 3600
+  ) => {
+    const invoiceId = Array(16).fill(0);
+    invoiceId[0] = nextInvoiceId++;
+    const invoice = invoicePDA(invoiceId);
+    const expiresAt = Math.floor(Date.now() / 1000) + ttlSecs;
+    await program.methods
+      .createInvoice(
+        invoiceId,
+        mode as any,
+        new anchor.BN(amount),
+        new anchor.BN(expiresAt)
+      )
+      .accountsPartial({
+        invoice,
+        merchant: fixture.merchant.publicKey,
+        rentPayer: fixture.merchant.publicKey,
+      })
+      .signers([fixture.merchant])
+      .rpc();
+    return invoice;
+  };
+
+  const pay = (invoice: PublicKey, amount: number) => {
+    const nonce = nextNonce++;
+    return program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `invoice-payment-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({ ...settleAccounts(fixture), invoice })
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  const closeInvoice = (invoice: PublicKey) =>
+    program.methods
+      .closeInvoice()
+      .accountsPartial({
+        invoice,
+        merchant: fixture.merchant.publicKey,
+        rentPayer: fixture.merchant.publicKey,
+      })
+      .signers([fixture.merchant])
+      .rpc();
+
+  const expectError = async (promise: Promise<unknown>, code: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${code}`);
+    } catch (err) {
+      assert.include(err.toString(), code);
+    }
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 200_000000);
+    await airdrop(provider, fixture.merchant.publicKey);
+  });
+
+  describe("exact", () => {
+    it("Rejects one unit under and over the amount", async () => {
+      const invoice = await createInvoice({ exact: {} }, 10_000000);
+      await expectError(pay(invoice, 9_999999), "InvoiceUnderpaid");
+      await expectError(pay(invoice, 10_000001), "InvoiceOverpaid");
+    });
+
+    it("Accepts the exact amount once", async () => {
+      const invoice = await createInvoice({ exact: {} }, 10_000000);
+      await pay(invoice, 10_000000);
+
+      const account = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(account.status, { paid: {} });
+      assert.equal(account.amountPaid.toNumber(), 10_000000);
+
+      await expectError(pay(invoice, 10_000000), "InvoiceAlreadyPaid");
+    });
+  });
+
+  describe("up-to", () => {
+    it("Accepts partial payments until the cap is exhausted", async () => {
+      const invoice = await createInvoice({ upTo: {} }, 10_000000);
+
+      await pay(invoice, 4_000000);
+      let account = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(account.status, { partiallyPaid: {} });
+
+      // One unit over the remaining cap
+      await expectError(pay(invoice, 6_000001), "InvoiceOverpaid");
+
+      await pay(invoice, 6_000000);
+      account = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(account.status, { paid: {} });
+      assert.equal(account.amountPaid.toNumber(), 10_000000);
+      assert.equal(account.paymentCount, 2);
+
+      await expectError(pay(invoice, 1), "InvoiceAlreadyPaid");
+    });
+  });
+
+  describe("open", () => {
+    it("Accepts any amount", async () => {
+      const invoice = await createInvoice({ open: {} }, 0);
+      await pay(invoice, 1);
+
+      const account = await program.account.invoice.fetch(invoice);
+      assert.deepEqual(account.status, { paid: {} });
+      assert.equal(account.amountPaid.toNumber(), 1);
+    });
+  });
+
+  it("Rejects an invoice issued by another merchant", async () => {
+    const other = await createEscrowFixture(provider, program, 1_000000);
+    await airdrop(provider, other.merchant.publicKey);
+    const invoiceId = Array(16).fill(7);
+    const [invoice] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("invoice"),
+        other.merchant.publicKey.toBuffer(),
+        Buffer.from(invoiceId),
+      ],
+      program.programId
+    );
+    await program.methods
+      .createInvoice(
+        invoiceId,
+        { open: {} } as any,
+        new anchor.BN(0),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 3600)
+      )
+      .accountsPartial({ invoice, merchant: other.merchant.publicKey })
+      .signers([other.merchant])
+      .rpc();
+
+    try {
+      await pay(invoice, 1_000000);
+      assert.fail("Should have rejected a foreign invoice");
+    } catch (err) {
+      assert.match(err.toString(), /InvoiceMerchantMismatch|ConstraintSeeds/);
+    }
+  });
+
+  describe("expiry and cleanup", () => {
+    it("Lets the merchant close a paid invoice immediately", async () => {
+      const invoice = await createInvoice({ exact: {} }, 1_000000);
+      await pay(invoice, 1_000000);
+
+      await program.methods
+        .closeInvoice()
+        .accountsPartial({
+          invoice,
+          merchant: fixture.merchant.publicKey,
+          rentPayer: fixture.merchant.publicKey,
+        })
+        .signers([fixture.merchant])
+        .rpc();
+      assert.isNull(await provider.connection.getAccountInfo(invoice));
+    });
+
+    it("Refuses to close an unpaid, unexpired invoice", async () => {
+      const invoice = await createInvoice({ exact: {} }, 1_000000);
+      await expectError(
+        program.methods
+          .closeInvoice()
+          .accountsPartial({
+            invoice,
+            merchant: fixture.merchant.publicKey,
+            rentPayer: fixture.merchant.publicKey,
+          })
+          .signers([fixture.merchant])
+          .rpc(),
+        "InvoiceStillOpen"
+      );
+    });
+
+    it("Rejects settlement after expiry and lets a keeper close it", async () => {
+      const invoice = await createInvoice({ open: {} }, 0, 2);
+      await new Promise((resolve) => setTimeout(resolve, 4000));
+
+      await expectError(pay(invoice, 1_000000), "InvoiceExpired");
+
+      const merchantBefore = await provider.connection.getBalance(
+        fixture.merchant.publicKey
+      );
+      await program.methods
+        .closeExpiredInvoice()
+        .accountsPartial({
+          invoice,
+          merchant: fixture.merchant.publicKey,
+          rentPayer: fixture.merchant.publicKey,
+          keeper: provider.wallet.publicKey,
+        })
+        .rpc();
+
+      assert.isNull(await provider.connection.getAccountInfo(invoice));
+      const merchantAfter = await provider.connection.getBalance(
+        fixture.merchant.publicKey
+      );
+      assert.isAbove(merchantAfter, merchantBefore);
+    });
+
+    it("Holds a disputed invoice open for the fraud withdrawal delay", async () => {
+      const config = await ensureConfig(provider, program);
+      const setDelay = (seconds: number) =>
+        program.methods
+          .updateConfig({ fraudWithdrawalDelay: new anchor.BN(seconds) })
+          .accountsPartial({ config, admin: provider.wallet.publicKey })
+          .rpc();
+      const reporter = Keypair.generate();
+      await airdrop(provider, reporter.publicKey);
+      await setDelay(3);
+
+      const invoice = await createInvoice({ exact: {} }, 1_000000);
+      const nonce = nextNonce;
+      await pay(invoice, 1_000000);
+      const { conflictingHash, evidence } = await signConflictingBundle(
+        fixture.owner,
+        Buffer.alloc(32, 5),
+        fixture.merchant.publicKey,
+        1_000000,
+        nonce
+      );
+      await program.methods
+        .reportFraudulentBundle(
+          `invoice-payment-${nonce}`,
+          conflictingHash,
+          { duplicateBundle: {} },
+          evidence as any
+        )
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          payer: fixture.owner.publicKey,
+          reporter: reporter.publicKey,
+          invoice,
+        })
+        .signers([reporter])
+        .rpc();
+
+      try {
+        await expectError(closeInvoice(invoice), "InvoiceDisputed");
+        await new Promise((resolve) => setTimeout(resolve, 4000));
+        await closeInvoice(invoice);
+        assert.isNull(await provider.connection.getAccountInfo(invoice));
+      } finally {
+        await setDelay(0);
+      }
+    });
+  });
+});
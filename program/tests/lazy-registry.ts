@@ -0,0 +1,138 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+  settleAccounts,
+} from "./fixtures";
+
+describe("lazy nonce registry creation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const settle = (user: EscrowFixture, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `lazy-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({
+        ...settleAccounts(user),
+        rentPayer: provider.wallet.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([user.owner])
+      .rpc({ commitment: "confirmed" });
+
+  // A funded escrow whose owner never ran initialize_nonce_registry
+  before(async () => {
+    await ensureConfig(provider, program);
+    const owner = Keypair.generate();
+    const merchant = Keypair.generate();
+    await airdrop(provider, owner.publicKey);
+    const mint = await createMint(provider.connection, owner, owner.publicKey, null, 6);
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, owner, mint, owner.publicKey)
+    ).address;
+    const merchantTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(provider.connection, owner, mint, merchant.publicKey)
+    ).address;
+    const escrowPDA = findEscrowPDA(program, owner.publicKey);
+    const escrowTokenAccount = await createAccount(
+      provider.connection,
+      owner,
+      mint,
+      escrowPDA,
+      Keypair.generate()
+    );
+    await mintTo(provider.connection, owner, mint, ownerTokenAccount, owner, 10_000000);
+    await program.methods
+      .initializeEscrow(new anchor.BN(10_000000))
+      .accountsPartial({
+        escrowAccount: escrowPDA,
+        owner: owner.publicKey,
+        rentPayer: owner.publicKey,
+        ownerTokenAccount,
+        escrowTokenAccount,
+        referrer: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    fixture = {
+      owner,
+      merchant,
+      mint,
+      ownerTokenAccount,
+      merchantTokenAccount,
+      escrowPDA,
+      escrowTokenAccount,
+      nonceRegistry: findNonceRegistryPDA(program, owner.publicKey),
+    };
+  });
+
+  it("Creates the payer's registry on their first settlement", async () => {
+    assert.isNull(await provider.connection.getAccountInfo(fixture.nonceRegistry));
+
+    const signature = await settle(fixture, 1);
+
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    assert.ok(registry.owner.equals(fixture.owner.publicKey));
+    assert.equal(registry.lastNonce.toNumber(), 1);
+    assert.equal(registry.bundleHistory.length, 1);
+    assert.ok(registry.rentPayer.equals(provider.wallet.publicKey));
+    const bootstrapped = (await eventsOf(signature)).find(
+      (event) => event.name === "nonceRegistryBootstrapped"
+    );
+    assert.ok(bootstrapped.data.rentPayer.equals(provider.wallet.publicKey));
+  });
+
+  it("Reuses the registry afterwards without resetting it", async () => {
+    const signature = await settle(fixture, 2);
+
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    assert.equal(registry.lastNonce.toNumber(), 2);
+    assert.equal(registry.bundleHistory.length, 2);
+    const events = await eventsOf(signature);
+    assert.isUndefined(events.find((event) => event.name === "nonceRegistryBootstrapped"));
+  });
+
+  it("Refuses another owner's registry", async () => {
+    const other = await createEscrowFixture(provider, program, 5_000000);
+    try {
+      await settle({ ...fixture, nonceRegistry: other.nonceRegistry }, 3);
+      assert.fail("Should have failed with ConstraintSeeds");
+    } catch (err) {
+      assert.include(err.toString(), "ConstraintSeeds");
+    }
+  });
+});
@@ -0,0 +1,105 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { keccak_256 } from "@noble/hashes/sha3";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("pending liabilities", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const bundleHash = (bundleId: string) =>
+    Array.from(keccak_256(Buffer.from(bundleId)));
+
+  const register = (bundleId: string, amount: number) =>
+    program.methods
+      .registerLiability(
+        bundleHash(bundleId),
+        fixture.merchant.publicKey,
+        new anchor.BN(amount)
+      )
+      .accountsPartial({
+        nonceRegistry: fixture.nonceRegistry,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const withdraw = (amount: number) =>
+    program.methods
+      .withdrawEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const liabilities = async () =>
+    (await program.account.nonceRegistry.fetch(fixture.nonceRegistry))
+      .pendingLiabilities;
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+  });
+
+  it("Blocks withdrawals below the registered total", async () => {
+    await register("market-1", 30_000000);
+    const [entry] = await liabilities();
+    assert.deepEqual(entry.bundleHash, bundleHash("market-1"));
+    assert.ok(entry.merchant.equals(fixture.merchant.publicKey));
+
+    try {
+      await withdraw(20_000001);
+      assert.fail("Should have failed with OutstandingLiabilities");
+    } catch (err) {
+      assert.include(err.toString(), "OutstandingLiabilities");
+    }
+    await withdraw(20_000000);
+  });
+
+  it("Clears the entry when its bundle settles", async () => {
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(30_000000),
+        new anchor.BN(1),
+        "market-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+    assert.lengthOf(await liabilities(), 0);
+  });
+
+  it("Rejects registering the same bundle twice", async () => {
+    await register("market-2", 1);
+    try {
+      await register("market-2", 1);
+      assert.fail("Should have failed with DuplicateBundle");
+    } catch (err) {
+      assert.include(err.toString(), "DuplicateBundle");
+    }
+  });
+
+  it("Rejects new entries once the list is full of live liabilities", async () => {
+    for (let i = 3; i <= 9; i++) {
+      await register(`market-${i}`, 1);
+    }
+    try {
+      await register("market-10", 1);
+      assert.fail("Should have failed with PendingLiabilityLimitReached");
+    } catch (err) {
+      assert.include(err.toString(), "PendingLiabilityLimitReached");
+    }
+  });
+});
@@ -0,0 +1,77 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("per-merchant settlement cap", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const setLimit = (limit: number) =>
+    program.methods
+      .setMerchantLimit(fixture.merchant.publicKey, new anchor.BN(limit))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `merchant-limit-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program);
+  });
+
+  it("Settles up to the merchant cap and rejects beyond it", async () => {
+    await setLimit(15_000000);
+    await settle(10_000000, 1);
+
+    try {
+      await settle(5_000001, 2);
+      assert.fail("Should have failed with MerchantLimitExceeded");
+    } catch (err) {
+      assert.include(err.toString(), "MerchantLimitExceeded");
+    }
+
+    await settle(5_000000, 3);
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    const entry = escrow.merchantLimits.find((e) =>
+      e.merchant.equals(fixture.merchant.publicKey)
+    );
+    assert.equal(entry.settled.toNumber(), 15_000000);
+  });
+
+  it("Raising the cap keeps the amount already settled", async () => {
+    await setLimit(20_000000);
+    try {
+      await settle(5_000001, 4);
+      assert.fail("Should have failed with MerchantLimitExceeded");
+    } catch (err) {
+      assert.include(err.toString(), "MerchantLimitExceeded");
+    }
+    await settle(5_000000, 5);
+  });
+
+  it("Removing the cap lifts the restriction", async () => {
+    await setLimit(0);
+    await settle(10_000000, 6);
+  });
+});
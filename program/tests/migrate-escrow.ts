@@ -0,0 +1,55 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, LAMPORTS_PER_SOL, PublicKey, SystemProgram } from "@solana/web3.js";
+import { assert } from "chai";
+import { airdrop } from "./fixtures";
+
+describe("escrow migration", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const owner = Keypair.generate();
+  let escrowPDA: PublicKey;
+
+  before(async () => {
+    await airdrop(provider, owner.publicKey);
+    escrowPDA = PublicKey.findProgramAddressSync(
+      [Buffer.from("escrow"), owner.publicKey.toBuffer()],
+      program.programId
+    )[0];
+    // Something other than an escrow now lives at the escrow address
+    await provider.sendAndConfirm(
+      new anchor.web3.Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: provider.wallet.publicKey,
+          toPubkey: escrowPDA,
+          lamports: LAMPORTS_PER_SOL / 100,
+        })
+      )
+    );
+  });
+
+  it("Refuses an account that isn't an escrow before touching it", async () => {
+    try {
+      await program.methods
+        .migrateEscrow()
+        .accountsPartial({
+          escrowAccount: escrowPDA,
+          owner: owner.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+      assert.fail("Should have failed with AccountDiscriminatorMismatch");
+    } catch (err) {
+      assert.include(err.toString(), "AccountDiscriminatorMismatch");
+    }
+
+    const info = await provider.connection.getAccountInfo(escrowPDA);
+    assert.equal(info.data.length, 0);
+    assert.ok(info.owner.equals(SystemProgram.programId));
+  });
+});
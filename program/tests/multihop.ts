@@ -0,0 +1,164 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("multi-hop settlement", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 10_000000;
+  const HOP_FEE = 500000;
+  const runner = Keypair.generate();
+  let fixture: EscrowFixture;
+  let runnerRegistry: PublicKey;
+  let runnerTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  // A -> runner for amount + hop fee, runner -> merchant for amount
+  const route = async (
+    firstId: string,
+    secondId: string,
+    payerNonce: number,
+    runnerNonce: number,
+    secondAmount = AMOUNT
+  ) => ({
+    first: {
+      bundleId: firstId,
+      nonce: new anchor.BN(payerNonce),
+      evidence: {
+        payerProof: await createAttestationProof(
+          AttestationRole.Payer,
+          firstId,
+          fixture.owner.publicKey,
+          runner.publicKey,
+          AMOUNT + HOP_FEE,
+          payerNonce
+        ),
+        merchantProof: null,
+      },
+    },
+    second: {
+      bundleId: secondId,
+      nonce: new anchor.BN(runnerNonce),
+      evidence: {
+        payerProof: await createAttestationProof(
+          AttestationRole.Payer,
+          secondId,
+          runner.publicKey,
+          fixture.merchant.publicKey,
+          secondAmount,
+          runnerNonce
+        ),
+        merchantProof: null,
+      },
+    },
+  });
+
+  const settle = ({ first, second }: Awaited<ReturnType<typeof route>>) =>
+    program.methods
+      .settleMultihop(new anchor.BN(AMOUNT), new anchor.BN(HOP_FEE), first, second)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        nonceRegistry: fixture.nonceRegistry,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        runner: runner.publicKey,
+        runnerNonceRegistry: runnerRegistry,
+        runnerTokenAccount,
+        merchant: fixture.merchant.publicKey,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        treasuryTokenAccount: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner, runner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    await airdrop(provider, runner.publicKey);
+    runnerRegistry = findNonceRegistryPDA(program, runner.publicKey);
+    await program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: runner.publicKey,
+        rentPayer: runner.publicKey,
+        nonceRegistry: runnerRegistry,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([runner])
+      .rpc();
+    runnerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        runner,
+        fixture.mint,
+        runner.publicKey
+      )
+    ).address;
+  });
+
+  it("Pays the merchant and the runner's hop fee from the payer's escrow", async () => {
+    await settle(await route("hop-a-1", "hop-b-1", 1, 1));
+
+    assert.equal(await balanceOf(fixture.merchantTokenAccount), AMOUNT);
+    assert.equal(await balanceOf(runnerTokenAccount), HOP_FEE);
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 50_000000 - AMOUNT - HOP_FEE);
+
+    const payerRegistry = await program.account.nonceRegistry.fetch(
+      fixture.nonceRegistry
+    );
+    const runnerState = await program.account.nonceRegistry.fetch(
+      runnerRegistry
+    );
+    assert.equal(payerRegistry.lastNonce.toNumber(), 1);
+    assert.equal(runnerState.lastNonce.toNumber(), 1);
+    assert.lengthOf(runnerState.recentBundleHashes, 1);
+    assert.lengthOf(runnerState.bundleHistory, 0);
+  });
+
+  it("Rejects replaying either leg", async () => {
+    try {
+      await settle(await route("hop-a-2", "hop-b-1", 2, 2));
+      assert.fail("Should have failed with DuplicateBundle");
+    } catch (err) {
+      assert.include(err.toString(), "DuplicateBundle");
+    }
+  });
+
+  it("Settles neither leg when the chain doesn't link", async () => {
+    const before = await balanceOf(fixture.merchantTokenAccount);
+    try {
+      // The runner's bundle pays a different amount than the route claims
+      await settle(await route("hop-a-3", "hop-b-3", 3, 3, AMOUNT - 1));
+      assert.fail("Should have failed with InvalidAttestation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidAttestation");
+    }
+    assert.equal(await balanceOf(fixture.merchantTokenAccount), before);
+    const payerRegistry = await program.account.nonceRegistry.fetch(
+      fixture.nonceRegistry
+    );
+    assert.equal(payerRegistry.lastNonce.toNumber(), 1);
+  });
+});
@@ -0,0 +1,154 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("netted settlement", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const partyB = Keypair.generate();
+  let a: EscrowFixture;
+  let escrowB: PublicKey;
+  let registryB: PublicKey;
+  let vaultB: PublicKey;
+
+  const item = (amount: number, nonce: number, bundleId: string) => ({
+    amount: new anchor.BN(amount),
+    payerNonce: new anchor.BN(nonce),
+    bundleId,
+    evidence: { payerProof: null, merchantProof: null },
+  });
+
+  const settleNetted = (aToB: object[], bToA: object[]) =>
+    program.methods
+      .settleNetted(aToB as any, bToA as any)
+      .accountsPartial({
+        escrowA: a.escrowPDA,
+        partyA: a.owner.publicKey,
+        nonceRegistryA: a.nonceRegistry,
+        escrowTokenAccountA: a.escrowTokenAccount,
+        escrowB,
+        partyB: partyB.publicKey,
+        nonceRegistryB: registryB,
+        escrowTokenAccountB: vaultB,
+        treasuryTokenAccount: null,
+        verifierHeartbeat: null,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([a.owner, partyB])
+      .rpc();
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const escrow = (pda: PublicKey) => program.account.offlineEscrowAccount.fetch(pda);
+
+  before(async () => {
+    a = await createEscrowFixture(provider, program, 50_000000);
+
+    // Counterparty escrow in the same mint
+    await airdrop(provider, partyB.publicKey);
+    escrowB = findEscrowPDA(program, partyB.publicKey);
+    registryB = findNonceRegistryPDA(program, partyB.publicKey);
+    vaultB = await createAccount(
+      provider.connection,
+      partyB,
+      a.mint,
+      escrowB,
+      Keypair.generate()
+    );
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        partyB,
+        a.mint,
+        partyB.publicKey
+      )
+    ).address;
+    await mintTo(provider.connection, a.owner, a.mint, ownerTokenAccount, a.owner, 50_000000);
+    await program.methods
+      .initializeEscrow(new anchor.BN(50_000000))
+      .accounts({
+        escrowAccount: escrowB,
+        owner: partyB.publicKey,
+        rentPayer: partyB.publicKey,
+        ownerTokenAccount,
+        escrowTokenAccount: vaultB,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([partyB])
+      .rpc();
+    await program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: partyB.publicKey,
+        rentPayer: partyB.publicKey,
+        nonceRegistry: registryB,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([partyB])
+      .rpc();
+  });
+
+  it("Moves only the difference and books every bundle at face value", async () => {
+    await settleNetted(
+      [item(10_000000, 1, "net-a-1"), item(5_000000, 2, "net-a-2")],
+      [item(12_000000, 1, "net-b-1")]
+    );
+
+    // A owed 15, B owed 12: three leave A's vault
+    assert.equal(await balanceOf(a.escrowTokenAccount), 47_000000);
+    assert.equal(await balanceOf(vaultB), 53_000000);
+
+    const stateA = await escrow(a.escrowPDA);
+    const stateB = await escrow(escrowB);
+    assert.equal(stateA.escrowBalance.toNumber(), 47_000000);
+    assert.equal(stateB.escrowBalance.toNumber(), 53_000000);
+    assert.equal(stateA.totalSpent.toNumber(), 15_000000);
+    assert.equal(stateB.totalSpent.toNumber(), 12_000000);
+    assert.equal(stateA.lastNonce.toNumber(), 2);
+    assert.equal(stateB.lastNonce.toNumber(), 1);
+
+    const registryA = await program.account.nonceRegistry.fetch(a.nonceRegistry);
+    assert.equal(registryA.recentBundleHashes.length, 2);
+  });
+
+  it("Rejects the whole netting when one bundle is invalid", async () => {
+    try {
+      // B's nonce 1 was consumed above
+      await settleNetted([item(1_000000, 3, "net-a-3")], [item(1_000000, 1, "net-b-2")]);
+      assert.fail("Should have failed with InvalidNonce");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidNonce");
+    }
+    assert.equal((await escrow(a.escrowPDA)).lastNonce.toNumber(), 2);
+  });
+
+  it("Requires at least one bundle", async () => {
+    try {
+      await settleNetted([], []);
+      assert.fail("Should have failed with InvalidBatchSize");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidBatchSize");
+    }
+  });
+});
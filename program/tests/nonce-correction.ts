@@ -0,0 +1,160 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("nonce correction window", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const HOUR = 3_600;
+  const RUNAWAY_NONCE = 1_000_000_000;
+  let config: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setWindow = (seconds: number) =>
+    program.methods
+      .updateConfig({ nonceCorrectionWindow: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const correct = (fixture: EscrowFixture) =>
+    program.methods
+      .correctNonceState()
+      .accountsPartial({
+        nonceRegistry: fixture.nonceRegistry,
+        escrowAccount: fixture.escrowPDA,
+        config,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+
+  const settle = (fixture: EscrowFixture, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `correction-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const lastNonce = async (fixture: EscrowFixture) =>
+    (await program.account.nonceRegistry.fetch(fixture.nonceRegistry)).lastNonce.toNumber();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+  });
+
+  after(async () => {
+    await setWindow(0);
+  });
+
+  it("Bounds the configurable window", async () => {
+    await expectError(setWindow(8 * 24 * HOUR), "InvalidConfig");
+    await expectError(setWindow(-1), "InvalidConfig");
+  });
+
+  it("Refuses corrections while the window is disabled", async () => {
+    const fixture = await createEscrowFixture(provider, program, 10_000000);
+    await expectError(correct(fixture), "NonceCorrectionWindowClosed");
+  });
+
+  describe("within the window", () => {
+    let fixture: EscrowFixture;
+
+    before(async () => {
+      await setWindow(HOUR);
+      fixture = await createEscrowFixture(provider, program, 10_000000);
+
+      // A far-ahead nonce arrives through a merge with a test wallet's registry
+      const testWallet = await createEscrowFixture(provider, program, 10_000000);
+      await settle(testWallet, RUNAWAY_NONCE);
+      await program.methods
+        .mergeRegistries()
+        .accountsPartial({
+          sourceRegistry: testWallet.nonceRegistry,
+          destinationRegistry: fixture.nonceRegistry,
+          config,
+          sourceOwner: testWallet.owner.publicKey,
+          destinationOwner: fixture.owner.publicKey,
+          sourceRentPayer: testWallet.owner.publicKey,
+        })
+        .signers([testWallet.owner, fixture.owner])
+        .rpc();
+      await program.methods
+        .reserveNonce(new anchor.BN(RUNAWAY_NONCE + 5))
+        .accountsPartial({ nonceRegistry: fixture.nonceRegistry, owner: fixture.owner.publicKey })
+        .signers([fixture.owner])
+        .rpc();
+    });
+
+    it("Only lets the owner correct", async () => {
+      const stranger = await createEscrowFixture(provider, program, 1_000000);
+      await expectError(
+        program.methods
+          .correctNonceState()
+          .accountsPartial({
+            nonceRegistry: fixture.nonceRegistry,
+            escrowAccount: fixture.escrowPDA,
+            config,
+            owner: stranger.owner.publicKey,
+          })
+          .signers([stranger.owner])
+          .rpc(),
+        "ConstraintSeeds"
+      );
+    });
+
+    it("Resets the nonce mark and drops reservations", async () => {
+      assert.equal(await lastNonce(fixture), RUNAWAY_NONCE);
+      await expectError(settle(fixture, 1), "InvalidNonce");
+
+      const signature = await correct(fixture);
+      const corrected = (await eventsOf(signature)).find((event) => event.name === "nonceCorrected");
+      assert.equal(corrected.data.previousLastNonce.toNumber(), RUNAWAY_NONCE);
+      assert.deepEqual(
+        corrected.data.releasedNonces.map((nonce: anchor.BN) => nonce.toNumber()),
+        [RUNAWAY_NONCE + 5]
+      );
+
+      const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+      assert.equal(registry.lastNonce.toNumber(), 0);
+      assert.isEmpty(registry.pendingNonces);
+      await settle(fixture, 1);
+    });
+
+    it("Locks corrections once the escrow has settled", async () => {
+      await expectError(correct(fixture), "NonceCorrectionLocked");
+    });
+  });
+
+  it("Locks corrections once the window has passed", async () => {
+    await setWindow(1);
+    const fixture = await createEscrowFixture(provider, program, 10_000000);
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+    await expectError(correct(fixture), "NonceCorrectionWindowClosed");
+  });
+});
@@ -0,0 +1,90 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("order references", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 1_000000;
+  let fixture: EscrowFixture;
+
+  const encodeOrderRef = (text: string): number[] => {
+    const orderRef = Buffer.alloc(16);
+    Buffer.from(text, "utf8").copy(orderRef);
+    return Array.from(orderRef);
+  };
+
+  const settle = (
+    nonce: number,
+    bundleId: string,
+    payerProof: Awaited<ReturnType<typeof createAttestationProof>> | null,
+    orderRef: number[] | null
+  ) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(AMOUNT), new anchor.BN(nonce), bundleId, {
+        payerProof,
+        merchantProof: null,
+        orderRef,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const historyFor = async (nonce: number) =>
+    (
+      await program.account.nonceRegistry.fetch(fixture.nonceRegistry)
+    ).bundleHistory.find((record) => record.nonce.toNumber() === nonce);
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Stores the attested order reference on the bundle record", async () => {
+    const orderRef = encodeOrderRef("PO-2024-0042");
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      "order-1",
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      1,
+      undefined,
+      null,
+      Uint8Array.from(orderRef)
+    );
+    await settle(1, "order-1", payerProof, orderRef);
+
+    assert.deepEqual((await historyFor(1)).orderRef, orderRef);
+  });
+
+  it("Rejects an order reference the attestation did not commit to", async () => {
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      "order-2",
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      2,
+      undefined,
+      null,
+      Uint8Array.from(encodeOrderRef("PO-A"))
+    );
+    try {
+      await settle(2, "order-2", payerProof, encodeOrderRef("PO-B"));
+      assert.fail("Should have failed with InvalidAttestation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidAttestation");
+    }
+  });
+
+  it("Records a zeroed reference when none is supplied", async () => {
+    await settle(3, "order-3", null, null);
+    assert.deepEqual((await historyFor(3)).orderRef, Array(16).fill(0));
+  });
+});
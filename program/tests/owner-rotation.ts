@@ -0,0 +1,162 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { createAttestationProof, AttestationRole } from "./attestation-helper";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("owner key rotation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  const newOwner = Keypair.generate();
+  let newEscrowPDA: PublicKey;
+  let newNonceRegistry: PublicKey;
+  let newEscrowTokenAccount: PublicKey;
+  let tombstone: PublicKey;
+  let grownRegistryLen: number;
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    // A grown registry must move with its capacity
+    await program.methods
+      .growRegistry()
+      .accountsPartial({ nonceRegistry: fixture.nonceRegistry, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+    grownRegistryLen = (await provider.connection.getAccountInfo(fixture.nonceRegistry)).data
+      .length;
+    newEscrowPDA = findEscrowPDA(program, newOwner.publicKey);
+    newNonceRegistry = findNonceRegistryPDA(program, newOwner.publicKey);
+    newEscrowTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      newEscrowPDA,
+      Keypair.generate()
+    );
+    [tombstone] = PublicKey.findProgramAddressSync(
+      [Buffer.from("tombstone"), fixture.owner.publicKey.toBuffer()],
+      program.programId
+    );
+  });
+
+  it("Moves balance and history to the new key with both signatures", async () => {
+    await program.methods
+      .rotateOwnerKey()
+      .accountsPartial({
+        oldEscrow: fixture.escrowPDA,
+        oldNonceRegistry: fixture.nonceRegistry,
+        oldEscrowTokenAccount: fixture.escrowTokenAccount,
+        newEscrow: newEscrowPDA,
+        newNonceRegistry,
+        newEscrowTokenAccount,
+        ownerTombstone: tombstone,
+        oldOwner: fixture.owner.publicKey,
+        newOwner: newOwner.publicKey,
+        escrowRentPayer: fixture.owner.publicKey,
+        registryRentPayer: fixture.owner.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.owner, newOwner])
+      .rpc();
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      newEscrowPDA
+    );
+    assert.equal(escrow.owner.toString(), newOwner.publicKey.toString());
+    assert.equal(escrow.escrowBalance.toNumber(), 50_000000);
+
+    const vault = await getAccount(provider.connection, newEscrowTokenAccount);
+    assert.equal(vault.amount.toString(), "50000000");
+
+    const record = await program.account.ownerTombstone.fetch(tombstone);
+    assert.equal(record.newOwner.toString(), newOwner.publicKey.toString());
+
+    const oldEscrow = await provider.connection.getAccountInfo(
+      fixture.escrowPDA
+    );
+    assert.isNull(oldEscrow);
+
+    const registry = await provider.connection.getAccountInfo(newNonceRegistry);
+    assert.equal(registry.data.length, grownRegistryLen);
+    assert.isNull(await provider.connection.getAccountInfo(fixture.nonceRegistry));
+  });
+
+  it("Settles a bundle signed by the old key during the grace period", async () => {
+    const amount = 5_000000;
+    const nonce = 1;
+    const bundleId = "rotated-bundle-1";
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      amount,
+      nonce
+    );
+
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(amount), new anchor.BN(nonce), bundleId, {
+        payerProof,
+        merchantProof: null,
+      })
+      .accountsPartial({
+        escrowAccount: newEscrowPDA,
+        owner: newOwner.publicKey,
+        payer: fixture.owner.publicKey,
+        ownerTombstone: tombstone,
+        merchant: fixture.merchant.publicKey,
+        escrowTokenAccount: newEscrowTokenAccount,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        nonceRegistry: newNonceRegistry,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      newEscrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 45_000000);
+  });
+
+  it("Rejects the old key without its tombstone", async () => {
+    try {
+      await program.methods
+        .settleOfflinePayment(
+          new anchor.BN(1_000000),
+          new anchor.BN(2),
+          "rotated-bundle-2",
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial({
+          escrowAccount: newEscrowPDA,
+          owner: newOwner.publicKey,
+          payer: fixture.owner.publicKey,
+          ownerTombstone: null,
+          merchant: fixture.merchant.publicKey,
+          escrowTokenAccount: newEscrowTokenAccount,
+          merchantTokenAccount: fixture.merchantTokenAccount,
+          nonceRegistry: newNonceRegistry,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.owner])
+        .rpc();
+      assert.fail("Should have failed with InvalidOwner");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidOwner");
+    }
+  });
+});
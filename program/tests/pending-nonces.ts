@@ -0,0 +1,107 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("reserved nonces", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const settle = (amount: number, nonce: number, bundleId: string) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        bundleId,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const manage = { nonceRegistry: null, owner: null };
+  const reserve = (nonce: number) =>
+    program.methods
+      .reserveNonce(new anchor.BN(nonce))
+      .accountsPartial(manage)
+      .signers([fixture.owner])
+      .rpc();
+  const release = (nonce: number) =>
+    program.methods
+      .releaseNonce(new anchor.BN(nonce))
+      .accountsPartial(manage)
+      .signers([fixture.owner])
+      .rpc();
+
+  const pending = async () =>
+    (
+      await program.account.nonceRegistry.fetch(fixture.nonceRegistry)
+    ).pendingNonces.map((n) => n.toNumber());
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    manage.nonceRegistry = fixture.nonceRegistry;
+    manage.owner = fixture.owner.publicKey;
+  });
+
+  it("Reserve, fail, then reuse a nonce after a higher one settled", async () => {
+    await reserve(7);
+
+    // The settlement for nonce 7 fails for an unrelated reason...
+    try {
+      await settle(50_000000, 7, "pending-7");
+      assert.fail("Should have failed with InsufficientFunds");
+    } catch (err) {
+      assert.include(err.toString(), "InsufficientFunds");
+    }
+
+    // ...a later bundle moves the high-water mark past it...
+    await settle(1_000000, 8, "pending-8");
+
+    // ...and the retried bundle still settles, consuming the reservation
+    await settle(1_000000, 7, "pending-7");
+    assert.deepEqual(await pending(), []);
+
+    const registry = await program.account.nonceRegistry.fetch(
+      fixture.nonceRegistry
+    );
+    assert.equal(registry.lastNonce.toNumber(), 8);
+  });
+
+  it("A consumed reservation cannot be replayed", async () => {
+    try {
+      await settle(1_000000, 7, "pending-7-replay");
+      assert.fail("Should have failed with InvalidNonce");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidNonce");
+    }
+  });
+
+  it("Released nonces are no longer accepted below the mark", async () => {
+    await reserve(9);
+    await settle(1_000000, 10, "pending-10");
+    await release(9);
+    assert.deepEqual(await pending(), []);
+
+    try {
+      await settle(1_000000, 9, "pending-9");
+      assert.fail("Should have failed with InvalidNonce");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidNonce");
+    }
+  });
+
+  it("Only nonces above the mark can be reserved", async () => {
+    try {
+      await reserve(10);
+      assert.fail("Should have failed with InvalidNonce");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidNonce");
+    }
+  });
+});
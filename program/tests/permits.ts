@@ -0,0 +1,176 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, airdrop, createEscrowFixture } from "./fixtures";
+
+describe("merchant permits", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let permit: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const grant = (totalCap: number, perPullCap: number, expiresAt: number) =>
+    program.methods
+      .grantPermit(new anchor.BN(totalCap), new anchor.BN(perPullCap), new anchor.BN(expiresAt))
+      .accountsPartial({
+        permit,
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        merchant: fixture.merchant.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const pull = (amount: number) =>
+    program.methods
+      .pullWithPermit(new anchor.BN(amount))
+      .accountsPartial({
+        permit,
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        merchant: fixture.merchant.publicKey,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.merchant])
+      .rpc({ commitment: "confirmed" });
+
+  const revoke = () =>
+    program.methods
+      .revokePermit()
+      .accountsPartial({ permit, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const close = () =>
+    program.methods
+      .closePermit()
+      .accountsPartial({ permit, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const summaries = (permits: PublicKey[]) =>
+    program.methods
+      .getPermits()
+      .accountsPartial({ owner: fixture.owner.publicKey })
+      .remainingAccounts(permits.map((pubkey) => ({ pubkey, isSigner: false, isWritable: false })))
+      .view();
+
+  const setMerchantLimit = (limit: number) =>
+    program.methods
+      .setMerchantLimit(fixture.merchant.publicKey, new anchor.BN(limit))
+      .accountsPartial({ escrowAccount: fixture.escrowPDA, owner: fixture.owner.publicKey })
+      .signers([fixture.owner])
+      .rpc();
+
+  const hourFromNow = () => Math.floor(Date.now() / 1000) + 3600;
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 100_000000);
+    await airdrop(provider, fixture.merchant.publicKey);
+    permit = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("permit"),
+        fixture.owner.publicKey.toBuffer(),
+        fixture.merchant.publicKey.toBuffer(),
+      ],
+      program.programId
+    )[0];
+  });
+
+  it("Rejects a per-pull cap above the total", async () => {
+    await expectError(grant(10_000000, 10_000001, hourFromNow()), "InvalidPermit");
+    await expectError(grant(10_000000, 0, hourFromNow()), "InvalidPermit");
+  });
+
+  it("Lets the merchant pull and reports what remains", async () => {
+    await grant(30_000000, 10_000000, hourFromNow());
+    const signature = await pull(8_000000);
+
+    const pulled = (await eventsOf(signature)).find((event) => event.name === "permitPulled").data;
+    assert.equal(pulled.amount.toNumber(), 8_000000);
+    assert.equal(pulled.remaining.toNumber(), 22_000000);
+
+    const state = await program.account.permit.fetch(permit);
+    assert.equal(state.pulled.toNumber(), 8_000000);
+    assert.equal(state.pullCount, 1);
+    assert.equal(
+      Number((await getAccount(provider.connection, fixture.merchantTokenAccount)).amount),
+      8_000000
+    );
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.escrowBalance.toNumber(), 92_000000);
+  });
+
+  it("Enforces the per-pull and total caps", async () => {
+    await expectError(pull(10_000001), "PermitPullCapExceeded");
+    await pull(10_000000);
+    await pull(10_000000);
+    await expectError(pull(2_000001), "PermitCapExceeded");
+  });
+
+  it("Respects the escrow's own merchant cap", async () => {
+    await setMerchantLimit(29_000000);
+    await expectError(pull(2_000000), "MerchantLimitExceeded");
+    await setMerchantLimit(0);
+  });
+
+  it("Summarizes the payer's permits", async () => {
+    const [summary] = await summaries([permit]);
+    assert.ok(summary.merchant.equals(fixture.merchant.publicKey));
+    assert.equal(summary.remaining.toNumber(), 2_000000);
+    assert.deepEqual(summary.status, { active: {} });
+  });
+
+  it("Only closes a permit once it is revoked or expired", async () => {
+    await expectError(close(), "PermitStillActive");
+  });
+
+  it("Stops pulls as soon as the payer revokes", async () => {
+    await revoke();
+    await expectError(pull(1_000000), "PermitRevoked");
+    await expectError(revoke(), "PermitRevoked");
+
+    const [summary] = await summaries([permit]);
+    assert.deepEqual(summary.status, { revoked: {} });
+
+    await close();
+    assert.isNull(await provider.connection.getAccountInfo(permit));
+  });
+
+  it("Rejects pulls after expiry", async () => {
+    await grant(10_000000, 10_000000, Math.floor(Date.now() / 1000) + 2);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+    await expectError(pull(1_000000), "PermitExpired");
+
+    const [summary] = await summaries([permit]);
+    assert.deepEqual(summary.status, { expired: {} });
+    await close();
+  });
+});
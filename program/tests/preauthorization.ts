@@ -0,0 +1,130 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { keccak_256 } from "@noble/hashes/sha3";
+import {
+  AttestationRole,
+  AttestationProof,
+  createAttestationProof,
+} from "./attestation-helper";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("bundle pre-authorization", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 2_000000;
+  let fixture: EscrowFixture;
+
+  const bundleHash = (bundleId: string) =>
+    Array.from(keccak_256(Buffer.from(bundleId)));
+
+  const preauthorize = (
+    bundleId: string,
+    nonce: number,
+    merchant: PublicKey = fixture.merchant.publicKey
+  ) =>
+    program.methods
+      .preauthorizeBundle(
+        bundleHash(bundleId),
+        merchant,
+        new anchor.BN(AMOUNT),
+        new anchor.BN(nonce)
+      )
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const settle = (
+    nonce: number,
+    bundleId: string,
+    payerProof: AttestationProof | null
+  ) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(AMOUNT), new anchor.BN(nonce), bundleId, {
+        payerProof,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  // A well-formed proof that does not verify, so only a pre-authorization lets it through
+  const bogusProof = (bundleId: string, nonce: number) =>
+    createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT + 1,
+      nonce
+    );
+
+  const activeSlots = async () =>
+    (
+      await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)
+    ).preauthorizations.filter((entry) => entry.amount.toNumber() > 0);
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+  });
+
+  it("Settles a pre-authorized bundle without verifying its attestation", async () => {
+    await preauthorize("preauth-1", 1);
+    assert.lengthOf(await activeSlots(), 1);
+
+    const listener = new Promise<any>((resolve) => {
+      const id = program.addEventListener("preauthorizationConsumed", (event) => {
+        program.removeEventListener(id);
+        resolve(event);
+      });
+    });
+    await settle(1, "preauth-1", await bogusProof("preauth-1", 1));
+
+    const event = await listener;
+    assert.equal(event.nonce.toNumber(), 1);
+    assert.lengthOf(await activeSlots(), 0);
+  });
+
+  it("Still verifies attestations for bundles that don't match", async () => {
+    await preauthorize("preauth-2", 2);
+    try {
+      // Right hash and amount, wrong nonce
+      await settle(3, "preauth-2", await bogusProof("preauth-2", 3));
+      assert.fail("Should have failed with InvalidAttestation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidAttestation");
+    }
+  });
+
+  it("Frees a slot on cancel", async () => {
+    await program.methods
+      .cancelPreauthorization(bundleHash("preauth-2"))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+    assert.lengthOf(await activeSlots(), 0);
+  });
+
+  it("Bounds the pre-authorization set", async () => {
+    for (let nonce = 10; nonce < 14; nonce++) {
+      await preauthorize(`preauth-${nonce}`, nonce);
+    }
+    try {
+      await preauthorize("preauth-14", 14);
+      assert.fail("Should have failed with PreauthorizationTableFull");
+    } catch (err) {
+      assert.include(err.toString(), "PreauthorizationTableFull");
+    }
+  });
+});
@@ -0,0 +1,89 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  settleAccounts,
+  simulateReturnData,
+} from "./fixtures";
+
+describe("settlement preflight", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const errorCode = (name: string) => program.idl.errors.find((err) => err.name === name).code;
+
+  const args = (amount: number, nonce: number, bundleId: string) =>
+    [
+      new anchor.BN(amount),
+      new anchor.BN(nonce),
+      bundleId,
+      { payerProof: null, merchantProof: null },
+    ] as const;
+
+  const preflight = async (amount: number, nonce: number, bundleId: string) =>
+    program.coder.types.decode(
+      "settlementPreflight",
+      await simulateReturnData(
+        provider,
+        await program.methods
+          .preflightSettlement(...args(amount, nonce, bundleId))
+          .accountsPartial(settleAccounts(fixture))
+          .transaction()
+      )
+    );
+
+  const settle = (amount: number, nonce: number, bundleId: string) =>
+    program.methods
+      .settleOfflinePayment(...args(amount, nonce, bundleId))
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Passes a settleable bundle without settling it", async () => {
+    const before = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    const result = await preflight(2_000000, 1, "preflight-1");
+    assert.deepEqual(result.check, { passed: {} });
+    assert.equal(result.errorCode, 0);
+
+    const after = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(after.escrowBalance.toString(), before.escrowBalance.toString());
+    assert.equal(after.settlementCount.toString(), before.settlementCount.toString());
+    await settle(2_000000, 1, "preflight-1");
+  });
+
+  it("Names the first check a bundle would fail", async () => {
+    const cases: [number, number, string, string, string][] = [
+      [1_000000, 2, "", "bundleId", "InvalidBundleId"],
+      [1_000000, 2, "preflight-1", "duplicate", "DuplicateBundle"],
+      [1_000000, 1, "preflight-2", "nonce", "InvalidNonce"],
+      [50_000000, 2, "preflight-2", "balance", "InsufficientFunds"],
+    ];
+    for (const [amount, nonce, bundleId, check, error] of cases) {
+      const result = await preflight(amount, nonce, bundleId);
+      assert.deepEqual(result.check, { [check]: {} }, bundleId);
+      assert.equal(result.errorCode, errorCode(error), bundleId);
+    }
+  });
+
+  it("Agrees with the settlement it stands in for", async () => {
+    try {
+      await settle(1_000000, 1, "preflight-2");
+      assert.fail("Should have failed with InvalidNonce");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidNonce");
+    }
+    assert.deepEqual((await preflight(1_000000, 2, "preflight-2")).check, { passed: {} });
+    await settle(1_000000, 2, "preflight-2");
+  });
+});
@@ -0,0 +1,95 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("settlement punctuality", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const HOUR = 3_600;
+  let fixture: EscrowFixture;
+
+  // Settle a bundle whose payer attestation was made `ageSecs` ago, or an
+  // unattested one when `ageSecs` is null
+  const settle = async (nonce: number, ageSecs: number | null) => {
+    const bundleId = `punctual-${nonce}`;
+    const payerProof =
+      ageSecs === null
+        ? null
+        : await createAttestationProof(
+            AttestationRole.Payer,
+            bundleId,
+            fixture.owner.publicKey,
+            fixture.merchant.publicKey,
+            1_000000,
+            nonce,
+            undefined,
+            null,
+            null,
+            null,
+            null,
+            null,
+            Math.floor(Date.now() / 1000) - ageSecs
+          );
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), bundleId, {
+        payerProof,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  const summary = () =>
+    program.methods
+      .getEscrowSummary()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA })
+      .view();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  it("Exports the bucket bounds in the IDL", () => {
+    const buckets = program.idl.constants.find((c) => c.name === "PUNCTUALITY_BUCKETS");
+    assert.equal(buckets.value, "[3600, 86400, 604800]");
+  });
+
+  it("Records the attested creation time on the bundle record", async () => {
+    await settle(1, 60);
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const record = registry.bundleHistory[registry.bundleHistory.length - 1];
+    const age = record.settledAt.toNumber() - record.bundleCreatedAt.toNumber();
+    assert.isAtLeast(age, 30);
+    assert.isAtMost(age, 120);
+  });
+
+  it("Counts settlements into the buckets they landed within", async () => {
+    await settle(2, 2 * HOUR);
+    await settle(3, 20 * HOUR);
+
+    const result = await summary();
+    assert.equal(result.timedSettlementCount.toNumber(), 3);
+    assert.deepEqual(
+      result.settledWithin.map((count) => count.toNumber()),
+      [1, 3, 3]
+    );
+  });
+
+  it("Leaves unattested settlements out of the aggregates", async () => {
+    await settle(4, null);
+
+    const result = await summary();
+    assert.equal(result.settlementCount.toNumber(), 4);
+    assert.equal(result.timedSettlementCount.toNumber(), 3);
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const record = registry.bundleHistory[registry.bundleHistory.length - 1];
+    assert.equal(record.bundleCreatedAt.toNumber(), 0);
+  });
+});
@@ -0,0 +1,187 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey, Keypair, SystemProgram } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  createMint,
+  getAccount,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  findEscrowPDA,
+  settleAccounts,
+} from "./fixtures";
+
+describe("referral rewards", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const referrer = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let treasuryTokenAccount: PublicKey;
+  let referrerTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const settle = (nonce: number, withTreasury = true) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(10_000000),
+        new anchor.BN(nonce),
+        `referral-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        treasuryTokenAccount: withTreasury ? treasuryTokenAccount : null,
+        referrerTokenAccount,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(
+      provider,
+      program,
+      100_000000,
+      1000_000000,
+      referrer.publicKey
+    );
+
+    treasuryTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      provider.wallet.publicKey,
+      Keypair.generate()
+    );
+    referrerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        fixture.owner,
+        fixture.mint,
+        referrer.publicKey
+      )
+    ).address;
+
+    // 1% fee, of which 0.05 tokens goes to the referrer on the first two settlements
+    await program.methods
+      .updateConfig({
+        feeBps: 100,
+        treasury: provider.wallet.publicKey,
+        referralReward: new anchor.BN(50000),
+        referralRewardLimit: 2,
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .updateConfig({
+        feeBps: 0,
+        referralReward: new anchor.BN(0),
+        referralRewardLimit: 0,
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Stores the referrer at initialization", async () => {
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.ok(escrow.referrer.equals(referrer.publicKey));
+  });
+
+  it("Rejects self-referral", async () => {
+    const owner = Keypair.generate();
+    await airdrop(provider, owner.publicKey);
+    const mint = await createMint(
+      provider.connection,
+      owner,
+      owner.publicKey,
+      null,
+      6
+    );
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        owner,
+        mint,
+        owner.publicKey
+      )
+    ).address;
+    const escrowPDA = findEscrowPDA(program, owner.publicKey);
+    const escrowTokenAccount = await createAccount(
+      provider.connection,
+      owner,
+      mint,
+      escrowPDA,
+      Keypair.generate()
+    );
+
+    try {
+      await program.methods
+        .initializeEscrow(new anchor.BN(0))
+        .accounts({
+          escrowAccount: escrowPDA,
+          owner: owner.publicKey,
+          rentPayer: owner.publicKey,
+          ownerTokenAccount,
+          escrowTokenAccount,
+          referrer: owner.publicKey,
+          tokenProgram: TOKEN_PROGRAM_ID,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([owner])
+        .rpc();
+      assert.fail("Should have failed with SelfReferral");
+    } catch (err) {
+      assert.include(err.toString(), "SelfReferral");
+    }
+  });
+
+  it("Pays the referrer out of the fee until the limit is reached", async () => {
+    const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+
+    await settle(1);
+    await settle(2);
+    await settle(3);
+
+    // The merchant always receives the amount net of the 1% fee
+    assert.equal(
+      await balanceOf(fixture.merchantTokenAccount),
+      merchantBefore + 3 * 9_900000
+    );
+    assert.equal(await balanceOf(referrerTokenAccount), 2 * 50000);
+    assert.equal(await balanceOf(treasuryTokenAccount), 2 * 50000 + 100000);
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.referralRewardsPaid, 2);
+    assert.equal(escrow.escrowBalance.toNumber(), 70_000000);
+  });
+
+  it("Requires the treasury account while a fee is configured", async () => {
+    try {
+      await settle(4, false);
+      assert.fail("Should have failed with InvalidTreasuryAccount");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidTreasuryAccount");
+    }
+  });
+});
@@ -0,0 +1,155 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("nonce registry merging", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const reporter = Keypair.generate();
+  let config: PublicKey;
+  let oldWallet: EscrowFixture;
+  let newWallet: EscrowFixture;
+  let disputed: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setDelay = (seconds: number) =>
+    program.methods
+      .updateConfig({ fraudWithdrawalDelay: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settle = (fixture: EscrowFixture, nonce: number, bundleId: string) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), bundleId, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const merge = (source: EscrowFixture, destination: EscrowFixture) =>
+    program.methods
+      .mergeRegistries()
+      .accountsPartial({
+        sourceRegistry: source.nonceRegistry,
+        destinationRegistry: destination.nonceRegistry,
+        config,
+        sourceOwner: source.owner.publicKey,
+        destinationOwner: destination.owner.publicKey,
+        sourceRentPayer: source.owner.publicKey,
+      })
+      .signers(
+        source === destination ? [source.owner] : [source.owner, destination.owner]
+      )
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    await airdrop(provider, reporter.publicKey);
+    oldWallet = await createEscrowFixture(provider, program, 20_000000);
+    newWallet = await createEscrowFixture(provider, program, 20_000000);
+    disputed = await createEscrowFixture(provider, program, 20_000000);
+
+    for (const nonce of [1, 2, 3]) {
+      await settle(oldWallet, nonce, `old-${nonce}`);
+    }
+    await settle(newWallet, 1, "new-1");
+  });
+
+  after(async () => {
+    await setDelay(0);
+  });
+
+  it("Refuses to merge a registry into itself", async () => {
+    await expectError(merge(oldWallet, oldWallet), "InvalidRegistryMerge");
+  });
+
+  it("Refuses a source with reserved nonces", async () => {
+    const manage = {
+      nonceRegistry: oldWallet.nonceRegistry,
+      owner: oldWallet.owner.publicKey,
+    };
+    await program.methods
+      .reserveNonce(new anchor.BN(5))
+      .accountsPartial(manage)
+      .signers([oldWallet.owner])
+      .rpc();
+    await expectError(merge(oldWallet, newWallet), "DeviceRegistryBusy");
+    await program.methods
+      .releaseNonce(new anchor.BN(5))
+      .accountsPartial(manage)
+      .signers([oldWallet.owner])
+      .rpc();
+  });
+
+  it("Appends the source's history and keeps the higher nonce", async () => {
+    const before = await program.account.nonceRegistry.fetch(newWallet.nonceRegistry);
+    await merge(oldWallet, newWallet);
+
+    const after = await program.account.nonceRegistry.fetch(newWallet.nonceRegistry);
+    assert.equal(after.bundleHistory.length, before.bundleHistory.length + 3);
+    assert.equal(after.lastNonce.toNumber(), 3);
+    assert.isNull(await program.account.nonceRegistry.fetchNullable(oldWallet.nonceRegistry));
+
+    // Bundles settled from the old wallet are duplicates for the new one
+    const hashes = after.recentBundleHashes.map((hash) => Buffer.from(hash).toString("hex"));
+    assert.equal(new Set(hashes).size, hashes.length);
+    await expectError(settle(newWallet, 4, "old-2"), "DuplicateBundle");
+  });
+
+  it("Blocks merging a source with an open dispute", async () => {
+    await setDelay(3600);
+    await settle(disputed, 1, "disputed-1");
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      disputed.owner,
+      Buffer.alloc(32, 9),
+      disputed.merchant.publicKey,
+      1_000000,
+      1
+    );
+    await program.methods
+      .reportFraudulentBundle(
+        "disputed-1",
+        conflictingHash,
+        { duplicateBundle: {} },
+        evidence as any
+      )
+      .accountsPartial({
+        escrowAccount: disputed.escrowPDA,
+        payer: disputed.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+
+    await expectError(merge(disputed, newWallet), "RegistryDisputed");
+
+    // Once the delay has lapsed the report no longer holds the registry open
+    await setDelay(0);
+    await merge(disputed, newWallet);
+    const after = await program.account.nonceRegistry.fetch(newWallet.nonceRegistry);
+    assert.equal(after.fraudRecords.length, 1);
+  });
+});
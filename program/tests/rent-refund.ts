@@ -0,0 +1,233 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram, Transaction } from "@solana/web3.js";
+import {
+  TOKEN_PROGRAM_ID,
+  createAccount,
+  getOrCreateAssociatedTokenAccount,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("rent refunds", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const balanceOf = (account: PublicKey) => provider.connection.getBalance(account);
+
+  const newSponsor = async () => {
+    const sponsor = Keypair.generate();
+    await airdrop(provider, sponsor.publicKey);
+    return sponsor;
+  };
+
+  // An unfunded escrow of a second key of the fixture's user, with rent from `sponsor`
+  const openSponsoredEscrow = async (sponsor: Keypair) => {
+    const owner = Keypair.generate();
+    const escrow = findEscrowPDA(program, owner.publicKey);
+    const vault = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      escrow,
+      Keypair.generate()
+    );
+    const ownerTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        fixture.owner,
+        fixture.mint,
+        owner.publicKey
+      )
+    ).address;
+    await program.methods
+      .initializeEscrow(new anchor.BN(0))
+      .accountsPartial({
+        escrowAccount: escrow,
+        owner: owner.publicKey,
+        rentPayer: sponsor.publicKey,
+        ownerTokenAccount,
+        escrowTokenAccount: vault,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts([{ pubkey: owner.publicKey, isSigner: true, isWritable: false }])
+      .signers([owner, sponsor])
+      .rpc();
+    return { owner, escrow, vault };
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 1_000000);
+  });
+
+  it("Returns a consolidated escrow's rent to its rent payer", async () => {
+    const sponsor = await newSponsor();
+    const source = await openSponsoredEscrow(sponsor);
+    const consolidate = (rentPayer: PublicKey) =>
+      program.methods
+        .consolidateEscrows()
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          owner: fixture.owner.publicKey,
+          escrowTokenAccount: fixture.escrowTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .remainingAccounts([
+          { pubkey: source.escrow, isSigner: false, isWritable: true },
+          { pubkey: source.vault, isSigner: false, isWritable: true },
+          { pubkey: source.owner.publicKey, isSigner: true, isWritable: true },
+          // The sponsored source never opened a nonce registry
+          {
+            pubkey: findNonceRegistryPDA(program, source.owner.publicKey),
+            isSigner: false,
+            isWritable: true,
+          },
+          { pubkey: rentPayer, isSigner: false, isWritable: true },
+          { pubkey: source.owner.publicKey, isSigner: false, isWritable: true },
+        ])
+        .signers([fixture.owner, source.owner])
+        .rpc();
+
+    await expectError(consolidate(source.owner.publicKey), "InvalidRentRecipient");
+
+    const escrowRent = await balanceOf(source.escrow);
+    const sponsorBefore = await balanceOf(sponsor.publicKey);
+    await consolidate(sponsor.publicKey);
+
+    assert.isNull(await provider.connection.getAccountInfo(source.escrow));
+    assert.equal(await balanceOf(sponsor.publicKey), sponsorBefore + escrowRent);
+  });
+
+  it("Returns an invoice's rent to its rent payer rather than the merchant", async () => {
+    const sponsor = await newSponsor();
+    const invoiceId = Array.from(Keypair.generate().publicKey.toBytes().slice(0, 16));
+    const [invoice] = PublicKey.findProgramAddressSync(
+      [Buffer.from("invoice"), fixture.merchant.publicKey.toBuffer(), Buffer.from(invoiceId)],
+      program.programId
+    );
+    await program.methods
+      .createInvoice(
+        invoiceId,
+        { open: {} },
+        new anchor.BN(0),
+        new anchor.BN(Math.floor(Date.now() / 1000) + 2)
+      )
+      .accountsPartial({
+        invoice,
+        merchant: fixture.merchant.publicKey,
+        rentPayer: sponsor.publicKey,
+      })
+      .signers([fixture.merchant, sponsor])
+      .rpc();
+    assert.ok((await program.account.invoice.fetch(invoice)).rentPayer.equals(sponsor.publicKey));
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    const close = (rentPayer: PublicKey) =>
+      program.methods
+        .closeInvoice()
+        .accountsPartial({ invoice, merchant: fixture.merchant.publicKey, rentPayer })
+        .signers([fixture.merchant])
+        .rpc();
+    await expectError(close(fixture.merchant.publicKey), "InvalidRentRecipient");
+
+    const invoiceRent = await balanceOf(invoice);
+    const sponsorBefore = await balanceOf(sponsor.publicKey);
+    await close(sponsor.publicKey);
+    assert.equal(await balanceOf(sponsor.publicKey), sponsorBefore + invoiceRent);
+  });
+
+  it("Returns a merged registry's rent to its rent payer rather than the owner", async () => {
+    const config = await ensureConfig(provider, program);
+    const sponsor = await newSponsor();
+    const owner = Keypair.generate();
+    const registry = findNonceRegistryPDA(program, owner.publicKey);
+    await program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: owner.publicKey,
+        rentPayer: sponsor.publicKey,
+        nonceRegistry: registry,
+      })
+      .signers([owner, sponsor])
+      .rpc();
+    assert.ok(
+      (await program.account.nonceRegistry.fetch(registry)).rentPayer.equals(sponsor.publicKey)
+    );
+    const merge = (sourceRentPayer: PublicKey) =>
+      program.methods
+        .mergeRegistries()
+        .accountsPartial({
+          sourceRegistry: registry,
+          destinationRegistry: fixture.nonceRegistry,
+          config,
+          sourceOwner: owner.publicKey,
+          destinationOwner: fixture.owner.publicKey,
+          sourceRentPayer,
+        })
+        .signers([owner, fixture.owner])
+        .rpc();
+
+    await expectError(merge(owner.publicKey), "InvalidRentRecipient");
+
+    const registryRent = await balanceOf(registry);
+    const sponsorBefore = await balanceOf(sponsor.publicKey);
+    await merge(sponsor.publicKey);
+
+    assert.isNull(await provider.connection.getAccountInfo(registry));
+    assert.equal(await balanceOf(sponsor.publicKey), sponsorBefore + registryRent);
+  });
+
+  it("Lets the owner redirect the refund only once the rent payer is gone", async () => {
+    const sponsor = await newSponsor();
+    const source = await openSponsoredEscrow(sponsor);
+    const redirect = () =>
+      program.methods
+        .setEscrowRentPayer(source.owner.publicKey)
+        .accountsPartial({
+          escrowAccount: source.escrow,
+          owner: source.owner.publicKey,
+          currentRentPayer: sponsor.publicKey,
+        })
+        .signers([source.owner])
+        .rpc();
+
+    await expectError(redirect(), "RentPayerStillExists");
+
+    // Empty the sponsor's account so it no longer exists
+    await provider.sendAndConfirm(
+      new Transaction().add(
+        SystemProgram.transfer({
+          fromPubkey: sponsor.publicKey,
+          toPubkey: provider.wallet.publicKey,
+          lamports: await balanceOf(sponsor.publicKey),
+        })
+      ),
+      [sponsor]
+    );
+    await redirect();
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(source.escrow);
+    assert.ok(escrow.rentPayer.equals(source.owner.publicKey));
+  });
+});
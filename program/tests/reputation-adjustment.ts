@@ -0,0 +1,105 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig } from "./fixtures";
+
+describe("manual reputation adjustments", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const arbiters = [Keypair.generate(), Keypair.generate(), Keypair.generate()];
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const adjust = (delta: number, reasonCode: number, cosigners: Keypair[] = []) =>
+    program.methods
+      .adjustReputation(delta, reasonCode)
+      .accountsPartial({
+        config,
+        admin: provider.wallet.publicKey,
+        escrowAccount: fixture.escrowPDA,
+      })
+      .remainingAccounts(
+        cosigners.map((arbiter) => ({
+          pubkey: arbiter.publicKey,
+          isSigner: true,
+          isWritable: false,
+        }))
+      )
+      .signers(cosigners)
+      .rpc();
+
+  const reputation = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA))
+      .reputationScore;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 1_000000);
+    await program.methods
+      .setArbiters(
+        arbiters.map((arbiter) => arbiter.publicKey),
+        2
+      )
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await program.methods
+      .updateConfig({ reputationQuorumThreshold: 100 })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .setArbiters([], 0)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await program.methods
+      .updateConfig({ reputationQuorumThreshold: 0 })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Applies small adjustments on the admin's signature alone", async () => {
+    const before = await reputation();
+    await adjust(50, 1);
+    assert.equal(await reputation(), before + 50);
+  });
+
+  it("Requires the arbiter quorum above the threshold", async () => {
+    await expectError(adjust(500, 2), "ArbiterQuorumNotMet");
+    await expectError(adjust(500, 2, [arbiters[0]]), "ArbiterQuorumNotMet");
+    const before = await reputation();
+    await adjust(-500, 2, arbiters.slice(0, 2));
+    assert.equal(await reputation(), before - 500);
+  });
+
+  it("Rejects unexplained or unbounded adjustments", async () => {
+    await expectError(adjust(10, 0), "InvalidReputationAdjustment");
+    await expectError(
+      adjust(1_001, 3, arbiters.slice(0, 2)),
+      "InvalidReputationAdjustment"
+    );
+  });
+
+  it("Caps the total adjusted per period", async () => {
+    await adjust(1_000, 4, arbiters.slice(1));
+    await expectError(
+      adjust(500, 5, arbiters.slice(1)),
+      "ReputationAdjustmentCapExceeded"
+    );
+  });
+});
@@ -0,0 +1,93 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, SystemProgram } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("reputation bounds", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const INITIAL_REPUTATION = 100;
+  const MIN_REPUTATION = -10_000;
+  const FRAUD_PENALTY = 1_000;
+  const reporter = Keypair.generate();
+  let fixture: EscrowFixture;
+
+  const reputation = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA))
+      .reputationScore;
+
+  const reportFraud = async (conflict: number) => {
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, conflict),
+      fixture.merchant.publicKey,
+      500000,
+      1
+    );
+    return program.methods
+      .reportFraudulentBundle("bounds-1", conflictingHash, { duplicateBundle: {} }, evidence as any)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+  };
+
+  before(async () => {
+    // Each report slashes twice the bundle amount, so keep it small
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await airdrop(provider, reporter.publicKey);
+    await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(500000),
+        new anchor.BN(1),
+        "bounds-1",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+  });
+
+  it("Starts new escrows at the initial score", async () => {
+    assert.equal(await reputation(), INITIAL_REPUTATION);
+  });
+
+  it("Goes negative on fraud instead of stopping at zero", async () => {
+    await reportFraud(1);
+    assert.equal(await reputation(), INITIAL_REPUTATION - FRAUD_PENALTY);
+  });
+
+  it("Saturates at the lower bound", async () => {
+    for (let conflict = 2; conflict <= 12; conflict++) {
+      await reportFraud(conflict);
+    }
+    assert.equal(await reputation(), MIN_REPUTATION);
+  });
+
+  it("Leaves a current-layout score alone on migration", async () => {
+    await program.methods
+      .migrateEscrow()
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([fixture.owner])
+      .rpc();
+    assert.equal(await reputation(), MIN_REPUTATION);
+  });
+});
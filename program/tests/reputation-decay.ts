@@ -0,0 +1,137 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("reputation decay", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const INITIAL_REPUTATION = 100;
+  const BASELINE = 60;
+  const POINTS = 15;
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setDecay = (period: number, points = POINTS, baseline = BASELINE) =>
+    program.methods
+      .updateConfig({
+        reputationDecayPeriod: new anchor.BN(period),
+        reputationDecayPoints: points,
+        reputationDecayBaseline: baseline,
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settle = async (nonce: number) => {
+    const signature = await program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), `decay-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+    return (await eventsOf(signature)).find((event) => event.name === "reputationDecayed");
+  };
+
+  const summaryScore = async () =>
+    (
+      await program.methods
+        .getEscrowSummary()
+        .accountsPartial({ escrowAccount: fixture.escrowPDA })
+        .view()
+    ).reputationScore;
+
+  const storedScore = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)).reputationScore;
+
+  const sleep = (ms: number) => new Promise((resolve) => setTimeout(resolve, ms));
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  after(async () => {
+    await setDecay(0, 0, 0);
+  });
+
+  it("Bounds the decay configuration", async () => {
+    await expectError(setDecay(-1), "InvalidConfig");
+    await expectError(setDecay(1, 1_001), "InvalidConfig");
+    await expectError(setDecay(1, POINTS, 10_001), "InvalidConfig");
+  });
+
+  it("Leaves escrows that never settled alone", async () => {
+    await setDecay(1);
+    await sleep(2000);
+    assert.equal(await summaryScore(), INITIAL_REPUTATION);
+    // Starts the inactivity clock without decaying anything
+    assert.isUndefined(await settle(1));
+    assert.equal(await storedScore(), INITIAL_REPUTATION);
+  });
+
+  it("Reports decay through the getter without touching the stored score", async () => {
+    await sleep(2500);
+    const score = await summaryScore();
+    assert.isBelow(score, INITIAL_REPUTATION);
+    assert.isAtLeast(score, BASELINE);
+    assert.equal((INITIAL_REPUTATION - score) % POINTS, 0);
+    assert.equal(await storedScore(), INITIAL_REPUTATION);
+  });
+
+  it("Folds decay into the score on the next settlement", async () => {
+    const decayed = await settle(2);
+    assert.ok(decayed);
+    assert.equal(decayed.data.oldScore, INITIAL_REPUTATION);
+    assert.isAtLeast(decayed.data.inactivePeriods.toNumber(), 2);
+    assert.equal(
+      decayed.data.newScore,
+      Math.max(BASELINE, INITIAL_REPUTATION - POINTS * decayed.data.inactivePeriods.toNumber())
+    );
+    assert.equal(await storedScore(), decayed.data.newScore);
+  });
+
+  it("Never decays below the baseline", async () => {
+    await sleep(4000);
+    assert.equal(await summaryScore(), BASELINE);
+    const decayed = await settle(3);
+    assert.equal(decayed.data.newScore, BASELINE);
+    await sleep(2000);
+    assert.isUndefined(await settle(4));
+  });
+
+  it("Stops decaying once disabled", async () => {
+    await program.methods
+      .adjustReputation(40, 1)
+      .accountsPartial({ escrowAccount: fixture.escrowPDA, config, admin: provider.wallet.publicKey })
+      .rpc();
+    await setDecay(0);
+    await sleep(2000);
+    assert.equal(await summaryScore(), BASELINE + 40);
+    assert.isUndefined(await settle(5));
+  });
+});
@@ -0,0 +1,124 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { getOrCreateAssociatedTokenAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig, settleAccounts } from "./fixtures";
+
+describe("settlement reputation rewards", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const REWARD = 10;
+  const MERCHANT_CAP = 2;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let nonce = 0;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setRewards = (reward: number, cap: number, period: number) =>
+    program.methods
+      .updateConfig({
+        settlementReputationReward: reward,
+        reputationRewardMerchantCap: cap,
+        reputationRewardPeriod: new anchor.BN(period),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settleTo = async (merchant: PublicKey, merchantTokenAccount: PublicKey) => {
+    nonce += 1;
+    const signature = await program.methods
+      .settleOfflinePayment(new anchor.BN(100000), new anchor.BN(nonce), `reward-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({ ...settleAccounts(fixture), merchant, merchantTokenAccount })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+    return (await eventsOf(signature)).find(
+      (event) => event.name === "settlementReputationRewarded"
+    ).data;
+  };
+
+  const score = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)).reputationScore;
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  after(async () => {
+    await setRewards(0, 0, 0);
+  });
+
+  it("Bounds the reward to a manual adjustment", async () => {
+    await expectError(setRewards(1_001, MERCHANT_CAP, 3600), "InvalidConfig");
+    await expectError(setRewards(REWARD, MERCHANT_CAP, -1), "InvalidConfig");
+  });
+
+  it("Stops rewarding repeat payments to one merchant at the cap", async () => {
+    await setRewards(REWARD, MERCHANT_CAP, 3600);
+    const start = await score();
+
+    for (let paid = 0; paid < MERCHANT_CAP; paid++) {
+      const rewarded = await settleTo(fixture.merchant.publicKey, fixture.merchantTokenAccount);
+      assert.equal(rewarded.reward, REWARD);
+      assert.equal(rewarded.priorToMerchant, paid);
+    }
+    const capped = await settleTo(fixture.merchant.publicKey, fixture.merchantTokenAccount);
+    assert.equal(capped.reward, 0);
+    assert.equal(capped.priorToMerchant, MERCHANT_CAP);
+    assert.equal(capped.distinctMerchants, 1);
+
+    assert.equal(await score(), start + MERCHANT_CAP * REWARD);
+  });
+
+  it("Keeps rewarding payments to merchants not yet paid", async () => {
+    const other = Keypair.generate();
+    const otherTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        fixture.owner,
+        fixture.mint,
+        other.publicKey
+      )
+    ).address;
+    const before = await score();
+
+    const rewarded = await settleTo(other.publicKey, otherTokenAccount);
+    assert.equal(rewarded.reward, REWARD);
+    assert.equal(rewarded.priorToMerchant, 0);
+    assert.equal(rewarded.distinctMerchants, 1);
+    assert.equal(await score(), before + REWARD);
+  });
+
+  it("Lifts the cap once earlier payments leave the period", async () => {
+    await setRewards(REWARD, MERCHANT_CAP, 1);
+    await new Promise((resolve) => setTimeout(resolve, 3000));
+    const rewarded = await settleTo(fixture.merchant.publicKey, fixture.merchantTokenAccount);
+    assert.equal(rewarded.reward, REWARD);
+    assert.equal(rewarded.priorToMerchant, 0);
+  });
+});
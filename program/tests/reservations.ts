@@ -0,0 +1,118 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("balance reservations", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const ownerAction = () => ({
+    escrowAccount: fixture.escrowPDA,
+    owner: fixture.owner.publicKey,
+  });
+
+  const reserve = (amount: number, expiresAt: number) =>
+    program.methods
+      .reserveFunds(new anchor.BN(amount), new anchor.BN(expiresAt))
+      .accountsPartial(ownerAction())
+      .signers([fixture.owner])
+      .rpc();
+
+  const withdraw = (amount: number) =>
+    program.methods
+      .withdrawEscrow(new anchor.BN(amount))
+      .accountsPartial({
+        ...ownerAction(),
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `reservation-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const escrow = () =>
+    program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+
+  const expectReserved = async (promise: Promise<unknown>) => {
+    try {
+      await promise;
+      assert.fail("Should have failed with FundsReserved");
+    } catch (err) {
+      assert.include(err.toString(), "FundsReserved");
+    }
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 30_000000);
+  });
+
+  it("Keeps reserved funds out of reach of withdrawals", async () => {
+    await reserve(20_000000, Math.floor(Date.now() / 1000) + 3600);
+    await expectReserved(withdraw(10_000001));
+    await withdraw(10_000000);
+    assert.equal((await escrow()).escrowBalance.toNumber(), 20_000000);
+  });
+
+  it("Settlements draw the reservation down first", async () => {
+    await settle(5_000000, 1);
+    const state = await escrow();
+    assert.equal(state.reservedBalance.toNumber(), 15_000000);
+    assert.equal(state.escrowBalance.toNumber(), 15_000000);
+  });
+
+  it("Releases unused reservation early", async () => {
+    await program.methods
+      .releaseReservation(new anchor.BN(5_000000))
+      .accountsPartial(ownerAction())
+      .signers([fixture.owner])
+      .rpc();
+    assert.equal((await escrow()).reservedBalance.toNumber(), 10_000000);
+    await withdraw(5_000000);
+    await expectReserved(withdraw(1));
+  });
+
+  it("Expired reservations stop blocking and can be cranked away", async () => {
+    await program.methods
+      .releaseReservation(new anchor.BN(10_000000))
+      .accountsPartial(ownerAction())
+      .signers([fixture.owner])
+      .rpc();
+    await reserve(4_000000, Math.floor(Date.now() / 1000) + 2);
+    await new Promise((resolve) => setTimeout(resolve, 4000));
+
+    await program.methods
+      .expireReservation()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA })
+      .rpc();
+    assert.equal((await escrow()).reservedBalance.toNumber(), 0);
+    await withdraw(4_000000);
+  });
+
+  it("Rejects a reservation larger than the unreserved balance", async () => {
+    try {
+      await reserve(100_000000, Math.floor(Date.now() / 1000) + 3600);
+      assert.fail("Should have failed with InsufficientFunds");
+    } catch (err) {
+      assert.include(err.toString(), "InsufficientFunds");
+    }
+  });
+});
@@ -0,0 +1,85 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("rolling settlement cap", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+  let config: anchor.web3.PublicKey;
+
+  const setRollingLimit = (days: number, cap: number) =>
+    program.methods
+      .updateConfig({
+        rollingWindowDays: days,
+        rollingCap: new anchor.BN(cap),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `rolling-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const remainingBudget = () =>
+    program.methods
+      .getRollingBudget()
+      .accountsPartial({ escrowAccount: fixture.escrowPDA })
+      .view();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 100_000000);
+    await setRollingLimit(7, 10_000000);
+  });
+
+  after(async () => {
+    await setRollingLimit(0, 0);
+  });
+
+  it("Reports the full budget before any settlement", async () => {
+    assert.equal((await remainingBudget()).toNumber(), 10_000000);
+  });
+
+  it("Settles up to the cap and rejects beyond it", async () => {
+    await settle(6_000000, 1);
+    assert.equal((await remainingBudget()).toNumber(), 4_000000);
+
+    try {
+      await settle(4_000001, 2);
+      assert.fail("Should have failed with RollingLimitExceeded");
+    } catch (err) {
+      assert.include(err.toString(), "RollingLimitExceeded");
+    }
+
+    await settle(4_000000, 3);
+    assert.equal((await remainingBudget()).toNumber(), 0);
+  });
+
+  it("Rejects a window longer than the bucket ring", async () => {
+    try {
+      await setRollingLimit(60, 10_000000);
+      assert.fail("Should have failed with InvalidConfig");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidConfig");
+    }
+  });
+});
@@ -0,0 +1,111 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("round-up donations", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const UNIT = 1_000000;
+  const charity = Keypair.generate();
+  let fixture: EscrowFixture;
+  let charityTokenAccount: PublicKey;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  const setRoundup = (enabled: boolean, unit: number, charityAccount: PublicKey | null) =>
+    program.methods
+      .setRoundupConfig(enabled, new anchor.BN(unit))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        charityTokenAccount: charityAccount,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  // Returns the round-up reported by PaymentSettled
+  const settle = async (amount: number, nonce: number) => {
+    const signature = await program.methods
+      .settleOfflinePayment(new anchor.BN(amount), new anchor.BN(nonce), `roundup-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({ ...settleAccounts(fixture), charityTokenAccount })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+    const settled = (await eventsOf(signature)).find((event) => event.name === "paymentSettled");
+    return settled.data.roundup.toNumber();
+  };
+
+  const charityBalance = async () =>
+    Number((await getAccount(provider.connection, charityTokenAccount)).amount);
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 5_000000);
+    charityTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      charity.publicKey,
+      Keypair.generate()
+    );
+  });
+
+  it("Requires a charity account and a unit to enable", async () => {
+    await expectError(setRoundup(true, UNIT, null), "InvalidRoundupConfig");
+    await expectError(setRoundup(true, 0, charityTokenAccount), "InvalidRoundupConfig");
+  });
+
+  it("Donates the difference up to the next whole unit", async () => {
+    await setRoundup(true, UNIT, charityTokenAccount);
+
+    assert.equal(await settle(1_250000, 1), 750000);
+    assert.equal(await charityBalance(), 750000);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.escrowBalance.toNumber(), 3_000000);
+    assert.equal(escrow.totalDonated.toNumber(), 750000);
+    assert.equal(escrow.totalSpent.toNumber(), 1_250000);
+
+    // Whole amounts have nothing to round
+    assert.equal(await settle(1_000000, 2), 0);
+  });
+
+  it("Skips the round-up when the balance can't cover it", async () => {
+    assert.equal(await settle(1_900000, 3), 0);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.escrowBalance.toNumber(), 100000);
+    assert.equal(await charityBalance(), 750000);
+  });
+
+  it("Stops donating once disabled", async () => {
+    await setRoundup(false, 0, null);
+    assert.equal(await settle(50000, 4), 0);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.isFalse(escrow.roundupConfig.enabled);
+    assert.ok(escrow.roundupConfig.charityTokenAccount.equals(charityTokenAccount));
+  });
+});
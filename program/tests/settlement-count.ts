@@ -0,0 +1,86 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("settlement count", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const settle = (nonce: number, amount = 1_000000) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `count-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+
+  const settlementCount = async () =>
+    (
+      await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)
+    ).settlementCount.toNumber();
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Starts at zero", async () => {
+    assert.equal(await settlementCount(), 0);
+  });
+
+  it("Counts settlements sequentially even when nonces skip", async () => {
+    await settle(1);
+    await settle(5);
+    const signature = await settle(9);
+    assert.equal(await settlementCount(), 3);
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const settled = Array.from(parser.parseLogs(tx.meta.logMessages)).find(
+      (event) => event.name === "paymentSettled"
+    );
+    assert.equal(settled.data.settlementCount.toNumber(), 3);
+  });
+
+  it("Stamps each settlement's index into its record, event and receipt", async () => {
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    assert.deepEqual(
+      registry.bundleHistory.map((record) => record.settlementIndex.toNumber()),
+      [1, 2, 3]
+    );
+
+    const signature = await settle(12);
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const events = Array.from(parser.parseLogs(tx.meta.logMessages));
+    const recorded = events.find((event) => event.name === "bundleHistoryRecorded");
+    const issued = events.find((event) => event.name === "settlementReceiptIssued");
+    assert.equal(recorded.data.settlementIndex.toNumber(), 4);
+    assert.equal(issued.data.receipt.settlementIndex.toNumber(), 4);
+  });
+
+  it("Leaves the count alone when a settlement fails", async () => {
+    try {
+      await settle(13, 100_000000);
+      assert.fail("Should have failed with InsufficientFunds");
+    } catch (err) {
+      assert.include(err.toString(), "InsufficientFunds");
+    }
+    assert.equal(await settlementCount(), 4);
+  });
+});
@@ -0,0 +1,118 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import {
+  AttestationRole,
+  SettlementDeadline,
+  createAttestationProof,
+} from "./attestation-helper";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("attested settlement deadlines", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 1_000000;
+  let fixture: EscrowFixture;
+
+  const settleBefore = async (nonce: number, deadline: SettlementDeadline) => {
+    const bundleId = `deadline-${nonce}`;
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      nonce,
+      undefined,
+      deadline
+    );
+    return program.methods
+      .settleOfflinePayment(
+        new anchor.BN(AMOUNT),
+        new anchor.BN(nonce),
+        bundleId,
+        { payerProof, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  const expectDeadlinePassed = async (promise: Promise<unknown>) => {
+    try {
+      await promise;
+      assert.fail("Should have failed with SettlementDeadlinePassed");
+    } catch (err) {
+      assert.include(err.toString(), "SettlementDeadlinePassed");
+    }
+  };
+
+  const slotDeadline = (slot: number): SettlementDeadline => ({
+    slot: { 0: new anchor.BN(slot) },
+  });
+  const timestampDeadline = (ts: number): SettlementDeadline => ({
+    timestamp: { 0: new anchor.BN(ts) },
+  });
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+  });
+
+  it("Rejects a slot deadline one slot behind the current slot", async () => {
+    const slot = await provider.connection.getSlot("processed");
+    await expectDeadlinePassed(settleBefore(1, slotDeadline(slot - 1)));
+  });
+
+  it("Accepts a slot deadline that has not been reached", async () => {
+    const slot = await provider.connection.getSlot("processed");
+    await settleBefore(2, slotDeadline(slot + 150));
+  });
+
+  it("Rejects a timestamp deadline one second in the past", async () => {
+    const slot = await provider.connection.getSlot("processed");
+    const blockTime = await provider.connection.getBlockTime(slot);
+    await expectDeadlinePassed(
+      settleBefore(3, timestampDeadline(blockTime - 1))
+    );
+  });
+
+  it("Accepts a timestamp deadline in the future", async () => {
+    await settleBefore(4, timestampDeadline(Math.floor(Date.now() / 1000) + 600));
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.lastNonce.toNumber(), 4);
+  });
+
+  it("Rejects a proof whose deadline was changed after signing", async () => {
+    const slot = await provider.connection.getSlot("processed");
+    const bundleId = "deadline-5";
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      5,
+      undefined,
+      slotDeadline(slot + 10)
+    );
+    try {
+      await program.methods
+        .settleOfflinePayment(new anchor.BN(AMOUNT), new anchor.BN(5), bundleId, {
+          payerProof: { ...payerProof, deadline: slotDeadline(slot + 10_000) },
+          merchantProof: null,
+        })
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc();
+      assert.fail("Should have failed with InvalidAttestation");
+    } catch (err) {
+      assert.include(err.toString(), "InvalidAttestation");
+    }
+  });
+});
@@ -0,0 +1,107 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, settleAccounts } from "./fixtures";
+
+describe("settlement events", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  let fixture: EscrowFixture;
+
+  const eventsOf = async (signature: string) => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    return Array.from(parser.parseLogs(tx.meta.logMessages));
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 40_000000);
+  });
+
+  it("PaymentSettled carries the post-settlement balance and total spent", async () => {
+    for (const [nonce, amount] of [
+      [1, 3_000000],
+      [2, 5_000000],
+    ]) {
+      const signature = await program.methods
+        .settleOfflinePayment(
+          new anchor.BN(amount),
+          new anchor.BN(nonce),
+          `events-${nonce}`,
+          { payerProof: null, merchantProof: null }
+        )
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc({ commitment: "confirmed" });
+
+      const settled = (await eventsOf(signature)).find(
+        (event) => event.name === "paymentSettled"
+      );
+      const escrow = await program.account.offlineEscrowAccount.fetch(
+        fixture.escrowPDA
+      );
+      assert.equal(
+        settled.data.escrowBalance.toString(),
+        escrow.escrowBalance.toString()
+      );
+      assert.equal(
+        settled.data.totalSpent.toString(),
+        escrow.totalSpent.toString()
+      );
+    }
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.escrowBalance.toNumber(), 32_000000);
+    assert.equal(escrow.totalSpent.toNumber(), 8_000000);
+  });
+
+  it("PaymentSettled names the merchant token account and the vault", async () => {
+    const signature = await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(3),
+        "events-3",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+
+    const settled = (await eventsOf(signature)).find(
+      (event) => event.name === "paymentSettled"
+    );
+    assert.ok(settled.data.merchantTokenAccount.equals(fixture.merchantTokenAccount));
+    assert.ok(settled.data.escrowTokenAccount.equals(fixture.escrowTokenAccount));
+  });
+
+  it("Funding and withdrawal events name the vault", async () => {
+    for (const [method, name] of [
+      ["fundEscrow", "escrowFunded"],
+      ["withdrawEscrow", "escrowWithdrawn"],
+    ] as const) {
+      const signature = await program.methods[method](new anchor.BN(1_000000))
+        .accountsPartial({
+          escrowAccount: fixture.escrowPDA,
+          owner: fixture.owner.publicKey,
+          ownerTokenAccount: fixture.ownerTokenAccount,
+          escrowTokenAccount: fixture.escrowTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .signers([fixture.owner])
+        .rpc({ commitment: "confirmed" });
+
+      const event = (await eventsOf(signature)).find((e) => e.name === name);
+      assert.ok(event.data.escrowTokenAccount.equals(fixture.escrowTokenAccount));
+    }
+  });
+});
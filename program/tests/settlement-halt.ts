@@ -0,0 +1,176 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("settlement circuit breaker", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const INCIDENT = 7;
+  // ProgramConfig::status bit
+  const SETTLEMENTS_HALTED = 1 << 0;
+  const reporter = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const settle = (nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(nonce),
+        `halt-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const escrowBalance = async () =>
+    (
+      await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)
+    ).escrowBalance.toNumber();
+
+  const expectHalted = async (promise: Promise<unknown>) => {
+    try {
+      await promise;
+      assert.fail("Should have failed with SettlementsHalted");
+    } catch (err) {
+      assert.include(err.toString(), "SettlementsHalted");
+    }
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    await airdrop(provider, reporter.publicKey);
+    await settle(1);
+
+    await program.methods
+      .haltSettlements(INCIDENT)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .resumeSettlements(0)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Records the halt and its reason", async () => {
+    const state = await program.account.programConfig.fetch(config);
+    assert.equal(state.status & SETTLEMENTS_HALTED, SETTLEMENTS_HALTED);
+    assert.equal(state.haltReason, INCIDENT);
+  });
+
+  it("Rejects single and batch settlements while halted", async () => {
+    await expectHalted(settle(2));
+    await expectHalted(
+      program.methods
+        .settleBatchBestEffort(
+          [
+            {
+              amount: new anchor.BN(1_000000),
+              payerNonce: new anchor.BN(2),
+              bundleId: "halt-batch",
+              evidence: { payerProof: null, merchantProof: null },
+            },
+          ],
+          false
+        )
+        .accountsPartial(settleAccounts(fixture))
+        .signers([fixture.owner])
+        .rpc()
+    );
+  });
+
+  it("Keeps funding and withdrawals open while halted", async () => {
+    const before = await escrowBalance();
+    await program.methods
+      .fundEscrow(new anchor.BN(5_000000))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+    await program.methods
+      .withdrawEscrow(new anchor.BN(10_000000))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc();
+    assert.equal(await escrowBalance(), before - 5_000000);
+  });
+
+  it("Keeps fraud reporting open while halted", async () => {
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, 9),
+      fixture.merchant.publicKey,
+      1_000000,
+      1
+    );
+    await program.methods
+      .reportFraudulentBundle(
+        "halt-1",
+        conflictingHash,
+        { duplicateBundle: {} },
+        evidence as any
+      )
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.fraudCount, 1);
+  });
+
+  it("Only the admin can halt", async () => {
+    try {
+      await program.methods
+        .haltSettlements(1)
+        .accountsPartial({ config, admin: reporter.publicKey })
+        .signers([reporter])
+        .rpc();
+      assert.fail("Should have failed with Unauthorized");
+    } catch (err) {
+      assert.include(err.toString(), "Unauthorized");
+    }
+  });
+
+  it("Settles again after resuming", async () => {
+    await program.methods
+      .resumeSettlements(INCIDENT)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await settle(2);
+  });
+});
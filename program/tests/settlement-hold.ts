@@ -0,0 +1,284 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, getAccount } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  fetchReturnData,
+  settleAccounts,
+} from "./fixtures";
+
+describe("settlement holds", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const HOUR = 3_600;
+  const AMOUNT = 2_000000;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  const arbiters = [Keypair.generate(), Keypair.generate()];
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const escrowBalance = async () =>
+    (await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)).escrowBalance.toNumber();
+
+  const setHoldPeriod = (seconds: number) =>
+    program.methods
+      .updateConfig({ settlementHoldPeriod: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  // The hold is keyed by the index the next settlement will get
+  const nextHold = async () => {
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    const [hold] = PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("settlement_hold"),
+        fixture.escrowPDA.toBuffer(),
+        escrow.settlementCount.addn(1).toArrayLike(Buffer, "le", 8),
+      ],
+      program.programId
+    );
+    const vault = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      hold,
+      Keypair.generate()
+    );
+    return { hold, vault };
+  };
+
+  const settle = (nonce: number, hold: { hold: PublicKey; vault: PublicKey } | null) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(AMOUNT), new anchor.BN(nonce), `hold-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        settlementHold: hold?.hold ?? null,
+        holdVault: hold?.vault ?? null,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  // Sent for real rather than simulated, to show it writes nothing
+  const preflight = (nonce: number, hold: { hold: PublicKey; vault: PublicKey } | null) =>
+    program.methods
+      .preflightSettlement(new anchor.BN(AMOUNT), new anchor.BN(nonce), `hold-${nonce}`, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        settlementHold: hold?.hold ?? null,
+        holdVault: hold?.vault ?? null,
+      })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+
+  const release = (hold: { hold: PublicKey; vault: PublicKey }) =>
+    program.methods
+      .releaseHeldFunds()
+      .accountsPartial({
+        settlementHold: hold.hold,
+        holdVault: hold.vault,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        rentPayer: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .rpc();
+
+  const dispute = (hold: { hold: PublicKey; vault: PublicKey }) =>
+    program.methods
+      .disputeHeldSettlement()
+      .accountsPartial({
+        settlementHold: hold.hold,
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+      })
+      .signers([fixture.owner])
+      .rpc();
+
+  const resolve = (
+    hold: { hold: PublicKey; vault: PublicKey },
+    outcome: object,
+    cosigners: Keypair[]
+  ) =>
+    program.methods
+      .resolveHeldSettlement(outcome as any)
+      .accountsPartial({
+        settlementHold: hold.hold,
+        holdVault: hold.vault,
+        escrowAccount: fixture.escrowPDA,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        merchantTokenAccount: fixture.merchantTokenAccount,
+        resolver: provider.wallet.publicKey,
+        config,
+        rentPayer: provider.wallet.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .remainingAccounts(
+        cosigners.map((arbiter) => ({ pubkey: arbiter.publicKey, isSigner: true, isWritable: false }))
+      )
+      .signers(cosigners)
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    await program.methods
+      .setArbiters(
+        arbiters.map((arbiter) => arbiter.publicKey),
+        2
+      )
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await setHoldPeriod(0);
+    await program.methods
+      .setArbiters([], 0)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Bounds the hold period", async () => {
+    await expectError(setHoldPeriod(31 * 24 * HOUR), "InvalidConfig");
+    await expectError(setHoldPeriod(-1), "InvalidConfig");
+  });
+
+  it("Refuses to create holds while settlements pay directly", async () => {
+    await expectError(settle(1, await nextHold()), "InvalidSettlementHold");
+  });
+
+  describe("within the hold period", () => {
+    let held: { hold: PublicKey; vault: PublicKey };
+
+    before(async () => {
+      await setHoldPeriod(HOUR);
+    });
+
+    it("Requires a hold for every settlement", async () => {
+      await expectError(settle(1, null), "SettlementHoldRequired");
+      await expectError(
+        program.methods
+          .settleBatchBestEffort(
+            [
+              {
+                amount: new anchor.BN(AMOUNT),
+                payerNonce: new anchor.BN(1),
+                bundleId: "hold-batch",
+                evidence: { payerProof: null, merchantProof: null },
+              },
+            ],
+            false
+          )
+          .accountsPartial(settleAccounts(fixture))
+          .signers([fixture.owner])
+          .rpc(),
+        "SettlementHoldUnsupported"
+      );
+    });
+
+    it("Preflights without creating the hold the settlement needs", async () => {
+      held = await nextHold();
+      await expectError(preflight(1, held), "InvalidSettlementHold");
+
+      const result = program.coder.types.decode(
+        "settlementPreflight",
+        await fetchReturnData(provider, await preflight(1, null))
+      );
+      assert.deepEqual(result.check, { passed: {} });
+      assert.isNull(await provider.connection.getAccountInfo(held.hold));
+    });
+
+    it("Pays the settlement into its hold instead of to the merchant", async () => {
+      const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+      await settle(1, held);
+
+      assert.equal(await balanceOf(fixture.merchantTokenAccount), merchantBefore);
+      assert.equal(await balanceOf(held.vault), AMOUNT);
+      const hold = await program.account.settlementHold.fetch(held.hold);
+      assert.ok(hold.merchant.equals(fixture.merchant.publicKey));
+      assert.equal(hold.amount.toNumber(), AMOUNT);
+      assert.equal(hold.releaseAt.toNumber() - hold.heldAt.toNumber(), HOUR);
+    });
+
+    it("Keeps the funds from the merchant until the period ends", async () => {
+      await expectError(release(held), "SettlementHoldActive");
+    });
+
+    it("Freezes the hold when the payer disputes", async () => {
+      await expectError(resolve(held, { forPayer: {} }, arbiters), "SettlementHoldNotDisputed");
+      await dispute(held);
+      await expectError(dispute(held), "SettlementHoldDisputed");
+      await expectError(release(held), "SettlementHoldDisputed");
+      assert.equal(await balanceOf(held.vault), AMOUNT);
+    });
+
+    it("Refunds the payer's escrow when the arbiters rule for them", async () => {
+      await expectError(resolve(held, { forPayer: {} }, [arbiters[0]]), "ArbiterQuorumNotMet");
+
+      const before = await escrowBalance();
+      await resolve(held, { forPayer: {} }, arbiters);
+
+      assert.equal(await escrowBalance(), before + AMOUNT);
+      assert.isNull(await provider.connection.getAccountInfo(held.hold));
+      assert.isNull(await provider.connection.getAccountInfo(held.vault));
+    });
+
+    it("Pays the merchant when the arbiters rule for them", async () => {
+      held = await nextHold();
+      await settle(2, held);
+      await dispute(held);
+
+      const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+      await resolve(held, { forMerchant: {} }, arbiters);
+      assert.equal(await balanceOf(fixture.merchantTokenAccount), merchantBefore + AMOUNT);
+      assert.isNull(await provider.connection.getAccountInfo(held.hold));
+    });
+  });
+
+  describe("after the hold period", () => {
+    let held: { hold: PublicKey; vault: PublicKey };
+
+    before(async () => {
+      await setHoldPeriod(1);
+      held = await nextHold();
+      await settle(3, held);
+      await new Promise((resolve) => setTimeout(resolve, 3000));
+    });
+
+    it("No longer lets the payer dispute", async () => {
+      await expectError(dispute(held), "SettlementHoldReleasable");
+    });
+
+    it("Releases the funds to the merchant", async () => {
+      const merchantBefore = await balanceOf(fixture.merchantTokenAccount);
+      await release(held);
+
+      assert.equal(await balanceOf(fixture.merchantTokenAccount), merchantBefore + AMOUNT);
+      assert.isNull(await provider.connection.getAccountInfo(held.hold));
+    });
+  });
+});
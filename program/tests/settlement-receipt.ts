@@ -0,0 +1,106 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  fetchReturnData,
+  settleAccounts,
+} from "./fixtures";
+
+describe("settlement receipts", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // bundle_hash [32] | amount u64 | merchant [32] | payer [32] | nonce u64 | slot u64
+  // | settlement_index u64 | fee u64 | escrow_balance u64
+  const RECEIPT_LEN = 32 + 8 + 32 + 32 + 8 + 8 + 8 + 8 + 8;
+  let fixture: EscrowFixture;
+  let signature: string;
+  let receipt: Buffer;
+
+  const u64 = (value: number | bigint) => {
+    const out = Buffer.alloc(8);
+    out.writeBigUInt64LE(BigInt(value));
+    return out;
+  };
+
+  before(async () => {
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+    signature = await program.methods
+      .settleOfflinePayment(
+        new anchor.BN(2_500000),
+        new anchor.BN(7),
+        "receipt-7",
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+    receipt = await fetchReturnData(provider, signature);
+  });
+
+  it("Returns the receipt in its fixed little-endian layout", async () => {
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    const record = registry.bundleHistory[registry.bundleHistory.length - 1];
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+
+    const expected = Buffer.concat([
+      Buffer.from(record.bundleHash),
+      u64(2_500000),
+      fixture.merchant.publicKey.toBuffer(),
+      fixture.owner.publicKey.toBuffer(),
+      u64(7),
+      u64(tx.slot),
+      u64(1),
+      // Fees are off in the shared test config
+      u64(0),
+      u64(escrow.escrowBalance.toNumber()),
+    ]);
+    assert.equal(receipt.length, RECEIPT_LEN);
+    assert.ok(receipt.equals(expected));
+  });
+
+  it("Decodes through the IDL type and matches the emitted event", async () => {
+    const decoded = program.coder.types.decode("settlementReceipt", receipt);
+    assert.equal(decoded.amount.toNumber(), 2_500000);
+    assert.equal(decoded.nonce.toNumber(), 7);
+    assert.ok(decoded.merchant.equals(fixture.merchant.publicKey));
+    assert.ok(decoded.payer.equals(fixture.owner.publicKey));
+
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    const parser = new anchor.EventParser(program.programId, program.coder);
+    const issued = Array.from(parser.parseLogs(tx.meta.logMessages)).find(
+      (event) => event.name === "settlementReceiptIssued"
+    );
+    assert.deepEqual(
+      Buffer.from(issued.data.receipt.bundleHash),
+      receipt.subarray(0, 32)
+    );
+    assert.equal(issued.data.receipt.slot.toString(), decoded.slot.toString());
+  });
+
+  it("Leaves the receipt in return data under the program's id for CPI callers", async () => {
+    // What a calling program gets back from `get_return_data` after the CPI
+    const tx = await provider.connection.getTransaction(signature, {
+      commitment: "confirmed",
+      maxSupportedTransactionVersion: 0,
+    });
+    assert.equal(tx.meta.returnData.programId, program.programId.toBase58());
+
+    const decoded = program.coder.types.decode("settlementReceipt", receipt);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(decoded.escrowBalance.toString(), escrow.escrowBalance.toString());
+    assert.equal(decoded.escrowBalance.toNumber(), 20_000000 - 2_500000);
+  });
+});
@@ -17,6 +17,7 @@ import {
   getAccount,
 } from "@solana/spl-token";
 import { assert } from "chai";
+import { ensureConfig } from "./fixtures";
 
 describe("beam-simple", () => {
   const provider = anchor.AnchorProvider.env();
@@ -35,6 +36,7 @@ describe("beam-simple", () => {
 
   before(async () => {
     payer = (provider.wallet as anchor.Wallet).payer;
+    await ensureConfig(provider, program);
 
     // Create USDC mint
     mint = await createMint(
@@ -129,6 +131,7 @@ describe("beam-simple", () => {
       .accounts({
         escrowAccount: escrowPDA,
         owner: payer.publicKey,
+        rentPayer: payer.publicKey,
         ownerTokenAccount: payerTokenAccount,
         escrowTokenAccount: escrowTokenAccount.publicKey,
         tokenProgram: TOKEN_PROGRAM_ID,
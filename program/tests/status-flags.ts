@@ -0,0 +1,77 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { createEscrowFixture, ensureConfig } from "./fixtures";
+
+describe("status flags", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // ProgramConfig::status bits
+  const SETTLEMENTS_HALTED = 1 << 0;
+  const BLOCK_ZERO_REPUTATION = 1 << 1;
+  // OfflineEscrowAccount::status bits
+  const REPUTATION_MIGRATED = 1 << 0;
+  let config: PublicKey;
+
+  const admin = () => ({ config, admin: provider.wallet.publicKey });
+
+  const status = async () =>
+    (await program.account.programConfig.fetch(config)).status;
+
+  const halt = (on: boolean) =>
+    (on
+      ? program.methods.haltSettlements(1)
+      : program.methods.resumeSettlements(1)
+    )
+      .accountsPartial(admin())
+      .rpc();
+
+  const blockZeroReputation = (on: boolean) =>
+    program.methods
+      .updateConfig({ blockZeroReputation: on })
+      .accountsPartial(admin())
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+  });
+
+  after(async () => {
+    await halt(false);
+    await blockZeroReputation(false);
+  });
+
+  it("Sets and clears each config flag independently", async () => {
+    const base = (await status()) & ~(SETTLEMENTS_HALTED | BLOCK_ZERO_REPUTATION);
+
+    await halt(true);
+    assert.equal(await status(), base | SETTLEMENTS_HALTED);
+
+    await blockZeroReputation(true);
+    assert.equal(await status(), base | SETTLEMENTS_HALTED | BLOCK_ZERO_REPUTATION);
+
+    await halt(false);
+    assert.equal(await status(), base | BLOCK_ZERO_REPUTATION);
+
+    await halt(true);
+    await blockZeroReputation(false);
+    assert.equal(await status(), base | SETTLEMENTS_HALTED);
+
+    await halt(false);
+    assert.equal(await status(), base);
+  });
+
+  it("Marks new escrows as carrying a migrated reputation", async () => {
+    const fixture = await createEscrowFixture(provider, program, 1_000000);
+    const escrow = await program.account.offlineEscrowAccount.fetch(
+      fixture.escrowPDA
+    );
+    assert.equal(escrow.status, REPUTATION_MIGRATED);
+    assert.isFalse(escrow.legacyReputationMigrated);
+  });
+});
@@ -0,0 +1,97 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("strict vault checks", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const STRICT_VAULT_CHECKS = 1 << 2;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let nonce = 0;
+
+  const setStrict = (on: boolean) =>
+    program.methods
+      .updateConfig({ strictVaultChecks: on })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const computeUnits = async (sig: string) =>
+    (
+      await provider.connection.getTransaction(sig, {
+        commitment: "confirmed",
+        maxSupportedTransactionVersion: 0,
+      })
+    ).meta.computeUnitsConsumed;
+
+  const settle = () => {
+    nonce += 1;
+    return program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(nonce),
+        `strict-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+  };
+
+  const escrowTransfer = (method: "fundEscrow" | "withdrawEscrow") =>
+    program.methods[method](new anchor.BN(1_000000))
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        owner: fixture.owner.publicKey,
+        ownerTokenAccount: fixture.ownerTokenAccount,
+        escrowTokenAccount: fixture.escrowTokenAccount,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([fixture.owner])
+      .rpc({ commitment: "confirmed" });
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+  });
+
+  after(async () => {
+    await setStrict(false);
+  });
+
+  it("Sets the strict flag through the config", async () => {
+    await setStrict(true);
+    const status = (await program.account.programConfig.fetch(config)).status;
+    assert.equal(status & STRICT_VAULT_CHECKS, STRICT_VAULT_CHECKS);
+  });
+
+  it("Passes funding, settlement and withdrawal when the vault matches", async () => {
+    await escrowTransfer("fundEscrow");
+    await settle();
+    await escrowTransfer("withdrawEscrow");
+
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.escrowBalance.toNumber(), 49_000000);
+  });
+
+  it("Reports the compute cost of the reloads", async () => {
+    const strict = await computeUnits(await settle());
+    await setStrict(false);
+    const relaxed = await computeUnits(await settle());
+
+    console.log(`      settlement CU: ${relaxed} relaxed, ${strict} strict`);
+    assert.isAbove(strict, relaxed);
+  });
+});
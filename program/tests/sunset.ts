@@ -0,0 +1,80 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID } from "@solana/spl-token";
+import { assert } from "chai";
+import { EscrowFixture, createEscrowFixture, ensureConfig } from "./fixtures";
+
+describe("program sunset", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const SUNSET_TIMELOCK = 14 * 86_400;
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const admin = () => ({ config, admin: provider.wallet.publicKey });
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 5_000000);
+  });
+
+  after(async () => {
+    if ((await program.account.programConfig.fetch(config)).sunsetAt.toNumber()) {
+      await program.methods.cancelSunset().accountsPartial(admin()).rpc();
+    }
+  });
+
+  it("Declares a sunset behind the timelock", async () => {
+    const before = Math.floor(Date.now() / 1000);
+    await program.methods.declareSunset().accountsPartial(admin()).rpc();
+    const sunsetAt = (await program.account.programConfig.fetch(config)).sunsetAt;
+    assert.isAtLeast(sunsetAt.toNumber(), before + SUNSET_TIMELOCK - 60);
+
+    await expectError(
+      program.methods.declareSunset().accountsPartial(admin()).rpc(),
+      "SunsetAlreadyDeclared"
+    );
+  });
+
+  it("Keeps stake locked until the sunset takes effect", async () => {
+    await expectError(
+      program.methods
+        .emergencyUnlockStake()
+        .accountsPartial({
+          ...admin(),
+          escrowAccount: fixture.escrowPDA,
+          escrowTokenAccount: fixture.escrowTokenAccount,
+          ownerTokenAccount: fixture.ownerTokenAccount,
+          tokenProgram: TOKEN_PROGRAM_ID,
+        })
+        .rpc(),
+      "SunsetNotActive"
+    );
+  });
+
+  it("Cancels a declared sunset", async () => {
+    await program.methods.cancelSunset().accountsPartial(admin()).rpc();
+    assert.equal(
+      (await program.account.programConfig.fetch(config)).sunsetAt.toNumber(),
+      0
+    );
+    await expectError(
+      program.methods.cancelSunset().accountsPartial(admin()).rpc(),
+      "SunsetNotActive"
+    );
+  });
+});
@@ -0,0 +1,327 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import {
+  Keypair,
+  PublicKey,
+  SystemProgram,
+  Transaction,
+  sendAndConfirmTransaction,
+} from "@solana/web3.js";
+import {
+  ExtensionType,
+  TOKEN_2022_PROGRAM_ID,
+  createAccount,
+  createInitializeMintInstruction,
+  createInitializeTransferFeeConfigInstruction,
+  getAccount,
+  getMintLen,
+  getOrCreateAssociatedTokenAccount,
+  mintTo,
+} from "@solana/spl-token";
+import { assert } from "chai";
+import {
+  airdrop,
+  ensureConfig,
+  findEscrowPDA,
+  findNonceRegistryPDA,
+} from "./fixtures";
+
+describe("Token-2022 transfer fees", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  // 1% with a cap high enough never to apply here
+  const FEE_BPS = 100;
+  const MAX_FEE = 1_000_000000;
+  const owner = Keypair.generate();
+  const merchant = Keypair.generate();
+  let config: PublicKey;
+  let mint: PublicKey;
+  let ownerTokenAccount: PublicKey;
+  let merchantTokenAccount: PublicKey;
+  let escrowPDA: PublicKey;
+  let escrowTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number(
+      (await getAccount(provider.connection, account, undefined, TOKEN_2022_PROGRAM_ID))
+        .amount
+    );
+
+  const escrow = () => program.account.offlineEscrowAccount.fetch(escrowPDA);
+
+  const setFeePayer = (payer: object) =>
+    program.methods
+      .updateConfig({ transferFeePayer: payer as any })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settle = (amount: number, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(amount),
+        new anchor.BN(nonce),
+        `transfer-fee-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial({
+        escrowAccount: escrowPDA,
+        nonceRegistry: findNonceRegistryPDA(program, owner.publicKey),
+        owner: owner.publicKey,
+        payer: owner.publicKey,
+        merchant: merchant.publicKey,
+        escrowTokenAccount,
+        merchantTokenAccount,
+        mint,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+  // The vault must always hold exactly the booked balance
+  const assertNoDrift = async () =>
+    assert.equal(
+      await balanceOf(escrowTokenAccount),
+      (await escrow()).escrowBalance.toNumber()
+    );
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    await airdrop(provider, owner.publicKey);
+
+    const mintKeypair = Keypair.generate();
+    mint = mintKeypair.publicKey;
+    const mintLen = getMintLen([ExtensionType.TransferFeeConfig]);
+    await sendAndConfirmTransaction(
+      provider.connection,
+      new Transaction().add(
+        SystemProgram.createAccount({
+          fromPubkey: owner.publicKey,
+          newAccountPubkey: mint,
+          space: mintLen,
+          lamports:
+            await provider.connection.getMinimumBalanceForRentExemption(mintLen),
+          programId: TOKEN_2022_PROGRAM_ID,
+        }),
+        createInitializeTransferFeeConfigInstruction(
+          mint,
+          owner.publicKey,
+          owner.publicKey,
+          FEE_BPS,
+          BigInt(MAX_FEE),
+          TOKEN_2022_PROGRAM_ID
+        ),
+        createInitializeMintInstruction(mint, 6, owner.publicKey, null, TOKEN_2022_PROGRAM_ID)
+      ),
+      [owner, mintKeypair]
+    );
+
+    const ata = async (holder: PublicKey) =>
+      (
+        await getOrCreateAssociatedTokenAccount(
+          provider.connection,
+          owner,
+          mint,
+          holder,
+          false,
+          undefined,
+          undefined,
+          TOKEN_2022_PROGRAM_ID
+        )
+      ).address;
+    ownerTokenAccount = await ata(owner.publicKey);
+    merchantTokenAccount = await ata(merchant.publicKey);
+    await mintTo(
+      provider.connection,
+      owner,
+      mint,
+      ownerTokenAccount,
+      owner,
+      100_000000,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+
+    escrowPDA = findEscrowPDA(program, owner.publicKey);
+    escrowTokenAccount = await createAccount(
+      provider.connection,
+      owner,
+      mint,
+      escrowPDA,
+      Keypair.generate(),
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await program.methods
+      .initializeNonceRegistry()
+      .accountsPartial({
+        owner: owner.publicKey,
+        rentPayer: owner.publicKey,
+        nonceRegistry: findNonceRegistryPDA(program, owner.publicKey),
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+  });
+
+  after(async () => {
+    await setFeePayer({ merchant: {} });
+  });
+
+  it("Credits only the amount that arrives on funding", async () => {
+    await program.methods
+      .initializeEscrow(new anchor.BN(10_000000))
+      .accountsPartial({
+        escrowAccount: escrowPDA,
+        owner: owner.publicKey,
+        rentPayer: owner.publicKey,
+        ownerTokenAccount,
+        escrowTokenAccount,
+        mint,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([owner])
+      .rpc();
+
+    const state = await escrow();
+    assert.equal(state.escrowBalance.toNumber(), 9_900000);
+    assert.equal(state.totalTransferFees.toNumber(), 100000);
+    await assertNoDrift();
+  });
+
+  it("Takes the fee out of the merchant's payout by default", async () => {
+    await settle(1_000000, 1);
+    assert.equal(await balanceOf(merchantTokenAccount), 990000);
+    assert.equal((await escrow()).escrowBalance.toNumber(), 8_900000);
+    await assertNoDrift();
+  });
+
+  it("Grosses the payout up from the escrow when the payer absorbs the fee", async () => {
+    await setFeePayer({ payer: {} });
+    const before = await escrow();
+    const merchantBefore = await balanceOf(merchantTokenAccount);
+
+    await settle(1_000000, 2);
+
+    assert.equal((await balanceOf(merchantTokenAccount)) - merchantBefore, 1_000000);
+    const after = await escrow();
+    const absorbed = after.totalTransferFees.sub(before.totalTransferFees).toNumber();
+    assert.isAbove(absorbed, 0);
+    assert.equal(
+      before.escrowBalance.toNumber() - after.escrowBalance.toNumber(),
+      1_000000 + absorbed
+    );
+    await assertNoDrift();
+  });
+
+  it("Draws a guarantee shortfall gross of the fee from the guarantor", async () => {
+    await setFeePayer({ merchant: {} });
+    const guarantor = Keypair.generate();
+    await airdrop(provider, guarantor.publicKey);
+    const guarantorEscrow = findEscrowPDA(program, guarantor.publicKey);
+    const guarantorVault = await createAccount(
+      provider.connection,
+      guarantor,
+      mint,
+      guarantorEscrow,
+      Keypair.generate(),
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    const guarantorTokenAccount = (
+      await getOrCreateAssociatedTokenAccount(
+        provider.connection,
+        guarantor,
+        mint,
+        guarantor.publicKey,
+        false,
+        undefined,
+        undefined,
+        TOKEN_2022_PROGRAM_ID
+      )
+    ).address;
+    await mintTo(
+      provider.connection,
+      owner,
+      mint,
+      guarantorTokenAccount,
+      owner,
+      20_000000,
+      [],
+      undefined,
+      TOKEN_2022_PROGRAM_ID
+    );
+    await program.methods
+      .initializeEscrow(new anchor.BN(20_000000))
+      .accountsPartial({
+        escrowAccount: guarantorEscrow,
+        owner: guarantor.publicKey,
+        rentPayer: guarantor.publicKey,
+        ownerTokenAccount: guarantorTokenAccount,
+        escrowTokenAccount: guarantorVault,
+        mint,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guarantor])
+      .rpc();
+    const [guarantee] = PublicKey.findProgramAddressSync(
+      [Buffer.from("guarantee"), guarantor.publicKey.toBuffer(), owner.publicKey.toBuffer()],
+      program.programId
+    );
+    await program.methods
+      .grantGuarantee(new anchor.BN(5_000000))
+      .accountsPartial({
+        guarantee,
+        guarantorEscrow,
+        guarantor: guarantor.publicKey,
+        payer: owner.publicKey,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([guarantor])
+      .rpc();
+
+    const shortfall = 1_000000;
+    const amount = (await escrow()).escrowBalance.toNumber() + shortfall;
+    const backerBefore = await program.account.offlineEscrowAccount.fetch(guarantorEscrow);
+    await program.methods
+      .settleOfflinePayment(new anchor.BN(amount), new anchor.BN(3), "transfer-fee-3", {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial({
+        escrowAccount: escrowPDA,
+        nonceRegistry: findNonceRegistryPDA(program, owner.publicKey),
+        owner: owner.publicKey,
+        payer: owner.publicKey,
+        merchant: merchant.publicKey,
+        escrowTokenAccount,
+        merchantTokenAccount,
+        guarantee,
+        guarantorEscrow,
+        guarantorTokenAccount: guarantorVault,
+        mint,
+        tokenProgram: TOKEN_2022_PROGRAM_ID,
+      })
+      .signers([owner])
+      .rpc();
+
+    // The whole shortfall reached the payer; the guarantor also paid the fee on it
+    assert.equal((await escrow()).escrowBalance.toNumber(), 0);
+    await assertNoDrift();
+    const backer = await program.account.offlineEscrowAccount.fetch(guarantorEscrow);
+    const fee = backer.totalTransferFees.sub(backerBefore.totalTransferFees).toNumber();
+    assert.isAbove(fee, 0);
+    assert.equal(
+      backerBefore.escrowBalance.toNumber() - backer.escrowBalance.toNumber(),
+      shortfall + fee
+    );
+    assert.equal(await balanceOf(guarantorVault), backer.escrowBalance.toNumber());
+    assert.equal((await program.account.guarantee.fetch(guarantee)).drawn.toNumber(), shortfall);
+  });
+});
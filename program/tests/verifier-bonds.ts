@@ -0,0 +1,316 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import { TOKEN_PROGRAM_ID, createAccount, getAccount, mintTo } from "@solana/spl-token";
+import { keccak_256 } from "@noble/hashes/sha3";
+import * as crypto from "crypto";
+import { assert } from "chai";
+import {
+  AttestationRole,
+  createAttestationProof,
+  signConflictingBundle,
+} from "./attestation-helper";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("verifier bonds", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 1_000000;
+  const MIN_BOND = 10_000000;
+  // Half of the bond per attested double spend
+  const SLASH_BPS = 5000;
+  const verifierSeed = Uint8Array.from(crypto.randomBytes(32));
+  const verifier = Keypair.fromSeed(verifierSeed);
+  const reporter = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let verifierBond: PublicKey;
+  let bondVault: PublicKey;
+  let verifierTokenAccount: PublicKey;
+  let treasuryTokenAccount: PublicKey;
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const updateConfig = (update: Record<string, unknown>) =>
+    program.methods
+      .updateConfig(update as any)
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const topUp = (amount: number) =>
+    program.methods
+      .topUpVerifierBond(new anchor.BN(amount))
+      .accountsPartial({
+        verifierBond,
+        bondVault,
+        verifierTokenAccount,
+        verifier: verifier.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([verifier])
+      .rpc();
+
+  const setFallback = (withBond: boolean) =>
+    program.methods
+      .setFallbackVerifier(verifier.publicKey, new anchor.BN(AMOUNT))
+      .accountsPartial({
+        config,
+        admin: provider.wallet.publicKey,
+        verifierBond: withBond ? verifierBond : null,
+      })
+      .rpc();
+
+  const settle = (bundleId: string, nonce: number) =>
+    program.methods
+      .settleOfflinePayment(new anchor.BN(AMOUNT), new anchor.BN(nonce), bundleId, {
+        payerProof: null,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const report = async (bundleId: string, nonce: number) => {
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, nonce),
+      fixture.merchant.publicKey,
+      AMOUNT,
+      nonce
+    );
+    await program.methods
+      .reportFraudulentBundle(bundleId, conflictingHash, { duplicateBundle: {} }, evidence as any)
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+  };
+
+  const attestation = async (bundleId: string, nonce: number, signer: Keypair) => ({
+    proof: await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      AMOUNT,
+      nonce,
+      signer.secretKey.slice(0, 32)
+    ),
+    role: { payer: {} },
+    courier: null,
+    deviceIdHash: null,
+    conditionId: null,
+  });
+
+  const slashMarkerOf = (bundleId: string) =>
+    PublicKey.findProgramAddressSync(
+      [
+        Buffer.from("verifier_slash"),
+        verifier.publicKey.toBuffer(),
+        Buffer.from(keccak_256(Buffer.from(bundleId))),
+      ],
+      program.programId
+    )[0];
+
+  const slash = async (bundleId: string, signer: Keypair, nonce: number) =>
+    program.methods
+      .slashVerifier(bundleId, (await attestation(bundleId, nonce, signer)) as any)
+      .accountsPartial({
+        verifierBond,
+        bondVault,
+        nonceRegistry: fixture.nonceRegistry,
+        verifierSlash: slashMarkerOf(bundleId),
+        treasuryTokenAccount,
+        reporter: reporter.publicKey,
+        config,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([reporter])
+      .rpc();
+
+  const withdraw = (destination: PublicKey) =>
+    program.methods
+      .withdrawVerifierBond()
+      .accountsPartial({
+        verifierBond,
+        bondVault,
+        destinationTokenAccount: destination,
+        verifier: verifier.publicKey,
+        config,
+        tokenProgram: TOKEN_PROGRAM_ID,
+      })
+      .signers([verifier])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    await airdrop(provider, verifier.publicKey);
+    await airdrop(provider, reporter.publicKey);
+    [verifierBond] = PublicKey.findProgramAddressSync(
+      [Buffer.from("verifier_bond"), verifier.publicKey.toBuffer()],
+      program.programId
+    );
+    bondVault = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      verifierBond,
+      Keypair.generate()
+    );
+    verifierTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      verifier.publicKey,
+      Keypair.generate()
+    );
+    await mintTo(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      verifierTokenAccount,
+      fixture.owner,
+      2 * MIN_BOND
+    );
+    treasuryTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      provider.wallet.publicKey,
+      Keypair.generate()
+    );
+    await updateConfig({
+      treasury: provider.wallet.publicKey,
+      minVerifierBond: new anchor.BN(MIN_BOND),
+      verifierSlashBps: SLASH_BPS,
+      verifierBondCooldown: new anchor.BN(3600),
+      fraudWithdrawalDelay: new anchor.BN(0),
+    });
+
+    await program.methods
+      .registerVerifierBond(new anchor.BN(MIN_BOND / 2))
+      .accountsPartial({
+        verifierBond,
+        bondVault,
+        verifierTokenAccount,
+        verifier: verifier.publicKey,
+        tokenProgram: TOKEN_PROGRAM_ID,
+        systemProgram: SystemProgram.programId,
+      })
+      .signers([verifier])
+      .rpc();
+  });
+
+  after(async () => {
+    await updateConfig({
+      minVerifierBond: new anchor.BN(0),
+      verifierSlashBps: 0,
+      verifierBondCooldown: new anchor.BN(0),
+    });
+  });
+
+  it("Makes the fallback key hold the minimum bond", async () => {
+    await expectError(setFallback(false), "VerifierBondRequired");
+    await expectError(setFallback(true), "VerifierBondRequired");
+
+    await topUp(MIN_BOND / 2);
+    const bond = await program.account.verifierBond.fetch(verifierBond);
+    assert.equal(bond.amount.toNumber(), MIN_BOND);
+    assert.equal(await balanceOf(bondVault), MIN_BOND);
+
+    await setFallback(true);
+    const updated = await program.account.programConfig.fetch(config);
+    assert.ok(updated.fallbackVerifier.equals(verifier.publicKey));
+  });
+
+  it("Slashes the verifier that attested a reported double spend", async () => {
+    await settle("bonded-1", 1);
+
+    // Nothing to answer for before the bundle is reported
+    await expectError(slash("bonded-1", verifier, 1), "FraudNotUpheld");
+
+    await report("bonded-1", 1);
+    await expectError(slash("bonded-1", Keypair.generate(), 1), "VerifierDidNotAttest");
+
+    const treasuryBefore = await balanceOf(treasuryTokenAccount);
+    await slash("bonded-1", verifier, 1);
+
+    const slashed = MIN_BOND * SLASH_BPS / 10_000;
+    assert.equal(await balanceOf(treasuryTokenAccount) - treasuryBefore, slashed);
+    const bond = await program.account.verifierBond.fetch(verifierBond);
+    assert.equal(bond.amount.toNumber(), MIN_BOND - slashed);
+    assert.equal(bond.slashed.toNumber(), slashed);
+    const marker = await program.account.verifierSlash.fetch(slashMarkerOf("bonded-1"));
+    assert.ok(marker.payer.equals(fixture.owner.publicKey));
+    assert.equal(marker.amount.toNumber(), slashed);
+
+    // Each bundle costs the verifier once
+    await expectError(slash("bonded-1", verifier, 1), "already in use");
+  });
+
+  it("Keeps a report inside its dispute window from slashing", async () => {
+    await updateConfig({ fraudWithdrawalDelay: new anchor.BN(3600) });
+    await settle("bonded-2", 2);
+    await report("bonded-2", 2);
+    await expectError(slash("bonded-2", verifier, 2), "FraudNotUpheld");
+    await updateConfig({ fraudWithdrawalDelay: new anchor.BN(0) });
+  });
+
+  it("Releases the bond only after the cooldown that follows deregistration", async () => {
+    const deregister = () =>
+      program.methods
+        .deregisterVerifierBond()
+        .accountsPartial({ verifierBond, verifier: verifier.publicKey, config })
+        .signers([verifier])
+        .rpc();
+
+    await expectError(deregister(), "VerifierStillActive");
+    await program.methods
+      .revokeFallbackVerifier()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await deregister();
+    await expectError(topUp(1), "VerifierDeregistered");
+
+    const destination = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      verifier.publicKey,
+      Keypair.generate()
+    );
+    await expectError(withdraw(destination), "VerifierBondLocked");
+
+    await updateConfig({ verifierBondCooldown: new anchor.BN(0) });
+    const remaining = (await program.account.verifierBond.fetch(verifierBond)).amount.toNumber();
+    await withdraw(destination);
+    assert.equal(await balanceOf(destination), remaining);
+    assert.isNull(await provider.connection.getAccountInfo(verifierBond));
+    assert.isNull(await provider.connection.getAccountInfo(bondVault));
+  });
+});
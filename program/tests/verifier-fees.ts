@@ -0,0 +1,221 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { createAccount, getAccount } from "@solana/spl-token";
+import * as crypto from "crypto";
+import { assert } from "chai";
+import {
+  AttestationRole,
+  createAttestationProof,
+  getTestVerifierPrivateKey,
+} from "./attestation-helper";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("verifier fees", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const AMOUNT = 10_000000;
+  // 1% protocol fee, of which 0.02 tokens goes to the verifier
+  const FEE = AMOUNT / 100;
+  const VERIFIER_FEE = 20000;
+  const PRIMARY_DOWN = 1;
+  const primary = Keypair.fromSeed(getTestVerifierPrivateKey());
+  const fallbackSeed = Uint8Array.from(crypto.randomBytes(32));
+  const fallback = Keypair.fromSeed(fallbackSeed);
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+  let treasuryTokenAccount: PublicKey;
+  const vaults = new Map<string, PublicKey>();
+
+  const balanceOf = async (account: PublicKey) =>
+    Number((await getAccount(provider.connection, account)).amount);
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const feeAccountOf = (verifier: PublicKey) =>
+    PublicKey.findProgramAddressSync(
+      [Buffer.from("verifier_fees"), verifier.toBuffer()],
+      program.programId
+    )[0];
+
+  const register = async (verifier: Keypair) => {
+    await airdrop(provider, verifier.publicKey);
+    const feeAccount = feeAccountOf(verifier.publicKey);
+    const feeVault = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      feeAccount,
+      Keypair.generate()
+    );
+    await program.methods
+      .registerVerifierFeeAccount()
+      .accountsPartial({ verifierFeeAccount: feeAccount, feeVault, verifier: verifier.publicKey })
+      .signers([verifier])
+      .rpc();
+    vaults.set(verifier.publicKey.toBase58(), feeVault);
+  };
+
+  const settle = async (
+    nonce: number,
+    verifier: Keypair,
+    signedBy: Keypair | null,
+    fallbackReason: number | null = null
+  ) => {
+    const bundleId = `verifier-fee-${nonce}`;
+    const payerProof = signedBy
+      ? await createAttestationProof(
+          AttestationRole.Payer,
+          bundleId,
+          fixture.owner.publicKey,
+          fixture.merchant.publicKey,
+          AMOUNT,
+          nonce,
+          signedBy.secretKey.slice(0, 32),
+          null,
+          null,
+          null,
+          fallbackReason
+        )
+      : null;
+    return program.methods
+      .settleOfflinePayment(new anchor.BN(AMOUNT), new anchor.BN(nonce), bundleId, {
+        payerProof,
+        merchantProof: null,
+      })
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        treasuryTokenAccount,
+        verifierFeeAccount: feeAccountOf(verifier.publicKey),
+        verifierFeeVault: vaults.get(verifier.publicKey.toBase58()),
+      })
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  const claim = (verifier: Keypair, destination: PublicKey) =>
+    program.methods
+      .claimVerifierFees()
+      .accountsPartial({
+        verifierFeeAccount: feeAccountOf(verifier.publicKey),
+        feeVault: vaults.get(verifier.publicKey.toBase58()),
+        destinationTokenAccount: destination,
+        verifier: verifier.publicKey,
+      })
+      .signers([verifier])
+      .rpc();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 100_000000);
+    treasuryTokenAccount = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      provider.wallet.publicKey,
+      Keypair.generate()
+    );
+    await register(primary);
+    await register(fallback);
+    await program.methods
+      .updateConfig({
+        feeBps: 100,
+        treasury: provider.wallet.publicKey,
+        verifierFee: new anchor.BN(VERIFIER_FEE),
+      })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  after(async () => {
+    await program.methods
+      .updateConfig({ feeBps: 0, verifierFee: new anchor.BN(0) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+  });
+
+  it("Pays the verifier whose proof backed the settlement out of the protocol fee", async () => {
+    const vault = vaults.get(primary.publicKey.toBase58());
+    const treasuryBefore = await balanceOf(treasuryTokenAccount);
+
+    await settle(1, primary, primary);
+
+    assert.equal(await balanceOf(vault), VERIFIER_FEE);
+    assert.equal(await balanceOf(treasuryTokenAccount) - treasuryBefore, FEE - VERIFIER_FEE);
+    const account = await program.account.verifierFeeAccount.fetch(feeAccountOf(primary.publicKey));
+    assert.equal(account.earned.toNumber(), VERIFIER_FEE);
+  });
+
+  it("Rejects fee accounts of keys that didn't sign the bundle", async () => {
+    await expectError(settle(2, primary, null), "InvalidVerifierFeeAccount");
+    await expectError(settle(2, fallback, primary), "InvalidVerifierFeeAccount");
+  });
+
+  it("Lets only the verifier sweep its fees", async () => {
+    const destination = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      primary.publicKey,
+      Keypair.generate()
+    );
+    await expectError(
+      program.methods
+        .claimVerifierFees()
+        .accountsPartial({
+          verifierFeeAccount: feeAccountOf(primary.publicKey),
+          feeVault: vaults.get(primary.publicKey.toBase58()),
+          destinationTokenAccount: destination,
+          verifier: fallback.publicKey,
+        })
+        .signers([fallback])
+        .rpc(),
+      "ConstraintSeeds"
+    );
+
+    await claim(primary, destination);
+    assert.equal(await balanceOf(destination), VERIFIER_FEE);
+    const account = await program.account.verifierFeeAccount.fetch(feeAccountOf(primary.publicKey));
+    assert.equal(account.claimed.toNumber(), VERIFIER_FEE);
+    await expectError(claim(primary, destination), "NoVerifierFeesToClaim");
+  });
+
+  it("Keeps fees claimable after the key is rotated out", async () => {
+    await program.methods
+      .setFallbackVerifier(fallback.publicKey, new anchor.BN(AMOUNT))
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+    await settle(2, fallback, fallback, PRIMARY_DOWN);
+    await program.methods
+      .revokeFallbackVerifier()
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+    const destination = await createAccount(
+      provider.connection,
+      fixture.owner,
+      fixture.mint,
+      fallback.publicKey,
+      Keypair.generate()
+    );
+    await claim(fallback, destination);
+    assert.equal(await balanceOf(destination), VERIFIER_FEE);
+  });
+});
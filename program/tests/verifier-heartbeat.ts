@@ -0,0 +1,131 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey, SystemProgram } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  AttestationRole,
+  createAttestationProof,
+  getTestVerifierPrivateKey,
+} from "./attestation-helper";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("verifier heartbeat", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const verifier = Keypair.fromSeed(getTestVerifierPrivateKey());
+  const [heartbeat] = PublicKey.findProgramAddressSync(
+    [Buffer.from("verifier_heartbeat")],
+    program.programId
+  );
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const beat = (counter: number, signer = verifier) =>
+    program.methods
+      .recordHeartbeat(new anchor.BN(counter))
+      .accountsPartial({ verifierHeartbeat: heartbeat, verifier: signer.publicKey })
+      .signers([signer])
+      .rpc();
+
+  const setMaxAge = (seconds: number) =>
+    program.methods
+      .updateConfig({ maxHeartbeatAge: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const settle = async (nonce: number, withHeartbeat: boolean) => {
+    const bundleId = `heartbeat-${nonce}`;
+    const payerProof = await createAttestationProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      1_000000,
+      nonce
+    );
+    return program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(nonce),
+        bundleId,
+        { payerProof, merchantProof: null }
+      )
+      .accountsPartial({
+        ...settleAccounts(fixture),
+        verifierHeartbeat: withHeartbeat ? heartbeat : null,
+      })
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  const counter = async () =>
+    (await program.account.verifierHeartbeat.fetch(heartbeat)).counter.toNumber();
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 10_000000);
+    await airdrop(provider, verifier.publicKey);
+    if (!(await provider.connection.getAccountInfo(heartbeat))) {
+      await program.methods
+        .initializeVerifierHeartbeat()
+        .accountsPartial({
+          verifierHeartbeat: heartbeat,
+          verifier: verifier.publicKey,
+          systemProgram: SystemProgram.programId,
+        })
+        .signers([verifier])
+        .rpc();
+    }
+  });
+
+  after(async () => {
+    await setMaxAge(0);
+  });
+
+  it("Advances the counter on each beat", async () => {
+    const next = (await counter()) + 1;
+    await beat(next);
+    assert.equal(await counter(), next);
+  });
+
+  it("Rejects replayed counters", async () => {
+    try {
+      await beat(await counter());
+      assert.fail("Should have failed with StaleHeartbeatCounter");
+    } catch (err) {
+      assert.include(err.toString(), "StaleHeartbeatCounter");
+    }
+  });
+
+  it("Only the verifier key can beat", async () => {
+    const impostor = Keypair.generate();
+    await airdrop(provider, impostor.publicKey);
+    try {
+      await beat((await counter()) + 1, impostor);
+      assert.fail("Should have failed with Unauthorized");
+    } catch (err) {
+      assert.include(err.toString(), "Unauthorized");
+    }
+  });
+
+  it("Requires a fresh heartbeat for proofs once enforced", async () => {
+    await setMaxAge(3600);
+    try {
+      await settle(1, false);
+      assert.fail("Should have failed with VerifierHeartbeatStale");
+    } catch (err) {
+      assert.include(err.toString(), "VerifierHeartbeatStale");
+    }
+    await settle(1, true);
+  });
+});
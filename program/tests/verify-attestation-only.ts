@@ -0,0 +1,91 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import * as crypto from "crypto";
+import { assert } from "chai";
+import {
+  AttestationRole,
+  createAttestationProof,
+  getTestVerifierPublicKey,
+} from "./attestation-helper";
+import { ensureConfig } from "./fixtures";
+
+describe("attestation dry-run", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const payer = Keypair.generate().publicKey;
+  const merchant = Keypair.generate().publicKey;
+  const AMOUNT = 2_000000;
+  const BUNDLE_ID = "attestation-dry-run-1";
+  const bundle = {
+    bundleId: BUNDLE_ID,
+    payer,
+    merchant,
+    amount: new anchor.BN(AMOUNT),
+    nonce: new anchor.BN(1),
+    orderRef: null,
+    courier: null,
+    deviceIdHash: null,
+    conditionId: null,
+  };
+
+  const errorCode = (name: string) =>
+    program.idl.errors.find((error) => error.name === name).code;
+
+  const proof = (role: AttestationRole, amount = AMOUNT, key?: Uint8Array) =>
+    createAttestationProof(role, BUNDLE_ID, payer, merchant, amount, 1, key);
+
+  const verify = (
+    attestation: Parameters<typeof program.methods.verifyAttestationOnly>[0],
+    role: Parameters<typeof program.methods.verifyAttestationOnly>[1]
+  ) => program.methods.verifyAttestationOnly(attestation, role, bundle).view();
+
+  before(async () => {
+    await ensureConfig(provider, program);
+  });
+
+  it("Accepts a proof settlement would accept and names its signer", async () => {
+    const attestation = await proof(AttestationRole.Merchant);
+    const result = await verify(attestation, { merchant: {} });
+    assert.isTrue(result.valid);
+    assert.equal(result.errorCode, 0);
+    assert.isTrue(result.rootMatches);
+    assert.deepEqual(result.expectedRoot, attestation.attestationRoot);
+    assert.ok(result.verifier.equals(new PublicKey(getTestVerifierPublicKey())));
+  });
+
+  it("Reports the root a proof for other bundle fields should have carried", async () => {
+    const attestation = await proof(AttestationRole.Payer, AMOUNT + 1);
+    const result = await verify(attestation, { payer: {} });
+    assert.isFalse(result.valid);
+    assert.equal(result.errorCode, errorCode("InvalidAttestation"));
+    assert.isFalse(result.rootMatches);
+    assert.notDeepEqual(result.expectedRoot, attestation.attestationRoot);
+
+    // The same proof checked under the wrong role fails the same way
+    const wrongRole = await verify(await proof(AttestationRole.Payer), { merchant: {} });
+    assert.isFalse(wrongRole.rootMatches);
+  });
+
+  it("Tells a bad signature apart from a mismatched root", async () => {
+    const result = await verify(
+      await proof(AttestationRole.Payer, AMOUNT, Uint8Array.from(crypto.randomBytes(32))),
+      { payer: {} }
+    );
+    assert.isFalse(result.valid);
+    assert.isTrue(result.rootMatches);
+    assert.equal(result.errorCode, errorCode("InvalidAttestation"));
+  });
+
+  it("Flags malformed proofs", async () => {
+    const result = await verify(
+      { ...(await proof(AttestationRole.Payer)), verifierSignature: Array(64).fill(0) },
+      { payer: {} }
+    );
+    assert.equal(result.errorCode, errorCode("MalformedAttestation"));
+  });
+});
@@ -0,0 +1,73 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair } from "@solana/web3.js";
+import * as crypto from "crypto";
+import { assert } from "chai";
+import { AttestationRole, createAttestationProof } from "./attestation-helper";
+import { ensureConfig } from "./fixtures";
+
+describe("evidence dry-run", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const payer = Keypair.generate().publicKey;
+  const merchant = Keypair.generate().publicKey;
+  const AMOUNT = 3_000000;
+  const BUNDLE_ID = "dry-run-1";
+
+  const errorCode = (name: string) =>
+    program.idl.errors.find((error) => error.name === name).code;
+
+  const proof = (role: AttestationRole, amount = AMOUNT, key?: Uint8Array) =>
+    createAttestationProof(role, BUNDLE_ID, payer, merchant, amount, 1, key);
+
+  const verify = (evidence: Parameters<typeof program.methods.verifyEvidence>[5]) =>
+    program.methods
+      .verifyEvidence(payer, merchant, new anchor.BN(AMOUNT), new anchor.BN(1), BUNDLE_ID, evidence)
+      .view();
+
+  before(async () => {
+    await ensureConfig(provider, program);
+  });
+
+  it("Reports both proofs valid", async () => {
+    const result = await verify({
+      payerProof: await proof(AttestationRole.Payer),
+      merchantProof: await proof(AttestationRole.Merchant),
+    });
+    assert.isTrue(result.payer.present && result.payer.valid);
+    assert.isTrue(result.merchant.present && result.merchant.valid);
+    assert.equal(result.payer.errorCode, 0);
+  });
+
+  it("Pinpoints the proof that fails and why", async () => {
+    const result = await verify({
+      payerProof: await proof(AttestationRole.Payer),
+      merchantProof: await proof(
+        AttestationRole.Merchant,
+        AMOUNT,
+        Uint8Array.from(crypto.randomBytes(32))
+      ),
+    });
+    assert.isTrue(result.payer.valid);
+    assert.isTrue(result.merchant.present);
+    assert.isFalse(result.merchant.valid);
+    assert.equal(result.merchant.errorCode, errorCode("InvalidAttestation"));
+  });
+
+  it("Flags malformed proofs and leaves absent ones unset", async () => {
+    const result = await verify({
+      payerProof: {
+        ...(await proof(AttestationRole.Payer)),
+        verifierSignature: Array(64).fill(0),
+      },
+      merchantProof: null,
+    });
+    assert.equal(result.payer.errorCode, errorCode("MalformedAttestation"));
+    assert.isFalse(result.merchant.present);
+    assert.isFalse(result.merchant.valid);
+  });
+});
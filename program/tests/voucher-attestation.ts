@@ -0,0 +1,110 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import { AttestationRole, createVoucherProof } from "./attestation-helper";
+import {
+  EscrowFixture,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+
+describe("voucher attestation", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const DAY = 86_400;
+  const YEAR = 365 * DAY;
+  let config: PublicKey;
+  let fixture: EscrowFixture;
+
+  const expectError = async (promise: Promise<unknown>, name: string) => {
+    try {
+      await promise;
+      assert.fail(`Should have failed with ${name}`);
+    } catch (err) {
+      assert.include(err.toString(), name);
+    }
+  };
+
+  const setMaxValidity = (seconds: number) =>
+    program.methods
+      .updateConfig({ maxVoucherValidity: new anchor.BN(seconds) })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const now = () => Math.floor(Date.now() / 1000);
+
+  const redeem = async (nonce: number, validFrom: number, validUntil: number) => {
+    const bundleId = `voucher-${nonce}`;
+    const proof = await createVoucherProof(
+      AttestationRole.Payer,
+      bundleId,
+      fixture.owner.publicKey,
+      fixture.merchant.publicKey,
+      1_000000,
+      nonce,
+      validFrom,
+      validUntil
+    );
+    return program.methods
+      .settleOfflinePayment(new anchor.BN(1_000000), new anchor.BN(nonce), bundleId, {
+        payerProof: proof,
+        merchantProof: null,
+      })
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+  };
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 20_000000);
+  });
+
+  after(async () => {
+    await setMaxValidity(0);
+  });
+
+  it("Rejects vouchers until the config allows them", async () => {
+    await expectError(redeem(1, now() - DAY, now() + DAY), "VoucherAttestationDisabled");
+  });
+
+  it("Bounds the configurable validity", async () => {
+    await expectError(setMaxValidity(3 * YEAR), "InvalidConfig");
+    await setMaxValidity(YEAR);
+    const policy = await program.methods.getAttestationPolicy().accountsPartial({ config }).view();
+    assert.equal(policy.maxVoucherValidity.toNumber(), YEAR);
+  });
+
+  it("Redeems a voucher issued long before the attestation age limit", async () => {
+    await redeem(1, now() - 90 * DAY, now() + 90 * DAY);
+    const escrow = await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA);
+    assert.equal(escrow.settlementCount.toNumber(), 1);
+    // Vouchers carry no creation time, so they add no punctuality sample
+    const registry = await program.account.nonceRegistry.fetch(fixture.nonceRegistry);
+    assert.equal(registry.bundleHistory[0].bundleCreatedAt.toNumber(), 0);
+  });
+
+  it("Accepts redemption just inside either end of the window", async () => {
+    await redeem(2, now() - 30, now() + YEAR - 60);
+    await redeem(3, now() - YEAR + 60, now() + 30);
+  });
+
+  it("Rejects redemption before the window opens", async () => {
+    await expectError(redeem(4, now() + 600, now() + DAY), "VoucherNotYetValid");
+  });
+
+  it("Rejects redemption after the window closes", async () => {
+    await expectError(redeem(4, now() - DAY, now() - 600), "VoucherExpired");
+  });
+
+  it("Rejects windows longer than configured or inverted", async () => {
+    await expectError(redeem(4, now() - DAY, now() + YEAR), "InvalidValidityWindow");
+    await expectError(redeem(4, now() + DAY, now() - DAY), "InvalidValidityWindow");
+  });
+});
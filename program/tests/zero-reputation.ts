@@ -0,0 +1,100 @@
+import * as anchor from "@coral-xyz/anchor";
+import { Program } from "@coral-xyz/anchor";
+import { Beam } from "../target/types/beam";
+import { Keypair, PublicKey } from "@solana/web3.js";
+import { assert } from "chai";
+import {
+  EscrowFixture,
+  airdrop,
+  createEscrowFixture,
+  ensureConfig,
+  settleAccounts,
+} from "./fixtures";
+import { signConflictingBundle } from "./attestation-helper";
+
+describe("zero-reputation blocking", () => {
+  const provider = anchor.AnchorProvider.env();
+  anchor.setProvider(provider);
+
+  const program = anchor.workspace.Beam as Program<Beam>;
+
+  const reporter = Keypair.generate();
+  let fixture: EscrowFixture;
+  let config: PublicKey;
+
+  const settle = (nonce: number) =>
+    program.methods
+      .settleOfflinePayment(
+        new anchor.BN(1_000000),
+        new anchor.BN(nonce),
+        `reputation-${nonce}`,
+        { payerProof: null, merchantProof: null }
+      )
+      .accountsPartial(settleAccounts(fixture))
+      .signers([fixture.owner])
+      .rpc();
+
+  const setBlocking = (blockZeroReputation: boolean) =>
+    program.methods
+      .updateConfig({ blockZeroReputation })
+      .accountsPartial({ config, admin: provider.wallet.publicKey })
+      .rpc();
+
+  const reputation = async () =>
+    (
+      await program.account.offlineEscrowAccount.fetch(fixture.escrowPDA)
+    ).reputationScore;
+
+  before(async () => {
+    config = await ensureConfig(provider, program);
+    fixture = await createEscrowFixture(provider, program, 50_000000);
+    await airdrop(provider, reporter.publicKey);
+    await setBlocking(true);
+  });
+
+  after(async () => {
+    await setBlocking(false);
+  });
+
+  it("Settles while reputation is above zero", async () => {
+    assert.isAbove(await reputation(), 0);
+    await settle(1);
+  });
+
+  it("Rejects settlements once reputation drops to zero or below", async () => {
+    const { conflictingHash, evidence } = await signConflictingBundle(
+      fixture.owner,
+      Buffer.alloc(32, 7),
+      fixture.merchant.publicKey,
+      1_000000,
+      1
+    );
+    await program.methods
+      .reportFraudulentBundle(
+        "reputation-1",
+        conflictingHash,
+        { duplicateBundle: {} },
+        evidence as any
+      )
+      .accountsPartial({
+        escrowAccount: fixture.escrowPDA,
+        payer: fixture.owner.publicKey,
+        reporter: reporter.publicKey,
+      })
+      .signers([reporter])
+      .rpc();
+    assert.isAtMost(await reputation(), 0);
+
+    try {
+      await settle(2);
+      assert.fail("Should have failed with ReputationExhausted");
+    } catch (err) {
+      assert.include(err.toString(), "ReputationExhausted");
+    }
+  });
+
+  it("Allows zero-reputation settlements when the flag is off", async () => {
+    await setBlocking(false);
+    await settle(2);
+  });
+});